@@ -0,0 +1,147 @@
+//! `--encrypt-age` / `--decrypt-age`: wrap or unwrap a single value with
+//! [age](https://age-encryption.org) so secrets can live inside config files
+//! as ASCII-armored blobs.
+//!
+//! confctl does not link an age implementation; like the clipboard support
+//! it pipes through the first tool it finds (`age`, then the CLI-compatible
+//! `rage`). The plaintext only ever travels over the child's stdin.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+const TOOLS: &[&str] = &["age", "rage"];
+
+/// Which direction the age transform runs in, built from the CLI flags.
+#[derive(Debug, Clone)]
+pub(crate) enum AgeOp {
+    Encrypt { recipients: Vec<String> },
+    Decrypt { identity: PathBuf },
+}
+
+impl AgeOp {
+    pub(crate) fn from_cli(
+        recipients: &[String],
+        decrypt: bool,
+        identity: Option<&Path>,
+    ) -> Option<Self> {
+        if !recipients.is_empty() {
+            return Some(AgeOp::Encrypt {
+                recipients: recipients.to_vec(),
+            });
+        }
+        match (decrypt, identity) {
+            (true, Some(identity)) => Some(AgeOp::Decrypt {
+                identity: identity.to_path_buf(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn apply(&self, input: &str) -> Result<String> {
+        match self {
+            AgeOp::Encrypt { recipients } => encrypt(input, recipients),
+            AgeOp::Decrypt { identity } => decrypt(input, identity),
+        }
+    }
+}
+
+/// Encrypt `plaintext` to every recipient, returning armored output.
+pub(crate) fn encrypt(plaintext: &str, recipients: &[String]) -> Result<String> {
+    let args = encrypt_args(recipients, |p| Path::new(p).is_file());
+    let out = run_age(&args, plaintext.as_bytes())?;
+    String::from_utf8(out).context("age produced non-UTF-8 armored output")
+}
+
+/// Decrypt an armored (or raw) age payload with the identity file.
+pub(crate) fn decrypt(ciphertext: &str, identity: &Path) -> Result<String> {
+    if !identity.is_file() {
+        bail!("age identity file not found: {}", identity.display());
+    }
+    let args = vec![
+        "--decrypt".to_string(),
+        "--identity".to_string(),
+        identity.display().to_string(),
+    ];
+    // Values copied out of YAML often lose the trailing newline age expects.
+    let mut input = ciphertext.trim().to_string();
+    input.push('\n');
+    let out = run_age(&args, input.as_bytes())?;
+    String::from_utf8(out).context("Decrypted age payload is not valid UTF-8")
+}
+
+/// `-r` for literal recipients (`age1…`, `ssh-…`), `-R` for recipient files.
+fn encrypt_args(recipients: &[String], is_file: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut args = vec!["--encrypt".to_string(), "--armor".to_string()];
+    for recipient in recipients {
+        let literal = recipient.starts_with("age1") || recipient.starts_with("ssh-");
+        let flag = if !literal && is_file(recipient) {
+            "--recipients-file"
+        } else {
+            "--recipient"
+        };
+        args.push(flag.to_string());
+        args.push(recipient.clone());
+    }
+    args
+}
+
+fn run_age(args: &[String], input: &[u8]) -> Result<Vec<u8>> {
+    for tool in TOOLS {
+        let spawned = Command::new(tool)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn();
+        let Ok(child) = spawned else { continue };
+        return crate::compress::feed(child, tool, input);
+    }
+    bail!("no age tool available — install age (https://age-encryption.org) or rage")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_args_distinguish_literal_and_file_recipients() {
+        let recipients = vec![
+            "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p".to_string(),
+            "team.txt".to_string(),
+        ];
+        let args = encrypt_args(&recipients, |p| p == "team.txt");
+        assert_eq!(
+            args,
+            vec![
+                "--encrypt",
+                "--armor",
+                "--recipient",
+                "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+                "--recipients-file",
+                "team.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn from_cli_prefers_encrypt_and_needs_identity_to_decrypt() {
+        assert!(AgeOp::from_cli(&[], false, None).is_none());
+        assert!(AgeOp::from_cli(&[], true, None).is_none());
+        assert!(matches!(
+            AgeOp::from_cli(&[], true, Some(Path::new("key.txt"))),
+            Some(AgeOp::Decrypt { .. })
+        ));
+        assert!(matches!(
+            AgeOp::from_cli(&["age1x".to_string()], false, None),
+            Some(AgeOp::Encrypt { .. })
+        ));
+    }
+
+    #[test]
+    fn decrypt_rejects_missing_identity_before_spawning() {
+        let err = decrypt("x", Path::new("/nonexistent/confctl-key.txt")).unwrap_err();
+        assert!(err.to_string().contains("identity file not found"));
+    }
+}
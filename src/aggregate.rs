@@ -0,0 +1,241 @@
+//! Summaries of the resolved value printed instead of the value itself:
+//! `confctl config.json clubs --length` rather than piping to `jq length`,
+//! `confctl metrics.json samples --sum` for quick reports.
+//!
+//! The numeric aggregations take the items of an array (or the values of an
+//! object). Numeric strings count as numbers, since ENV and CSV values are
+//! always strings; `null` items are skipped.
+
+use anyhow::{bail, Result};
+use serde_json::{Number, Value};
+
+use crate::formats::float_value;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Aggregate {
+    Length,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    Unique,
+}
+
+impl Aggregate {
+    fn flag(self) -> &'static str {
+        match self {
+            Aggregate::Length => "--length",
+            Aggregate::Sum => "--sum",
+            Aggregate::Min => "--min",
+            Aggregate::Max => "--max",
+            Aggregate::Avg => "--avg",
+            Aggregate::Unique => "--unique",
+        }
+    }
+}
+
+pub(crate) fn apply(op: Aggregate, value: &Value) -> Result<Value> {
+    if op == Aggregate::Length {
+        return Ok(Value::from(length(value)?));
+    }
+    let items: Vec<&Value> = match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(map) => map.values().collect(),
+        other => bail!(
+            "{} needs an array or object, got {}",
+            op.flag(),
+            type_name(other)
+        ),
+    };
+    if op == Aggregate::Unique {
+        let mut seen: Vec<&Value> = Vec::new();
+        for item in items {
+            if !seen.contains(&item) {
+                seen.push(item);
+            }
+        }
+        return Ok(Value::Array(seen.into_iter().cloned().collect()));
+    }
+
+    let numbers = numbers(op, &items)?;
+    let result = match op {
+        Aggregate::Sum => sum(&numbers),
+        Aggregate::Min => extreme(&numbers, |a, b| a < b),
+        Aggregate::Max => extreme(&numbers, |a, b| a > b),
+        Aggregate::Avg if numbers.is_empty() => Value::Null,
+        Aggregate::Avg => {
+            let total: f64 = numbers.iter().map(Num::as_f64).sum();
+            float_value(total / numbers.len() as f64)
+        }
+        Aggregate::Length | Aggregate::Unique => unreachable!("handled above"),
+    };
+    Ok(result)
+}
+
+/// Elements of an array, keys of an object, characters of a string; `null`
+/// counts as empty.
+pub(crate) fn length(value: &Value) -> Result<usize> {
+    match value {
+        Value::Array(items) => Ok(items.len()),
+        Value::Object(map) => Ok(map.len()),
+        Value::String(s) => Ok(s.chars().count()),
+        Value::Null => Ok(0),
+        other => bail!(
+            "--length needs an array, object or string, got {}",
+            type_name(other)
+        ),
+    }
+}
+
+/// A number as parsed: integers stay exact so sums of counters don't turn
+/// into floats.
+#[derive(Clone, Copy, Debug)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn as_f64(&self) -> f64 {
+        match *self {
+            Num::Int(n) => n as f64,
+            Num::Float(f) => f,
+        }
+    }
+
+    fn to_value(self) -> Value {
+        match self {
+            Num::Int(n) => Value::from(n),
+            Num::Float(f) => float_value(f),
+        }
+    }
+}
+
+fn numbers(op: Aggregate, items: &[&Value]) -> Result<Vec<Num>> {
+    let mut out = Vec::with_capacity(items.len());
+    for (i, item) in items.iter().enumerate() {
+        let num = match item {
+            Value::Null => continue,
+            Value::Number(n) => number(n),
+            Value::String(s) => match s.trim().parse::<i64>() {
+                Ok(n) => Some(Num::Int(n)),
+                Err(_) => s
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|f| f.is_finite())
+                    .map(Num::Float),
+            },
+            _ => None,
+        };
+        match num {
+            Some(num) => out.push(num),
+            None => bail!(
+                "{} needs numbers, but item {i} is {}",
+                op.flag(),
+                type_name(item)
+            ),
+        }
+    }
+    Ok(out)
+}
+
+fn number(n: &Number) -> Option<Num> {
+    match n.as_i64() {
+        Some(i) => Some(Num::Int(i)),
+        None => n.as_f64().map(Num::Float),
+    }
+}
+
+fn sum(numbers: &[Num]) -> Value {
+    let mut int_total: Option<i64> = Some(0);
+    for num in numbers {
+        int_total = match (int_total, num) {
+            (Some(total), Num::Int(n)) => total.checked_add(*n),
+            _ => None,
+        };
+    }
+    match int_total {
+        Some(total) => Value::from(total),
+        None => float_value(numbers.iter().map(Num::as_f64).sum()),
+    }
+}
+
+/// The first item that beats every other by `better`; `null` when empty.
+fn extreme(numbers: &[Num], better: impl Fn(f64, f64) -> bool) -> Value {
+    numbers
+        .iter()
+        .copied()
+        .reduce(|best, n| {
+            if better(n.as_f64(), best.as_f64()) {
+                n
+            } else {
+                best
+            }
+        })
+        .map_or(Value::Null, Num::to_value)
+}
+
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn length_counts_items_keys_and_chars() {
+        assert_eq!(length(&json!([1, 2, 3])).unwrap(), 3);
+        assert_eq!(length(&json!({"a": 1, "b": 2})).unwrap(), 2);
+        assert_eq!(length(&json!("São Januário")).unwrap(), 12);
+        assert_eq!(length(&Value::Null).unwrap(), 0);
+        let err = length(&json!(42)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--length needs an array, object or string, got a number"
+        );
+    }
+
+    #[test]
+    fn numeric_aggregations() {
+        let samples = json!([3, "4", null, 1.5, 7]);
+        assert_eq!(apply(Aggregate::Sum, &samples).unwrap(), json!(15.5));
+        assert_eq!(apply(Aggregate::Min, &samples).unwrap(), json!(1.5));
+        assert_eq!(apply(Aggregate::Max, &samples).unwrap(), json!(7));
+        assert_eq!(apply(Aggregate::Avg, &samples).unwrap(), json!(3.875));
+        assert_eq!(
+            apply(Aggregate::Sum, &json!({"a": 2, "b": 3})).unwrap(),
+            json!(5)
+        );
+        assert_eq!(apply(Aggregate::Max, &json!([])).unwrap(), Value::Null);
+        assert_eq!(apply(Aggregate::Sum, &json!([])).unwrap(), json!(0));
+    }
+
+    #[test]
+    fn unique_keeps_first_occurrences_and_errors_name_the_item() {
+        let value = json!(["a", "b", "a", 1, {"x": 1}, {"x": 1}]);
+        assert_eq!(
+            apply(Aggregate::Unique, &value).unwrap(),
+            json!(["a", "b", 1, {"x": 1}])
+        );
+        let err = apply(Aggregate::Avg, &json!([1, "two"])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--avg needs numbers, but item 1 is a string"
+        );
+        let err = apply(Aggregate::Sum, &json!(3)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--sum needs an array or object, got a number"
+        );
+    }
+}
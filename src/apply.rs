@@ -0,0 +1,148 @@
+//! `--apply STEPS`: transforms run in order on the value at PATH, for
+//! configs that embed encoded documents in string values. With
+//! `--apply base64d,json,.db.host` the string is base64-decoded, parsed as
+//! JSON, and `db.host` is resolved inside the result.
+//!
+//! A step is `base64d` or `base64e`, `trim`, `upper` or `lower`, a
+//! `--format` name (`json`, `yaml`, `toml`, `env`, ...) to parse a string
+//! as that format, or `.PATH` to go on resolving inside the current value.
+//! The result then prints as PATH's value would.
+
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde_json::Value;
+
+use crate::aggregate::type_name;
+use crate::{apply_base64_transform, parse_content, resolve_path, Format};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Step {
+    Base64Decode,
+    Base64Encode,
+    Trim,
+    Upper,
+    Lower,
+    Parse(Format),
+    Path(String),
+}
+
+/// clap value parser for one step of `--apply`.
+pub(crate) fn parse_step(text: &str) -> Result<Step, String> {
+    let step = match text.trim() {
+        "base64d" => Step::Base64Decode,
+        "base64e" => Step::Base64Encode,
+        "trim" => Step::Trim,
+        "upper" => Step::Upper,
+        "lower" => Step::Lower,
+        "." => return Err("`.` is the value itself; give a path such as `.db.host`".into()),
+        path if path.starts_with('.') => Step::Path(path[1..].to_string()),
+        name => match Format::from_str(name, true) {
+            Ok(format) => Step::Parse(format),
+            Err(_) => {
+                return Err(format!(
+                    "unknown step `{name}`: expected base64d, base64e, trim, upper, lower, \
+                     a format name such as json or yaml, or a .PATH"
+                ))
+            }
+        },
+    };
+    Ok(step)
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Base64Decode => f.write_str("base64d"),
+            Step::Base64Encode => f.write_str("base64e"),
+            Step::Trim => f.write_str("trim"),
+            Step::Upper => f.write_str("upper"),
+            Step::Lower => f.write_str("lower"),
+            Step::Parse(format) => f.write_str(format.to_possible_value().unwrap().get_name()),
+            Step::Path(path) => write!(f, ".{path}"),
+        }
+    }
+}
+
+/// `value` after every step, each error naming the step that failed.
+pub(crate) fn run(value: Value, steps: &[Step]) -> Result<Value> {
+    steps
+        .iter()
+        .enumerate()
+        .try_fold(value, |value, (index, step)| {
+            apply(value, step).with_context(|| format!("--apply step {} (`{step}`)", index + 1))
+        })
+}
+
+fn apply(value: Value, step: &Step) -> Result<Value> {
+    if let Step::Path(path) = step {
+        return resolve_path(&value, path).cloned();
+    }
+    if let Step::Base64Encode = step {
+        let text = match &value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        return Ok(Value::String(apply_base64_transform(&text, false, true)?));
+    }
+    let Value::String(text) = value else {
+        bail!("expected a string, got {}", type_name(&value));
+    };
+    Ok(match step {
+        Step::Base64Decode => Value::String(apply_base64_transform(&text, true, false)?),
+        Step::Trim => Value::String(text.trim().to_string()),
+        Step::Upper => Value::String(text.to_uppercase()),
+        Step::Lower => Value::String(text.to_lowercase()),
+        Step::Parse(format) => parse_content("--apply", &text, Some(*format))?,
+        Step::Path(_) | Step::Base64Encode => unreachable!(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn steps(text: &str) -> Vec<Step> {
+        text.split(',')
+            .map(|step| parse_step(step).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn steps_decode_parse_and_resolve_in_order() {
+        // {"db":{"host":" db.internal "}}
+        let secret = json!("eyJkYiI6eyJob3N0IjoiIGRiLmludGVybmFsICJ9fQ==");
+        assert_eq!(
+            run(secret.clone(), &steps("base64d,json,.db.host,trim,upper")).unwrap(),
+            json!("DB.INTERNAL")
+        );
+        assert_eq!(
+            run(json!("port: 8080\n"), &steps("yaml,.port")).unwrap(),
+            json!(8080)
+        );
+        assert_eq!(
+            run(json!({"a": 1}), &steps("base64e,base64d,json")).unwrap(),
+            json!({"a": 1})
+        );
+
+        let err = run(secret, &steps("base64d,yaml,.db.port")).unwrap_err();
+        assert_eq!(err.to_string(), "--apply step 3 (`.db.port`)");
+        let err = run(json!(1), &steps("trim")).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "--apply step 1 (`trim`): expected a string, got a number"
+        );
+    }
+
+    #[test]
+    fn step_names_are_checked_when_parsing() {
+        assert_eq!(parse_step("JSON5"), Ok(Step::Parse(Format::Json5)));
+        assert_eq!(parse_step(".a.0"), Ok(Step::Path("a.0".into())));
+        assert!(parse_step("gzip")
+            .unwrap_err()
+            .starts_with("unknown step `gzip`"));
+        assert!(parse_step(".").is_err());
+    }
+}
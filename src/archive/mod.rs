@@ -0,0 +1,66 @@
+//! Tar and zip archives: snapshot bundles are tar files, and any input can
+//! name a member of an archive as `ARCHIVE::MEMBER`
+//! (`backup.tar.gz::etc/app/config.yaml`), read without extracting it.
+
+pub(crate) mod tar;
+pub(crate) mod zip;
+
+use anyhow::{bail, Context, Result};
+
+use crate::compress::Compression;
+
+/// Member `member` of the archive `name` holds. Tar archives may be gzip-
+/// or zstd-compressed; zip is recognised by its magic number. A leading
+/// `./` or `/` on either side is ignored.
+pub(crate) fn member(name: &str, bytes: &[u8], member: &str) -> Result<Vec<u8>> {
+    let wanted = normalize(member);
+    if bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06") {
+        let entries = zip::entries(bytes).with_context(|| format!("reading {name}"))?;
+        if let Some(entry) = entries.iter().find(|e| normalize(&e.name) == wanted) {
+            return entry
+                .contents()
+                .with_context(|| format!("reading {name}::{member}"));
+        }
+    } else {
+        let (compression, _) = Compression::of_input(name, bytes);
+        let archive = compression
+            .decompress(bytes)
+            .with_context(|| format!("decompressing {name}"))?;
+        let entries = tar::read(&archive).with_context(|| format!("reading {name}"))?;
+        if let Some((_, data)) = entries.into_iter().find(|(n, _)| normalize(n) == wanted) {
+            return Ok(data);
+        }
+    }
+    bail!("{name} has no member `{member}`")
+}
+
+fn normalize(name: &str) -> &str {
+    name.trim_start_matches("./").trim_start_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn members_of_tar_and_zip() {
+        let archive = tar::write(&[("./etc/app/config.yaml", b"port: 80")], 0).unwrap();
+        assert_eq!(
+            member("backup.tar", &archive, "etc/app/config.yaml").unwrap(),
+            b"port: 80"
+        );
+        let err = member("backup.tar", &archive, "etc/missing.yaml").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "backup.tar has no member `etc/missing.yaml`"
+        );
+
+        let zip = std::fs::read("testdata/archive/configs.zip").unwrap();
+        assert_eq!(
+            member("configs.zip", &zip, "app/stored.json").unwrap(),
+            b"{\"stored\": true}\n"
+        );
+        let deflated = member("configs.zip", &zip, "app/config.yaml").unwrap();
+        assert!(deflated.starts_with(b"server:\n  port: 8080\n"));
+    }
+}
@@ -0,0 +1,148 @@
+//! Just enough tar for snapshot bundles and `backup.tar::member` inputs:
+//! regular files, written and read in memory. Writing keeps to short
+//! ustar names; reading also follows the ustar prefix field and the long
+//! names GNU (`L`) and pax (`x`) headers carry. Compression is layered on
+//! top by the caller.
+
+use anyhow::{bail, Context, Result};
+
+const BLOCK: usize = 512;
+
+/// Archive `entries` (name, contents) with every file stamped `mtime`.
+pub(crate) fn write(entries: &[(&str, &[u8])], mtime: i64) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for (name, data) in entries {
+        if name.len() > 100 {
+            bail!("tar entry name too long: {name}");
+        }
+        let mut header = [0u8; BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        put_octal(&mut header[100..108], 0o644);
+        put_octal(&mut header[108..116], 0);
+        put_octal(&mut header[116..124], 0);
+        put_octal(&mut header[124..136], data.len() as u64);
+        put_octal(&mut header[136..148], mtime.max(0) as u64);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[148..156].fill(b' ');
+        let sum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+        put_octal(&mut header[148..155], u64::from(sum));
+        header[155] = b' ';
+
+        out.extend_from_slice(&header);
+        out.extend_from_slice(data);
+        out.resize(out.len().next_multiple_of(BLOCK), 0);
+    }
+    out.resize(out.len() + 2 * BLOCK, 0);
+    Ok(out)
+}
+
+/// Regular files in archive order; directories and other entry types are
+/// skipped.
+pub(crate) fn read(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut at = 0;
+    while at + BLOCK <= bytes.len() {
+        let header = &bytes[at..at + BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let mut name = text(&header[..100]);
+        if &header[257..262] == b"ustar" && header[345] != 0 {
+            name = format!("{}/{name}", text(&header[345..500]));
+        }
+        let size = octal(&header[124..136]).with_context(|| format!("size of tar entry {name}"))?;
+        let start = at + BLOCK;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= bytes.len())
+            .with_context(|| format!("tar entry {name} is truncated"))?;
+        let data = &bytes[start..end];
+        match header[156] {
+            b'L' => long_name = Some(text(data)),
+            b'x' => long_name = pax_path(data).or(long_name),
+            b'0' | 0 => entries.push((long_name.take().unwrap_or(name), data.to_vec())),
+            _ => long_name = None,
+        }
+        at = start + size.next_multiple_of(BLOCK);
+    }
+    Ok(entries)
+}
+
+/// A NUL-terminated header field or GNU long name.
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// The `path` record of a pax extended header (`<len> path=<name>\n`).
+fn pax_path(data: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(data)
+        .lines()
+        .filter_map(|record| record.split_once(' ')?.1.strip_prefix("path="))
+        .next_back()
+        .map(str::to_string)
+}
+
+fn put_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let text = format!("{value:0digits$o}");
+    field[..digits].copy_from_slice(text.as_bytes());
+    field[digits] = 0;
+}
+
+fn octal(field: &[u8]) -> Result<usize> {
+    let text = std::str::from_utf8(field)?.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    Ok(usize::from_str_radix(text, 8)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_pads_to_blocks() {
+        let archive = write(&[("a.json", b"{}"), ("b.sig", &[7u8; 600])], 1_700_000_000).unwrap();
+        assert_eq!(archive.len() % BLOCK, 0);
+        let entries = read(&archive).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], ("a.json".to_string(), b"{}".to_vec()));
+        assert_eq!(entries[1].1.len(), 600);
+        assert!(read(&archive[..3 * BLOCK + 64]).is_err());
+    }
+
+    /// One entry, without the end-of-archive blocks, of type `kind`.
+    fn entry(name: &str, data: &[u8], kind: u8) -> Vec<u8> {
+        let mut entry = write(&[(name, data)], 0).unwrap();
+        entry.truncate(entry.len() - 2 * BLOCK);
+        entry[156] = kind;
+        entry
+    }
+
+    #[test]
+    fn long_names_from_prefix_and_extension_headers() {
+        let long = format!("{}/config.yaml", "deep/".repeat(30));
+        let mut ustar = entry("app.yaml", b"b: 2", b'0');
+        ustar[345..348].copy_from_slice(b"etc");
+        let archive = [
+            entry("././@LongLink", long.as_bytes(), b'L'),
+            entry("truncated", b"a: 1", b'0'),
+            entry("PaxHeader", b"25 path=etc/app/pax.yaml\n", b'x'),
+            entry("pax.yaml", b"c: 3", b'0'),
+            ustar,
+            vec![0; 2 * BLOCK],
+        ]
+        .concat();
+        let names: Vec<String> = read(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, [long.as_str(), "etc/app/pax.yaml", "etc/app.yaml"]);
+    }
+}
@@ -0,0 +1,102 @@
+//! Reading zip archives in memory, from the central directory. Stored
+//! members are copied out; deflated ones are wrapped in a gzip header and
+//! trailer and inflated by `gzip -d`, which also checks the CRC. Zip64 and
+//! encrypted members are not supported.
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::compress::Compression;
+
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+
+pub(crate) struct Entry<'a> {
+    pub(crate) name: String,
+    method: u16,
+    crc: u32,
+    size: u32,
+    data: &'a [u8],
+}
+
+impl Entry<'_> {
+    /// The member's contents, inflated.
+    pub(crate) fn contents(&self) -> Result<Vec<u8>> {
+        match self.method {
+            0 => Ok(self.data.to_vec()),
+            8 => {
+                let mut gzip = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+                gzip.extend_from_slice(self.data);
+                gzip.extend_from_slice(&self.crc.to_le_bytes());
+                gzip.extend_from_slice(&self.size.to_le_bytes());
+                Compression::Gzip.decompress(&gzip)
+            }
+            method => bail!("zip member {} uses compression method {method}", self.name),
+        }
+    }
+}
+
+/// Every file in the archive; directories are skipped.
+pub(crate) fn entries(bytes: &[u8]) -> Result<Vec<Entry<'_>>> {
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(65_536 + 22)
+        .find(|&at| u32_at(bytes, at) == Some(END_OF_DIRECTORY))
+        .ok_or_else(|| anyhow!("not a zip archive: no end of central directory"))?;
+    let count = u16_at(bytes, end + 10).unwrap_or_default();
+    let mut at = u32_at(bytes, end + 16).unwrap_or_default() as usize;
+
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        if u32_at(bytes, at) != Some(DIRECTORY_ENTRY) {
+            bail!("corrupt zip central directory at byte {at}");
+        }
+        let field = |offset: usize| u32_at(bytes, at + offset).unwrap_or_default();
+        let short = |offset: usize| usize::from(u16_at(bytes, at + offset).unwrap_or_default());
+        let name_len = short(28);
+        let name = bytes
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| anyhow!("zip entry name at byte {at} is truncated"))?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        let (flags, method, crc) = (short(8), short(10) as u16, field(16));
+        let (compressed, size, local) = (field(20), field(24), field(42));
+        at += 46 + name_len + short(30) + short(32);
+
+        if name.ends_with('/') {
+            continue;
+        }
+        if [compressed, size, local].contains(&u32::MAX) {
+            bail!("zip member {name} needs zip64, which is not supported");
+        }
+        if flags & 1 != 0 {
+            bail!("zip member {name} is encrypted");
+        }
+        let local = local as usize;
+        if u32_at(bytes, local) != Some(LOCAL_HEADER) {
+            bail!("zip member {name}: no local header at byte {local}");
+        }
+        let start = local
+            + 30
+            + usize::from(u16_at(bytes, local + 26).unwrap_or_default())
+            + usize::from(u16_at(bytes, local + 28).unwrap_or_default());
+        let data = bytes
+            .get(start..start + compressed as usize)
+            .ok_or_else(|| anyhow!("zip member {name} is truncated"))?;
+        entries.push(Entry {
+            name,
+            method,
+            crc,
+            size,
+            data,
+        });
+    }
+    Ok(entries)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
@@ -0,0 +1,172 @@
+//! `confctl assert` — config contract checks for CI as a one-liner:
+//! `confctl assert config.yaml 'server.port == 8080' 'tls.enabled'`.
+//!
+//! Each predicate is an expression (see `crate::expr`) and holds when its
+//! value is truthy: not `false`, `null`, `0`, `""` or empty. A predicate
+//! that cannot be evaluated, such as one naming a missing key, fails. Every
+//! predicate is checked; the failures show the values their paths hold, and
+//! the command exits 1 when any failed.
+
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+use serde_json::Value;
+
+use crate::expr::{self, Expr};
+use crate::formats::ParseOptions;
+use crate::{format_value_with, parse_file, resolve_path, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct AssertCli {
+    /// Config file, or `-` for stdin.
+    pub(crate) file: String,
+
+    /// Predicates that must hold, e.g. `'server.port == 8080'`.
+    #[arg(required = true, value_name = "PREDICATE")]
+    pub(crate) predicates: Vec<String>,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Print only the failures.
+    #[arg(short = 'q', long)]
+    pub(crate) quiet: bool,
+}
+
+/// The outcome of one predicate.
+struct Check {
+    predicate: String,
+    passed: bool,
+    /// Why it failed: the values of its paths, or the evaluation error.
+    detail: Option<String>,
+}
+
+/// Prints a line per predicate; `true` when any failed.
+pub(crate) fn run(cli: AssertCli) -> Result<bool> {
+    let predicates = cli
+        .predicates
+        .iter()
+        .map(|text| expr::parse(text).with_context(|| format!("in the predicate `{text}`")))
+        .collect::<Result<Vec<_>>>()?;
+    let value = parse_file(&cli.file, cli.format, &ParseOptions::default())?;
+    let checks: Vec<Check> = cli
+        .predicates
+        .iter()
+        .zip(&predicates)
+        .map(|(text, predicate)| check(text, predicate, &value))
+        .collect();
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    for check in &checks {
+        if check.passed {
+            if !cli.quiet {
+                println!("{} {}", "✓".green().bold(), check.predicate);
+            }
+            continue;
+        }
+        match &check.detail {
+            Some(detail) => println!("{} {}  ({detail})", "✗".red().bold(), check.predicate),
+            None => println!("{} {}", "✗".red().bold(), check.predicate),
+        }
+    }
+    if failed > 0 {
+        println!(
+            "{failed} of {} assertion{} failed",
+            checks.len(),
+            if checks.len() == 1 { "" } else { "s" }
+        );
+    }
+    Ok(failed > 0)
+}
+
+fn check(text: &str, predicate: &Expr, value: &Value) -> Check {
+    let (passed, detail) = match predicate.eval(value) {
+        Ok(result) if expr::truthy(&result) => (true, None),
+        Ok(result) => {
+            let values: Vec<String> = predicate
+                .paths()
+                .into_iter()
+                .map(|path| match resolve_path(value, path) {
+                    Ok(found) => format!("{path} = {}", shown(found)),
+                    Err(_) => format!("{path} is missing"),
+                })
+                .collect();
+            let detail = if values.is_empty() {
+                format!("got {}", shown(&result))
+            } else {
+                values.join(", ")
+            };
+            (false, Some(detail))
+        }
+        Err(err) => (false, Some(format!("{err:#}"))),
+    };
+    Check {
+        predicate: text.to_string(),
+        passed,
+        detail,
+    }
+}
+
+/// A value as it is written in a predicate: strings quoted, the rest as
+/// compact JSON.
+fn shown(value: &Value) -> String {
+    match value {
+        Value::String(_) => value.to_string(),
+        other => format_value_with(other, true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn checks(predicates: &[&str]) -> Vec<Check> {
+        let value = json!({
+            "server": {"port": 8080, "host": "0.0.0.0"},
+            "tls": {"enabled": false},
+            "replicas": 3
+        });
+        predicates
+            .iter()
+            .map(|text| check(text, &expr::parse(text).unwrap(), &value))
+            .collect()
+    }
+
+    #[test]
+    fn passing_and_failing_predicates() {
+        let results = checks(&[
+            "server.port == 8080",
+            "tls.enabled == true",
+            "tls.enabled",
+            "replicas >= 2 && server.host != 'localhost'",
+            "server.port > replicas * 10000",
+            "db.port == 5432",
+        ]);
+        let outcome: Vec<(bool, Option<&str>)> = results
+            .iter()
+            .map(|check| (check.passed, check.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            outcome,
+            vec![
+                (true, None),
+                (false, Some("tls.enabled = false")),
+                (false, Some("tls.enabled = false")),
+                (true, None),
+                (false, Some("server.port = 8080, replicas = 3")),
+                (false, Some("Key not found: 'db' (at path 'db')")),
+            ]
+        );
+    }
+
+    #[test]
+    fn details_quote_strings_and_fall_back_to_the_result() {
+        let results = checks(&["server.host == 'localhost'", "1 > 2"]);
+        assert_eq!(
+            results[0].detail.as_deref(),
+            Some("server.host = \"0.0.0.0\"")
+        );
+        assert_eq!(results[1].detail.as_deref(), Some("got false"));
+    }
+}
@@ -0,0 +1,252 @@
+//! Crash-safe file writes. Every command that writes a file goes through
+//! [`write`]: the new contents go to a temporary file next to the target,
+//! are flushed to disk, and are renamed over it, so a crash or a full disk
+//! leaves either the old file or the new one, never a truncated mix.
+//!
+//! An existing file keeps its permissions and, where the OS allows it, its
+//! owner. A symlink is followed, so the link stays a link.
+//!
+//! With `--diff` nothing is written: each write prints a unified diff of
+//! the file as it is against what would replace it, so every command that
+//! writes files can be previewed the same way.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::diff;
+
+/// `--diff`, and whether its output is coloured.
+static PREVIEW: OnceLock<Option<bool>> = OnceLock::new();
+
+/// Preview writes instead of making them for this process (`--diff`).
+pub(crate) fn configure(preview: bool, use_color: bool) {
+    let _ = PREVIEW.set(preview.then_some(use_color));
+}
+
+/// Whether writes are only previewed; commands then leave out the
+/// confirmations of what they wrote.
+pub(crate) fn previewing() -> bool {
+    matches!(PREVIEW.get(), Some(Some(_)))
+}
+
+/// `--backup`, shared by every command that replaces a file in place.
+#[derive(Args, Debug, Default)]
+pub(crate) struct BackupArg {
+    /// Copy a file to FILE + SUFFIX before replacing it: `.bak` for a bare
+    /// `--backup`, another suffix as `--backup=SUFFIX` (with the `=`).
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".bak"
+    )]
+    pub(crate) backup: Option<String>,
+}
+
+impl BackupArg {
+    /// The suffix to pass to [`write`].
+    pub(crate) fn suffix(&self) -> Option<&str> {
+        self.backup.as_deref()
+    }
+}
+
+/// Replace `path` with `contents`. With `backup`, an existing file is first
+/// copied to `path` + `backup` (`config.yaml.bak` for `.bak`).
+pub(crate) fn write(path: &Path, contents: impl AsRef<[u8]>, backup: Option<&str>) -> Result<()> {
+    if let Some(Some(use_color)) = PREVIEW.get() {
+        return preview(path, Some(contents.as_ref()), *use_color);
+    }
+    let target = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)
+            .with_context(|| format!("following the symlink {}", path.display()))?,
+        _ => path.to_path_buf(),
+    };
+    let existing = fs::metadata(&target).ok();
+
+    let temp = temp_path(&target);
+    let written = write_temp(&temp, contents.as_ref(), existing.as_ref());
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp);
+        return Err(err).with_context(|| format!("writing {}", path.display()));
+    }
+
+    if let (Some(suffix), Some(_)) = (backup, &existing) {
+        let copy = backup_path(&target, suffix);
+        if let Err(err) = fs::copy(&target, &copy) {
+            let _ = fs::remove_file(&temp);
+            return Err(err)
+                .with_context(|| format!("backing up {} to {}", target.display(), copy.display()));
+        }
+    }
+
+    if let Err(err) = fs::rename(&temp, &target) {
+        let _ = fs::remove_file(&temp);
+        return Err(err).with_context(|| format!("writing {}", path.display()));
+    }
+    sync_dir(&target);
+    Ok(())
+}
+
+/// Remove `path`, or preview its removal with `--diff`.
+pub(crate) fn remove(path: &Path) -> Result<()> {
+    if let Some(Some(use_color)) = PREVIEW.get() {
+        return preview(path, None, *use_color);
+    }
+    fs::remove_file(path).with_context(|| format!("removing {}", path.display()))
+}
+
+/// Print how `path` would change into `contents` (`None`: removed).
+fn preview(path: &Path, contents: Option<&[u8]>, use_color: bool) -> Result<()> {
+    let name = path.display().to_string();
+    let existing = match fs::read(path) {
+        Ok(bytes) => Some(bytes),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err).with_context(|| format!("reading {name}")),
+    };
+    if existing.as_deref() == contents {
+        eprintln!("{name}: no changes");
+        return Ok(());
+    }
+    let old = existing.as_deref().map(std::str::from_utf8).transpose();
+    let new = contents.map(std::str::from_utf8).transpose();
+    let (Ok(old), Ok(new)) = (old, new) else {
+        println!("Binary file {name} would change");
+        return Ok(());
+    };
+    let label = |text: Option<&str>| match text {
+        Some(_) => name.as_str(),
+        None => "/dev/null",
+    };
+    print!(
+        "{}",
+        diff::unified(
+            old.unwrap_or_default(),
+            new.unwrap_or_default(),
+            label(old),
+            label(new),
+            use_color
+        )
+    );
+    Ok(())
+}
+
+/// `path` with `suffix` appended to its file name.
+pub(crate) fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// A hidden sibling of `target`, so the rename stays on one filesystem.
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+fn write_temp(temp: &Path, contents: &[u8], existing: Option<&fs::Metadata>) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // Never readable by more people than the file it replaces, even briefly.
+    #[cfg(unix)]
+    if let Some(meta) = existing {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(meta.permissions().mode() & 0o777);
+    }
+    let mut file = options
+        .open(temp)
+        .with_context(|| format!("creating {}", temp.display()))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    if let Some(meta) = existing {
+        fs::set_permissions(temp, meta.permissions())?;
+        keep_owner(temp, meta);
+    }
+    Ok(())
+}
+
+/// Give `temp` the owner of the file it replaces. Only root (or the owner,
+/// for the group) may do so; otherwise the file is the writer's, as it
+/// would be without the rename.
+#[cfg(unix)]
+fn keep_owner(temp: &Path, meta: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    let _ = std::os::unix::fs::chown(temp, Some(meta.uid()), Some(meta.gid()));
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_temp: &Path, _meta: &fs::Metadata) {}
+
+/// Flush the rename itself. Best effort: not every platform can open a
+/// directory.
+fn sync_dir(target: &Path) {
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_the_file_and_keeps_a_backup() {
+        let dir = crate::scratch_dir("atomic-backup");
+        let path = dir.join("config.yaml");
+        write(&path, "a: 1\n", Some(".bak")).unwrap();
+        assert!(
+            !dir.join("config.yaml.bak").exists(),
+            "nothing to back up yet"
+        );
+
+        write(&path, "a: 2\n", Some(".bak")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a: 2\n");
+        assert_eq!(
+            fs::read_to_string(dir.join("config.yaml.bak")).unwrap(),
+            "a: 1\n"
+        );
+        let leftovers: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions_and_symlinks() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = crate::scratch_dir("atomic-perms");
+        let real = dir.join("real.env");
+        fs::write(&real, "A=1\n").unwrap();
+        fs::set_permissions(&real, fs::Permissions::from_mode(0o600)).unwrap();
+        let link = dir.join(".env");
+        symlink(&real, &link).unwrap();
+
+        write(&link, "A=2\n", None).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&real).unwrap(), "A=2\n");
+        let mode = fs::metadata(&real).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! An on-disk cache for remote sources (`git::`, `k8s://`, cloud storage,
+//! `vault://`, `etcd://`, `consul://`), so a script querying the same URI
+//! many times fetches it once per TTL.
+//!
+//! It is off unless `--cache-ttl` or `CONFCTL_CACHE_TTL` gives a lifetime;
+//! `--no-cache` turns it off again for one command. Entries live under
+//! `$XDG_CACHE_HOME/confctl/sources` (`CONFCTL_CACHE_DIR` overrides it),
+//! one file per URI and read kind named by a SHA-256 of both, readable by
+//! the owner only since they can hold secrets. Where the URI leaves the
+//! endpoint to the environment (`VAULT_ADDR`, kubectl's current context,
+//! `ETCDCTL_ENDPOINTS`, `CONSUL_HTTP_ADDR`), the endpoint is hashed in too,
+//! so switching environments never serves the previous one's data. An entry older than the TTL
+//! is fetched again; a cache that cannot be written only warns.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::formats::ParseOptions;
+use crate::input::InputSource;
+use crate::Format;
+
+/// Env var with the default TTL, as `--cache-ttl` takes it.
+pub(crate) const TTL_ENV: &str = "CONFCTL_CACHE_TTL";
+/// Env var naming the cache directory.
+const DIR_ENV: &str = "CONFCTL_CACHE_DIR";
+
+static TTL: OnceLock<Option<Duration>> = OnceLock::new();
+
+/// Set the TTL for this process; `None` (or zero) disables the cache.
+pub(crate) fn configure(ttl: Option<Duration>) {
+    let _ = TTL.set(ttl.filter(|ttl| !ttl.is_zero()));
+}
+
+/// `source` behind the cache, when it is on and a directory exists for it.
+pub(crate) fn wrap(uri: &str, source: Box<dyn InputSource>) -> Box<dyn InputSource> {
+    match (TTL.get().copied().flatten(), dir()) {
+        (Some(ttl), Some(dir)) => Box::new(Cached {
+            uri: uri.to_string(),
+            scope: scope(uri),
+            source,
+            ttl,
+            dir,
+        }),
+        _ => source,
+    }
+}
+
+/// What picks the data `uri` names besides the URI itself: the endpoint
+/// and context it takes from the environment.
+fn scope(uri: &str) -> String {
+    if uri.starts_with(crate::input::VAULT_KV) {
+        let env = |name| std::env::var(name).unwrap_or_default();
+        format!("{} {}", env("VAULT_ADDR"), env("VAULT_NAMESPACE"))
+    } else if uri.starts_with(crate::k8s::PREFIX) {
+        crate::k8s::cluster(uri)
+    } else if crate::kvstore::is_uri(uri) {
+        crate::kvstore::endpoint(uri).unwrap_or_default()
+    } else {
+        String::new()
+    }
+}
+
+fn dir() -> Option<PathBuf> {
+    match std::env::var_os(DIR_ENV) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => dirs::cache_dir().map(|dir| dir.join("confctl").join("sources")),
+    }
+}
+
+struct Cached {
+    uri: String,
+    /// The environment's part in what `uri` names; see `scope`.
+    scope: String,
+    source: Box<dyn InputSource>,
+    ttl: Duration,
+    dir: PathBuf,
+}
+
+impl InputSource for Cached {
+    /// Stored as the name, a newline, then the bytes.
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let entry = self.entry("read");
+        if let Some(stored) = fresh(&entry, self.ttl) {
+            if let Some(newline) = stored.iter().position(|&b| b == b'\n') {
+                let name = String::from_utf8_lossy(&stored[..newline]).into_owned();
+                return Ok((name, stored[newline + 1..].to_vec()));
+            }
+        }
+        let (name, bytes) = self.source.read()?;
+        let mut stored = format!("{name}\n").into_bytes();
+        stored.extend_from_slice(&bytes);
+        self.store(&entry, &stored);
+        Ok((name, bytes))
+    }
+
+    /// Stored as the parsed document's JSON, keyed by the format and
+    /// options it was parsed with too.
+    fn load(&self, format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        let entry = self.entry(&format!("load {format:?} {opts:?}"));
+        if let Some(stored) = fresh(&entry, self.ttl) {
+            if let Ok(document) = serde_json::from_slice(&stored) {
+                return Ok(document);
+            }
+        }
+        let document = self.source.load(format, opts)?;
+        self.store(&entry, &serde_json::to_vec(&document)?);
+        Ok(document)
+    }
+}
+
+impl Cached {
+    fn entry(&self, kind: &str) -> PathBuf {
+        let key = format!("{}\n{}\n{kind}", self.uri, self.scope);
+        let digest = crate::hash::sha2::sha256(key.as_bytes());
+        let name: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+        self.dir.join(name)
+    }
+
+    fn store(&self, entry: &Path, contents: &[u8]) {
+        if let Err(err) = store(&self.dir, entry, contents) {
+            eprintln!(
+                "warning: could not cache {} in {}: {err}",
+                self.uri,
+                self.dir.display()
+            );
+        }
+    }
+}
+
+/// The entry's contents, when it was written less than `ttl` ago.
+fn fresh(entry: &Path, ttl: Duration) -> Option<Vec<u8>> {
+    let modified = fs::metadata(entry).ok()?.modified().ok()?;
+    let age = SystemTime::now().duration_since(modified).ok()?;
+    if age >= ttl {
+        return None;
+    }
+    fs::read(entry).ok()
+}
+
+/// Write `entry` through a temporary file, so a reader never sees half of
+/// it; the directory is private and the file owner-only on unix.
+fn store(dir: &Path, entry: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+        builder.mode(0o700);
+        options.mode(0o600);
+    }
+    builder.create(dir)?;
+    let temp = entry.with_extension(format!("tmp{}", std::process::id()));
+    let written = options
+        .open(&temp)
+        .and_then(|mut file| file.write_all(contents))
+        .and_then(|()| fs::rename(&temp, entry));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Counting(Rc<Cell<u32>>);
+
+    impl InputSource for Counting {
+        fn read(&self) -> Result<(String, Vec<u8>)> {
+            self.0.set(self.0.get() + 1);
+            Ok(("app.json".into(), br#"{"port": 8080}"#.to_vec()))
+        }
+    }
+
+    fn cached(dir: &Path, ttl: Duration, fetches: &Rc<Cell<u32>>) -> Cached {
+        Cached {
+            uri: "s3://configs/app.json".into(),
+            scope: String::new(),
+            source: Box::new(Counting(fetches.clone())),
+            ttl,
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    #[test]
+    fn entries_are_reused_until_they_expire() {
+        let dir = std::env::temp_dir().join(format!("confctl-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let fetches = Rc::new(Cell::new(0));
+
+        let source = cached(&dir, Duration::from_secs(60), &fetches);
+        let opts = ParseOptions::default();
+        assert_eq!(source.read().unwrap().0, "app.json");
+        assert_eq!(source.load(None, &opts).unwrap()["port"], 8080);
+        assert_eq!(fetches.get(), 2);
+        let again = cached(&dir, Duration::from_secs(60), &fetches);
+        assert_eq!(again.read().unwrap().1, br#"{"port": 8080}"#);
+        assert_eq!(again.load(None, &opts).unwrap()["port"], 8080);
+        assert_eq!(fetches.get(), 2);
+        // Other parse options are another entry.
+        let typed = ParseOptions {
+            infer_types: true,
+            ..ParseOptions::default()
+        };
+        again.load(None, &typed).unwrap();
+        assert_eq!(fetches.get(), 3);
+
+        let expired = cached(&dir, Duration::from_nanos(1), &fetches);
+        expired.read().unwrap();
+        assert_eq!(fetches.get(), 4);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(expired.entry("read"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_endpoint_is_part_of_the_entry() {
+        let fetches = Rc::new(Cell::new(0));
+        let dir = Path::new("/nonexistent");
+        let mut prod = cached(dir, Duration::from_secs(60), &fetches);
+        prod.scope = "https://vault.prod:8200 ".into();
+        let mut staging = cached(dir, Duration::from_secs(60), &fetches);
+        staging.scope = "https://vault.staging:8200 ".into();
+        assert_ne!(prod.entry("read"), staging.entry("read"));
+
+        assert_eq!(
+            scope("etcd://10.0.0.5/app/config.json"),
+            "http://10.0.0.5:2379"
+        );
+        assert_eq!(
+            scope("consul+https://kv.local:8501/kv/app.json"),
+            "https://kv.local:8501"
+        );
+        assert_eq!(scope("s3://configs/app.json"), "");
+    }
+}
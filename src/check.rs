@@ -0,0 +1,504 @@
+//! `confctl check` — parse every file given (directories are searched for
+//! config files) and optionally validate them against JSON Schemas, with a
+//! line per file and exit 1 when any fails. Made for pre-commit hooks, which
+//! pass the changed files as arguments.
+//!
+//! `--schema FILE` validates every file against one schema; `--schemas FILE`
+//! names a document mapping file globs to schemas, e.g. in YAML:
+//!
+//! ```yaml
+//! "deploy/*.yaml": schemas/deploy.json
+//! "**/values.yaml": schemas/values.json
+//! ```
+//!
+//! A glob without a `/` matches the file name in any directory; schema
+//! paths are relative to the mapping file. Every matching schema applies.
+//!
+//! Validation covers the common keywords: `type`, `enum`, `const`,
+//! `properties`, `required`, `additionalProperties`, `items`, `minItems`,
+//! `maxItems`, `uniqueItems`, `minimum`, `maximum`, the exclusive bounds,
+//! `minLength`, `maxLength`, `pattern` (see `crate::regex`), `allOf`,
+//! `anyOf`, `oneOf`, `not` and `$ref` within the schema. Other keywords are
+//! ignored.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use serde_json::Value;
+
+use crate::aggregate::type_name;
+use crate::convert::files_under;
+use crate::formats::ParseOptions;
+use crate::regex::Regex;
+use crate::{glob, parse_file, path, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct CheckCli {
+    /// Files to check, or directories to search for config files (hidden
+    /// directories are skipped).
+    #[arg(required = true)]
+    pub(crate) files: Vec<String>,
+
+    /// Validate every file against this JSON Schema (in any input format).
+    #[arg(long, value_name = "FILE", conflicts_with = "schemas")]
+    pub(crate) schema: Option<String>,
+
+    /// A document mapping file globs to JSON Schemas to validate them with.
+    #[arg(long, value_name = "FILE")]
+    pub(crate) schemas: Option<String>,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Print only the files that fail.
+    #[arg(short = 'q', long)]
+    pub(crate) quiet: bool,
+}
+
+/// A schema and the globs of the files it validates (none: every file).
+struct Schema {
+    name: String,
+    globs: Vec<String>,
+    document: Value,
+}
+
+/// Prints a line per file; `true` when any failed.
+pub(crate) fn run(cli: CheckCli) -> Result<bool> {
+    let schemas = load_schemas(&cli)?;
+    let mut files = Vec::new();
+    for arg in &cli.files {
+        if Path::new(arg).is_dir() {
+            let found = files_under(Path::new(arg), &|_| true)?;
+            files.extend(found.iter().map(|path| path.display().to_string()));
+        } else {
+            files.push(arg.clone());
+        }
+    }
+    if files.is_empty() {
+        bail!("no config files to check in {}", cli.files.join(", "));
+    }
+    let mut failed = 0;
+    for file in &files {
+        match check_file(file, cli.format, &schemas) {
+            Ok(problems) if problems.is_empty() => {
+                if !cli.quiet {
+                    println!("{} {file}", "✓".green().bold());
+                }
+            }
+            Ok(problems) => {
+                failed += 1;
+                println!("{} {file}", "✗".red().bold());
+                for problem in problems {
+                    println!("    {problem}");
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                println!("{} {file}: {err:#}", "✗".red().bold());
+            }
+        }
+    }
+    if failed > 0 {
+        println!(
+            "{failed} of {} file{} failed",
+            files.len(),
+            if files.len() == 1 { "" } else { "s" }
+        );
+    }
+    Ok(failed > 0)
+}
+
+fn load_schemas(cli: &CheckCli) -> Result<Vec<Schema>> {
+    let read = |file: &str| {
+        parse_file(file, None, &ParseOptions::default())
+            .with_context(|| format!("reading the schema {file}"))
+    };
+    if let Some(file) = &cli.schema {
+        return Ok(vec![Schema {
+            name: file.clone(),
+            globs: Vec::new(),
+            document: read(file)?,
+        }]);
+    }
+    let Some(mapping_file) = &cli.schemas else {
+        return Ok(Vec::new());
+    };
+    let mapping = parse_file(mapping_file, None, &ParseOptions::default())
+        .with_context(|| format!("reading {mapping_file}"))?;
+    let Value::Object(mapping) = mapping else {
+        bail!(
+            "{mapping_file} should map file globs to schema files, got {}",
+            type_name(&mapping)
+        );
+    };
+    let base = Path::new(mapping_file).parent().unwrap_or(Path::new(""));
+    let mut schemas: Vec<Schema> = Vec::new();
+    for (pattern, target) in mapping {
+        let Value::String(target) = target else {
+            bail!(
+                "{mapping_file}: `{pattern}` should name a schema file, got {}",
+                type_name(&target)
+            );
+        };
+        let name = schema_path(base, &target).display().to_string();
+        match schemas.iter_mut().find(|schema| schema.name == name) {
+            Some(schema) => schema.globs.push(pattern),
+            None => schemas.push(Schema {
+                document: read(&name)?,
+                name,
+                globs: vec![pattern],
+            }),
+        }
+    }
+    Ok(schemas)
+}
+
+fn schema_path(base: &Path, target: &str) -> PathBuf {
+    if Path::new(target).is_absolute() {
+        PathBuf::from(target)
+    } else {
+        base.join(target)
+    }
+}
+
+/// The schema problems of `file`, each naming its schema; an error when the
+/// file does not parse.
+fn check_file(file: &str, format: Option<Format>, schemas: &[Schema]) -> Result<Vec<String>> {
+    let value = parse_file(file, format, &ParseOptions::default())?;
+    let mut problems = Vec::new();
+    for schema in schemas {
+        let applies = schema.globs.is_empty()
+            || schema
+                .globs
+                .iter()
+                .any(|pattern| glob::matches_path(pattern, file));
+        if applies {
+            problems.extend(
+                validate(&value, &schema.document)
+                    .into_iter()
+                    .map(|problem| format!("{problem} ({})", schema.name)),
+            );
+        }
+    }
+    Ok(problems)
+}
+
+/// Every way `value` breaks `schema`, as `path: problem` lines.
+fn validate(value: &Value, schema: &Value) -> Vec<String> {
+    let mut problems = Vec::new();
+    validate_at(value, schema, schema, &mut Vec::new(), &mut problems);
+    problems
+}
+
+fn validate_at(
+    value: &Value,
+    schema: &Value,
+    root: &Value,
+    trail: &mut Vec<String>,
+    problems: &mut Vec<String>,
+) {
+    let at = if trail.is_empty() {
+        "the document".to_string()
+    } else {
+        path::join(trail)
+    };
+    let schema = match schema {
+        Value::Bool(false) => return problems.push(format!("{at}: no value is allowed here")),
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+        {
+            Some(target) => validate_at(value, target, root, trail, problems),
+            None => problems.push(format!("{at}: cannot resolve $ref `{reference}`")),
+        }
+    }
+    {
+        let mut fail = |problem: String| problems.push(format!("{at}: {problem}"));
+        if let Some(types) = schema.get("type") {
+            let names: Vec<&str> = match types {
+                Value::String(name) => vec![name],
+                Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !names.is_empty() && !names.iter().any(|name| has_type(value, name)) {
+                fail(format!(
+                    "expected {}, got {}",
+                    names.join(" or "),
+                    type_name(value)
+                ));
+                return;
+            }
+        }
+        if let Some(Value::Array(options)) = schema.get("enum") {
+            if !options.contains(value) {
+                fail(format!(
+                    "{} is not one of {}",
+                    compact(value),
+                    compact(&Value::Array(options.clone()))
+                ));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != value {
+                fail(format!(
+                    "expected {}, got {}",
+                    compact(expected),
+                    compact(value)
+                ));
+            }
+        }
+        let limit = |key: &str| schema.get(key).and_then(Value::as_f64);
+        match value {
+            Value::Number(number) => {
+                let n = number.as_f64().unwrap_or(f64::NAN);
+                let bounds = [
+                    ("minimum", "at least"),
+                    ("maximum", "at most"),
+                    ("exclusiveMinimum", "more than"),
+                    ("exclusiveMaximum", "less than"),
+                ];
+                for (key, words) in bounds {
+                    let holds = |bound: f64| match key {
+                        "minimum" => n >= bound,
+                        "maximum" => n <= bound,
+                        "exclusiveMinimum" => n > bound,
+                        _ => n < bound,
+                    };
+                    if limit(key).is_some_and(|bound| !holds(bound)) {
+                        fail(format!("{number} should be {words} {}", schema[key]));
+                    }
+                }
+            }
+            Value::String(text) => {
+                let length = text.chars().count() as f64;
+                if limit("minLength").is_some_and(|min| length < min) {
+                    fail(format!(
+                        "length {length}, at least {} expected",
+                        schema["minLength"]
+                    ));
+                }
+                if limit("maxLength").is_some_and(|max| length > max) {
+                    fail(format!(
+                        "length {length}, at most {} expected",
+                        schema["maxLength"]
+                    ));
+                }
+                if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                    match Regex::new(pattern, false) {
+                        Ok(regex) if regex.is_match(text) => {}
+                        Ok(_) => fail(format!("{} does not match `{pattern}`", compact(value))),
+                        Err(err) => fail(format!(
+                            "the schema's pattern `{pattern}` is invalid: {err}"
+                        )),
+                    }
+                }
+            }
+            Value::Array(items) => {
+                let count = items.len() as f64;
+                if limit("minItems").is_some_and(|min| count < min) {
+                    fail(format!(
+                        "{count} items, at least {} expected",
+                        schema["minItems"]
+                    ));
+                }
+                if limit("maxItems").is_some_and(|max| count > max) {
+                    fail(format!(
+                        "{count} items, at most {} expected",
+                        schema["maxItems"]
+                    ));
+                }
+                if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+                    let repeated = items
+                        .iter()
+                        .enumerate()
+                        .find(|(index, item)| items[..*index].contains(item));
+                    if let Some((index, _)) = repeated {
+                        fail(format!("item {index} repeats an earlier item"));
+                    }
+                }
+            }
+            Value::Object(map) => {
+                if let Some(Value::Array(required)) = schema.get("required") {
+                    for key in required.iter().filter_map(Value::as_str) {
+                        if !map.contains_key(key) {
+                            fail(format!("missing required key `{key}`"));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema.get("additionalProperties");
+            for (key, child) in map {
+                let sub = match properties.and_then(|p| p.get(key)) {
+                    Some(sub) => sub,
+                    None => match additional {
+                        Some(Value::Bool(false)) => {
+                            problems.push(format!("{at}: unexpected key `{key}`"));
+                            continue;
+                        }
+                        Some(sub) => sub,
+                        None => continue,
+                    },
+                };
+                trail.push(key.clone());
+                validate_at(child, sub, root, trail, problems);
+                trail.pop();
+            }
+        }
+        Value::Array(items) => {
+            if let Some(sub) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    trail.push(index.to_string());
+                    validate_at(item, sub, root, trail, problems);
+                    trail.pop();
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(all)) = schema.get("allOf") {
+        for sub in all {
+            validate_at(value, sub, root, trail, problems);
+        }
+    }
+    let passes = |sub: &Value| {
+        let mut found = Vec::new();
+        validate_at(value, sub, root, &mut trail.clone(), &mut found);
+        found.is_empty()
+    };
+    if let Some(Value::Array(any)) = schema.get("anyOf") {
+        if !any.iter().any(passes) {
+            problems.push(format!("{at}: matches none of the anyOf schemas"));
+        }
+    }
+    if let Some(Value::Array(one)) = schema.get("oneOf") {
+        let matched = one.iter().filter(|sub| passes(sub)).count();
+        if matched != 1 {
+            problems.push(format!(
+                "{at}: matches {matched} of the oneOf schemas, not exactly one"
+            ));
+        }
+    }
+    if let Some(not) = schema.get("not") {
+        if passes(not) {
+            problems.push(format!("{at}: matches the schema under `not`"));
+        }
+    }
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "number" => value.is_number(),
+        "integer" => value
+            .as_f64()
+            .is_some_and(|n| value.is_i64() || value.is_u64() || n.fract() == 0.0),
+        _ => true,
+    }
+}
+
+fn compact(value: &Value) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validation_names_each_problem_by_path() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "port"],
+            "additionalProperties": false,
+            "properties": {
+                "name": {"type": "string", "minLength": 2, "pattern": "^[a-z]+$"},
+                "port": {"type": "integer", "minimum": 1, "maximum": 65535},
+                "env": {"enum": ["dev", "prod"]},
+                "hosts": {"type": "array", "items": {"type": "string"}, "uniqueItems": true}
+            }
+        });
+        let good = json!({"name": "api", "port": 8080, "env": "prod", "hosts": ["a", "b"]});
+        assert!(validate(&good, &schema).is_empty());
+        let bad =
+            json!({"name": "A", "port": 70000.0, "env": "qa", "hosts": ["a", 1, "a"], "x": 1});
+        assert_eq!(
+            validate(&bad, &schema),
+            [
+                "env: \"qa\" is not one of [\"dev\",\"prod\"]",
+                "hosts: item 2 repeats an earlier item",
+                "hosts.1: expected string, got a number",
+                "name: length 1, at least 2 expected",
+                "name: \"A\" does not match `^[a-z]+$`",
+                "port: 70000.0 should be at most 65535",
+                "the document: unexpected key `x`",
+            ]
+        );
+        assert_eq!(
+            validate(&json!({"name": "ok"}), &schema),
+            ["the document: missing required key `port`"]
+        );
+    }
+
+    #[test]
+    fn combinators_and_refs() {
+        let schema = json!({
+            "$defs": {"port": {"type": "integer", "exclusiveMinimum": 0}},
+            "properties": {
+                "port": {"$ref": "#/$defs/port"},
+                "tls": {"anyOf": [{"type": "boolean"}, {"required": ["cert"]}]},
+                "mode": {"oneOf": [{"const": "a"}, {"type": "string", "maxLength": 1}]},
+                "name": {"not": {"const": "root"}},
+                "bad": {"$ref": "#/$defs/missing"}
+            }
+        });
+        let value = json!({"port": 0, "tls": {}, "mode": "a", "name": "root", "bad": 1});
+        assert_eq!(
+            validate(&value, &schema),
+            [
+                "bad: cannot resolve $ref `#/$defs/missing`",
+                "mode: matches 2 of the oneOf schemas, not exactly one",
+                "name: matches the schema under `not`",
+                "port: 0 should be more than 0",
+                "tls: matches none of the anyOf schemas",
+            ]
+        );
+        assert!(validate(&json!({"port": 3, "tls": true, "mode": "b"}), &schema).is_empty());
+    }
+
+    #[test]
+    fn files_are_parsed_and_matched_to_schemas() {
+        let schemas = vec![Schema {
+            name: "clubs.json".to_string(),
+            globs: vec!["config.json".to_string()],
+            document: json!({"properties": {"clubs": {"maxItems": 1}}}),
+        }];
+        assert!(check_file("testdata/config.yaml", None, &schemas)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            check_file("testdata/config.json", None, &schemas).unwrap(),
+            ["clubs: 4 items, at most 1 expected (clubs.json)"]
+        );
+        assert!(check_file("testdata/missing.json", None, &[]).is_err());
+    }
+}
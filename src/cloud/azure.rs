@@ -0,0 +1,284 @@
+//! `az://CONTAINER/BLOB` through the Blob service REST API. The storage
+//! account is `AZURE_STORAGE_ACCOUNT` or the connection string's, or is
+//! written in the URI as `az://ACCOUNT.blob.core.windows.net/CONTAINER/BLOB`.
+//!
+//! Credentials are looked up in the Azure CLI and SDKs' order:
+//!   1. `AZURE_STORAGE_CONNECTION_STRING` (an account key or a SAS).
+//!   2. `AZURE_STORAGE_SAS_TOKEN`, then `AZURE_STORAGE_KEY` (Shared Key).
+//!   3. A service principal: `AZURE_TENANT_ID`, `AZURE_CLIENT_ID` and
+//!      `AZURE_CLIENT_SECRET`.
+//!   4. `az account get-access-token` for the signed-in user.
+//!   5. Managed identity, through the instance metadata service.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use serde::Deserialize;
+
+use super::{body, cli_output, encode, metadata_agent, xml_error};
+use crate::hash::sha2::hmac_sha256;
+use crate::net::{self, agent};
+
+/// The Blob service version requests ask for; bearer tokens need 2017-11-09
+/// or later.
+const API_VERSION: &str = "2021-08-06";
+const RESOURCE: &str = "https://storage.azure.com/";
+const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+#[derive(Debug, PartialEq)]
+struct Location {
+    account: String,
+    endpoint: String,
+    /// `/CONTAINER/BLOB`, encoded.
+    path: String,
+}
+
+enum Auth {
+    Sas(String),
+    SharedKey(Vec<u8>),
+    Bearer(String),
+}
+
+/// The blob's bytes.
+pub(super) fn get(container: &str, blob: &str) -> Result<Vec<u8>> {
+    let connection = std::env::var("AZURE_STORAGE_CONNECTION_STRING")
+        .map(|text| connection_string(&text))
+        .unwrap_or_default();
+    let location = locate(container, blob, &connection)?;
+    let auth = auth(&location.account, &connection)?;
+    let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let url = match &auth {
+        Auth::Sas(sas) => format!(
+            "{}{}?{}",
+            location.endpoint,
+            location.path,
+            sas.trim_start_matches('?')
+        ),
+        _ => format!("{}{}", location.endpoint, location.path),
+    };
+    let request = agent()
+        .get(&url)
+        .set("x-ms-date", &date)
+        .set("x-ms-version", API_VERSION);
+    let request = match &auth {
+        Auth::Sas(_) => request,
+        Auth::SharedKey(key) => {
+            let to_sign = string_to_sign(&location.account, &date, &location.path);
+            let signature = STANDARD.encode(hmac_sha256(key, to_sign.as_bytes()));
+            request.set(
+                "Authorization",
+                &format!("SharedKey {}:{signature}", location.account),
+            )
+        }
+        Auth::Bearer(token) => request.set("Authorization", &format!("Bearer {token}")),
+    };
+    net::call(&request)
+        .map_err(|failed| failed.describe(|err| xml_error("Azure Storage", err)))
+        .and_then(body)
+}
+
+/// Where the blob lives: the account in the URI when its first segment is
+/// a host name, else the configured one.
+fn locate(container: &str, blob: &str, connection: &HashMap<String, String>) -> Result<Location> {
+    if container.contains('.') {
+        let host = container;
+        let Some((container, blob)) = blob.split_once('/').filter(|(_, b)| !b.is_empty()) else {
+            bail!("`az://{host}/{blob}` names no blob: write az://HOST/CONTAINER/BLOB");
+        };
+        return Ok(Location {
+            account: host.split('.').next().unwrap_or(host).to_string(),
+            endpoint: format!("https://{host}"),
+            path: format!("/{}/{}", encode(container, false), encode(blob, true)),
+        });
+    }
+    let account = std::env::var("AZURE_STORAGE_ACCOUNT")
+        .ok()
+        .filter(|account| !account.is_empty())
+        .or_else(|| connection.get("AccountName").cloned())
+        .context(
+            "no storage account for az:// inputs: set AZURE_STORAGE_ACCOUNT or write \
+             az://ACCOUNT.blob.core.windows.net/CONTAINER/BLOB",
+        )?;
+    let endpoint = match connection.get("BlobEndpoint") {
+        Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+        None => format!(
+            "{}://{account}.blob.{}",
+            connection
+                .get("DefaultEndpointsProtocol")
+                .map_or("https", String::as_str),
+            connection
+                .get("EndpointSuffix")
+                .map_or("core.windows.net", String::as_str)
+        ),
+    };
+    Ok(Location {
+        account,
+        endpoint,
+        path: format!("/{}/{}", encode(container, false), encode(blob, true)),
+    })
+}
+
+/// The `Name=value` pairs of a connection string.
+fn connection_string(text: &str) -> HashMap<String, String> {
+    text.split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn auth(account: &str, connection: &HashMap<String, String>) -> Result<Auth> {
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let same_account = connection
+        .get("AccountName")
+        .is_none_or(|name| name == account);
+    if same_account {
+        if let Some(sas) = connection.get("SharedAccessSignature") {
+            return Ok(Auth::Sas(sas.clone()));
+        }
+        if let Some(key) = connection.get("AccountKey") {
+            return shared_key(key);
+        }
+    }
+    if let Some(sas) = env("AZURE_STORAGE_SAS_TOKEN") {
+        return Ok(Auth::Sas(sas));
+    }
+    if let Some(key) = env("AZURE_STORAGE_KEY") {
+        return shared_key(&key);
+    }
+    if let (Some(tenant), Some(client_id), Some(secret)) = (
+        env("AZURE_TENANT_ID"),
+        env("AZURE_CLIENT_ID"),
+        env("AZURE_CLIENT_SECRET"),
+    ) {
+        return service_principal_token(&tenant, &client_id, &secret).map(Auth::Bearer);
+    }
+    let cli = cli_output(
+        "az",
+        &[
+            "account",
+            "get-access-token",
+            "--resource",
+            RESOURCE,
+            "--query",
+            "accessToken",
+            "--output",
+            "tsv",
+        ],
+    );
+    if let Some(token) = cli.or_else(managed_identity_token) {
+        return Ok(Auth::Bearer(token));
+    }
+    bail!(
+        "no Azure credentials found. Set AZURE_STORAGE_CONNECTION_STRING or AZURE_STORAGE_KEY, \
+         run `az login`, or run with a managed identity."
+    )
+}
+
+fn shared_key(key: &str) -> Result<Auth> {
+    let key = STANDARD
+        .decode(key)
+        .context("the storage account key is not base64")?;
+    Ok(Auth::SharedKey(key))
+}
+
+fn service_principal_token(tenant: &str, client_id: &str, secret: &str) -> Result<String> {
+    let url = format!("https://login.microsoftonline.com/{tenant}/oauth2/v2.0/token");
+    let scope = format!("{RESOURCE}.default");
+    let response = agent()
+        .post(&url)
+        .send_form(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", client_id),
+            ("client_secret", secret),
+            ("scope", &scope),
+        ])
+        .map_err(|err| match err {
+            ureq::Error::Status(status, response) => {
+                let body = response.into_string().unwrap_or_default();
+                let reason = serde_json::from_str::<serde_json::Value>(&body)
+                    .ok()
+                    .and_then(|v| v["error_description"].as_str().map(str::to_string))
+                    .unwrap_or(body);
+                let reason = reason.lines().next().unwrap_or_default().to_string();
+                anyhow::anyhow!(
+                    "signing in as service principal {client_id} returned {status}: {reason}"
+                )
+            }
+            ureq::Error::Transport(t) => anyhow::anyhow!("transport error against Azure AD: {t}"),
+        })?;
+    let token: TokenResponse = response.into_json().context("parsing Azure AD token")?;
+    Ok(token.access_token)
+}
+
+fn managed_identity_token() -> Option<String> {
+    let response = metadata_agent()
+        .get(IMDS_TOKEN_URL)
+        .query("api-version", "2018-02-01")
+        .query("resource", RESOURCE)
+        .set("Metadata", "true")
+        .call()
+        .ok()?;
+    response
+        .into_json::<TokenResponse>()
+        .ok()
+        .map(|token| token.access_token)
+}
+
+/// What Shared Key signs for a GET without content headers: the verb, eleven
+/// empty standard headers, the `x-ms-` headers and the resource.
+fn string_to_sign(account: &str, date: &str, path: &str) -> String {
+    format!(
+        "GET\n{}x-ms-date:{date}\nx-ms-version:{API_VERSION}\n/{account}{path}",
+        "\n".repeat(11)
+    )
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blobs_are_located_from_uri_or_connection_string() {
+        let connection = connection_string(
+            "DefaultEndpointsProtocol=https;AccountName=acme;AccountKey=a2V5;\
+             EndpointSuffix=core.windows.net",
+        );
+        assert_eq!(connection["AccountKey"], "a2V5");
+        let location = locate("configs", "prod/app settings.json", &connection).unwrap();
+        assert_eq!(
+            location,
+            Location {
+                account: "acme".into(),
+                endpoint: "https://acme.blob.core.windows.net".into(),
+                path: "/configs/prod/app%20settings.json".into(),
+            }
+        );
+        let location = locate(
+            "other.blob.core.windows.net",
+            "configs/app.yaml",
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(location.account, "other");
+        assert_eq!(location.endpoint, "https://other.blob.core.windows.net");
+        assert_eq!(location.path, "/configs/app.yaml");
+        assert!(locate("other.blob.core.windows.net", "configs", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn shared_key_signs_the_canonical_request() {
+        let date = "Fri, 26 Jun 2015 23:39:12 GMT";
+        assert_eq!(
+            string_to_sign("acme", date, "/configs/app.yaml"),
+            "GET\n\n\n\n\n\n\n\n\n\n\n\nx-ms-date:Fri, 26 Jun 2015 23:39:12 GMT\n\
+             x-ms-version:2021-08-06\n/acme/configs/app.yaml"
+        );
+    }
+}
@@ -0,0 +1,38 @@
+//! `gs://BUCKET/OBJECT` through the Cloud Storage JSON API, with an access
+//! token from Application Default Credentials (see `google`).
+//! `STORAGE_EMULATOR_HOST` points at a local emulator, as the Google client
+//! libraries allow.
+
+use anyhow::Result;
+
+use super::google::{self, api_error};
+use super::{body, encode};
+use crate::net::{self, agent};
+
+const API_BASE: &str = "https://storage.googleapis.com";
+
+/// The object's bytes.
+pub(super) fn get(bucket: &str, object: &str) -> Result<Vec<u8>> {
+    let agent = agent();
+    let emulator = std::env::var("STORAGE_EMULATOR_HOST")
+        .ok()
+        .filter(|host| !host.is_empty());
+    let base = match &emulator {
+        Some(host) if host.contains("://") => host.trim_end_matches('/').to_string(),
+        Some(host) => format!("http://{host}"),
+        None => API_BASE.to_string(),
+    };
+    let url = format!(
+        "{base}/storage/v1/b/{}/o/{}?alt=media",
+        encode(bucket, false),
+        encode(object, false)
+    );
+    let mut request = agent.get(&url);
+    if emulator.is_none() {
+        let token = google::access_token(&agent, None)?;
+        request = request.set("Authorization", &format!("Bearer {token}"));
+    }
+    net::call(&request)
+        .map_err(|failed| failed.describe(api_error))
+        .and_then(body)
+}
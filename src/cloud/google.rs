@@ -0,0 +1,167 @@
+//! Google access tokens from Application Default Credentials, for `gs://`
+//! inputs and the vault `gcp` backend. Nothing is cached by confctl.
+//! Discovery order:
+//!   1. `CLOUDSDK_AUTH_ACCESS_TOKEN` env var (gcloud's own override).
+//!   2. Credentials JSON (authorized_user): the caller's explicit file →
+//!      `GOOGLE_APPLICATION_CREDENTIALS` → the gcloud ADC file at
+//!      ~/.config/gcloud/application_default_credentials.json. The
+//!      refresh_token is exchanged at https://oauth2.googleapis.com/token.
+//!   3. `gcloud auth application-default print-access-token`, then
+//!      `gcloud auth print-access-token` (covers service accounts too).
+//!   4. GCE metadata server (inside GCP compute).
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+
+const OAUTH_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+
+/// An access token from the first source above that has one;
+/// `credentials_file` comes before `GOOGLE_APPLICATION_CREDENTIALS`.
+pub(crate) fn access_token(
+    agent: &ureq::Agent,
+    credentials_file: Option<PathBuf>,
+) -> Result<String> {
+    if let Ok(t) = std::env::var("CLOUDSDK_AUTH_ACCESS_TOKEN") {
+        if !t.is_empty() {
+            return Ok(t);
+        }
+    }
+    if let Some(path) = credentials_file.or_else(adc_credentials_path) {
+        match token_from_adc_file(agent, &path) {
+            Ok(t) => return Ok(t),
+            Err(err) => eprintln!(
+                "warning: ADC credentials at {} unusable ({err}); trying gcloud CLI.",
+                path.display()
+            ),
+        }
+    }
+    let gcloud_arg_sets: [&[&str]; 2] = [
+        &["auth", "application-default", "print-access-token"],
+        &["auth", "print-access-token"],
+    ];
+    for args in gcloud_arg_sets {
+        if let Some(t) = gcloud(args) {
+            return Ok(t);
+        }
+    }
+    if let Ok(t) = token_from_metadata_server() {
+        return Ok(t);
+    }
+    bail!(
+        "no Google credentials found. Run `gcloud auth application-default login`, \
+         set GOOGLE_APPLICATION_CREDENTIALS, or run inside GCP."
+    )
+}
+
+/// `GOOGLE_APPLICATION_CREDENTIALS`, else gcloud's ADC file when it exists.
+pub(crate) fn adc_credentials_path() -> Option<PathBuf> {
+    if let Ok(p) = std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+        if !p.is_empty() {
+            return Some(PathBuf::from(p));
+        }
+    }
+    let default = dirs::config_dir()?
+        .join("gcloud")
+        .join("application_default_credentials.json");
+    default.exists().then_some(default)
+}
+
+/// Exchange an `authorized_user` ADC refresh_token for an access token.
+/// Service-account JSONs need RS256 signing — those are served by the
+/// gcloud-CLI fallback instead.
+fn token_from_adc_file(agent: &ureq::Agent, path: &Path) -> Result<String> {
+    let body = std::fs::read_to_string(path)
+        .with_context(|| format!("reading credentials at {}", path.display()))?;
+    let creds: AdcCredentials = serde_json::from_str(&body)
+        .with_context(|| format!("parsing credentials at {}", path.display()))?;
+    if creds.credential_type.as_deref() != Some("authorized_user") {
+        bail!(
+            "credentials type {:?} is not `authorized_user`",
+            creds.credential_type.as_deref().unwrap_or("unknown")
+        );
+    }
+    let resp = agent
+        .post(OAUTH_TOKEN_URL)
+        .send_json(json!({
+            "client_id": creds.client_id,
+            "client_secret": creds.client_secret,
+            "refresh_token": creds.refresh_token,
+            "grant_type": "refresh_token",
+        }))
+        .map_err(api_error)?;
+    let token: OauthTokenResponse = resp.into_json().context("parsing oauth token response")?;
+    Ok(token.access_token)
+}
+
+fn token_from_metadata_server() -> Result<String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(std::time::Duration::from_millis(500))
+        .timeout(std::time::Duration::from_secs(3))
+        .build();
+    let resp = agent
+        .get(METADATA_TOKEN_URL)
+        .set("Metadata-Flavor", "Google")
+        .call()
+        .map_err(api_error)?;
+    let token: OauthTokenResponse = resp
+        .into_json()
+        .context("parsing metadata server token response")?;
+    Ok(token.access_token)
+}
+
+/// The trimmed stdout of `gcloud ARGS`, when it succeeds with some.
+pub(crate) fn gcloud(args: &[&str]) -> Option<String> {
+    let output = Command::new("gcloud").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!token.is_empty()).then_some(token)
+}
+
+/// A failed Google API call, with the message from its JSON error body.
+pub(crate) fn api_error(err: ureq::Error) -> anyhow::Error {
+    match err {
+        ureq::Error::Status(status, resp) => {
+            let body = resp
+                .into_string()
+                .unwrap_or_else(|_| format!("HTTP {status}"));
+            // Google errors look like {"error": {"message": "...", "status": "..."}}.
+            let pretty = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|v| {
+                    v.get("error")
+                        .and_then(|e| e.get("message"))
+                        .and_then(|m| m.as_str())
+                        .map(str::to_string)
+                })
+                .filter(|s| !s.is_empty())
+                .unwrap_or(body);
+            anyhow!("Google API returned {status}: {pretty}")
+        }
+        ureq::Error::Transport(t) => anyhow!("transport error against Google API: {t}"),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AdcCredentials {
+    #[serde(rename = "type")]
+    credential_type: Option<String>,
+    #[serde(default)]
+    client_id: String,
+    #[serde(default)]
+    client_secret: String,
+    #[serde(default)]
+    refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OauthTokenResponse {
+    access_token: String,
+}
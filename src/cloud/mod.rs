@@ -0,0 +1,171 @@
+//! `s3://BUCKET/KEY`, `gs://BUCKET/OBJECT` and `az://CONTAINER/BLOB` file
+//! arguments: one object from cloud storage, fetched with the credentials
+//! the provider's own CLI would find. Each provider is a Cargo feature
+//! (`s3`, `gcs`, `azure`), on by default and implying `http`; requests are
+//! signed here rather than through an SDK. The credential chains are
+//! described in each provider's module. Requests go through `crate::net`,
+//! so `--timeout` and `--retries` apply.
+
+#[cfg(feature = "azure")]
+mod azure;
+#[cfg(feature = "gcs")]
+mod gcs;
+#[cfg(any(feature = "gcs", feature = "vault"))]
+pub(crate) mod google;
+#[cfg(feature = "s3")]
+mod s3;
+
+use anyhow::{bail, Context, Result};
+
+use crate::input::InputSource;
+
+/// URI schemes and the feature that reads each.
+const SCHEMES: [(&str, &str); 3] = [("s3", "s3"), ("gs", "gcs"), ("az", "azure")];
+
+/// Whether `file` is a cloud storage URI.
+pub(crate) fn is_uri(file: &str) -> bool {
+    file.split_once("://")
+        .is_some_and(|(scheme, _)| SCHEMES.iter().any(|(known, _)| *known == scheme))
+}
+
+/// A cloud storage URI, fetched when it is read.
+pub(crate) struct Object(pub(crate) String);
+
+impl InputSource for Object {
+    /// Named after the key, so its extension picks the format.
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let (scheme, bucket, key) = split(&self.0)?;
+        let bytes: Result<Vec<u8>> = match scheme {
+            #[cfg(feature = "s3")]
+            "s3" => s3::get(bucket, key),
+            #[cfg(feature = "gcs")]
+            "gs" => gcs::get(bucket, key),
+            #[cfg(feature = "azure")]
+            "az" => azure::get(bucket, key),
+            _ => {
+                let feature = SCHEMES
+                    .iter()
+                    .find(|(known, _)| *known == scheme)
+                    .map_or(scheme, |(_, feature)| feature);
+                let _ = bucket;
+                let what = format!("{scheme}:// inputs");
+                Err(anyhow::anyhow!(crate::input::disabled(&what, feature)))
+            }
+        };
+        let bytes = bytes.with_context(|| format!("reading {}", self.0))?;
+        Ok((key.to_string(), bytes))
+    }
+}
+
+/// `SCHEME://BUCKET/KEY` split up.
+fn split(uri: &str) -> Result<(&str, &str, &str)> {
+    let (scheme, rest) = uri.split_once("://").unwrap_or(("", uri));
+    match rest.split_once('/') {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok((scheme, bucket, key)),
+        _ => bail!("`{uri}` names no object: write {scheme}://BUCKET/KEY"),
+    }
+}
+
+/// An agent for instance metadata endpoints, which only answer inside the
+/// cloud and should not hold up a laptop.
+#[cfg(any(feature = "s3", feature = "azure"))]
+fn metadata_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(std::time::Duration::from_millis(500))
+        .timeout(std::time::Duration::from_secs(3))
+        .build()
+}
+
+/// The body of a successful response.
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+fn body(response: ureq::Response) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    std::io::Read::read_to_end(&mut response.into_reader(), &mut bytes)
+        .context("reading the response")?;
+    Ok(bytes)
+}
+
+/// A failed S3 or Azure call, with the code and message of its XML error
+/// body.
+#[cfg(any(feature = "s3", feature = "azure"))]
+fn xml_error(service: &str, err: ureq::Error) -> anyhow::Error {
+    match err {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            let tag = |name: &str| {
+                let start = body.find(&format!("<{name}>"))? + name.len() + 2;
+                let end = body[start..].find(&format!("</{name}>"))? + start;
+                Some(body[start..end].trim().to_string())
+            };
+            match (tag("Code"), tag("Message")) {
+                (Some(code), Some(message)) => {
+                    let message = message.lines().next().unwrap_or_default();
+                    anyhow::anyhow!("{service} returned {status} {code}: {message}")
+                }
+                (Some(code), None) => anyhow::anyhow!("{service} returned {status} {code}"),
+                _ => anyhow::anyhow!("{service} returned {status}"),
+            }
+        }
+        ureq::Error::Transport(t) => anyhow::anyhow!("transport error against {service}: {t}"),
+    }
+}
+
+/// `text` percent-encoded as RFC 3986 unreserved characters, keeping `/`
+/// when `keep_slash`.
+#[cfg(feature = "http")]
+pub(crate) fn encode(text: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric()
+            || matches!(byte, b'-' | b'_' | b'.' | b'~')
+            || (keep_slash && byte == b'/')
+        {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(any(feature = "s3", feature = "azure"))]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The trimmed stdout of a provider CLI, when it runs and succeeds with some.
+#[cfg(any(feature = "s3", feature = "azure"))]
+fn cli_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uris_split_into_bucket_and_key() {
+        assert!(is_uri("s3://bucket/app.yaml"));
+        assert!(is_uri("gs://bucket/app.yaml"));
+        assert!(is_uri("az://container/app.yaml"));
+        assert!(!is_uri("https://example.com/app.yaml"));
+        assert!(!is_uri("s3.yaml"));
+        assert_eq!(
+            split("s3://configs/prod/app.yaml").unwrap(),
+            ("s3", "configs", "prod/app.yaml")
+        );
+        assert!(split("gs://configs")
+            .unwrap_err()
+            .to_string()
+            .contains("names no object"));
+        assert!(split("az:///app.yaml").is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! `convert --types` and `--schema`: give values the types the target
+//! format should hold, instead of carrying every string across.
+//!
+//! A .env file only has strings, so `PORT=8080` converts to `"8080"`.
+//! `--types PORT=int,DEBUG=bool` names the type at each path (a `*` segment
+//! matches every key or item); `--schema FILE` reads the types from a JSON
+//! Schema's `type`, `properties`, `additionalProperties` and `items` (no
+//! `$ref`). The schema only retypes strings; `--types` retypes any scalar
+//! and runs after the schema, so it wins.
+//!
+//! A value that does not read as its type is an error naming the path.
+//! Paths that match nothing are skipped, so one type map can serve every
+//! file of a directory.
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use crate::formats::ParseOptions;
+use crate::set::ValueType;
+use crate::{parse_file, path, resolve_path_mut, select_paths};
+
+/// One `--types` entry: the type of every value `path` matches.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TypeRule {
+    path: String,
+    kind: ValueType,
+}
+
+/// clap value parser for `--types`.
+pub(crate) fn parse_rule(raw: &str) -> Result<TypeRule, String> {
+    let Some((path, kind)) = raw
+        .rsplit_once('=')
+        .filter(|(path, _)| !path.trim().is_empty())
+    else {
+        return Err(format!("expected PATH=TYPE (e.g. PORT=int), got `{raw}`"));
+    };
+    let kind = ValueType::from_str(kind.trim(), true)
+        .map_err(|_| format!("unknown type `{kind}`: use string, int, float, bool or json"))?;
+    path::segments(path.trim()).map_err(|err| err.to_string())?;
+    Ok(TypeRule {
+        path: path.trim().to_string(),
+        kind,
+    })
+}
+
+/// The `--types` rules and `--schema` document of one `convert` run.
+#[derive(Debug, Default)]
+pub(crate) struct Coercion {
+    rules: Vec<TypeRule>,
+    schema: Option<Value>,
+}
+
+impl Coercion {
+    /// `rules` plus the schema in `schema`, which may be in any input format.
+    pub(crate) fn load(rules: Vec<TypeRule>, schema: Option<&str>) -> Result<Self> {
+        let schema = schema
+            .map(|file| {
+                parse_file(file, None, &ParseOptions::default())
+                    .with_context(|| format!("reading the schema {file}"))
+            })
+            .transpose()?;
+        Ok(Self { rules, schema })
+    }
+
+    pub(crate) fn apply(&self, value: &mut Value) -> Result<()> {
+        if let Some(schema) = &self.schema {
+            by_schema(value, schema, &mut Vec::new())?;
+        }
+        for rule in &self.rules {
+            let flag = format!("--types {}={}", rule.path, rule.kind.name());
+            let matched: Vec<String> = select_paths(value, &rule.path)?
+                .into_iter()
+                .map(|(trail, _)| path::join(&trail))
+                .collect();
+            for at in matched {
+                let target = resolve_path_mut(value, &at)?;
+                *target = retype(target, rule.kind, &flag).map_err(|err| anyhow!("{at}: {err}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `value` as `kind`: scalars are read from their text, nulls stay null.
+fn retype(value: &Value, kind: ValueType, flag: &str) -> Result<Value> {
+    match value {
+        Value::Null => Ok(Value::Null),
+        Value::String(text) => kind.parse_for(text, flag),
+        Value::Number(_) | Value::Bool(_) if kind == ValueType::Json => Ok(value.clone()),
+        Value::Number(_) | Value::Bool(_) => kind.parse_for(&value.to_string(), flag),
+        Value::Array(_) | Value::Object(_) if kind == ValueType::Json => Ok(value.clone()),
+        Value::Array(_) => bail!("an array cannot be retyped ({flag})"),
+        Value::Object(_) => bail!("an object cannot be retyped ({flag})"),
+    }
+}
+
+fn by_schema(value: &mut Value, schema: &Value, trail: &mut Vec<String>) -> Result<()> {
+    let Value::Object(schema) = schema else {
+        return Ok(());
+    };
+    if let (Value::String(text), Some((kind, name))) = (&*value, schema_type(schema)) {
+        let flag = format!("schema type {name}");
+        let typed = match text.trim().parse::<i64>() {
+            Ok(n) if kind == ValueType::Float => Ok(Value::from(n)),
+            _ => kind.parse_for(text, &flag),
+        };
+        *value = typed.map_err(|err| {
+            let at = if trail.is_empty() {
+                "the document".to_string()
+            } else {
+                path::join(trail)
+            };
+            anyhow!("{at}: {err}")
+        })?;
+    }
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema.get("additionalProperties");
+            for (key, child) in map.iter_mut() {
+                let Some(sub) = properties.and_then(|p| p.get(key)).or(additional) else {
+                    continue;
+                };
+                trail.push(key.clone());
+                by_schema(child, sub, trail)?;
+                trail.pop();
+            }
+        }
+        Value::Array(items) => {
+            let Some(sub) = schema.get("items") else {
+                return Ok(());
+            };
+            for (index, item) in items.iter_mut().enumerate() {
+                trail.push(index.to_string());
+                by_schema(item, sub, trail)?;
+                trail.pop();
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The type a string under `schema` should become, with the schema's name
+/// for it. None when the schema allows strings or names no type.
+fn schema_type(schema: &Map<String, Value>) -> Option<(ValueType, &str)> {
+    let types: Vec<&str> = match schema.get("type")? {
+        Value::String(name) => vec![name],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => return None,
+    };
+    if types.contains(&"string") {
+        return None;
+    }
+    types.into_iter().find_map(|name| {
+        let kind = match name {
+            "integer" => ValueType::Int,
+            "number" => ValueType::Float,
+            "boolean" => ValueType::Bool,
+            "array" | "object" => ValueType::Json,
+            _ => return None,
+        };
+        Some((kind, name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rules(raw: &[&str]) -> Vec<TypeRule> {
+        raw.iter().map(|r| parse_rule(r).unwrap()).collect()
+    }
+
+    #[test]
+    fn types_retype_matching_paths() {
+        let mut value = json!({
+            "PORT": "8080",
+            "DEBUG": "TRUE",
+            "RATIO": 2,
+            "VERSION": 1.5,
+            "HOSTS": "[\"a\", \"b\"]",
+            "pools": [{"size": "5"}, {"size": "10"}],
+            "UNSET": null
+        });
+        let coercion = Coercion {
+            rules: rules(&[
+                "PORT=int",
+                "DEBUG=bool",
+                "RATIO=float",
+                "VERSION=string",
+                "HOSTS=json",
+                "pools.*.size=int",
+                "UNSET=int",
+                "MISSING=int",
+            ]),
+            schema: None,
+        };
+        coercion.apply(&mut value).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "PORT": 8080,
+                "DEBUG": true,
+                "RATIO": 2.0,
+                "VERSION": "1.5",
+                "HOSTS": ["a", "b"],
+                "pools": [{"size": 5}, {"size": 10}],
+                "UNSET": null
+            })
+        );
+    }
+
+    #[test]
+    fn type_errors_name_the_path_and_rule() {
+        let mut value = json!({"db": {"port": "80x"}});
+        let coercion = Coercion {
+            rules: rules(&["db.port=int"]),
+            schema: None,
+        };
+        assert_eq!(
+            coercion.apply(&mut value).unwrap_err().to_string(),
+            "db.port: `80x` is not an integer (--types db.port=int)"
+        );
+        assert!(parse_rule("PORT").is_err());
+        assert!(parse_rule("PORT=integer")
+            .unwrap_err()
+            .contains("unknown type"));
+    }
+
+    #[test]
+    fn schema_types_strings() {
+        let mut value = json!({
+            "port": "8080",
+            "ratio": "0.5",
+            "weight": "3",
+            "name": "007",
+            "flags": {"tls": "false"},
+            "ids": ["1", "2"],
+            "tags": "[\"x\"]",
+            "note": "1"
+        });
+        let schema = json!({
+            "properties": {
+                "port": {"type": "integer"},
+                "ratio": {"type": "number"},
+                "weight": {"type": "number"},
+                "name": {"type": "string"},
+                "flags": {"additionalProperties": {"type": "boolean"}},
+                "ids": {"items": {"type": ["integer", "null"]}},
+                "tags": {"type": "array"},
+                "note": {"type": ["integer", "string"]}
+            }
+        });
+        let coercion = Coercion {
+            rules: Vec::new(),
+            schema: Some(schema),
+        };
+        coercion.apply(&mut value).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "port": 8080,
+                "ratio": 0.5,
+                "weight": 3,
+                "name": "007",
+                "flags": {"tls": false},
+                "ids": [1, 2],
+                "tags": ["x"],
+                "note": "1"
+            })
+        );
+
+        let mut bad = json!({"ids": ["1", "two"]});
+        let coercion = Coercion {
+            rules: Vec::new(),
+            schema: Some(json!({"properties": {"ids": {"items": {"type": "integer"}}}})),
+        };
+        assert_eq!(
+            coercion.apply(&mut bad).unwrap_err().to_string(),
+            "ids.1: `two` is not an integer (schema type integer)"
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! `confctl completions SHELL`: a completion script for bash, zsh or fish.
+//!
+//! The scripts hold no list of flags: on Tab they run the hidden
+//! `confctl __complete -- WORDS...` with the command line so far, which
+//! reads the clap definitions to offer subcommands, flags and their values,
+//! and keys for PATH arguments, so completion always matches the binary.
+//! Keys come from the file named by `CONFCTL_COMPLETE_FILE` when it is set,
+//! pinning one project's config, and otherwise from the FILE already typed.
+
+use anyhow::Result;
+use clap::{Arg, ArgAction, Args, Command, CommandFactory, ValueEnum};
+use serde_json::Value;
+
+use crate::formats::ParseOptions;
+
+/// Names the file PATH arguments complete from, whatever FILE is typed.
+const PINNED_FILE_ENV: &str = "CONFCTL_COMPLETE_FILE";
+
+/// The line `__complete` prints when the shell should also offer file
+/// names.
+const FILES_MARKER: &str = "@files";
+
+#[derive(Args, Debug)]
+pub(crate) struct CompletionsCli {
+    /// Shell to print the script for.
+    #[arg(value_enum)]
+    pub(crate) shell: Shell,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum Shell {
+    /// Source it: `eval "$(confctl completions bash)"` in ~/.bashrc.
+    Bash,
+    /// Save it as `_confctl` in a directory on $fpath, or source it.
+    Zsh,
+    /// Save it as ~/.config/fish/completions/confctl.fish.
+    Fish,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct CompleteCli {
+    /// The command line up to the cursor, the word being completed last.
+    #[arg(allow_hyphen_values = true)]
+    pub(crate) words: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Candidate {
+    /// A word to offer, with a line about it.
+    Word(String, String),
+    /// File names, which the shell lists itself.
+    Files,
+}
+
+pub(crate) fn run(cli: CompletionsCli) -> Result<()> {
+    print!("{}", script(cli.shell));
+    Ok(())
+}
+
+/// `__complete`: one candidate per line, `WORD<TAB>HELP`, and
+/// [`FILES_MARKER`] when file names fit too. Nothing is printed on errors;
+/// a broken file should not spill messages into the prompt.
+pub(crate) fn run_complete(cli: CompleteCli) -> Result<()> {
+    let mut root = crate::Cli::command();
+    root.build();
+    let pinned = std::env::var(PINNED_FILE_ENV)
+        .ok()
+        .filter(|file| !file.is_empty());
+    let load = |file: &str| crate::parse_file(file, None, &ParseOptions::default()).ok();
+    for candidate in candidates(&root, &cli.words, pinned.as_deref(), load) {
+        match candidate {
+            Candidate::Word(word, help) => println!("{word}\t{help}"),
+            Candidate::Files => println!("{FILES_MARKER}"),
+        }
+    }
+    Ok(())
+}
+
+fn script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH,
+        Shell::Zsh => ZSH,
+        Shell::Fish => FISH,
+    }
+}
+
+const BASH: &str = r#"# confctl completion for bash; add to ~/.bashrc:
+#   eval "$(confctl completions bash)"
+_confctl() {
+    local line files=0
+    COMPREPLY=()
+    while IFS= read -r line; do
+        if [[ $line == @files ]]; then
+            files=1
+        else
+            COMPREPLY+=("${line%%$'\t'*}")
+        fi
+    done < <(confctl __complete -- "${COMP_WORDS[@]:0:COMP_CWORD+1}" 2>/dev/null)
+    if ((files)); then
+        local IFS=$'\n'
+        COMPREPLY+=($(compgen -f -- "${COMP_WORDS[COMP_CWORD]}"))
+    fi
+}
+complete -o filenames -o bashdefault -F _confctl confctl
+"#;
+
+const ZSH: &str = r#"#compdef confctl
+# confctl completion for zsh; save as _confctl in a directory on $fpath:
+#   confctl completions zsh > "${fpath[1]}/_confctl"
+_confctl() {
+    local line files=0
+    local -a values
+    for line in ${(f)"$(confctl __complete -- "${(@)words[1,CURRENT]}" 2>/dev/null)"}; do
+        if [[ $line == @files ]]; then
+            files=1
+        else
+            values+=("${${line%%$'\t'*}//:/\\:}:${line#*$'\t'}")
+        fi
+    done
+    (( ${#values} )) && _describe -t values confctl values
+    (( files )) && _files
+    return 0
+}
+if [[ $funcstack[1] == _confctl ]]; then
+    _confctl "$@"
+else
+    compdef _confctl confctl
+fi
+"#;
+
+const FISH: &str = r#"# confctl completion for fish; save it where fish looks for completions:
+#   confctl completions fish > ~/.config/fish/completions/confctl.fish
+function __confctl_complete
+    set -l current (commandline -ct)
+    for line in (confctl __complete -- (commandline -opc) $current 2>/dev/null)
+        if test "$line" = @files
+            __fish_complete_path $current
+        else
+            echo $line
+        end
+    end
+end
+complete -c confctl -f -a '(__confctl_complete)'
+"#;
+
+/// What to offer for the last of `words` (the first is the program name),
+/// with keys read by `load` from `pinned` or the FILE typed before PATH.
+fn candidates(
+    root: &Command,
+    words: &[String],
+    pinned: Option<&str>,
+    load: impl Fn(&str) -> Option<Value>,
+) -> Vec<Candidate> {
+    let Some((current, typed)) = words.split_last() else {
+        return Vec::new();
+    };
+    let mut command = root;
+    let mut positionals: Vec<&str> = Vec::new();
+    let mut pending: Option<&Arg> = None;
+    let mut only_positionals = false;
+    for word in typed.iter().skip(1) {
+        // bash splits `--output=json` into three words.
+        if pending.is_some() && word == "=" {
+            continue;
+        }
+        if pending.take().is_some() {
+            continue;
+        }
+        if only_positionals || word == "-" || !word.starts_with('-') {
+            let subcommand = positionals
+                .is_empty()
+                .then(|| command.find_subcommand(word))
+                .flatten()
+                .filter(|sub| !sub.is_hide_set());
+            match subcommand {
+                Some(sub) => command = sub,
+                None => positionals.push(word),
+            }
+        } else if word == "--" {
+            only_positionals = true;
+        } else if let Some(long) = word.strip_prefix("--") {
+            let arg = long_arg(command, long);
+            pending = arg.filter(|arg| takes_value(arg) && !long.contains('='));
+        } else {
+            pending = short_value_arg(command, &word[1..]);
+        }
+    }
+
+    if let Some(arg) = pending {
+        if current == "=" {
+            return Vec::new();
+        }
+        return values(arg, "", current);
+    }
+    if !only_positionals && current.starts_with('-') && current != "-" {
+        if let Some((long, value)) = current.strip_prefix("--").and_then(|c| c.split_once('=')) {
+            return match long_arg(command, long) {
+                Some(arg) => values(arg, &format!("--{long}="), value),
+                None => Vec::new(),
+            };
+        }
+        return flags(command, current);
+    }
+
+    let mut offered = Vec::new();
+    if positionals.is_empty() {
+        offered.extend(
+            command
+                .get_subcommands()
+                .filter(|sub| !sub.is_hide_set() && sub.get_name().starts_with(current.as_str()))
+                .map(|sub| Candidate::Word(sub.get_name().to_string(), about(sub))),
+        );
+    }
+    match positional(command, positionals.len()) {
+        Some(arg) if arg.get_id() == "path" => {
+            let file = pinned.or_else(|| typed_file(command, &positionals));
+            if let Some(document) = file.and_then(load) {
+                offered.extend(
+                    crate::repl::complete_path(&document, current)
+                        .into_iter()
+                        .map(|path| Candidate::Word(path, String::new())),
+                );
+            }
+        }
+        Some(arg) => offered.extend(values(arg, "", current)),
+        None => {}
+    }
+    offered
+}
+
+/// The FILE typed before PATH, when it names one to read.
+fn typed_file<'a>(command: &Command, positionals: &[&'a str]) -> Option<&'a str> {
+    positionals
+        .iter()
+        .enumerate()
+        .find(|(index, _)| positional(command, *index).is_some_and(|arg| arg.get_id() == "file"))
+        .map(|(_, file)| *file)
+        .filter(|file| *file != "-")
+}
+
+/// The positional argument the `index`th positional word fills; the last
+/// one takes every word left when it accepts several.
+fn positional(command: &Command, index: usize) -> Option<&Arg> {
+    let args: Vec<&Arg> = command.get_positionals().collect();
+    args.get(index).copied().or_else(|| {
+        args.last().copied().filter(|arg| {
+            arg.get_num_args()
+                .is_some_and(|range| range.max_values() > 1)
+        })
+    })
+}
+
+fn long_arg<'a>(command: &'a Command, long: &str) -> Option<&'a Arg> {
+    let name = long.split('=').next().unwrap_or(long);
+    command.get_arguments().find(|arg| {
+        arg.get_long() == Some(name) || arg.get_all_aliases().is_some_and(|a| a.contains(&name))
+    })
+}
+
+/// The option still waiting for its value after a cluster such as `-rco`.
+fn short_value_arg<'a>(command: &'a Command, cluster: &str) -> Option<&'a Arg> {
+    let mut chars = cluster.chars();
+    while let Some(short) = chars.next() {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_short() == Some(short))?;
+        if takes_value(arg) {
+            return chars.as_str().is_empty().then_some(arg);
+        }
+    }
+    None
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values() && !matches!(arg.get_action(), ArgAction::Help)
+}
+
+/// `arg`'s possible values starting with `current`, each after `prefix`;
+/// file names when clap lists none.
+fn values(arg: &Arg, prefix: &str, current: &str) -> Vec<Candidate> {
+    let possible = arg.get_possible_values();
+    if possible.is_empty() {
+        return vec![Candidate::Files];
+    }
+    possible
+        .iter()
+        .filter(|value| !value.is_hide_set() && value.get_name().starts_with(current))
+        .map(|value| {
+            let help = value
+                .get_help()
+                .map(|help| first_sentence(&help.to_string()));
+            Candidate::Word(
+                format!("{prefix}{}", value.get_name()),
+                help.unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+fn flags(command: &Command, current: &str) -> Vec<Candidate> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set())
+        .filter_map(|arg| {
+            let flag = format!("--{}", arg.get_long()?);
+            let help = arg.get_help().map(|help| first_sentence(&help.to_string()));
+            flag.starts_with(current)
+                .then(|| Candidate::Word(flag, help.unwrap_or_default()))
+        })
+        .collect()
+}
+
+fn about(command: &Command) -> String {
+    command
+        .get_about()
+        .map(|about| first_sentence(&about.to_string()))
+        .unwrap_or_default()
+}
+
+/// The first sentence of a help text, without its full stop, to fit on
+/// one line of a completion menu.
+fn first_sentence(help: &str) -> String {
+    let line = help.lines().next().unwrap_or_default();
+    let sentence = line.split(". ").next().unwrap_or(line);
+    sentence.strip_suffix('.').unwrap_or(sentence).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn complete(line: &str, pinned: Option<&str>) -> Vec<Candidate> {
+        let mut root = crate::Cli::command();
+        root.build();
+        let mut words: Vec<String> = line.split(' ').map(str::to_string).collect();
+        words.insert(0, "confctl".into());
+        let load = |file: &str| match file {
+            "config.yaml" => Some(json!({"db": {"host": "x", "port": 1}, "debug": true})),
+            "pinned.yaml" => Some(json!({"server": {"port": 8080}})),
+            _ => None,
+        };
+        candidates(&root, &words, pinned, load)
+    }
+
+    fn words(candidates: Vec<Candidate>) -> Vec<String> {
+        candidates
+            .into_iter()
+            .map(|candidate| match candidate {
+                Candidate::Word(word, _) => word,
+                Candidate::Files => FILES_MARKER.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn subcommands_flags_and_values_come_from_the_cli() {
+        assert_eq!(words(complete("conv", None)), ["convert", FILES_MARKER]);
+        assert!(complete("ex", None).contains(&Candidate::Word(
+            "exec".into(),
+            "Run a command with a config subtree exported as environment variables".into()
+        )));
+        assert_eq!(words(complete("--comp", None)), ["--compact"]);
+        assert_eq!(words(complete("x.yaml -o ya", None)), ["yaml"]);
+        assert_eq!(
+            words(complete("x.yaml --output=ya", None)),
+            ["--output=yaml"]
+        );
+        assert_eq!(words(complete("x.yaml -rco ya", None)), ["yaml"]);
+        assert_eq!(words(complete("x.yaml --output = ya", None)), ["yaml"]);
+        assert!(complete("x.yaml --output =", None).is_empty());
+        assert_eq!(words(complete("x.yaml --out ", None)), [FILES_MARKER]);
+        assert_eq!(words(complete("completions f", None)), ["fish"]);
+        assert!(words(complete("", None))
+            .iter()
+            .all(|word| word != "__complete"));
+    }
+
+    #[test]
+    fn paths_complete_from_the_typed_or_pinned_file() {
+        assert_eq!(words(complete("config.yaml d", None)), ["db", "debug"]);
+        assert_eq!(
+            words(complete("config.yaml db.", None)),
+            ["db.host", "db.port"]
+        );
+        assert_eq!(words(complete("-c config.yaml db.p", None)), ["db.port"]);
+        assert_eq!(words(complete("edit config.yaml db.h", None)), ["db.host"]);
+        assert_eq!(
+            words(complete("config.yaml s", Some("pinned.yaml"))),
+            ["server"]
+        );
+        assert_eq!(
+            words(complete("explain server.", Some("pinned.yaml"))),
+            ["server.port"]
+        );
+        assert!(words(complete("missing.yaml d", None)).is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! `confctl compose` — the environment docker compose hands each service,
+//! as one document: `{service: {environment: {NAME: value}}}`, so
+//! `web.environment.DATABASE_URL` is a path like any other.
+//!
+//! Per service, as compose builds it:
+//!
+//! 1. `env_file:` files in order, later ones winning. Entries are a path
+//!    or `{path, required}`; paths are relative to the compose file, and a
+//!    missing file is an error unless `required: false`. They are read with
+//!    the `--strict-env` rules (quotes, escapes, `export`).
+//! 2. `environment:` on top, as a `KEY=value` list or a map. A bare `KEY`
+//!    (or `KEY:` with no value) passes the variable through from the
+//!    calling environment and is left out when that is unset.
+//!
+//! Values are strings; `${VAR}` interpolation is not performed.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde_json::{json, Map, Value};
+
+use crate::emit::{self, OutputFormat};
+use crate::formats::{self, ParseOptions};
+use crate::{env_override, format_value_with, parse_file, resolve_path};
+
+#[derive(Args, Debug)]
+pub(crate) struct ComposeCli {
+    /// Compose file, or `-` for stdin (`env_file:` paths are then relative
+    /// to the current directory).
+    pub(crate) file: String,
+
+    /// Dotted path into the result, e.g. `web.environment.DATABASE_URL`.
+    pub(crate) path: Option<String>,
+
+    /// Output format of the result (default: JSON, scalars bare).
+    #[arg(short = 'o', long, value_enum)]
+    pub(crate) output: Option<OutputFormat>,
+}
+
+pub(crate) fn run(cli: ComposeCli) -> Result<()> {
+    let compose = parse_file(&cli.file, None, &ParseOptions::default())?;
+    let base_dir = match cli.file.as_str() {
+        "-" => PathBuf::from("."),
+        file => Path::new(file)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+    let resolved = environments(&compose, &base_dir, &env_override::process_vars())?;
+    let result = match &cli.path {
+        Some(path) => resolve_path(&resolved, path)?,
+        None => &resolved,
+    };
+    match cli.output {
+        Some(format) => print!("{}", emit::render(result, format)?),
+        None => println!("{}", format_value_with(result, false)),
+    }
+    Ok(())
+}
+
+/// Every service's resolved environment. `shell` answers pass-through
+/// variables.
+pub(crate) fn environments(
+    compose: &Value,
+    base_dir: &Path,
+    shell: &HashMap<String, String>,
+) -> Result<Value> {
+    let Some(services) = compose.get("services").and_then(Value::as_object) else {
+        bail!("not a compose file: no `services:` mapping");
+    };
+    let mut out = Map::new();
+    for (name, service) in services {
+        let environment = service_environment(service, base_dir, shell)
+            .with_context(|| format!("service `{name}`"))?;
+        out.insert(name.clone(), json!({ "environment": environment }));
+    }
+    Ok(Value::Object(out))
+}
+
+fn service_environment(
+    service: &Value,
+    base_dir: &Path,
+    shell: &HashMap<String, String>,
+) -> Result<Map<String, Value>> {
+    let mut env = Map::new();
+    for (path, required) in env_files(service.get("env_file"))? {
+        let path = base_dir.join(path);
+        if !required && !path.exists() {
+            continue;
+        }
+        let display = path.display().to_string();
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read file: {display}"))?;
+        let vars = formats::dotenv::parse(&content, false)
+            .with_context(|| format!("Failed to parse ENV: {display}"))?;
+        if let Value::Object(vars) = vars {
+            env.extend(vars);
+        }
+    }
+
+    let mut set = |key: &str, value: Option<String>| {
+        match value.or_else(|| shell.get(key).cloned()) {
+            Some(value) => env.insert(key.to_string(), Value::String(value)),
+            None => env.remove(key),
+        };
+    };
+    match service.get("environment") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(items)) => {
+            for item in items {
+                let Some(item) = item.as_str() else {
+                    bail!("`environment:` list entries must be strings, got {item}");
+                };
+                match item.split_once('=') {
+                    Some((key, value)) => set(key, Some(value.to_string())),
+                    None => set(item, None),
+                }
+            }
+        }
+        Some(Value::Object(map)) => {
+            for (key, value) in map {
+                set(key, scalar_text(value));
+            }
+        }
+        Some(other) => bail!("`environment:` must be a list or a mapping, got {other}"),
+    }
+    Ok(env)
+}
+
+/// `env_file:` as (path, required) pairs, in order.
+fn env_files(value: Option<&Value>) -> Result<Vec<(String, bool)>> {
+    let entries = match value {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(single) => vec![single],
+    };
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Value::String(path) => Ok((path.clone(), true)),
+            Value::Object(map) => {
+                let Some(path) = map.get("path").and_then(Value::as_str) else {
+                    bail!("`env_file:` entries need a `path`");
+                };
+                let required = map.get("required").and_then(Value::as_bool);
+                Ok((path.to_string(), required.unwrap_or(true)))
+            }
+            other => bail!("`env_file:` entries must be paths, got {other}"),
+        })
+        .collect()
+}
+
+/// Map values as compose passes them: scalars as text, `null` as unset.
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_files_then_environment_per_service() {
+        let compose = parse_file(
+            "testdata/compose/docker-compose.yml",
+            None,
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        let shell = HashMap::from([("DEBUG".to_string(), "1".to_string())]);
+        let resolved = environments(&compose, Path::new("testdata/compose"), &shell).unwrap();
+        assert_eq!(
+            resolved,
+            json!({
+                "web": {"environment": {
+                    "LOG_LEVEL": "debug",
+                    "TZ": "UTC",
+                    "PORT": "8080",
+                    "GREETING": "hello world",
+                    "DATABASE_URL": "postgres://db/app",
+                    "DEBUG": "1"
+                }},
+                "worker": {"environment": {
+                    "LOG_LEVEL": "info",
+                    "TZ": "UTC",
+                    "QUEUE": "jobs",
+                    "CONCURRENCY": "4",
+                    "VERBOSE": "true"
+                }},
+                "db": {"environment": {}}
+            })
+        );
+    }
+
+    #[test]
+    fn missing_required_env_file_is_an_error() {
+        let compose = json!({"services": {"api": {"env_file": ["nope.env"]}}});
+        let err =
+            environments(&compose, Path::new("testdata/compose"), &HashMap::new()).unwrap_err();
+        assert_eq!(err.to_string(), "service `api`");
+        assert_eq!(
+            err.root_cause().to_string(),
+            "No such file or directory (os error 2)"
+        );
+    }
+}
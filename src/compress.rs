@@ -0,0 +1,145 @@
+//! gzip and zstd, for compressed inputs (`app.yaml.gz`, a zstd API dump on
+//! stdin) and snapshot bundles. Like the other external formats, the work
+//! is piped through the `gzip` and `zstd` tools on PATH rather than linked.
+
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Compression {
+    None,
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    /// By magic number, so a renamed file still loads.
+    pub(crate) fn sniff(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if bytes.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+
+    /// How an input named `name` is compressed, and its name without the
+    /// compression suffix (`app.yaml.gz` → `app.yaml`). Without a `.gz` or
+    /// `.zst` suffix the magic number decides.
+    pub(crate) fn of_input<'a>(name: &'a str, bytes: &[u8]) -> (Self, &'a str) {
+        let lower = name.to_ascii_lowercase();
+        for (suffix, compression) in [
+            (".gz", Compression::Gzip),
+            (".gzip", Compression::Gzip),
+            (".zst", Compression::Zstd),
+            (".zstd", Compression::Zstd),
+        ] {
+            if lower.ends_with(suffix) {
+                return (compression, &name[..name.len() - suffix.len()]);
+            }
+        }
+        (Self::sniff(bytes), name)
+    }
+
+    // Only snapshot bundles are written compressed.
+    #[cfg_attr(not(feature = "archive"), allow(dead_code))]
+    pub(crate) fn compress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            Compression::Zstd => pipe("zstd", &["-q", "-c"], bytes),
+            Compression::Gzip => pipe("gzip", &["-c"], bytes),
+        }
+    }
+
+    pub(crate) fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            Compression::Zstd => pipe("zstd", &["-q", "-d", "-c"], bytes),
+            Compression::Gzip => pipe("gzip", &["-d", "-c"], bytes),
+        }
+    }
+}
+
+/// Run `tool` with `input` on stdin and return its stdout.
+pub(crate) fn pipe(tool: &str, args: &[&str], input: &[u8]) -> Result<Vec<u8>> {
+    let child = Command::new(tool)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("running {tool} (is it installed?)"))?;
+    feed(child, tool, input)
+}
+
+/// Write `input` to the piped stdin of `child` while reading its stdout and
+/// stderr, and return the stdout. The write runs on its own thread: a tool
+/// that streams would otherwise fill the stdout pipe and wait for us while
+/// we wait for it to take the rest of stdin.
+pub(crate) fn feed(mut child: Child, tool: &str, input: &[u8]) -> Result<Vec<u8>> {
+    let stdin = child.stdin.take();
+    let (output, written) = std::thread::scope(|scope| {
+        let writer = scope.spawn(move || match stdin {
+            Some(mut stdin) => stdin.write_all(input),
+            None => Ok(()),
+        });
+        let output = child.wait_with_output();
+        (output, writer.join())
+    });
+    let output = output.with_context(|| format!("waiting for {tool}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{tool} failed: {}", stderr.trim());
+    }
+    match written {
+        Ok(written) => written.with_context(|| format!("writing to {tool}"))?,
+        Err(_) => bail!("writing to {tool} panicked"),
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inputs_by_suffix_or_magic() {
+        assert_eq!(
+            Compression::of_input("app.yaml.gz", b""),
+            (Compression::Gzip, "app.yaml")
+        );
+        assert_eq!(
+            Compression::of_input("dump.JSON.ZST", b""),
+            (Compression::Zstd, "dump.JSON")
+        );
+        assert_eq!(
+            Compression::of_input("-", &[0x1f, 0x8b, 8]),
+            (Compression::Gzip, "-")
+        );
+        assert_eq!(
+            Compression::of_input("app.yaml", b"a: 1"),
+            (Compression::None, "app.yaml")
+        );
+    }
+
+    #[test]
+    fn large_inputs_round_trip_without_blocking() {
+        // Incompressible bytes, so both directions move megabytes through
+        // the pipes.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let input: Vec<u8> = (0..4 << 20)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        let packed = Compression::Gzip.compress(&input).unwrap();
+        assert!(packed.len() > 1 << 20);
+        assert_eq!(Compression::Gzip.decompress(&packed).unwrap(), input);
+    }
+}
@@ -0,0 +1,398 @@
+//! `confctl convert` — re-serialise a whole config file in another format,
+//! e.g. `confctl convert app.yaml --to properties` for a Java service.
+//!
+//! Prints to stdout by default; `--out` writes a file instead, which makes
+//! the command a mutation for `--read-only`. Flat targets report what they
+//! lose (see `emit::loss`); `strict` turns that report into an error.
+//!
+//! Given a directory, every file under it in the `--from` format is
+//! converted, either next to itself (`--in-place`, which replaces
+//! `app.yaml` with `app.json`) or into the same layout under `--out DIR`.
+//! Hidden directories such as `.git` are skipped. A file that fails is
+//! reported and the rest still run; the summary says how many failed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Args};
+use colored::Colorize;
+
+use crate::atomic::{self, BackupArg};
+use crate::coerce::{self, Coercion, TypeRule};
+use crate::emit::{self, EmitOptions, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::theme;
+use crate::undo;
+use crate::{detect_format, parse_file, Format};
+
+#[derive(Args, Debug)]
+#[command(group(ArgGroup::new("dest").args(["out", "in_place"])))]
+#[command(mut_arg("backup", |arg| arg.requires("dest")))]
+pub(crate) struct ConvertCli {
+    /// Config file to convert, `-` for stdin, or a directory: every
+    /// `--from` file under it is converted (needs --in-place or --out).
+    pub(crate) file: String,
+
+    /// Format to write. Defaults to the extension of `--out`.
+    #[arg(long, value_enum)]
+    pub(crate) to: Option<OutputFormat>,
+
+    /// Input format override, as in the top-level `--format`. For a
+    /// directory, the format of the files to convert.
+    #[arg(long, value_enum, visible_alias = "from")]
+    pub(crate) format: Option<Format>,
+
+    /// Write the result to this file instead of stdout; for a directory,
+    /// the directory to write the converted files under.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) out: Option<PathBuf>,
+
+    /// Replace each file with its conversion, renamed to the new extension
+    /// (`app.yaml` becomes `app.json`).
+    #[arg(long)]
+    pub(crate) in_place: bool,
+
+    /// Overwrite files that already exist where the output goes; --backup
+    /// implies it.
+    #[arg(long, requires = "dest")]
+    pub(crate) force: bool,
+
+    #[command(flatten)]
+    pub(crate) backup: BackupArg,
+
+    /// Comma-separated PATH=TYPE pairs (string, int, float, bool, json)
+    /// giving values their type, e.g. `PORT=int,DEBUG=bool` for a .env
+    /// file. A `*` segment matches every key or item.
+    #[arg(
+        long,
+        value_name = "PATH=TYPE",
+        value_delimiter = ',',
+        value_parser = coerce::parse_rule
+    )]
+    pub(crate) types: Vec<TypeRule>,
+
+    /// JSON Schema whose types strings are coerced to (`"8080"` becomes
+    /// 8080 where the schema says integer). Applied before --types.
+    #[arg(long, value_name = "FILE")]
+    pub(crate) schema: Option<String>,
+
+    /// Keep `${…}` and `%{…}` in strings as HCL templates for `--to hcl`
+    /// (Terraform JSON); by default they are escaped and stay literal.
+    #[arg(long)]
+    pub(crate) hcl_templates: bool,
+}
+
+impl ConvertCli {
+    pub(crate) fn mutation(&self) -> Option<&'static str> {
+        if self.in_place {
+            return Some("convert --in-place");
+        }
+        self.out.as_ref().map(|_| "convert --out")
+    }
+}
+
+pub(crate) fn run(cli: ConvertCli, strict: bool) -> Result<()> {
+    let dir = cli.file != "-" && Path::new(&cli.file).is_dir();
+    let to = match cli.to.or_else(|| {
+        let out = cli.out.as_deref().filter(|_| !dir)?;
+        format_for_path(out)
+    }) {
+        Some(format) => format,
+        None => bail!("pass --to to choose the output format"),
+    };
+    let coercion = Coercion::load(cli.types.clone(), cli.schema.as_deref())?;
+    if dir {
+        return run_dir(&cli, &coercion, to, strict);
+    }
+    if cli.in_place {
+        if cli.file == "-" {
+            bail!("--in-place needs a file, not stdin");
+        }
+        let source = Path::new(&cli.file);
+        let target = converted_path(source, to);
+        convert_to(&cli, &coercion, source, &target, to, strict)?;
+        if !atomic::previewing() {
+            println!(
+                "{} {} → {}",
+                "✓".green().bold(),
+                source.display(),
+                target.display()
+            );
+        }
+        return Ok(());
+    }
+    let body = convert(
+        &cli.file,
+        cli.format,
+        &coercion,
+        to,
+        strict,
+        cli.hcl_templates,
+    )?;
+
+    let Some(path) = &cli.out else {
+        print!("{body}");
+        return Ok(());
+    };
+    write(&cli, path, body)?;
+    if !atomic::previewing() {
+        println!("{} wrote {}", "✓".green().bold(), path.display());
+    }
+    Ok(())
+}
+
+/// Every `--from` file under the directory FILE, converted one by one.
+fn run_dir(cli: &ConvertCli, coercion: &Coercion, to: OutputFormat, strict: bool) -> Result<()> {
+    let dir = Path::new(&cli.file);
+    let Some(from) = cli.format else {
+        bail!(
+            "pass --from to choose which files under {} to convert",
+            dir.display()
+        );
+    };
+    if !cli.in_place && cli.out.is_none() {
+        bail!(
+            "{} is a directory; pass --in-place or --out DIR",
+            dir.display()
+        );
+    }
+    let sources = files_under(dir, &|format| format == from)?;
+    if sources.is_empty() {
+        bail!("no {} files under {}", from.extension(), dir.display());
+    }
+
+    let mut failed = 0;
+    for source in &sources {
+        let target = match &cli.out {
+            Some(out) => converted_path(&out.join(source.strip_prefix(dir)?), to),
+            None => converted_path(source, to),
+        };
+        match convert_to(cli, coercion, source, &target, to, strict) {
+            Ok(()) if atomic::previewing() => {}
+            Ok(()) => println!(
+                "{} {} → {}",
+                "✓".green().bold(),
+                source.display(),
+                target.display()
+            ),
+            Err(err) => {
+                failed += 1;
+                eprintln!(
+                    "{} {}: {err:#}",
+                    theme::stderr("✗".red().bold()),
+                    source.display()
+                );
+            }
+        }
+    }
+    let total = sources.len();
+    if failed > 0 {
+        bail!(
+            "converted {} of {total} files; {failed} failed",
+            total - failed
+        );
+    }
+    if atomic::previewing() {
+        return Ok(());
+    }
+    println!(
+        "converted {total} file{}",
+        if total == 1 { "" } else { "s" }
+    );
+    Ok(())
+}
+
+/// `file` parsed, retyped and rendered as `to`, after the loss check.
+fn convert(
+    file: &str,
+    format: Option<Format>,
+    coercion: &Coercion,
+    to: OutputFormat,
+    strict: bool,
+    hcl_templates: bool,
+) -> Result<String> {
+    let mut value = parse_file(file, format, &ParseOptions::default())?;
+    coercion
+        .apply(&mut value)
+        .with_context(|| format!("retyping {file}"))?;
+    emit::loss::check(&emit::loss::report(&value, to), to, strict)?;
+    let opts = EmitOptions {
+        hcl_templates,
+        ..EmitOptions::default()
+    };
+    emit::render_with(&value, to, &opts)
+        .with_context(|| format!("converting {file} to {}", to.extension()))
+}
+
+/// `source` converted into `target`; with `--in-place`, `source` is then
+/// removed unless the target replaced it.
+fn convert_to(
+    cli: &ConvertCli,
+    coercion: &Coercion,
+    source: &Path,
+    target: &Path,
+    to: OutputFormat,
+    strict: bool,
+) -> Result<()> {
+    let body = convert(
+        &source.display().to_string(),
+        cli.format,
+        coercion,
+        to,
+        strict,
+        cli.hcl_templates,
+    )?;
+    if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) {
+        if !atomic::previewing() {
+            fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+        }
+    }
+    write(cli, target, body)?;
+    if cli.in_place && target != source {
+        undo::record(source, "convert")?;
+        atomic::remove(source)?;
+    }
+    Ok(())
+}
+
+fn write(cli: &ConvertCli, path: &Path, body: String) -> Result<()> {
+    if path.exists() && !cli.force && cli.backup.suffix().is_none() {
+        bail!(
+            "{} already exists; pass --force to overwrite",
+            path.display()
+        );
+    }
+    if cli.in_place {
+        undo::record(path, "convert")?;
+    }
+    atomic::write(path, body, cli.backup.suffix())
+}
+
+/// The files under `dir` whose names say they are in a `wanted` format,
+/// sorted; hidden directories are not entered.
+pub(crate) fn files_under(dir: &Path, wanted: &dyn Fn(Format) -> bool) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let entries = fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("reading {}", dir.display()))?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            if !name.starts_with('.') {
+                found.extend(files_under(&path, wanted)?);
+            }
+        } else if format_by_name(&path, &name).is_some_and(wanted) {
+            found.push(path);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// The format a file name implies, without reading the file.
+fn format_by_name(path: &Path, name: &str) -> Option<Format> {
+    let env_name = name == ".env" || name.starts_with(".env.");
+    if !env_name && path.extension().is_none() {
+        return None;
+    }
+    detect_format(&path.display().to_string(), "", None).ok()
+}
+
+/// Where the conversion of `source` goes: the same name with the writer's
+/// extension, added after `.env` names rather than replacing their suffix.
+fn converted_path(source: &Path, to: OutputFormat) -> PathBuf {
+    let name = source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if name == ".env" || name.starts_with(".env.") {
+        return source.with_file_name(format!("{name}.{}", to.extension()));
+    }
+    source.with_extension(to.extension())
+}
+
+/// The writer implied by a file name, for `--out` without `--to`.
+fn format_for_path(path: &Path) -> Option<OutputFormat> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let format = match ext.as_str() {
+        "json" => OutputFormat::Json,
+        "jsonl" | "ndjson" => OutputFormat::JsonStream,
+        "yaml" | "yml" => OutputFormat::Yaml,
+        "toml" => OutputFormat::Toml,
+        "env" => OutputFormat::Env,
+        "csv" => OutputFormat::Csv,
+        "tsv" => OutputFormat::Tsv,
+        "tfvars" | "hcl" => OutputFormat::Hcl,
+        "xml" => OutputFormat::Xml,
+        "properties" => OutputFormat::Properties,
+        "ini" | "cfg" | "conf" => OutputFormat::Ini,
+        _ => return None,
+    };
+    Some(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_extension_picks_the_writer() {
+        let pick = |name: &str| format_for_path(Path::new(name));
+        assert_eq!(pick("app.properties"), Some(OutputFormat::Properties));
+        assert_eq!(pick("setup.CFG"), Some(OutputFormat::Ini));
+        assert_eq!(pick("vars.tfvars"), Some(OutputFormat::Hcl));
+        assert_eq!(pick("notes.txt"), None);
+        assert_eq!(pick(".env"), None);
+    }
+
+    #[test]
+    fn directory_converts_in_place_and_skips_hidden_dirs() {
+        let dir = std::env::temp_dir().join(format!("confctl-convert-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join("app.json"), "{\"port\": 8080}").unwrap();
+        fs::write(dir.join("nested/db.json"), "{\"host\": \"db\"}").unwrap();
+        fs::write(dir.join(".git/config.json"), "{}").unwrap();
+        fs::write(dir.join("notes.yaml"), "a: 1\n").unwrap();
+
+        let cli = ConvertCli {
+            file: dir.display().to_string(),
+            to: Some(OutputFormat::Env),
+            format: Some(Format::Json),
+            out: None,
+            in_place: true,
+            force: false,
+            backup: BackupArg::default(),
+            types: Vec::new(),
+            schema: None,
+            hcl_templates: false,
+        };
+        run(cli, false).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("app.env")).unwrap(),
+            "PORT=8080\n"
+        );
+        assert!(dir.join("nested/db.env").exists());
+        assert!(!dir.join("app.json").exists());
+        assert!(dir.join(".git/config.json").exists());
+        assert!(dir.join("notes.yaml").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn env_names_keep_their_prefix() {
+        let to = OutputFormat::Json;
+        assert_eq!(
+            converted_path(Path::new("a/.env.prod"), to),
+            Path::new("a/.env.prod.json")
+        );
+        assert_eq!(
+            converted_path(Path::new("a/app.yml"), to),
+            Path::new("a/app.json")
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! `--as-epoch`, `--as-rfc3339` and `--humanize-duration`: rewrite
+//! timestamps and durations at PATH into one spelling, so values written
+//! differently across configs can be compared.
+//!
+//! Timestamps are read as RFC 3339, RFC 2822, `YYYY-MM-DD[ HH:MM:SS]`
+//! (UTC unless an offset follows) or epoch seconds, in milliseconds when
+//! the number is too big for seconds. Durations are read as `90s`,
+//! `1h30m`, `1.5 hours`, ISO 8601 `PT1H30M`, `HH:MM:SS` or seconds.
+//!
+//! When PATH is an object or array, every string leaf that reads as one is
+//! rewritten and the rest are left alone; bare numbers are only taken for
+//! a timestamp or duration when they are PATH's value itself, since a
+//! subtree's numbers are mostly ports and counts.
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Conversion {
+    Epoch,
+    Rfc3339,
+    HumanDuration,
+}
+
+/// Rewrite the value at PATH (or every matching leaf under it).
+pub(crate) fn convert(value: &mut Value, conversion: Conversion) -> Result<()> {
+    match value {
+        Value::Object(_) | Value::Array(_) => {
+            convert_leaves(value, conversion);
+            Ok(())
+        }
+        scalar => match convert_scalar(scalar, conversion, true) {
+            Some(converted) => {
+                *scalar = converted;
+                Ok(())
+            }
+            None => {
+                let text = crate::format_value_with(scalar, true);
+                match conversion {
+                    Conversion::HumanDuration => bail!(
+                        "`{text}` is not a duration: expected e.g. 90s, 1h30m, PT1H30M, 01:30:00 \
+                         or seconds"
+                    ),
+                    _ => bail!(
+                        "`{text}` is not a timestamp: expected RFC 3339, RFC 2822, \
+                         YYYY-MM-DD[ HH:MM:SS] or epoch seconds"
+                    ),
+                }
+            }
+        },
+    }
+}
+
+fn convert_leaves(value: &mut Value, conversion: Conversion) {
+    match value {
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|child| convert_leaves(child, conversion)),
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|child| convert_leaves(child, conversion)),
+        Value::String(_) => {
+            if let Some(converted) = convert_scalar(value, conversion, false) {
+                *value = converted;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The converted scalar, or `None` when it is not a timestamp or duration.
+/// Numbers, and strings that are just a number, count only when `numbers`.
+fn convert_scalar(value: &Value, conversion: Conversion, numbers: bool) -> Option<Value> {
+    let number = match value {
+        Value::Number(number) => Some(number.as_f64()?),
+        Value::String(text) => text.trim().parse::<f64>().ok(),
+        _ => return None,
+    };
+    if number.is_some() && !numbers {
+        return None;
+    }
+    match conversion {
+        Conversion::HumanDuration => {
+            let seconds = match (number, value) {
+                (Some(seconds), _) => seconds,
+                (None, Value::String(text)) => parse_duration(text)?,
+                _ => return None,
+            };
+            Some(Value::String(humanize(seconds)?))
+        }
+        Conversion::Epoch | Conversion::Rfc3339 => {
+            let timestamp = match (number, value) {
+                (Some(epoch), _) => from_epoch(epoch)?,
+                (None, Value::String(text)) => parse_timestamp(text)?,
+                _ => return None,
+            };
+            Some(match conversion {
+                Conversion::Epoch => Value::from(timestamp.timestamp()),
+                _ => Value::String(timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+            })
+        }
+    }
+}
+
+/// Epoch seconds, or milliseconds past the year 5138 in seconds.
+fn from_epoch(epoch: f64) -> Option<DateTime<Utc>> {
+    let millis = if epoch.abs() >= 1e11 {
+        epoch
+    } else {
+        epoch * 1000.0
+    };
+    Utc.timestamp_millis_opt(millis.round() as i64).single()
+}
+
+fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    let with_offset = DateTime::parse_from_rfc3339(text)
+        .or_else(|_| DateTime::parse_from_rfc2822(text))
+        .ok()
+        .or_else(|| {
+            ["%Y-%m-%d %H:%M:%S%.f %:z", "%Y-%m-%d %H:%M:%S%.f %z"]
+                .iter()
+                .find_map(|format| DateTime::<FixedOffset>::parse_from_str(text, format).ok())
+        });
+    if let Some(timestamp) = with_offset {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+    let naive = [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)
+    })?;
+    Some(naive.and_utc())
+}
+
+/// Seconds in `text`, a duration in one of the spellings the module
+/// comment lists.
+fn parse_duration(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Some(iso) = text.strip_prefix(['P', 'p']) {
+        return parse_iso_duration(iso);
+    }
+    if text.contains(':') {
+        let parts: Vec<f64> = text
+            .split(':')
+            .map(|part| part.parse::<f64>().ok())
+            .collect::<Option<_>>()?;
+        let [hours, minutes, seconds] = parts[..] else {
+            return None;
+        };
+        return Some(hours * 3600.0 + minutes * 60.0 + seconds);
+    }
+    let mut rest = text;
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let (amount, unit, after) = amount_and_unit(rest)?;
+        total += amount * unit_seconds(unit)?;
+        rest = after.trim_start();
+    }
+    (!text.is_empty()).then_some(total)
+}
+
+/// A duration flag's value (`--timeout`, `--cache-ttl`): bare seconds or
+/// one of the spellings above.
+pub(crate) fn parse_duration_arg(text: &str) -> std::result::Result<std::time::Duration, String> {
+    let seconds = match text.trim().parse::<f64>() {
+        Ok(seconds) => Some(seconds),
+        Err(_) => parse_duration(text),
+    };
+    match seconds {
+        Some(seconds) if seconds.is_finite() && seconds >= 0.0 => {
+            Ok(std::time::Duration::from_secs_f64(seconds))
+        }
+        _ => Err(format!("`{text}` is not a duration (try 60s, 5m or 1h)")),
+    }
+}
+
+/// `PT1H30M`, `P2DT3H`, `P1W` after the `P`; years and months have no
+/// fixed length and are refused.
+fn parse_iso_duration(iso: &str) -> Option<f64> {
+    let (date, time) = match iso.split_once(['T', 't']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (iso, None),
+    };
+    let mut total = 0.0;
+    for (part, units) in [(date, "WD"), (time.unwrap_or(""), "HMS")] {
+        let mut rest = part;
+        while !rest.is_empty() {
+            let (amount, unit, after) = amount_and_unit(rest)?;
+            let unit = unit.to_ascii_uppercase();
+            if !units.contains(unit.as_str()) || unit.len() != 1 {
+                return None;
+            }
+            let seconds = match unit.as_str() {
+                "W" => 604_800.0,
+                "D" => 86_400.0,
+                "H" => 3_600.0,
+                "M" => 60.0,
+                _ => 1.0,
+            };
+            total += amount * seconds;
+            rest = after;
+        }
+    }
+    (time.is_none_or(|time| !time.is_empty()) && !iso.is_empty()).then_some(total)
+}
+
+/// A number, then the letters after it (spaces allowed between), then the
+/// rest of `text`.
+fn amount_and_unit(text: &str) -> Option<(f64, &str, &str)> {
+    let number_end = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let amount = text[..number_end].parse::<f64>().ok()?;
+    let after_number = text[number_end..].trim_start();
+    let unit_end = after_number
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(after_number.len());
+    let unit = &after_number[..unit_end];
+    if unit.is_empty() {
+        return None;
+    }
+    Some((amount, unit, &after_number[unit_end..]))
+}
+
+fn unit_seconds(unit: &str) -> Option<f64> {
+    Some(match unit.to_ascii_lowercase().as_str() {
+        "ns" | "nanosecond" | "nanoseconds" => 1e-9,
+        "us" | "microsecond" | "microseconds" => 1e-6,
+        "ms" | "millisecond" | "milliseconds" => 1e-3,
+        "s" | "sec" | "secs" | "second" | "seconds" => 1.0,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60.0,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 3_600.0,
+        "d" | "day" | "days" => 86_400.0,
+        "w" | "week" | "weeks" => 604_800.0,
+        _ => return None,
+    })
+}
+
+/// `1d 2h 3m 4s`, leaving out zero parts; under a minute, fractions of a
+/// second are kept to the millisecond (`1.5s`).
+fn humanize(seconds: f64) -> Option<String> {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+    let millis = (seconds * 1000.0).round() as u64;
+    if millis < 60_000 {
+        let text = format!("{:.3}", millis as f64 / 1000.0);
+        let text = text.trim_end_matches('0').trim_end_matches('.');
+        return Some(format!("{text}s"));
+    }
+    let total = millis / 1000;
+    let parts = [
+        (total / 86_400, "d"),
+        (total % 86_400 / 3_600, "h"),
+        (total % 3_600 / 60, "m"),
+        (total % 60, "s"),
+    ];
+    let words: Vec<String> = parts
+        .iter()
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{amount}{unit}"))
+        .collect();
+    Some(words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn converted(value: Value, conversion: Conversion) -> Value {
+        let mut value = value;
+        convert(&mut value, conversion).unwrap();
+        value
+    }
+
+    #[test]
+    fn timestamps_in_any_spelling_become_one() {
+        let same = [
+            json!("2024-05-01T12:00:00Z"),
+            json!("2024-05-01T14:00:00+02:00"),
+            json!("2024-05-01 12:00:00"),
+            json!("2024-05-01 07:00:00 -05:00"),
+            json!("Wed, 01 May 2024 12:00:00 +0000"),
+            json!(1714564800),
+            json!("1714564800"),
+            json!(1714564800000_i64),
+        ];
+        for value in same {
+            assert_eq!(
+                converted(value.clone(), Conversion::Epoch),
+                json!(1714564800),
+                "{value}"
+            );
+            assert_eq!(
+                converted(value.clone(), Conversion::Rfc3339),
+                json!("2024-05-01T12:00:00Z"),
+                "{value}"
+            );
+        }
+        assert_eq!(
+            converted(json!("2024-05-01"), Conversion::Rfc3339),
+            json!("2024-05-01T00:00:00Z")
+        );
+        assert_eq!(
+            converted(json!("2024-05-01T12:00:00.250Z"), Conversion::Rfc3339),
+            json!("2024-05-01T12:00:00.250Z")
+        );
+
+        let err = convert(&mut json!("soon"), Conversion::Epoch).unwrap_err();
+        assert!(err.to_string().starts_with("`soon` is not a timestamp"));
+    }
+
+    #[test]
+    fn durations_are_humanized() {
+        let cases = [
+            (json!("90s"), "1m 30s"),
+            (json!("1h30m"), "1h 30m"),
+            (json!("1.5 hours"), "1h 30m"),
+            (json!("PT1H30M"), "1h 30m"),
+            (json!("P2DT3H"), "2d 3h"),
+            (json!("01:30:00"), "1h 30m"),
+            (json!("1500ms"), "1.5s"),
+            (json!(5400), "1h 30m"),
+            (json!("0s"), "0s"),
+            (json!("1w"), "7d"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                converted(value.clone(), Conversion::HumanDuration),
+                json!(expected),
+                "{value}"
+            );
+        }
+        for text in ["P1M", "PT", "1 fortnight", "10:30", "h"] {
+            assert!(
+                convert(&mut json!(text), Conversion::HumanDuration).is_err(),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn duration_arguments_are_std_durations() {
+        use std::time::Duration;
+        assert_eq!(parse_duration_arg("60s").unwrap(), Duration::from_secs(60));
+        assert_eq!(
+            parse_duration_arg("1h30m").unwrap(),
+            Duration::from_secs(5400)
+        );
+        assert_eq!(parse_duration_arg("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(
+            parse_duration_arg("0.5").unwrap(),
+            Duration::from_millis(500)
+        );
+        assert!(parse_duration_arg("soon")
+            .unwrap_err()
+            .contains("not a duration"));
+        assert!(parse_duration_arg("-5").is_err());
+    }
+
+    #[test]
+    fn subtrees_convert_only_the_strings_that_parse() {
+        let value = json!({
+            "created": "2024-05-01 12:00:00",
+            "port": 8080,
+            "name": "web",
+            "jobs": [{"timeout": "PT5M"}, {"timeout": "300"}]
+        });
+        assert_eq!(
+            converted(value.clone(), Conversion::Epoch),
+            json!({
+                "created": 1714564800,
+                "port": 8080,
+                "name": "web",
+                "jobs": [{"timeout": "PT5M"}, {"timeout": "300"}]
+            })
+        );
+        assert_eq!(
+            converted(value, Conversion::HumanDuration)["jobs"],
+            json!([{"timeout": "5m"}, {"timeout": "300"}])
+        );
+    }
+}
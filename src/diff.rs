@@ -1,12 +1,11 @@
 use std::collections::BTreeSet;
-use std::fs;
 
-use anyhow::{Context, Result};
-use clap::Args;
-use colored::Colorize;
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use colored::{Color, Colorize};
 use serde_json::Value;
 
-use crate::{parse_content, Format};
+use crate::{input, parse_content, Format};
 
 #[derive(Args, Debug)]
 pub(crate) struct DiffCli {
@@ -18,6 +17,18 @@ pub(crate) struct DiffCli {
 
     #[arg(long)]
     pub(crate) show_secrets: bool,
+
+    /// `text` for people, or `json-patch` for an RFC 6902 patch that turns
+    /// the left file into the right one (`confctl patch` applies it). The
+    /// patch carries real values: secrets are not masked.
+    #[arg(long, value_enum, default_value_t = DiffOutput::Text)]
+    pub(crate) output: DiffOutput,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum DiffOutput {
+    Text,
+    JsonPatch,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -34,6 +45,14 @@ enum DiffInput {
 
 pub(crate) fn run(cli: DiffCli, use_color: bool) -> Result<bool> {
     let input = load_diff_input(&cli)?;
+    if cli.output == DiffOutput::JsonPatch {
+        let DiffInput::Structured(left, right) = input else {
+            bail!("--output json-patch needs two files confctl can parse");
+        };
+        let ops = crate::patch::between(&left, &right);
+        println!("{}", serde_json::to_string_pretty(&ops)?);
+        return Ok(!ops.is_empty());
+    }
     let output = match input {
         DiffInput::Structured(left, right) => {
             let changes = diff_values(&left, &right);
@@ -57,10 +76,8 @@ pub(crate) fn run(cli: DiffCli, use_color: bool) -> Result<bool> {
 }
 
 fn load_diff_input(cli: &DiffCli) -> Result<DiffInput> {
-    let left = fs::read_to_string(&cli.left_file)
-        .with_context(|| format!("Failed to read file: {}", cli.left_file))?;
-    let right = fs::read_to_string(&cli.right_file)
-        .with_context(|| format!("Failed to read file: {}", cli.right_file))?;
+    let left = read_text(&cli.left_file)?;
+    let right = read_text(&cli.right_file)?;
 
     if cli.format.is_some() {
         let left_value = parse_content(&cli.left_file, &left, cli.format)?;
@@ -77,6 +94,12 @@ fn load_diff_input(cli: &DiffCli) -> Result<DiffInput> {
     }
 }
 
+/// Files, stdin and archive members, as text for the line-diff fallback.
+fn read_text(file: &str) -> Result<String> {
+    let (_, bytes) = input::open(file).read()?;
+    String::from_utf8(bytes).with_context(|| format!("Failed to read file: {file}"))
+}
+
 pub(crate) fn diff_values(left: &Value, right: &Value) -> Vec<DiffChange> {
     let mut changes = Vec::new();
     diff_value_at("", left, right, &mut changes);
@@ -294,6 +317,101 @@ pub(crate) fn format_text_diff(left: &str, right: &str, use_color: bool) -> Stri
     output.join("\n")
 }
 
+/// Lines of context around each change in `unified`.
+const CONTEXT: usize = 3;
+
+/// `diff -u`-style hunks turning `old` into `new`, under `--- old_name` and
+/// `+++ new_name`; empty when they are the same.
+pub(crate) fn unified(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    use_color: bool,
+) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    // Each line with its tag, and how many old and new lines come before it.
+    let mut ops: Vec<(char, &str, usize, usize)> = Vec::new();
+    let (mut old_index, mut new_index) = (0, 0);
+    // The end of both is the last pair, so the tail of changes is flushed.
+    let end = (old_lines.len(), new_lines.len());
+    for (next_old, next_new) in lcs_pairs(&old_lines, &new_lines).into_iter().chain([end]) {
+        while old_index < next_old {
+            ops.push(('-', old_lines[old_index], old_index, new_index));
+            old_index += 1;
+        }
+        while new_index < next_new {
+            ops.push(('+', new_lines[new_index], old_index, new_index));
+            new_index += 1;
+        }
+        if next_old < old_lines.len() {
+            ops.push((' ', old_lines[next_old], old_index, new_index));
+            old_index += 1;
+            new_index += 1;
+        }
+    }
+
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (at, _) in ops.iter().enumerate().filter(|(_, op)| op.0 != ' ') {
+        let start = at.saturating_sub(CONTEXT);
+        let end = (at + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let paint = |line: String, color: Color| -> String {
+        if use_color {
+            line.color(color).to_string()
+        } else {
+            line
+        }
+    };
+    let mut output = vec![
+        heading(&format!("--- {old_name}"), use_color),
+        heading(&format!("+++ {new_name}"), use_color),
+    ];
+    for (start, end) in hunks {
+        let hunk = &ops[start..end];
+        let old_count = hunk.iter().filter(|op| op.0 != '+').count();
+        let new_count = hunk.iter().filter(|op| op.0 != '-').count();
+        let (_, _, old_before, new_before) = hunk[0];
+        output.push(paint(
+            format!(
+                "@@ -{} +{} @@",
+                hunk_range(old_before, old_count),
+                hunk_range(new_before, new_count)
+            ),
+            Color::Cyan,
+        ));
+        for (tag, line, _, _) in hunk {
+            let line = format!("{tag}{line}");
+            output.push(match tag {
+                '-' => paint(line, Color::Red),
+                '+' => paint(line, Color::Green),
+                _ => line,
+            });
+        }
+    }
+    output.push(String::new());
+    output.join("\n")
+}
+
+/// A hunk's `start,count` for lines after the first `before`: the count is
+/// left out when it is 1, and an empty range starts at the line before it.
+fn hunk_range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{before},0"),
+        1 => (before + 1).to_string(),
+        _ => format!("{},{count}", before + 1),
+    }
+}
+
 fn lcs_pairs(left: &[&str], right: &[&str]) -> Vec<(usize, usize)> {
     let mut lengths = vec![vec![0usize; right.len() + 1]; left.len() + 1];
     for i in (0..left.len()).rev() {
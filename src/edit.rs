@@ -0,0 +1,286 @@
+//! `confctl edit` — open a subtree of a JSON, YAML or TOML file in
+//! `$VISUAL` / `$EDITOR`, like `kubectl edit`:
+//!
+//! ```text
+//! confctl edit config.yaml server      # just `server`
+//! confctl edit config.toml             # the whole file
+//! ```
+//!
+//! The subtree is written to a private temporary file in the file's own
+//! format (JSON when TOML cannot hold it, e.g. a lone array). On save the
+//! buffer is parsed; a broken buffer can be reopened, and nothing is written
+//! until it parses. The result is spliced back and the file re-serialised,
+//! as `set` does, so comments are not kept.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use serde_json::Value;
+
+use crate::atomic::{self, BackupArg};
+use crate::emit::{self, OutputFormat};
+use crate::theme;
+use crate::undo;
+use crate::{detect_format, parse_content, resolve_path, resolve_path_mut, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct EditCli {
+    /// JSON, YAML or TOML file to edit.
+    pub(crate) file: String,
+
+    /// Path of the subtree to edit (the whole document when omitted).
+    pub(crate) path: Option<String>,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    #[command(flatten)]
+    pub(crate) backup: BackupArg,
+}
+
+/// `$VISUAL`, else `$EDITOR`, else `vi`, split on whitespace so values like
+/// `code --wait` work.
+fn editor() -> Vec<String> {
+    let command = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    command.split_whitespace().map(str::to_string).collect()
+}
+
+pub(crate) fn run(cli: EditCli) -> Result<()> {
+    edit_with(&cli, &editor())
+}
+
+fn edit_with(cli: &EditCli, editor: &[String]) -> Result<()> {
+    let file = &cli.file;
+    let content = fs::read_to_string(file).with_context(|| format!("reading {file}"))?;
+    let format = detect_format(file, &content, cli.format)?;
+    if !matches!(
+        format,
+        Format::Json | Format::Json5 | Format::Yaml | Format::Toml
+    ) {
+        bail!("edit works on JSON, YAML and TOML files; {file} is {format:?}");
+    }
+    let mut doc = parse_content(file, &content, Some(format))?;
+    let original = match &cli.path {
+        Some(path) => resolve_path(&doc, path)?.clone(),
+        None => doc.clone(),
+    };
+
+    let (buffer_format, text) = buffer(&original, format)?;
+    let temp = temp_file(buffer_format, &text)?;
+    let edited = match edit_until_valid(editor, &temp, &text, buffer_format) {
+        Ok(edited) => edited,
+        Err(err) => {
+            if fs::read_to_string(&temp).is_ok_and(|now| now != text) {
+                eprintln!("Your edits are kept in {}", temp.display());
+            } else {
+                let _ = fs::remove_file(&temp);
+            }
+            return Err(err);
+        }
+    };
+    let _ = fs::remove_file(&temp);
+
+    let Some(edited) = edited.filter(|edited| *edited != original) else {
+        println!("No changes; {file} left as it was");
+        return Ok(());
+    };
+    match &cli.path {
+        Some(path) => *resolve_path_mut(&mut doc, path)? = edited,
+        None => doc = edited,
+    }
+    let output = OutputFormat::from_input(format).unwrap_or(OutputFormat::Json);
+    let rendered = emit::render(&doc, output).with_context(|| format!("writing {file}"))?;
+    undo::record(Path::new(file), "edit")?;
+    atomic::write(Path::new(file), rendered, cli.backup.suffix())?;
+    if atomic::previewing() {
+        return Ok(());
+    }
+    match &cli.path {
+        Some(path) => println!("{} edited {} in {file}", "✓".green().bold(), path.bold()),
+        None => println!("{} edited {file}", "✓".green().bold()),
+    }
+    Ok(())
+}
+
+/// `value` as the editor sees it: in the file's format when that can hold
+/// it, JSON otherwise.
+fn buffer(value: &Value, format: Format) -> Result<(Format, String)> {
+    let native = match format {
+        Format::Yaml => Some(OutputFormat::Yaml),
+        Format::Toml => Some(OutputFormat::Toml),
+        _ => None,
+    };
+    if let Some(text) = native.and_then(|output| emit::render(value, output).ok()) {
+        return Ok((format, text));
+    }
+    Ok((Format::Json, emit::render(value, OutputFormat::Json)?))
+}
+
+/// A new file only the current user can read: the subtree may hold secrets.
+fn temp_file(format: Format, text: &str) -> Result<PathBuf> {
+    let extension = match format {
+        Format::Yaml => "yaml",
+        Format::Toml => "toml",
+        _ => "json",
+    };
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    for n in 0.. {
+        let path = std::env::temp_dir().join(format!(
+            "confctl-edit-{}-{n}.{extension}",
+            std::process::id()
+        ));
+        match options.open(&path) {
+            Ok(mut handle) => {
+                handle.write_all(text.as_bytes())?;
+                return Ok(path);
+            }
+            // Left over from an earlier edit that failed to parse.
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("creating {}", path.display()));
+            }
+        }
+    }
+    unreachable!("0.. never ends")
+}
+
+/// Run the editor on `temp` until the buffer parses. `None` when it was
+/// saved unchanged or emptied, which cancels the edit.
+fn edit_until_valid(
+    editor: &[String],
+    temp: &Path,
+    original: &str,
+    format: Format,
+) -> Result<Option<Value>> {
+    let Some((program, args)) = editor.split_first() else {
+        bail!("no editor configured; set $EDITOR");
+    };
+    let name = temp.to_string_lossy();
+    loop {
+        let status = Command::new(program)
+            .args(args)
+            .arg(temp)
+            .status()
+            .with_context(|| format!("starting the editor `{}`", editor.join(" ")))?;
+        if !status.success() {
+            bail!("the editor `{}` exited with {status}", editor.join(" "));
+        }
+
+        let text = fs::read_to_string(temp).with_context(|| format!("reading {name}"))?;
+        if text == original || text.trim().is_empty() {
+            return Ok(None);
+        }
+        match parse_content(&name, &text, Some(format)) {
+            Ok(value) => return Ok(Some(value)),
+            Err(err) if io::stdin().is_terminal() => {
+                eprintln!("{} {err:#}", theme::stderr("error:".red().bold()));
+                if !reopen()? {
+                    bail!("the edited {} does not parse", format_name(format));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn reopen() -> Result<bool> {
+    eprint!("Edit again? [Y/n] ");
+    io::stderr().flush().ok();
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .context("reading from stdin")?;
+    Ok(!matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "n" | "no"
+    ))
+}
+
+fn format_name(format: Format) -> &'static str {
+    match format {
+        Format::Yaml => "YAML",
+        Format::Toml => "TOML",
+        _ => "JSON",
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn cli(file: &Path, path: Option<&str>) -> EditCli {
+        EditCli {
+            file: file.to_string_lossy().into_owned(),
+            path: path.map(str::to_string),
+            format: None,
+            backup: BackupArg::default(),
+        }
+    }
+
+    /// An "editor" that runs `script` with the buffer as `$0`.
+    fn editor(script: &str) -> Vec<String> {
+        vec!["sh".to_string(), "-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn splices_the_edited_subtree_back() {
+        let dir = crate::scratch_dir("edit-splice");
+        let file = dir.join("config.yaml");
+        fs::write(&file, "server:\n  port: 8080\nname: app\n").unwrap();
+
+        let script = r#"grep -qx 'port: 8080' "$0" && sed -i 's/8080/9090/' "$0""#;
+        edit_with(&cli(&file, Some("server")), &editor(script)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            "name: app\nserver:\n  port: 9090\n"
+        );
+
+        // Saving without changes leaves the file alone.
+        fs::write(&file, "b: 1\na: 2\n").unwrap();
+        edit_with(&cli(&file, None), &editor("true")).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "b: 1\na: 2\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_edits_and_failed_editors_write_nothing() {
+        let dir = crate::scratch_dir("edit-broken");
+        let file = dir.join("config.json");
+        fs::write(&file, "{\"ports\": [80]}").unwrap();
+
+        // stdin is not a terminal under `cargo test`, so there is no reopen.
+        let err = edit_with(&cli(&file, Some("ports")), &editor(r#"echo '[80,' > "$0""#));
+        assert!(err.is_err());
+        let err = edit_with(&cli(&file, None), &editor("exit 3")).unwrap_err();
+        assert!(err.to_string().contains("exited with"), "{err}");
+        assert_eq!(fs::read_to_string(&file).unwrap(), "{\"ports\": [80]}");
+
+        // The broken buffer is kept for the user, and only that one.
+        let prefix = format!("confctl-edit-{}-", std::process::id());
+        let kept: Vec<_> = fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .flatten()
+            .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+            .filter(|e| fs::read_to_string(e.path()).is_ok_and(|text| text == "[80,\n"))
+            .collect();
+        assert_eq!(kept.len(), 1);
+        fs::remove_file(kept[0].path()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+//! `--parse-embedded`: expand string leaves that hold a document of their
+//! own, as ConfigMaps, Helm values and API responses carry JSON or YAML in
+//! strings, so a path can go on into them:
+//! `confctl configmap.yaml data.config.json.db.host`.
+//!
+//! A string is expanded when it parses to an object or an array: by its
+//! key's extension when the key looks like a file name (`config.json`,
+//! `app.properties`, `.env`), otherwise as JSON when it starts with `{` or
+//! `[`, or as YAML when it spans several lines. Anything else stays a
+//! string. Expanded documents are searched again, for strings nested in
+//! them.
+
+use std::path::Path;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::{detect_format, parse_content, path, Format};
+
+/// Expand every embedded document in `value`, in place.
+pub(crate) fn expand(value: &mut Value) {
+    expand_under(value, None);
+}
+
+fn expand_under(value: &mut Value, key: Option<&str>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                expand_under(child, Some(key));
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                expand_under(item, None);
+            }
+        }
+        Value::String(text) => {
+            if let Some(mut document) = embedded(key, text) {
+                expand_under(&mut document, None);
+                *value = document;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The object or array `text` holds, if it holds one.
+fn embedded(key: Option<&str>, text: &str) -> Option<Value> {
+    let by_key = key
+        .filter(|key| *key == ".env" || Path::new(key).extension().is_some())
+        .and_then(|key| Some((key, detect_format(key, text, None).ok()?)))
+        .filter(|(_, format)| !matches!(format, Format::Msgpack | Format::Cbor));
+    let parsed = match by_key {
+        Some((key, format)) => parse_content(key, text, Some(format)),
+        None => {
+            let trimmed = text.trim_start();
+            if trimmed.starts_with(['{', '[']) {
+                parse_content("embedded", text, Some(Format::Json))
+            } else if text.trim().contains('\n') {
+                parse_content("embedded", text, Some(Format::Yaml))
+            } else {
+                return None;
+            }
+        }
+    };
+    parsed
+        .ok()
+        .filter(|document| document.is_object() || document.is_array())
+}
+
+/// `path` with its segments joined back where a key has dots in it, so
+/// `data.config.json.db` reads as `data."config.json".db` when that is the
+/// way through `value`. When no way reaches the end, the one that gets
+/// furthest is kept, so resolve_path reports the segment really missing.
+pub(crate) fn join_dotted_keys(value: &Value, dotted_path: &str) -> Result<String> {
+    let segments = path::segments(dotted_path)?;
+    Ok(path::join(&route(value, &segments).0))
+}
+
+/// The keys for `segments` through `value` and how many segments of them
+/// resolve, trying each segment alone before joining it with the next.
+fn route(value: &Value, segments: &[String]) -> (Vec<String>, usize) {
+    let unresolved = (segments.to_vec(), 0);
+    let Some(first) = segments.first() else {
+        return unresolved;
+    };
+    match value {
+        Value::Object(map) => {
+            let mut best = unresolved;
+            for n in 1..=segments.len() {
+                let key = segments[..n].join(".");
+                let Some(child) = map.get(&key) else {
+                    continue;
+                };
+                let (mut keys, depth) = route(child, &segments[n..]);
+                if n + depth > best.1 {
+                    keys.insert(0, key);
+                    best = (keys, n + depth);
+                }
+                if best.1 == segments.len() {
+                    break;
+                }
+            }
+            best
+        }
+        Value::Array(items) => match first.parse::<usize>().ok().and_then(|i| items.get(i)) {
+            Some(child) => {
+                let (mut keys, depth) = route(child, &segments[1..]);
+                keys.insert(0, first.clone());
+                (keys, depth + 1)
+            }
+            None => unresolved,
+        },
+        _ => unresolved,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn strings_holding_documents_are_expanded() {
+        let mut value = json!({
+            "data": {
+                "config.json": "{\"db\": {\"host\": \"db.internal\"}}",
+                "app.properties": "server.port=8080\n",
+                "rules": "- allow: web\n- deny: '*'\n",
+                "note": "key: value",
+                "list": "[not json",
+                "nested": "{\"inner\": \"[1, 2]\"}"
+            },
+            "items": ["{\"a\": 1}", "plain"]
+        });
+        expand(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "data": {
+                    "config.json": {"db": {"host": "db.internal"}},
+                    "app.properties": {"server": {"port": "8080"}},
+                    "rules": [{"allow": "web"}, {"deny": "*"}],
+                    "note": "key: value",
+                    "list": "[not json",
+                    "nested": {"inner": [1, 2]}
+                },
+                "items": [{"a": 1}, "plain"]
+            })
+        );
+    }
+
+    #[test]
+    fn dotted_keys_are_found_by_joining_segments() {
+        let value = json!({
+            "data": {"config.json": {"db": {"host": "x"}}, "config": {"yaml": 1}},
+            "list": [{"a.b": 1}]
+        });
+        let join = |path: &str| join_dotted_keys(&value, path).unwrap();
+        assert_eq!(
+            join("data.config.json.db.host"),
+            "data.\"config.json\".db.host"
+        );
+        assert_eq!(join("data.config.yaml"), "data.config.yaml");
+        assert_eq!(join("list.0.a.b"), "list.0.\"a.b\"");
+        assert_eq!(join("data.missing.key"), "data.missing.key");
+        assert_eq!(
+            join("data.config.json.db.user"),
+            "data.\"config.json\".db.user"
+        );
+        assert_eq!(join("list.1"), "list.1");
+    }
+}
@@ -0,0 +1,290 @@
+//! YAML with comments, which serde_yaml cannot write: the skeletons of
+//! `generate` (descriptions above keys), `compose-dir --annotate-source`
+//! (the file a value came from, at the end of its line) and the conflicts
+//! of `merge`, between git's `<<<<<<<`, `=======` and `>>>>>>>` lines.
+//!
+//! Only block mappings and sequences of the values a document can hold are
+//! written, keys in the order given; empty ones are `{}` and `[]`.
+
+use serde_json::Value;
+
+/// A value and its comments.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Node {
+    /// Lines above the value (above its key or `- `; at the top for the root).
+    pub(crate) comments: Vec<String>,
+    /// Said at the end of the value's line, or of its key's for a mapping
+    /// or sequence.
+    pub(crate) note: Option<String>,
+    pub(crate) shape: Shape,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Shape {
+    Scalar(Value),
+    Object(Vec<(String, Node)>),
+    Array(Vec<Node>),
+    /// Two versions of one value.
+    Conflict(Box<Conflict>),
+}
+
+/// What each side of a merge made of a value; `None` is a side that
+/// removed it. The labels follow the markers.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Conflict {
+    pub(crate) ours: Option<Node>,
+    pub(crate) theirs: Option<Node>,
+    pub(crate) labels: [String; 2],
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape::Scalar(Value::Null)
+    }
+}
+
+impl Node {
+    /// `value` without comments.
+    pub(crate) fn plain(value: &Value) -> Node {
+        let shape = match value {
+            Value::Object(map) => Shape::Object(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), Node::plain(value)))
+                    .collect(),
+            ),
+            Value::Array(items) => Shape::Array(items.iter().map(Node::plain).collect()),
+            scalar => Shape::Scalar(scalar.clone()),
+        };
+        Node {
+            shape,
+            ..Node::default()
+        }
+    }
+
+    /// The value, comments dropped; ours where there is a conflict.
+    pub(crate) fn value(&self) -> Value {
+        self.ours().unwrap_or(Value::Null)
+    }
+
+    fn ours(&self) -> Option<Value> {
+        Some(match &self.shape {
+            Shape::Scalar(value) => value.clone(),
+            Shape::Object(entries) => Value::Object(
+                entries
+                    .iter()
+                    .filter_map(|(key, node)| Some((key.clone(), node.ours()?)))
+                    .collect(),
+            ),
+            Shape::Array(items) => Value::Array(items.iter().filter_map(Node::ours).collect()),
+            Shape::Conflict(conflict) => conflict.ours.as_ref()?.ours()?,
+        })
+    }
+}
+
+/// `node` as a YAML document.
+pub(crate) fn render(node: &Node) -> String {
+    let mut out = String::new();
+    document(&mut out, node);
+    out
+}
+
+fn document(out: &mut String, node: &Node) {
+    comment(out, 0, &node.comments);
+    match &node.shape {
+        Shape::Scalar(value) => line(out, scalar(value), node),
+        shape if is_empty(shape) => line(out, flow(shape).to_string(), node),
+        Shape::Object(entries) => {
+            comment(out, 0, node.note.as_slice());
+            entries_yaml(out, 0, entries);
+        }
+        Shape::Array(items) => {
+            comment(out, 0, node.note.as_slice());
+            items_yaml(out, 0, items);
+        }
+        Shape::Conflict(conflict) => markers(out, conflict, document),
+    }
+}
+
+fn entries_yaml(out: &mut String, indent: usize, entries: &[(String, Node)]) {
+    for (key, node) in entries {
+        entry_yaml(out, indent, key, node);
+    }
+}
+
+fn entry_yaml(out: &mut String, indent: usize, key: &str, node: &Node) {
+    let pad = " ".repeat(indent);
+    comment(out, indent, &node.comments);
+    let text = scalar(&Value::from(key));
+    match &node.shape {
+        Shape::Scalar(value) => line(out, format!("{pad}{text}: {}", scalar(value)), node),
+        shape if is_empty(shape) => line(out, format!("{pad}{text}: {}", flow(shape)), node),
+        Shape::Object(entries) => {
+            line(out, format!("{pad}{text}:"), node);
+            entries_yaml(out, indent + 2, entries);
+        }
+        Shape::Array(items) => {
+            line(out, format!("{pad}{text}:"), node);
+            items_yaml(out, indent + 2, items);
+        }
+        Shape::Conflict(conflict) => markers(out, conflict, |out, node| {
+            entry_yaml(out, indent, key, node)
+        }),
+    }
+}
+
+/// Sequence items; a mapping item starts on the `- ` line, so its note goes
+/// above it with the comments.
+fn items_yaml(out: &mut String, indent: usize, items: &[Node]) {
+    for node in items {
+        item_yaml(out, indent, node);
+    }
+}
+
+fn item_yaml(out: &mut String, indent: usize, node: &Node) {
+    let pad = " ".repeat(indent);
+    comment(out, indent, &node.comments);
+    match &node.shape {
+        Shape::Scalar(value) => line(out, format!("{pad}- {}", scalar(value)), node),
+        shape if is_empty(shape) => line(out, format!("{pad}- {}", flow(shape)), node),
+        Shape::Object(entries) => {
+            comment(out, indent, node.note.as_slice());
+            let mut block = String::new();
+            entries_yaml(&mut block, indent + 2, entries);
+            let inner = " ".repeat(indent + 2);
+            match block.strip_prefix(&inner) {
+                Some(rest) => out.push_str(&format!("{pad}- {rest}")),
+                // A conflict marker comes first, and markers start lines.
+                None => out.push_str(&format!("{pad}-\n{block}")),
+            }
+        }
+        Shape::Array(items) => {
+            line(out, format!("{pad}-"), node);
+            items_yaml(out, indent + 2, items);
+        }
+        Shape::Conflict(conflict) => {
+            markers(out, conflict, |out, node| item_yaml(out, indent, node))
+        }
+    }
+}
+
+/// Each side of `conflict` written by `write`, between git's markers.
+fn markers(out: &mut String, conflict: &Conflict, write: impl Fn(&mut String, &Node)) {
+    let [ours, theirs] = &conflict.labels;
+    out.push_str(&format!("<<<<<<< {ours}\n"));
+    if let Some(node) = &conflict.ours {
+        write(out, node);
+    }
+    out.push_str("=======\n");
+    if let Some(node) = &conflict.theirs {
+        write(out, node);
+    }
+    out.push_str(&format!(">>>>>>> {theirs}\n"));
+}
+
+/// `text` and `node`'s note, as one line.
+fn line(out: &mut String, text: String, node: &Node) {
+    out.push_str(&text);
+    if let Some(note) = &node.note {
+        out.push_str(&format!("  # {note}"));
+    }
+    out.push('\n');
+}
+
+fn comment(out: &mut String, indent: usize, lines: &[String]) {
+    let pad = " ".repeat(indent);
+    for line in lines {
+        match line.as_str() {
+            "" => out.push_str(&format!("{pad}#\n")),
+            line => out.push_str(&format!("{pad}# {line}\n")),
+        }
+    }
+}
+
+fn is_empty(shape: &Shape) -> bool {
+    match shape {
+        Shape::Object(entries) => entries.is_empty(),
+        Shape::Array(items) => items.is_empty(),
+        Shape::Scalar(_) | Shape::Conflict(_) => false,
+    }
+}
+
+fn flow(shape: &Shape) -> &'static str {
+    match shape {
+        Shape::Object(_) => "{}",
+        _ => "[]",
+    }
+}
+
+/// A YAML scalar: strings plain when YAML would read them back as the same
+/// string, double-quoted (JSON escapes are YAML's too) otherwise.
+fn scalar(value: &Value) -> String {
+    let Value::String(text) = value else {
+        return value.to_string();
+    };
+    let plain = !text.is_empty()
+        && text.trim() == text
+        && text
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_./@ ".contains(c))
+        && !text.starts_with(['-', '.', '@'])
+        && !text.contains(" -")
+        && text.parse::<f64>().is_err()
+        && !matches!(
+            text.to_ascii_lowercase().as_str(),
+            "null" | "~" | "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n"
+        );
+    if plain {
+        text.clone()
+    } else {
+        Value::String(text.clone()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn noted(value: Value, note: &str) -> Node {
+        Node {
+            note: Some(note.into()),
+            ..Node::plain(&value)
+        }
+    }
+
+    #[test]
+    fn notes_end_lines_and_strings_are_quoted_when_needed() {
+        let node = Node {
+            shape: Shape::Object(vec![
+                ("db".into(), noted(json!({"host": "db1"}), "10-db.yaml")),
+                ("empty".into(), noted(json!({}), "20-app.yaml")),
+                ("ports".into(), noted(json!([80, 443]), "20-app.yaml")),
+                (
+                    "workers".into(),
+                    Node {
+                        shape: Shape::Array(vec![noted(json!({"name": "a"}), "30-x.yaml")]),
+                        ..Node::default()
+                    },
+                ),
+            ]),
+            ..Node::default()
+        };
+        assert_eq!(
+            render(&node),
+            "db:  # 10-db.yaml\n\
+             \x20 host: db1\n\
+             empty: {}  # 20-app.yaml\n\
+             ports:  # 20-app.yaml\n\
+             \x20 - 80\n\
+             \x20 - 443\n\
+             workers:\n\
+             \x20 # 30-x.yaml\n\
+             \x20 - name: a\n"
+        );
+        assert_eq!(render(&noted(json!("on"), "a.env")), "\"on\"  # a.env\n");
+        assert_eq!(scalar(&json!("8080")), "\"8080\"");
+        assert_eq!(scalar(&json!("-x")), "\"-x\"");
+        assert_eq!(scalar(&json!("a: b")), "\"a: b\"");
+        assert_eq!(scalar(&json!("eu-west-1")), "eu-west-1");
+    }
+}
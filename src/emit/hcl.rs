@@ -0,0 +1,424 @@
+//! HCL writer: `.tfvars` files for plain documents, native block syntax for
+//! documents in Terraform's JSON syntax.
+//!
+//! Blocks vs attributes is decided by shape and name:
+//! - At the root, Terraform block types (`resource`, `variable`, …) become
+//!   blocks, consuming as many nested keys as that type has labels
+//!   (`resource "aws_s3_bucket" "logs" { … }`).
+//! - Inside a block, known nested blocks (`lifecycle`, `backend "s3"`, …)
+//!   and arrays of objects (`ingress { … }` per element) become blocks.
+//! - Everything else is an attribute; objects render as `{ k = v }` maps.
+//!
+//! Strings are literal: `${` and `%{` are written as `$${` and `%%{`, so a
+//! value is read back as the same text. With `templates` they are left as
+//! HCL templates, exactly as Terraform reads them in JSON syntax, so
+//! `${var.x}` interpolations carry over unchanged.
+//!
+//! Attribute names in a body must be identifiers; any other key is an error,
+//! as HCL has no quoted form for them. Map keys may be quoted.
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+/// Top-level block types and how many labels each takes.
+const ROOT_BLOCKS: &[(&str, usize)] = &[
+    ("resource", 2),
+    ("data", 2),
+    ("module", 1),
+    ("variable", 1),
+    ("output", 1),
+    ("provider", 1),
+    ("check", 1),
+    ("terraform", 0),
+    ("locals", 0),
+    ("moved", 0),
+    ("import", 0),
+];
+
+/// Block types that appear inside other blocks, with their label counts.
+const NESTED_BLOCKS: &[(&str, usize)] = &[
+    ("backend", 1),
+    ("provisioner", 1),
+    ("dynamic", 1),
+    ("lifecycle", 0),
+    ("connection", 0),
+    ("required_providers", 0),
+    ("cloud", 0),
+    ("content", 0),
+    ("timeouts", 0),
+    ("precondition", 0),
+    ("postcondition", 0),
+    ("validation", 0),
+];
+
+/// Scalar arrays longer than this are wrapped one element per line.
+const INLINE_WIDTH: usize = 80;
+
+/// `value` as an HCL body; `templates` keeps `${…}` and `%{…}` live.
+pub(crate) fn render(value: &Value, templates: bool) -> Result<String> {
+    let Value::Object(map) = value else {
+        bail!("HCL output needs an object at the top level");
+    };
+    let mut out = String::new();
+    write_body(&mut out, &body_items(map, 0, true, templates)?, 0);
+    Ok(out)
+}
+
+enum Item {
+    Attribute { key: String, expr: String },
+    Block(String),
+}
+
+fn body_items(
+    map: &Map<String, Value>,
+    indent: usize,
+    root: bool,
+    templates: bool,
+) -> Result<Vec<Item>> {
+    let known = if root { ROOT_BLOCKS } else { NESTED_BLOCKS };
+    let mut items = Vec::new();
+    for (key, value) in map {
+        let labels = known.iter().find(|(name, _)| name == key).map(|(_, n)| *n);
+        match labels {
+            Some(labels) if is_block_shaped(value, labels) => {
+                let mut text = String::new();
+                write_blocks(
+                    &mut text,
+                    key,
+                    &mut Vec::new(),
+                    labels,
+                    value,
+                    indent,
+                    templates,
+                )?;
+                items.push(Item::Block(text));
+            }
+            _ if !root && is_array_of_objects(value) => {
+                let mut text = String::new();
+                write_blocks(&mut text, key, &mut Vec::new(), 0, value, indent, templates)?;
+                items.push(Item::Block(text));
+            }
+            _ if !is_identifier(key) => {
+                bail!("{key:?} is not a valid HCL attribute name (letters, digits, _ and -)")
+            }
+            _ => items.push(Item::Attribute {
+                key: key.clone(),
+                expr: render_expr(value, indent, templates),
+            }),
+        }
+    }
+    Ok(items)
+}
+
+fn is_array_of_objects(value: &Value) -> bool {
+    matches!(value, Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+/// Whether `value` can be written as a block taking `labels` more labels.
+fn is_block_shaped(value: &Value, labels: usize) -> bool {
+    match value {
+        Value::Object(_) if labels == 0 => true,
+        Value::Object(map) => {
+            !map.is_empty() && map.values().all(|v| is_block_shaped(v, labels - 1))
+        }
+        Value::Array(items) => {
+            !items.is_empty()
+                && items
+                    .iter()
+                    .all(|v| v.is_object() && is_block_shaped(v, labels))
+        }
+        _ => false,
+    }
+}
+
+fn write_blocks(
+    out: &mut String,
+    block_type: &str,
+    labels: &mut Vec<String>,
+    remaining: usize,
+    value: &Value,
+    indent: usize,
+    templates: bool,
+) -> Result<()> {
+    match value {
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                }
+                write_blocks(out, block_type, labels, remaining, item, indent, templates)?;
+            }
+        }
+        Value::Object(map) if remaining > 0 => {
+            for (i, (label, inner)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                }
+                labels.push(label.clone());
+                write_blocks(
+                    out,
+                    block_type,
+                    labels,
+                    remaining - 1,
+                    inner,
+                    indent,
+                    templates,
+                )?;
+                labels.pop();
+            }
+        }
+        Value::Object(map) => {
+            let pad = " ".repeat(indent);
+            out.push_str(&pad);
+            out.push_str(block_type);
+            for label in labels.iter() {
+                out.push(' ');
+                out.push_str(&quote(label));
+            }
+            if map.is_empty() {
+                out.push_str(" {}\n");
+                return Ok(());
+            }
+            out.push_str(" {\n");
+            let items = body_items(map, indent + 2, false, templates)?;
+            write_body(out, &items, indent + 2);
+            out.push_str(&pad);
+            out.push_str("}\n");
+        }
+        _ => unreachable!("checked by is_block_shaped"),
+    }
+    Ok(())
+}
+
+/// Attributes with `=` aligned across runs of single-line values (as
+/// `terraform fmt` does), and a blank line around every block.
+fn write_body(out: &mut String, items: &[Item], indent: usize) {
+    let pad = " ".repeat(indent);
+    let mut i = 0;
+    let mut last_was_block = None;
+    while i < items.len() {
+        if let Some(was_block) = last_was_block {
+            if was_block || matches!(items[i], Item::Block(_)) {
+                out.push('\n');
+            }
+        }
+        match &items[i] {
+            Item::Block(text) => {
+                out.push_str(text);
+                last_was_block = Some(true);
+                i += 1;
+            }
+            Item::Attribute { .. } => {
+                let run_end = items[i..]
+                    .iter()
+                    .position(|item| !matches!(item, Item::Attribute { .. }))
+                    .map_or(items.len(), |n| i + n);
+                write_attributes(out, &items[i..run_end], &pad);
+                last_was_block = Some(false);
+                i = run_end;
+            }
+        }
+    }
+}
+
+fn write_attributes(out: &mut String, items: &[Item], pad: &str) {
+    let single_line = |item: &Item| match item {
+        Item::Attribute { expr, .. } => !expr.contains('\n'),
+        Item::Block(_) => false,
+    };
+    let mut start = 0;
+    while start < items.len() {
+        let end = if single_line(&items[start]) {
+            items[start..]
+                .iter()
+                .position(|item| !single_line(item))
+                .map_or(items.len(), |n| start + n)
+        } else {
+            start + 1
+        };
+        let width = items[start..end]
+            .iter()
+            .map(|item| match item {
+                Item::Attribute { key, .. } => key.chars().count(),
+                Item::Block(_) => 0,
+            })
+            .max()
+            .unwrap_or(0);
+        for item in &items[start..end] {
+            if let Item::Attribute { key, expr } = item {
+                out.push_str(&format!("{pad}{key:<width$} = {expr}\n"));
+            }
+        }
+        start = end;
+    }
+}
+
+/// An expression for `value`; nested lines are indented relative to `indent`.
+fn render_expr(value: &Value, indent: usize, templates: bool) -> String {
+    match value {
+        Value::Null | Value::Bool(_) | Value::Number(_) => value.to_string(),
+        Value::String(s) => template(s, templates),
+        Value::Array(items) if items.is_empty() => "[]".to_string(),
+        Value::Array(items) => {
+            let scalars = items.iter().all(|v| !v.is_array() && !v.is_object());
+            if scalars {
+                let inline: Vec<String> =
+                    items.iter().map(|v| render_expr(v, 0, templates)).collect();
+                let inline = format!("[{}]", inline.join(", "));
+                if inline.len() <= INLINE_WIDTH {
+                    return inline;
+                }
+            }
+            let pad = " ".repeat(indent + 2);
+            let mut out = "[\n".to_string();
+            for item in items {
+                out.push_str(&format!(
+                    "{pad}{},\n",
+                    render_expr(item, indent + 2, templates)
+                ));
+            }
+            out.push_str(&" ".repeat(indent));
+            out.push(']');
+            out
+        }
+        Value::Object(map) if map.is_empty() => "{}".to_string(),
+        Value::Object(map) => {
+            let items: Vec<Item> = map
+                .iter()
+                .map(|(key, value)| Item::Attribute {
+                    key: render_key(key, templates),
+                    expr: render_expr(value, indent + 2, templates),
+                })
+                .collect();
+            let mut out = "{\n".to_string();
+            write_attributes(&mut out, &items, &" ".repeat(indent + 2));
+            out.push_str(&" ".repeat(indent));
+            out.push('}');
+            out
+        }
+    }
+}
+
+/// Map key: bare identifier when HCL allows one, quoted string otherwise.
+fn render_key(key: &str, templates: bool) -> String {
+    if is_identifier(key) {
+        key.to_string()
+    } else {
+        template(key, templates)
+    }
+}
+
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// A quoted string expression; without `templates`, `${` and `%{` are
+/// escaped so the text is read back literally.
+fn template(s: &str, templates: bool) -> String {
+    let quoted = quote(s);
+    if templates {
+        quoted
+    } else {
+        quoted.replace("${", "$${").replace("%{", "%%{")
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn plain_documents_become_tfvars() {
+        let value = json!({
+            "region": "eu-west-1",
+            "instance_count": 3,
+            "tags": {"team": "infra", "cost-center": "42"},
+            "subnets": [{"cidr": "10.0.0.0/24"}]
+        });
+        let text = render(&value, false).unwrap();
+        assert_eq!(
+            text,
+            "instance_count = 3\n\
+             region         = \"eu-west-1\"\n\
+             subnets = [\n  {\n    cidr = \"10.0.0.0/24\"\n  },\n]\n\
+             tags = {\n  cost-center = \"42\"\n  team        = \"infra\"\n}\n"
+        );
+    }
+
+    #[test]
+    fn terraform_json_becomes_labelled_blocks() {
+        let value = json!({
+            "resource": {"aws_s3_bucket": {"logs": {
+                "bucket": "${var.prefix}-logs",
+                "lifecycle": {"prevent_destroy": true},
+                "rule": [{"id": "a"}, {"id": "b"}]
+            }}},
+            "terraform": {"backend": {"s3": {"key": "state"}}}
+        });
+        let text = render(&value, true).unwrap();
+        assert_eq!(
+            text,
+            "resource \"aws_s3_bucket\" \"logs\" {\n  bucket = \"${var.prefix}-logs\"\n\n  \
+             lifecycle {\n    prevent_destroy = true\n  }\n\n  \
+             rule {\n    id = \"a\"\n  }\n\n  rule {\n    id = \"b\"\n  }\n}\n\n\
+             terraform {\n  backend \"s3\" {\n    key = \"state\"\n  }\n}\n"
+        );
+    }
+
+    #[test]
+    fn misshapen_block_keywords_stay_attributes() {
+        let text = render(&json!({"variable": "x", "module": {"a": 1}}), false).unwrap();
+        assert_eq!(text, "module = {\n  a = 1\n}\nvariable = \"x\"\n");
+    }
+
+    #[test]
+    fn strings_and_keys_are_escaped() {
+        let text = render(&json!({"m": {"a b": "say \"hi\"\n"}}), false).unwrap();
+        assert_eq!(text, "m = {\n  \"a b\" = \"say \\\"hi\\\"\\n\"\n}\n");
+        assert!(render(&json!([1]), false).is_err());
+    }
+
+    #[test]
+    fn attribute_names_must_be_identifiers() {
+        let err = render(&json!({"a b": 1}), false).unwrap_err();
+        assert!(err.to_string().contains("\"a b\""), "{err}");
+        let block = json!({"resource": {"t": {"n": {"x.y": 1}}}});
+        assert!(render(&block, false).is_err());
+    }
+
+    #[test]
+    fn template_sequences_are_literal_unless_asked() {
+        let value = json!({"motd": "cost ${5} at 100%{x}", "tags": {"${k}": "v"}});
+        assert_eq!(
+            render(&value, false).unwrap(),
+            "motd = \"cost $${5} at 100%%{x}\"\ntags = {\n  \"$${k}\" = \"v\"\n}\n"
+        );
+        assert_eq!(
+            render(&json!({"motd": "${var.x}"}), true).unwrap(),
+            "motd = \"${var.x}\"\n"
+        );
+    }
+}
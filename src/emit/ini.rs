@@ -0,0 +1,153 @@
+//! INI writer.
+//!
+//! Top-level objects become `[sections]`; top-level scalars are written
+//! first, before any section header. Anything nested deeper than a section
+//! flattens into dotted keys (`pool.size`), arrays into `hosts[0]`.
+//!
+//! Values are quoted when a reader would otherwise mangle them (leading or
+//! trailing spaces, `;`/`#` comment markers, quotes, control characters),
+//! with backslash escapes inside the quotes. Separators and comment markers
+//! in keys and section names are backslash-escaped. Unicode is kept as-is:
+//! INI files are read as UTF-8 by the common parsers.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+use super::properties::{flatten, scalar_text};
+
+pub(crate) fn render(value: &Value) -> Result<String> {
+    let Value::Object(map) = value else {
+        bail!("INI output needs an object at the top level");
+    };
+
+    let mut out = String::new();
+    let mut globals = Vec::new();
+    for (key, value) in map.iter().filter(|(_, v)| !v.is_object()) {
+        flatten(key, value, &mut globals);
+    }
+    write_entries(&mut out, &globals);
+
+    for (name, section) in map.iter().filter(|(_, v)| v.is_object()) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("[{}]\n", escape_key(name, "]")));
+        let mut entries = Vec::new();
+        if let Value::Object(fields) = section {
+            for (key, value) in fields {
+                flatten(key, value, &mut entries);
+            }
+        }
+        write_entries(&mut out, &entries);
+    }
+    Ok(out)
+}
+
+fn write_entries(out: &mut String, entries: &[(String, Value)]) {
+    for (key, value) in entries {
+        let text = scalar_text(value);
+        out.push_str(&format!(
+            "{} = {}\n",
+            escape_key(key, "=:"),
+            quote_value(&text)
+        ));
+    }
+}
+
+/// Backslash-escape `special` plus the characters that would start a
+/// comment or a section header, or be trimmed away.
+fn escape_key(key: &str, special: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    for c in key.chars() {
+        match c {
+            '\\' | ';' | '#' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if special.contains(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    if out.starts_with([' ', '[']) {
+        out.insert(0, '\\');
+    }
+    if out.ends_with(' ') {
+        out.insert(out.len() - 1, '\\');
+    }
+    out
+}
+
+fn quote_value(text: &str) -> String {
+    let needs_quotes = text.starts_with(char::is_whitespace)
+        || text.ends_with(char::is_whitespace)
+        || text.contains([';', '#', '"', '\\'])
+        || text.contains(char::is_control);
+    if !needs_quotes {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn first_level_objects_become_sections() {
+        let value = json!({
+            "name": "api",
+            "tags": ["a", "b"],
+            "database": {"host": "db.local", "pool": {"size": 5}},
+            "empty": {}
+        });
+        assert_eq!(
+            render(&value).unwrap(),
+            "name = api\n\
+             tags[0] = a\n\
+             tags[1] = b\n\
+             \n\
+             [database]\n\
+             host = db.local\n\
+             pool.size = 5\n\
+             \n\
+             [empty]\n"
+        );
+    }
+
+    #[test]
+    fn quotes_values_and_escapes_keys() {
+        let value = json!({
+            "s]ec": {"a=b": " padded ", "path": "C:\\tmp", "note": "x ; y", "utf": "ção"}
+        });
+        assert_eq!(
+            render(&value).unwrap(),
+            "[s\\]ec]\n\
+             a\\=b = \" padded \"\n\
+             note = \"x ; y\"\n\
+             path = \"C:\\\\tmp\"\n\
+             utf = ção\n"
+        );
+        assert!(render(&json!([1])).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! JSON Canonicalization Scheme (RFC 8785) writer for
+//! `--output json-canonical`: no whitespace, object keys sorted by their
+//! UTF-16 code units, strings escaped minimally, and numbers written the way
+//! ECMAScript's `Number.prototype.toString` writes an IEEE-754 double. The
+//! bytes match other JCS implementations, so they can be hashed or signed.
+
+use serde_json::{Number, Value};
+
+/// The canonical text, without a trailing newline (it would change the hash).
+pub(super) fn render(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&number(n)),
+        Value::String(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Only `"`, `\` and control characters are escaped; everything else is
+/// written as UTF-8.
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\u{c}' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if u32::from(c) < 0x20 => out.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// ECMAScript number-to-string. JCS models every number as a double, so
+/// integers beyond 2^53 are rounded like any other JCS tool would.
+fn number(n: &Number) -> String {
+    let value = n.as_f64().unwrap_or_default();
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    // `{:e}` gives the shortest digits that round-trip, as ECMAScript needs.
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap_or(0) + 1;
+
+    let body = if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat((-n) as usize))
+    } else {
+        let sign = if n > 0 { '+' } else { '-' };
+        let exp = (n - 1).abs();
+        match digits.split_at(1) {
+            (first, "") => format!("{first}e{sign}{exp}"),
+            (first, rest) => format!("{first}.{rest}e{sign}{exp}"),
+        }
+    };
+    if value < 0.0 {
+        format!("-{body}")
+    } else {
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rfc_8785_sample() {
+        let input = r#"{
+            "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+            "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+            "literals": [null, true, false]
+        }"#;
+        let value: Value = serde_json::from_str(input).unwrap();
+        assert_eq!(
+            render(&value),
+            r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+        );
+    }
+
+    #[test]
+    fn keys_sort_by_utf16_code_units() {
+        let value = json!({
+            "\u{20ac}": "Euro Sign",
+            "\r": "Carriage Return",
+            "\u{fb33}": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\u{1f600}": "Emoji: Grinning Face",
+            "\u{80}": "Control",
+            "\u{f6}": "Latin Small Letter O With Diaeresis"
+        });
+        let keys: Vec<String> = render(&value)
+            .split(['{', ','])
+            .filter(|part| !part.is_empty())
+            .map(|part| part.split(':').next().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "\"\\r\"",
+                "\"1\"",
+                "\"\u{80}\"",
+                "\"\u{f6}\"",
+                "\"\u{20ac}\"",
+                "\"\u{1f600}\"",
+                "\"\u{fb33}\""
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_follow_ecmascript() {
+        let cases = [
+            (json!(0), "0"),
+            (json!(-0.0), "0"),
+            (json!(-42), "-42"),
+            (json!(1e20), "100000000000000000000"),
+            (json!(1e21), "1e+21"),
+            (json!(0.000001), "0.000001"),
+            (json!(1e-7), "1e-7"),
+            (json!(123.456), "123.456"),
+            (json!(5e-324), "5e-324"),
+            (json!(-1.7976931348623157e308), "-1.7976931348623157e+308"),
+            (json!(9007199254740993_u64), "9007199254740992"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(render(&value), expected, "{value}");
+        }
+    }
+}
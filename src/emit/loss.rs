@@ -0,0 +1,347 @@
+//! What a conversion to a flat format (ENV, properties, INI) or to TOML
+//! loses.
+//!
+//! The writers never fail on nesting; they flatten, stringify or, when two
+//! paths end up under the same key, silently keep only the last. TOML keeps
+//! structure but has no null, so its writer leaves nulls out. `report`
+//! walks the document the same way the writers do and lists every such
+//! path, arrays flattened into indexed keys included, so `convert` and
+//! `--output` can warn (or fail with `--strict-convert`). Numbers and
+//! booleans becoming text is not listed: every reader of these formats
+//! parses them back.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+use serde_json::Value;
+
+use super::OutputFormat;
+use crate::theme;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum LossKind {
+    /// Nested structure written as prefixed keys.
+    Flattened,
+    /// Value written as text that no longer says what type it was.
+    Stringified,
+    /// Value missing from the output altogether.
+    Dropped,
+}
+
+impl fmt::Display for LossKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            LossKind::Flattened => "flattened",
+            LossKind::Stringified => "stringified",
+            LossKind::Dropped => "dropped",
+        })
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Loss {
+    /// Dotted path in the source document.
+    pub(crate) path: String,
+    pub(crate) kind: LossKind,
+    pub(crate) detail: String,
+}
+
+/// Every path that `format` cannot represent faithfully; empty for the
+/// formats that keep structure.
+pub(crate) fn report(value: &Value, format: OutputFormat) -> Vec<Loss> {
+    if format == OutputFormat::Toml {
+        return toml_nulls(value);
+    }
+    let flat = matches!(
+        format,
+        OutputFormat::Env | OutputFormat::Properties | OutputFormat::Ini
+    );
+    // Non-object roots are rejected by the writers themselves.
+    let (true, Value::Object(map)) = (flat, value) else {
+        return Vec::new();
+    };
+
+    let mut walker = Walker {
+        format,
+        losses: Vec::new(),
+        written: HashMap::new(),
+    };
+    for (key, child) in map {
+        match child {
+            // INI keeps one level of nesting as sections.
+            Value::Object(fields) if format == OutputFormat::Ini => {
+                for (field, value) in fields {
+                    walker.walk(key, field, &format!("{key}.{field}"), value, Parent::Root);
+                }
+            }
+            _ => walker.walk("", &walker.root_key(key), key, child, Parent::Root),
+        }
+    }
+    walker.losses
+}
+
+/// Print the report to stderr, or turn it into an error when `strict`.
+pub(crate) fn check(losses: &[Loss], format: OutputFormat, strict: bool) -> Result<()> {
+    if losses.is_empty() {
+        return Ok(());
+    }
+    let width = losses.iter().map(|l| l.path.len()).max().unwrap_or(0);
+    let lines: Vec<String> = losses
+        .iter()
+        .map(|l| format!("  {:<11}  {:<width$}  {}", l.kind, l.path, l.detail))
+        .collect();
+    let summary = format!(
+        "{} output loses information at {} path{}",
+        format.extension(),
+        losses.len(),
+        if losses.len() == 1 { "" } else { "s" }
+    );
+    if strict {
+        bail!("{summary} (--strict-convert):\n{}", lines.join("\n"));
+    }
+    eprintln!("{} {summary}:", theme::stderr("warning:".yellow().bold()));
+    for line in lines {
+        eprintln!("{line}");
+    }
+    Ok(())
+}
+
+fn toml_nulls(value: &Value) -> Vec<Loss> {
+    let mut losses = Vec::new();
+    crate::find::walk(value, "", &mut |path, key, child| {
+        if child.is_null() {
+            let what = if key.is_some() { "key" } else { "item" };
+            losses.push(Loss {
+                path: path.to_string(),
+                kind: LossKind::Dropped,
+                detail: format!("TOML has no null; {what} left out"),
+            });
+        }
+    });
+    losses
+}
+
+/// What a value sits in, as far as the flattening report is concerned.
+#[derive(Clone, Copy, PartialEq)]
+enum Parent {
+    /// A top-level key (or an INI section field): nothing reported yet.
+    Root,
+    /// A flattened object, already reported.
+    Object,
+    /// A flattened array, already reported.
+    Array,
+}
+
+struct Walker {
+    format: OutputFormat,
+    losses: Vec<Loss>,
+    /// (section, written key) → source path that currently owns it.
+    written: HashMap<(String, String), String>,
+}
+
+impl Walker {
+    fn root_key(&self, key: &str) -> String {
+        match self.format {
+            OutputFormat::Env => super::env_key(key),
+            _ => key.to_string(),
+        }
+    }
+
+    fn child_key(&self, parent: &str, key: &str) -> String {
+        match self.format {
+            OutputFormat::Env => format!("{parent}_{}", super::env_key(key)),
+            _ => format!("{parent}.{key}"),
+        }
+    }
+
+    fn push(&mut self, path: &str, kind: LossKind, detail: String) {
+        self.losses.push(Loss {
+            path: path.to_string(),
+            kind,
+            detail,
+        });
+    }
+
+    /// Only the outermost flattened object is listed, but an array inside
+    /// it is listed as well, as its items become indexed keys; nothing below
+    /// a listed array is.
+    fn walk(&mut self, section: &str, key: &str, path: &str, value: &Value, parent: Parent) {
+        let env = self.format == OutputFormat::Env;
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                if parent == Parent::Root {
+                    let keys = match self.format {
+                        OutputFormat::Env => format!("{key}_*"),
+                        _ => format!("{key}.*"),
+                    };
+                    self.push(
+                        path,
+                        LossKind::Flattened,
+                        format!("nested keys written as {keys}"),
+                    );
+                }
+                for (child, inner) in map {
+                    let child_key = self.child_key(key, child);
+                    let child_path = format!("{path}.{child}");
+                    let under = match parent {
+                        Parent::Array => Parent::Array,
+                        _ => Parent::Object,
+                    };
+                    self.walk(section, &child_key, &child_path, inner, under);
+                }
+                return;
+            }
+            Value::Array(items) if !items.is_empty() && !env => {
+                if parent != Parent::Array {
+                    self.push(
+                        path,
+                        LossKind::Flattened,
+                        format!("items written as {key}[0], {key}[1], …"),
+                    );
+                }
+                for (i, item) in items.iter().enumerate() {
+                    self.walk(
+                        section,
+                        &format!("{key}[{i}]"),
+                        &format!("{path}.{i}"),
+                        item,
+                        Parent::Array,
+                    );
+                }
+                return;
+            }
+            Value::Object(_) if env => {
+                self.push(
+                    path,
+                    LossKind::Dropped,
+                    "empty object has no ENV form".to_string(),
+                );
+                return;
+            }
+            Value::Array(_) if env => self.push(
+                path,
+                LossKind::Stringified,
+                "array written as JSON text".to_string(),
+            ),
+            Value::Object(_) | Value::Array(_) => self.push(
+                path,
+                LossKind::Stringified,
+                format!("empty container written as `{value}`"),
+            ),
+            Value::Null => self.push(
+                path,
+                LossKind::Stringified,
+                "null written as an empty value".to_string(),
+            ),
+            _ => {}
+        }
+
+        let slot = (section.to_string(), key.to_string());
+        if let Some(previous) = self.written.insert(slot, path.to_string()) {
+            self.push(
+                &previous,
+                LossKind::Dropped,
+                format!("overwritten by {path} (both write `{key}`)"),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kinds(value: &Value, format: OutputFormat) -> Vec<(String, LossKind)> {
+        report(value, format)
+            .into_iter()
+            .map(|l| (l.path, l.kind))
+            .collect()
+    }
+
+    #[test]
+    fn env_reports_flattening_arrays_and_collisions() {
+        let value = json!({
+            "db": {"host": "x", "pool": {"size": 5}},
+            "db_host": "y",
+            "hosts": ["a"],
+            "empty": {},
+            "debug": null
+        });
+        assert_eq!(
+            kinds(&value, OutputFormat::Env),
+            vec![
+                ("db".to_string(), LossKind::Flattened),
+                ("db.host".to_string(), LossKind::Dropped),
+                ("debug".to_string(), LossKind::Stringified),
+                ("empty".to_string(), LossKind::Dropped),
+                ("hosts".to_string(), LossKind::Stringified),
+            ]
+        );
+    }
+
+    #[test]
+    fn ini_keeps_sections_and_properties_do_not() {
+        let value = json!({"server": {"port": 80, "tls": {"on": true}}, "tags": []});
+        assert_eq!(
+            kinds(&value, OutputFormat::Ini),
+            vec![
+                ("server.tls".to_string(), LossKind::Flattened),
+                ("tags".to_string(), LossKind::Stringified),
+            ]
+        );
+        assert_eq!(
+            kinds(&value, OutputFormat::Properties),
+            vec![
+                ("server".to_string(), LossKind::Flattened),
+                ("tags".to_string(), LossKind::Stringified),
+            ]
+        );
+        assert!(report(&value, OutputFormat::Yaml).is_empty());
+    }
+
+    #[test]
+    fn toml_reports_nulls() {
+        let value = json!({"a": null, "b": {"c": [1, null], "d": 2}});
+        assert_eq!(
+            kinds(&value, OutputFormat::Toml),
+            vec![
+                ("a".to_string(), LossKind::Dropped),
+                ("b.c.1".to_string(), LossKind::Dropped),
+            ]
+        );
+        assert!(report(&json!({"mixed": [1, "a"]}), OutputFormat::Toml).is_empty());
+    }
+
+    #[test]
+    fn arrays_inside_flattened_objects_are_listed() {
+        let value = json!({"server": {"hosts": ["a", {"ports": [1]}], "name": "x"}});
+        let losses = report(&value, OutputFormat::Properties);
+        let listed: Vec<_> = losses.iter().map(|l| (l.path.as_str(), l.kind)).collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("server", LossKind::Flattened),
+                ("server.hosts", LossKind::Flattened),
+            ]
+        );
+        assert_eq!(
+            losses[1].detail,
+            "items written as server.hosts[0], server.hosts[1], …"
+        );
+    }
+
+    #[test]
+    fn strict_mode_fails_with_the_report() {
+        let losses = report(&json!({"a": {"b": 1}}), OutputFormat::Properties);
+        let err = check(&losses, OutputFormat::Properties, true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "properties output loses information at 1 path (--strict-convert):\n  \
+             flattened    a  nested keys written as a.*"
+        );
+        assert!(check(&[], OutputFormat::Env, true).is_ok());
+    }
+}
@@ -0,0 +1,329 @@
+//! Serialisers that turn a normalised `serde_json::Value` back into one of
+//! the supported config formats. Used by `--output` on queries and by the
+//! subcommands that *write* config (`convert`, `split`, `compose-dir`).
+
+pub(crate) mod commented;
+mod hcl;
+mod ini;
+mod jcs;
+pub(crate) mod loss;
+mod properties;
+mod table;
+mod xml;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::Format;
+
+/// Formats confctl can write. A superset of the text input formats minus
+/// the binary ones, plus write-only targets such as HCL.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OutputFormat {
+    Json,
+    /// RFC 8785 canonical JSON, for hashing and signing.
+    JsonCanonical,
+    /// One compact JSON value per line (NDJSON): each item of an array, or
+    /// the value itself.
+    JsonStream,
+    Yaml,
+    Toml,
+    Env,
+    Csv,
+    Tsv,
+    /// HCL / `.tfvars` (Terraform).
+    Hcl,
+    Xml,
+    /// Java `.properties`.
+    Properties,
+    Ini,
+    /// Aligned columns for reading in a terminal, not for parsing.
+    Table,
+}
+
+/// Writer settings that only some formats look at. `Default` matches
+/// `render`.
+#[derive(Clone, Debug)]
+pub(crate) struct EmitOptions {
+    /// Root element name for XML output.
+    pub(crate) xml_root: String,
+    /// Object keys with this prefix and a scalar value become XML attributes.
+    pub(crate) xml_attr_prefix: String,
+    /// Spaces per nesting level in XML output; 0 writes a single line.
+    pub(crate) xml_indent: usize,
+    /// Colour the table header (when stdout is a terminal).
+    pub(crate) color: bool,
+    /// Leading columns of CSV, TSV and table output, in this order (the
+    /// `--pluck` fields); other keys follow in first-seen order.
+    pub(crate) columns: Vec<String>,
+    /// Leave `${…}` and `%{…}` in HCL strings live instead of escaping them.
+    pub(crate) hcl_templates: bool,
+}
+
+impl Default for EmitOptions {
+    fn default() -> Self {
+        Self {
+            xml_root: "config".to_string(),
+            xml_attr_prefix: "@".to_string(),
+            xml_indent: 2,
+            color: false,
+            columns: Vec::new(),
+            hcl_templates: false,
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Canonical file extension (without the dot) used when writing files.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Json | OutputFormat::JsonCanonical => "json",
+            OutputFormat::JsonStream => "jsonl",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Env => "env",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Hcl => "tfvars",
+            OutputFormat::Xml => "xml",
+            OutputFormat::Properties => "properties",
+            OutputFormat::Ini => "ini",
+            OutputFormat::Table => "txt",
+        }
+    }
+
+    /// The writer matching an input format, if there is one.
+    pub(crate) fn from_input(format: Format) -> Option<Self> {
+        match format {
+            Format::Json | Format::Json5 => Some(OutputFormat::Json),
+            Format::Yaml => Some(OutputFormat::Yaml),
+            Format::Toml => Some(OutputFormat::Toml),
+            Format::Env => Some(OutputFormat::Env),
+            Format::Csv => Some(OutputFormat::Csv),
+            Format::Properties => Some(OutputFormat::Properties),
+            Format::Msgpack | Format::Cbor | Format::Plist => None,
+        }
+    }
+
+    /// YAML and TOML output come with their parsers, so a build without the
+    /// `yaml` or `toml` feature cannot write them either.
+    pub(crate) fn ensure_compiled(self) -> Result<()> {
+        match self {
+            OutputFormat::Yaml => Format::Yaml.ensure_compiled(),
+            OutputFormat::Toml => Format::Toml.ensure_compiled(),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Render `value` as a complete document in `format`, newline-terminated
+/// (except canonical JSON, whose exact bytes matter).
+pub(crate) fn render(value: &Value, format: OutputFormat) -> Result<String> {
+    render_with(value, format, &EmitOptions::default())
+}
+
+pub(crate) fn render_with(
+    value: &Value,
+    format: OutputFormat,
+    opts: &EmitOptions,
+) -> Result<String> {
+    format.ensure_compiled()?;
+    let body = match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(value).context("Failed to serialize value to JSON")? + "\n"
+        }
+        OutputFormat::JsonCanonical => jcs::render(value),
+        OutputFormat::JsonStream => render_json_stream(value)?,
+        #[cfg(feature = "yaml")]
+        OutputFormat::Yaml => {
+            serde_yaml::to_string(value).context("Failed to serialize value to YAML")?
+        }
+        #[cfg(feature = "toml")]
+        OutputFormat::Toml => render_toml(value)?,
+        OutputFormat::Env => render_env(value)?,
+        OutputFormat::Csv => crate::formats::csv::render(value, ',', &opts.columns)?,
+        OutputFormat::Tsv => crate::formats::csv::render(value, '\t', &opts.columns)?,
+        OutputFormat::Hcl => hcl::render(value, opts.hcl_templates)?,
+        OutputFormat::Xml => xml::render(value, opts),
+        OutputFormat::Properties => properties::render(value)?,
+        OutputFormat::Ini => ini::render(value)?,
+        OutputFormat::Table => table::render(value, opts.color, &opts.columns)?,
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("ensure_compiled turns away {format:?}"),
+    };
+    Ok(body)
+}
+
+fn render_json_stream(value: &Value) -> Result<String> {
+    let items = match value {
+        Value::Array(items) => items.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+    let mut out = String::new();
+    for item in items {
+        out.push_str(&serde_json::to_string(item).context("Failed to serialize value to JSON")?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Nested objects become `[table]` sections and arrays of objects
+/// `[[array]]` blocks; other arrays, mixed ones included (TOML 1.0 allows
+/// them), stay inline. TOML has no null, so null keys and array items are
+/// left out — `loss::report` lists them.
+#[cfg(feature = "toml")]
+fn render_toml(value: &Value) -> Result<String> {
+    if !value.is_object() {
+        bail!("TOML documents must be a table at the top level");
+    }
+    toml::to_string_pretty(&without_nulls(value)).context("Failed to serialize value to TOML")
+}
+
+#[cfg(feature = "toml")]
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k.clone(), without_nulls(v)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .filter(|v| !v.is_null())
+                .map(without_nulls)
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// `KEY=value` lines. Nested objects flatten into `PARENT_CHILD` keys;
+/// arrays are written as compact JSON so they survive a round-trip.
+fn render_env(value: &Value) -> Result<String> {
+    let mut out = String::new();
+    for (key, text) in env_pairs(value)? {
+        out.push_str(&format!("{key}={}\n", crate::env_edit::render_value(&text)));
+    }
+    Ok(out)
+}
+
+/// The variables ENV output (and `confctl exec`) derives from an object,
+/// with their unquoted values, in key order.
+pub(crate) fn env_pairs(value: &Value) -> Result<Vec<(String, String)>> {
+    let Value::Object(map) = value else {
+        bail!("ENV output needs an object at the top level");
+    };
+    let mut flat = Map::new();
+    flatten_env("", map, &mut flat);
+
+    let pairs = flat
+        .into_iter()
+        .map(|(key, value)| {
+            let text = match value {
+                Value::String(s) => s,
+                Value::Null => String::new(),
+                Value::Bool(_) | Value::Number(_) => value.to_string(),
+                _ => serde_json::to_string(&value).unwrap_or_default(),
+            };
+            (key, text)
+        })
+        .collect();
+    Ok(pairs)
+}
+
+fn flatten_env(prefix: &str, map: &Map<String, Value>, out: &mut Map<String, Value>) {
+    for (key, value) in map {
+        let key = env_key(key);
+        let full = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}_{key}")
+        };
+        match value {
+            Value::Object(child) => flatten_env(&full, child, out),
+            other => {
+                out.insert(full, other.clone());
+            }
+        }
+    }
+}
+
+/// Upper-case and fold anything outside `[A-Z0-9_]` into `_`.
+pub(crate) fn env_key(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn json_and_yaml_round_trip() {
+        let value = json!({"name": "api", "ports": [80, 443]});
+        for format in [Format::Json, Format::Yaml, Format::Toml] {
+            let output = OutputFormat::from_input(format).unwrap();
+            let text = render(&value, output).unwrap();
+            assert!(text.ends_with('\n'));
+            let back = crate::parse_content("piece", &text, Some(format)).unwrap();
+            assert_eq!(back, value, "{format:?} did not round-trip");
+        }
+    }
+
+    #[test]
+    fn json_stream_writes_a_line_per_item() {
+        let value = json!([{"id": 1, "tags": ["a"]}, "two", null]);
+        assert_eq!(
+            render(&value, OutputFormat::JsonStream).unwrap(),
+            "{\"id\":1,\"tags\":[\"a\"]}\n\"two\"\nnull\n"
+        );
+        assert_eq!(
+            render(&json!({"a": 1}), OutputFormat::JsonStream).unwrap(),
+            "{\"a\":1}\n"
+        );
+        assert_eq!(render(&json!([]), OutputFormat::JsonStream).unwrap(), "");
+    }
+
+    #[test]
+    fn toml_rejects_non_table_root() {
+        assert!(render(&json!([1, 2]), OutputFormat::Toml).is_err());
+    }
+
+    #[test]
+    fn toml_writes_tables_and_arrays_of_tables_without_nulls() {
+        let value = json!({
+            "title": "app",
+            "owner": {"name": "ops", "email": null},
+            "servers": [{"name": "a"}, {"name": "b", "tags": ["x", 1, null]}]
+        });
+        assert_eq!(
+            render(&value, OutputFormat::Toml).unwrap(),
+            "title = \"app\"\n\n\
+             [owner]\nname = \"ops\"\n\n\
+             [[servers]]\nname = \"a\"\n\n\
+             [[servers]]\nname = \"b\"\ntags = [\n    \"x\",\n    1,\n]\n"
+        );
+    }
+
+    #[test]
+    fn env_flattens_nested_keys() {
+        let value = json!({"db": {"host": "localhost", "port": 5432}, "debug": true});
+        let text = render(&value, OutputFormat::Env).unwrap();
+        assert!(text.contains("DB_HOST=localhost\n"));
+        assert!(text.contains("DB_PORT=5432\n"));
+        assert!(text.contains("DEBUG=true\n"));
+    }
+}
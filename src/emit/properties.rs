@@ -0,0 +1,138 @@
+//! Java `.properties` writer.
+//!
+//! Nested objects flatten into dotted keys and arrays into Spring-style
+//! indices (`servers[0].host`). Output follows the `Properties.load` rules,
+//! so it stays ISO-8859-1 clean: separators, comment markers and leading
+//! spaces are backslash-escaped, and anything outside printable ASCII is
+//! written as `\uXXXX` (UTF-16 surrogate pairs for astral characters).
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+pub(crate) fn render(value: &Value) -> Result<String> {
+    if !value.is_object() {
+        bail!("properties output needs an object at the top level");
+    }
+    let mut entries = Vec::new();
+    flatten("", value, &mut entries);
+
+    let mut out = String::new();
+    for (key, value) in &entries {
+        out.push_str(&escape(key, true));
+        out.push('=');
+        out.push_str(&escape(&scalar_text(value), false));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Leaf entries of `value` under dotted/indexed keys. Empty objects and
+/// arrays are leaves too, so they are not silently dropped.
+pub(super) fn flatten(prefix: &str, value: &Value, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                let full = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&full, child, out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (i, item) in items.iter().enumerate() {
+                flatten(&format!("{prefix}[{i}]"), item, out);
+            }
+        }
+        other => out.push((prefix.to_string(), other.clone())),
+    }
+}
+
+/// Text for a leaf: strings verbatim, `null` as empty, empty containers as
+/// `{}` / `[]` so they stay recognisable.
+pub(super) fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn escape(text: &str, is_key: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\u{c}' => out.push_str("\\f"),
+            // In keys every separator ends the key; in values only a leading
+            // space would be swallowed as part of the separator.
+            ' ' if is_key || i == 0 => out.push_str("\\ "),
+            '=' | ':' if is_key => {
+                out.push('\\');
+                out.push(c);
+            }
+            // `#`/`!` only start a comment at the beginning of a line.
+            '#' | '!' if is_key && i == 0 => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    out.push_str(&format!("\\u{unit:04X}"));
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn flattens_objects_and_arrays() {
+        let value = json!({
+            "server": {"port": 8080, "hosts": ["a", "b"]},
+            "db": {"pool": [{"size": 5}], "password": null},
+            "empty": {}
+        });
+        assert_eq!(
+            render(&value).unwrap(),
+            "db.password=\n\
+             db.pool[0].size=5\n\
+             empty={}\n\
+             server.hosts[0]=a\n\
+             server.hosts[1]=b\n\
+             server.port=8080\n"
+        );
+    }
+
+    #[test]
+    fn escapes_separators_and_unicode() {
+        let value = json!({
+            "key with=sep:s": " lead, trail ",
+            "#not-a-comment": "a=b:c #ok",
+            "greeting": "olá\tmundo 😀\\"
+        });
+        assert_eq!(
+            render(&value).unwrap(),
+            "\\#not-a-comment=a=b:c #ok\n\
+             greeting=ol\\u00E1\\tmundo \\uD83D\\uDE00\\\\\n\
+             key\\ with\\=sep\\:s=\\ lead, trail \n"
+        );
+    }
+
+    #[test]
+    fn rejects_non_object_root() {
+        assert!(render(&json!([1, 2])).is_err());
+        assert!(render(&json!("x")).is_err());
+    }
+}
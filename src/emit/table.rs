@@ -0,0 +1,135 @@
+//! Aligned text table for arrays of objects (one column per key, in
+//! first-seen order) or arrays of arrays, for eyeballing list responses in
+//! a terminal. Cells read as in CSV output; line breaks inside a cell are
+//! shown as `\n` so rows stay on one line, and columns holding only numbers
+//! are right-aligned. With `color` the header is bold and the rule dimmed.
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+use serde_json::Value;
+
+use crate::formats::csv::{cell_text, columns};
+
+pub(crate) fn render(value: &Value, color: bool, order: &[String]) -> Result<String> {
+    let Value::Array(rows) = value else {
+        bail!("table output needs an array of objects or arrays at the top level");
+    };
+
+    let (header, body): (Option<Vec<String>>, Vec<Vec<String>>) =
+        if rows.iter().all(Value::is_object) {
+            let keys = columns(rows, order);
+            let body = rows
+                .iter()
+                .filter_map(Value::as_object)
+                .map(|row| {
+                    keys.iter()
+                        .map(|k| row.get(*k).map(cell).unwrap_or_default())
+                        .collect()
+                })
+                .collect();
+            (Some(keys.iter().map(|k| k.to_string()).collect()), body)
+        } else if rows.iter().all(Value::is_array) {
+            let body = rows
+                .iter()
+                .filter_map(Value::as_array)
+                .map(|row| row.iter().map(cell).collect())
+                .collect();
+            (None, body)
+        } else {
+            bail!("table output needs every row to be an object, or every row to be an array");
+        };
+
+    let width = header.iter().chain(&body).map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; width];
+    for row in header.iter().chain(&body) {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+    let numeric: Vec<bool> = (0..width)
+        .map(|i| {
+            let mut cells = body.iter().filter_map(|row| row.get(i)).peekable();
+            cells.peek().is_some() && cells.all(|c| c.is_empty() || c.parse::<f64>().is_ok())
+        })
+        .collect();
+
+    let mut out = String::new();
+    if let Some(header) = &header {
+        let line = format_row(header, &widths, &vec![false; width]);
+        let rule = format_row(
+            &widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>(),
+            &widths,
+            &numeric,
+        );
+        if color {
+            out.push_str(&format!("{}\n{}\n", line.bold(), rule.dimmed()));
+        } else {
+            out.push_str(&format!("{line}\n{rule}\n"));
+        }
+    }
+    for row in &body {
+        out.push_str(&format_row(row, &widths, &numeric));
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn cell(value: &Value) -> String {
+    cell_text(value)
+        .replace('\r', "\\r")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+/// Cells padded to their column, two spaces apart, without trailing blanks.
+fn format_row(cells: &[String], widths: &[usize], right: &[bool]) -> String {
+    let mut line = String::new();
+    for (i, width) in widths.iter().enumerate() {
+        let cell = cells.get(i).map(String::as_str).unwrap_or("");
+        if i > 0 {
+            line.push_str("  ");
+        }
+        if right[i] {
+            line.push_str(&format!("{cell:>width$}"));
+        } else {
+            line.push_str(&format!("{cell:<width$}"));
+        }
+    }
+    line.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn aligns_columns() {
+        let value = json!([
+            {"name": "api", "replicas": 3, "tag": "1.4.2"},
+            {"name": "worker", "note": "two\nlines", "replicas": 12}
+        ]);
+        assert_eq!(
+            render(&value, false, &[]).unwrap(),
+            "name    replicas  tag    note\n\
+             ------  --------  -----  ----------\n\
+             api            3  1.4.2\n\
+             worker        12         two\\nlines\n"
+        );
+        assert_eq!(
+            render(&json!([["a", 1], ["bb"]]), false, &[]).unwrap(),
+            "a   1\nbb\n"
+        );
+    }
+
+    #[test]
+    fn needs_rows() {
+        let err = render(&json!({"a": 1}), false, &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "table output needs an array of objects or arrays at the top level"
+        );
+        let err = render(&json!([{"a": 1}, 2]), false, &[]).unwrap_err();
+        assert!(err.to_string().contains("every row"));
+    }
+}
@@ -0,0 +1,257 @@
+//! XML writer for legacy consumers.
+//!
+//! Mapping: object keys become child elements, arrays repeat their parent
+//! element once per item, scalar keys starting with the attribute prefix
+//! (`@id` by default) become attributes, and a `#text` key becomes the
+//! element's text. Anything that is not a valid XML name is sanitised.
+
+use serde_json::{Map, Value};
+
+use super::EmitOptions;
+
+/// Key whose value becomes the text content of the enclosing element.
+const TEXT_KEY: &str = "#text";
+
+/// Element used for items of arrays that have no key of their own.
+const ITEM_ELEMENT: &str = "item";
+
+pub(crate) fn render(value: &Value, opts: &EmitOptions) -> String {
+    let mut writer = Writer {
+        opts,
+        out: String::new(),
+    };
+    writer
+        .out
+        .push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>");
+    writer.newline();
+    let root = element_name(&opts.xml_root);
+    match value {
+        // A root array has no name of its own: wrap the items in the root.
+        Value::Array(_) => {
+            writer.open(&root, &[], 0);
+            writer.element(ITEM_ELEMENT, value, 1);
+            writer.close(&root, 0);
+        }
+        other => writer.element(&root, other, 0),
+    }
+    if !writer.out.ends_with('\n') {
+        writer.out.push('\n');
+    }
+    writer.out
+}
+
+struct Writer<'a> {
+    opts: &'a EmitOptions,
+    out: String,
+}
+
+impl Writer<'_> {
+    fn pretty(&self) -> bool {
+        self.opts.xml_indent > 0
+    }
+
+    fn newline(&mut self) {
+        if self.pretty() {
+            self.out.push('\n');
+        }
+    }
+
+    fn pad(&mut self, depth: usize) {
+        self.out.push_str(&" ".repeat(depth * self.opts.xml_indent));
+    }
+
+    fn open(&mut self, name: &str, attrs: &[(String, String)], depth: usize) {
+        self.pad(depth);
+        self.out.push('<');
+        self.out.push_str(name);
+        self.attributes(attrs);
+        self.out.push('>');
+        self.newline();
+    }
+
+    fn close(&mut self, name: &str, depth: usize) {
+        self.pad(depth);
+        self.out.push_str(&format!("</{name}>"));
+        self.newline();
+    }
+
+    fn attributes(&mut self, attrs: &[(String, String)]) {
+        for (name, value) in attrs {
+            self.out
+                .push_str(&format!(" {name}=\"{}\"", escape(value, true)));
+        }
+    }
+
+    fn element(&mut self, name: &str, value: &Value, depth: usize) {
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    if item.is_array() {
+                        // Arrays of arrays: keep the inner grouping visible.
+                        self.open(name, &[], depth);
+                        self.element(ITEM_ELEMENT, item, depth + 1);
+                        self.close(name, depth);
+                    } else {
+                        self.element(name, item, depth);
+                    }
+                }
+            }
+            Value::Object(map) => self.object(name, map, depth),
+            Value::Null => {
+                self.pad(depth);
+                self.out.push_str(&format!("<{name}/>"));
+                self.newline();
+            }
+            scalar => self.leaf(name, &[], &scalar_text(scalar), depth),
+        }
+    }
+
+    fn object(&mut self, name: &str, map: &Map<String, Value>, depth: usize) {
+        let prefix = &self.opts.xml_attr_prefix;
+        let mut attrs = Vec::new();
+        let mut text = None;
+        let mut children = Vec::new();
+        for (key, value) in map {
+            let is_scalar = !value.is_object() && !value.is_array();
+            match key.strip_prefix(prefix.as_str()) {
+                Some(attr) if !prefix.is_empty() && is_scalar => {
+                    attrs.push((element_name(attr), scalar_text(value)));
+                }
+                _ if key == TEXT_KEY && is_scalar => text = Some(scalar_text(value)),
+                _ => children.push((element_name(key), value)),
+            }
+        }
+
+        if children.is_empty() {
+            match text {
+                Some(text) => self.leaf(name, &attrs, &text, depth),
+                None => {
+                    self.pad(depth);
+                    self.out.push('<');
+                    self.out.push_str(name);
+                    self.attributes(&attrs);
+                    self.out.push_str("/>");
+                    self.newline();
+                }
+            }
+            return;
+        }
+
+        self.open(name, &attrs, depth);
+        if let Some(text) = text {
+            self.pad(depth + 1);
+            self.out.push_str(&escape(&text, false));
+            self.newline();
+        }
+        for (child, value) in children {
+            self.element(&child, value, depth + 1);
+        }
+        self.close(name, depth);
+    }
+
+    fn leaf(&mut self, name: &str, attrs: &[(String, String)], text: &str, depth: usize) {
+        self.pad(depth);
+        self.out.push('<');
+        self.out.push_str(name);
+        self.attributes(attrs);
+        self.out
+            .push_str(&format!(">{}</{name}>", escape(text, false)));
+        self.newline();
+    }
+}
+
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// A valid XML element/attribute name: characters outside `[A-Za-z0-9_.-]`
+/// become `_`, and names that cannot start an element get a `_` prefix.
+fn element_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '.' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let bad_start = !name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_');
+    if bad_start || name.to_ascii_lowercase().starts_with("xml") {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn escape(text: &str, attribute: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            '\n' if attribute => out.push_str("&#10;"),
+            // XML 1.0 cannot carry most control characters at all.
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn maps_objects_arrays_and_attributes() {
+        let value = json!({
+            "server": {"@port": 8080, "host": "a&b", "tags": ["x", "y"]},
+            "empty": null
+        });
+        let text = render(&value, &EmitOptions::default());
+        assert_eq!(
+            text,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <config>\n  <empty/>\n  <server port=\"8080\">\n    <host>a&amp;b</host>\n    \
+             <tags>x</tags>\n    <tags>y</tags>\n  </server>\n</config>\n"
+        );
+    }
+
+    #[test]
+    fn custom_root_text_key_and_compact_output() {
+        let opts = EmitOptions {
+            xml_root: "settings".to_string(),
+            xml_attr_prefix: "_".to_string(),
+            xml_indent: 0,
+            ..EmitOptions::default()
+        };
+        let value = json!({"name": {"_lang": "en", "#text": "Vasco"}, "1st": true});
+        assert_eq!(
+            render(&value, &opts),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><settings><_1st>true</_1st>\
+             <name lang=\"en\">Vasco</name></settings>\n"
+        );
+    }
+
+    #[test]
+    fn root_arrays_become_items() {
+        let text = render(&json!([1, [2]]), &EmitOptions::default());
+        assert!(
+            text.contains(
+                "<config>\n  <item>1</item>\n  <item>\n    <item>2</item>\n  </item>\n</config>"
+            ),
+            "{text}"
+        );
+    }
+}
@@ -3,6 +3,7 @@
 //! Edits are line-based, never parse→reserialize, so comments, blank lines,
 //! ordering, `export ` prefixes, and inline ` # comments` all survive. This
 //! exists so tools (and AI agents) can mutate a .env without reading it.
+//! `set` on a JSON, YAML or TOML file is handed to [`crate::set`].
 
 use std::path::Path;
 
@@ -10,13 +11,26 @@ use anyhow::{bail, Context, Result};
 use clap::Args;
 use colored::Colorize;
 
+use crate::atomic::{self, BackupArg};
+use crate::set::ValueType;
+use crate::undo;
+
 #[derive(Args, Debug)]
 pub(crate) struct SetCli {
-    /// Path to the .env file. Created if it does not exist.
+    /// Path to the .env, JSON, YAML or TOML file. Created if it does not
+    /// exist.
     pub(crate) file: String,
-    /// One or more KEY=VALUE pairs to add or update.
+    /// One or more KEY=VALUE pairs to add or update. In JSON, YAML and TOML
+    /// files KEY is a path (`db.pool.size=5`), and `PATH VALUE` also works.
     #[arg(required = true)]
     pub(crate) pairs: Vec<String>,
+    /// Read each VALUE as `string`, `int`, `float`, `bool` or `json` (objects,
+    /// arrays, any JSON literal) instead of guessing: numbers and booleans
+    /// otherwise become numbers and booleans in JSON, YAML and TOML files.
+    #[arg(long = "type", visible_alias = "value-type", value_enum)]
+    pub(crate) value_type: Option<ValueType>,
+    #[command(flatten)]
+    pub(crate) backup: BackupArg,
 }
 
 #[derive(Args, Debug)]
@@ -26,6 +40,8 @@ pub(crate) struct UnsetCli {
     /// One or more keys to remove.
     #[arg(required = true)]
     pub(crate) keys: Vec<String>,
+    #[command(flatten)]
+    pub(crate) backup: BackupArg,
 }
 
 #[derive(Debug, PartialEq)]
@@ -46,7 +62,7 @@ fn line_key(line: &str) -> Option<&str> {
 }
 
 /// Quote the value when it would not survive a round-trip unquoted.
-fn render_value(value: &str) -> String {
+pub(crate) fn render_value(value: &str) -> String {
     let needs_quotes = value.is_empty()
         || value.contains(char::is_whitespace)
         || value.contains('#')
@@ -137,6 +153,9 @@ fn parse_pair(pair: &str) -> Result<(&str, &str)> {
 }
 
 pub(crate) fn run_set(cli: SetCli) -> Result<()> {
+    if let Some(format) = crate::set::structured_format(&cli.file)? {
+        return crate::set::run(&cli, format);
+    }
     let path = Path::new(&cli.file);
     let mut content = if path.exists() {
         std::fs::read_to_string(path)
@@ -148,12 +167,21 @@ pub(crate) fn run_set(cli: SetCli) -> Result<()> {
     let mut report = Vec::new();
     for pair in &cli.pairs {
         let (key, value) = parse_pair(pair)?;
-        let (next, outcome) = set_key(&content, key, value);
+        let value = match cli.value_type {
+            // Checked against the type, then written as text like `-o env`.
+            Some(value_type) => crate::format_value_with(&value_type.parse(value)?, true),
+            None => value.to_string(),
+        };
+        let (next, outcome) = set_key(&content, key, &value);
         content = next;
         report.push((key.to_string(), outcome));
     }
 
-    std::fs::write(path, &content).with_context(|| format!("writing {}", path.display()))?;
+    undo::record(path, "set")?;
+    atomic::write(path, &content, cli.backup.suffix())?;
+    if atomic::previewing() {
+        return Ok(());
+    }
 
     for (key, outcome) in report {
         let verb = match outcome {
@@ -177,7 +205,11 @@ pub(crate) fn run_unset(cli: UnsetCli) -> Result<()> {
         report.push((key.clone(), removed));
     }
 
-    std::fs::write(path, &content).with_context(|| format!("writing {}", path.display()))?;
+    undo::record(path, "unset")?;
+    atomic::write(path, &content, cli.backup.suffix())?;
+    if atomic::previewing() {
+        return Ok(());
+    }
 
     for (key, removed) in report {
         if removed {
@@ -0,0 +1,133 @@
+//! `--env-prefix APP_`: 12-factor overrides from the environment. Each leaf
+//! of the document answers to the variable name `-o env` gives it
+//! (`server.port` → `SERVER_PORT`), so `APP_SERVER_PORT=9090` replaces
+//! `server.port`.
+//!
+//! Only leaves the file already has are overridden; a variable cannot add a
+//! key, since `A_B_C` could be `a.b_c` as well as `a_b.c`. The new value
+//! keeps the type of the one it replaces: numbers and booleans must parse,
+//! arrays take the JSON text `-o env` writes for them.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::{Map, Value};
+
+use crate::emit::env_key;
+
+/// Override the leaves of `value` that have a variable in `vars`.
+pub(crate) fn apply(value: &mut Value, prefix: &str, vars: &HashMap<String, String>) -> Result<()> {
+    match value {
+        Value::Object(map) => walk(map, prefix, "", vars),
+        _ => Ok(()),
+    }
+}
+
+/// The environment as UTF-8 pairs; variables that are not UTF-8 cannot be
+/// meant for a config file and are left out.
+pub(crate) fn process_vars() -> HashMap<String, String> {
+    std::env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect()
+}
+
+fn walk(
+    map: &mut Map<String, Value>,
+    name: &str,
+    path: &str,
+    vars: &HashMap<String, String>,
+) -> Result<()> {
+    for (key, child) in map.iter_mut() {
+        // The prefix is used verbatim; nested keys join with `_` as in -o env.
+        let name = if path.is_empty() {
+            format!("{name}{}", env_key(key))
+        } else {
+            format!("{name}_{}", env_key(key))
+        };
+        let path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{path}.{key}")
+        };
+        if let Value::Object(inner) = child {
+            walk(inner, &name, &path, vars)?;
+            continue;
+        }
+        if let Some(text) = vars.get(&name) {
+            *child = typed_like(child, text)
+                .map_err(|err| anyhow!("{name} cannot override {path}: {err}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn typed_like(current: &Value, text: &str) -> Result<Value> {
+    Ok(match current {
+        Value::Number(_) => {
+            let parsed = text.trim().parse::<serde_json::Number>();
+            Value::Number(parsed.map_err(|_| anyhow!("`{text}` is not a number"))?)
+        }
+        Value::Bool(_) => match text.trim().to_ascii_lowercase().as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => bail!("`{text}` is not true or false"),
+        },
+        Value::Array(_) => match serde_json::from_str(text) {
+            Ok(array @ Value::Array(_)) => array,
+            _ => bail!("`{text}` is not a JSON array"),
+        },
+        _ => Value::String(text.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn prefixed_variables_override_matching_leaves() {
+        let mut value = json!({
+            "server": {"port": 8080, "tls": false, "host": "0.0.0.0"},
+            "log-level": "info",
+            "hosts": ["a"],
+            "timeout": null
+        });
+        let env = vars(&[
+            ("APP_SERVER_PORT", "9090"),
+            ("APP_SERVER_TLS", "TRUE"),
+            ("APP_LOG_LEVEL", "debug"),
+            ("APP_HOSTS", r#"["b", "c"]"#),
+            ("APP_TIMEOUT", "30s"),
+            ("APP_SERVER_EXTRA", "ignored"),
+            ("SERVER_HOST", "unprefixed"),
+        ]);
+        apply(&mut value, "APP_", &env).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "server": {"port": 9090, "tls": true, "host": "0.0.0.0"},
+                "log-level": "debug",
+                "hosts": ["b", "c"],
+                "timeout": "30s"
+            })
+        );
+    }
+
+    #[test]
+    fn overrides_keep_the_type() {
+        let mut value = json!({"server": {"port": 8080}});
+        let err = apply(&mut value, "APP_", &vars(&[("APP_SERVER_PORT", "http")])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "APP_SERVER_PORT cannot override server.port: `http` is not a number"
+        );
+    }
+}
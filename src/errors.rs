@@ -0,0 +1,378 @@
+//! Typed errors for the failures wrapper tools care about, and
+//! `--error-format json`, which reports any error as a single JSON object on
+//! stderr instead of anyhow's text.
+//!
+//! The text output does not change: the types below display exactly the
+//! messages confctl always printed. They only let the JSON report pick out
+//! the failing path segment, file and position without scraping strings,
+//! and let `main` pick the exit code.
+
+use std::fmt;
+
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+
+use crate::Format;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub(crate) enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Why `resolve_path` stopped. `path` is the dotted path up to and
+/// including the failing `segment`.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum PathError {
+    #[error("Key not found: '{segment}' (at path '{path}')")]
+    KeyNotFound { segment: String, path: String },
+    #[error("Expected numeric index for array access, got '{segment}' (at path '{path}')")]
+    NotAnIndex { segment: String, path: String },
+    #[error("Array index {index} out of bounds (length {len}) at path '{path}'")]
+    OutOfBounds {
+        segment: String,
+        path: String,
+        index: usize,
+        len: usize,
+    },
+    #[error("Cannot traverse into a scalar value at '{parent}' (trying to access '{segment}')")]
+    Scalar {
+        segment: String,
+        path: String,
+        parent: String,
+    },
+}
+
+impl PathError {
+    fn code(&self) -> &'static str {
+        match self {
+            PathError::KeyNotFound { .. } => "key_not_found",
+            PathError::NotAnIndex { .. } => "not_an_index",
+            PathError::OutOfBounds { .. } => "index_out_of_bounds",
+            PathError::Scalar { .. } => "not_a_container",
+        }
+    }
+
+    /// Whether the path names nothing, as opposed to going through a value
+    /// of the wrong type.
+    pub(crate) fn is_missing(&self) -> bool {
+        matches!(
+            self,
+            PathError::KeyNotFound { .. } | PathError::OutOfBounds { .. }
+        )
+    }
+
+    fn segment_and_path(&self) -> (&str, &str) {
+        match self {
+            PathError::KeyNotFound { segment, path }
+            | PathError::NotAnIndex { segment, path }
+            | PathError::OutOfBounds { segment, path, .. }
+            | PathError::Scalar { segment, path, .. } => (segment, path),
+        }
+    }
+}
+
+/// Context attached when a text document fails to parse, with the 1-based
+/// line and column when the parser reports one, and that line's text for the
+/// annotated snippet.
+#[derive(Debug)]
+pub(crate) struct ParseFailure {
+    pub(crate) file: String,
+    pub(crate) format: Format,
+    pub(crate) position: Option<(usize, usize)>,
+    pub(crate) source_line: Option<String>,
+}
+
+impl ParseFailure {
+    /// The offending line with a caret under the column, laid out like
+    /// rustc's diagnostics:
+    ///
+    /// ```text
+    ///  --> app.yaml:2:2
+    ///   |
+    /// 2 | b: 2
+    ///   |  ^
+    /// ```
+    fn snippet(&self) -> Option<String> {
+        let (line, column) = self.position?;
+        let text = self.source_line.as_deref()?;
+        let gutter = " ".repeat(line.to_string().len());
+        // Tabs stay tabs so the caret lines up however the terminal expands them.
+        let indent: String = text
+            .chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        Some(format!(
+            "{gutter}--> {}:{line}:{column}\n{gutter} |\n{line} | {text}\n{gutter} | {indent}^",
+            self.file
+        ))
+    }
+}
+
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.format.extension().to_ascii_uppercase();
+        write!(f, "Failed to parse {name}: {}", self.file)
+    }
+}
+
+/// A network request that failed for good, after `attempts` tries. The
+/// message is the one the source always printed; `kind` and `status` let
+/// the JSON report tell an outage from a typo.
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) struct RemoteError {
+    pub(crate) kind: RemoteKind,
+    pub(crate) status: Option<u16>,
+    pub(crate) attempts: u32,
+    pub(crate) message: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub(crate) enum RemoteKind {
+    /// The host name did not resolve.
+    Dns,
+    /// The TLS handshake failed (certificate, protocol).
+    Tls,
+    /// Nothing answered: refused, reset or unreachable.
+    Connect,
+    /// No answer within `--timeout`.
+    Timeout,
+    /// A 4xx status: the request or its credentials are wrong.
+    Client,
+    /// A 5xx status: the service is failing.
+    Server,
+    /// Anything else, such as a malformed URL or response.
+    Other,
+}
+
+impl RemoteKind {
+    fn name(self) -> &'static str {
+        match self {
+            RemoteKind::Dns => "dns",
+            RemoteKind::Tls => "tls",
+            RemoteKind::Connect => "connect",
+            RemoteKind::Timeout => "timeout",
+            RemoteKind::Client => "client",
+            RemoteKind::Server => "server",
+            RemoteKind::Other => "other",
+        }
+    }
+}
+
+/// Exit status for an error that parsed fine but named nothing.
+pub(crate) const EXIT_NOT_FOUND: u8 = 2;
+/// Exit status for a path that goes through a value of the wrong type.
+pub(crate) const EXIT_TYPE_ERROR: u8 = 3;
+
+/// The exit status `err` ends the process with: 2 when the path is missing,
+/// 3 when it goes through a scalar or indexes an array with a key, and 1 for
+/// everything else, parse errors included.
+pub(crate) fn exit_code(err: &anyhow::Error) -> u8 {
+    match err.downcast_ref::<PathError>() {
+        Some(path_err) if path_err.is_missing() => EXIT_NOT_FOUND,
+        Some(_) => EXIT_TYPE_ERROR,
+        None => 1,
+    }
+}
+
+/// Whether `err` is a path that names nothing, which `--no-fail` forgives.
+pub(crate) fn is_missing_path(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<PathError>()
+        .is_some_and(PathError::is_missing)
+}
+
+pub(crate) fn json_position(err: &serde_json::Error) -> Option<(usize, usize)> {
+    (err.line() > 0).then(|| (err.line(), err.column()))
+}
+
+#[cfg(feature = "yaml")]
+pub(crate) fn yaml_position(err: &anyhow::Error) -> Option<(usize, usize)> {
+    let location = err.downcast_ref::<serde_yaml::Error>()?.location()?;
+    Some((location.line(), location.column()))
+}
+
+#[cfg(feature = "toml")]
+pub(crate) fn toml_position(err: &toml::de::Error, content: &str) -> Option<(usize, usize)> {
+    let offset = err.span()?.start.min(content.len());
+    let before = content.get(..offset)?;
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    Some((line, column))
+}
+
+/// Print `err` the way `--error-format` asks. `input` is the file the query
+/// read, for errors that don't carry one themselves.
+pub(crate) fn print(err: &anyhow::Error, format: ErrorFormat, input: Option<&str>) {
+    match format {
+        ErrorFormat::Text => {
+            eprintln!("Error: {err:?}");
+            if let Some(snippet) = err
+                .downcast_ref::<ParseFailure>()
+                .and_then(ParseFailure::snippet)
+            {
+                eprintln!("\n{snippet}");
+            }
+        }
+        ErrorFormat::Json => eprintln!("{}", report(err, input)),
+    }
+}
+
+fn report(err: &anyhow::Error, input: Option<&str>) -> Value {
+    let mut out = Map::new();
+    let causes: Vec<String> = err.chain().skip(1).map(|e| e.to_string()).collect();
+    out.insert("message".into(), json!(err.to_string()));
+    out.insert("causes".into(), json!(causes));
+
+    let code = if let Some(path_err) = err.downcast_ref::<PathError>() {
+        let (segment, path) = path_err.segment_and_path();
+        out.insert("segment".into(), json!(segment));
+        out.insert("path".into(), json!(path));
+        path_err.code()
+    } else if let Some(failure) = err.downcast_ref::<ParseFailure>() {
+        out.insert("file".into(), json!(failure.file));
+        out.insert("format".into(), json!(failure.format.extension()));
+        if let Some((line, column)) = failure.position {
+            out.insert("line".into(), json!(line));
+            out.insert("column".into(), json!(column));
+        }
+        "parse_error"
+    } else if let Some(remote) = err.downcast_ref::<RemoteError>() {
+        out.insert("kind".into(), json!(remote.kind.name()));
+        if let Some(status) = remote.status {
+            out.insert("status".into(), json!(status));
+        }
+        out.insert("attempts".into(), json!(remote.attempts));
+        "remote_error"
+    } else if err.downcast_ref::<std::io::Error>().is_some() {
+        "io_error"
+    } else {
+        "error"
+    };
+    out.insert("code".into(), json!(code));
+    if let Some(input) = input {
+        out.entry("file").or_insert_with(|| json!(input));
+    }
+    json!({ "error": out })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn path_errors_report_segment_and_path() {
+        let err = anyhow::Error::new(PathError::KeyNotFound {
+            segment: "port".into(),
+            path: "db.port".into(),
+        });
+        let report = report(&err, Some("app.yaml"));
+        assert_eq!(
+            report,
+            json!({"error": {
+                "code": "key_not_found",
+                "message": "Key not found: 'port' (at path 'db.port')",
+                "causes": [],
+                "segment": "port",
+                "path": "db.port",
+                "file": "app.yaml"
+            }})
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn parse_failures_keep_file_format_and_position() {
+        let content = "name = \"api\"\nport = = 1\n";
+        let toml_err = toml::from_str::<toml::Value>(content).unwrap_err();
+        let position = toml_position(&toml_err, content);
+        let err = Err::<(), _>(toml_err)
+            .context(ParseFailure {
+                file: "app.toml".into(),
+                format: Format::Toml,
+                position,
+                source_line: Some("port = = 1".into()),
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Failed to parse TOML: app.toml");
+        let report = report(&err, Some("ignored.toml"));
+        assert_eq!(report["error"]["code"], "parse_error");
+        assert_eq!(report["error"]["file"], "app.toml");
+        assert_eq!(report["error"]["format"], "toml");
+        assert_eq!(report["error"]["line"], 2);
+        assert_eq!(report["error"]["column"], 8);
+        assert_eq!(report["error"]["causes"].as_array().map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn exit_codes_follow_the_error_kind() {
+        let path_err = |err: PathError| anyhow::Error::new(err).context("querying app.yaml");
+        let missing = path_err(PathError::OutOfBounds {
+            segment: "9".into(),
+            path: "clubs.9".into(),
+            index: 9,
+            len: 4,
+        });
+        assert_eq!(exit_code(&missing), EXIT_NOT_FOUND);
+        assert!(is_missing_path(&missing));
+
+        let scalar = path_err(PathError::Scalar {
+            segment: "host".into(),
+            path: "port.host".into(),
+            parent: "port".into(),
+        });
+        assert_eq!(exit_code(&scalar), EXIT_TYPE_ERROR);
+        assert!(!is_missing_path(&scalar));
+
+        let parse = Err::<(), _>(anyhow::anyhow!("expected value"))
+            .context(ParseFailure {
+                file: "app.json".into(),
+                format: Format::Json,
+                position: None,
+                source_line: None,
+            })
+            .unwrap_err();
+        assert_eq!(exit_code(&parse), 1);
+        assert_eq!(exit_code(&anyhow::anyhow!("anything else")), 1);
+    }
+
+    #[test]
+    fn remote_errors_report_kind_status_and_attempts() {
+        let err = anyhow::Error::new(RemoteError {
+            kind: RemoteKind::Server,
+            status: Some(503),
+            attempts: 3,
+            message: "S3 returned 503 SlowDown: Please reduce your request rate.".into(),
+        })
+        .context("reading s3://configs/app.yaml");
+        let report = report(&err, None);
+        assert_eq!(report["error"]["code"], "remote_error");
+        assert_eq!(report["error"]["kind"], "server");
+        assert_eq!(report["error"]["status"], 503);
+        assert_eq!(report["error"]["attempts"], 3);
+        assert_eq!(
+            report["error"]["causes"][0],
+            "S3 returned 503 SlowDown: Please reduce your request rate."
+        );
+        assert_eq!(exit_code(&err), 1);
+    }
+
+    #[test]
+    fn snippet_points_at_the_column() {
+        let failure = ParseFailure {
+            file: "app.json".into(),
+            format: Format::Json,
+            position: Some((12, 8)),
+            source_line: Some("\t\"b\": }".into()),
+        };
+        assert_eq!(
+            failure.snippet().unwrap(),
+            "  --> app.json:12:8\n   |\n12 | \t\"b\": }\n   | \t      ^"
+        );
+    }
+}
@@ -0,0 +1,410 @@
+//! `confctl examples [TOPIC]`: a cookbook of commands grouped by task. The
+//! same entries fill the EXAMPLES section of `confctl man` and the tutorial
+//! printed when confctl runs on a terminal with nothing to read.
+
+use anyhow::{bail, Result};
+use clap::Args;
+use colored::Colorize;
+
+#[derive(Args, Debug)]
+pub(crate) struct ExamplesCli {
+    /// Print only this topic's examples, e.g. `converting` or `stdin`.
+    #[arg(value_name = "TOPIC")]
+    pub(crate) topic: Option<String>,
+
+    /// List the topics with a line about each instead.
+    #[arg(long, conflicts_with = "topic")]
+    pub(crate) list: bool,
+}
+
+pub(crate) struct Topic {
+    pub(crate) name: &'static str,
+    pub(crate) summary: &'static str,
+    pub(crate) examples: &'static [Example],
+}
+
+pub(crate) struct Example {
+    pub(crate) about: &'static str,
+    pub(crate) command: &'static str,
+}
+
+const fn example(about: &'static str, command: &'static str) -> Example {
+    Example { about, command }
+}
+
+pub(crate) const TOPICS: &[Topic] = &[
+    Topic {
+        name: "querying",
+        summary: "Read values by path, count and sort them, query many files",
+        examples: &[
+            example(
+                "Print a value by its dotted path; indexes pick array items",
+                "confctl config.yaml clubs.0.name",
+            ),
+            example("Print the whole file as JSON", "confctl config.toml"),
+            example(
+                "Paths go as deep as the document does",
+                "confctl config.yaml clubs.1.players.0.name",
+            ),
+            example(
+                "Count the items of an array (also --sum, --min, --max, --avg, --unique)",
+                "confctl config.yaml clubs --length",
+            ),
+            example(
+                "Sort an array and pick fields as a table",
+                "confctl config.yaml clubs --sort-by founded --pluck name,founded -o table",
+            ),
+            example(
+                "Run one query over every file a glob matches",
+                "confctl 'services/*/config.yaml' image.tag",
+            ),
+            example(
+                "Print nothing and exit 0 when the path is missing",
+                "confctl config.yaml features.beta --no-fail",
+            ),
+        ],
+    },
+    Topic {
+        name: "expressions",
+        summary: "Compute with values instead of printing them as they are",
+        examples: &[
+            example(
+                "Arithmetic and comparisons",
+                "confctl config.yaml 'clubs.0.founded + 100'",
+            ),
+            example(
+                "Call a function",
+                "confctl config.yaml 'upper(clubs.0.country)'",
+            ),
+            example(
+                "`*` matches every key or item; join the matches into one string",
+                "confctl config.yaml 'join(clubs.*.name, \", \")'",
+            ),
+            example(
+                "Fill a line with several values",
+                "confctl config.yaml clubs.0 --template '{.name} ({.founded})'",
+            ),
+        ],
+    },
+    Topic {
+        name: "stdin",
+        summary: "Query piped input, the clipboard or the environment",
+        examples: &[
+            example(
+                "Piped input is read when FILE is not given",
+                "curl -s https://api.github.com/users | confctl 0.login",
+            ),
+            example(
+                "Name stdin with `-` and say its format when it cannot be told",
+                "curl -s https://api.github.com/users | confctl - 0.login --format json",
+            ),
+            example(
+                "Query a Kubernetes object",
+                "kubectl get configmap app -o yaml | confctl - data",
+            ),
+            example(
+                "Query the clipboard",
+                "confctl --clipboard server.port",
+            ),
+            example(
+                "Query the environment variables",
+                "confctl --from-env HOME",
+            ),
+        ],
+    },
+    Topic {
+        name: "converting",
+        summary: "Print or write a document in another format",
+        examples: &[
+            example("Print a file as YAML", "confctl config.toml -o yaml"),
+            example(
+                "Print a subtree as KEY=VALUE lines",
+                "confctl config.yaml clubs.0.titles -o env",
+            ),
+            example("Minified JSON for CI variables", "confctl config.yaml -c"),
+            example(
+                "Convert a whole file, the format taken from --out",
+                "confctl convert app.yaml --out app.json",
+            ),
+            example(
+                "Convert piped input",
+                "cat app.json | confctl convert - --to yaml",
+            ),
+            example(
+                "Convert every YAML file under a directory in place",
+                "confctl convert --from yaml --to json --in-place ./configs/",
+            ),
+        ],
+    },
+    Topic {
+        name: "base64",
+        summary: "Decode and encode base64 values",
+        examples: &[
+            example(
+                "Decode the value at a path",
+                "confctl secret.yaml data.password -d",
+            ),
+            example(
+                "Encode the value at a path",
+                "confctl config.yaml db.password -e",
+            ),
+            example(
+                "Decode a Kubernetes Secret's values and keep its structure",
+                "kubectl get secret app -o yaml | confctl - data --decode-path 'data.*' -o yaml",
+            ),
+            example(
+                "Query a config file stored in a Secret, decoded",
+                "kubectl get secret app -o yaml | confctl k8s - --data-key config.json db.password",
+            ),
+        ],
+    },
+    Topic {
+        name: "secrets",
+        summary: "Mask secrets and work with age-encrypted values",
+        examples: &[
+            example("Mask passwords, tokens and keys", "confctl config.yaml -r"),
+            example(
+                "Mask more keys, with your own placeholder",
+                "confctl config.yaml --mask-pattern dsn --mask-with '*****'",
+            ),
+            example(
+                "Encrypt a value to an age recipient",
+                "confctl config.yaml db.password --encrypt-age age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p",
+            ),
+            example(
+                "Decrypt it again",
+                "confctl config.yaml db.password --decrypt-age --identity key.txt",
+            ),
+        ],
+    },
+    Topic {
+        name: "editing",
+        summary: "Change files in place, keeping .env comments",
+        examples: &[
+            example(
+                "Add or update .env keys",
+                "confctl set .env DB_HOST=10.0.0.5 NEW_FLAG=on",
+            ),
+            example("Remove .env keys", "confctl unset .env DEBUG OLD_KEY"),
+            example(
+                "Set a value in a JSON, YAML or TOML file",
+                "confctl set cfg.yaml db.pool.size=10",
+            ),
+            example(
+                "Apply a JSON Patch or merge patch in place",
+                "confctl patch values.yaml --patch ops.json -i",
+            ),
+            example("Edit a subtree in $EDITOR", "confctl edit cfg.yaml db"),
+        ],
+    },
+    Topic {
+        name: "searching",
+        summary: "Find keys and values, compare files, trace where values come from",
+        examples: &[
+            example(
+                "Every key named `name`, at any depth",
+                "confctl find config.yaml --key name",
+            ),
+            example(
+                "Every value matching a regex",
+                "confctl grep config.yaml 'Madrid|Barcelona'",
+            ),
+            example("Compare two files", "confctl diff staging.yaml prod.yaml"),
+            example(
+                "Which of several layered files set a value",
+                "confctl explain db.host base.yaml prod.yaml",
+            ),
+        ],
+    },
+    Topic {
+        name: "validating",
+        summary: "Lint files, check them against schemas, assert values in CI",
+        examples: &[
+            example(
+                "Report duplicate keys and ambiguous YAML",
+                "confctl lint config.yaml",
+            ),
+            example(
+                "Parse and validate every config file under a directory",
+                "confctl check config --schemas schemas.yaml",
+            ),
+            example(
+                "Fail a CI step when a value is wrong",
+                "confctl assert config.yaml 'server.port == 8080'",
+            ),
+            example(
+                "Infer a JSON Schema from a file",
+                "confctl to-schema config.yaml",
+            ),
+        ],
+    },
+    Topic {
+        name: "environment",
+        summary: "Pass config to programs as environment variables",
+        examples: &[
+            example(
+                "Preview the variables a subtree becomes",
+                "confctl config.yaml database -o env",
+            ),
+            example(
+                "Run a command with them",
+                "confctl exec config.yaml database -- ./migrate",
+            ),
+            example(
+                "Let APP_* variables override the file",
+                "confctl config.yaml server.port --env-prefix APP_",
+            ),
+            example(
+                "A docker compose service's resolved environment",
+                "confctl compose docker-compose.yml web.environment",
+            ),
+        ],
+    },
+    Topic {
+        name: "output",
+        summary: "Send results to files and the clipboard",
+        examples: &[
+            example(
+                "Write the result to a file, creating its directory",
+                "confctl config.yaml clubs.0 --out build/club.json --mkdir",
+            ),
+            example(
+                "Also copy the result to the clipboard",
+                "confctl config.yaml clubs.0.name --copy",
+            ),
+            example(
+                "Where a value is written, as file:line:column",
+                "confctl config.yaml clubs.0.name --location-only",
+            ),
+        ],
+    },
+];
+
+pub(crate) fn run(cli: ExamplesCli) -> Result<()> {
+    if cli.list {
+        let width = TOPICS
+            .iter()
+            .map(|topic| topic.name.len())
+            .max()
+            .unwrap_or(0);
+        for topic in TOPICS {
+            println!("{:width$}  {}", topic.name, topic.summary);
+        }
+        return Ok(());
+    }
+    let topics: Vec<&Topic> = match cli.topic.as_deref() {
+        Some(name) => vec![find(name)?],
+        None => TOPICS.iter().collect(),
+    };
+    let pages: Vec<String> = topics.into_iter().map(render).collect();
+    print!("{}", pages.join("\n"));
+    Ok(())
+}
+
+fn find(name: &str) -> Result<&'static Topic> {
+    match TOPICS.iter().find(|topic| topic.name == name) {
+        Some(topic) => Ok(topic),
+        None => {
+            let names: Vec<&str> = TOPICS.iter().map(|topic| topic.name).collect();
+            bail!("unknown topic `{name}`; topics: {}", names.join(", "))
+        }
+    }
+}
+
+/// A topic as `examples` prints it: a heading, then each command under a
+/// `#` line saying what it does.
+fn render(topic: &Topic) -> String {
+    let mut text = format!("{}\n", format!("{}: {}", topic.name, topic.summary).bold());
+    for example in topic.examples {
+        text.push_str(&format!("  {}\n", format!("# {}", example.about).dimmed()));
+        text.push_str(&format!("  {}\n", example.command));
+    }
+    text
+}
+
+/// What confctl prints when run on a terminal with no FILE.
+pub(crate) fn tutorial() -> String {
+    format!(
+        "No input detected.\n\nMini tutorial:\n{}\n\
+         Run 'confctl examples' for more by topic, or 'confctl --help' for full usage.",
+        first_examples()
+    )
+}
+
+/// The end of `--help`.
+pub(crate) fn help_footer() -> String {
+    format!(
+        "Examples:\n{}\n\
+         Run 'confctl examples' for more by topic, and 'confctl man' for the manual page.",
+        first_examples()
+    )
+}
+
+/// The first examples of a few topics, one command per line.
+fn first_examples() -> String {
+    let mut text = String::new();
+    for (name, count) in [("querying", 2), ("stdin", 2), ("converting", 1)] {
+        let topic = TOPICS.iter().find(|topic| topic.name == name);
+        for example in topic.into_iter().flat_map(|topic| &topic.examples[..count]) {
+            text.push_str(&format!("  {}\n", example.command));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    /// The confctl part of a shell line, split into words as a shell would
+    /// for the quoting the examples use.
+    fn words(command: &str) -> Vec<String> {
+        let confctl = command.rsplit("| ").next().unwrap();
+        let mut words = Vec::new();
+        let mut word: Option<String> = None;
+        let mut quote = None;
+        for c in confctl.chars() {
+            match quote {
+                Some(open) if c == open => quote = None,
+                Some(_) => word.get_or_insert_with(String::new).push(c),
+                None if c == '\'' || c == '"' => {
+                    quote = Some(c);
+                    word.get_or_insert_with(String::new);
+                }
+                None if c.is_whitespace() => words.extend(word.take()),
+                None => word.get_or_insert_with(String::new).push(c),
+            }
+        }
+        words.extend(word);
+        words
+    }
+
+    #[test]
+    fn every_example_parses_as_a_confctl_command() {
+        for topic in TOPICS {
+            for example in topic.examples {
+                let args = words(example.command);
+                assert_eq!(args[0], "confctl", "{}", example.command);
+                if let Err(err) = crate::Cli::try_parse_from(&args) {
+                    panic!("{}: {err}", example.command);
+                }
+            }
+        }
+        assert_eq!(
+            words("cat x | confctl a 'join(b.*, \", \")'"),
+            ["confctl", "a", "join(b.*, \", \")"]
+        );
+    }
+
+    #[test]
+    fn topics_are_found_by_name() {
+        assert_eq!(find("base64").unwrap().name, "base64");
+        assert_eq!(
+            find("nope").err().unwrap().to_string(),
+            "unknown topic `nope`; topics: querying, expressions, stdin, converting, base64, \
+             secrets, editing, searching, validating, environment, output"
+        );
+        assert!(tutorial().contains("  confctl config.yaml clubs.0.name\n"));
+    }
+}
@@ -0,0 +1,110 @@
+//! `confctl exec` — run a command with a config subtree as environment
+//! variables, e.g. `confctl exec config.yaml database -- ./migrate`.
+//!
+//! Keys flatten exactly as `-o env` writes them (`database.pool.size` under
+//! `database` → `POOL_SIZE`), so `confctl config.yaml database -o env` shows
+//! what the child will see (arrays arrive as JSON text, `null` as empty).
+//! Variables are added to the inherited environment and win over it.
+//! confctl waits for the child and exits with its status.
+
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+
+use crate::emit;
+use crate::formats::ParseOptions;
+use crate::{parse_file, resolve_path, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct ExecCli {
+    /// Config file, or `-` for stdin.
+    pub(crate) file: String,
+
+    /// Dotted path of the object to export (default: the whole file).
+    pub(crate) path: Option<String>,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Prepend this to every variable name, e.g. `--prefix APP_`.
+    #[arg(long, default_value = "")]
+    pub(crate) prefix: String,
+
+    /// Command and arguments to run, after `--`.
+    #[arg(last = true, required = true, value_name = "COMMAND")]
+    pub(crate) command: Vec<String>,
+}
+
+/// The child's exit status, for `main` to exit with.
+pub(crate) fn run(cli: ExecCli) -> Result<u8> {
+    let value = parse_file(&cli.file, cli.format, &ParseOptions::default())?;
+    let subtree = match &cli.path {
+        Some(path) => resolve_path(&value, path)?,
+        None => &value,
+    };
+    if !subtree.is_object() {
+        bail!(
+            "exec needs an object to turn into variables, but {} is not one",
+            cli.path.as_deref().unwrap_or("the document")
+        );
+    }
+    let vars = env_vars(subtree, &cli.prefix)?;
+
+    let (program, args) = cli
+        .command
+        .split_first()
+        .context("no command given after --")?;
+    let status = Command::new(program)
+        .args(args)
+        .envs(vars)
+        .status()
+        .with_context(|| format!("running {program}"))?;
+    Ok(exit_code(status))
+}
+
+fn env_vars(subtree: &serde_json::Value, prefix: &str) -> Result<Vec<(String, String)>> {
+    let pairs = emit::env_pairs(subtree)?;
+    Ok(pairs
+        .into_iter()
+        .map(|(key, value)| (format!("{prefix}{key}"), value))
+        .collect())
+}
+
+/// The child's exit code; a child killed by a signal maps to 128 + signal,
+/// as shells report it.
+fn exit_code(status: std::process::ExitStatus) -> u8 {
+    if let Some(code) = status.code() {
+        return code.clamp(0, 255) as u8;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return (128 + signal).clamp(0, 255) as u8;
+        }
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn subtree_flattens_like_env_output_with_prefix() {
+        let database =
+            json!({"host": "db", "pool": {"size": 5}, "replicas": ["a", "b"], "tls": null});
+        assert_eq!(
+            env_vars(&database, "APP_").unwrap(),
+            vec![
+                ("APP_HOST".to_string(), "db".to_string()),
+                ("APP_POOL_SIZE".to_string(), "5".to_string()),
+                ("APP_REPLICAS".to_string(), r#"["a","b"]"#.to_string()),
+                ("APP_TLS".to_string(), String::new()),
+            ]
+        );
+    }
+}
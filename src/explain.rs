@@ -0,0 +1,444 @@
+//! `confctl explain PATH FILE...` — where a value of a layered
+//! configuration comes from, for "why is this setting X in prod".
+//!
+//! The layers stack the way queries stack them: each FILE in turn (deep
+//! merged, later files winning) with its `--profile` layers, then the
+//! process environment under `--env-prefix`. Every layer is listed with
+//! what it has at PATH. The last one to set the value is `final`, the ones
+//! before it `overridden` (`merged` when both are objects), and an
+//! environment variable for a key no earlier layer has is `ignored`, as
+//! `--env-prefix` ignores it.
+//!
+//! Lines come from `location::locate`, as for `--with-location`. Binary and
+//! compressed files have none.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use serde_json::{json, Map, Value};
+
+use crate::emit::{self, env_key, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::merge::deep_merge;
+use crate::profile::{self, Layer, DEFAULT_LAYERS};
+use crate::{
+    detect_format, env_override, format_value_with, input, location, parse_bytes, path,
+    resolve_path, Format,
+};
+
+#[derive(Args, Debug)]
+pub(crate) struct ExplainCli {
+    /// Path to explain, as in queries.
+    pub(crate) path: String,
+
+    /// Config files merged in order, later files winning.
+    #[arg(required = true)]
+    pub(crate) files: Vec<String>,
+
+    /// Stack each FILE's profile layers, as the top-level `--profile` does.
+    #[arg(long, value_name = "NAME")]
+    pub(crate) profile: Option<String>,
+
+    /// Order of the `--profile` layers. Default: base,profile,dotenv.
+    #[arg(long, value_enum, value_delimiter = ',', requires = "profile")]
+    pub(crate) layers: Vec<Layer>,
+
+    /// Apply PREFIX environment variables last, as the top-level
+    /// `--env-prefix` does. Also the prefix of the profile's .env variables.
+    #[arg(long, value_name = "PREFIX")]
+    pub(crate) env_prefix: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Print the explanation as a document in this format.
+    #[arg(short = 'o', long, value_enum)]
+    pub(crate) output: Option<OutputFormat>,
+}
+
+/// One layer, read but not yet merged.
+struct Source {
+    layer: &'static str,
+    origin: String,
+    contents: Contents,
+}
+
+enum Contents {
+    Document {
+        value: Value,
+        /// The file's text and format, for finding lines.
+        text: Option<(String, Format)>,
+    },
+    Variables {
+        vars: HashMap<String, String>,
+        prefix: String,
+        text: Option<String>,
+    },
+}
+
+/// What one layer did to the value at the path.
+#[derive(Debug)]
+struct Step {
+    layer: &'static str,
+    origin: String,
+    line: Option<usize>,
+    variable: Option<String>,
+    value: Option<Value>,
+    status: Status,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Status {
+    Final,
+    Overridden,
+    Merged,
+    Ignored,
+    NotSet,
+}
+
+impl Status {
+    fn name(self) -> &'static str {
+        match self {
+            Status::Final => "final",
+            Status::Overridden => "overridden",
+            Status::Merged => "merged",
+            Status::Ignored => "ignored",
+            Status::NotSet => "not set",
+        }
+    }
+}
+
+pub(crate) fn run(cli: ExplainCli) -> Result<()> {
+    let sources = sources(&cli)?;
+    let (value, steps) = explain(sources, &cli.path)?;
+    if let Some(format) = cli.output {
+        print!(
+            "{}",
+            emit::render(&document(&cli.path, &value, &steps), format)?
+        );
+        return Ok(());
+    }
+
+    println!("{} = {}", cli.path.bold(), format_value_with(&value, true));
+    let rows: Vec<[String; 3]> = steps
+        .iter()
+        .map(|step| {
+            let mut origin = step.origin.clone();
+            if let Some(line) = step.line {
+                origin = format!("{origin}:{line}");
+            }
+            if let Some(variable) = &step.variable {
+                origin = format!("{origin} ({variable})");
+            }
+            let value = match &step.value {
+                Some(value) => format_value_with(value, true),
+                None => "-".to_string(),
+            };
+            [step.layer.to_string(), origin, value]
+        })
+        .collect();
+    let width = |i: usize| {
+        rows.iter()
+            .map(|row| row[i].chars().count())
+            .max()
+            .unwrap_or(0)
+    };
+    let (layer_width, origin_width, value_width) = (width(0), width(1), width(2));
+    for (row, step) in rows.iter().zip(&steps) {
+        let status = match step.status {
+            Status::Final => step.status.name().green().bold(),
+            Status::Overridden | Status::Merged => step.status.name().yellow(),
+            Status::Ignored | Status::NotSet => step.status.name().dimmed(),
+        };
+        println!(
+            "  {:<layer_width$}  {:<origin_width$}  {:<value_width$}  {status}",
+            row[0], row[1], row[2]
+        );
+    }
+    Ok(())
+}
+
+/// The layers of `cli`, in the order they apply.
+fn sources(cli: &ExplainCli) -> Result<Vec<Source>> {
+    let prefix = cli.env_prefix.clone().unwrap_or_default();
+    let mut sources = Vec::new();
+    for file in &cli.files {
+        let Some(name) = &cli.profile else {
+            sources.push(read_document("file", file, cli.format)?);
+            continue;
+        };
+        if file == "-" {
+            bail!("--profile needs a file to find the profile's layers next to, not stdin");
+        }
+        let layers = if cli.layers.is_empty() {
+            DEFAULT_LAYERS.to_vec()
+        } else {
+            cli.layers.clone()
+        };
+        for layer in layers {
+            match layer {
+                Layer::Base => sources.push(read_document("base", file, cli.format)?),
+                Layer::Profile => {
+                    let path = profile::profile_file(Path::new(file), name)?;
+                    let path = path.display().to_string();
+                    sources.push(read_document("profile", &path, cli.format)?);
+                }
+                Layer::Dotenv => {
+                    let path = Path::new(file).with_file_name(".env");
+                    if path.is_file() {
+                        sources.push(Source {
+                            layer: "dotenv",
+                            origin: path.display().to_string(),
+                            contents: Contents::Variables {
+                                vars: profile::dotenv_vars(&path, &ParseOptions::default())?,
+                                prefix: prefix.clone(),
+                                text: fs::read_to_string(&path).ok(),
+                            },
+                        });
+                    }
+                }
+            }
+        }
+    }
+    if let Some(prefix) = &cli.env_prefix {
+        sources.push(Source {
+            layer: "env",
+            origin: "environment".to_string(),
+            contents: Contents::Variables {
+                vars: env_override::process_vars(),
+                prefix: prefix.clone(),
+                text: None,
+            },
+        });
+    }
+    Ok(sources)
+}
+
+fn read_document(layer: &'static str, file: &str, format: Option<Format>) -> Result<Source> {
+    let (name, bytes) = input::open(file).read()?;
+    let value = parse_bytes(&name, &bytes, format, &ParseOptions::default())?;
+    let text = String::from_utf8(bytes).ok().and_then(|text| {
+        let format = detect_format(&name, &text, format).ok()?;
+        Some((text, format))
+    });
+    Ok(Source {
+        layer,
+        origin: name,
+        contents: Contents::Document { value, text },
+    })
+}
+
+/// The merged value at `path`, and each layer's part in it.
+fn explain(sources: Vec<Source>, path: &str) -> Result<(Value, Vec<Step>)> {
+    let segments = path::segments(path)?;
+    let mut merged = Value::Object(Map::new());
+    let mut steps = Vec::new();
+    for source in sources {
+        let step = match source.contents {
+            Contents::Document { value, text } => {
+                let found = resolve_path(&value, path).ok().cloned();
+                let line = found
+                    .as_ref()
+                    .and(text)
+                    .and_then(|(text, format)| location::locate(&text, format, &segments))
+                    .map(|(line, _)| line);
+                deep_merge(&mut merged, value);
+                Step {
+                    layer: source.layer,
+                    origin: source.origin,
+                    line,
+                    variable: None,
+                    status: if found.is_some() {
+                        Status::Final
+                    } else {
+                        Status::NotSet
+                    },
+                    value: found,
+                }
+            }
+            Contents::Variables { vars, prefix, text } => {
+                let mut name = variable_name(&prefix, &segments);
+                let overridable = leaf_under_objects(&merged, &segments);
+                let before = resolve_path(&merged, path).ok().cloned();
+                env_override::apply(&mut merged, &prefix, &vars)
+                    .with_context(|| source.origin.clone())?;
+                let after = resolve_path(&merged, path).ok().cloned();
+                let (value, status) = match vars.get(&name) {
+                    // Variables for keys below an object change part of it.
+                    None if before != after => {
+                        name = format!("{name}_*");
+                        (after, Status::Final)
+                    }
+                    None => (None, Status::NotSet),
+                    Some(text) if !overridable => {
+                        (Some(Value::String(text.clone())), Status::Ignored)
+                    }
+                    Some(_) => (after, Status::Final),
+                };
+                Step {
+                    layer: source.layer,
+                    origin: source.origin,
+                    line: value
+                        .as_ref()
+                        .and(text.as_deref())
+                        .and_then(|text| location::locate(text, Format::Env, &[name.clone()]))
+                        .map(|(line, _)| line),
+                    variable: Some(name),
+                    value,
+                    status,
+                }
+            }
+        };
+        steps.push(step);
+    }
+
+    let value = resolve_path(&merged, path)?.clone();
+    let last = steps.iter().rposition(|step| step.status == Status::Final);
+    for (index, step) in steps.iter_mut().enumerate() {
+        if step.status != Status::Final || Some(index) == last {
+            continue;
+        }
+        let objects = value.is_object() && step.value.as_ref().is_some_and(Value::is_object);
+        step.status = if objects {
+            Status::Merged
+        } else {
+            Status::Overridden
+        };
+    }
+    Ok((value, steps))
+}
+
+/// The variable `--env-prefix` reads for the path: `server.port` under
+/// `APP_` is `APP_SERVER_PORT`.
+fn variable_name(prefix: &str, segments: &[String]) -> String {
+    let keys: Vec<String> = segments.iter().map(|key| env_key(key)).collect();
+    format!("{prefix}{}", keys.join("_"))
+}
+
+/// Whether the path names a leaf reached through objects only, which is
+/// what an environment variable can override.
+fn leaf_under_objects(value: &Value, segments: &[String]) -> bool {
+    let mut current = value;
+    for segment in segments {
+        match current {
+            Value::Object(map) => match map.get(segment) {
+                Some(child) => current = child,
+                None => return false,
+            },
+            _ => return false,
+        }
+    }
+    !current.is_object()
+}
+
+/// The explanation as a document for `-o`.
+fn document(path: &str, value: &Value, steps: &[Step]) -> Value {
+    let layers = steps
+        .iter()
+        .map(|step| {
+            let mut entry = Map::new();
+            entry.insert("layer".into(), json!(step.layer));
+            entry.insert("source".into(), json!(step.origin));
+            if let Some(line) = step.line {
+                entry.insert("line".into(), json!(line));
+            }
+            if let Some(variable) = &step.variable {
+                entry.insert("variable".into(), json!(variable));
+            }
+            if let Some(value) = &step.value {
+                entry.insert("value".into(), value.clone());
+            }
+            entry.insert("status".into(), json!(step.status.name()));
+            Value::Object(entry)
+        })
+        .collect();
+    json!({"path": path, "value": value, "layers": Value::Array(layers)})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(path: &str, layers: &[Layer]) -> ExplainCli {
+        ExplainCli {
+            path: path.to_string(),
+            files: vec!["testdata/profile/config.yaml".to_string()],
+            profile: Some("prod".to_string()),
+            layers: layers.to_vec(),
+            env_prefix: None,
+            format: None,
+            output: None,
+        }
+    }
+
+    fn statuses(steps: &[Step]) -> Vec<(&str, Option<usize>, &str)> {
+        steps
+            .iter()
+            .map(|step| (step.layer, step.line, step.status.name()))
+            .collect()
+    }
+
+    #[test]
+    fn profile_layers_are_traced_to_their_lines() {
+        let cli = cli("server.port", &[]);
+        let (value, steps) = explain(sources(&cli).unwrap(), &cli.path).unwrap();
+        assert_eq!(value, json!(9443));
+        assert_eq!(
+            statuses(&steps),
+            [
+                ("base", Some(3), "overridden"),
+                ("profile", Some(2), "overridden"),
+                ("dotenv", Some(2), "final"),
+            ]
+        );
+        assert_eq!(steps[2].variable.as_deref(), Some("SERVER_PORT"));
+
+        let cli = self::cli("db", &[Layer::Base, Layer::Profile]);
+        let (_, steps) = explain(sources(&cli).unwrap(), &cli.path).unwrap();
+        assert_eq!(
+            statuses(&steps),
+            [("base", Some(4), "merged"), ("profile", Some(3), "final")]
+        );
+    }
+
+    #[test]
+    fn variables_for_missing_keys_are_ignored() {
+        let file = |value: Value| Source {
+            layer: "file",
+            origin: "file".to_string(),
+            contents: Contents::Document { value, text: None },
+        };
+        let env = Source {
+            layer: "env",
+            origin: "environment".to_string(),
+            contents: Contents::Variables {
+                vars: HashMap::from([("APP_DB_HOST".to_string(), "env-db".to_string())]),
+                prefix: "APP_".to_string(),
+                text: None,
+            },
+        };
+        let sources = vec![
+            file(json!({"db": {"port": 5432}})),
+            env,
+            file(json!({"db": {"host": "db"}})),
+        ];
+        let (value, steps) = explain(sources, "db.host").unwrap();
+        assert_eq!(value, json!("db"));
+        assert_eq!(
+            statuses(&steps),
+            [
+                ("file", None, "not set"),
+                ("env", None, "ignored"),
+                ("file", None, "final"),
+            ]
+        );
+        assert_eq!(steps[1].value, Some(json!("env-db")));
+
+        let err = explain(Vec::new(), "db.host").unwrap_err();
+        assert!(err.to_string().contains("Key not found"), "{err}");
+    }
+}
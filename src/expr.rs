@@ -0,0 +1,805 @@
+//! Expressions in place of PATH: `confctl cfg.yaml 'replicas * 2'`,
+//! `'upper(env)'`, `'join(hosts, ",")'` — small computations that would
+//! otherwise need a jq pipeline.
+//!
+//! PATH is read as an expression when it calls one of the functions, starts
+//! with `!` or has an operator with a space before it, outside quotes,
+//! brackets and `\` escapes; otherwise it stays a plain path, so keys such
+//! as `url/path`, `k+1`, `x*y`, `len(x)` or `app\.kubernetes\.io/name`
+//! resolve as they always did. Operands are paths (any path syntax, with `*`
+//! giving the array of matches), numbers, `"strings"` or `'strings'`,
+//! `true`, `false` and `null`. The operators are `+ - * / %`,
+//! `== != < <= > >=`, `&& || !` and parentheses; write the binary operators
+//! with a space before them, since keys may contain them.
+//! `+` joins strings when either side is one; the other arithmetic reads
+//! numeric strings as numbers, as the aggregates do. Integers stay integers
+//! until a result needs a fraction.
+
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+
+use crate::aggregate::{self, type_name, Aggregate};
+use crate::errors;
+use crate::formats::float_value;
+use crate::{format_value_with, path, resolve_path, select_paths};
+
+/// The functions an expression can call, for help and error text.
+const FUNCTIONS: &str = "upper, lower, trim, length, join, split, replace, contains, \
+starts_with, ends_with, keys, values, sum, min, max, avg, unique, round, floor, ceil, abs, \
+string, number, default";
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Expr {
+    Literal(Value),
+    Path(String),
+    Call(String, Vec<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Rem => "%",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::And => "&&",
+            Op::Or => "||",
+        }
+    }
+}
+
+/// PATH as an expression, or None when it is a plain path.
+pub(crate) fn parse_query(path: Option<&str>) -> Result<Option<Expr>> {
+    match path {
+        Some(text) if is_expression(text) => parse(text)
+            .with_context(|| format!("in the expression `{text}`"))
+            .map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Whether `text` calls one of the functions, starts with `!`, or has an
+/// operator with a space before it, outside quotes, brackets and `\`
+/// escapes. Keys hold `-`, `/`, `+`, `*`, parentheses and spaces, so those
+/// alone leave a path.
+fn is_expression(text: &str) -> bool {
+    let mut quote = None;
+    let mut depth = 0;
+    let mut previous: Option<char> = None;
+    let mut chars = text.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\\') => {
+                chars.next();
+            }
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ if depth > 0 => {}
+            (None, '(') if calls_function(&text[..at]) => return true,
+            (None, '!') if previous.is_none() => return true,
+            (None, c) if "+-*/%<>=!&|".contains(c) && previous.is_some_and(char::is_whitespace) => {
+                return true
+            }
+            _ => {}
+        }
+        previous = Some(c);
+    }
+    false
+}
+
+/// Whether `before`, the text ahead of a `(`, ends with a function name
+/// that is not itself a key segment (`a.len(x)` is a path).
+fn calls_function(before: &str) -> bool {
+    let name = before.trim_end();
+    let start = name
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .map_or(0, |at| at + 1);
+    let is_function = FUNCTIONS.split(", ").any(|f| f == &name[start..]);
+    is_function && !name[..start].ends_with(['.', '\\'])
+}
+
+pub(crate) fn parse(text: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(expr),
+        Some(token) => bail!("unexpected {}", token.describe()),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(Value),
+    Text(String),
+    Word(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Number(n) => format!("number {n}"),
+            Token::Text(text) => format!("string {text:?}"),
+            Token::Word(word) => format!("`{word}`"),
+            Token::Symbol(symbol) => format!("`{symbol}`"),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 17] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!", "(", ")", ",",
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| anyhow!("unclosed {c} string"))?;
+            tokens.push(Token::Text(rest[1..=end].to_string()));
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = &rest[..end];
+            let value = match number.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => number
+                    .parse::<f64>()
+                    .map(float_value)
+                    .map_err(|_| anyhow!("`{number}` is not a number"))?,
+            };
+            tokens.push(Token::Number(value));
+            rest = &rest[end..];
+        } else if c.is_alphanumeric() || c == '_' || c == '[' {
+            let end = word_end(rest);
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            bail!("unexpected `{c}`");
+        }
+    }
+    Ok(tokens)
+}
+
+/// The length of the path at the start of `text`: key characters, `-`,
+/// `/`, `+`, dots, `*` after a dot, `\`-escaped characters, and brackets or
+/// quotes with their contents.
+fn word_end(text: &str) -> usize {
+    let mut quote = None;
+    let mut depth = 0;
+    let mut previous = ' ';
+    for (at, c) in text.char_indices() {
+        match (quote, c) {
+            _ if previous == '\\' => {
+                // An escaped backslash escapes nothing after it.
+                previous = ' ';
+                continue;
+            }
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') if previous == '.' || depth > 0 => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, _) if depth > 0 => {}
+            (None, '*') if previous == '.' => {}
+            (None, c) if c.is_alphanumeric() || "_-/+.\\".contains(c) => {}
+            _ => return at,
+        }
+        previous = c;
+    }
+    text.len()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        let found = self.tokens.get(self.pos) == Some(&Token::Symbol(symbol));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[(&'static str, Op)],
+        next: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut left = next(self)?;
+        'outer: loop {
+            for (symbol, op) in ops {
+                if self.eat(symbol) {
+                    let right = next(self)?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.binary(&[("||", Op::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.binary(&[("&&", Op::And)], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        self.binary(
+            &[
+                ("==", Op::Eq),
+                ("!=", Op::Ne),
+                ("<=", Op::Le),
+                (">=", Op::Ge),
+                ("<", Op::Lt),
+                (">", Op::Gt),
+            ],
+            Self::sum,
+        )
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        self.binary(&[("+", Op::Add), ("-", Op::Sub)], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        self.binary(
+            &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+            Self::unary,
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            bail!("the expression ends early");
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Literal(n)),
+            Token::Text(text) => Ok(Expr::Literal(Value::String(text))),
+            Token::Symbol("(") => {
+                let inner = self.or()?;
+                if !self.eat(")") {
+                    bail!("missing `)`");
+                }
+                Ok(inner)
+            }
+            Token::Word(word) if self.eat("(") => {
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.or()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            bail!("expected `,` or `)` in the arguments of {word}()");
+                        }
+                    }
+                }
+                Ok(Expr::Call(word, args))
+            }
+            Token::Word(word) => Ok(match word.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                "null" => Expr::Literal(Value::Null),
+                _ => {
+                    path::segments(&word)?;
+                    Expr::Path(word)
+                }
+            }),
+            token => bail!("unexpected {}", token.describe()),
+        }
+    }
+}
+
+impl Expr {
+    /// The paths the expression reads, each once, in the order written.
+    pub(crate) fn paths(&self) -> Vec<&str> {
+        let mut paths = Vec::new();
+        self.collect_paths(&mut paths);
+        paths
+    }
+
+    fn collect_paths<'a>(&'a self, paths: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Path(text) => {
+                if !paths.contains(&text.as_str()) {
+                    paths.push(text);
+                }
+            }
+            Expr::Call(_, args) => args.iter().for_each(|arg| arg.collect_paths(paths)),
+            Expr::Not(inner) | Expr::Negate(inner) => inner.collect_paths(paths),
+            Expr::Binary(_, left, right) => {
+                left.collect_paths(paths);
+                right.collect_paths(paths);
+            }
+        }
+    }
+
+    /// The expression's value with paths resolved against `root`.
+    pub(crate) fn eval(&self, root: &Value) -> Result<Value> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Path(text) => {
+                if !path::segments(text)?.iter().any(|segment| segment == "*") {
+                    return resolve_path(root, text).cloned();
+                }
+                Ok(Value::Array(
+                    select_paths(root, text)?
+                        .into_iter()
+                        .map(|(_, found)| found.clone())
+                        .collect(),
+                ))
+            }
+            Expr::Not(inner) => Ok(Value::Bool(!truthy(&inner.eval(root)?))),
+            Expr::Negate(inner) => {
+                let value = inner.eval(root)?;
+                arithmetic(Op::Sub, &Value::from(0), &value)
+            }
+            Expr::Binary(Op::And, left, right) => Ok(Value::Bool(
+                truthy(&left.eval(root)?) && truthy(&right.eval(root)?),
+            )),
+            Expr::Binary(Op::Or, left, right) => Ok(Value::Bool(
+                truthy(&left.eval(root)?) || truthy(&right.eval(root)?),
+            )),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.eval(root)?, right.eval(root)?);
+                match op {
+                    Op::Eq => Ok(Value::Bool(compare(&left, &right) == Some(Ordering::Equal))),
+                    Op::Ne => Ok(Value::Bool(compare(&left, &right) != Some(Ordering::Equal))),
+                    Op::Lt | Op::Le | Op::Gt | Op::Ge => {
+                        let Some(ordering) = compare(&left, &right) else {
+                            bail!(
+                                "cannot compare {} with {} using {}",
+                                type_name(&left),
+                                type_name(&right),
+                                op.symbol()
+                            );
+                        };
+                        Ok(Value::Bool(match op {
+                            Op::Lt => ordering.is_lt(),
+                            Op::Le => ordering.is_le(),
+                            Op::Gt => ordering.is_gt(),
+                            _ => ordering.is_ge(),
+                        }))
+                    }
+                    Op::Add if left.is_string() || right.is_string() => Ok(Value::String(
+                        format_value_with(&left, true) + &format_value_with(&right, true),
+                    )),
+                    _ => arithmetic(*op, &left, &right),
+                }
+            }
+            Expr::Call(name, args) => call(name, args, root),
+        }
+    }
+}
+
+/// `false`, `null`, `0`, `""` and empty arrays and objects are false.
+pub(crate) fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(flag) => *flag,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(text) => !text.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// Numbers (and numeric strings) by value, strings by text, anything else
+/// only by equality.
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (numeric(left), numeric(right)) {
+        return a.as_f64().partial_cmp(&b.as_f64());
+    }
+    match (left, right) {
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => (left == right).then_some(Ordering::Equal),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn as_f64(self) -> f64 {
+        match self {
+            Num::Int(n) => n as f64,
+            Num::Float(f) => f,
+        }
+    }
+}
+
+fn numeric(value: &Value) -> Option<Num> {
+    match value {
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Some(Num::Int(i)),
+            None => n.as_f64().map(Num::Float),
+        },
+        Value::String(text) => match text.trim().parse::<i64>() {
+            Ok(n) => Some(Num::Int(n)),
+            Err(_) => text
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite())
+                .map(Num::Float),
+        },
+        _ => None,
+    }
+}
+
+fn arithmetic(op: Op, left: &Value, right: &Value) -> Result<Value> {
+    let operand = |value: &Value| {
+        numeric(value).ok_or_else(|| {
+            anyhow!(
+                "{} needs numbers, got {}",
+                op.symbol(),
+                describe_value(value)
+            )
+        })
+    };
+    let (a, b) = (operand(left)?, operand(right)?);
+    if let (Num::Int(a), Num::Int(b)) = (a, b) {
+        let exact = match op {
+            Op::Add => a.checked_add(b),
+            Op::Sub => a.checked_sub(b),
+            Op::Mul => a.checked_mul(b),
+            Op::Div | Op::Rem if b == 0 => bail!("division by zero"),
+            Op::Div => a.checked_rem(b).filter(|r| *r == 0).and(a.checked_div(b)),
+            Op::Rem => a.checked_rem(b),
+            _ => None,
+        };
+        if let Some(n) = exact {
+            return Ok(Value::from(n));
+        }
+    }
+    let (a, b) = (a.as_f64(), b.as_f64());
+    let result = match op {
+        Op::Add => a + b,
+        Op::Sub => a - b,
+        Op::Mul => a * b,
+        Op::Div | Op::Rem if b == 0.0 => bail!("division by zero"),
+        Op::Div => a / b,
+        _ => a % b,
+    };
+    Ok(float_value(result))
+}
+
+/// A value for error text: its type, with short scalars shown.
+fn describe_value(value: &Value) -> String {
+    match value {
+        Value::String(text) if text.chars().count() <= 20 => format!("the string {text:?}"),
+        Value::Bool(flag) => format!("{flag}"),
+        other => type_name(other).to_string(),
+    }
+}
+
+fn call(name: &str, args: &[Expr], root: &Value) -> Result<Value> {
+    // default() is the one function that looks at a failure rather than a
+    // value: a missing path gives the fallback.
+    if name == "default" {
+        let [value, fallback] = args else {
+            bail!(
+                "default() takes 2 arguments (value, fallback), got {}",
+                args.len()
+            );
+        };
+        return match value.eval(root) {
+            Ok(Value::Null) => fallback.eval(root),
+            Err(err) if errors::is_missing_path(&err) => fallback.eval(root),
+            other => other,
+        };
+    }
+    let values = args
+        .iter()
+        .map(|arg| arg.eval(root))
+        .collect::<Result<Vec<_>>>()?;
+    let text = |value: &Value| match value {
+        Value::String(text) => Ok(text.clone()),
+        Value::Number(_) | Value::Bool(_) => Ok(value.to_string()),
+        other => bail!("{name}() needs a string, got {}", type_name(other)),
+    };
+    let number = |value: &Value| {
+        numeric(value)
+            .map(Num::as_f64)
+            .ok_or_else(|| anyhow!("{name}() needs a number, got {}", describe_value(value)))
+    };
+    let arity = |count: usize| {
+        if values.len() == count {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "{name}() takes {count} argument{}, got {}",
+                if count == 1 { "" } else { "s" },
+                values.len()
+            ))
+        }
+    };
+    let aggregate = |op: Aggregate| -> Result<Value> {
+        arity(1)?;
+        aggregate::apply(op, &values[0]).map_err(|err| anyhow!("{name}(): {err}"))
+    };
+    let rounded = |f: fn(f64) -> f64| -> Result<Value> {
+        arity(1)?;
+        if let Some(Num::Int(n)) = numeric(&values[0]) {
+            return Ok(Value::from(n));
+        }
+        let result = f(number(&values[0])?);
+        Ok(if result.abs() < i64::MAX as f64 {
+            Value::from(result as i64)
+        } else {
+            float_value(result)
+        })
+    };
+    match name {
+        "upper" => {
+            arity(1)?;
+            Ok(Value::String(text(&values[0])?.to_uppercase()))
+        }
+        "lower" => {
+            arity(1)?;
+            Ok(Value::String(text(&values[0])?.to_lowercase()))
+        }
+        "trim" => {
+            arity(1)?;
+            Ok(Value::String(text(&values[0])?.trim().to_string()))
+        }
+        "length" => {
+            arity(1)?;
+            let length = aggregate::length(&values[0]).map_err(|err| anyhow!("{name}(): {err}"))?;
+            Ok(Value::from(length))
+        }
+        "join" => {
+            arity(2)?;
+            let Value::Array(items) = &values[0] else {
+                bail!("join() needs an array, got {}", type_name(&values[0]));
+            };
+            let parts = items
+                .iter()
+                .map(|item| format_value_with(item, true))
+                .collect::<Vec<_>>();
+            Ok(Value::String(parts.join(&text(&values[1])?)))
+        }
+        "split" => {
+            arity(2)?;
+            let separator = text(&values[1])?;
+            if separator.is_empty() {
+                bail!("split() needs a non-empty separator");
+            }
+            Ok(Value::Array(
+                text(&values[0])?
+                    .split(separator.as_str())
+                    .map(|part| Value::String(part.to_string()))
+                    .collect(),
+            ))
+        }
+        "replace" => {
+            arity(3)?;
+            let (from, to) = (text(&values[1])?, text(&values[2])?);
+            Ok(Value::String(text(&values[0])?.replace(&from, &to)))
+        }
+        "contains" => {
+            arity(2)?;
+            Ok(Value::Bool(match &values[0] {
+                Value::Array(items) => items
+                    .iter()
+                    .any(|item| compare(item, &values[1]) == Some(Ordering::Equal)),
+                Value::Object(map) => map.contains_key(&text(&values[1])?),
+                haystack => text(haystack)?.contains(&text(&values[1])?),
+            }))
+        }
+        "starts_with" => {
+            arity(2)?;
+            Ok(Value::Bool(
+                text(&values[0])?.starts_with(&text(&values[1])?),
+            ))
+        }
+        "ends_with" => {
+            arity(2)?;
+            Ok(Value::Bool(text(&values[0])?.ends_with(&text(&values[1])?)))
+        }
+        "keys" | "values" => {
+            arity(1)?;
+            let Value::Object(map) = &values[0] else {
+                bail!("{name}() needs an object, got {}", type_name(&values[0]));
+            };
+            Ok(Value::Array(if name == "keys" {
+                map.keys().cloned().map(Value::String).collect()
+            } else {
+                map.values().cloned().collect()
+            }))
+        }
+        "sum" => aggregate(Aggregate::Sum),
+        "min" => aggregate(Aggregate::Min),
+        "max" => aggregate(Aggregate::Max),
+        "avg" => aggregate(Aggregate::Avg),
+        "unique" => aggregate(Aggregate::Unique),
+        "round" => rounded(f64::round),
+        "floor" => rounded(f64::floor),
+        "ceil" => rounded(f64::ceil),
+        "abs" => {
+            arity(1)?;
+            match numeric(&values[0]) {
+                Some(Num::Int(n)) if n != i64::MIN => Ok(Value::from(n.abs())),
+                _ => Ok(float_value(number(&values[0])?.abs())),
+            }
+        }
+        "string" => {
+            arity(1)?;
+            Ok(Value::String(format_value_with(&values[0], true)))
+        }
+        "number" => {
+            arity(1)?;
+            match numeric(&values[0]) {
+                Some(Num::Int(n)) => Ok(Value::from(n)),
+                _ => Ok(float_value(number(&values[0])?)),
+            }
+        }
+        _ => bail!("unknown function {name}(); the functions are {FUNCTIONS}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> Value {
+        json!({
+            "replicas": 3,
+            "ratio": 0.5,
+            "env": "prod",
+            "port": "8080",
+            "hosts": ["a.local", "b.local"],
+            "servers": [{"name": "a", "cpu": 2}, {"name": "b", "cpu": 4}],
+            "db": {"host": "db.local", "user": null},
+            "max-conns": 10
+        })
+    }
+
+    fn eval(text: &str) -> Value {
+        parse(text).unwrap().eval(&config()).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        match parse(text) {
+            Ok(expr) => expr.eval(&config()).unwrap_err().to_string(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn paths_stay_paths_unless_they_look_like_expressions() {
+        assert!(!is_expression("db.host"));
+        assert!(!is_expression("servers.*.name"));
+        assert!(!is_expression("max-conns"));
+        assert!(!is_expression("labels[\"a b(c)\"]"));
+        assert!(is_expression("replicas * 2"));
+        assert!(is_expression("upper(env)"));
+        assert!(is_expression("replicas + 1"));
+        assert!(!is_expression("x*y"));
+        assert!(!is_expression("len(x)"));
+        assert!(!is_expression("a.upper(x)"));
+        assert!(is_expression("length (hosts) > 2"));
+        assert!(is_expression("(replicas) * 2"));
+        assert!(is_expression("!debug"));
+        assert!(!is_expression("url/path"));
+        assert!(!is_expression("k+1"));
+        assert!(!is_expression("my key"));
+        assert!(!is_expression("metadata.labels.app\\.kubernetes\\.io/name"));
+        assert_eq!(parse_query(Some("db.host")).unwrap(), None);
+        assert!(parse_query(Some("upper(")).is_err());
+    }
+
+    #[test]
+    fn arithmetic_keeps_integers_exact() {
+        assert_eq!(eval("replicas * 2"), json!(6));
+        assert_eq!(eval("replicas * ratio"), json!(1.5));
+        assert_eq!(eval("(replicas + 1) / 2"), json!(2));
+        assert_eq!(eval("replicas / 2"), json!(1.5));
+        assert_eq!(eval("port + 1"), json!("80801"));
+        assert_eq!(eval("number(port) + 1"), json!(8081));
+        assert_eq!(eval("port * 1 - max-conns"), json!(8070));
+        assert_eq!(eval("-replicas % 2"), json!(-1));
+        assert_eq!(eval("servers[1].cpu >= 4 && env == 'prod'"), json!(true));
+        assert_eq!(eval("!db.user || 1 > 2"), json!(true));
+    }
+
+    #[test]
+    fn functions_cover_strings_arrays_and_numbers() {
+        assert_eq!(eval("upper(env)"), json!("PROD"));
+        assert_eq!(eval("join(hosts, \",\")"), json!("a.local,b.local"));
+        assert_eq!(eval("join(servers.*.name, '-')"), json!("a-b"));
+        assert_eq!(eval("split(db.host, '.')"), json!(["db", "local"]));
+        assert_eq!(eval("replace(env, 'prod', 'dev') + '-1'"), json!("dev-1"));
+        assert_eq!(eval("length(hosts) + length(env)"), json!(6));
+        assert_eq!(eval("sum(servers.*.cpu) * 2"), json!(12));
+        assert_eq!(eval("round(ratio * 5)"), json!(3));
+        assert_eq!(eval("contains(hosts, 'b.local')"), json!(true));
+        assert_eq!(eval("keys(db)"), json!(["host", "user"]));
+        assert_eq!(eval("default(db.password, 'none')"), json!("none"));
+        assert_eq!(eval("default(db.user, db.host)"), json!("db.local"));
+    }
+
+    #[test]
+    fn errors_explain_what_went_wrong() {
+        assert_eq!(error("env * 2"), "* needs numbers, got the string \"prod\"");
+        assert_eq!(error("replicas / 0"), "division by zero");
+        assert!(error("upper(nope)").contains("Key not found: 'nope'"));
+        assert!(error("shout(env)").starts_with("unknown function shout()"));
+        assert_eq!(error("join(hosts)"), "join() takes 2 arguments, got 1");
+        assert_eq!(error("(replicas * 2"), "missing `)`");
+        assert_eq!(error("replicas *"), "the expression ends early");
+        assert_eq!(
+            error("hosts < 2"),
+            "cannot compare an array with a number using <"
+        );
+        assert_eq!(error("'open"), "unclosed ' string");
+    }
+}
@@ -0,0 +1,173 @@
+//! `confctl find` — every path in a document whose last key matches a
+//! name, with its value, for finding a setting in a large unfamiliar file
+//! without knowing where it lives.
+//!
+//! Keys match exactly (or case-insensitively with `-i`), or as a regular
+//! expression with `--regex` (see `crate::regex`). Only object keys are
+//! names: array items are searched but their indexes never match.
+
+use anyhow::Result;
+use clap::Args;
+use serde_json::{Map, Value};
+
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::path::quote_segment;
+use crate::regex::Regex;
+use crate::{format_value_with, parse_file, redact_with, Format, RedactOptions};
+
+#[derive(Args, Debug)]
+pub(crate) struct FindCli {
+    /// Config file, or `-` for stdin.
+    pub(crate) file: String,
+
+    /// Key name to look for: the last segment of a path.
+    #[arg(long, value_name = "NAME")]
+    pub(crate) key: String,
+
+    /// Treat --key as a regular expression, matched anywhere in the key
+    /// (anchor it with `^...$`).
+    #[arg(long)]
+    pub(crate) regex: bool,
+
+    /// Match case-insensitively.
+    #[arg(short = 'i', long)]
+    pub(crate) ignore_case: bool,
+
+    /// Mask secret values in the output, as `--redact` does for queries.
+    #[arg(short = 'r', long)]
+    pub(crate) redact: bool,
+
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Print the matches as one document keyed by path in this format
+    /// instead of `path: value` lines.
+    #[arg(short = 'o', long, value_enum)]
+    pub(crate) output: Option<OutputFormat>,
+}
+
+/// Prints the matches; `false` when there were none.
+pub(crate) fn run(cli: FindCli) -> Result<bool> {
+    let mut value = parse_file(&cli.file, cli.format, &ParseOptions::default())?;
+    if cli.redact {
+        value = redact_with(&value, &RedactOptions::from_cli(&[], None));
+    }
+    let matcher = if cli.regex {
+        Some(Regex::new(&cli.key, cli.ignore_case)?)
+    } else {
+        None
+    };
+    let matches = find_keys(&value, &|key| match &matcher {
+        Some(regex) => regex.is_match(key),
+        None if cli.ignore_case => key.eq_ignore_ascii_case(&cli.key),
+        None => key == cli.key,
+    });
+    print_matches(&matches, cli.output)?;
+    Ok(!matches.is_empty())
+}
+
+/// `path: value` lines, or with `output` one document keyed by path.
+/// Shared with `grep`.
+pub(crate) fn print_matches(
+    matches: &[(String, &Value)],
+    output: Option<OutputFormat>,
+) -> Result<()> {
+    match output {
+        Some(format) => {
+            let keyed: Map<String, Value> = matches
+                .iter()
+                .map(|(path, value)| (path.clone(), (*value).clone()))
+                .collect();
+            print!("{}", emit::render(&Value::Object(keyed), format)?);
+        }
+        None => {
+            for (path, value) in matches {
+                println!("{path}: {}", format_value_with(value, true));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Every `(path, value)` whose last key satisfies `matches`, in the order
+/// the document prints. A match's children are searched too.
+pub(crate) fn find_keys<'a>(
+    value: &'a Value,
+    matches: &dyn Fn(&str) -> bool,
+) -> Vec<(String, &'a Value)> {
+    let mut found = Vec::new();
+    walk(value, "", &mut |path, key, value| {
+        if key.is_some_and(matches) {
+            found.push((path.to_string(), value));
+        }
+    });
+    found
+}
+
+/// Visit every node below the root with its dotted path (keys quoted as
+/// `resolve_path` reads them) and, for object entries, its key.
+pub(crate) fn walk<'a>(
+    value: &'a Value,
+    path: &str,
+    visit: &mut dyn FnMut(&str, Option<&str>, &'a Value),
+) {
+    let join = |segment: &str| {
+        if path.is_empty() {
+            segment.to_string()
+        } else {
+            format!("{path}.{segment}")
+        }
+    };
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let child_path = join(&quote_segment(key));
+                visit(&child_path, Some(key), child);
+                walk(child, &child_path, visit);
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter().enumerate() {
+                let child_path = join(&index.to_string());
+                visit(&child_path, None, child);
+                walk(child, &child_path, visit);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn keys_match_at_any_depth() {
+        let value = json!({
+            "password": "top",
+            "db": {"primary": {"password": "p1"}, "replicas": [{"password": "r1", "port": 5432}]},
+            "cache": {"Password": "c1", "password_file": "/run/secret"}
+        });
+        let paths = |matches: Vec<(String, &Value)>| -> Vec<String> {
+            matches.into_iter().map(|(path, _)| path).collect()
+        };
+        assert_eq!(
+            paths(find_keys(&value, &|key| key == "password")),
+            ["db.primary.password", "db.replicas.0.password", "password"]
+        );
+        let regex = Regex::new("^password(_file)?$", true).unwrap();
+        assert_eq!(
+            paths(find_keys(&value, &|key| regex.is_match(key))),
+            [
+                "cache.Password",
+                "cache.password_file",
+                "db.primary.password",
+                "db.replicas.0.password",
+                "password"
+            ]
+        );
+        assert!(find_keys(&value, &|key| key == "0").is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+//! CBOR decoder (RFC 8949): definite and indefinite lengths, half/single/
+//! double floats, and the common tags. Byte strings become base64 text;
+//! bignums that fit in 64 bits become numbers, larger ones decimal strings.
+
+use anyhow::{bail, Result};
+use serde_json::{json, Map, Value};
+
+use super::{bytes_value, float_value, key_string, ByteReader, MAX_DEPTH};
+
+/// "Break" stop code closing an indefinite-length item.
+const BREAK: u8 = 0xff;
+
+/// Decode exactly one CBOR data item; trailing bytes are an error.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Value> {
+    let mut reader = ByteReader::new(bytes);
+    let value = read_value(&mut reader, 0)?;
+    if !reader.is_empty() {
+        bail!("trailing data after CBOR item at byte {}", reader.pos());
+    }
+    Ok(value)
+}
+
+/// Argument of an initial byte: the count/length/value for `info`, or `None`
+/// for indefinite length (info 31).
+fn argument(r: &mut ByteReader, info: u8, at: usize) -> Result<Option<u64>> {
+    match info {
+        0..=23 => Ok(Some(u64::from(info))),
+        24..=27 => Ok(Some(r.uint(1 << (info - 24))?)),
+        31 => Ok(None),
+        _ => bail!("reserved CBOR additional info {info} at byte {at}"),
+    }
+}
+
+fn read_value(r: &mut ByteReader, depth: usize) -> Result<Value> {
+    if depth > MAX_DEPTH {
+        bail!("CBOR nesting deeper than {MAX_DEPTH} at byte {}", r.pos());
+    }
+    let at = r.pos();
+    let initial = r.u8()?;
+    let (major, info) = (initial >> 5, initial & 0x1f);
+
+    if major == 7 {
+        return read_simple(r, info, at);
+    }
+    let arg = argument(r, info, at)?;
+    let definite = |what: &str| match arg {
+        Some(n) => Ok(n),
+        None => bail!("indefinite-length {what} is not allowed at byte {at}"),
+    };
+
+    let value = match major {
+        0 => json!(definite("integer")?),
+        1 => {
+            let n = definite("integer")?;
+            match i64::try_from(n) {
+                Ok(n) => json!(-1 - n),
+                Err(_) => Value::String(format!("-{}", u128::from(n) + 1)),
+            }
+        }
+        2 => bytes_value(&read_chunks(r, arg, 2)?),
+        3 => {
+            let raw = read_chunks(r, arg, 3)?;
+            match String::from_utf8(raw) {
+                Ok(s) => Value::String(s),
+                Err(_) => bail!("invalid UTF-8 in CBOR text string at byte {at}"),
+            }
+        }
+        4 => {
+            let mut items = Vec::new();
+            match arg {
+                Some(n) => {
+                    for _ in 0..n {
+                        items.push(read_value(r, depth + 1)?);
+                    }
+                }
+                None => {
+                    while r.peek() != Some(BREAK) {
+                        items.push(read_value(r, depth + 1)?);
+                    }
+                    r.u8()?;
+                }
+            }
+            Value::Array(items)
+        }
+        5 => {
+            let mut map = Map::new();
+            let mut remaining = arg;
+            loop {
+                match remaining {
+                    Some(0) => break,
+                    Some(n) => remaining = Some(n - 1),
+                    None if r.peek() == Some(BREAK) => {
+                        r.u8()?;
+                        break;
+                    }
+                    None => {}
+                }
+                let key = key_string(read_value(r, depth + 1)?);
+                let value = read_value(r, depth + 1)?;
+                map.insert(key, value);
+            }
+            Value::Object(map)
+        }
+        6 => read_tagged(r, definite("tag")?, depth)?,
+        _ => unreachable!("major type is three bits"),
+    };
+    Ok(value)
+}
+
+/// Byte/text string contents, concatenating the chunks of an indefinite
+/// string (each chunk must be a definite string of the same major type).
+fn read_chunks(r: &mut ByteReader, arg: Option<u64>, major: u8) -> Result<Vec<u8>> {
+    if let Some(len) = arg {
+        let len = usize::try_from(len)?;
+        return Ok(r.take(len)?.to_vec());
+    }
+    let mut out = Vec::new();
+    loop {
+        let at = r.pos();
+        let initial = r.u8()?;
+        if initial == BREAK {
+            return Ok(out);
+        }
+        if initial >> 5 != major || initial & 0x1f == 31 {
+            bail!("invalid chunk in indefinite-length string at byte {at}");
+        }
+        let Some(len) = argument(r, initial & 0x1f, at)? else {
+            unreachable!("indefinite chunks rejected above");
+        };
+        out.extend_from_slice(r.take(usize::try_from(len)?)?);
+    }
+}
+
+fn read_tagged(r: &mut ByteReader, tag: u64, depth: usize) -> Result<Value> {
+    let at = r.pos();
+    let is_bytes = r.peek().is_some_and(|b| b >> 5 == 2);
+    if !(matches!(tag, 2 | 3) && is_bytes) {
+        // Date/time strings, epoch times, self-describe and everything else:
+        // the tag adds nothing JSON can carry, so keep the content.
+        return read_value(r, depth + 1);
+    }
+
+    // Unsigned / negative bignum: the payload is a big-endian byte string.
+    let initial = r.u8()?;
+    let arg = argument(r, initial & 0x1f, at)?;
+    let raw = read_chunks(r, arg, 2)?;
+    let magnitude = raw.iter().try_fold(0u128, |acc, &b| {
+        acc.checked_mul(256).map(|acc| acc + u128::from(b))
+    });
+    Ok(match (tag, magnitude) {
+        (2, Some(n)) => {
+            u64::try_from(n).map_or_else(|_| Value::String(n.to_string()), |n| json!(n))
+        }
+        (3, Some(n)) => match i64::try_from(n) {
+            Ok(n) => json!(-1 - n),
+            Err(_) if n < u128::MAX => Value::String(format!("-{}", n + 1)),
+            Err(_) => Value::String(format!("-1 - 0x{}", hex(&raw))),
+        },
+        // Wider than u128: keep the sign the tag carries.
+        (3, None) => Value::String(format!("-1 - 0x{}", hex(&raw))),
+        _ => Value::String(format!("0x{}", hex(&raw))),
+    })
+}
+
+fn read_simple(r: &mut ByteReader, info: u8, at: usize) -> Result<Value> {
+    Ok(match info {
+        20 => Value::Bool(false),
+        21 => Value::Bool(true),
+        22 | 23 => Value::Null,
+        24 => json!({"simple": r.u8()?}),
+        25 => float_value(half_to_f64(r.uint(2)? as u16)),
+        26 => float_value(f64::from(f32::from_bits(r.uint(4)? as u32))),
+        27 => float_value(f64::from_bits(r.uint(8)?)),
+        31 => bail!("unexpected CBOR break at byte {at}"),
+        0..=19 => json!({"simple": info}),
+        _ => bail!("reserved CBOR simple value {info} at byte {at}"),
+    })
+}
+
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = i32::from((bits >> 10) & 0x1f);
+    let frac = f64::from(bits & 0x3ff);
+    sign * match exp {
+        0 => frac * 2f64.powi(-24),
+        31 if frac == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + frac / 1024.0) * 2f64.powi(exp - 15),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_rfc_examples() {
+        // {"a": 1, "b": [2, 3]}
+        let bytes = [0xa2, 0x61, b'a', 0x01, 0x61, b'b', 0x82, 0x02, 0x03];
+        assert_eq!(decode(&bytes).unwrap(), json!({"a": 1, "b": [2, 3]}));
+        assert_eq!(decode(&[0x38, 0x63]).unwrap(), json!(-100));
+        assert_eq!(decode(&[0xf9, 0x3c, 0x00]).unwrap(), json!(1.0));
+        assert_eq!(decode(&[0xf9, 0xc4, 0x00]).unwrap(), json!(-4.0));
+        assert_eq!(decode(&[0xf6]).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn indefinite_lengths_and_tags() {
+        // 55799(self-describe) {_ "a": [_ 1, 2], "s": (_ "ab" "c")}
+        let bytes = [
+            0xd9, 0xd9, 0xf7, 0xbf, 0x61, b'a', 0x9f, 0x01, 0x02, 0xff, 0x61, b's', 0x7f, 0x62,
+            b'a', b'b', 0x61, b'c', 0xff, 0xff,
+        ];
+        assert_eq!(decode(&bytes).unwrap(), json!({"a": [1, 2], "s": "abc"}));
+        // 2(h'010000000000000000') = 2^64
+        let big = [0xc2, 0x49, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(decode(&big).unwrap(), json!("18446744073709551616"));
+    }
+
+    #[test]
+    fn wide_bignums_keep_their_sign() {
+        // 17-byte payloads do not fit a u128.
+        let mut payload = vec![1];
+        payload.extend([0; 16]);
+        let wide = |tag: u8| [&[tag, 0x51][..], &payload].concat();
+        let digits = format!("01{}", "00".repeat(16));
+        assert_eq!(decode(&wide(0xc2)).unwrap(), json!(format!("0x{digits}")));
+        assert_eq!(
+            decode(&wide(0xc3)).unwrap(),
+            json!(format!("-1 - 0x{digits}"))
+        );
+    }
+
+    #[test]
+    fn malformed_input_fails() {
+        assert!(decode(&[0x82, 0x01]).is_err());
+        assert!(decode(&[0x01, 0x01]).is_err());
+        assert!(decode(&[0xff]).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! CSV/TSV reading and writing (RFC 4180 quoting, no external crate).
+//!
+//! With a header row every record becomes an object keyed by column name;
+//! without one, records are plain arrays. Cells always stay strings —
+//! CSV carries no types, and guessing would mangle zip codes and IDs.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+#[cfg(feature = "csv")]
+use super::ParseOptions;
+
+/// Accepts a single character, or `\t` / `tab` for tab-separated input.
+pub(crate) fn parse_delimiter(raw: &str) -> Result<char, String> {
+    match raw {
+        "\\t" | "tab" | "TAB" => return Ok('\t'),
+        _ => {}
+    }
+    let mut chars = raw.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c != '"' && c != '\n' && c != '\r' => Ok(c),
+        _ => Err(format!(
+            "expected a single delimiter character (or `\\t`), got `{raw}`"
+        )),
+    }
+}
+
+/// Delimiter used when `--delimiter` is absent: tab for `.tsv`, else comma.
+#[cfg(feature = "csv")]
+pub(crate) fn default_delimiter(file_path: &str) -> char {
+    let is_tsv = std::path::Path::new(file_path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("tsv"));
+    if is_tsv {
+        '\t'
+    } else {
+        ','
+    }
+}
+
+#[cfg(feature = "csv")]
+pub(crate) fn parse(file_path: &str, content: &str, opts: &ParseOptions) -> Result<Value> {
+    let delimiter = opts
+        .delimiter
+        .unwrap_or_else(|| default_delimiter(file_path));
+    let mut records = read_records(content, delimiter)?.into_iter();
+
+    if opts.no_headers {
+        let rows = records
+            .map(|(_, cells)| Value::Array(cells.into_iter().map(Value::String).collect()))
+            .collect();
+        return Ok(Value::Array(rows));
+    }
+
+    let Some((header_line, header)) = records.next() else {
+        return Ok(Value::Array(Vec::new()));
+    };
+    for (i, name) in header.iter().enumerate() {
+        if header[..i].contains(name) {
+            bail!(
+                "{file_path}:{header_line}: column {name:?} appears more than once in the header; \
+                 rename it or pass --no-headers"
+            );
+        }
+    }
+    let mut rows = Vec::new();
+    for (line, cells) in records {
+        if cells.len() != header.len() {
+            bail!(
+                "{file_path}:{line}: record has {} fields but the header has {}",
+                cells.len(),
+                header.len()
+            );
+        }
+        let row: serde_json::Map<String, Value> = header
+            .iter()
+            .cloned()
+            .zip(cells.into_iter().map(Value::String))
+            .collect();
+        rows.push(Value::Object(row));
+    }
+    Ok(Value::Array(rows))
+}
+
+/// Split `content` into records, each tagged with the line it starts on.
+/// Blank lines are skipped; quoted fields may span lines.
+#[cfg(feature = "csv")]
+fn read_records(content: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>> {
+    let mut records = Vec::new();
+    let mut chars = content
+        .strip_prefix('\u{feff}')
+        .unwrap_or(content)
+        .chars()
+        .peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start_line = line;
+        let mut cells = Vec::new();
+        let mut cell = String::new();
+        let mut quoted = false;
+        let mut was_quoted = false;
+
+        loop {
+            let Some(c) = chars.next() else {
+                if quoted {
+                    bail!("line {start_line}: unterminated quoted field");
+                }
+                break;
+            };
+            if quoted {
+                match c {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    }
+                    '"' => quoted = false,
+                    '\n' => {
+                        line += 1;
+                        cell.push(c);
+                    }
+                    _ => cell.push(c),
+                }
+                continue;
+            }
+            match c {
+                '"' if cell.is_empty() && !was_quoted => {
+                    quoted = true;
+                    was_quoted = true;
+                }
+                '\r' if chars.peek() == Some(&'\n') => {}
+                '\n' => {
+                    line += 1;
+                    break;
+                }
+                c if c == delimiter => {
+                    cells.push(std::mem::take(&mut cell));
+                    was_quoted = false;
+                }
+                _ => cell.push(c),
+            }
+        }
+        cells.push(cell);
+
+        let blank = cells.len() == 1 && cells[0].is_empty() && !was_quoted;
+        if !blank {
+            records.push((start_line, cells));
+        }
+    }
+    Ok(records)
+}
+
+/// Render an array of objects (header = union of keys in first-seen order)
+/// or an array of arrays as `delimiter`-separated text.
+pub(crate) fn render(value: &Value, delimiter: char, order: &[String]) -> Result<String> {
+    let Value::Array(rows) = value else {
+        bail!("CSV output needs an array of objects or arrays at the top level");
+    };
+
+    let mut out = String::new();
+    if rows.iter().all(Value::is_object) {
+        let header = columns(rows, order);
+        write_record(&mut out, delimiter, header.iter().map(|k| k.to_string()));
+        for row in rows.iter().filter_map(Value::as_object) {
+            write_record(
+                &mut out,
+                delimiter,
+                header
+                    .iter()
+                    .map(|k| row.get(*k).map(cell_text).unwrap_or_default()),
+            );
+        }
+    } else if rows.iter().all(Value::is_array) {
+        for row in rows.iter().filter_map(Value::as_array) {
+            write_record(&mut out, delimiter, row.iter().map(cell_text));
+        }
+    } else {
+        bail!("CSV output needs every row to be an object, or every row to be an array");
+    }
+    Ok(out)
+}
+
+/// Every key of an array of objects: those in `order` that some row has,
+/// in that order, then the rest in first-seen order.
+pub(crate) fn columns<'a>(rows: &'a [Value], order: &'a [String]) -> Vec<&'a str> {
+    let mut header: Vec<&str> = order
+        .iter()
+        .map(String::as_str)
+        .filter(|key| rows.iter().any(|row| row.get(key).is_some()))
+        .collect();
+    for row in rows.iter().filter_map(Value::as_object) {
+        for key in row.keys() {
+            if !header.contains(&key.as_str()) {
+                header.push(key);
+            }
+        }
+    }
+    header
+}
+
+/// A cell's text: strings bare, null empty, nested values as compact JSON.
+pub(crate) fn cell_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        Value::Bool(_) | Value::Number(_) => value.to_string(),
+        _ => serde_json::to_string(value).unwrap_or_default(),
+    }
+}
+
+fn write_record(out: &mut String, delimiter: char, cells: impl Iterator<Item = String>) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+        if cell.contains([delimiter, '"', '\n', '\r']) {
+            out.push('"');
+            out.push_str(&cell.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(&cell);
+        }
+    }
+    out.push('\n');
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn header_row_becomes_object_keys() {
+        let text =
+            "name,email\nada,ada@example.com\r\n\"Hopper, Grace\",\"g\"\"h\"\"@example.com\"\n";
+        let value = parse("users.csv", text, &ParseOptions::default()).unwrap();
+        assert_eq!(
+            value,
+            json!([
+                {"name": "ada", "email": "ada@example.com"},
+                {"name": "Hopper, Grace", "email": "g\"h\"@example.com"}
+            ])
+        );
+    }
+
+    #[test]
+    fn tsv_extension_and_no_headers() {
+        let opts = ParseOptions {
+            no_headers: true,
+            ..ParseOptions::default()
+        };
+        let value = parse("hosts.tsv", "a\tb\n\n\"multi\nline\"\tc\n", &opts).unwrap();
+        assert_eq!(value, json!([["a", "b"], ["multi\nline", "c"]]));
+    }
+
+    #[test]
+    fn ragged_rows_report_their_line() {
+        let err = parse("x.csv", "a,b\n1,2\n3\n", &ParseOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("x.csv:3"), "{err}");
+    }
+
+    #[test]
+    fn duplicate_header_columns_are_rejected() {
+        let text = "\nid,name,id\n1,a,2\n";
+        let err = parse("x.csv", text, &ParseOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("x.csv:2"), "{err}");
+        assert!(err.to_string().contains("\"id\""), "{err}");
+    }
+
+    #[test]
+    fn render_round_trips_objects() {
+        let value = json!([{"id": "1", "note": "a, b"}, {"id": "2", "note": "say \"hi\""}]);
+        let text = render(&value, ',', &[]).unwrap();
+        assert_eq!(
+            parse("out.csv", &text, &ParseOptions::default()).unwrap(),
+            value
+        );
+        let text = render(&value, '\t', &[]).unwrap();
+        assert_eq!(text, "id\tnote\n1\ta, b\n2\t\"say \"\"hi\"\"\"\n");
+        assert_eq!(
+            parse("out.tsv", &text, &ParseOptions::default()).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn delimiter_accepts_tab_escape() {
+        assert_eq!(parse_delimiter("\\t"), Ok('\t'));
+        assert_eq!(parse_delimiter(";"), Ok(';'));
+        assert!(parse_delimiter(";;").is_err());
+    }
+}
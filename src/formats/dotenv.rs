@@ -0,0 +1,262 @@
+//! `--strict-env`: a dotenv parser with the quoting rules docker compose and
+//! python-dotenv share, for files the line-oriented default misreads.
+//!
+//! - `export KEY=value` is the same as `KEY=value`.
+//! - Unquoted values run to the end of the line; ` #` starts a comment.
+//! - Single quotes are literal and may span lines.
+//! - Double quotes may span lines and understand `\n`, `\r`, `\t`, `\"`,
+//!   `\\` and `\$`; other backslashes are kept as written.
+//!
+//! Values are strings. With `--infer-types`, unquoted values are typed as
+//! the default parser types them (numbers, booleans); quoted values stay
+//! strings either way. Errors carry the line and column, like the JSON5
+//! parser's.
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+pub(crate) fn parse(content: &str, infer_types: bool) -> Result<Value> {
+    let mut parser = Parser {
+        chars: content.chars().collect(),
+        pos: 0,
+    };
+    let mut map = Map::new();
+    while let Some((key, value)) = parser.entry(infer_types)? {
+        map.insert(key, value);
+    }
+    Ok(Value::Object(map))
+}
+
+/// `--infer-types`: a number or boolean when the text reads as one.
+pub(crate) fn typed(value: &str) -> Value {
+    if let Ok(n) = value.parse::<i64>() {
+        Value::Number(n.into())
+    } else if let Ok(n) = value.parse::<f64>() {
+        Value::Number(serde_json::Number::from_f64(n).unwrap_or_else(|| 0.into()))
+    } else if value.eq_ignore_ascii_case("true") {
+        Value::Bool(true)
+    } else if value.eq_ignore_ascii_case("false") {
+        Value::Bool(false)
+    } else {
+        Value::String(value.to_string())
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn location_at(&self, pos: usize) -> String {
+        let consumed = &self.chars[..pos.min(self.chars.len())];
+        let line = consumed.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = consumed.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        format!("line {line}, column {column}")
+    }
+
+    fn location(&self) -> String {
+        self.location_at(self.pos)
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.pos += 1;
+        }
+    }
+
+    /// The next `KEY=value`, skipping blank and comment lines.
+    fn entry(&mut self, infer_types: bool) -> Result<Option<(String, Value)>> {
+        loop {
+            match self.peek() {
+                None => return Ok(None),
+                Some(' ' | '\t' | '\r' | '\n') => self.pos += 1,
+                Some('#') => self.skip_line(),
+                Some(_) => break,
+            }
+        }
+        let key = self.key()?;
+        self.skip_blanks();
+        match self.peek() {
+            Some('=') => self.pos += 1,
+            Some(c) => bail!(
+                "{}: expected `=` after `{key}`, found `{c}`",
+                self.location()
+            ),
+            None => bail!("{}: expected `=` after `{key}`", self.location()),
+        }
+        self.skip_blanks();
+        let value = match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                let text = self.quoted(quote)?;
+                self.end_of_quoted()?;
+                Value::String(text)
+            }
+            _ if infer_types => typed(&self.unquoted()),
+            _ => Value::String(self.unquoted()),
+        };
+        Ok(Some((key, value)))
+    }
+
+    fn key(&mut self) -> Result<String> {
+        let start = self.pos;
+        let word = self.word();
+        if word == "export" && matches!(self.peek(), Some(' ' | '\t')) {
+            self.skip_blanks();
+            return self.key();
+        }
+        let valid = word
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && word
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if !valid {
+            bail!(
+                "{}: invalid variable name `{word}`",
+                self.location_at(start)
+            );
+        }
+        Ok(word)
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some('=' | ' ' | '\t' | '\r' | '\n')) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<String> {
+        let start = self.location();
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                bail!("{start}: unterminated {quote}-quoted value");
+            };
+            self.pos += 1;
+            match c {
+                c if c == quote => return Ok(text),
+                '\\' if quote == '"' => {
+                    let Some(escaped) = self.peek() else { continue };
+                    self.pos += 1;
+                    match escaped {
+                        'n' => text.push('\n'),
+                        'r' => text.push('\r'),
+                        't' => text.push('\t'),
+                        '"' | '\\' | '$' => text.push(escaped),
+                        other => {
+                            text.push('\\');
+                            text.push(other);
+                        }
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+    }
+
+    /// Only blanks or a comment may follow a closing quote.
+    fn end_of_quoted(&mut self) -> Result<()> {
+        self.skip_blanks();
+        match self.peek() {
+            None | Some('\r' | '\n') => Ok(()),
+            Some('#') => {
+                self.skip_line();
+                Ok(())
+            }
+            Some(c) => bail!("{}: unexpected `{c}` after closing quote", self.location()),
+        }
+    }
+
+    fn unquoted(&mut self) -> String {
+        let start = self.pos;
+        self.skip_line();
+        let line: String = self.chars[start..self.pos].iter().collect();
+        let mut end = line.len();
+        let mut prev_blank = true;
+        for (i, c) in line.char_indices() {
+            if c == '#' && prev_blank {
+                end = i;
+                break;
+            }
+            prev_blank = c == ' ' || c == '\t';
+        }
+        line[..end].trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn quoting_exports_and_comments() {
+        let text = "# app\n\
+                    export PORT=8080\n\
+                    NAME=api # inline comment\n\
+                    URL=http://host/#anchor\n\
+                    KEY='literal \\n $HOME'\n\
+                    MSG=\"say \\\"hi\\\"\\nbye\" # trailing\n\
+                    PEM=\"-----BEGIN-----\n\
+                    abc\n\
+                    -----END-----\"\n\
+                    QUOTED_NUM=\"42\"\n\
+                    EMPTY=\n";
+        assert_eq!(
+            parse(text, true).unwrap(),
+            json!({
+                "PORT": 8080,
+                "NAME": "api",
+                "URL": "http://host/#anchor",
+                "KEY": "literal \\n $HOME",
+                "MSG": "say \"hi\"\nbye",
+                "PEM": "-----BEGIN-----\nabc\n-----END-----",
+                "QUOTED_NUM": "42",
+                "EMPTY": ""
+            })
+        );
+        assert_eq!(
+            parse("VERSION=1.10\nPORT=8080\n", false).unwrap(),
+            json!({"VERSION": "1.10", "PORT": "8080"})
+        );
+    }
+
+    #[test]
+    fn errors_carry_line_and_column() {
+        let err = parse("A=1\nB=\"open\nC=2\n", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 3: unterminated \"-quoted value"
+        );
+        let err = parse("A=1\n  bad key=1\n", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 7: expected `=` after `bad`, found `k`"
+        );
+        let err = parse("A='x' y\n", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1, column 7: unexpected `y` after closing quote"
+        );
+        let err = parse("1A=x\n", false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 1, column 1: invalid variable name `1A`"
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! JSON5 parser, which also covers JSONC (VS Code settings, tsconfig):
+//! `//` and `/* */` comments, trailing commas, unquoted keys, single-quoted
+//! strings, hex numbers, leading/trailing decimal points and `+` signs.
+//! `Infinity`/`NaN` have no JSON form and become `null`.
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Number, Value};
+
+use super::{float_value, MAX_DEPTH};
+
+pub(crate) fn parse(content: &str) -> Result<Value> {
+    Parser::new(content, None).document()
+}
+
+/// Where the value at `path` is in `content`, as the 1-based line and
+/// column of its key, or of the value itself for array items and the root.
+/// Of duplicate keys the last counts, as it does in `parse`.
+pub(crate) fn locate(content: &str, path: &[String]) -> Result<Option<(usize, usize)>> {
+    let mut parser = Parser::new(content, Some(path.to_vec()));
+    parser.document()?;
+    Ok(parser.found.map(|pos| parser.line_column(pos)))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    /// With `locate`: the path wanted, the path of the value being read, and
+    /// where the wanted value was last seen.
+    target: Option<Vec<String>>,
+    trail: Vec<String>,
+    found: Option<usize>,
+}
+
+impl Parser {
+    fn new(content: &str, target: Option<Vec<String>>) -> Parser {
+        Parser {
+            chars: content.chars().collect(),
+            pos: 0,
+            target,
+            trail: Vec::new(),
+            found: None,
+        }
+    }
+
+    fn document(&mut self) -> Result<Value> {
+        self.skip_trivia()?;
+        if self.target.as_ref().is_some_and(Vec::is_empty) {
+            self.found = Some(self.pos);
+        }
+        let value = self.value(0)?;
+        self.skip_trivia()?;
+        if self.pos < self.chars.len() {
+            bail!("{}: unexpected trailing content", self.location());
+        }
+        Ok(value)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn line_column(&self, pos: usize) -> (usize, usize) {
+        let consumed = &self.chars[..pos.min(self.chars.len())];
+        let line = consumed.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = consumed.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        (line, column)
+    }
+
+    fn location(&self) -> String {
+        let (line, column) = self.line_column(self.pos);
+        format!("line {line}, column {column}")
+    }
+
+    /// Read a child value at `segment` of the current path, written from
+    /// `start` on.
+    fn child(
+        &mut self,
+        segment: impl FnOnce() -> String,
+        start: usize,
+        depth: usize,
+    ) -> Result<Value> {
+        if self.target.is_none() {
+            return self.value(depth);
+        }
+        self.trail.push(segment());
+        if self.target.as_ref() == Some(&self.trail) {
+            self.found = Some(start);
+        }
+        let value = self.value(depth);
+        self.trail.pop();
+        value
+    }
+
+    fn expect(&mut self, want: char) -> Result<()> {
+        match self.peek() {
+            Some(c) if c == want => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => bail!("{}: expected `{want}`, found `{c}`", self.location()),
+            None => bail!("{}: expected `{want}`, found end of input", self.location()),
+        }
+    }
+
+    /// Whitespace (including the extra JSON5 line terminators) and comments.
+    fn skip_trivia(&mut self) -> Result<()> {
+        loop {
+            match (self.peek(), self.chars.get(self.pos + 1)) {
+                (Some(c), _) if c.is_whitespace() || c == '\u{feff}' => self.pos += 1,
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.location();
+                    self.pos += 2;
+                    loop {
+                        match (self.peek(), self.chars.get(self.pos + 1)) {
+                            (Some('*'), Some('/')) => {
+                                self.pos += 2;
+                                break;
+                            }
+                            (Some(_), _) => self.pos += 1,
+                            (None, _) => bail!("{start}: unterminated block comment"),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("{}: nesting deeper than {MAX_DEPTH}", self.location());
+        }
+        match self.peek() {
+            Some('{') => self.object(depth),
+            Some('[') => self.array(depth),
+            Some(quote @ ('"' | '\'')) => Ok(Value::String(self.string(quote)?)),
+            Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-' | '.') => self.number(),
+            Some(c) if is_ident_start(c) => {
+                let start = self.location();
+                match self.identifier().as_str() {
+                    "null" => Ok(Value::Null),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "Infinity" | "NaN" => Ok(Value::Null),
+                    other => bail!("{start}: unexpected identifier `{other}`"),
+                }
+            }
+            Some(c) => bail!("{}: unexpected character `{c}`", self.location()),
+            None => bail!("{}: unexpected end of input", self.location()),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value> {
+        self.expect('{')?;
+        let mut map = Map::new();
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Value::Object(map));
+            }
+            let start = self.pos;
+            let key = match self.peek() {
+                Some(quote @ ('"' | '\'')) => self.string(quote)?,
+                Some(c) if is_ident_start(c) => self.identifier(),
+                Some(c) => bail!("{}: expected an object key, found `{c}`", self.location()),
+                None => bail!("{}: unterminated object", self.location()),
+            };
+            self.skip_trivia()?;
+            self.expect(':')?;
+            self.skip_trivia()?;
+            let value = self.child(|| key.clone(), start, depth + 1)?;
+            map.insert(key, value);
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                Some(c) => bail!("{}: expected `,` or `}}`, found `{c}`", self.location()),
+                None => bail!("{}: unterminated object", self.location()),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            let index = items.len();
+            let start = self.pos;
+            items.push(self.child(|| index.to_string(), start, depth + 1)?);
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                Some(c) => bail!("{}: expected `,` or `]`, found `{c}`", self.location()),
+                None => bail!("{}: unterminated array", self.location()),
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(is_ident_continue) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn string(&mut self, quote: char) -> Result<String> {
+        let start = self.location();
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                bail!("{start}: unterminated string");
+            };
+            self.pos += 1;
+            match c {
+                c if c == quote => return Ok(out),
+                '\n' => bail!("{start}: unescaped newline in string"),
+                '\\' => self.escape(&mut out)?,
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self, out: &mut String) -> Result<()> {
+        let Some(c) = self.peek() else {
+            bail!("{}: unterminated escape", self.location());
+        };
+        self.pos += 1;
+        match c {
+            'b' => out.push('\u{8}'),
+            'f' => out.push('\u{c}'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            'v' => out.push('\u{b}'),
+            '0' if !self.peek().is_some_and(|c| c.is_ascii_digit()) => out.push('\0'),
+            'x' => {
+                let code = self.hex_digits(2)?;
+                out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            'u' => {
+                let mut code = self.hex_digits(4)?;
+                // A high surrogate followed by `\uDCxx` encodes one astral char.
+                if (0xd800..0xdc00).contains(&code)
+                    && self.chars.get(self.pos) == Some(&'\\')
+                    && self.chars.get(self.pos + 1) == Some(&'u')
+                {
+                    self.pos += 2;
+                    let low = self.hex_digits(4)?;
+                    code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                }
+                out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            // Line continuation: backslash + line terminator contributes nothing.
+            '\r' => {
+                if self.peek() == Some('\n') {
+                    self.pos += 1;
+                }
+            }
+            '\n' | '\u{2028}' | '\u{2029}' => {}
+            c if c.is_ascii_digit() => {
+                bail!("{}: octal escapes are not allowed", self.location())
+            }
+            c => out.push(c),
+        }
+        Ok(())
+    }
+
+    fn hex_digits(&mut self, n: usize) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..n {
+            let Some(digit) = self.peek().and_then(|c| c.to_digit(16)) else {
+                bail!("{}: expected {n} hex digits", self.location());
+            };
+            code = code * 16 + digit;
+            self.pos += 1;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let start = self.location();
+        let negative = match self.peek() {
+            Some('-') => {
+                self.pos += 1;
+                true
+            }
+            Some('+') => {
+                self.pos += 1;
+                false
+            }
+            _ => false,
+        };
+
+        if self.peek().is_some_and(is_ident_start) {
+            return match self.identifier().as_str() {
+                "Infinity" | "NaN" => Ok(Value::Null),
+                other => bail!("{start}: invalid number `{other}`"),
+            };
+        }
+
+        let lead_zero = self.peek() == Some('0');
+        if lead_zero && matches!(self.chars.get(self.pos + 1), Some('x' | 'X')) {
+            self.pos += 2;
+            let digits = self.take_while(|c| c.is_ascii_hexdigit());
+            let Ok(magnitude) = u64::from_str_radix(&digits, 16) else {
+                bail!("{start}: invalid hexadecimal number");
+            };
+            return Ok(signed(negative, magnitude));
+        }
+
+        let text =
+            self.take_while(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+        let is_integer = !text.contains(['.', 'e', 'E']);
+        // JSON5 keeps JSON's ban on leading zeros such as `01`.
+        if lead_zero && is_integer && text.len() > 1 {
+            bail!("{start}: invalid number `{text}`");
+        }
+        if is_integer {
+            if let Ok(magnitude) = text.parse::<u64>() {
+                return Ok(signed(negative, magnitude));
+            }
+        }
+        // Rust's float parser accepts `.5` and `5.`, but not `5.e3`.
+        let normalised = text.replace(".e", ".0e").replace(".E", ".0E");
+        match normalised.parse::<f64>() {
+            Ok(f) if !text.is_empty() => Ok(float_value(if negative { -f } else { f })),
+            _ => bail!("{start}: invalid number `{text}`"),
+        }
+    }
+
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&keep) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+}
+
+fn signed(negative: bool, magnitude: u64) -> Value {
+    match (negative, i64::try_from(magnitude)) {
+        (false, _) => Value::Number(magnitude.into()),
+        (true, Ok(n)) => Value::Number((-n).into()),
+        (true, Err(_)) => Number::from_f64(-(magnitude as f64)).map_or(Value::Null, Value::Number),
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::error_position;
+    use serde_json::json;
+
+    #[test]
+    fn parses_jsonc_settings() {
+        let text = r#"{
+            // editor
+            "editor.fontSize": 14, /* px */
+            "files.exclude": { "**/.git": true, },
+            "list": [1, 2, 3,],
+        }"#;
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({"editor.fontSize": 14, "files.exclude": {"**/.git": true}, "list": [1, 2, 3]})
+        );
+    }
+
+    #[test]
+    fn parses_json5_extensions() {
+        let text = "{unquoted: 'single \\'quoted\\'', hex: 0xFF, half: .5, trailing: 5., \
+                    plus: +1, neg: -0x10, inf: Infinity, line: 'a\\\nb', $id: null}";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({"unquoted": "single 'quoted'", "hex": 255, "half": 0.5, "trailing": 5.0,
+                   "plus": 1, "neg": -16, "inf": null, "line": "ab", "$id": null})
+        );
+    }
+
+    #[test]
+    fn errors_carry_line_and_column() {
+        let err = parse("{\n  a: 1,\n  b: ?\n}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 6: unexpected character `?`"
+        );
+        assert_eq!(error_position(&err), Some((3, 6)));
+        assert!(parse("/* open").is_err());
+        assert!(parse("[1] 2").is_err());
+        assert!(parse("[01]").is_err());
+    }
+
+    #[test]
+    fn locate_finds_where_values_start() {
+        let text = "{\n  // db\n  db: {host: 'a', ports: [1,\n    2]},\n  db: {host: \"b\"}\n}";
+        let at = |path: &[&str]| {
+            let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+            locate(text, &path).unwrap()
+        };
+        assert_eq!(at(&["db", "host"]), Some((5, 8)));
+        assert_eq!(at(&["db", "ports", "1"]), Some((4, 5)));
+        assert_eq!(at(&[]), Some((1, 1)));
+        assert_eq!(at(&["nope"]), None);
+    }
+}
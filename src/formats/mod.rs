@@ -0,0 +1,165 @@
+//! Decoders for input formats beyond the serde-backed JSON/YAML/TOML trio
+//! and the `.env` parser that live in `main.rs`. Each decoder produces the
+//! same normalised `Value` the resolver walks.
+//!
+//! Every decoder but `.env` is a Cargo feature of the same name (`csv`
+//! covers TSV), all on through the default `formats` feature; see
+//! `Format::compiled`. The CSV writer stays, as table output shares it.
+
+#[cfg(feature = "cbor")]
+pub(crate) mod cbor;
+pub(crate) mod csv;
+pub(crate) mod dotenv;
+#[cfg(feature = "json5")]
+pub(crate) mod json5;
+#[cfg(feature = "msgpack")]
+pub(crate) mod msgpack;
+#[cfg(feature = "plist")]
+pub(crate) mod plist;
+#[cfg(feature = "properties")]
+pub(crate) mod properties;
+
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "plist"))]
+use anyhow::{anyhow, Result};
+use serde_json::{Number, Value};
+
+/// Knobs that only some decoders look at. `Default` matches what
+/// `parse_content` has always done.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ParseOptions {
+    /// CSV field separator; `None` means tab for `.tsv` files, comma otherwise.
+    #[cfg_attr(not(feature = "csv"), allow(dead_code))]
+    pub(crate) delimiter: Option<char>,
+    /// Treat the first CSV row as data: rows become arrays, not objects.
+    #[cfg_attr(not(feature = "csv"), allow(dead_code))]
+    pub(crate) no_headers: bool,
+    /// Parse `.env` input with `formats::dotenv` (quotes, escapes,
+    /// multi-line values) instead of the line-oriented default.
+    pub(crate) strict_env: bool,
+    /// Type unquoted `.env` values as numbers and booleans. Off, every
+    /// value stays the string it was written as (`VERSION=1.10`).
+    pub(crate) infer_types: bool,
+    /// Apply YAML `<<` merge keys (`--yaml-anchors expand`). Off, `<<` is
+    /// an ordinary key holding the mapping it names.
+    #[cfg_attr(not(feature = "yaml"), allow(dead_code))]
+    pub(crate) merge_keys: bool,
+    /// Type plain YAML scalars by the YAML 1.2 JSON schema (`--yaml-1.2`)
+    /// rather than the core schema serde_yaml uses.
+    #[cfg_attr(not(feature = "yaml"), allow(dead_code))]
+    pub(crate) yaml_1_2: bool,
+    /// Read every key under an etcd or Consul prefix as nested objects
+    /// (`--recurse`) instead of one key's value.
+    pub(crate) recurse: bool,
+}
+
+/// The 1-based line and column a JSON5, `--strict-env`, plist or properties
+/// error starts with (`line 3, column 6: ...`).
+pub(crate) fn error_position(err: &anyhow::Error) -> Option<(usize, usize)> {
+    let message = err.to_string();
+    let (location, _) = message.split_once(": ")?;
+    let (line, column) = location.strip_prefix("line ")?.split_once(", column ")?;
+    Some((line.parse().ok()?, column.parse().ok()?))
+}
+
+/// Big-endian cursor shared by the binary decoders. Errors carry the byte
+/// offset so a corrupt blob can be inspected with `xxd`.
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "plist"))]
+pub(crate) struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "plist"))]
+// Each decoder uses its own subset of the methods.
+#[cfg_attr(
+    not(all(feature = "msgpack", feature = "cbor", feature = "plist")),
+    allow(dead_code)
+)]
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    pub(crate) fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or_else(|| {
+                anyhow!(
+                    "unexpected end of input at byte {} (needed {n} more)",
+                    self.pos
+                )
+            })?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Unsigned big-endian integer of `n` (1, 2, 4 or 8) bytes.
+    pub(crate) fn uint(&mut self, n: usize) -> Result<u64> {
+        Ok(self
+            .take(n)?
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | u64::from(b)))
+    }
+
+    pub(crate) fn len(&mut self, n: usize) -> Result<usize> {
+        let len = self.uint(n)?;
+        usize::try_from(len).map_err(|_| anyhow!("length {len} too large"))
+    }
+
+    pub(crate) fn utf8(&mut self, n: usize) -> Result<String> {
+        let at = self.pos;
+        let raw = self.take(n)?;
+        String::from_utf8(raw.to_vec()).map_err(|_| anyhow!("invalid UTF-8 in string at byte {at}"))
+    }
+}
+
+/// Nesting limit for the binary decoders, so hostile input cannot blow the stack.
+#[cfg(any(
+    feature = "json5",
+    feature = "msgpack",
+    feature = "cbor",
+    feature = "plist"
+))]
+pub(crate) const MAX_DEPTH: usize = 512;
+
+/// JSON has no float NaN/inf; map them to null like `serde_json::json!` does.
+pub(crate) fn float_value(f: f64) -> Value {
+    Number::from_f64(f).map_or(Value::Null, Value::Number)
+}
+
+/// Binary payloads (msgpack `bin`, CBOR byte strings) surface as base64 text,
+/// which pairs with `-d` to get the raw bytes back.
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "plist"))]
+pub(crate) fn bytes_value(raw: &[u8]) -> Value {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    Value::String(STANDARD.encode(raw))
+}
+
+/// JSON object keys must be strings: text keys pass through, anything else
+/// uses its compact JSON rendering (`1`, `true`, `[1,2]`).
+#[cfg(any(feature = "msgpack", feature = "cbor", feature = "plist"))]
+pub(crate) fn key_string(key: Value) -> String {
+    match key {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
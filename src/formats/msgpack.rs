@@ -0,0 +1,162 @@
+//! MessagePack decoder (spec 2.0, including the timestamp extension).
+//!
+//! `bin` payloads become base64 strings, other extension types become
+//! `{"ext": <type>, "data": "<base64>"}`, and non-string map keys are
+//! stringified so the result is always valid JSON.
+
+use anyhow::{bail, Result};
+use chrono::DateTime;
+use serde_json::{json, Map, Value};
+
+use super::{bytes_value, float_value, key_string, ByteReader, MAX_DEPTH};
+
+/// Decode exactly one MessagePack value; trailing bytes are an error.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Value> {
+    let mut reader = ByteReader::new(bytes);
+    let value = read_value(&mut reader, 0)?;
+    if !reader.is_empty() {
+        bail!(
+            "trailing data after MessagePack value at byte {}",
+            reader.pos()
+        );
+    }
+    Ok(value)
+}
+
+fn read_value(r: &mut ByteReader, depth: usize) -> Result<Value> {
+    if depth > MAX_DEPTH {
+        bail!(
+            "MessagePack nesting deeper than {MAX_DEPTH} at byte {}",
+            r.pos()
+        );
+    }
+    let at = r.pos();
+    let marker = r.u8()?;
+    let value = match marker {
+        0x00..=0x7f => json!(marker),
+        0x80..=0x8f => read_map(r, usize::from(marker & 0x0f), depth)?,
+        0x90..=0x9f => read_array(r, usize::from(marker & 0x0f), depth)?,
+        0xa0..=0xbf => Value::String(r.utf8(usize::from(marker & 0x1f))?),
+        0xc0 => Value::Null,
+        0xc2 => Value::Bool(false),
+        0xc3 => Value::Bool(true),
+        0xc4..=0xc6 => {
+            let len = r.len(1 << (marker - 0xc4))?;
+            bytes_value(r.take(len)?)
+        }
+        0xc7..=0xc9 => {
+            let len = r.len(1 << (marker - 0xc7))?;
+            read_ext(r, len)?
+        }
+        0xca => float_value(f64::from(f32::from_bits(r.uint(4)? as u32))),
+        0xcb => float_value(f64::from_bits(r.uint(8)?)),
+        0xcc..=0xcf => json!(r.uint(1 << (marker - 0xcc))?),
+        0xd0 => json!(r.uint(1)? as u8 as i8),
+        0xd1 => json!(r.uint(2)? as u16 as i16),
+        0xd2 => json!(r.uint(4)? as u32 as i32),
+        0xd3 => json!(r.uint(8)? as i64),
+        0xd4..=0xd8 => read_ext(r, 1 << (marker - 0xd4))?,
+        0xd9..=0xdb => {
+            let len = r.len(1 << (marker - 0xd9))?;
+            Value::String(r.utf8(len)?)
+        }
+        0xdc | 0xdd => {
+            let len = r.len(if marker == 0xdc { 2 } else { 4 })?;
+            read_array(r, len, depth)?
+        }
+        0xde | 0xdf => {
+            let len = r.len(if marker == 0xde { 2 } else { 4 })?;
+            read_map(r, len, depth)?
+        }
+        0xe0..=0xff => json!(marker as i8),
+        0xc1 => bail!("invalid MessagePack marker 0xc1 at byte {at}"),
+    };
+    Ok(value)
+}
+
+fn read_array(r: &mut ByteReader, len: usize, depth: usize) -> Result<Value> {
+    // Cap the pre-allocation: a corrupt length must not reserve gigabytes.
+    let mut items = Vec::with_capacity(len.min(1024));
+    for _ in 0..len {
+        items.push(read_value(r, depth + 1)?);
+    }
+    Ok(Value::Array(items))
+}
+
+fn read_map(r: &mut ByteReader, len: usize, depth: usize) -> Result<Value> {
+    let mut map = Map::new();
+    for _ in 0..len {
+        let key = key_string(read_value(r, depth + 1)?);
+        let value = read_value(r, depth + 1)?;
+        map.insert(key, value);
+    }
+    Ok(Value::Object(map))
+}
+
+/// Extension payload of `len` bytes. Type -1 (timestamp) decodes to an
+/// RFC 3339 string; everything else is kept opaque.
+fn read_ext(r: &mut ByteReader, len: usize) -> Result<Value> {
+    let at = r.pos();
+    let ext_type = r.u8()? as i8;
+    let data = r.take(len)?;
+    if ext_type != -1 {
+        return Ok(json!({"ext": ext_type, "data": bytes_value(data)}));
+    }
+
+    let (secs, nanos) = match data.len() {
+        4 => (i64::from(be(data) as u32), 0),
+        8 => {
+            let raw = be(data);
+            ((raw & 0x3_ffff_ffff) as i64, (raw >> 34) as u32)
+        }
+        12 => (be(&data[4..]) as i64, be(&data[..4]) as u32),
+        n => bail!("MessagePack timestamp at byte {at} has invalid length {n}"),
+    };
+    match DateTime::from_timestamp(secs, nanos) {
+        Some(ts) => Ok(Value::String(ts.to_rfc3339())),
+        None => bail!("MessagePack timestamp at byte {at} is out of range"),
+    }
+}
+
+fn be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_nested_document() {
+        // {"name": "api", "ports": [80, 443], "tls": true, "ratio": 0.5, "off": -3, "x": nil}
+        let bytes = [
+            0x86, 0xa4, b'n', b'a', b'm', b'e', 0xa3, b'a', b'p', b'i', 0xa5, b'p', b'o', b'r',
+            b't', b's', 0x92, 0x50, 0xcd, 0x01, 0xbb, 0xa3, b't', b'l', b's', 0xc3, 0xa5, b'r',
+            b'a', b't', b'i', b'o', 0xcb, 0x3f, 0xe0, 0, 0, 0, 0, 0, 0, 0xa3, b'o', b'f', b'f',
+            0xfd, 0xa1, b'x', 0xc0,
+        ];
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            json!({"name": "api", "ports": [80, 443], "tls": true, "ratio": 0.5, "off": -3, "x": null})
+        );
+    }
+
+    #[test]
+    fn bin_ext_and_int_keys() {
+        // {1: bin[0xff], "t": timestamp32(0)}
+        let bytes = [
+            0x82, 0x01, 0xc4, 0x01, 0xff, 0xa1, b't', 0xd6, 0xff, 0, 0, 0, 0,
+        ];
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            json!({"1": "/w==", "t": "1970-01-01T00:00:00+00:00"})
+        );
+    }
+
+    #[test]
+    fn truncated_and_trailing_input_fail() {
+        assert!(decode(&[0x92, 0x01]).is_err());
+        assert!(decode(&[0x01, 0x02]).is_err());
+        assert!(decode(&[0xc1]).is_err());
+    }
+}
@@ -0,0 +1,580 @@
+//! Apple property lists, XML and binary (`bplist00`).
+//!
+//! Both map onto JSON the way `plutil -convert json` would if it could
+//! carry every type: `<data>` becomes base64 text, `<date>` an RFC 3339
+//! string in UTC, and binary-only UIDs (NSKeyedArchiver) `{"CF$UID": n}`.
+//! XML errors carry the line and column, like the JSON5 parser's; binary
+//! errors carry the byte offset, like the other binary decoders'.
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, SecondsFormat};
+use serde_json::{json, Map, Value};
+
+use super::{bytes_value, float_value, ByteReader, MAX_DEPTH};
+
+/// Magic at the start of every binary plist.
+pub(crate) const BINARY_MAGIC: &[u8] = b"bplist0";
+
+/// Seconds between the Unix epoch and Apple's (2001-01-01T00:00:00Z).
+const APPLE_EPOCH: f64 = 978_307_200.0;
+
+/// True when `content` is an XML document whose root is `<plist>`.
+pub(crate) fn looks_like_xml(content: &str) -> bool {
+    let head = content.trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<!DOCTYPE plist")) && head.contains("<plist")
+}
+
+/// Parse an XML plist; the `<plist>` root's single value is the document.
+pub(crate) fn parse_xml(content: &str) -> Result<Value> {
+    let mut xml = Xml {
+        text: content,
+        pos: 0,
+    };
+    xml.skip_misc()?;
+    let value = match xml.tag()? {
+        Tag::Open("plist") => {
+            xml.skip_misc()?;
+            if xml.at_close("plist") {
+                Value::Null
+            } else {
+                let tag = xml.tag()?;
+                let value = xml.value(tag, 0)?;
+                xml.skip_misc()?;
+                xml.expect_close("plist")?;
+                value
+            }
+        }
+        Tag::Empty("plist") => Value::Null,
+        _ => bail!("{}: expected a <plist> root element", xml.location()),
+    };
+    xml.skip_misc()?;
+    if xml.pos < xml.text.len() {
+        bail!("{}: unexpected content after </plist>", xml.location());
+    }
+    Ok(value)
+}
+
+/// Decode a binary plist.
+pub(crate) fn decode_binary(bytes: &[u8]) -> Result<Value> {
+    if !bytes.starts_with(BINARY_MAGIC) {
+        bail!("not a binary plist (missing bplist00 header)");
+    }
+    if bytes.len() < 8 + 32 {
+        bail!("binary plist too short ({} bytes)", bytes.len());
+    }
+    let mut trailer = ByteReader::new(&bytes[bytes.len() - 32..]);
+    trailer.take(6)?;
+    let offset_size = usize::from(trailer.u8()?);
+    let ref_size = usize::from(trailer.u8()?);
+    let count = trailer.len(8)?;
+    let top = trailer.len(8)?;
+    let table = trailer.len(8)?;
+    if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+        bail!("binary plist trailer has invalid integer sizes ({offset_size}, {ref_size})");
+    }
+    let table_end = count
+        .checked_mul(offset_size)
+        .and_then(|len| len.checked_add(table))
+        .filter(|&end| end <= bytes.len() - 32)
+        .ok_or_else(|| anyhow!("binary plist offset table at byte {table} is out of range"))?;
+    let mut offsets = ByteReader::new(&bytes[table..table_end]);
+    let offsets = (0..count)
+        .map(|_| offsets.len(offset_size))
+        .collect::<Result<Vec<_>>>()?;
+    let plist = Binary {
+        bytes,
+        offsets,
+        ref_size,
+    };
+    plist.object(top, 0)
+}
+
+enum Tag<'a> {
+    Open(&'a str),
+    Close(&'a str),
+    Empty(&'a str),
+}
+
+struct Xml<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Xml<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn location(&self) -> String {
+        let consumed = &self.text[..self.pos];
+        let line = consumed.matches('\n').count() + 1;
+        let column = consumed.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        format!("line {line}, column {column}")
+    }
+
+    /// Whitespace, the XML declaration, the DOCTYPE and comments.
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            let end = if trimmed.starts_with("<?") {
+                "?>"
+            } else if trimmed.starts_with("<!--") {
+                "-->"
+            } else if trimmed.starts_with("<!DOCTYPE") {
+                ">"
+            } else {
+                return Ok(());
+            };
+            match trimmed.find(end) {
+                Some(i) => self.pos += i + end.len(),
+                None => bail!("{}: unterminated markup", self.location()),
+            }
+        }
+    }
+
+    fn at_close(&self, name: &str) -> bool {
+        self.rest()
+            .strip_prefix("</")
+            .and_then(|rest| rest.strip_prefix(name))
+            .is_some_and(|rest| rest.trim_start().starts_with('>'))
+    }
+
+    fn tag(&mut self) -> Result<Tag<'a>> {
+        let start = self.location();
+        let rest = self.rest();
+        if !rest.starts_with('<') {
+            match rest.chars().next() {
+                Some(c) => bail!("{start}: expected an element, found `{c}`"),
+                None => bail!("{start}: expected an element, found end of input"),
+            }
+        }
+        let end = rest
+            .find('>')
+            .ok_or_else(|| anyhow!("{start}: unterminated tag"))?;
+        let inner = &rest[1..end];
+        self.pos += end + 1;
+        if let Some(name) = inner.strip_prefix('/') {
+            return Ok(Tag::Close(name.trim()));
+        }
+        let (inner, empty) = match inner.strip_suffix('/') {
+            Some(inner) => (inner, true),
+            None => (inner, false),
+        };
+        // Attributes (`<plist version="1.0">`) carry nothing we need.
+        let name = inner.split_whitespace().next().unwrap_or("");
+        if name.is_empty() {
+            bail!("{start}: empty tag");
+        }
+        Ok(if empty {
+            Tag::Empty(name)
+        } else {
+            Tag::Open(name)
+        })
+    }
+
+    fn expect_close(&mut self, name: &str) -> Result<()> {
+        let start = self.location();
+        match self.tag()? {
+            Tag::Close(found) if found == name => Ok(()),
+            Tag::Close(found) => bail!("{start}: expected </{name}>, found </{found}>"),
+            Tag::Open(found) | Tag::Empty(found) => {
+                bail!("{start}: expected </{name}>, found <{found}>")
+            }
+        }
+    }
+
+    /// Character data up to `</name>`, with entities and CDATA decoded.
+    fn text(&mut self, name: &str) -> Result<String> {
+        let mut out = String::new();
+        loop {
+            let rest = self.rest();
+            if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = cdata
+                    .find("]]>")
+                    .ok_or_else(|| anyhow!("{}: unterminated CDATA", self.location()))?;
+                out.push_str(&cdata[..end]);
+                self.pos += "<![CDATA[".len() + end + "]]>".len();
+            } else if rest.starts_with("<!--") {
+                let end = rest
+                    .find("-->")
+                    .ok_or_else(|| anyhow!("{}: unterminated comment", self.location()))?;
+                self.pos += end + "-->".len();
+            } else if rest.starts_with('<') {
+                self.expect_close(name)?;
+                return Ok(out);
+            } else if let Some(entity) = rest.strip_prefix('&') {
+                let end = entity
+                    .find(';')
+                    .filter(|&end| end <= 10)
+                    .ok_or_else(|| anyhow!("{}: unterminated entity", self.location()))?;
+                out.push(self.entity(&entity[..end])?);
+                self.pos += end + 2;
+            } else if rest.is_empty() {
+                bail!(
+                    "{}: expected </{name}>, found end of input",
+                    self.location()
+                );
+            } else {
+                let end = rest.find(['<', '&']).unwrap_or(rest.len());
+                out.push_str(&rest[..end]);
+                self.pos += end;
+            }
+        }
+    }
+
+    fn entity(&self, name: &str) -> Result<char> {
+        let code = match name {
+            "lt" => return Ok('<'),
+            "gt" => return Ok('>'),
+            "amp" => return Ok('&'),
+            "quot" => return Ok('"'),
+            "apos" => return Ok('\''),
+            _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => name.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+            },
+        };
+        code.and_then(char::from_u32)
+            .ok_or_else(|| anyhow!("{}: unknown entity `&{name};`", self.location()))
+    }
+
+    fn value(&mut self, tag: Tag<'a>, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("{}: nesting deeper than {MAX_DEPTH}", self.location());
+        }
+        let start = self.location();
+        let name = match tag {
+            Tag::Empty(name) => {
+                return match name {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "string" | "data" => Ok(json!("")),
+                    "dict" => Ok(json!({})),
+                    "array" => Ok(json!([])),
+                    other => bail!("{start}: <{other}/> is not a plist value"),
+                };
+            }
+            Tag::Close(name) => bail!("{start}: unexpected </{name}>"),
+            Tag::Open(name) => name,
+        };
+        let value = match name {
+            "dict" => self.dict(depth)?,
+            "array" => {
+                let mut items = Vec::new();
+                loop {
+                    self.skip_misc()?;
+                    match self.tag()? {
+                        Tag::Close("array") => break,
+                        tag => items.push(self.value(tag, depth + 1)?),
+                    }
+                }
+                Value::Array(items)
+            }
+            "string" => Value::String(self.text(name)?),
+            "true" | "false" => {
+                self.expect_close(name)?;
+                Value::Bool(name == "true")
+            }
+            "integer" => {
+                let text = self.text(name)?;
+                integer(text.trim()).ok_or_else(|| anyhow!("{start}: invalid integer `{text}`"))?
+            }
+            "real" => {
+                let text = self.text(name)?;
+                let real = match text.trim().to_ascii_lowercase().as_str() {
+                    "nan" => f64::NAN,
+                    "inf" | "+inf" | "infinity" => f64::INFINITY,
+                    "-inf" | "-infinity" => f64::NEG_INFINITY,
+                    other => other
+                        .parse()
+                        .map_err(|_| anyhow!("{start}: invalid real `{text}`"))?,
+                };
+                float_value(real)
+            }
+            "date" => Value::String(self.text(name)?.trim().to_string()),
+            "data" => {
+                let text = self.text(name)?;
+                let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+                let raw = STANDARD
+                    .decode(compact)
+                    .with_context(|| format!("{start}: invalid base64 in <data>"))?;
+                bytes_value(&raw)
+            }
+            other => bail!("{start}: <{other}> is not a plist value"),
+        };
+        Ok(value)
+    }
+
+    fn dict(&mut self, depth: usize) -> Result<Value> {
+        let mut map = Map::new();
+        loop {
+            self.skip_misc()?;
+            let start = self.location();
+            let key = match self.tag()? {
+                Tag::Close("dict") => return Ok(Value::Object(map)),
+                Tag::Open("key") => self.text("key")?,
+                Tag::Empty("key") => String::new(),
+                Tag::Open(other) | Tag::Empty(other) => {
+                    bail!("{start}: expected <key> in <dict>, found <{other}>")
+                }
+                Tag::Close(other) => bail!("{start}: unexpected </{other}> in <dict>"),
+            };
+            self.skip_misc()?;
+            let start = self.location();
+            let value = match self.tag()? {
+                Tag::Close(_) => bail!("{start}: <key>{key}</key> has no value"),
+                tag => self.value(tag, depth + 1)?,
+            };
+            map.insert(key, value);
+        }
+    }
+}
+
+/// Decimal or `0x` hex, with an optional sign; large unsigned values fit
+/// as u64 (CFNumber stores them that way).
+fn integer(text: &str) -> Option<Value> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let magnitude = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => digits.parse::<u64>().ok()?,
+    };
+    if negative {
+        let value = 0i64.checked_sub_unsigned(magnitude)?;
+        Some(json!(value))
+    } else {
+        Some(json!(magnitude))
+    }
+}
+
+struct Binary<'a> {
+    bytes: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+}
+
+impl Binary<'_> {
+    fn object(&self, index: usize, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            bail!("binary plist nesting deeper than {MAX_DEPTH} (or a reference cycle)");
+        }
+        let offset = *self
+            .offsets
+            .get(index)
+            .ok_or_else(|| anyhow!("binary plist refers to missing object {index}"))?;
+        let body = self.bytes.get(offset..).ok_or_else(|| {
+            anyhow!("binary plist object {index} at byte {offset} is out of range")
+        })?;
+        let mut r = ByteReader::new(body);
+        let marker = r.u8()?;
+        let low = marker & 0x0f;
+        let value = match marker >> 4 {
+            0x0 => match marker {
+                0x00 => Value::Null,
+                0x08 => Value::Bool(false),
+                0x09 => Value::Bool(true),
+                _ => bail!("unknown binary plist marker {marker:#04x} at byte {offset}"),
+            },
+            0x1 => self.int(&mut r, low)?,
+            0x2 => match low {
+                2 => float_value(f64::from(f32::from_bits(r.uint(4)? as u32))),
+                3 => float_value(f64::from_bits(r.uint(8)?)),
+                _ => bail!("unsupported real size 2^{low} at byte {offset}"),
+            },
+            0x3 if marker == 0x33 => {
+                let secs = f64::from_bits(r.uint(8)?) + APPLE_EPOCH;
+                let whole = secs.floor();
+                let nanos = ((secs - whole) * 1e9).round().min(999_999_999.0) as u32;
+                match DateTime::from_timestamp(whole as i64, nanos) {
+                    Some(date) => Value::String(date.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+                    None => bail!("date out of range at byte {offset}"),
+                }
+            }
+            0x4 => {
+                let len = self.length(&mut r, low)?;
+                bytes_value(r.take(len)?)
+            }
+            0x5 => {
+                let len = self.length(&mut r, low)?;
+                // Strings marked ASCII are sometimes Latin-1 in the wild.
+                Value::String(r.take(len)?.iter().map(|&b| char::from(b)).collect())
+            }
+            0x6 => {
+                let units = self.length(&mut r, low)?;
+                let raw = r.take(units.checked_mul(2).context("string too long")?)?;
+                let units: Vec<u16> = raw
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                Value::String(
+                    String::from_utf16(&units)
+                        .map_err(|_| anyhow!("invalid UTF-16 string at byte {offset}"))?,
+                )
+            }
+            0x8 => json!({ "CF$UID": r.uint(usize::from(low) + 1)? }),
+            0xa | 0xc => {
+                let len = self.length(&mut r, low)?;
+                let refs = self.refs(&mut r, len)?;
+                let items = refs
+                    .into_iter()
+                    .map(|item| self.object(item, depth + 1))
+                    .collect::<Result<Vec<_>>>()?;
+                Value::Array(items)
+            }
+            0xd => {
+                let len = self.length(&mut r, low)?;
+                let keys = self.refs(&mut r, len)?;
+                let values = self.refs(&mut r, len)?;
+                let mut map = Map::new();
+                for (key, value) in keys.into_iter().zip(values) {
+                    let key = match self.object(key, depth + 1)? {
+                        Value::String(key) => key,
+                        other => super::key_string(other),
+                    };
+                    map.insert(key, self.object(value, depth + 1)?);
+                }
+                Value::Object(map)
+            }
+            _ => bail!("unknown binary plist marker {marker:#04x} at byte {offset}"),
+        };
+        Ok(value)
+    }
+
+    /// Integers of 1, 2 and 4 bytes are unsigned, 8 bytes signed; 16-byte
+    /// integers only hold values beyond i64 in their low half.
+    fn int(&self, r: &mut ByteReader, size_log2: u8) -> Result<Value> {
+        Ok(match size_log2 {
+            0..=2 => json!(r.uint(1 << size_log2)?),
+            3 => json!(r.uint(8)? as i64),
+            4 => {
+                r.take(8)?;
+                json!(r.uint(8)?)
+            }
+            _ => bail!("unsupported integer size 2^{size_log2}"),
+        })
+    }
+
+    /// Container and string lengths: the low nibble, or 0xF followed by an
+    /// integer object.
+    fn length(&self, r: &mut ByteReader, low: u8) -> Result<usize> {
+        if low != 0x0f {
+            return Ok(usize::from(low));
+        }
+        let marker = r.u8()?;
+        if marker >> 4 != 0x1 {
+            bail!("expected an integer length, found marker {marker:#04x}");
+        }
+        r.len(1 << (marker & 0x0f).min(3))
+    }
+
+    fn refs(&self, r: &mut ByteReader, len: usize) -> Result<Vec<usize>> {
+        (0..len).map(|_| r.len(self.ref_size)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::error_position;
+
+    #[test]
+    fn xml_plist_types() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Name</key>
+	<string>Tom &amp; Jerry &#x263A;</string>
+	<!-- a comment -->
+	<key>Count</key>
+	<integer>-42</integer>
+	<key>Ratio</key>
+	<real>0.5</real>
+	<key>Enabled</key>
+	<true/>
+	<key>Updated</key>
+	<date>2024-01-02T03:04:05Z</date>
+	<key>Blob</key>
+	<data>
+	aGVs
+	bG8=
+	</data>
+	<key>Items</key>
+	<array>
+		<string/>
+		<dict/>
+	</array>
+</dict>
+</plist>
+"#;
+        assert!(looks_like_xml(text));
+        assert_eq!(
+            parse_xml(text).unwrap(),
+            json!({
+                "Name": "Tom & Jerry \u{263A}",
+                "Count": -42,
+                "Ratio": 0.5,
+                "Enabled": true,
+                "Updated": "2024-01-02T03:04:05Z",
+                "Blob": "aGVsbG8=",
+                "Items": ["", {}]
+            })
+        );
+
+        let err =
+            parse_xml("<plist>\n<dict>\n  <key>a</key>\n  <integer>x</integer>\n</dict>\n</plist>")
+                .unwrap_err();
+        assert_eq!(err.to_string(), "line 4, column 12: invalid integer `x`");
+        assert_eq!(error_position(&err), Some((4, 12)));
+    }
+
+    #[test]
+    fn binary_plist_types() {
+        // {"a": [1, true, 2.5, "é", <data 0x00ff>, date 0], "b": UID 7} with
+        // one-byte offsets and references.
+        let mut objects: Vec<Vec<u8>> = vec![
+            vec![0xd2, 1, 2, 3, 4],        // 0: dict {1: 3, 2: 4}
+            vec![0x51, b'a'],              // 1: "a"
+            vec![0x51, b'b'],              // 2: "b"
+            vec![0xa6, 5, 6, 7, 8, 9, 10], // 3: array
+            vec![0x80, 7],                 // 4: UID 7
+            vec![0x10, 1],                 // 5: 1
+            vec![0x09],                    // 6: true
+            [vec![0x23], 2.5f64.to_bits().to_be_bytes().to_vec()].concat(), // 7: 2.5
+            vec![0x61, 0x00, 0xe9],        // 8: "é" (UTF-16)
+            vec![0x42, 0x00, 0xff],        // 9: data
+            [vec![0x33], 0f64.to_bits().to_be_bytes().to_vec()].concat(), // 10: date
+        ];
+        let mut bytes = b"bplist00".to_vec();
+        let mut offsets = Vec::new();
+        for object in objects.drain(..) {
+            offsets.push(bytes.len() as u8);
+            bytes.extend(object);
+        }
+        let table = bytes.len();
+        bytes.extend(&offsets);
+        bytes.extend([0; 6]);
+        bytes.extend([1, 1]);
+        bytes.extend((offsets.len() as u64).to_be_bytes());
+        bytes.extend(0u64.to_be_bytes());
+        bytes.extend((table as u64).to_be_bytes());
+
+        assert_eq!(
+            decode_binary(&bytes).unwrap(),
+            json!({
+                "a": [1, true, 2.5, "é", "AP8=", "2001-01-01T00:00:00Z"],
+                "b": {"CF$UID": 7}
+            })
+        );
+        assert!(decode_binary(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode_binary(b"bplist00").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Java `.properties` reader, following `Properties.load`:
+//!
+//! - `#` and `!` start comment lines.
+//! - The key ends at the first unescaped `=`, `:` or blank; blanks around
+//!   the separator are dropped.
+//! - A line ending in an odd number of backslashes continues on the next,
+//!   whose leading blanks are dropped.
+//! - `\t`, `\n`, `\r`, `\f` and `\uXXXX` are escapes; any other escaped
+//!   character stands for itself.
+//!
+//! Values are strings. Dotted keys nest (`server.port` → `server: {port}`),
+//! the inverse of `-o properties`, unless a key is also the prefix of
+//! another (`logging.level` next to `logging.level.root`) or has an empty
+//! segment: then every key is kept flat, as written.
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+pub(crate) fn parse(content: &str) -> Result<Value> {
+    let mut entries = Vec::new();
+    let mut lines = content.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let start = line.trim_start_matches([' ', '\t', '\x0c']);
+        if start.is_empty() || start.starts_with(['#', '!']) {
+            continue;
+        }
+        // Join continuation lines into one logical line.
+        let mut logical = start.to_string();
+        while ends_with_odd_backslashes(&logical) {
+            logical.pop();
+            match lines.next() {
+                Some((_, next)) => logical.push_str(next.trim_start_matches([' ', '\t', '\x0c'])),
+                None => break,
+            }
+        }
+        let column = line.len() - start.len() + 1;
+        entries.push(entry(&logical, index + 1, column)?);
+    }
+    Ok(nest(entries))
+}
+
+fn ends_with_odd_backslashes(line: &str) -> bool {
+    line.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+/// Split a logical line into its unescaped key and value.
+fn entry(line: &str, line_no: usize, column: usize) -> Result<(String, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut key_end = chars.len();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '=' | ':' | ' ' | '\t' | '\x0c' => {
+                key_end = i;
+                break;
+            }
+            _ => i += 1,
+        }
+    }
+    let mut value_start = key_end;
+    while matches!(chars.get(value_start), Some(' ' | '\t' | '\x0c')) {
+        value_start += 1;
+    }
+    if matches!(chars.get(value_start), Some('=' | ':')) {
+        value_start += 1;
+        while matches!(chars.get(value_start), Some(' ' | '\t' | '\x0c')) {
+            value_start += 1;
+        }
+    }
+    let key = unescape(&chars[..key_end], line_no, column)?;
+    let value = unescape(
+        &chars[value_start.min(chars.len())..],
+        line_no,
+        column + value_start,
+    )?;
+    Ok((key, value))
+}
+
+fn unescape(chars: &[char], line: usize, column: usize) -> Result<String> {
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let Some(&escaped) = chars.get(i) else { break };
+        i += 1;
+        match escaped {
+            't' => out.push('\t'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            'f' => out.push('\x0c'),
+            'u' => {
+                let hex: String = chars.iter().skip(i).take(4).collect();
+                let Some(unit) = (hex.len() == 4)
+                    .then(|| u16::from_str_radix(&hex, 16).ok())
+                    .flatten()
+                else {
+                    bail!(
+                        "line {line}, column {}: malformed \\uXXXX escape",
+                        column + i - 2
+                    );
+                };
+                i += 4;
+                push_utf16(&mut out, unit, chars, &mut i);
+            }
+            other => out.push(other),
+        }
+    }
+    Ok(out)
+}
+
+/// Push one UTF-16 code unit, pairing a high surrogate with a following
+/// `\uXXXX` low surrogate; unpaired surrogates become U+FFFD.
+fn push_utf16(out: &mut String, unit: u16, chars: &[char], i: &mut usize) {
+    if (0xD800..0xDC00).contains(&unit)
+        && chars.get(*i) == Some(&'\\')
+        && chars.get(*i + 1) == Some(&'u')
+    {
+        let hex: String = chars.iter().skip(*i + 2).take(4).collect();
+        if let Ok(low @ 0xDC00..=0xDFFF) = u16::from_str_radix(&hex, 16) {
+            *i += 6;
+            out.extend(char::decode_utf16([unit, low]).map(|c| c.unwrap_or('\u{FFFD}')));
+            return;
+        }
+    }
+    out.extend(char::decode_utf16([unit]).map(|c| c.unwrap_or('\u{FFFD}')));
+}
+
+/// Nest dotted keys unless that would lose or merge entries; later
+/// duplicates win either way, as in `Properties.load`.
+fn nest(entries: Vec<(String, String)>) -> Value {
+    let flat: Map<String, Value> = entries
+        .into_iter()
+        .map(|(key, value)| (key, Value::String(value)))
+        .collect();
+    let nestable = flat.keys().all(|key| {
+        !key.split('.').any(str::is_empty)
+            && !flat.keys().any(|other| {
+                other.len() > key.len()
+                    && other.starts_with(key.as_str())
+                    && other[key.len()..].starts_with('.')
+            })
+    });
+    if !nestable {
+        return Value::Object(flat);
+    }
+    let mut root = Map::new();
+    for (key, value) in flat {
+        let mut segments: Vec<&str> = key.split('.').collect();
+        let last = segments.pop().unwrap_or_default();
+        let mut map = &mut root;
+        for segment in segments {
+            map = match map
+                .entry(segment)
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                Value::Object(inner) => inner,
+                // Ruled out above: no key is a prefix of another.
+                _ => unreachable!("property prefix conflict"),
+            };
+        }
+        map.insert(last.to_string(), value);
+    }
+    Value::Object(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn load_rules_and_nesting() {
+        let text = "# app\n\
+                    ! also a comment\n\
+                    server.port = 8080\n\
+                    server.host:0.0.0.0\n\
+                    greeting hello world\n\
+                    path=C:\\\\data\\tlogs\n\
+                    list = a, \\\n    b, \\\n    c\n\
+                    name=caf\\u00e9 \\uD83D\\uDE00\n\
+                    key\\=with\\:seps = x\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "server": {"port": "8080", "host": "0.0.0.0"},
+                "greeting": "hello world",
+                "path": "C:\\data\tlogs",
+                "list": "a, b, c",
+                "name": "café 😀",
+                "key=with:seps": "x"
+            })
+        );
+    }
+
+    #[test]
+    fn prefix_conflicts_stay_flat() {
+        let text = "logging.level=INFO\nlogging.level.root=WARN\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({"logging.level": "INFO", "logging.level.root": "WARN"})
+        );
+        let err = parse("a=1\nb = \\u12G4\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 2, column 5: malformed \\uXXXX escape"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! `confctl generate` — a skeleton config from a JSON Schema, to start a new
+//! service's config from rather than a blank file.
+//!
+//! Every property gets a value: its `default`, else its `const`, the first
+//! of its `enum` or `examples`, else a placeholder for its type (`""`, the
+//! `minimum` or 0, `false`, one skeleton item for an array of objects, an
+//! empty list otherwise). `$ref` within the schema, `allOf` and the first
+//! option of `oneOf`/`anyOf` are followed; a `$ref` met again inside its
+//! own expansion is left out (null), so recursive schemas end.
+//!
+//! YAML output carries each `description` (or `title`) as a comment above
+//! its key, and the other choices of an `enum`; other formats get the same
+//! values without them. Keys come out sorted, as everywhere in confctl.
+
+use anyhow::Result;
+use clap::Args;
+use serde_json::{Map, Value};
+
+use crate::emit::commented::{self, Node, Shape};
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::{parse_file, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct GenerateCli {
+    /// JSON Schema to follow (JSON, YAML or any input format), or `-`.
+    pub(crate) schema: String,
+
+    /// Format of the skeleton. Only YAML has room for the comments.
+    #[arg(long, value_enum, default_value = "yaml")]
+    pub(crate) format: OutputFormat,
+
+    /// Input format override for the schema, as in the top-level `--format`.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub(crate) schema_format: Option<Format>,
+
+    /// Leave out properties the schema does not list as `required`.
+    #[arg(long)]
+    pub(crate) required_only: bool,
+}
+
+pub(crate) fn run(cli: GenerateCli) -> Result<()> {
+    let schema = parse_file(&cli.schema, cli.schema_format, &ParseOptions::default())?;
+    let skeleton = Skeleton {
+        root: &schema,
+        required_only: cli.required_only,
+    }
+    .node(&schema, &[]);
+    match cli.format {
+        OutputFormat::Yaml => print!("{}", commented::render(&skeleton)),
+        format => print!("{}", emit::render(&skeleton.value(), format)?),
+    }
+    Ok(())
+}
+
+struct Skeleton<'a> {
+    root: &'a Value,
+    required_only: bool,
+}
+
+impl Skeleton<'_> {
+    /// The skeleton of `schema`, reached through the `$ref`s in `refs`.
+    fn node(&self, schema: &Value, refs: &[String]) -> Node {
+        let mut refs = refs.to_vec();
+        let schema = self.resolve(schema, &mut refs);
+        let mut comments: Vec<String> = schema
+            .get("description")
+            .or_else(|| schema.get("title"))
+            .and_then(Value::as_str)
+            .map(|text| {
+                text.lines()
+                    .map(|line| line.trim_end().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let choices = schema.get("enum").and_then(Value::as_array);
+        if let Some(choices) = choices.filter(|choices| choices.len() > 1) {
+            let choices: Vec<String> = choices.iter().map(Value::to_string).collect();
+            comments.push(format!("one of: {}", choices.join(", ")));
+        }
+        let given = schema
+            .get("default")
+            .or_else(|| schema.get("const"))
+            .or_else(|| choices.and_then(|choices| choices.first()))
+            .or_else(|| {
+                schema
+                    .get("examples")
+                    .and_then(Value::as_array)
+                    .and_then(|examples| examples.first())
+            });
+        let shape = match given {
+            Some(value) => Node::plain(value).shape,
+            None => self.placeholder(&schema, &refs),
+        };
+        Node {
+            comments,
+            shape,
+            ..Node::default()
+        }
+    }
+
+    fn placeholder(&self, schema: &Map<String, Value>, refs: &[String]) -> Shape {
+        let number = || {
+            schema
+                .get("minimum")
+                .or_else(|| schema.get("exclusiveMinimum"))
+                .cloned()
+                .unwrap_or(Value::from(0))
+        };
+        match type_of(schema) {
+            Some("object") => {
+                let required: Vec<&str> = schema
+                    .get("required")
+                    .and_then(Value::as_array)
+                    .map(|keys| keys.iter().filter_map(Value::as_str).collect())
+                    .unwrap_or_default();
+                let properties = schema.get("properties").and_then(Value::as_object);
+                Shape::Object(
+                    properties
+                        .into_iter()
+                        .flatten()
+                        .filter(|(key, _)| !self.required_only || required.contains(&key.as_str()))
+                        .map(|(key, property)| (key.clone(), self.node(property, refs)))
+                        .collect(),
+                )
+            }
+            Some("array") => match schema.get("items") {
+                Some(items)
+                    if type_of(&self.resolve(items, &mut refs.to_vec())) == Some("object") =>
+                {
+                    Shape::Array(vec![self.node(items, refs)])
+                }
+                _ => Shape::Array(Vec::new()),
+            },
+            Some("string") => Shape::Scalar(Value::from("")),
+            Some("integer") => Shape::Scalar(number()),
+            Some("number") => Shape::Scalar(number()),
+            Some("boolean") => Shape::Scalar(Value::Bool(false)),
+            _ => Shape::Scalar(Value::Null),
+        }
+    }
+
+    /// `schema`'s keywords with its `$ref`, `allOf` and first `oneOf` or
+    /// `anyOf` option folded in; its own keywords win, and `properties` and
+    /// `required` add up. `refs` gains the references followed.
+    fn resolve(&self, schema: &Value, refs: &mut Vec<String>) -> Map<String, Value> {
+        let Value::Object(own) = schema else {
+            return Map::new();
+        };
+        let mut merged = own.clone();
+        let reference = match own.get("$ref").and_then(Value::as_str) {
+            Some(reference) if !refs.iter().any(|seen| seen == reference) => {
+                refs.push(reference.to_string());
+                reference
+                    .strip_prefix('#')
+                    .and_then(|pointer| self.root.pointer(pointer))
+            }
+            _ => None,
+        };
+        let first_option = ["oneOf", "anyOf"]
+            .iter()
+            .find_map(|keyword| own.get(*keyword)?.as_array()?.first());
+        let all_of = own.get("allOf").and_then(Value::as_array);
+        for part in reference
+            .into_iter()
+            .chain(all_of.into_iter().flatten())
+            .chain(first_option)
+        {
+            for (key, value) in self.resolve(part, refs) {
+                match (key.as_str(), merged.get_mut(&key), value) {
+                    ("properties", Some(Value::Object(mine)), Value::Object(theirs)) => {
+                        for (name, property) in theirs {
+                            mine.entry(name).or_insert(property);
+                        }
+                    }
+                    ("required", Some(Value::Array(mine)), Value::Array(theirs)) => {
+                        for key in theirs {
+                            if !mine.contains(&key) {
+                                mine.push(key);
+                            }
+                        }
+                    }
+                    (_, Some(_), _) => {}
+                    (_, None, value) => {
+                        merged.insert(key, value);
+                    }
+                }
+            }
+        }
+        merged
+    }
+}
+
+/// The first type `schema` allows besides null; `object` when it only lists
+/// `properties`.
+fn type_of(schema: &Map<String, Value>) -> Option<&str> {
+    match schema.get("type") {
+        Some(Value::String(name)) => Some(name),
+        Some(Value::Array(names)) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|name| *name != "null")
+            .or(names.first().and_then(Value::as_str)),
+        _ if schema.contains_key("properties") => Some("object"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn skeleton(schema: &Value, required_only: bool) -> Node {
+        Skeleton {
+            root: schema,
+            required_only,
+        }
+        .node(schema, &[])
+    }
+
+    #[test]
+    fn values_come_from_defaults_enums_and_types() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "port"],
+            "properties": {
+                "name": {"type": "string"},
+                "port": {"type": "integer", "minimum": 1024},
+                "level": {"enum": ["info", "debug"]},
+                "debug": {"type": ["null", "boolean"]},
+                "replicas": {"type": "integer", "default": 2},
+                "tags": {"type": "array", "items": {"type": "string"}},
+                "database": {"$ref": "#/$defs/database"},
+                "listeners": {"type": "array", "items": {"allOf": [
+                    {"$ref": "#/$defs/listener"},
+                    {"properties": {"tls": {"const": true}}}
+                ]}},
+                "backend": {"oneOf": [{"type": "string"}, {"type": "integer"}]},
+                "tree": {"$ref": "#"}
+            },
+            "$defs": {
+                "database": {"properties": {"url": {"examples": ["postgres://db/app"]}}},
+                "listener": {"type": "object", "properties": {"port": {"type": "number"}}}
+            }
+        });
+        let value = skeleton(&schema, false).value();
+        assert_eq!(value["name"], "");
+        assert_eq!(value["port"], 1024);
+        assert_eq!(value["level"], "info");
+        assert_eq!(value["debug"], false);
+        assert_eq!(value["replicas"], 2);
+        assert_eq!(value["tags"], json!([]));
+        assert_eq!(value["database"], json!({"url": "postgres://db/app"}));
+        assert_eq!(value["listeners"], json!([{"port": 0, "tls": true}]));
+        assert_eq!(value["backend"], "");
+        // A schema refers to itself once.
+        assert_eq!(value["tree"]["port"], 1024);
+        assert_eq!(value["tree"]["tree"], Value::Null);
+
+        let required = skeleton(&schema, true).value();
+        assert_eq!(required, json!({"name": "", "port": 1024}));
+    }
+
+    #[test]
+    fn yaml_carries_descriptions_and_choices() {
+        let schema = json!({
+            "title": "Payments service",
+            "properties": {
+                "log": {
+                    "description": "Logging.\nSent to stderr.",
+                    "properties": {
+                        "level": {"enum": ["info", "debug"], "description": "How chatty."},
+                        "format": {"type": "string", "default": "json lines"}
+                    }
+                },
+                "hosts": {"type": "array", "items": {"properties": {
+                    "name": {"description": "DNS name.", "type": "string"},
+                    "port": {"default": 443}
+                }}},
+                "on": {"type": "string", "default": "yes"},
+                "extra": {"type": "object"}
+            }
+        });
+        assert_eq!(
+            commented::render(&skeleton(&schema, false)),
+            "# Payments service\n\
+             extra: {}\n\
+             hosts:\n\
+             \x20 - # DNS name.\n\
+             \x20   name: \"\"\n\
+             \x20   port: 443\n\
+             # Logging.\n\
+             # Sent to stderr.\n\
+             log:\n\
+             \x20 format: json lines\n\
+             \x20 # How chatty.\n\
+             \x20 # one of: \"info\", \"debug\"\n\
+             \x20 level: info\n\
+             \"on\": \"yes\"\n"
+        );
+    }
+}
@@ -0,0 +1,262 @@
+//! `git::REPO//PATH@REF` file arguments: one file from a git repository at
+//! a branch, tag or commit, without checking the repository out.
+//!
+//! REPO is anything `git fetch` takes (`git@github.com:org/infra`,
+//! `https://github.com/org/infra`) or a local repository; `//` ends it, as
+//! in Terraform module sources. REF defaults to the remote's HEAD. Remote
+//! refs are fetched with `--depth 1` into a bare repository under the cache
+//! directory (`~/.cache/confctl/git`, or `CONFCTL_GIT_CACHE`), so later reads
+//! only download what changed; a commit already there is not fetched again,
+//! and when the fetch fails the ref's last fetched commit is used, with a
+//! warning. Local repositories are read in place.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::input::InputSource;
+
+/// The file argument prefix.
+pub(crate) const PREFIX: &str = "git::";
+
+/// Overrides where fetched repositories are kept.
+const CACHE_ENV: &str = "CONFCTL_GIT_CACHE";
+
+/// A file argument after `git::`, parsed when it is read.
+pub(crate) struct GitFile(pub(crate) String);
+
+#[derive(Debug, PartialEq)]
+struct Source {
+    repo: String,
+    path: String,
+    reference: Option<String>,
+}
+
+impl InputSource for GitFile {
+    /// Named after PATH, so its extension picks the format.
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let source = parse(&self.0)?;
+        let reference = source.reference.as_deref().unwrap_or("HEAD");
+        let bytes = if Path::new(&source.repo).is_dir() {
+            show(Path::new(&source.repo), reference, &source.path)
+        } else {
+            cache_dir().and_then(|cache| fetch_cached(&source, &cache))
+        };
+        let bytes = bytes.with_context(|| {
+            format!(
+                "reading {} at {reference} from {}",
+                source.path, source.repo
+            )
+        })?;
+        Ok((source.path, bytes))
+    }
+}
+
+/// `REPO//PATH@REF` split up; the `//` of a URL scheme does not count.
+fn parse(spec: &str) -> Result<Source> {
+    let after_scheme = spec.find("://").map_or(0, |at| at + 3);
+    let Some(split) = spec[after_scheme..].find("//").map(|at| at + after_scheme) else {
+        bail!(
+            "`{PREFIX}{spec}` names no file: write {PREFIX}REPO//PATH[@REF], e.g. \
+             {PREFIX}git@github.com:org/infra//prod/values.yaml@main"
+        );
+    };
+    let repo = &spec[..split];
+    let (path, reference) = match spec[split + 2..].rsplit_once('@') {
+        Some((path, reference)) => (path, Some(reference.to_string())),
+        None => (&spec[split + 2..], None),
+    };
+    if repo.is_empty() || path.is_empty() || reference.as_deref() == Some("") {
+        bail!("`{PREFIX}{spec}` needs a repository, a path and, after `@`, a ref");
+    }
+    // git would take them for options (`--upload-pack=…` runs a command).
+    if repo.starts_with('-') || reference.as_deref().is_some_and(|r| r.starts_with('-')) {
+        bail!("`{PREFIX}{spec}`: a repository or ref cannot start with `-`");
+    }
+    Ok(Source {
+        repo: repo.to_string(),
+        path: path.trim_start_matches('/').to_string(),
+        reference,
+    })
+}
+
+fn cache_dir() -> Result<PathBuf> {
+    match std::env::var_os(CACHE_ENV) {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => dirs::cache_dir()
+            .map(|dir| dir.join("confctl").join("git"))
+            .context("no cache directory to fetch git repositories into; set CONFCTL_GIT_CACHE"),
+    }
+}
+
+/// PATH at REF of a remote repository, through its bare copy under `cache`.
+fn fetch_cached(source: &Source, cache: &Path) -> Result<Vec<u8>> {
+    let repo = cache.join(slug(&source.repo));
+    if !repo.join("HEAD").is_file() {
+        fs::create_dir_all(&repo)
+            .with_context(|| format!("Failed to create {}", repo.display()))?;
+        git(&repo, &["init", "--bare", "--quiet"])?;
+    }
+    let reference = source.reference.as_deref().unwrap_or("HEAD");
+    let fetched = format!("refs/confctl/{reference}");
+    let commit = format!("{reference}^{{commit}}");
+    let known_commit = is_commit_id(reference) && git(&repo, &["cat-file", "-e", &commit]).is_ok();
+    if known_commit {
+        return show(&repo, reference, &source.path);
+    }
+    let fetch = git(
+        &repo,
+        &[
+            "fetch",
+            "--quiet",
+            "--depth",
+            "1",
+            "--no-tags",
+            "--",
+            &source.repo,
+            reference,
+        ],
+    )
+    .and_then(|_| git(&repo, &["update-ref", &fetched, "FETCH_HEAD"]));
+    if let Err(err) = fetch {
+        if git(&repo, &["rev-parse", "--verify", "--quiet", &fetched]).is_err() {
+            return Err(err);
+        }
+        eprintln!(
+            "warning: fetching {reference} from {} failed ({err:#}); reading the copy fetched \
+             before",
+            source.repo
+        );
+    }
+    show(&repo, &fetched, &source.path)
+}
+
+/// The bytes of `path` at `reference` in `repo`.
+fn show(repo: &Path, reference: &str, path: &str) -> Result<Vec<u8>> {
+    git(repo, &["show", &format!("{reference}:{path}")])
+}
+
+/// Runs git in `dir`, returning its stdout, or the first line of its
+/// stderr as the error.
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("git is needed for git:: inputs but could not be run")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr.lines().next().unwrap_or_default().trim();
+        bail!("git {} failed: {reason}", args[0]);
+    }
+    Ok(output.stdout)
+}
+
+/// A full or abbreviated commit id, which cannot move once fetched.
+fn is_commit_id(reference: &str) -> bool {
+    (7..=40).contains(&reference.len()) && reference.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The cache directory name for `repo`: its URL with every run of other
+/// characters than letters, digits, `.` and `_` turned into one `-`, then
+/// the start of the URL's SHA-256, as `org/infra` and `org-infra` would
+/// otherwise share a copy.
+fn slug(repo: &str) -> String {
+    let mut slug = String::new();
+    for c in repo.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let digest = crate::hash::sha2::sha256(repo.as_bytes());
+    let hash: String = digest[..6].iter().map(|b| format!("{b:02x}")).collect();
+    format!("{}-{hash}", slug.trim_matches('-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs_split_into_repo_path_and_ref() {
+        let source = |repo: &str, path: &str, reference: Option<&str>| Source {
+            repo: repo.into(),
+            path: path.into(),
+            reference: reference.map(str::to_string),
+        };
+        assert_eq!(
+            parse("git@github.com:org/infra//prod/values.yaml@main").unwrap(),
+            source("git@github.com:org/infra", "prod/values.yaml", Some("main"))
+        );
+        assert_eq!(
+            parse("https://github.com/org/infra.git//values.yaml").unwrap(),
+            source("https://github.com/org/infra.git", "values.yaml", None)
+        );
+        assert_eq!(
+            parse("../infra//a/b.json@v1.2.0").unwrap(),
+            source("../infra", "a/b.json", Some("v1.2.0"))
+        );
+        assert!(parse("https://github.com/org/infra")
+            .unwrap_err()
+            .to_string()
+            .contains("names no file"));
+        assert!(parse("repo//file@").is_err());
+        assert!(parse("--upload-pack=touch x;true//x.json@main").is_err());
+        assert!(parse("../infra//x.json@--output=y").is_err());
+        assert!(slug("git@github.com:org/infra.git").starts_with("git-github.com-org-infra.git-"));
+        assert_ne!(slug("org/infra"), slug("org-infra"));
+    }
+
+    #[test]
+    fn files_are_read_from_local_and_fetched_repositories() {
+        let root = std::env::temp_dir().join(format!("confctl-git-{}", std::process::id()));
+        let work = root.join("work");
+        fs::create_dir_all(work.join("prod")).unwrap();
+        let run = |args: &[&str]| {
+            git(&work, args).unwrap();
+        };
+        run(&["init", "--quiet", "--initial-branch", "main"]);
+        run(&["config", "user.email", "test@example.com"]);
+        run(&["config", "user.name", "test"]);
+        fs::write(work.join("prod/values.yaml"), "image:\n  tag: v1\n").unwrap();
+        run(&["add", "."]);
+        run(&["commit", "--quiet", "-m", "v1"]);
+        run(&["tag", "v1"]);
+        fs::write(work.join("prod/values.yaml"), "image:\n  tag: v2\n").unwrap();
+        run(&["commit", "--quiet", "-am", "v2"]);
+
+        let local = GitFile(format!("{}//prod/values.yaml@v1", work.display()));
+        let (name, bytes) = local.read().unwrap();
+        assert_eq!(name, "prod/values.yaml");
+        assert_eq!(bytes, b"image:\n  tag: v1\n");
+
+        let remote = |reference: &str| Source {
+            repo: format!("file://{}", work.display()),
+            path: "prod/values.yaml".into(),
+            reference: Some(reference.into()),
+        };
+        let cache = root.join("cache");
+        assert_eq!(
+            fetch_cached(&remote("main"), &cache).unwrap(),
+            b"image:\n  tag: v2\n"
+        );
+        assert_eq!(
+            fetch_cached(&remote("v1"), &cache).unwrap(),
+            b"image:\n  tag: v1\n"
+        );
+
+        // The fetched copy is kept for when the remote cannot be reached.
+        fs::rename(&work, root.join("moved")).unwrap();
+        assert_eq!(
+            fetch_cached(&remote("main"), &cache).unwrap(),
+            b"image:\n  tag: v2\n"
+        );
+        assert!(fetch_cached(&remote("other"), &cache).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
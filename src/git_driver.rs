@@ -0,0 +1,238 @@
+//! `confctl git-merge-driver` and `confctl git-textconv`: the commands git
+//! runs when `.gitattributes` hands a config file to confctl, so branches
+//! merge and diff by structure rather than line by line.
+//!
+//! The merge driver follows git's contract: `%O %A %B` are the ancestor,
+//! ours and theirs in temporary files, the result replaces `%A`, and the
+//! exit code is 0 for a clean merge and 1 when conflicts are left. `%P`,
+//! the path in the repository, gives the format, as the temporary names
+//! have no extension. The merge is `confctl merge`'s; a result equal to one
+//! side keeps that side's bytes (comments and all), conflicts in YAML are
+//! written between markers, and anything confctl cannot merge (a file that
+//! does not parse, conflicts in another format) falls back to
+//! `git merge-file`, git's own line merge.
+//!
+//! The textconv prints a document as YAML with sorted keys, so `git diff`
+//! shows what changed rather than how it was reformatted; binary formats
+//! become readable too. A file that does not parse is printed as it is.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde_json::Value;
+
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::merge3::{self, Side};
+use crate::{atomic, detect_format, parse_bytes, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct MergeDriverCli {
+    /// The common ancestor (`%O`).
+    pub(crate) base: String,
+
+    /// Our version (`%A`), replaced by the result.
+    pub(crate) ours: String,
+
+    /// Their version (`%B`).
+    pub(crate) theirs: String,
+
+    /// The file's path in the repository (`%P`), for its format.
+    pub(crate) path: Option<String>,
+
+    /// Input format of all three, instead of detecting it.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Settle conflicts with this side's version instead of leaving them.
+    #[arg(long, value_enum, value_name = "SIDE")]
+    pub(crate) favor: Option<Side>,
+
+    /// Match the elements of arrays of objects by this field, as in `merge`.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) array_key: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct TextconvCli {
+    /// The file to print (git passes a temporary copy).
+    pub(crate) file: String,
+
+    /// Input format, instead of detecting it.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+}
+
+/// Merge into OURS; whether conflicts are left.
+pub(crate) fn merge_driver(cli: MergeDriverCli) -> Result<bool> {
+    let read = |file: &str| fs::read(file).with_context(|| format!("reading {file}"));
+    let (base, ours, theirs) = (read(&cli.base)?, read(&cli.ours)?, read(&cli.theirs)?);
+    let name = cli.path.as_deref().unwrap_or(&cli.ours);
+    let parsed =
+        detect_format(name, &String::from_utf8_lossy(&ours), cli.format).and_then(|format| {
+            let parse =
+                |bytes: &[u8]| parse_bytes(name, bytes, Some(format), &ParseOptions::default());
+            Ok((format, parse(&base)?, parse(&ours)?, parse(&theirs)?))
+        });
+    let (format, base, ours_value, theirs_value) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("warning: {name}: {err:#}; merging line by line");
+            return line_merge(&cli);
+        }
+    };
+
+    let labels = ["ours".to_string(), "theirs".to_string()];
+    let (merged, conflicts) = merge3::merge(
+        &base,
+        &ours_value,
+        &theirs_value,
+        cli.favor,
+        &cli.array_key,
+        labels,
+    );
+    merge3::report(&conflicts);
+    if conflicts.is_empty() {
+        let value = merged.value();
+        if value == ours_value {
+            return Ok(false);
+        }
+        if value == theirs_value {
+            atomic::write(Path::new(&cli.ours), theirs, None)?;
+            return Ok(false);
+        }
+    }
+    let Some(output) = OutputFormat::from_input(format) else {
+        bail!("{name}: confctl cannot write {format:?}, so the merge is left to be done by hand");
+    };
+    match merge3::render(&merged, conflicts.is_empty(), output)? {
+        Some(body) => {
+            atomic::write(Path::new(&cli.ours), body, None)?;
+            Ok(!conflicts.is_empty())
+        }
+        None => {
+            eprintln!("warning: {name}: only YAML can hold conflict markers; merging line by line");
+            line_merge(&cli)
+        }
+    }
+}
+
+/// `git merge-file` into OURS; whether conflicts are left.
+fn line_merge(cli: &MergeDriverCli) -> Result<bool> {
+    let status = Command::new("git")
+        .args(["merge-file", "-L", "ours", "-L", "base", "-L", "theirs"])
+        .args([&cli.ours, &cli.base, &cli.theirs])
+        .status()
+        .context("running git merge-file")?;
+    // The exit code is the number of conflicts; negative on errors.
+    match status.code() {
+        Some(0) => Ok(false),
+        Some(1..=127) => Ok(true),
+        _ => bail!("git merge-file failed ({status})"),
+    }
+}
+
+pub(crate) fn textconv(cli: TextconvCli) -> Result<()> {
+    let bytes = fs::read(&cli.file).with_context(|| format!("reading {}", cli.file))?;
+    let text = textconv_bytes(&cli.file, bytes, cli.format)?;
+    std::io::stdout().write_all(&text)?;
+    Ok(())
+}
+
+/// The document in `bytes` as sorted YAML, or `bytes` themselves when they
+/// do not parse (or are empty).
+fn textconv_bytes(name: &str, bytes: Vec<u8>, format: Option<Format>) -> Result<Vec<u8>> {
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        return Ok(bytes);
+    }
+    let value: Value = match parse_bytes(name, &bytes, format, &ParseOptions::default()) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("warning: {name}: {err:#}; shown as it is");
+            return Ok(bytes);
+        }
+    };
+    Ok(emit::render(&value, OutputFormat::Yaml)?.into_bytes())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Run the driver on `.merge_file_*` copies of three versions of `path`,
+    /// as git does; OURS's contents afterwards and whether conflicts are left.
+    fn drive(tag: &str, path: &str, versions: [&str; 3]) -> (String, bool) {
+        let dir = crate::scratch_dir(&format!("driver-{tag}"));
+        let [base, ours, theirs] = ["O", "A", "B"].map(|name| {
+            dir.join(format!(".merge_file_{name}"))
+                .display()
+                .to_string()
+        });
+        for (file, contents) in [&base, &ours, &theirs].into_iter().zip(versions) {
+            fs::write(file, contents).unwrap();
+        }
+        let conflicts = merge_driver(MergeDriverCli {
+            base,
+            ours: ours.clone(),
+            theirs,
+            path: Some(path.to_string()),
+            format: None,
+            favor: None,
+            array_key: Vec::new(),
+        })
+        .unwrap();
+        let merged = fs::read_to_string(&ours).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        (merged, conflicts)
+    }
+
+    #[test]
+    fn merges_by_structure_in_the_paths_format() {
+        let base = "{\n  \"db\": {\"host\": \"a\", \"port\": 1}\n}\n";
+        let ours = "{\n  \"db\": {\"host\": \"b\", \"port\": 1}\n}\n";
+        let theirs = "{\n  \"db\": {\"host\": \"a\", \"port\": 2}\n}\n";
+        let (merged, conflicts) = drive("clean", "app.json", [base, ours, theirs]);
+        assert!(!conflicts);
+        assert_eq!(
+            serde_json::from_str::<Value>(&merged).unwrap(),
+            serde_json::json!({"db": {"host": "b", "port": 2}})
+        );
+
+        // Only theirs changed anything: their bytes are kept, comment and all.
+        let base = "port: 1\n";
+        let theirs = "# bumped\nport: 2\n";
+        let (merged, conflicts) = drive("theirs", "app.yaml", [base, base, theirs]);
+        assert!(!conflicts);
+        assert_eq!(merged, theirs);
+
+        let (merged, conflicts) = drive("yaml", "app.yaml", [base, "port: 3\n", "port: 4\n"]);
+        assert!(conflicts);
+        assert_eq!(
+            merged,
+            "<<<<<<< ours\nport: 3\n=======\nport: 4\n>>>>>>> theirs\n"
+        );
+    }
+
+    #[test]
+    fn what_does_not_parse_merges_line_by_line() {
+        let base = "a: 1\nb: [\n";
+        let (merged, conflicts) = drive("lines", "app.yaml", [base, "a: 2\nb: [\n", base]);
+        assert!(!conflicts);
+        assert_eq!(merged, "a: 2\nb: [\n");
+    }
+
+    #[test]
+    fn textconv_sorts_and_falls_back_to_the_bytes() {
+        let text = textconv_bytes("x.json", br#"{"b": 1, "a": {"c": true}}"#.to_vec(), None);
+        assert_eq!(text.unwrap(), b"a:\n  c: true\nb: 1\n");
+        let broken = b"{\"b\": ".to_vec();
+        assert_eq!(
+            textconv_bytes("x.json", broken.clone(), None).unwrap(),
+            broken
+        );
+    }
+}
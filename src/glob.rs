@@ -0,0 +1,199 @@
+//! Shell-style globs for FILE (`'services/*/config.yaml'`), expanded by
+//! confctl rather than the shell so a quoted pattern behaves the same
+//! everywhere. `*` and `?` stay within one path segment, `[abc]` / `[a-z]`
+//! / `[!abc]` match one character and a `**` segment any number of
+//! directories. As in the shell, wildcards do not match a leading `.`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+pub(crate) fn is_pattern(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// The files matching `pattern`, sorted.
+pub(crate) fn expand(pattern: &str) -> Result<Vec<String>> {
+    let root = if pattern.starts_with('/') { "/" } else { "" };
+    let segments: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let mut found = Vec::new();
+    walk(Path::new(root), &segments, &mut found);
+    found.sort();
+    found.dedup();
+    if found.is_empty() {
+        bail!("no files match `{pattern}`");
+    }
+    Ok(found
+        .into_iter()
+        .map(|path| path.display().to_string())
+        .collect())
+}
+
+fn walk(dir: &Path, segments: &[&str], found: &mut Vec<PathBuf>) {
+    let Some((&segment, rest)) = segments.split_first() else {
+        if dir.is_file() {
+            found.push(dir.to_path_buf());
+        }
+        return;
+    };
+    if !is_pattern(segment) {
+        return walk(&dir.join(segment), rest, found);
+    }
+    let listing = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = fs::read_dir(listing) else {
+        return;
+    };
+    if segment == "**" {
+        walk(dir, rest, found);
+    }
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue };
+        let path = dir.join(name);
+        if segment == "**" {
+            if !name.starts_with('.') && path.is_dir() {
+                walk(&path, segments, found);
+            }
+        } else if matches(segment, name) {
+            walk(&path, rest, found);
+        }
+    }
+}
+
+/// Whether `name` (one path segment) matches the glob `pattern`.
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_from(&pattern, &name)
+}
+
+fn matches_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| matches_from(rest, &name[skip..])),
+        Some((&first, rest)) => {
+            let Some((&c, name_rest)) = name.split_first() else {
+                return false;
+            };
+            match first {
+                '?' => matches_from(rest, name_rest),
+                '[' => match class(rest, c) {
+                    Some((true, after)) => matches_from(after, name_rest),
+                    Some((false, _)) => false,
+                    // No closing `]`: a literal `[`.
+                    None => c == '[' && matches_from(rest, name_rest),
+                },
+                literal => c == literal && matches_from(rest, name_rest),
+            }
+        }
+    }
+}
+
+/// Whether the `/`-separated `path` matches `pattern` segment by segment,
+/// a `**` segment standing for any number of directories. A pattern
+/// without a `/` matches the file name in any directory.
+pub(crate) fn matches_path(pattern: &str, path: &str) -> bool {
+    let split = |text: &str| -> Vec<String> {
+        text.split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .map(str::to_string)
+            .collect()
+    };
+    let path = split(path);
+    if !pattern.contains('/') {
+        return path.last().is_some_and(|name| matches(pattern, name));
+    }
+    segments_match(&split(pattern), &path)
+}
+
+fn segments_match(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((segment, rest)) if segment == "**" => (0..=path.len())
+            .take_while(|&skip| skip == 0 || !path[skip - 1].starts_with('.'))
+            .any(|skip| segments_match(rest, &path[skip..])),
+        Some((segment, rest)) => path
+            .split_first()
+            .is_some_and(|(name, tail)| matches(segment, name) && segments_match(rest, tail)),
+    }
+}
+
+/// Match `c` against the class after a `[`; returns whether it matched and
+/// the pattern after the closing `]`.
+fn class(pattern: &[char], c: char) -> Option<(bool, &[char])> {
+    let (negated, body) = match pattern.first() {
+        Some('!' | '^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+    // A `]` right after the `[` (or `[!`) is a member, not the end.
+    let close = body.iter().skip(1).position(|&ch| ch == ']')? + 1;
+    let members = &body[..close];
+    let mut hit = false;
+    let mut i = 0;
+    while i < members.len() {
+        if members.get(i + 1) == Some(&'-') && i + 2 < members.len() {
+            hit |= (members[i]..=members[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            hit |= members[i] == c;
+            i += 1;
+        }
+    }
+    Some((hit != negated, &body[close + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_matching() {
+        assert!(matches("*.yaml", "config.yaml"));
+        assert!(!matches("*.yaml", ".hidden.yaml"));
+        assert!(matches(".*.yaml", ".hidden.yaml"));
+        assert!(matches("config.?ml", "config.yml"));
+        assert!(matches("[a-c]pi", "api"));
+        assert!(!matches("[!a-c]pi", "api"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("a[b", "a[b"));
+        assert!(!matches("*.yaml", "config.yaml.bak"));
+    }
+
+    #[test]
+    fn path_matching() {
+        assert!(matches_path("*.yaml", "deploy/web/values.yaml"));
+        assert!(matches_path("deploy/*.yaml", "./deploy/web.yaml"));
+        assert!(!matches_path("deploy/*.yaml", "deploy/web/values.yaml"));
+        assert!(matches_path("deploy/**/values.yaml", "deploy/values.yaml"));
+        assert!(matches_path("**/values.yaml", "a/b/values.yaml"));
+        assert!(!matches_path("**/values.yaml", "a/.git/values.yaml"));
+    }
+
+    #[test]
+    fn expands_across_directories() {
+        assert_eq!(
+            expand("testdata/services/*/config.yaml").unwrap(),
+            [
+                "testdata/services/api/config.yaml",
+                "testdata/services/web/config.yaml"
+            ]
+        );
+        assert_eq!(
+            expand("testdata/**/web/*.yaml").unwrap(),
+            ["testdata/services/web/config.yaml"]
+        );
+        let err = expand("testdata/services/*/nope.yaml").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no files match `testdata/services/*/nope.yaml`"
+        );
+    }
+}
@@ -0,0 +1,119 @@
+//! `confctl grep` — every leaf whose value matches a pattern, printed with
+//! its path: a grep that knows where in the document a line lives.
+//!
+//! Leaves are strings, numbers, booleans and nulls, matched on the text
+//! they print as (`8080`, `true`, a string without quotes). As in grep,
+//! PATTERN is a regular expression (see `crate::regex`) found anywhere in
+//! the value; `-F` takes it literally.
+
+use anyhow::Result;
+use clap::Args;
+use serde_json::Value;
+
+use crate::emit::OutputFormat;
+use crate::find::{print_matches, walk};
+use crate::formats::ParseOptions;
+use crate::regex::Regex;
+use crate::{format_value_with, parse_file, redact_with, Format, RedactOptions};
+
+#[derive(Args, Debug)]
+pub(crate) struct GrepCli {
+    /// Config file, or `-` for stdin.
+    pub(crate) file: String,
+
+    /// Regular expression to look for in leaf values.
+    pub(crate) pattern: String,
+
+    /// Match PATTERN as a plain substring.
+    #[arg(short = 'F', long)]
+    pub(crate) fixed_strings: bool,
+
+    /// Match case-insensitively.
+    #[arg(short = 'i', long)]
+    pub(crate) ignore_case: bool,
+
+    /// Mask secret values before searching, as `--redact` does for queries:
+    /// masked values are neither matched nor printed.
+    #[arg(short = 'r', long)]
+    pub(crate) redact: bool,
+
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Print the matches as one document keyed by path in this format
+    /// instead of `path: value` lines.
+    #[arg(short = 'o', long, value_enum)]
+    pub(crate) output: Option<OutputFormat>,
+}
+
+/// Prints the matches; `false` when there were none.
+pub(crate) fn run(cli: GrepCli) -> Result<bool> {
+    let mut value = parse_file(&cli.file, cli.format, &ParseOptions::default())?;
+    if cli.redact {
+        value = redact_with(&value, &RedactOptions::from_cli(&[], None));
+    }
+    let pattern = if cli.fixed_strings {
+        escape(&cli.pattern)
+    } else {
+        cli.pattern.clone()
+    };
+    let regex = Regex::new(&pattern, cli.ignore_case)?;
+    let matches = grep_leaves(&value, &regex);
+    print_matches(&matches, cli.output)?;
+    Ok(!matches.is_empty())
+}
+
+/// Every leaf whose printed text `regex` matches, with its dotted path.
+pub(crate) fn grep_leaves<'a>(value: &'a Value, regex: &Regex) -> Vec<(String, &'a Value)> {
+    let mut found = Vec::new();
+    walk(value, "", &mut |path, _, leaf| {
+        if !leaf.is_object() && !leaf.is_array() && regex.is_match(&format_value_with(leaf, true)) {
+            found.push((path.to_string(), leaf));
+        }
+    });
+    found
+}
+
+/// `text` as a regex that matches it literally.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.^$|()[]{}*+?".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn leaves_match_on_their_text() {
+        let value = json!({
+            "cache": {"url": "redis://cache:6379/0", "ttl": 6379},
+            "queue": ["redis://queue:6380", "amqp://mq"],
+            "debug": true
+        });
+        let paths = |regex: &Regex| -> Vec<String> {
+            grep_leaves(&value, regex)
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect()
+        };
+        assert_eq!(
+            paths(&Regex::new("redis://", false).unwrap()),
+            ["cache.url", "queue.0"]
+        );
+        assert_eq!(paths(&Regex::new(r"^\d+$", false).unwrap()), ["cache.ttl"]);
+        assert_eq!(paths(&Regex::new("TRUE", true).unwrap()), ["debug"]);
+        assert_eq!(
+            paths(&Regex::new(&escape("cache:6379/0"), false).unwrap()),
+            ["cache.url"]
+        );
+        assert_eq!(escape("a.b*(c)"), r"a\.b\*\(c\)");
+    }
+}
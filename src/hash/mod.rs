@@ -0,0 +1,87 @@
+//! `confctl hash` — fingerprint what a config file says rather than how it
+//! is written, for drift detection. The digest is taken over the document's
+//! `-o json-canonical` form (RFC 8785: sorted keys, no whitespace,
+//! ECMAScript number formatting), so the same settings as YAML or JSON,
+//! reordered, re-indented or written `8080.0`, hash the same.
+//!
+//! `confctl hash app.yaml` prints what
+//! `confctl app.yaml -o json-canonical | sha256sum` does.
+
+pub(crate) mod sha2;
+
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use serde_json::Value;
+
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::{parse_file, Format};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum Algo {
+    Sha256,
+    Sha512,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct HashCli {
+    /// Config files to hash, or `-` for stdin.
+    #[arg(required = true)]
+    pub(crate) files: Vec<String>,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Digest algorithm.
+    #[arg(long, value_enum, default_value = "sha256")]
+    pub(crate) algo: Algo,
+}
+
+/// One `<hex digest>  <file>` line per file, as `sha256sum` prints.
+pub(crate) fn run(cli: HashCli) -> Result<()> {
+    for file in &cli.files {
+        let value = parse_file(file, cli.format, &ParseOptions::default())?;
+        println!("{}  {file}", digest(&value, cli.algo)?);
+    }
+    Ok(())
+}
+
+/// Lower-case hex digest of `value`'s canonical JSON.
+pub(crate) fn digest(value: &Value, algo: Algo) -> Result<String> {
+    let canonical = emit::render(value, OutputFormat::JsonCanonical)?;
+    let bytes = match algo {
+        Algo::Sha256 => sha2::sha256(canonical.as_bytes()),
+        Algo::Sha512 => sha2::sha512(canonical.as_bytes()),
+    };
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_content;
+
+    #[test]
+    fn equivalent_documents_hash_the_same() {
+        let yaml = "server:\n  port: 8080\n  host: 0.0.0.0\ntags: [a, b]\n";
+        let json = r#"{"tags": ["a", "b"], "server": {"host": "0.0.0.0", "port": 8080.0}}"#;
+        let yaml = parse_content("app.yaml", yaml, None).unwrap();
+        let json = parse_content("app.json", json, None).unwrap();
+        assert_eq!(
+            digest(&yaml, Algo::Sha256).unwrap(),
+            digest(&json, Algo::Sha256).unwrap()
+        );
+        // sha256 of `{"server":{"host":"0.0.0.0","port":8080},"tags":["a","b"]}`.
+        assert_eq!(
+            digest(&yaml, Algo::Sha256).unwrap(),
+            "9f2eeb511676036f7353d5bc600bb6e41d9639091272d89c0c8374387248328f"
+        );
+
+        let changed = parse_content("app.json", r#"{"server": {"port": 8081}}"#, None).unwrap();
+        assert_ne!(
+            digest(&yaml, Algo::Sha512).unwrap(),
+            digest(&changed, Algo::Sha512).unwrap()
+        );
+    }
+}
@@ -0,0 +1,509 @@
+//! Opt-in, local-only invocation history. With `CONFCTL_HISTORY=1` every run
+//! appends one JSON line (time, cwd, argv with secrets redacted, exit code,
+//! error) to a user-only file; nothing is ever sent anywhere. `confctl
+//! history` lists, summarises, replays and clears it — handy for
+//! reconstructing what was run during an incident.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use clap::{Args, CommandFactory, Subcommand};
+use serde::{Deserialize, Serialize};
+
+/// Set to 1/true/yes/on to start recording.
+pub(crate) const HISTORY_ENV: &str = "CONFCTL_HISTORY";
+/// Overrides the history file location.
+pub(crate) const HISTORY_FILE_ENV: &str = "CONFCTL_HISTORY_FILE";
+
+#[derive(Args, Debug)]
+pub(crate) struct HistoryCli {
+    #[command(subcommand)]
+    cmd: Option<HistoryCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// Show recorded invocations, oldest first (the default).
+    List {
+        /// Only the last N entries.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Only runs that exited non-zero.
+        #[arg(long)]
+        failed: bool,
+        /// Print entries as JSON lines instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print entry N as a shell command, ready to paste.
+    Show { id: usize },
+    /// Run entry N again from its original working directory.
+    Rerun { id: usize },
+    /// Local usage statistics: runs per command, failure rate, top files.
+    Stats,
+    /// Delete the history file.
+    Clear,
+}
+
+impl HistoryCli {
+    pub(crate) fn mutation(&self) -> Option<&'static str> {
+        match self.cmd {
+            Some(HistoryCommand::Clear) => Some("history clear"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub(crate) struct Entry {
+    pub(crate) at: DateTime<Utc>,
+    pub(crate) cwd: String,
+    /// Arguments after the program name, secrets redacted.
+    pub(crate) args: Vec<String>,
+    /// Subcommand name, or `query` for the default file/path lookup.
+    pub(crate) command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) file: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) path: Option<String>,
+    pub(crate) exit_code: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    pub(crate) duration_ms: u64,
+}
+
+fn history_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os(HISTORY_FILE_ENV) {
+        return Ok(PathBuf::from(path));
+    }
+    let dir = dirs::data_local_dir().context("could not resolve user data dir")?;
+    Ok(dir.join("confctl").join("history.jsonl"))
+}
+
+/// Append the current invocation when recording is enabled. Failures are
+/// swallowed: history must never change the outcome of the real command.
+pub(crate) fn record(outcome: &Result<u8>, started: Instant) {
+    if !crate::env_flag(HISTORY_ENV) {
+        return;
+    }
+    let args: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect();
+    let Some(entry) = build_entry(args, outcome, started) else {
+        return;
+    };
+    if let Ok(path) = history_path() {
+        let _ = append(&path, &entry);
+    }
+}
+
+fn build_entry(args: Vec<String>, outcome: &Result<u8>, started: Instant) -> Option<Entry> {
+    let matches = crate::Cli::command()
+        .try_get_matches_from(std::iter::once("confctl".to_string()).chain(args.clone()))
+        .ok()?;
+    let (command, file, path) = match matches.subcommand_name() {
+        // Run by the shell and by git, not by hand.
+        Some("history" | "__complete" | "git-merge-driver" | "git-textconv") => return None,
+        Some(name) => (name.to_string(), None, None),
+        None => (
+            "query".to_string(),
+            matches.get_one::<String>("file").cloned(),
+            matches.get_one::<String>("path").cloned(),
+        ),
+    };
+    let (exit_code, error) = match outcome {
+        Ok(code) => (*code, None),
+        Err(err) => (crate::errors::exit_code(err), Some(format!("{err:#}"))),
+    };
+    let cwd = std::env::current_dir()
+        .map(|d| d.display().to_string())
+        .unwrap_or_default();
+    let args = redact_args(&args, matches.subcommand_name());
+    Some(Entry {
+        at: Utc::now(),
+        cwd,
+        args,
+        command,
+        file,
+        path,
+        exit_code,
+        error,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// Stands in for a secret argument in the history.
+const REDACTED: &str = "<redacted>";
+
+/// `args` with secrets replaced by `<redacted>`: the value of a sensitive
+/// `--flag` or `NAME=VALUE` assignment (by name, as `--redact` judges keys),
+/// the VALUE of `set FILE PATH VALUE` for a sensitive PATH, and anything
+/// shaped like a credential itself. Such entries can be shown but not re-run.
+fn redact_args(args: &[String], subcommand: Option<&str>) -> Vec<String> {
+    #[derive(PartialEq)]
+    enum Next {
+        Positional,
+        FlagValue,
+        Secret,
+    }
+    let command = crate::Cli::command();
+    let scope: Vec<&clap::Command> = subcommand
+        .and_then(|name| command.find_subcommand(name))
+        .into_iter()
+        .chain([&command])
+        .collect();
+    // Whether `--long` / `-s` is a flag that reads the next argument.
+    let takes_value = |flag: &str| {
+        scope
+            .iter()
+            .flat_map(|command| command.get_arguments())
+            .find(|arg| match flag.strip_prefix("--") {
+                Some(long) => arg.get_long() == Some(long),
+                None => flag.chars().nth(1) == arg.get_short() && flag.len() == 2,
+            })
+            .is_some_and(|arg| arg.get_action().takes_values())
+    };
+
+    let mut redacted = Vec::with_capacity(args.len());
+    let mut next = Next::Positional;
+    let mut positionals = 0;
+    for arg in args {
+        let kept = match std::mem::replace(&mut next, Next::Positional) {
+            _ if crate::looks_like_secret_value(arg) => REDACTED.to_string(),
+            Next::Secret => REDACTED.to_string(),
+            Next::FlagValue => arg.clone(),
+            Next::Positional if arg.starts_with('-') && arg.len() > 1 && arg != "--" => {
+                match arg.split_once('=') {
+                    Some((flag, value)) if sensitive(flag, value) => format!("{flag}={REDACTED}"),
+                    Some(_) => arg.clone(),
+                    None => {
+                        if takes_value(arg) {
+                            next = if crate::diff::is_sensitive_path(arg) {
+                                Next::Secret
+                            } else {
+                                Next::FlagValue
+                            };
+                        }
+                        arg.clone()
+                    }
+                }
+            }
+            Next::Positional => {
+                positionals += 1;
+                match arg.split_once('=') {
+                    Some((name, value)) if sensitive(name, value) => {
+                        format!("{name}={REDACTED}")
+                    }
+                    // `set FILE PATH VALUE`: the subcommand, FILE, then PATH.
+                    None if subcommand == Some("set")
+                        && positionals >= 3
+                        && crate::diff::is_sensitive_path(arg) =>
+                    {
+                        next = Next::Secret;
+                        arg.clone()
+                    }
+                    _ => arg.clone(),
+                }
+            }
+        };
+        redacted.push(kept);
+    }
+    redacted
+}
+
+fn sensitive(name: &str, value: &str) -> bool {
+    crate::diff::is_sensitive_path(name) || crate::looks_like_secret_value(value)
+}
+
+fn append(path: &Path, entry: &Entry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("creating {}", parent.display()))?;
+    }
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let line = serde_json::to_string(entry).context("serializing history entry")?;
+    writeln!(file, "{line}").with_context(|| format!("writing {}", path.display()))
+}
+
+/// Entries in file order; unreadable lines (older formats, truncated writes)
+/// are skipped rather than failing the whole listing.
+fn load(path: &Path) -> Result<Vec<Entry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let body = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    Ok(body
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+pub(crate) fn run(cli: HistoryCli, read_only: bool) -> Result<u8> {
+    let path = history_path()?;
+    let cmd = cli.cmd.unwrap_or(HistoryCommand::List {
+        limit: 20,
+        failed: false,
+        json: false,
+    });
+
+    match cmd {
+        HistoryCommand::Clear => {
+            if path.exists() {
+                fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+            }
+            println!("cleared {}", path.display());
+        }
+        HistoryCommand::List {
+            limit,
+            failed,
+            json,
+        } => {
+            let entries = load(&path)?;
+            if entries.is_empty() && !crate::env_flag(HISTORY_ENV) {
+                eprintln!("history is empty; set {HISTORY_ENV}=1 to start recording");
+            }
+            let selected: Vec<(usize, &Entry)> = entries
+                .iter()
+                .enumerate()
+                .map(|(i, e)| (i + 1, e))
+                .filter(|(_, e)| !failed || e.exit_code != 0)
+                .collect();
+            for (id, entry) in &selected[selected.len().saturating_sub(limit)..] {
+                if json {
+                    println!("{}", serde_json::to_string(entry)?);
+                } else {
+                    println!("{}", list_line(*id, entry));
+                }
+            }
+        }
+        HistoryCommand::Show { id } => {
+            let entry = nth(&load(&path)?, id)?.clone();
+            println!("cd {} && {}", shell_quote(&entry.cwd), command_line(&entry));
+        }
+        HistoryCommand::Rerun { id } => {
+            let entry = nth(&load(&path)?, id)?.clone();
+            ensure_rerunnable(id, &entry)?;
+            eprintln!("+ {}", command_line(&entry));
+            let exe = std::env::current_exe().context("locating the confctl binary")?;
+            let mut child = std::process::Command::new(exe);
+            child.args(&entry.args).current_dir(&entry.cwd);
+            if read_only {
+                child.env(crate::READ_ONLY_ENV, "1");
+            }
+            let status = child
+                .status()
+                .with_context(|| format!("re-running history entry {id}"))?;
+            return Ok(status.code().map_or(1, |c| c.clamp(0, 255) as u8));
+        }
+        HistoryCommand::Stats => print!("{}", stats(&load(&path)?)),
+    }
+    Ok(0)
+}
+
+/// Re-running an entry whose secrets were redacted would pass the
+/// placeholder on, e.g. `set` writing `<redacted>` over a password.
+fn ensure_rerunnable(id: usize, entry: &Entry) -> Result<()> {
+    if entry.args.iter().any(|arg| arg.contains(REDACTED)) {
+        bail!(
+            "history entry #{id} had secret arguments, which were not recorded; \
+             run it again by hand (`confctl history show {id}`)"
+        );
+    }
+    Ok(())
+}
+
+fn nth(entries: &[Entry], id: usize) -> Result<&Entry> {
+    match id.checked_sub(1).and_then(|i| entries.get(i)) {
+        Some(entry) => Ok(entry),
+        None => bail!("no history entry #{id} ({} recorded)", entries.len()),
+    }
+}
+
+fn list_line(id: usize, entry: &Entry) -> String {
+    let when = entry.at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+    let status = match &entry.error {
+        Some(err) => format!("exit {} — {}", entry.exit_code, first_line(err)),
+        None => format!("exit {}", entry.exit_code),
+    };
+    format!("{id:>4}  {when}  {}  ({status})", command_line(entry))
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or("")
+}
+
+fn command_line(entry: &Entry) -> String {
+    std::iter::once("confctl".to_string())
+        .chain(entry.args.iter().map(|a| shell_quote(a)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Single-quote `arg` unless it is made only of shell-safe characters.
+pub(crate) fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+fn stats(entries: &[Entry]) -> String {
+    let Some(first) = entries.first() else {
+        return "no history recorded\n".to_string();
+    };
+    let failed = entries.iter().filter(|e| e.exit_code != 0).count();
+    let ok_pct = 100.0 * (entries.len() - failed) as f64 / entries.len() as f64;
+
+    let mut commands: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut files: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in entries {
+        *commands.entry(&entry.command).or_default() += 1;
+        if let Some(file) = &entry.file {
+            *files.entry(file).or_default() += 1;
+        }
+    }
+
+    let mut out = format!(
+        "runs:     {} ({failed} failed, {ok_pct:.1}% ok)\nsince:    {}\n",
+        entries.len(),
+        first.at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    );
+    for (title, counts) in [("commands", commands), ("top files", files)] {
+        if counts.is_empty() {
+            continue;
+        }
+        let mut ranked: Vec<_> = counts.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        out.push_str(&format!("{title}:\n"));
+        for (name, count) in ranked.into_iter().take(10) {
+            out.push_str(&format!("  {count:>5}  {name}\n"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(args: &[&str], exit_code: u8) -> Entry {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        let outcome = if exit_code == 0 {
+            Ok(0)
+        } else {
+            Err(anyhow::anyhow!("boom"))
+        };
+        build_entry(args, &outcome, Instant::now()).unwrap()
+    }
+
+    #[test]
+    fn build_entry_names_query_file_and_path() {
+        let e = entry(&["config.json", "db.host", "-c"], 0);
+        assert_eq!(e.command, "query");
+        assert_eq!(e.file.as_deref(), Some("config.json"));
+        assert_eq!(e.path.as_deref(), Some("db.host"));
+        assert_eq!(e.error, None);
+
+        let e = entry(&["diff", "a.env", "b.env"], 1);
+        assert_eq!(e.command, "diff");
+        assert_eq!(e.exit_code, 1);
+        assert_eq!(e.error.as_deref(), Some("boom"));
+
+        let args = vec!["history".to_string(), "stats".to_string()];
+        assert!(build_entry(args, &Ok(0), Instant::now()).is_none());
+        let args = vec!["git-textconv".to_string(), "app.json".to_string()];
+        assert!(build_entry(args, &Ok(0), Instant::now()).is_none());
+    }
+
+    #[test]
+    fn secrets_in_arguments_are_not_kept() {
+        let e = entry(
+            &["set", "prod.yaml", "db.password=hunter2", "replicas=3"],
+            0,
+        );
+        assert_eq!(
+            e.args,
+            ["set", "prod.yaml", "db.password=<redacted>", "replicas=3"]
+        );
+        let e = entry(
+            &["set", "--type", "string", "f.yaml", "api.token", "xyz"],
+            0,
+        );
+        assert_eq!(e.args[5], "<redacted>");
+        assert_eq!(e.args[2], "string");
+        let e = entry(&["set", ".env", "GITHUB=ghp_abcdef0123456789"], 0);
+        assert_eq!(e.args[2], "GITHUB=<redacted>");
+        // A sensitive-sounding switch that takes no value hides nothing.
+        let e = entry(&["--mask-secrets", "app.yaml", "db"], 0);
+        assert_eq!(e.args, ["--mask-secrets", "app.yaml", "db"]);
+
+        let e = entry(&["set", "prod.yaml", "db.password", "hunter2"], 0);
+        let line = serde_json::to_string(&e).unwrap();
+        assert!(!line.contains("hunter2"), "{line}");
+    }
+
+    #[test]
+    fn redacted_entries_are_not_rerun() {
+        let e = entry(&["set", "p.yaml", "db.password", "hunter2"], 0);
+        let err = ensure_rerunnable(1, &e).unwrap_err();
+        assert!(err.to_string().contains("#1 had secret arguments"), "{err}");
+        assert!(ensure_rerunnable(2, &entry(&["set", "p.yaml", "replicas=3"], 0)).is_ok());
+    }
+
+    #[test]
+    fn append_then_load_round_trips() {
+        let pid = std::process::id();
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let path = std::env::temp_dir()
+            .join(format!("confctl-history-{pid}-{nanos}"))
+            .join("history.jsonl");
+        let first = entry(&["config.json", "a"], 0);
+        let second = entry(&["set", ".env", "A=1"], 0);
+        append(&path, &first).unwrap();
+        append(&path, &second).unwrap();
+        assert_eq!(load(&path).unwrap(), vec![first, second]);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn stats_counts_commands_and_files() {
+        let entries = vec![
+            entry(&["config.json", "a"], 0),
+            entry(&["config.json", "b"], 0),
+            entry(&["diff", "a.env", "b.env"], 1),
+        ];
+        let text = stats(&entries);
+        assert!(text.contains("runs:     3 (1 failed, 66.7% ok)"), "{text}");
+        assert!(text.contains("      2  query\n"), "{text}");
+        assert!(text.contains("      2  config.json\n"), "{text}");
+    }
+
+    #[test]
+    fn shell_quote_only_when_needed() {
+        assert_eq!(shell_quote("db.host"), "db.host");
+        assert_eq!(shell_quote("it's here"), r"'it'\''s here'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
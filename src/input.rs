@@ -0,0 +1,526 @@
+//! Where documents come from. Every reader goes through an [`InputSource`]:
+//! the query and the subcommands that take a file, snapshot sources, and
+//! `--from-snapshot`. Parsing and the query engine never see whether the
+//! bytes came from disk, stdin or the network.
+//!
+//! Files, stdin, the clipboard, the process environment, files in git
+//! repositories (through the `git` binary) and Kubernetes objects (through
+//! `kubectl`) are always built in. The other sources are Cargo features,
+//! all on by default:
+//!
+//! - `http`: URLs (snapshot `url:` sources), via ureq.
+//! - `vault`: remote secret backends, i.e. the `vault` subcommand and
+//!   snapshot `vault:` sources. Implies `http`.
+//! - `archive`: tar and zip archives, i.e. `ARCHIVE::MEMBER` inputs,
+//!   `snapshot` and `--from-snapshot`.
+//! - `s3`, `gcs`, `azure`: `s3://`, `gs://` and `az://` objects in cloud
+//!   storage (see `crate::cloud`). Each implies `http`.
+//!
+//! The `mmap` feature (unix, also on by default) parses local files of
+//! 16 MiB or more from a memory mapping instead of a copy on the heap. Input
+//! formats are features as well; see `Format::compiled`.
+//!
+//! `cargo build --no-default-features` leaves out every network and cloud
+//! code path (ureq, TLS, keyring, the vault crypto), for small static musl
+//! builds. Asking such a build for a missing source is an error naming the
+//! feature rather than a different code path.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::formats::ParseOptions;
+use crate::{parse_bytes, Format};
+
+pub(crate) trait InputSource {
+    /// The raw bytes, and the name format detection goes by (its extension)
+    /// and errors print.
+    fn read(&self) -> Result<(String, Vec<u8>)>;
+
+    /// The parsed document. `format` overrides detection, as `--format` does.
+    fn load(&self, format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        let (name, bytes) = self.read()?;
+        parse_bytes(&name, &bytes, format, opts)
+    }
+}
+
+/// The file argument that reads the system clipboard.
+pub(crate) const CLIPBOARD: &str = "clip:";
+
+/// The file argument prefix of Vault KV v2 secrets.
+pub(crate) const VAULT_KV: &str = "vault://";
+
+/// Whether `file` names a document elsewhere (a git repository, cloud
+/// storage, Vault, etcd, Consul or a Kubernetes cluster) rather than a
+/// local path or glob.
+pub(crate) fn is_remote(file: &str) -> bool {
+    file.starts_with(crate::git::PREFIX)
+        || file.starts_with(crate::k8s::PREFIX)
+        || crate::cloud::is_uri(file)
+        || file.starts_with(VAULT_KV)
+        || crate::kvstore::is_uri(file)
+}
+
+/// A file argument: `-` is stdin, `clip:` the clipboard (unless a file has
+/// that name), `git::REPO//PATH@REF` a file in a git repository,
+/// `s3://BUCKET/KEY`, `gs://BUCKET/KEY` and `az://CONTAINER/BLOB` an object
+/// in cloud storage, `vault://MOUNT/data/PATH` a Vault KV v2 secret,
+/// `etcd://HOST/KEY` and `consul://HOST/kv/KEY` a key of those stores,
+/// `k8s://KIND/NAMESPACE/NAME` a ConfigMap or Secret in the cluster,
+/// `ARCHIVE::MEMBER` a file inside a tar or zip archive (itself a path or
+/// `-`), `@NAME` an alias from the settings file, anything else a local path.
+/// Remote sources go through `crate::cache` when it is on, and every
+/// source but an alias (whose target is) through `crate::signature`.
+pub(crate) fn open(file: &str) -> Box<dyn InputSource> {
+    let source = fetch(file);
+    if file.starts_with('@') && !Path::new(file).exists() {
+        return source;
+    }
+    crate::signature::wrap(file, source)
+}
+
+/// `file` as `open` reads it, without the signature check: an archive whose
+/// member is checked, and signatures themselves.
+pub(crate) fn fetch(file: &str) -> Box<dyn InputSource> {
+    let source = source(file);
+    if is_remote(file) && !Path::new(file).exists() {
+        return crate::cache::wrap(file, source);
+    }
+    source
+}
+
+fn source(file: &str) -> Box<dyn InputSource> {
+    if file == "-" {
+        return Box::new(Stdin);
+    }
+    match file.strip_prefix(crate::git::PREFIX) {
+        Some(spec) if !Path::new(file).exists() => {
+            return Box::new(crate::git::GitFile(spec.to_string()))
+        }
+        _ => {}
+    }
+    if crate::cloud::is_uri(file) && !Path::new(file).exists() {
+        return Box::new(crate::cloud::Object(file.to_string()));
+    }
+    match file.strip_prefix(VAULT_KV) {
+        Some(spec) if !Path::new(file).exists() => return Box::new(VaultKv(spec.to_string())),
+        _ => {}
+    }
+    if crate::kvstore::is_uri(file) && !Path::new(file).exists() {
+        return Box::new(crate::kvstore::KvKey(file.to_string()));
+    }
+    if file.starts_with(crate::k8s::PREFIX) && !Path::new(file).exists() {
+        return Box::new(crate::k8s::Live(file.to_string()));
+    }
+    if file == CLIPBOARD && !Path::new(file).exists() {
+        return Box::new(Clipboard { tools: PASTE_TOOLS });
+    }
+    match file.split_once("::") {
+        Some((archive, member)) if !member.is_empty() && !Path::new(file).exists() => {
+            Box::new(Member {
+                archive: fetch(archive),
+                member: member.to_string(),
+            })
+        }
+        _ => match file.strip_prefix('@') {
+            Some(name) if !Path::new(file).exists() => Box::new(Alias(name.to_string())),
+            _ => Box::new(File(file.to_string())),
+        },
+    }
+}
+
+struct File(String);
+
+impl InputSource for File {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let bytes =
+            fs::read(&self.0).with_context(|| format!("Failed to read file: {}", self.0))?;
+        Ok((self.0.clone(), bytes))
+    }
+
+    /// Large files are parsed straight from a memory mapping.
+    fn load(&self, format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        #[cfg(all(unix, feature = "mmap"))]
+        if let Some(mapped) = crate::mmap::Mapped::large(&self.0) {
+            return parse_bytes(&self.0, &mapped, format, opts);
+        }
+        let (name, bytes) = self.read()?;
+        parse_bytes(&name, &bytes, format, opts)
+    }
+}
+
+struct Alias(String);
+
+impl InputSource for Alias {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        open(&crate::settings::get().alias(&self.0)?).read()
+    }
+
+    fn load(&self, format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        open(&crate::settings::get().alias(&self.0)?).load(format, opts)
+    }
+}
+
+struct Stdin;
+
+/// Stdin can only be read once, and `--yaml-anchors preserve` reads the text
+/// before the query loads it, so the first read is kept.
+static STDIN: OnceLock<Vec<u8>> = OnceLock::new();
+
+impl InputSource for Stdin {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        if let Some(bytes) = STDIN.get() {
+            return Ok(("-".to_string(), bytes.clone()));
+        }
+        let mut bytes = Vec::new();
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .context("Failed to read from stdin")?;
+        Ok(("-".to_string(), STDIN.get_or_init(|| bytes).clone()))
+    }
+}
+
+/// Clipboard readers, tried in order, as `--copy` tries their writers.
+const PASTE_TOOLS: &[(&str, &[&str])] = &[
+    ("wl-paste", &["--no-newline"]),
+    ("xclip", &["-selection", "clipboard", "-o"]),
+    ("xsel", &["--clipboard", "--output"]),
+    ("pbpaste", &[]),
+];
+
+struct Clipboard {
+    tools: &'static [(&'static str, &'static [&'static str])],
+}
+
+impl InputSource for Clipboard {
+    /// Named `clipboard`, without an extension, so the format is sniffed from
+    /// the content as it is for stdin.
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let mut failure = None;
+        for (tool, args) in self.tools {
+            let Ok(output) = Command::new(tool).args(*args).output() else {
+                continue;
+            };
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+                failure.get_or_insert(format!("{tool} failed: {stderr}"));
+                continue;
+            }
+            if output.stdout.iter().all(u8::is_ascii_whitespace) {
+                anyhow::bail!("the clipboard is empty");
+            }
+            return Ok(("clipboard".to_string(), output.stdout));
+        }
+        match failure {
+            Some(failure) => anyhow::bail!("reading the clipboard: {failure}"),
+            None => anyhow::bail!("no clipboard tool available — install wl-clipboard (Wayland), xclip/xsel (X11), or pbpaste (macOS)"),
+        }
+    }
+}
+
+struct Member {
+    archive: Box<dyn InputSource>,
+    member: String,
+}
+
+impl InputSource for Member {
+    /// Named after the member, so `config.yaml` is detected as YAML.
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let (archive, bytes) = self.archive.read()?;
+        #[cfg(feature = "archive")]
+        return Ok((
+            self.member.clone(),
+            crate::archive::member(&archive, &bytes, &self.member)?,
+        ));
+        #[cfg(not(feature = "archive"))]
+        {
+            let _ = (archive, bytes, &self.member);
+            anyhow::bail!(disabled("archive members", "archive"));
+        }
+    }
+}
+
+/// `--from-env`: the process environment, one key per variable.
+pub(crate) fn environment() -> Box<dyn InputSource> {
+    Box::new(Environment)
+}
+
+struct Environment;
+
+impl Environment {
+    /// Values are strings, as in a .env file, unless `infer_types`.
+    fn document(&self, infer_types: bool) -> Value {
+        let vars = std::env::vars_os().map(|(key, value)| {
+            let value = value.to_string_lossy();
+            let value = if infer_types {
+                crate::formats::dotenv::typed(&value)
+            } else {
+                Value::String(value.into_owned())
+            };
+            (key.to_string_lossy().into_owned(), value)
+        });
+        Value::Object(vars.collect())
+    }
+}
+
+impl InputSource for Environment {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let document = self.document(false);
+        Ok((
+            "environment.json".to_string(),
+            serde_json::to_vec(&document)?,
+        ))
+    }
+
+    /// Variables are already key/value pairs; of the options only
+    /// `--infer-types` applies.
+    fn load(&self, _format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        Ok(self.document(opts.infer_types))
+    }
+}
+
+/// GET `url`, with `--timeout` and `--retries`.
+// Only snapshot sources read URLs and vault secrets so far.
+#[cfg_attr(not(feature = "archive"), allow(dead_code))]
+pub(crate) fn http(url: &str) -> Result<Box<dyn InputSource>> {
+    #[cfg(feature = "http")]
+    return Ok(Box::new(Http(url.to_string())));
+    #[cfg(not(feature = "http"))]
+    {
+        let _ = url;
+        anyhow::bail!(disabled("URL inputs", "http"));
+    }
+}
+
+#[cfg(feature = "http")]
+struct Http(String);
+
+#[cfg(feature = "http")]
+impl InputSource for Http {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let url = &self.0;
+        let response = crate::net::call(&crate::net::agent().get(url))
+            .map_err(|failed| failed.describe(|e| anyhow::anyhow!("fetching {url}: {e}")))?;
+        let mut bytes = Vec::new();
+        response
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("reading {url}"))?;
+        // Detection goes by the URL path's extension, not the query.
+        let name = url.split(['?', '#']).next().unwrap_or(url);
+        Ok((name.to_string(), bytes))
+    }
+}
+
+/// The secret `vault pull` would fetch, from `backend` (a `--backend`
+/// name) or the configured one.
+#[cfg_attr(not(feature = "archive"), allow(dead_code))]
+pub(crate) fn vault(secret: &str, backend: Option<&str>) -> Result<Box<dyn InputSource>> {
+    #[cfg(feature = "vault")]
+    {
+        let backend = backend
+            .map(str::parse)
+            .transpose()
+            .map_err(anyhow::Error::msg)?;
+        Ok(Box::new(Vault {
+            secret: secret.to_string(),
+            backend,
+        }))
+    }
+    #[cfg(not(feature = "vault"))]
+    {
+        let _ = (secret, backend);
+        anyhow::bail!(disabled("vault inputs", "vault"));
+    }
+}
+
+#[cfg(feature = "vault")]
+struct Vault {
+    secret: String,
+    backend: Option<crate::vault::config::BackendKind>,
+}
+
+#[cfg(feature = "vault")]
+impl InputSource for Vault {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        use crate::vault::{backends, cli};
+
+        let read = cli::read_config_path(None)?;
+        let write = cli::write_config_path(None)?;
+        let mut backend = backends::open(&read, &write, self.backend)?;
+        let pulled = backend.pull(backends::PullRequest {
+            name: Some(&self.secret),
+            id: None,
+        })?;
+        let name = pulled.filename.unwrap_or_else(|| self.secret.clone());
+        Ok((name, pulled.bytes))
+    }
+}
+
+/// A `vault://` secret: its key/value pairs, read with `VAULT_ADDR` and
+/// `VAULT_TOKEN` rather than the configured backend.
+struct VaultKv(String);
+
+impl VaultKv {
+    fn document(&self) -> Result<Value> {
+        #[cfg(feature = "vault")]
+        return crate::vault::backends::hcp::read_kv(&self.0)
+            .with_context(|| format!("reading {VAULT_KV}{}", self.0));
+        #[cfg(not(feature = "vault"))]
+        {
+            let _ = &self.0;
+            anyhow::bail!(disabled("vault:// inputs", "vault"));
+        }
+    }
+}
+
+impl InputSource for VaultKv {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let document = self.document()?;
+        Ok((
+            format!("{VAULT_KV}{}.json", self.0),
+            serde_json::to_vec(&document)?,
+        ))
+    }
+
+    /// The secret is already key/value pairs; `format` does not apply.
+    fn load(&self, _format: Option<Format>, _opts: &ParseOptions) -> Result<Value> {
+        self.document()
+    }
+}
+
+/// Source `name` of a snapshot bundle (its metadata when `name` is empty),
+/// verified against `signers` when given.
+pub(crate) fn archive(
+    bundle: &Path,
+    name: &str,
+    signers: Option<&Path>,
+) -> Result<Box<dyn InputSource>> {
+    #[cfg(feature = "archive")]
+    return Ok(Box::new(Archive {
+        bundle: bundle.to_path_buf(),
+        name: name.to_string(),
+        signers: signers.map(Path::to_path_buf),
+    }));
+    #[cfg(not(feature = "archive"))]
+    {
+        let _ = (bundle, name, signers);
+        anyhow::bail!(disabled("snapshot bundles", "archive"));
+    }
+}
+
+#[cfg(feature = "archive")]
+struct Archive {
+    bundle: std::path::PathBuf,
+    name: String,
+    signers: Option<std::path::PathBuf>,
+}
+
+#[cfg(feature = "archive")]
+impl InputSource for Archive {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let document = self.document()?;
+        Ok((
+            format!("{}.json", self.name),
+            serde_json::to_vec(&document)?,
+        ))
+    }
+
+    /// Bundled documents are already parsed; `format` does not apply.
+    fn load(&self, _format: Option<Format>, _opts: &ParseOptions) -> Result<Value> {
+        self.document()
+    }
+}
+
+#[cfg(feature = "archive")]
+impl Archive {
+    fn document(&self) -> Result<Value> {
+        crate::snapshot::load(&self.bundle, &self.name, self.signers.as_deref())
+    }
+}
+
+/// Why a source left out of this build cannot be read.
+pub(crate) fn disabled(what: &str, feature: &str) -> String {
+    format!("{what} are not available: confctl was built without the `{feature}` feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_load_through_open() {
+        let value = open("testdata/config.json")
+            .load(None, &ParseOptions::default())
+            .unwrap();
+        assert!(value.is_object());
+
+        let err = open("testdata/missing.json").read().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to read file: testdata/missing.json"
+        );
+    }
+
+    #[cfg(feature = "archive")]
+    #[test]
+    fn archive_members_load_by_their_own_name() {
+        let value = open("testdata/archive/backup.tar.gz::etc/app/config.yaml")
+            .load(None, &ParseOptions::default())
+            .unwrap();
+        assert_eq!(value["server"]["port"], 8080);
+
+        let err = open("testdata/archive/configs.zip::app/missing.yaml")
+            .read()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "testdata/archive/configs.zip has no member `app/missing.yaml`"
+        );
+    }
+
+    #[test]
+    fn the_clipboard_is_read_with_the_first_tool_that_runs() {
+        const TOOLS: &[(&str, &[&str])] = &[
+            ("confctl-no-such-tool", &[]),
+            ("echo", &["{\"port\": 8080}"]),
+        ];
+        let value = Clipboard { tools: TOOLS }
+            .load(None, &ParseOptions::default())
+            .unwrap();
+        assert_eq!(value["port"], 8080);
+
+        let read = |tools| Clipboard { tools }.read().unwrap_err().to_string();
+        assert!(read(&[("confctl-no-such-tool", &[])]).starts_with("no clipboard tool"));
+        assert_eq!(read(&[("echo", &[""])]), "the clipboard is empty");
+        assert!(read(&[("false", &[])]).starts_with("reading the clipboard: false failed"));
+    }
+
+    #[test]
+    fn environment_is_an_object_of_strings() {
+        let value = environment().load(None, &ParseOptions::default()).unwrap();
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(value["PATH"], Value::String(path));
+        assert!(value.as_object().unwrap().values().all(Value::is_string));
+    }
+
+    #[cfg(feature = "vault")]
+    #[test]
+    fn vault_backend_names_are_checked_up_front() {
+        assert!(vault("app.env", Some("hcp")).is_ok());
+        let err = vault("app.env", Some("consul")).err().unwrap();
+        assert!(err.to_string().starts_with("unknown backend `consul`"));
+    }
+
+    #[cfg(not(feature = "http"))]
+    #[test]
+    fn disabled_sources_name_their_feature() {
+        let err = http("https://example.com/a.json").err().unwrap();
+        assert!(err.to_string().contains("`http` feature"), "{err}");
+    }
+}
@@ -0,0 +1,432 @@
+//! `confctl k8s` — read the config a Kubernetes ConfigMap or Secret carries.
+//!
+//! The manifest (one resource, a multi-document YAML stream, or a `List`) is
+//! searched for ConfigMaps and Secrets; `--name` picks one when there are
+//! several. Without `--data-key` the entry names are listed. With it, the
+//! entry is taken from `data` (base64-decoded for Secrets) or, failing that,
+//! from a Secret's `stringData` or a ConfigMap's `binaryData` (decoded), and
+//! parsed as a document of its own, its format detected from the key name
+//! (`app.properties`, `settings.yaml`) or its content, so PATH can query
+//! inside it.
+//!
+//! `k8s://KIND/NAMESPACE/NAME` reads the object from the cluster instead,
+//! through `kubectl` and so with its kubeconfig, context and auth plugins;
+//! `--timeout` becomes its `--request-timeout`.
+//! As the query's FILE it is the object itself, a Secret's `data` decoded;
+//! as the manifest of `confctl k8s` it is the object as stored.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::Args;
+use serde_json::{Map, Value};
+
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::input::InputSource;
+use crate::{format_value_with, parse_bytes, parse_file, resolve_path, Format};
+
+/// The file argument prefix of live cluster objects.
+pub(crate) const PREFIX: &str = "k8s://";
+
+#[derive(Args, Debug)]
+pub(crate) struct K8sCli {
+    /// Manifest holding the ConfigMap or Secret, `-` for stdin, or
+    /// `k8s://KIND/NAMESPACE/NAME` to fetch it from the cluster.
+    pub(crate) file: String,
+
+    /// Dotted path to extract from the embedded document.
+    #[arg(requires = "data_key")]
+    pub(crate) path: Option<String>,
+
+    /// Entry of `data` (or `stringData` / `binaryData`) to read. Omit it
+    /// to list the entries.
+    #[arg(long, value_name = "KEY")]
+    pub(crate) data_key: Option<String>,
+
+    /// `metadata.name` of the resource, when the manifest has several.
+    #[arg(long)]
+    pub(crate) name: Option<String>,
+
+    /// Format of the embedded document, instead of detecting it from the
+    /// key name and content.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Print the entry as stored, without parsing it.
+    #[arg(long, requires = "data_key", conflicts_with_all = ["path", "format", "output"])]
+    pub(crate) raw: bool,
+
+    /// Output format of the result (default: JSON, scalars bare).
+    #[arg(short = 'o', long, value_enum)]
+    pub(crate) output: Option<OutputFormat>,
+}
+
+pub(crate) fn run(cli: K8sCli) -> Result<()> {
+    let manifest = if cli.file.starts_with(PREFIX) && !Path::new(&cli.file).exists() {
+        Live(cli.file.clone()).object()?
+    } else {
+        parse_file(&cli.file, None, &ParseOptions::default())?
+    };
+    let resource = find_resource(&manifest, cli.name.as_deref())?;
+
+    let Some(key) = &cli.data_key else {
+        for key in entry_names(resource) {
+            println!("{key}");
+        }
+        return Ok(());
+    };
+    let bytes = entry(resource, key)?;
+    if cli.raw {
+        use std::io::Write;
+        std::io::stdout().write_all(&bytes)?;
+        return Ok(());
+    }
+
+    let document = parse_bytes(key, &bytes, cli.format, &ParseOptions::default())
+        .with_context(|| format!("reading `{key}` (pass --format, or --raw to print it as is)"))?;
+    let result = match &cli.path {
+        Some(path) => resolve_path(&document, path)?,
+        None => &document,
+    };
+    match cli.output {
+        Some(format) => print!("{}", emit::render(result, format)?),
+        None => println!("{}", format_value_with(result, false)),
+    }
+    Ok(())
+}
+
+/// A ConfigMap or Secret in the cluster, fetched when it is read.
+pub(crate) struct Live(pub(crate) String);
+
+#[derive(Debug, PartialEq)]
+struct Target<'a> {
+    kind: &'static str,
+    namespace: Option<&'a str>,
+    name: &'a str,
+    context: Option<&'a str>,
+}
+
+impl Live {
+    /// The object as the API server stores it.
+    fn object(&self) -> Result<Value> {
+        let (_, bytes) = self.read()?;
+        serde_json::from_slice(&bytes).with_context(|| format!("parsing {}", self.0))
+    }
+}
+
+impl InputSource for Live {
+    /// `kubectl get -o json`, named `NAME.json`.
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let target = target(&self.0)?;
+        let bytes = kubectl(&target).with_context(|| format!("reading {}", self.0))?;
+        Ok((format!("{}.json", target.name), bytes))
+    }
+
+    /// The object with a Secret's `data` decoded; `--format` does not apply.
+    fn load(&self, _format: Option<Format>, _opts: &ParseOptions) -> Result<Value> {
+        Ok(decoded(self.object()?))
+    }
+}
+
+/// `k8s://KIND/[NAMESPACE/]NAME[?context=CONTEXT]` split up; without a
+/// namespace, kubectl's current one is used.
+fn target(uri: &str) -> Result<Target<'_>> {
+    let spec = uri.strip_prefix(PREFIX).unwrap_or(uri);
+    let (spec, query) = spec.split_once('?').unwrap_or((spec, ""));
+    let context = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("context="))
+        .filter(|context| !context.is_empty());
+    let segments: Vec<&str> = spec.split('/').collect();
+    let kind = match segments[0].to_ascii_lowercase().as_str() {
+        "configmap" | "configmaps" | "cm" => "configmap",
+        "secret" | "secrets" => "secret",
+        _ => "",
+    };
+    let (namespace, name) = match segments[1..] {
+        [name] => (None, name),
+        [namespace, name] if !namespace.is_empty() => (Some(namespace), name),
+        _ => (None, ""),
+    };
+    if kind.is_empty() || name.is_empty() {
+        bail!(
+            "`{uri}` names no ConfigMap or Secret: write {PREFIX}configmap/NAMESPACE/NAME \
+             or {PREFIX}secret/NAMESPACE/NAME"
+        );
+    }
+    Ok(Target {
+        kind,
+        namespace,
+        name,
+        context,
+    })
+}
+
+/// The context, cluster server and namespace `uri` is read from, as kubectl
+/// resolves them from its kubeconfig; empty when kubectl cannot say.
+pub(crate) fn cluster(uri: &str) -> String {
+    let Ok(target) = target(uri) else {
+        return String::new();
+    };
+    let mut command = Command::new("kubectl");
+    command.args([
+        "config",
+        "view",
+        "--minify",
+        "--output",
+        "jsonpath={.contexts[0].name} {.clusters[0].cluster.server} \
+         {.contexts[0].context.namespace}",
+    ]);
+    if let Some(context) = target.context {
+        command.args(["--context", context]);
+    }
+    match command.output() {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        _ => String::new(),
+    }
+}
+
+/// The object's JSON, or the last line of kubectl's stderr (the ones before
+/// it are client-side logging) as the error.
+fn kubectl(target: &Target) -> Result<Vec<u8>> {
+    let mut command = Command::new("kubectl");
+    let timeout = crate::net::timeout().as_secs().max(1);
+    command.args(["get", target.kind, target.name, "--output", "json"]);
+    command.arg(format!("--request-timeout={timeout}s"));
+    if let Some(namespace) = target.namespace {
+        command.args(["--namespace", namespace]);
+    }
+    if let Some(context) = target.context {
+        command.args(["--context", context]);
+    }
+    let output = command
+        .output()
+        .context("kubectl is needed for k8s:// inputs but could not be run")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .rfind(|line| !line.trim().is_empty())
+            .unwrap_or_default()
+            .trim();
+        bail!("kubectl get failed: {reason}");
+    }
+    Ok(output.stdout)
+}
+
+/// `resource` with each entry of a Secret's `data` replaced by its decoded
+/// text; entries that are not UTF-8 stay base64.
+fn decoded(mut resource: Value) -> Value {
+    if kind(&resource) != Some("Secret") {
+        return resource;
+    }
+    if let Some(data) = resource.get_mut("data").and_then(Value::as_object_mut) {
+        for value in data.values_mut() {
+            let text = value
+                .as_str()
+                .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+                .and_then(|bytes| String::from_utf8(bytes).ok());
+            if let Some(text) = text {
+                *value = Value::String(text);
+            }
+        }
+    }
+    resource
+}
+
+fn kind(resource: &Value) -> Option<&str> {
+    resource
+        .get("kind")
+        .and_then(Value::as_str)
+        .filter(|kind| matches!(*kind, "ConfigMap" | "Secret"))
+}
+
+fn name(resource: &Value) -> &str {
+    resource
+        .pointer("/metadata/name")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+}
+
+/// The ConfigMap or Secret named `name_filter`, or the only one in the
+/// manifest.
+fn find_resource<'a>(manifest: &'a Value, name_filter: Option<&str>) -> Result<&'a Value> {
+    let documents: Vec<&Value> = match manifest {
+        Value::Array(docs) => docs.iter().collect(),
+        doc if doc.get("kind").and_then(Value::as_str) == Some("List") => doc
+            .get("items")
+            .and_then(Value::as_array)
+            .map(|items| items.iter().collect())
+            .unwrap_or_default(),
+        doc => vec![doc],
+    };
+    let candidates: Vec<&Value> = documents
+        .into_iter()
+        .filter(|doc| kind(doc).is_some())
+        .filter(|doc| name_filter.is_none_or(|wanted| name(doc) == wanted))
+        .collect();
+    match candidates.as_slice() {
+        [resource] => Ok(resource),
+        [] => match name_filter {
+            Some(wanted) => bail!("no ConfigMap or Secret named `{wanted}`"),
+            None => bail!("no ConfigMap or Secret in the manifest"),
+        },
+        several => {
+            let names: Vec<String> = several
+                .iter()
+                .map(|doc| format!("{}/{}", kind(doc).unwrap_or_default(), name(doc)))
+                .collect();
+            bail!(
+                "several ConfigMaps/Secrets match ({}); pick one with --name",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+/// The sections an entry can come from, and whether they hold base64.
+fn sections(resource: &Value) -> [(&'static str, bool); 2] {
+    if kind(resource) == Some("Secret") {
+        [("data", true), ("stringData", false)]
+    } else {
+        [("data", false), ("binaryData", true)]
+    }
+}
+
+fn entry_names(resource: &Value) -> Vec<String> {
+    let mut names: Vec<String> = sections(resource)
+        .iter()
+        .filter_map(|(section, _)| resource.get(*section).and_then(Value::as_object))
+        .flat_map(Map::keys)
+        .cloned()
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn entry(resource: &Value, key: &str) -> Result<Vec<u8>> {
+    for (section, base64) in sections(resource) {
+        let Some(value) = resource.get(section).and_then(|data| data.get(key)) else {
+            continue;
+        };
+        let Some(text) = value.as_str() else {
+            bail!("{section}.{key} is not a string");
+        };
+        if !base64 {
+            return Ok(text.as_bytes().to_vec());
+        }
+        return STANDARD
+            .decode(text.trim())
+            .with_context(|| format!("{section}.{key} is not valid base64"));
+    }
+    bail!(
+        "{}/{} has no entry `{key}` (entries: {})",
+        kind(resource).unwrap_or_default(),
+        name(resource),
+        entry_names(resource).join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn manifest() -> Value {
+        json!([
+            {"kind": "Deployment", "metadata": {"name": "api"}},
+            {
+                "kind": "ConfigMap",
+                "metadata": {"name": "api-config"},
+                "data": {"app.properties": "server.port=8080\n"},
+                "binaryData": {"logo.png": "iVBORw=="}
+            },
+            {
+                "kind": "Secret",
+                "metadata": {"name": "api-secrets"},
+                "data": {"db.yaml": STANDARD.encode("password: hunter2\n")},
+                "stringData": {"token": "plain"}
+            }
+        ])
+    }
+
+    #[test]
+    fn entries_decode_by_kind() {
+        let manifest = manifest();
+        let config = find_resource(&manifest, Some("api-config")).unwrap();
+        assert_eq!(entry_names(config), ["app.properties", "logo.png"]);
+        assert_eq!(
+            entry(config, "app.properties").unwrap(),
+            b"server.port=8080\n"
+        );
+        assert_eq!(entry(config, "logo.png").unwrap(), b"\x89PNG");
+
+        let secret = find_resource(&manifest, Some("api-secrets")).unwrap();
+        assert_eq!(entry(secret, "db.yaml").unwrap(), b"password: hunter2\n");
+        assert_eq!(entry(secret, "token").unwrap(), b"plain");
+        let err = entry(secret, "missing").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Secret/api-secrets has no entry `missing` (entries: db.yaml, token)"
+        );
+    }
+
+    #[test]
+    fn resources_are_picked_by_name() {
+        let manifest = manifest();
+        let err = find_resource(&manifest, None).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "several ConfigMaps/Secrets match (ConfigMap/api-config, Secret/api-secrets); pick one with --name"
+        );
+        let err = find_resource(&manifest, Some("api")).unwrap_err();
+        assert_eq!(err.to_string(), "no ConfigMap or Secret named `api`");
+
+        let list = json!({"kind": "List", "items": [manifest[2].clone()]});
+        assert_eq!(name(find_resource(&list, None).unwrap()), "api-secrets");
+    }
+
+    #[test]
+    fn live_uris_name_kind_namespace_and_name() {
+        assert_eq!(
+            target("k8s://configmap/my-ns/app-config").unwrap(),
+            Target {
+                kind: "configmap",
+                namespace: Some("my-ns"),
+                name: "app-config",
+                context: None,
+            }
+        );
+        let secret = target("k8s://Secrets/db?context=prod").unwrap();
+        assert_eq!(
+            (secret.kind, secret.namespace, secret.context),
+            ("secret", None, Some("prod"))
+        );
+        for uri in [
+            "k8s://deployment/ns/api",
+            "k8s://cm/ns/",
+            "k8s://cm//api",
+            "k8s://cm",
+        ] {
+            let err = target(uri).unwrap_err().to_string();
+            assert!(err.contains("names no ConfigMap or Secret"), "{err}");
+        }
+    }
+
+    #[test]
+    fn live_secrets_load_decoded() {
+        let manifest = manifest();
+        let mut secret = manifest[2].clone();
+        secret["data"]["blob"] = json!(STANDARD.encode(b"\xff\xfe"));
+        let secret = decoded(secret);
+        assert_eq!(secret["data"]["db.yaml"], "password: hunter2\n");
+        assert_eq!(secret["data"]["blob"], "//4=");
+        assert_eq!(decoded(manifest[1].clone()), manifest[1]);
+    }
+}
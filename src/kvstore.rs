@@ -0,0 +1,468 @@
+//! `etcd://HOST/KEY` and `consul://HOST/kv/KEY` file arguments: one key of
+//! an etcd (v3 JSON gateway) or Consul KV store, parsed by its name's
+//! extension like a file, or with `--recurse` every key under a prefix as a
+//! nested object (`config/app/db/host` → `{"db": {"host": ...}}`).
+//!
+//! HOST defaults to the one the store's CLI uses: the first of
+//! `ETCDCTL_ENDPOINTS`, or `CONSUL_HTTP_ADDR`, else localhost on the
+//! standard port (2379, 8500). `etcd+https://` and `consul+https://` (or
+//! `CONSUL_HTTP_SSL=true`) talk TLS. etcd users log in with
+//! `ETCDCTL_USER=name:password`, Consul tokens come from
+//! `CONSUL_HTTP_TOKEN`. Needs the `http` feature.
+
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+
+use crate::formats::ParseOptions;
+use crate::input::InputSource;
+use crate::{parse_bytes, Format};
+
+const SCHEMES: [&str; 4] = ["etcd://", "etcd+https://", "consul://", "consul+https://"];
+
+/// Whether `file` is an etcd or Consul URI.
+pub(crate) fn is_uri(file: &str) -> bool {
+    SCHEMES.iter().any(|scheme| file.starts_with(scheme))
+}
+
+/// An etcd or Consul URI, fetched when it is read.
+pub(crate) struct KvKey(pub(crate) String);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Store {
+    Etcd,
+    Consul,
+}
+
+#[derive(Debug, PartialEq)]
+struct Spec {
+    store: Store,
+    /// `http(s)://HOST:PORT`.
+    base: String,
+    key: String,
+}
+
+impl InputSource for KvKey {
+    /// Named after the key, so its extension picks the format.
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let spec = parse(&self.0)?;
+        if spec.key.is_empty() {
+            bail!("`{}` names no key", self.0);
+        }
+        let bytes = fetch(&spec).with_context(|| format!("reading {}", self.0))?;
+        Ok((spec.key, bytes))
+    }
+
+    /// With `--recurse`, the keys under the prefix; values are strings
+    /// unless `--infer-types`.
+    fn load(&self, format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        if !opts.recurse {
+            let (name, bytes) = self.read()?;
+            return parse_bytes(&name, &bytes, format, opts);
+        }
+        let spec = parse(&self.0)?;
+        let prefix = match spec.key.trim_end_matches('/') {
+            "" => String::new(),
+            key => format!("{key}/"),
+        };
+        let pairs = fetch_prefix(&spec, &prefix).with_context(|| format!("reading {}", self.0))?;
+        if pairs.is_empty() {
+            bail!("no keys under `{}`", self.0);
+        }
+        nest(pairs, &prefix, opts.infer_types)
+    }
+}
+
+/// `SCHEME://HOST/KEY` split up, with HOST filled in from the environment.
+fn parse(uri: &str) -> Result<Spec> {
+    let (scheme, rest) = uri.split_once("://").unwrap_or(("", uri));
+    let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let env = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+    let (store, fallback, tls, default_port) = match scheme {
+        "etcd" | "etcd+https" => {
+            let endpoints = env("ETCDCTL_ENDPOINTS")
+                .and_then(|list| list.split(',').next().map(str::to_string));
+            (Store::Etcd, endpoints, scheme == "etcd+https", 2379)
+        }
+        _ => {
+            let ssl = env("CONSUL_HTTP_SSL").is_some_and(|value| value == "true" || value == "1");
+            let tls = scheme == "consul+https" || ssl;
+            (Store::Consul, env("CONSUL_HTTP_ADDR"), tls, 8500)
+        }
+    };
+    let host = match host {
+        "" => fallback.unwrap_or_else(|| "127.0.0.1".to_string()),
+        written => written.to_string(),
+    };
+    let key = match store {
+        Store::Etcd => path.to_string(),
+        Store::Consul => match path.strip_prefix("kv") {
+            Some(key) if key.is_empty() || key.starts_with('/') => {
+                key.trim_start_matches('/').to_string()
+            }
+            _ => bail!("`{uri}` is not a KV path: write consul://HOST/kv/KEY"),
+        },
+    };
+    Ok(Spec {
+        store,
+        base: base_url(&host, tls, default_port),
+        key,
+    })
+}
+
+/// The base URL `uri` is read from, HOST filled in from the environment as
+/// a read would; None when `uri` does not parse.
+pub(crate) fn endpoint(uri: &str) -> Option<String> {
+    parse(uri).ok().map(|spec| spec.base)
+}
+
+/// `host` as a URL: kept when it has a scheme, else given one and, when it
+/// has none, the default port.
+fn base_url(host: &str, tls: bool, default_port: u16) -> String {
+    if host.contains("://") {
+        return host.trim_end_matches('/').to_string();
+    }
+    let scheme = if tls { "https" } else { "http" };
+    match host.contains(':') {
+        true => format!("{scheme}://{host}"),
+        false => format!("{scheme}://{host}:{default_port}"),
+    }
+}
+
+/// The value of one key.
+fn fetch(spec: &Spec) -> Result<Vec<u8>> {
+    #[cfg(feature = "http")]
+    return match spec.store {
+        Store::Etcd => {
+            let mut pairs = etcd::range(&spec.base, spec.key.as_bytes(), None)?;
+            match pairs.pop() {
+                Some((_, value)) => Ok(value),
+                None => bail!("etcd at {} has no key `{}`", spec.base, spec.key),
+            }
+        }
+        Store::Consul => consul::get(&spec.base, &spec.key),
+    };
+    #[cfg(not(feature = "http"))]
+    {
+        let _ = spec;
+        bail!(crate::input::disabled(
+            "etcd:// and consul:// inputs",
+            "http"
+        ));
+    }
+}
+
+/// Every key starting with `prefix`, and its value.
+fn fetch_prefix(spec: &Spec, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    #[cfg(feature = "http")]
+    return match spec.store {
+        Store::Etcd => {
+            let end = range_end(prefix.as_bytes());
+            etcd::range(&spec.base, prefix.as_bytes(), Some(&end))
+        }
+        Store::Consul => consul::list(&spec.base, prefix),
+    };
+    #[cfg(not(feature = "http"))]
+    {
+        let _ = (spec, prefix);
+        bail!(crate::input::disabled(
+            "etcd:// and consul:// inputs",
+            "http"
+        ));
+    }
+}
+
+/// etcd's `range_end` for every key starting with `prefix`: the prefix
+/// with its last byte below 0xff incremented (`\0` for every key).
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+fn range_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    vec![0]
+}
+
+/// `pairs` under `prefix` as nested objects, one level per `/`. Consul's
+/// folder entries (keys ending in `/`) are skipped.
+fn nest(pairs: Vec<(String, Vec<u8>)>, prefix: &str, infer_types: bool) -> Result<Value> {
+    let mut root = Map::new();
+    for (key, value) in pairs {
+        let Some(relative) = key.strip_prefix(prefix) else {
+            continue;
+        };
+        if relative.is_empty() || relative.ends_with('/') {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&value);
+        let value = if infer_types {
+            crate::formats::dotenv::typed(&text)
+        } else {
+            Value::String(text.into_owned())
+        };
+        let segments: Vec<&str> = relative.split('/').filter(|s| !s.is_empty()).collect();
+        let (name, parents) = segments.split_last().unwrap_or((&relative, &[]));
+        let mut map = &mut root;
+        for (depth, parent) in parents.iter().enumerate() {
+            let child = map
+                .entry(parent.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            let Value::Object(child) = child else {
+                bail!(
+                    "`{prefix}{}` is both a value and a prefix of `{key}`",
+                    segments[..=depth].join("/")
+                );
+            };
+            map = child;
+        }
+        if map.get(*name).is_some_and(Value::is_object) {
+            bail!("`{key}` is both a value and a prefix of other keys");
+        }
+        map.insert(name.to_string(), value);
+    }
+    Ok(Value::Object(root))
+}
+
+/// A failed call, with the first line of the store's error body.
+#[cfg(feature = "http")]
+fn api_error(store: &str, err: ureq::Error) -> anyhow::Error {
+    match err {
+        ureq::Error::Status(status, response) => {
+            let body = response.into_string().unwrap_or_default();
+            // etcd answers {"error": "...", "message": "..."}, Consul plain text.
+            let message = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|v| v["message"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            let message = message.lines().next().unwrap_or_default().trim();
+            anyhow::anyhow!("{store} returned {status}: {message}")
+        }
+        ureq::Error::Transport(t) => anyhow::anyhow!("transport error against {store}: {t}"),
+    }
+}
+
+#[cfg(feature = "http")]
+mod etcd {
+    use anyhow::{Context, Result};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::api_error;
+    use crate::net::{self, agent};
+
+    /// The keys from `key` up to `end` (just `key` without one) and their
+    /// values.
+    pub(super) fn range(
+        base: &str,
+        key: &[u8],
+        end: Option<&[u8]>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut body = json!({"key": STANDARD.encode(key)});
+        if let Some(end) = end {
+            body["range_end"] = json!(STANDARD.encode(end));
+        }
+        let mut request = agent().post(&format!("{base}/v3/kv/range"));
+        if let Some(token) = token(base)? {
+            request = request.set("Authorization", &token);
+        }
+        let response: RangeResponse = net::send_json(&request, &body)
+            .map_err(|failed| failed.describe(|err| api_error("etcd", err)))?
+            .into_json()
+            .context("parsing etcd's range response")?;
+        response
+            .kvs
+            .into_iter()
+            .map(|kv| {
+                let key = STANDARD.decode(kv.key).context("decoding an etcd key")?;
+                let value = STANDARD
+                    .decode(kv.value)
+                    .context("decoding an etcd value")?;
+                Ok((String::from_utf8_lossy(&key).into_owned(), value))
+            })
+            .collect()
+    }
+
+    /// A token for `ETCDCTL_USER` (`name:password`), when it is set.
+    fn token(base: &str) -> Result<Option<String>> {
+        let Some(user) = std::env::var("ETCDCTL_USER").ok().filter(|u| !u.is_empty()) else {
+            return Ok(None);
+        };
+        let (name, password) = match user.split_once(':') {
+            Some((name, password)) => (name.to_string(), password.to_string()),
+            None => (
+                user.clone(),
+                std::env::var("ETCDCTL_PASSWORD").unwrap_or_default(),
+            ),
+        };
+        let response: AuthResponse = agent()
+            .post(&format!("{base}/v3/auth/authenticate"))
+            .send_json(json!({"name": name, "password": password}))
+            .map_err(|err| api_error("etcd", err))
+            .with_context(|| format!("logging in to etcd as {name}"))?
+            .into_json()
+            .context("parsing etcd's authenticate response")?;
+        Ok(Some(response.token))
+    }
+
+    #[derive(Deserialize)]
+    struct RangeResponse {
+        #[serde(default)]
+        kvs: Vec<KeyValue>,
+    }
+
+    #[derive(Deserialize)]
+    struct KeyValue {
+        key: String,
+        #[serde(default)]
+        value: String,
+    }
+
+    #[derive(Deserialize)]
+    struct AuthResponse {
+        token: String,
+    }
+}
+
+#[cfg(feature = "http")]
+mod consul {
+    use anyhow::{bail, Context, Result};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::Deserialize;
+
+    use super::api_error;
+    use crate::cloud::encode;
+    use crate::net::{self, agent};
+
+    /// The raw value of `key`.
+    pub(super) fn get(base: &str, key: &str) -> Result<Vec<u8>> {
+        let url = format!("{base}/v1/kv/{}?raw", encode(key, true));
+        let request = request(&url);
+        match net::call(&request) {
+            Ok(response) => {
+                let mut bytes = Vec::new();
+                std::io::Read::read_to_end(&mut response.into_reader(), &mut bytes)
+                    .context("reading Consul's response")?;
+                Ok(bytes)
+            }
+            Err(failed) if failed.status() == Some(404) => {
+                bail!("Consul at {base} has no key `{key}`")
+            }
+            Err(failed) => Err(failed.describe(|err| api_error("Consul", err))),
+        }
+    }
+
+    /// Every key starting with `prefix` and its value.
+    pub(super) fn list(base: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>> {
+        let url = format!("{base}/v1/kv/{}?recurse", encode(prefix, true));
+        let request = request(&url);
+        let entries: Vec<Entry> = match net::call(&request) {
+            Ok(response) => response
+                .into_json()
+                .context("parsing Consul's KV listing")?,
+            Err(failed) if failed.status() == Some(404) => Vec::new(),
+            Err(failed) => return Err(failed.describe(|err| api_error("Consul", err))),
+        };
+        entries
+            .into_iter()
+            .map(|entry| {
+                let value = match entry.value {
+                    Some(value) => STANDARD.decode(value).context("decoding a Consul value")?,
+                    None => Vec::new(),
+                };
+                Ok((entry.key, value))
+            })
+            .collect()
+    }
+
+    fn request(url: &str) -> ureq::Request {
+        let request = agent().get(url);
+        match std::env::var("CONSUL_HTTP_TOKEN") {
+            Ok(token) if !token.is_empty() => request.set("X-Consul-Token", &token),
+            _ => request,
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Entry {
+        key: String,
+        value: Option<String>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn uris_name_the_store_host_and_key() {
+        let spec = |store, base: &str, key: &str| Spec {
+            store,
+            base: base.into(),
+            key: key.into(),
+        };
+        assert_eq!(
+            parse("etcd://10.0.0.5/config/app.yaml").unwrap(),
+            spec(Store::Etcd, "http://10.0.0.5:2379", "config/app.yaml")
+        );
+        assert_eq!(
+            parse("etcd+https://etcd.internal:2390/config").unwrap(),
+            spec(Store::Etcd, "https://etcd.internal:2390", "config")
+        );
+        assert_eq!(
+            parse("consul://consul.service:8500/kv/config/app").unwrap(),
+            spec(Store::Consul, "http://consul.service:8500", "config/app")
+        );
+        assert_eq!(parse("consul://c/kv").unwrap().key, "");
+        assert!(parse("consul://c/config/app")
+            .unwrap_err()
+            .to_string()
+            .contains("consul://HOST/kv/KEY"));
+        assert!(parse("consul://c/kvx/app").is_err());
+        assert_eq!(base_url("https://c:8501/", false, 8500), "https://c:8501");
+        assert_eq!(range_end(b"config/"), b"config0");
+        assert_eq!(range_end(b"a\xff"), b"b");
+        assert_eq!(range_end(b""), b"\0");
+    }
+
+    #[test]
+    fn prefixes_nest_by_slash() {
+        let pairs = |items: &[(&str, &str)]| -> Vec<(String, Vec<u8>)> {
+            items
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.as_bytes().to_vec()))
+                .collect()
+        };
+        let value = nest(
+            pairs(&[
+                ("config/app/", ""),
+                ("config/app/db/host", "db.internal"),
+                ("config/app/db/port", "5432"),
+                ("config/app/debug", "false"),
+            ]),
+            "config/app/",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            json!({"db": {"host": "db.internal", "port": "5432"}, "debug": "false"})
+        );
+        let typed = nest(pairs(&[("p/port", "5432")]), "p/", true).unwrap();
+        assert_eq!(typed, json!({"port": 5432}));
+        let err = nest(pairs(&[("p/a", "1"), ("p/a/b", "2")]), "p/", false).unwrap_err();
+        assert!(
+            err.to_string().contains("both a value and a prefix"),
+            "{err}"
+        );
+        let err = nest(pairs(&[("p/a/b", "2"), ("p/a", "1")]), "p/", false).unwrap_err();
+        assert!(
+            err.to_string().contains("both a value and a prefix"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,618 @@
+//! `confctl lint` — problems a parser accepts without a word, or that other
+//! tools read differently:
+//!
+//! - `duplicate-key` (error): YAML, JSON and .env files keep the last value
+//!   silently (TOML already refuses them, as a `parse-error`).
+//! - `tab-indent` (error): YAML forbids tabs in indentation.
+//! - `mixed-indent` (warning): tabs in one place and spaces in another, or
+//!   YAML blocks indented by different steps.
+//! - `trailing-whitespace` (warning).
+//! - `non-string-key` (warning): YAML keys such as `1:` or `true:`, which
+//!   become strings on the way to JSON.
+//! - `ambiguous-value` (warning): plain YAML scalars that YAML 1.1 parsers
+//!   (PyYAML, Go's yaml.v2) read differently from confctl: `no` → false (the
+//!   Norway problem), `0755` → octal, `1:30` → 90, and `1.10` → 1.1.
+//!
+//! Findings print as `FILE:LINE:COLUMN: severity: message [rule]`. The
+//! command exits 1 when there is an error (or, with `--strict`, a warning).
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+
+use crate::errors::ParseFailure;
+use crate::formats::ParseOptions;
+#[cfg(feature = "yaml")]
+use crate::yaml_preview::{indent_of, is_block_header, is_trivia, Line};
+#[cfg(feature = "yaml")]
+use crate::yaml_strict;
+use crate::{detect_format, input, parse_bytes, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct LintCli {
+    /// Files to check, or `-` for stdin.
+    #[arg(required = true)]
+    pub(crate) files: Vec<String>,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Fail on warnings too, not just errors.
+    #[arg(long)]
+    pub(crate) strict: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct Finding {
+    /// 1-based line and column.
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+    pub(crate) severity: Severity,
+    pub(crate) rule: &'static str,
+    pub(crate) message: String,
+}
+
+impl Finding {
+    fn new(line: usize, column: usize, severity: Severity, rule: &'static str) -> Self {
+        Finding {
+            line,
+            column,
+            severity,
+            rule,
+            message: String::new(),
+        }
+    }
+
+    fn with(mut self, message: String) -> Self {
+        self.message = message;
+        self
+    }
+}
+
+/// Lint every file; `true` when one of them fails (see `--strict`).
+pub(crate) fn run(cli: LintCli) -> Result<bool> {
+    let (mut errors, mut warnings) = (0, 0);
+    for file in &cli.files {
+        let (name, bytes) = input::open(file).read()?;
+        let findings = lint_bytes(&name, &bytes, cli.format)?;
+        if findings.is_empty() {
+            println!("{} {file}: no problems found", "✓".green().bold());
+        }
+        for finding in &findings {
+            let severity = match finding.severity {
+                Severity::Error => {
+                    errors += 1;
+                    "error".red().bold()
+                }
+                Severity::Warning => {
+                    warnings += 1;
+                    "warning".yellow().bold()
+                }
+            };
+            println!(
+                "{file}:{}:{}: {severity}: {} [{}]",
+                finding.line, finding.column, finding.message, finding.rule
+            );
+        }
+    }
+    if errors + warnings > 0 {
+        println!(
+            "{} problem{} ({errors} error{}, {warnings} warning{})",
+            errors + warnings,
+            plural(errors + warnings),
+            plural(errors),
+            plural(warnings)
+        );
+    }
+    Ok(errors > 0 || (cli.strict && warnings > 0))
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+/// Findings for one document, sorted by position. Binary formats are only
+/// checked for parsing.
+pub(crate) fn lint_bytes(name: &str, bytes: &[u8], format: Option<Format>) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    if let Err(err) = parse_bytes(name, bytes, format, &ParseOptions::default()) {
+        let (line, column) = err
+            .downcast_ref::<ParseFailure>()
+            .and_then(|failure| failure.position)
+            .unwrap_or((1, 1));
+        let message = err.root_cause().to_string();
+        let message = message.lines().next().unwrap_or_default().to_string();
+        findings.push(Finding::new(line, column, Severity::Error, "parse-error").with(message));
+    }
+    let Ok(content) = std::str::from_utf8(bytes) else {
+        return Ok(findings);
+    };
+    let format = detect_format(name, content, format).with_context(|| format!("linting {name}"))?;
+    findings.extend(lint_text(content, format));
+    findings.sort_by_key(|f| (f.line, f.column));
+    Ok(findings)
+}
+
+fn lint_text(content: &str, format: Format) -> Vec<Finding> {
+    if matches!(format, Format::Msgpack | Format::Cbor | Format::Plist) {
+        return Vec::new();
+    }
+    let mut findings = whitespace(content, format);
+    match format {
+        #[cfg(feature = "yaml")]
+        Format::Yaml => findings.extend(yaml(content)),
+        Format::Json | Format::Json5 => findings.extend(json_duplicates(content)),
+        Format::Env => findings.extend(env_duplicates(content)),
+        _ => {}
+    }
+    findings
+}
+
+/// Lines without the `\r` of CRLF files.
+fn lines(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .enumerate()
+        .map(|(at, line)| (at + 1, line))
+}
+
+fn whitespace(content: &str, format: Format) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    // The first line indented with spaces and with a tab, outside YAML
+    // (where any tab is an error of its own).
+    let mut first: [Option<usize>; 2] = [None, None];
+    for (line_no, line) in lines(content) {
+        let code = line.trim_end_matches([' ', '\t']);
+        if code.len() < line.len() {
+            findings.push(
+                Finding::new(
+                    line_no,
+                    code.chars().count() + 1,
+                    Severity::Warning,
+                    "trailing-whitespace",
+                )
+                .with("trailing whitespace".to_string()),
+            );
+        }
+        let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+        if indent.is_empty() || code.is_empty() {
+            continue;
+        }
+        if let Some(at) = indent.find('\t') {
+            if format == Format::Yaml {
+                findings.push(
+                    Finding::new(line_no, at + 1, Severity::Error, "tab-indent")
+                        .with("tab in indentation; YAML allows only spaces".to_string()),
+                );
+                continue;
+            }
+        }
+        let kind = usize::from(indent.starts_with('\t'));
+        let other = first[1 - kind];
+        if first[kind].is_none() {
+            first[kind] = Some(line_no);
+            if let Some(other) = other {
+                let (this, that) = if kind == 1 {
+                    ("a tab", "spaces")
+                } else {
+                    ("spaces", "tabs")
+                };
+                findings.push(
+                    Finding::new(line_no, 1, Severity::Warning, "mixed-indent").with(format!(
+                        "indented with {this}, but line {other} uses {that}"
+                    )),
+                );
+            }
+        }
+    }
+    findings
+}
+
+/// Duplicate keys, indentation steps, keys that are not strings and values
+/// YAML 1.1 reads differently, from the same line model as
+/// `--resolve-preview`.
+#[cfg(feature = "yaml")]
+fn yaml(content: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    // Open block mappings: the column of their keys and the keys seen.
+    let mut mappings: Vec<(usize, HashMap<String, usize>)> = Vec::new();
+    // The first indentation step, and the steps already reported.
+    let mut step: Option<(usize, usize)> = None;
+    let mut reported_steps = Vec::new();
+    let mut opener: Option<usize> = None;
+    let mut literal: Option<usize> = None;
+
+    for (line_no, raw) in lines(content) {
+        if let Some(col) = literal {
+            if raw.trim().is_empty() || indent_of(raw) > col {
+                continue;
+            }
+            literal = None;
+        }
+        if raw.starts_with("---") || raw.starts_with("...") {
+            mappings.clear();
+            opener = None;
+            continue;
+        }
+        if is_trivia(raw) || raw.trim_start().starts_with('%') || raw.contains('\t') {
+            continue;
+        }
+        let line = Line::parse(raw);
+        if line.key.is_none() && line.dash_col.is_none() {
+            continue;
+        }
+
+        // Indentation: how far the first entry of a block sits from the key
+        // that opened it. Sequences may sit at the key's own column.
+        if let Some(parent) = opener.take() {
+            let indent = line.indent;
+            if indent > parent {
+                let this = indent - parent;
+                match step {
+                    None => step = Some((this, line_no)),
+                    Some((first, at)) if first != this && !reported_steps.contains(&this) => {
+                        reported_steps.push(this);
+                        findings.push(
+                            Finding::new(line_no, indent + 1, Severity::Warning, "mixed-indent")
+                                .with(format!(
+                                "indented by {this} spaces, but line {at} is indented by {first}"
+                            )),
+                        );
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+
+        if let Some(dash) = line.dash_col {
+            mappings.retain(|(col, _)| *col <= dash);
+        }
+        let owner = match (&line.key, line.raw_key()) {
+            (Some(key), Some(raw_key)) => {
+                let col = line.key_col;
+                mappings.retain(|(c, _)| *c <= col);
+                if mappings.last().is_none_or(|(c, _)| *c < col) {
+                    mappings.push((col, HashMap::new()));
+                }
+                let (_, keys) = mappings.last_mut().expect("pushed above");
+                if let Some(first) = keys.get(key) {
+                    findings.push(
+                        Finding::new(line_no, col + 1, Severity::Error, "duplicate-key").with(
+                            format!(
+                                "duplicate key `{key}` (first at line {first}); the last one wins"
+                            ),
+                        ),
+                    );
+                } else {
+                    keys.insert(key.clone(), line_no);
+                }
+                if let Some(message) = non_string_key(raw_key) {
+                    findings.push(
+                        Finding::new(line_no, col + 1, Severity::Warning, "non-string-key")
+                            .with(message),
+                    );
+                }
+                col
+            }
+            _ => line.dash_col.unwrap_or(line.indent),
+        };
+
+        let value = line.value.as_str();
+        if value.is_empty() {
+            opener = Some(owner);
+        } else if is_block_header(value) {
+            literal = Some(owner);
+        } else if let Some(message) = ambiguous(value) {
+            let column = raw.find(value).map_or(1, |at| at + 1);
+            findings.push(
+                Finding::new(line_no, column, Severity::Warning, "ambiguous-value").with(message),
+            );
+        }
+    }
+    findings
+}
+
+/// Why the unquoted `key` is not a string, if it is not.
+#[cfg(feature = "yaml")]
+fn non_string_key(key: &str) -> Option<String> {
+    if key.starts_with(['"', '\'']) {
+        return None;
+    }
+    if let Some(message) = ambiguous(key) {
+        return Some(format!("key {message}"));
+    }
+    let what = match serde_yaml::from_str::<serde_yaml::Value>(key).ok()? {
+        serde_yaml::Value::Null => "null",
+        serde_yaml::Value::Bool(_) => "a boolean",
+        serde_yaml::Value::Number(_) => "a number",
+        _ => return None,
+    };
+    Some(format!(
+        "key `{key}` is {what}, not a string; it becomes \"{key}\" in JSON, quote it"
+    ))
+}
+
+/// A plain scalar that YAML 1.1 parsers read differently from confctl.
+#[cfg(feature = "yaml")]
+fn ambiguous(value: &str) -> Option<String> {
+    if value.starts_with(['"', '\'', '[', '{', '&', '*', '!', '|', '>']) {
+        return None;
+    }
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let unsigned = value.trim_start_matches(['-', '+']);
+    if matches!(
+        value.to_ascii_lowercase().as_str(),
+        "y" | "n" | "yes" | "no" | "on" | "off"
+    ) {
+        let boolean = matches!(value.to_ascii_lowercase().as_str(), "y" | "yes" | "on");
+        return Some(format!(
+            "`{value}` is the boolean {boolean} in YAML 1.1 parsers (PyYAML, Go yaml.v2) and a string here; quote it"
+        ));
+    }
+    if unsigned.len() > 1 && unsigned.starts_with('0') && digits(unsigned) {
+        let octal = match i64::from_str_radix(unsigned, 8) {
+            Ok(number) => format!("the octal number {number}"),
+            Err(_) => "not a number".to_string(),
+        };
+        return Some(format!(
+            "`{value}` is {octal} in YAML 1.1 parsers and a string here; quote it, or write 0o{}",
+            &unsigned[1..]
+        ));
+    }
+    if unsigned.contains(':') && unsigned.split(':').all(digits) {
+        return Some(format!(
+            "`{value}` is a base-60 number in YAML 1.1 parsers and a string here; quote it"
+        ));
+    }
+    if let Some((whole, fraction)) = unsigned.split_once('.') {
+        if digits(whole) && digits(fraction) && fraction.len() > 1 && fraction.ends_with('0') {
+            let number: f64 = value.parse().ok()?;
+            return Some(format!(
+                "`{value}` is the number {number}; quote it if it is a version"
+            ));
+        }
+    }
+    if yaml_strict::loose(value) {
+        if let Ok(serde_yaml::Value::Number(number)) = serde_yaml::from_str(value) {
+            return Some(format!(
+                "`{value}` is read as the number {number}; quote it to keep the text, or use --yaml-1.2"
+            ));
+        }
+    }
+    None
+}
+
+/// Duplicate keys in JSON objects (JSONC comments allowed), found with a
+/// small scanner since serde_json keeps the last value.
+fn json_duplicates(content: &str) -> Vec<Finding> {
+    enum Frame {
+        Object {
+            keys: HashMap<String, usize>,
+            expect_key: bool,
+        },
+        Array,
+    }
+    let mut findings = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let chars: Vec<char> = content.chars().collect();
+    let (mut line, mut column) = (1, 1);
+    let mut i = 0;
+    let advance = |c: char, line: &mut usize, column: &mut usize| {
+        if c == '\n' {
+            *line += 1;
+            *column = 1;
+        } else {
+            *column += 1;
+        }
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        let (start_line, start_column) = (line, column);
+        match c {
+            '{' => stack.push(Frame::Object {
+                keys: HashMap::new(),
+                expect_key: true,
+            }),
+            '[' => stack.push(Frame::Array),
+            '}' | ']' => {
+                stack.pop();
+            }
+            ',' => {
+                if let Some(Frame::Object { expect_key, .. }) = stack.last_mut() {
+                    *expect_key = true;
+                }
+            }
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    advance(chars[i], &mut line, &mut column);
+                    i += 1;
+                }
+                continue;
+            }
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                let end = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == '*' && chars[j + 1] == '/')
+                    .map_or(chars.len(), |j| j + 2);
+                while i < end {
+                    advance(chars[i], &mut line, &mut column);
+                    i += 1;
+                }
+                continue;
+            }
+            '"' => {
+                let mut end = i + 1;
+                while end < chars.len() && chars[end] != '"' {
+                    end += if chars[end] == '\\' { 2 } else { 1 };
+                }
+                let literal: String = chars[i..=end.min(chars.len() - 1)].iter().collect();
+                while i <= end && i < chars.len() {
+                    advance(chars[i], &mut line, &mut column);
+                    i += 1;
+                }
+                if let Some(Frame::Object { keys, expect_key }) = stack.last_mut() {
+                    if *expect_key {
+                        *expect_key = false;
+                        let key: String = serde_json::from_str(&literal).unwrap_or(literal);
+                        if let Some(first) = keys.get(&key) {
+                            findings.push(
+                                Finding::new(
+                                    start_line,
+                                    start_column,
+                                    Severity::Error,
+                                    "duplicate-key",
+                                )
+                                .with(format!(
+                                    "duplicate key `{key}` (first at line {first}); the last one wins"
+                                )),
+                            );
+                        } else {
+                            keys.insert(key, start_line);
+                        }
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        advance(c, &mut line, &mut column);
+        i += 1;
+    }
+    findings
+}
+
+/// `.env` keys set twice; the last assignment wins.
+fn env_duplicates(content: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for (line_no, line) in lines(content) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let assignment = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+        let Some((key, _)) = assignment.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        if let Some(first) = seen.get(key) {
+            let column = line.len() - assignment.len() + 1;
+            findings.push(
+                Finding::new(line_no, column, Severity::Error, "duplicate-key").with(format!(
+                    "duplicate key `{key}` (first at line {first}); the last one wins"
+                )),
+            );
+        } else {
+            seen.insert(key, line_no);
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(name: &str, content: &str) -> Vec<(usize, usize, &'static str)> {
+        lint_bytes(name, content.as_bytes(), None)
+            .unwrap()
+            .iter()
+            .map(|f| (f.line, f.column, f.rule))
+            .collect()
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_findings() {
+        let content = "\
+server:
+  port: 80
+  host: a\x20
+  port: 8080
+country: NO
+mode: 0755
+mask: 0o22
+timeout: 1:30
+version: 1.10
+1: one
+on: push
+jobs:
+    - name: a
+      name: b
+    - name: c
+";
+        assert_eq!(
+            rules("c.yaml", content),
+            [
+                (3, 10, "trailing-whitespace"),
+                (4, 3, "duplicate-key"),
+                (5, 10, "ambiguous-value"),
+                (6, 7, "ambiguous-value"),
+                (7, 7, "ambiguous-value"),
+                (8, 10, "ambiguous-value"),
+                (9, 10, "ambiguous-value"),
+                (10, 1, "non-string-key"),
+                (11, 1, "non-string-key"),
+                (13, 5, "mixed-indent"),
+                (14, 7, "duplicate-key"),
+            ]
+        );
+        let findings = lint_bytes("c.yaml", content.as_bytes(), None).unwrap();
+        assert_eq!(
+            findings[2].message,
+            "`NO` is the boolean false in YAML 1.1 parsers (PyYAML, Go yaml.v2) and a string here; quote it"
+        );
+        assert_eq!(
+            findings[3].message,
+            "`0755` is the octal number 493 in YAML 1.1 parsers and a string here; quote it, or write 0o755"
+        );
+        assert_eq!(
+            findings[4].message,
+            "`0o22` is read as the number 18; quote it to keep the text, or use --yaml-1.2"
+        );
+        assert_eq!(
+            findings[6].message,
+            "`1.10` is the number 1.1; quote it if it is a version"
+        );
+        assert_eq!(
+            rules("t.yaml", "a:\n\tb: 1\n"),
+            [(2, 1, "parse-error"), (2, 1, "tab-indent")]
+        );
+        assert!(rules("ok.yaml", "a:\n  b: \"no\"\n  c: |\n    x: 1\n    x: 2\n").is_empty());
+    }
+
+    #[test]
+    fn json_and_env_duplicates() {
+        let json = "{\n  \"a\": {\"x\": 1, \"y\": [{\"x\": 2}]},\n  // c\n  \"a\": 2\n}\n";
+        assert_eq!(rules("c.jsonc", json), [(4, 3, "duplicate-key")]);
+        assert!(rules("c.json", "{\"a\": \"a\", \"b\": {\"a\": 1}}").is_empty());
+
+        let env = "A=1\n# A=2\nexport B=1\nA=3\n\tC=1\n  D=2\n";
+        assert_eq!(
+            rules(".env", env),
+            [(4, 1, "duplicate-key"), (6, 1, "mixed-indent")]
+        );
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn parse_errors_carry_their_position() {
+        let findings = lint_bytes("c.toml", b"a = 1\na = 2\n", None).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].line, findings[0].rule), (2, "parse-error"));
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+}
@@ -0,0 +1,276 @@
+//! `--with-location` / `--location-only`: where in FILE the value at PATH
+//! is written, as `file:line:column` for editors (`code -g`, `vim +N`).
+//!
+//! Locations point at the key holding the value, or at the `- ` or first
+//! character of an array item. JSON and JSON5 are read again by the JSON5
+//! parser, which records where each key starts. Block YAML goes through the
+//! `--yaml-anchors` line scan. TOML is followed
+//! through its `[table]` and `[[array]]` headers and dotted keys, and .env
+//! and .properties files by their `KEY=` lines. Anything those miss (YAML
+//! flow collections, TOML inline tables) falls back to searching the text
+//! for the path's keys in order. CSV and binary formats have no location.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{bail, Result};
+
+#[cfg(feature = "json5")]
+use crate::formats::json5;
+use crate::formats::ParseOptions;
+use crate::{detect_format, input, parse_bytes, path, resolve_path, yaml_anchors, Format};
+
+/// A 1-based line and column in a file.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Location {
+    pub(crate) file: String,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Where the value at `path` is written in `file`. A path the document does
+/// not have fails as it does in a query.
+pub(crate) fn find(file: &str, format: Option<Format>, path: &str) -> Result<Location> {
+    let (name, bytes) = input::open(file).read()?;
+    let value = parse_bytes(&name, &bytes, format, &ParseOptions::default())?;
+    resolve_path(&value, path)?;
+    let segments = path::segments(path)?;
+    let found = std::str::from_utf8(&bytes).ok().and_then(|text| {
+        let format = detect_format(&name, text, format).ok()?;
+        locate(text, format, &segments)
+    });
+    match found {
+        Some((line, column)) => Ok(Location {
+            file: name,
+            line,
+            column,
+        }),
+        None => bail!("cannot tell where `{path}` is written in {name}"),
+    }
+}
+
+/// The line and column of the value at `segments` in `text`, read as
+/// `format`.
+pub(crate) fn locate(text: &str, format: Format, segments: &[String]) -> Option<(usize, usize)> {
+    let found = match format {
+        #[cfg(feature = "json5")]
+        Format::Json | Format::Json5 => json5::locate(text, segments).ok().flatten(),
+        Format::Yaml => yaml_anchors::locate(text, segments),
+        Format::Toml => toml_key(text, segments),
+        Format::Env | Format::Properties => assignment(text, &segments.join(".")),
+        Format::Csv | Format::Msgpack | Format::Cbor | Format::Plist => return None,
+        #[allow(unreachable_patterns)]
+        _ => None,
+    };
+    found.or_else(|| search(text, segments, 0))
+}
+
+/// The `KEY=`, `KEY:` or `KEY value` line of a .env or .properties file,
+/// pointing at the key; the last one when it repeats.
+fn assignment(text: &str, key: &str) -> Option<(usize, usize)> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, raw)| {
+            let line = raw.trim_start();
+            let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+            let rest = line.strip_prefix(key)?;
+            if !rest.starts_with(['=', ':', ' ', '\t']) {
+                return None;
+            }
+            Some((index + 1, raw[..raw.len() - line.len()].chars().count() + 1))
+        })
+        .last()
+}
+
+/// TOML: the header or key that defines the path. Values inside an inline
+/// table or array are searched for from the key that holds them.
+fn toml_key(text: &str, segments: &[String]) -> Option<(usize, usize)> {
+    let mut table: Vec<String> = Vec::new();
+    let mut arrays: HashMap<Vec<String>, usize> = HashMap::new();
+    let mut in_string = false;
+    let mut offset = 0;
+    for (index, raw) in text.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += raw.len();
+        let line = raw.trim();
+        // Lines inside a multi-line string are text, not keys.
+        let was_in_string = in_string;
+        if line.matches("\"\"\"").count() % 2 == 1 || line.matches("'''").count() % 2 == 1 {
+            in_string = !in_string;
+        }
+        if was_in_string {
+            continue;
+        }
+        let column = raw.len() - raw.trim_start().len();
+        let at = (index + 1, raw[..column].chars().count() + 1);
+        if let Some(header) = line.strip_prefix("[[") {
+            let keys = dotted_keys(header.split("]]").next()?)?;
+            let count = arrays.entry(keys.clone()).or_insert(0);
+            table = keys;
+            table.push(count.to_string());
+            *count += 1;
+            if table == segments {
+                return Some(at);
+            }
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            table = dotted_keys(header.split(']').next()?)?;
+            if table == segments {
+                return Some(at);
+            }
+            continue;
+        }
+        let Some(key) = line.split('=').next().filter(|_| line.contains('=')) else {
+            continue;
+        };
+        let Some(keys) = dotted_keys(key) else {
+            continue;
+        };
+        let mut full = table.clone();
+        full.extend(keys);
+        if full == segments {
+            return Some(at);
+        }
+        if segments.starts_with(&full) {
+            return search(text, &segments[full.len()..], start + column + key.len());
+        }
+    }
+    None
+}
+
+/// A TOML key as its segments: bare, `"quoted"` or `'literal'`, joined by
+/// dots. None for anything else, such as the start of an inline table.
+fn dotted_keys(text: &str) -> Option<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut chars = text.trim().chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        let key = match chars.peek()? {
+            quote @ ('"' | '\'') => {
+                let quote = *quote;
+                chars.next();
+                let key: String = chars.by_ref().take_while(|c| *c != quote).collect();
+                key
+            }
+            _ => {
+                let mut key = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+                {
+                    key.push(c);
+                }
+                if key.is_empty() {
+                    return None;
+                }
+                key
+            }
+        };
+        keys.push(key);
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        match chars.next() {
+            None => return Some(keys),
+            Some('.') => {}
+            Some(_) => return None,
+        }
+    }
+}
+
+/// The last key of `segments` found in `text` after byte `from`, each key
+/// searched for after the one before it. Array indexes are skipped, since
+/// they are not written out.
+fn search(text: &str, segments: &[String], from: usize) -> Option<(usize, usize)> {
+    let mut from = from;
+    let mut at = None;
+    for segment in segments {
+        match find_key(&text[from..], segment) {
+            Some(offset) => {
+                at = Some(from + offset);
+                from += offset + segment.len();
+            }
+            None if segment.parse::<usize>().is_ok() => {}
+            None => return None,
+        }
+    }
+    let at = at?;
+    let line_start = text[..at].rfind('\n').map_or(0, |i| i + 1);
+    let line = text[..at].matches('\n').count() + 1;
+    Some((line, text[line_start..at].chars().count() + 1))
+}
+
+/// The offset of `key` written as a key in `text`: bare, quoted, in a
+/// `[table]` header or a dotted key, and followed by what ends a key.
+fn find_key(text: &str, key: &str) -> Option<usize> {
+    text.match_indices(key).map(|(at, _)| at).find(|&at| {
+        let before = text[..at].chars().next_back();
+        let after = text[at + key.len()..].chars().next();
+        let starts = before.is_none_or(|c| c.is_whitespace() || "\"'.[{,".contains(c));
+        let ends = after.is_some_and(|c| c == ' ' || c == '\t' || "\"':=.]".contains(c));
+        starts && ends
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segments(path: &str) -> Vec<String> {
+        path::segments(path).unwrap()
+    }
+
+    #[test]
+    fn toml_follows_tables_and_dotted_keys() {
+        let text = "\
+title = \"x\"
+notes = '''
+port = 1
+'''
+
+[db]
+port = 5432
+pool.size = 5
+
+[[servers]]
+name = \"a\"
+
+[[servers]]
+  name = \"b\"
+  tags = { env = \"prod\" }
+";
+        let at = |path: &str| locate(text, Format::Toml, &segments(path));
+        assert_eq!(at("db"), Some((6, 1)));
+        assert_eq!(at("db.port"), Some((7, 1)));
+        assert_eq!(at("db.pool.size"), Some((8, 1)));
+        assert_eq!(at("servers.1.name"), Some((14, 3)));
+        assert_eq!(at("servers.1.tags.env"), Some((15, 12)));
+        // Not the `port = 1` line inside the multi-line string.
+        assert_eq!(toml_key(text, &segments("port")), None);
+    }
+
+    #[test]
+    fn env_and_properties_point_at_the_key() {
+        let env = "# x\nPORT=1\n  export HOST = a\nPORT=2\n";
+        assert_eq!(locate(env, Format::Env, &segments("PORT")), Some((4, 1)));
+        assert_eq!(locate(env, Format::Env, &segments("HOST")), Some((3, 10)));
+        let props = "server.port: 80\nserver.portal=x\n";
+        assert_eq!(
+            locate(props, Format::Properties, &segments("server.portal")),
+            Some((2, 1))
+        );
+    }
+
+    #[test]
+    fn search_finds_keys_in_order() {
+        let json = "{\n  \"port\": 1,\n  \"db\": {\n    \"import\": 2,\n    \"port\": 3\n  }\n}\n";
+        assert_eq!(search(json, &segments("db.port"), 0), Some((5, 6)));
+        let flow = "servers: [{name: a, port: 1}, {name: b, port: 2}]\n";
+        assert_eq!(search(flow, &segments("servers.0.port"), 0), Some((1, 21)));
+        assert_eq!(search(flow, &segments("servers.missing"), 0), None);
+    }
+}
@@ -1,41 +1,489 @@
-use std::fs;
-use std::io::{self, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail, Context, Result};
 use base64::{engine::general_purpose::STANDARD, Engine};
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{ArgGroup, Parser, Subcommand, ValueEnum};
 use colored::Colorize;
+#[cfg(feature = "yaml")]
+use serde::Deserialize;
 use serde_json::{Map, Value};
 
+use emit::{EmitOptions, OutputFormat};
+use errors::{ErrorFormat, ParseFailure, PathError};
+use formats::ParseOptions;
+use theme::Theme;
+
+mod age;
+mod aggregate;
+mod apply;
+#[cfg(feature = "archive")]
+mod archive;
+mod assert;
+mod atomic;
+mod cache;
+mod check;
+mod cloud;
+mod coerce;
+mod completions;
+mod compose;
+mod compress;
+mod convert;
+mod datetime;
 mod diff;
+mod edit;
+mod embedded;
+mod emit;
 mod env_edit;
+mod env_override;
+mod errors;
+mod examples;
+mod exec;
+mod explain;
+mod expr;
+mod find;
+mod formats;
+mod generate;
+mod git;
+mod git_driver;
+mod glob;
+mod grep;
+mod hash;
+mod history;
+mod input;
+mod k8s;
+mod kvstore;
+mod lint;
+mod location;
+mod man;
+mod merge;
+mod merge3;
+#[cfg(all(unix, feature = "mmap"))]
+mod mmap;
+mod net;
+mod parallel;
+mod patch;
+mod path;
+mod pluck;
+mod profile;
+mod regex;
+mod render;
+mod repl;
+mod schema;
+mod select;
+mod set;
+mod settings;
+mod signature;
+mod sizes;
+#[cfg(feature = "archive")]
+mod snapshot;
+mod sort;
+mod split;
+mod stream;
+mod template;
+mod theme;
+mod undo;
+#[cfg(feature = "vault")]
 mod vault;
+mod yaml_anchors;
+mod yaml_preview;
+#[cfg(feature = "yaml")]
+mod yaml_strict;
 
 #[derive(Parser)]
 #[command(
     name = "confctl",
     version,
-    about = "CLI for querying configuration files (JSON, YAML, TOML, ENV)"
+    long_version = long_version(),
+    after_long_help = examples::help_footer(),
+    about = "CLI for querying configuration files (JSON, YAML, TOML, ENV, CSV, MessagePack, CBOR)",
+    group(
+        ArgGroup::new("aggregate")
+            .args(["length", "sum", "min", "max", "avg", "unique"])
+            .conflicts_with_all(["output", "decode", "encode", "encrypt_age", "decrypt_age"])
+    ),
+    group(ArgGroup::new("time_conversion").args(["as_epoch", "as_rfc3339", "humanize_duration"])),
+    group(
+        ArgGroup::new("size_conversion")
+            .args(["as_bytes", "humanize_bytes"])
+            .conflicts_with("time_conversion")
+    )
 )]
 struct Cli {
+    /// Config file to read: `-` for stdin (the default when piped), a
+    /// quoted glob, `ARCHIVE::MEMBER`, `@alias`, `clip:`, or a remote
+    /// source such as `git::REPO//PATH`, `s3://`, `vault://`, `etcd://` or
+    /// `k8s://`.
     file: Option<String>,
+
+    /// Dotted path or expression to print; the whole document when omitted.
     path: Option<String>,
 
     #[arg(long, value_enum)]
     format: Option<Format>,
 
+    /// CSV field separator (default `,`; tab for .tsv). Use `\t` for tabs.
+    #[arg(long, value_name = "CHAR", value_parser = formats::csv::parse_delimiter)]
+    delimiter: Option<char>,
+
+    /// CSV has no header row: return rows as arrays instead of objects.
+    #[arg(long = "no-headers")]
+    no_headers: bool,
+
+    /// Parse .env input with dotenv quoting rules: `export KEY=`, escapes
+    /// in double quotes, and quoted values spanning several lines.
+    #[arg(long)]
+    strict_env: bool,
+
+    /// Type .env values (and `--from-env` variables) as numbers and
+    /// booleans (`PORT=8080` → 8080) instead of keeping them as strings.
+    #[arg(long)]
+    infer_types: bool,
+
+    /// With an `etcd://` or `consul://` FILE, read every key under it as a
+    /// nested object, one level per `/`, instead of one key's value.
+    #[arg(long)]
+    recurse: bool,
+
+    /// Keep only the documents (or top-level array items) where PATH equals
+    /// VALUE, e.g. `--select kind=Deployment`. Repeat to require several.
+    #[arg(long, value_name = "PATH=VALUE", value_parser = select::parse_selector)]
+    select: Vec<select::Selector>,
+
+    /// Read FILE from a bundle made by `confctl snapshot` instead of the
+    /// filesystem: FILE names a source in it (omit it for the bundle's
+    /// metadata).
+    #[arg(long, value_name = "BUNDLE", conflicts_with = "resolve_preview")]
+    from_snapshot: Option<PathBuf>,
+
+    /// Require the snapshot's signature to verify against this SSH allowed
+    /// signers file.
+    #[arg(long, value_name = "FILE", requires = "from_snapshot")]
+    snapshot_signers: Option<PathBuf>,
+
+    /// Query the process environment instead of a file: each variable is a
+    /// top-level key and the only positional is the path
+    /// (`confctl --from-env HOME`, `confctl --from-env -o yaml`).
+    #[arg(long, conflicts_with_all = ["from_snapshot", "resolve_preview"])]
+    from_env: bool,
+
+    /// Query the system clipboard, as the FILE `clip:` does: the only
+    /// positional is the path and the format is detected from the content
+    /// (wl-paste / xclip / xsel / pbpaste, first one found).
+    #[arg(long, conflicts_with_all = ["from_snapshot", "from_env", "profile"])]
+    clipboard: bool,
+
+    /// Override values from environment variables named PREFIX plus the key
+    /// as `-o env` spells it: with `--env-prefix APP_`, `APP_SERVER_PORT`
+    /// replaces `server.port`. Only keys the file has are overridden.
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["from_env", "resolve_preview"])]
+    env_prefix: Option<String>,
+
+    /// Layer FILE's profile over it, as apps do at startup: with
+    /// `config.yaml`, `config.NAME.yaml` is merged over it and `.env` next
+    /// to it overrides keys by their `-o env` name.
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["from_snapshot", "from_env", "resolve_preview"])]
+    profile: Option<String>,
+
+    /// Order of the `--profile` layers, later ones winning; leave one out to
+    /// skip it. Default: base,profile,dotenv.
+    #[arg(long, value_enum, value_delimiter = ',', requires = "profile")]
+    layers: Vec<profile::Layer>,
+
+    /// Print the YAML with anchors, aliases and `<<` merge keys expanded,
+    /// keeping its comments — the effective document, for review.
+    #[arg(long, conflicts_with_all = ["path", "output", "decode", "encode", "compact"])]
+    resolve_preview: bool,
+
+    /// YAML anchors, aliases and `<<` merge keys. `expand` applies merge
+    /// keys too, giving the document apps see; `preserve` prints YAML that
+    /// keeps `&anchors` and `*aliases`; `report` lists each alias and the
+    /// anchor it resolves from. Unset, aliases are copied and `<<` stays a
+    /// plain key.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        conflicts_with = "resolve_preview"
+    )]
+    yaml_anchors: Option<yaml_anchors::AnchorMode>,
+
+    /// Type plain YAML scalars by the YAML 1.2 JSON schema: only `true`,
+    /// `false`, `null` and JSON numbers are typed, so `True`, `~`, `0x1F`
+    /// or `+12` stay strings unless tagged (`!!int 0x1F`).
+    #[arg(long = "yaml-1.2")]
+    yaml_1_2: bool,
+
+    /// Extract PATH from a large JSON file without parsing all of it: only
+    /// the value at PATH is built, and reading stops after it. Automatic for
+    /// `.json` files of 256 MiB or more when nothing needs the whole
+    /// document.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "from_snapshot",
+            "from_env",
+            "profile",
+            "env_prefix",
+            "select",
+            "resolve_preview",
+            "yaml_anchors"
+        ]
+    )]
+    stream: bool,
+
+    /// Print where PATH's value is written in FILE, as `file:line:column`,
+    /// on a line before the value.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "from_snapshot",
+            "from_env",
+            "profile",
+            "env_prefix",
+            "select",
+            "resolve_preview",
+            "yaml_anchors",
+            "stream",
+            "aggregate"
+        ]
+    )]
+    with_location: bool,
+
+    /// Print only where PATH's value is written, as `file:line:column`:
+    /// `code -g $(confctl cfg.yaml db.host --location-only)`.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "from_snapshot",
+            "from_env",
+            "profile",
+            "env_prefix",
+            "select",
+            "resolve_preview",
+            "yaml_anchors",
+            "stream",
+            "with_location"
+        ]
+    )]
+    location_only: bool,
+
+    /// Query the files a glob FILE matches on N threads (default: one per
+    /// CPU). Results still print in file order.
+    #[arg(short = 'j', long, value_name = "N")]
+    jobs: Option<std::num::NonZeroUsize>,
+
+    /// Sort the resolved array by the value at KEY (a dotted path) in each
+    /// item before printing, e.g. `--sort-by metadata.name`. Items without
+    /// KEY go last.
+    #[arg(long, value_name = "KEY", conflicts_with = "resolve_preview")]
+    sort_by: Option<String>,
+
+    /// Sort descending with --sort-by.
+    #[arg(long, requires = "sort_by")]
+    reverse: bool,
+
+    /// Project each item of the resolved array to these comma-separated
+    /// fields (dotted paths): one field gives a list of its values, several
+    /// an object per item. Pair with `-o tsv` for a table.
+    #[arg(
+        long,
+        visible_alias = "map",
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        conflicts_with = "resolve_preview"
+    )]
+    pluck: Vec<String>,
+
+    /// Print the number of elements (array), keys (object) or characters
+    /// (string) at the path instead of the value.
+    #[arg(long)]
+    length: bool,
+
+    /// Print the sum of a numeric array (or of an object's values).
+    #[arg(long)]
+    sum: bool,
+
+    /// Print the smallest number in an array.
+    #[arg(long)]
+    min: bool,
+
+    /// Print the largest number in an array.
+    #[arg(long)]
+    max: bool,
+
+    /// Print the mean of a numeric array.
+    #[arg(long)]
+    avg: bool,
+
+    /// Print the distinct items of an array, in first-seen order.
+    #[arg(long)]
+    unique: bool,
+
+    /// Base64-decode the value at PATH.
     #[arg(short = 'd', long = "decode", conflicts_with = "encode")]
     decode: bool,
 
+    /// Base64-encode the value at PATH.
     #[arg(short = 'e', long = "encode", conflicts_with = "decode")]
     encode: bool,
 
+    /// Base64-decode the string leaves under every match of PATTERN (`*`
+    /// matches any key or index) and keep the structure, e.g.
+    /// `--decode-path data.*` for a Kubernetes Secret. Repeatable.
+    #[arg(long, value_name = "PATTERN")]
+    decode_path: Vec<String>,
+
+    /// Transform the value at PATH with comma-separated steps, in order:
+    /// `base64d`, `base64e`, `trim`, `upper`, `lower`, a `--format` name to
+    /// parse a string, or `.PATH` to resolve inside the result, e.g.
+    /// `--apply base64d,json,.db.host`.
+    #[arg(
+        long,
+        value_name = "STEPS",
+        value_delimiter = ',',
+        value_parser = apply::parse_step
+    )]
+    apply: Vec<apply::Step>,
+
+    /// Expand string values that hold JSON or YAML (or a format named by
+    /// their key, such as `config.json`) so PATH can go on into them:
+    /// `data.config.json.db.host`.
+    #[arg(long, conflicts_with_all = ["stream", "with_location", "location_only"])]
+    parse_embedded: bool,
+
+    /// Rewrite the timestamp at PATH, or every timestamp string under it,
+    /// as epoch seconds. Reads RFC 3339, RFC 2822, `YYYY-MM-DD[ HH:MM:SS]`
+    /// and epoch seconds or milliseconds.
+    #[arg(long)]
+    as_epoch: bool,
+
+    /// Rewrite timestamps as in --as-epoch, but as RFC 3339 in UTC.
+    #[arg(long)]
+    as_rfc3339: bool,
+
+    /// Rewrite the duration at PATH, or every duration string under it, as
+    /// `1h 30m`. Reads `90s`, `1h30m`, `1.5 hours`, `PT1H30M`, `01:30:00`
+    /// and seconds.
+    #[arg(long)]
+    humanize_duration: bool,
+
+    /// Rewrite the size at PATH, or every size string under it, as a byte
+    /// count. Reads `10Mi`, `2GB`, `1.5GiB`, `128k` and other Kubernetes,
+    /// SI and IEC suffixes.
+    #[arg(long)]
+    as_bytes: bool,
+
+    /// Rewrite sizes as in --as-bytes, but as `1.5Gi` in powers of 1024;
+    /// a number at PATH counts as bytes.
+    #[arg(long)]
+    humanize_bytes: bool,
+
     /// Mask sensitive values before printing: keys matching PASS, PWD, SECRET, TOKEN, KEY,
     /// HASH, CREDENTIAL plus values with known secret shapes (ghp_*, sk-*, AKIA*, JWTs, PEM).
-    #[arg(short = 'r', long = "redact")]
+    #[arg(short = 'r', long = "redact", visible_alias = "mask-secrets")]
     redact: bool,
 
+    /// Extra key pattern to mask (case-insensitive substring, repeatable).
+    /// Implies --redact. $CONFCTL_MASK_PATTERNS (comma-separated) adds
+    /// patterns too, but only when redacting; it does not turn it on.
+    #[arg(long = "mask-pattern", value_name = "PATTERN")]
+    mask_patterns: Vec<String>,
+
+    /// Text written in place of masked values (default `<redacted>`),
+    /// e.g. `--mask-with '*****'`. Implies --redact.
+    #[arg(long = "mask-with", value_name = "TEXT")]
+    mask_with: Option<String>,
+
+    /// Encrypt the output to an age recipient (`age1…`, `ssh-…`, or a
+    /// recipients file; repeatable). Prints ASCII-armored ciphertext.
+    #[arg(
+        long = "encrypt-age",
+        value_name = "RECIPIENT",
+        conflicts_with_all = ["decode", "encode", "decrypt_age"]
+    )]
+    encrypt_age: Vec<String>,
+
+    /// Decrypt an age-armored value with the key from --identity.
+    #[arg(
+        long = "decrypt-age",
+        requires = "identity",
+        conflicts_with_all = ["decode", "encode"]
+    )]
+    decrypt_age: bool,
+
+    /// age identity (private key) file used by --decrypt-age.
+    #[arg(long, value_name = "FILE")]
+    identity: Option<PathBuf>,
+
+    /// Print one line with values interpolated, e.g.
+    /// `--template 'host={.db.host} port={.db.port}'`. Paths start with `.`
+    /// at PATH's value (or the document); `{.}` is the value itself.
+    #[arg(
+        long,
+        value_name = "TEXT",
+        conflicts_with_all = [
+            "output",
+            "decode",
+            "compact",
+            "aggregate",
+            "resolve_preview",
+            "location_only"
+        ]
+    )]
+    template: Option<String>,
+
+    /// Print the result as yaml, toml, env, csv, xml, properties, ini, hcl
+    /// (Terraform `.tfvars` / blocks) or json-canonical (RFC 8785) instead
+    /// of JSON. Flat formats warn about what they lose.
+    #[arg(
+        short = 'o',
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        conflicts_with = "decode"
+    )]
+    output: Option<OutputFormat>,
+
+    /// Root element name for `--output xml`.
+    #[arg(long, value_name = "NAME", default_value = "config")]
+    xml_root: String,
+
+    /// Keys starting with this prefix become XML attributes (`@id` → id="…").
+    #[arg(long, value_name = "PREFIX", default_value = "@")]
+    xml_attr_prefix: String,
+
+    /// Spaces per level in XML output; 0 writes everything on one line.
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    xml_indent: usize,
+
+    /// Keep `${…}` and `%{…}` in strings as HCL templates for `--output hcl`
+    /// (Terraform JSON); by default they are escaped and stay literal.
+    #[arg(long)]
+    hcl_templates: bool,
+
+    /// Fail instead of warning when --output or `convert` would flatten,
+    /// stringify or drop values.
+    #[arg(long, global = true)]
+    strict_convert: bool,
+
+    /// How to report errors: `text`, or `json` for a single
+    /// `{"error": {...}}` object on stderr that wrapper tools can parse.
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    error_format: ErrorFormat,
+
+    /// When to colour output: `auto` (on a terminal, unless NO_COLOR is
+    /// set), `always` or `never`. The palette comes from CONFCTL_THEME.
+    #[arg(long, global = true, value_enum)]
+    color: Option<theme::ColorChoice>,
+
+    /// Print nothing and exit 0 when PATH is missing, instead of failing
+    /// with exit code 2. Parse and type errors still fail.
+    #[arg(long)]
+    no_fail: bool,
+
     /// Minified single-line JSON output — ideal for CI/pipeline env vars.
     #[arg(short = 'c', long = "compact")]
     compact: bool,
@@ -45,6 +493,57 @@ struct Cli {
     #[arg(long = "copy")]
     copy: bool,
 
+    /// Write the output to this file instead of stdout, never coloured.
+    /// Refuses to overwrite an existing file unless --force is given.
+    #[arg(long, value_name = "FILE", conflicts_with = "location_only")]
+    out: Option<PathBuf>,
+
+    /// Create `--out`'s parent directories when they do not exist.
+    #[arg(long, requires = "out")]
+    mkdir: bool,
+
+    /// Overwrite `--out` if it already exists.
+    #[arg(long, requires = "out")]
+    force: bool,
+
+    /// Refuse every subcommand that writes files or remote state. Also
+    /// enabled by CONFCTL_READ_ONLY=1 — for locked-down shells and sudo wrappers.
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// Print a unified diff of each file the command would write, instead
+    /// of writing it.
+    #[arg(long, global = true)]
+    diff: bool,
+
+    /// Cache `git::`, `k8s://`, cloud, `vault://`, `etcd://` and `consul://`
+    /// reads on disk for this long (`60s`, `5m`). Also CONFCTL_CACHE_TTL.
+    #[arg(long, global = true, value_name = "DURATION", value_parser = datetime::parse_duration_arg)]
+    cache_ttl: Option<Duration>,
+
+    /// Fetch remote sources again, ignoring --cache-ttl and CONFCTL_CACHE_TTL.
+    #[arg(long, global = true, conflicts_with = "cache_ttl")]
+    no_cache: bool,
+
+    /// Give up on a network request after this long (`30s`, the default).
+    #[arg(long, global = true, value_name = "DURATION", value_parser = datetime::parse_duration_arg)]
+    timeout: Option<Duration>,
+
+    /// Try a network request that failed in a way that may pass (refused,
+    /// timed out, 429, 5xx) this many more times, backing off in between.
+    #[arg(long, global = true, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Refuse any input without a good detached signature by this public
+    /// key: minisign, PEM (cosign) or OpenPGP (gpg).
+    #[arg(long, global = true, value_name = "KEY")]
+    verify_sig: Option<PathBuf>,
+
+    /// The signature --verify-sig checks, instead of FILE.minisig, FILE.sig
+    /// or FILE.asc next to the input.
+    #[arg(long, global = true, value_name = "SIG", requires = "verify_sig")]
+    signature: Option<String>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -54,22 +553,370 @@ enum Command {
     /// Compare two config files with a human-friendly diff.
     Diff(diff::DiffCli),
 
-    /// Add or update KEY=VALUE entries in a .env file, preserving comments.
+    /// Add or update KEY=VALUE entries in a .env file, preserving comments,
+    /// or values at a path in a JSON, YAML or TOML file.
     Set(env_edit::SetCli),
 
     /// Remove keys from a .env file, preserving comments.
     Unset(env_edit::UnsetCli),
 
+    /// Apply a JSON Patch (RFC 6902) or merge patch (RFC 7386) to a config
+    /// file, printing the result or editing it in place.
+    Patch(patch::PatchCli),
+
+    /// Edit a subtree of a JSON, YAML or TOML file in $EDITOR and splice it
+    /// back once it parses.
+    Edit(edit::EditCli),
+
+    /// Re-serialise a config file in another format (stdout or --out).
+    Convert(convert::ConvertCli),
+
+    /// Run a command with a config subtree exported as environment variables.
+    Exec(exec::ExecCli),
+
+    /// Fill a Handlebars-style template from config values.
+    Render(render::RenderCli),
+
+    /// Capture declared files, URLs and vault secrets into one bundle for
+    /// offline use with `--from-snapshot`.
+    #[cfg(feature = "archive")]
+    Snapshot(snapshot::SnapshotCli),
+
+    /// Write each subtree matched by a pattern to its own file.
+    Split(split::SplitCli),
+
+    /// Merge every config file in a directory, in lexical order.
+    ComposeDir(merge::ComposeDirCli),
+
+    /// Three-way merge of two versions of a config with their common
+    /// ancestor, key by key; exits 1 when conflicts are left.
+    Merge(merge3::MergeCli),
+
+    /// Git merge driver: merge %O %A %B by structure into %A; exits 1 when
+    /// conflicts are left. See `.gitattributes` in the README.
+    GitMergeDriver(git_driver::MergeDriverCli),
+
+    /// Git textconv: print FILE as YAML with sorted keys, for `git diff`.
+    GitTextconv(git_driver::TextconvCli),
+
+    /// Infer a JSON Schema (types, required keys, enums) from a config file.
+    ToSchema(schema::ToSchemaCli),
+
+    /// Write a skeleton config from a JSON Schema: defaults, enum
+    /// placeholders and the descriptions as YAML comments.
+    Generate(generate::GenerateCli),
+
+    /// Print a digest of each file's canonical form, equal for equivalent
+    /// YAML/JSON/TOML documents.
+    Hash(hash::HashCli),
+
+    /// Read a ConfigMap or Secret entry (Secrets base64-decoded) and query
+    /// it as a document of its own.
+    K8s(k8s::K8sCli),
+
+    /// Resolve each docker compose service's environment (`env_file:` plus
+    /// `environment:`) and query it.
+    Compose(compose::ComposeCli),
+
+    /// Print every path whose last key matches a name or regex, with its
+    /// value.
+    Find(find::FindCli),
+
+    /// Show which file, line or variable set a value of a layered config,
+    /// and which layers it overrode.
+    Explain(explain::ExplainCli),
+
+    /// Print every leaf whose value matches a regex, with its path.
+    Grep(grep::GrepCli),
+
+    /// Report duplicate keys, bad indentation, trailing whitespace and
+    /// values other YAML parsers read differently, with line numbers.
+    Lint(lint::LintCli),
+
+    /// Parse each file (or every config file under a directory) and
+    /// validate it against JSON Schemas, for pre-commit hooks.
+    Check(check::CheckCli),
+
+    /// Check predicates such as `'server.port == 8080'` against a config
+    /// file, exiting 1 and listing the failures when any does not hold.
+    Assert(assert::AssertCli),
+
+    /// Print example commands by topic (querying, converting, stdin,
+    /// base64, ...).
+    Examples(examples::ExamplesCli),
+
+    /// Print the manual page, as roff: `confctl man > confctl.1`.
+    Man(man::ManCli),
+
+    /// Print a completion script for bash, zsh or fish, which completes
+    /// keys in PATH arguments too.
+    Completions(completions::CompletionsCli),
+
+    /// What the completion scripts run on Tab.
+    #[command(name = "__complete", hide = true)]
+    Complete(completions::CompleteCli),
+
+    /// Load files once and query them interactively, with tab completion of
+    /// paths and a history.
+    Repl(repl::ReplCli),
+
+    /// List, summarise or re-run past invocations (opt-in via CONFCTL_HISTORY=1).
+    History(history::HistoryCli),
+
+    /// Restore a file as it was before its last in-place edit (opt-in via
+    /// CONFCTL_UNDO=1).
+    Undo(undo::UndoCli),
+
     /// Push/pull secret files to a Bunker Vault server.
+    #[cfg(feature = "vault")]
     Vault(vault::cli::VaultCli),
 }
 
+impl Cli {
+    /// The aggregation flag given, if any (clap allows at most one).
+    fn aggregate(&self) -> Option<aggregate::Aggregate> {
+        use aggregate::Aggregate;
+        [
+            (self.length, Aggregate::Length),
+            (self.sum, Aggregate::Sum),
+            (self.min, Aggregate::Min),
+            (self.max, Aggregate::Max),
+            (self.avg, Aggregate::Avg),
+            (self.unique, Aggregate::Unique),
+        ]
+        .into_iter()
+        .find_map(|(set, op)| set.then_some(op))
+    }
+
+    /// The timestamp or duration rewrite asked for, if any (clap allows at
+    /// most one).
+    fn time_conversion(&self) -> Option<datetime::Conversion> {
+        use datetime::Conversion;
+        [
+            (self.as_epoch, Conversion::Epoch),
+            (self.as_rfc3339, Conversion::Rfc3339),
+            (self.humanize_duration, Conversion::HumanDuration),
+        ]
+        .into_iter()
+        .find_map(|(set, conversion)| set.then_some(conversion))
+    }
+
+    /// The size rewrite asked for, if any.
+    fn size_conversion(&self) -> Option<sizes::Conversion> {
+        if self.as_bytes {
+            Some(sizes::Conversion::Bytes)
+        } else if self.humanize_bytes {
+            Some(sizes::Conversion::Human)
+        } else {
+            None
+        }
+    }
+
+    fn converts_units(&self) -> bool {
+        self.time_conversion().is_some() || self.size_conversion().is_some()
+    }
+}
+
+impl Command {
+    /// Name of the operation when running it would write local files or
+    /// remote state; `None` for read-only commands.
+    fn mutation(&self) -> Option<&'static str> {
+        match self {
+            Command::Diff(_)
+            | Command::ComposeDir(_)
+            | Command::ToSchema(_)
+            | Command::Generate(_)
+            | Command::GitTextconv(_)
+            | Command::Hash(_)
+            | Command::K8s(_)
+            | Command::Compose(_)
+            | Command::Find(_)
+            | Command::Explain(_)
+            | Command::Grep(_)
+            | Command::Lint(_)
+            | Command::Check(_)
+            | Command::Assert(_)
+            | Command::Repl(_)
+            | Command::Examples(_)
+            | Command::Man(_)
+            | Command::Completions(_)
+            | Command::Complete(_) => None,
+            Command::Set(_) => Some("set"),
+            Command::Unset(_) => Some("unset"),
+            Command::Patch(patch_cli) => patch_cli.mutation(),
+            Command::Edit(_) => Some("edit"),
+            Command::Convert(convert_cli) => convert_cli.mutation(),
+            Command::Merge(merge_cli) => merge_cli.mutation(),
+            Command::GitMergeDriver(_) => Some("git-merge-driver"),
+            // The child can do anything, so a read-only shell must not run it.
+            Command::Exec(_) => Some("exec"),
+            Command::Render(render_cli) => render_cli.mutation(),
+            #[cfg(feature = "archive")]
+            Command::Snapshot(_) => Some("snapshot"),
+            Command::Split(_) => Some("split"),
+            Command::History(history_cli) => history_cli.mutation(),
+            Command::Undo(undo_cli) => undo_cli.mutation(),
+            #[cfg(feature = "vault")]
+            Command::Vault(vault_cli) => vault_cli.cmd.mutation(),
+        }
+    }
+}
+
+/// Env var equivalent of `--read-only`.
+const READ_ONLY_ENV: &str = "CONFCTL_READ_ONLY";
+
+/// True when the env var is set to 1/true/yes/on (case-insensitive).
+pub(crate) fn env_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|v| {
+        matches!(
+            v.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        )
+    })
+}
+
+/// The writes `--diff` can show instead of making: those that only replace
+/// local files.
+const PREVIEWABLE: &[&str] = &[
+    "set",
+    "unset",
+    "patch --in-place",
+    "edit",
+    "convert --in-place",
+    "convert --out",
+    "render --out",
+    "split",
+    "merge --in-place",
+    "undo",
+    "--out",
+];
+
+/// Fail unless `mutation` is a write `--diff` can preview.
+fn enforce_preview(mutation: Option<&str>) -> Result<()> {
+    match mutation {
+        Some(op) if PREVIEWABLE.contains(&op) => Ok(()),
+        Some(op) => bail!("--diff cannot preview `{op}`: it does more than write local files"),
+        None => bail!("--diff previews the files a command writes, and this one writes none"),
+    }
+}
+
+/// Fail with a policy message when a mutating command runs in read-only mode.
+fn enforce_read_only(mutation: Option<&str>, read_only: bool) -> Result<()> {
+    match mutation {
+        Some(op) if read_only => bail!(
+            "read-only mode is active (--read-only / {READ_ONLY_ENV}): \
+             `{op}` modifies files or remote state and is disabled by policy"
+        ),
+        _ => Ok(()),
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
 pub(crate) enum Format {
     Json,
+    /// JSON5, which also covers JSONC (comments and trailing commas).
+    #[value(alias = "jsonc")]
+    Json5,
     Yaml,
     Toml,
     Env,
+    Csv,
+    Msgpack,
+    Cbor,
+    /// Apple property list, XML or binary.
+    Plist,
+    /// Java `.properties`.
+    Properties,
+}
+
+impl Format {
+    /// Canonical file extension (without the dot) used when writing files.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Json5 => "json5",
+            Format::Yaml => "yaml",
+            Format::Toml => "toml",
+            Format::Env => "env",
+            Format::Csv => "csv",
+            Format::Msgpack => "msgpack",
+            Format::Cbor => "cbor",
+            Format::Plist => "plist",
+            Format::Properties => "properties",
+        }
+    }
+
+    /// The Cargo feature that builds this format in; `None` for JSON and
+    /// `.env`, which every build reads.
+    pub(crate) fn feature(self) -> Option<&'static str> {
+        match self {
+            Format::Json | Format::Env => None,
+            Format::Csv => Some("csv"),
+            other => Some(other.extension()),
+        }
+    }
+
+    /// Whether this build can read the format.
+    pub(crate) fn compiled(self) -> bool {
+        match self {
+            Format::Json | Format::Env => true,
+            Format::Json5 => cfg!(feature = "json5"),
+            Format::Yaml => cfg!(feature = "yaml"),
+            Format::Toml => cfg!(feature = "toml"),
+            Format::Csv => cfg!(feature = "csv"),
+            Format::Msgpack => cfg!(feature = "msgpack"),
+            Format::Cbor => cfg!(feature = "cbor"),
+            Format::Plist => cfg!(feature = "plist"),
+            Format::Properties => cfg!(feature = "properties"),
+        }
+    }
+
+    /// An error naming the missing feature when this build leaves the
+    /// format out.
+    pub(crate) fn ensure_compiled(self) -> Result<()> {
+        match self.feature() {
+            Some(feature) if !self.compiled() => bail!(
+                "{} is not available: confctl was built without the `{feature}` feature",
+                self.extension().to_ascii_uppercase()
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `--version`: the version, then the input formats and optional
+/// features this build was compiled with.
+fn long_version() -> &'static str {
+    static TEXT: OnceLock<String> = OnceLock::new();
+    TEXT.get_or_init(|| {
+        let formats: Vec<&str> = Format::value_variants()
+            .iter()
+            .filter(|format| format.compiled())
+            .map(|format| format.extension())
+            .collect();
+        let features: Vec<&str> = [
+            ("http", cfg!(feature = "http")),
+            ("vault", cfg!(feature = "vault")),
+            ("archive", cfg!(feature = "archive")),
+            ("mmap", cfg!(feature = "mmap")),
+            ("s3", cfg!(feature = "s3")),
+            ("gcs", cfg!(feature = "gcs")),
+            ("azure", cfg!(feature = "azure")),
+        ]
+        .into_iter()
+        .filter_map(|(name, on)| on.then_some(name))
+        .collect();
+        format!(
+            "{}\nformats: {}\nfeatures: {}",
+            env!("CARGO_PKG_VERSION"),
+            formats.join(", "),
+            if features.is_empty() {
+                "none".to_string()
+            } else {
+                features.join(", ")
+            }
+        )
+    })
 }
 
 fn looks_like_env_format(content: &str) -> bool {
@@ -94,7 +941,11 @@ fn looks_like_env_format(content: &str) -> bool {
     total_non_empty > 0 && valid_lines == total_non_empty
 }
 
-fn detect_format(file_path: &str, content: &str, forced_format: Option<Format>) -> Result<Format> {
+pub(crate) fn detect_format(
+    file_path: &str,
+    content: &str,
+    forced_format: Option<Format>,
+) -> Result<Format> {
     if let Some(format) = forced_format {
         return Ok(format);
     }
@@ -113,33 +964,108 @@ fn detect_format(file_path: &str, content: &str, forced_format: Option<Format>)
 
     match ext.as_deref() {
         Some("json") => Ok(Format::Json),
+        Some("json5" | "jsonc") => Ok(Format::Json5),
         Some("yaml" | "yml") => Ok(Format::Yaml),
         Some("toml") => Ok(Format::Toml),
         Some("env") => Ok(Format::Env),
+        Some("csv" | "tsv") => Ok(Format::Csv),
+        Some("msgpack" | "mpk") => Ok(Format::Msgpack),
+        Some("cbor") => Ok(Format::Cbor),
+        Some("plist") => Ok(Format::Plist),
+        Some("properties") => Ok(Format::Properties),
         Some(other) => {
             bail!(
-                "Unsupported file extension: .{other}. Supported: .json, .yaml, .yml, .toml, .env"
+                "Unsupported file extension: .{other}. Supported: .json, .yaml, .yml, .toml, .env, .csv, .tsv, .msgpack, .cbor, .plist, .properties, .json5, .jsonc"
             )
         }
         None => {
             if looks_like_env_format(content) {
-                Ok(Format::Env)
-            } else if serde_json::from_str::<Value>(content).is_ok() {
-                Ok(Format::Json)
-            } else if toml::from_str::<toml::Value>(content).is_ok() {
-                Ok(Format::Toml)
-            } else if serde_yaml::from_str::<Value>(content).is_ok() {
-                Ok(Format::Yaml)
-            } else {
-                bail!(
-                    "Could not determine file format for '{file_path}'. Use a known extension or pass --format."
-                )
+                return Ok(Format::Env);
+            }
+            #[cfg(feature = "plist")]
+            if formats::plist::looks_like_xml(content) {
+                return Ok(Format::Plist);
+            }
+            if serde_json::from_str::<Value>(content).is_ok() {
+                return Ok(Format::Json);
             }
+            #[cfg(feature = "toml")]
+            if toml::from_str::<toml::Value>(content).is_ok() {
+                return Ok(Format::Toml);
+            }
+            #[cfg(feature = "yaml")]
+            if parse_yaml(content, &ParseOptions::default()).is_ok() {
+                return Ok(Format::Yaml);
+            }
+            bail!(
+                "Could not determine file format for '{file_path}'. Use a known extension or pass --format."
+            )
+        }
+    }
+}
+
+/// A YAML stream. Several `---`-separated documents (Kubernetes bundles,
+/// Helm output) become an array of the non-empty ones; a lone document is
+/// returned as-is. With `merge_keys`, `<<` keys are applied; with
+/// `yaml_1_2`, plain scalars are typed by the JSON schema.
+#[cfg(feature = "yaml")]
+fn parse_yaml(content: &str, opts: &ParseOptions) -> Result<Value> {
+    let strict;
+    let content = if opts.yaml_1_2 {
+        strict = yaml_strict::quote_loose_scalars(content);
+        &strict
+    } else {
+        content
+    };
+    let docs = serde_yaml::Deserializer::from_str(content)
+        .map(|doc| {
+            if !opts.merge_keys {
+                return Ok(Value::deserialize(doc)?);
+            }
+            let mut value = serde_yaml::Value::deserialize(doc)?;
+            value.apply_merge()?;
+            Ok(serde_json::to_value(value)?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut docs: Vec<Value> = docs.into_iter().filter(|doc| !doc.is_null()).collect();
+    Ok(match docs.len() {
+        0 => Value::Null,
+        1 => docs.remove(0),
+        _ => Value::Array(docs),
+    })
+}
+
+/// A parsed TOML document as a `Value`, moved over node by node rather than
+/// serialised to JSON and parsed again. Datetimes keep the shape serde has
+/// always given them here: `{"$__toml_private_datetime": "1979-05-27"}`.
+#[cfg(feature = "toml")]
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(text) => Value::String(text),
+        toml::Value::Integer(number) => Value::from(number),
+        toml::Value::Float(number) => {
+            serde_json::Number::from_f64(number).map_or(Value::Null, Value::Number)
+        }
+        toml::Value::Boolean(flag) => Value::Bool(flag),
+        toml::Value::Datetime(datetime) => {
+            let mut map = Map::new();
+            map.insert(
+                "$__toml_private_datetime".to_string(),
+                Value::String(datetime.to_string()),
+            );
+            Value::Object(map)
         }
+        toml::Value::Array(items) => Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
     }
 }
 
-fn parse_env_format(content: &str) -> Value {
+fn parse_env_format(content: &str, infer_types: bool) -> Value {
     let mut map = Map::new();
 
     for line in content.lines() {
@@ -159,19 +1085,12 @@ fn parse_env_format(content: &str) -> Value {
                 value = &value[1..value.len() - 1];
             }
 
-            let json_value = if let Ok(n) = value.parse::<i64>() {
-                Value::Number(n.into())
-            } else if let Ok(n) = value.parse::<f64>() {
-                Value::Number(serde_json::Number::from_f64(n).unwrap_or_else(|| 0.into()))
-            } else if value.eq_ignore_ascii_case("true") {
-                Value::Bool(true)
-            } else if value.eq_ignore_ascii_case("false") {
-                Value::Bool(false)
+            let value = if infer_types {
+                formats::dotenv::typed(value)
             } else {
                 Value::String(value.to_string())
             };
-
-            map.insert(key, json_value);
+            map.insert(key, value);
         }
     }
 
@@ -182,74 +1101,245 @@ pub(crate) fn parse_content(
     file_path: &str,
     content: &str,
     forced_format: Option<Format>,
+) -> Result<Value> {
+    parse_content_with(file_path, content, forced_format, &ParseOptions::default())
+}
+
+pub(crate) fn parse_content_with(
+    file_path: &str,
+    content: &str,
+    forced_format: Option<Format>,
+    opts: &ParseOptions,
 ) -> Result<Value> {
     let format = detect_format(file_path, content, forced_format)?;
+    format.ensure_compiled()?;
+    let failure = |position: Option<(usize, usize)>| ParseFailure {
+        file: file_path.to_string(),
+        format,
+        position,
+        source_line: position
+            .and_then(|(line, _)| content.lines().nth(line.checked_sub(1)?))
+            .map(str::to_string),
+    };
 
     let value = match format {
-        Format::Json => serde_json::from_str::<Value>(content)
-            .with_context(|| format!("Failed to parse JSON: {file_path}"))?,
-        Format::Yaml => serde_yaml::from_str::<Value>(content)
-            .with_context(|| format!("Failed to parse YAML: {file_path}"))?,
+        // `.json` files with comments (VS Code settings, tsconfig) are common
+        // enough that a strict failure falls back to the JSON5 parser.
+        Format::Json => match serde_json::from_str::<Value>(content) {
+            Ok(value) => value,
+            Err(err) => {
+                #[cfg(feature = "json5")]
+                let retried = formats::json5::parse(content);
+                #[cfg(not(feature = "json5"))]
+                let retried = Err(());
+                retried.map_err(|_| {
+                    let position = errors::json_position(&err);
+                    anyhow::Error::new(err).context(failure(position))
+                })?
+            }
+        },
+        #[cfg(feature = "json5")]
+        Format::Json5 => formats::json5::parse(content).map_err(|err| {
+            let position = formats::error_position(&err);
+            err.context(failure(position))
+        })?,
+        #[cfg(feature = "yaml")]
+        Format::Yaml => parse_yaml(content, opts).map_err(|err| {
+            let position = errors::yaml_position(&err);
+            err.context(failure(position))
+        })?,
+        #[cfg(feature = "toml")]
         Format::Toml => {
-            let toml_value: toml::Value = toml::from_str(content)
-                .with_context(|| format!("Failed to parse TOML: {file_path}"))?;
-            let json_str =
-                serde_json::to_string(&toml_value).context("Failed to serialize TOML to JSON")?;
-            serde_json::from_str::<Value>(&json_str).context("Failed to deserialize TOML-JSON")?
+            let toml_value: toml::Value = toml::from_str(content).map_err(|err| {
+                // toml's Display draws its own snippet; keep just the message
+                // so the caret is drawn once, the same way for every format.
+                let position = errors::toml_position(&err, content);
+                anyhow!("{}", err.message().trim_end()).context(failure(position))
+            })?;
+            toml_to_json(toml_value)
+        }
+        Format::Env if opts.strict_env => formats::dotenv::parse(content, opts.infer_types)
+            .map_err(|err| {
+                let position = formats::error_position(&err);
+                err.context(failure(position))
+            })?,
+        Format::Env => parse_env_format(content, opts.infer_types),
+        #[cfg(feature = "csv")]
+        Format::Csv => {
+            formats::csv::parse(file_path, content, opts).with_context(|| failure(None))?
+        }
+        Format::Msgpack | Format::Cbor => parse_binary(file_path, content.as_bytes(), format)?,
+        #[cfg(feature = "plist")]
+        Format::Plist if content.as_bytes().starts_with(formats::plist::BINARY_MAGIC) => {
+            parse_binary(file_path, content.as_bytes(), format)?
         }
-        Format::Env => parse_env_format(content),
+        #[cfg(feature = "plist")]
+        Format::Plist => formats::plist::parse_xml(content).map_err(|err| {
+            let position = formats::error_position(&err);
+            err.context(failure(position))
+        })?,
+        #[cfg(feature = "properties")]
+        Format::Properties => formats::properties::parse(content).map_err(|err| {
+            let position = formats::error_position(&err);
+            err.context(failure(position))
+        })?,
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("ensure_compiled turns away {format:?}"),
     };
 
     Ok(value)
 }
 
-fn parse_file(file_path: &str, forced_format: Option<Format>) -> Result<Value> {
-    let content = if file_path == "-" {
-        let mut input = String::new();
-        io::stdin()
-            .read_to_string(&mut input)
-            .context("Failed to read from stdin")?;
-        input
-    } else {
-        fs::read_to_string(file_path)
-            .with_context(|| format!("Failed to read file: {file_path}"))?
-    };
+/// Entry point for raw input. gzip/zstd input (by a `.gz`/`.zst` suffix or
+/// the magic bytes) is decompressed first, and the name without the suffix
+/// picks the format: `app.yaml.gz` is YAML.
+pub(crate) fn parse_bytes(
+    file_path: &str,
+    bytes: &[u8],
+    forced_format: Option<Format>,
+    opts: &ParseOptions,
+) -> Result<Value> {
+    let (compression, inner) = compress::Compression::of_input(file_path, bytes);
+    if compression == compress::Compression::None {
+        return parse_uncompressed(file_path, bytes, forced_format, opts);
+    }
+    let bytes = compression
+        .decompress(bytes)
+        .with_context(|| format!("Failed to decompress {file_path}"))?;
+    let by_name = forced_format.or_else(|| {
+        Path::new(inner).extension()?;
+        detect_format(inner, "", None).ok()
+    });
+    match by_name {
+        Some(format) => parse_uncompressed(file_path, &bytes, Some(format), opts),
+        None => parse_uncompressed(inner, &bytes, None, opts),
+    }
+}
+
+/// UTF-8 text goes through `parse_content_with`; anything else must be
+/// MessagePack or CBOR, chosen by --format, by the extension, or by trying
+/// MessagePack and then CBOR.
+fn parse_uncompressed(
+    file_path: &str,
+    bytes: &[u8],
+    forced_format: Option<Format>,
+    opts: &ParseOptions,
+) -> Result<Value> {
+    if let Ok(content) = std::str::from_utf8(bytes) {
+        return parse_content_with(file_path, content, forced_format, opts);
+    }
+
+    let by_name = forced_format.or_else(|| {
+        Path::new(file_path).extension()?;
+        detect_format(file_path, "", None).ok()
+    });
+    match by_name {
+        Some(format @ (Format::Msgpack | Format::Cbor | Format::Plist)) => {
+            parse_binary(file_path, bytes, format)
+        }
+        #[cfg(feature = "plist")]
+        None if bytes.starts_with(formats::plist::BINARY_MAGIC) => {
+            parse_binary(file_path, bytes, Format::Plist)
+        }
+        Some(format) => bail!(
+            "{file_path} is not valid UTF-8 text, so it cannot be parsed as {}",
+            format.extension()
+        ),
+        None => parse_binary(file_path, bytes, Format::Msgpack)
+            .or_else(|_| parse_binary(file_path, bytes, Format::Cbor))
+            .map_err(|_| {
+                anyhow!(
+                    "{file_path} is not valid UTF-8 text; pass --format msgpack or --format cbor for binary input"
+                )
+            }),
+    }
+}
+
+#[cfg_attr(
+    not(any(feature = "msgpack", feature = "cbor", feature = "plist")),
+    allow(unused_variables)
+)]
+fn parse_binary(file_path: &str, bytes: &[u8], format: Format) -> Result<Value> {
+    format.ensure_compiled()?;
+    match format {
+        #[cfg(feature = "msgpack")]
+        Format::Msgpack => formats::msgpack::decode(bytes)
+            .with_context(|| format!("Failed to parse MessagePack: {file_path}")),
+        #[cfg(feature = "plist")]
+        Format::Plist => formats::plist::decode_binary(bytes)
+            .with_context(|| format!("Failed to parse binary plist: {file_path}")),
+        #[cfg(feature = "cbor")]
+        Format::Cbor => formats::cbor::decode(bytes)
+            .with_context(|| format!("Failed to parse CBOR: {file_path}")),
+        _ => unreachable!("{format:?} is not a binary format"),
+    }
+}
+
+pub(crate) fn parse_file(
+    file_path: &str,
+    forced_format: Option<Format>,
+    opts: &ParseOptions,
+) -> Result<Value> {
+    input::open(file_path).load(forced_format, opts)
+}
 
-    parse_content(file_path, &content, forced_format)
+fn resolve_preview(file_path: &str, forced_format: Option<Format>) -> Result<String> {
+    let content = yaml_text(file_path, forced_format, "--resolve-preview")?;
+    #[cfg(feature = "yaml")]
+    return yaml_preview::expand(&content).with_context(|| format!("Failed to expand {file_path}"));
+    #[cfg(not(feature = "yaml"))]
+    unreachable!("yaml_text turns away {} bytes of YAML", content.len());
+}
+
+/// The text of a YAML FILE, for the flags that work on the source rather
+/// than the parsed value.
+fn yaml_text(file_path: &str, forced_format: Option<Format>, flag: &str) -> Result<String> {
+    let (name, bytes) = input::open(file_path).read()?;
+    let content = String::from_utf8(bytes).with_context(|| format!("{name} is not UTF-8 text"))?;
+    let format = detect_format(&name, &content, forced_format)?;
+    if format != Format::Yaml {
+        bail!(
+            "{flag} needs YAML input, but {file_path} is {}",
+            format.extension()
+        );
+    }
+    format.ensure_compiled()?;
+    Ok(content)
 }
 
-fn resolve_path<'a>(value: &'a Value, dotted_path: &str) -> Result<&'a Value> {
-    let segments: Vec<&str> = dotted_path.split('.').collect();
+pub(crate) fn resolve_path<'a>(value: &'a Value, dotted_path: &str) -> Result<&'a Value> {
+    let segments = path::segments(dotted_path)?;
     let mut current = value;
 
     for (i, segment) in segments.iter().enumerate() {
-        let path_so_far = segments[..=i].join(".");
+        let path_so_far = path::join(&segments[..=i]);
 
         match current {
             Value::Object(map) => {
-                current = map.get(*segment).ok_or_else(|| {
-                    anyhow!("Key not found: '{segment}' (at path '{path_so_far}')")
+                current = map.get(segment).ok_or_else(|| PathError::KeyNotFound {
+                    segment: segment.to_string(),
+                    path: path_so_far.clone(),
                 })?;
             }
             Value::Array(arr) => {
-                let index: usize = segment.parse().map_err(|_| {
-                    anyhow!(
-                        "Expected numeric index for array access, got '{segment}' (at path '{path_so_far}')"
-                    )
+                let index: usize = segment.parse().map_err(|_| PathError::NotAnIndex {
+                    segment: segment.to_string(),
+                    path: path_so_far.clone(),
                 })?;
-                current = arr.get(index).ok_or_else(|| {
-                    anyhow!(
-                        "Array index {index} out of bounds (length {}) at path '{path_so_far}'",
-                        arr.len()
-                    )
+                current = arr.get(index).ok_or_else(|| PathError::OutOfBounds {
+                    segment: segment.to_string(),
+                    path: path_so_far.clone(),
+                    index,
+                    len: arr.len(),
                 })?;
             }
             _ => {
-                let parent_path = segments[..i].join(".");
-                bail!(
-                    "Cannot traverse into a scalar value at '{parent_path}' \
-                     (trying to access '{segment}')"
-                );
+                return Err(PathError::Scalar {
+                    segment: segment.to_string(),
+                    path: path_so_far,
+                    parent: path::join(&segments[..i]),
+                }
+                .into());
             }
         }
     }
@@ -257,7 +1347,78 @@ fn resolve_path<'a>(value: &'a Value, dotted_path: &str) -> Result<&'a Value> {
     Ok(current)
 }
 
-fn format_value_with(value: &Value, compact: bool) -> String {
+/// `resolve_path` for in-place edits of the resolved value, with the same
+/// errors.
+pub(crate) fn resolve_path_mut<'a>(
+    value: &'a mut Value,
+    dotted_path: &str,
+) -> Result<&'a mut Value> {
+    resolve_path(value, dotted_path)?;
+    let segments = path::segments(dotted_path)?;
+    let target = segments
+        .iter()
+        .try_fold(value, |current, segment| match current {
+            Value::Object(map) => map.get_mut(segment),
+            Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?),
+            _ => None,
+        });
+    Ok(target.expect("resolved above"))
+}
+
+/// Every subtree matching a dotted pattern where a `*` segment matches any
+/// object key or array index. Returns the concrete segments of each match
+/// alongside the value, in document order. Literal segments that do not
+/// exist simply produce no match.
+pub(crate) fn select_paths<'a>(
+    value: &'a Value,
+    pattern: &str,
+) -> Result<Vec<(Vec<String>, &'a Value)>> {
+    let segments = path::segments(pattern)?;
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let mut matches = Vec::new();
+    collect_matches(value, &segments, &mut Vec::new(), &mut matches);
+    Ok(matches)
+}
+
+fn collect_matches<'a>(
+    value: &'a Value,
+    segments: &[&str],
+    trail: &mut Vec<String>,
+    out: &mut Vec<(Vec<String>, &'a Value)>,
+) {
+    let Some((segment, rest)) = segments.split_first() else {
+        out.push((trail.clone(), value));
+        return;
+    };
+
+    let children: Vec<(String, &Value)> = match (value, *segment) {
+        (Value::Object(map), "*") => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        (Value::Array(arr), "*") => arr
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i.to_string(), v))
+            .collect(),
+        (Value::Object(map), key) => map
+            .get(key)
+            .map(|v| vec![(key.to_string(), v)])
+            .unwrap_or_default(),
+        (Value::Array(arr), index) => index
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| arr.get(i))
+            .map(|v| vec![(index.to_string(), v)])
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+
+    for (key, child) in children {
+        trail.push(key);
+        collect_matches(child, rest, trail, out);
+        trail.pop();
+    }
+}
+
+pub(crate) fn format_value_with(value: &Value, compact: bool) -> String {
     match value {
         Value::String(s) => s.clone(),
         Value::Null => "null".to_string(),
@@ -296,7 +1457,10 @@ fn copy_to_clipboard(text: &str) -> Result<()> {
         }
         match child.wait() {
             Ok(status) if status.success() => {
-                eprintln!("{} copied to clipboard ({tool})", "✓".green().bold());
+                eprintln!(
+                    "{} copied to clipboard ({tool})",
+                    theme::stderr("✓".green().bold())
+                );
                 return Ok(());
             }
             _ => continue,
@@ -305,22 +1469,61 @@ fn copy_to_clipboard(text: &str) -> Result<()> {
     bail!("no clipboard tool available — install wl-clipboard (Wayland), xclip/xsel (X11), or pbcopy (macOS)")
 }
 
-fn colorize_json(value: &Value, indent: usize) -> String {
+/// Print a query's `text`, or with `--out` write it to that file, creating
+/// its directory with `--mkdir`. The confirmation goes to stderr, as
+/// `--copy`'s does.
+fn write_output(cli: &Cli, text: &str) -> Result<()> {
+    let Some(out) = &cli.out else {
+        print!("{text}");
+        return Ok(());
+    };
+    if out.exists() && !cli.force {
+        bail!(
+            "{} already exists; pass --force to overwrite",
+            out.display()
+        );
+    }
+    if let Some(dir) = out.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if cli.mkdir {
+            if !atomic::previewing() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("creating {}", dir.display()))?;
+            }
+        } else if !dir.is_dir() {
+            bail!(
+                "{} does not exist; pass --mkdir to create it",
+                dir.display()
+            );
+        }
+    }
+    atomic::write(out, text, None)?;
+    if atomic::previewing() {
+        return Ok(());
+    }
+    eprintln!(
+        "{} wrote {}",
+        theme::stderr("✓".green().bold()),
+        out.display()
+    );
+    Ok(())
+}
+
+fn colorize_json(value: &Value, indent: usize, theme: &Theme) -> String {
     let indent_str = "  ".repeat(indent);
     let next_indent = "  ".repeat(indent + 1);
 
     match value {
-        Value::Null => "null".bright_black().bold().to_string(),
-        Value::Bool(b) => b.to_string().white().to_string(),
-        Value::Number(n) => n.to_string().white().to_string(),
-        Value::String(s) => format!("\"{}\"", s).green().to_string(),
+        Value::Null => theme.null.paint("null"),
+        Value::Bool(b) => theme.boolean.paint(&b.to_string()),
+        Value::Number(n) => theme.number.paint(&n.to_string()),
+        Value::String(s) => theme.string.paint(&format!("\"{}\"", s)),
         Value::Array(arr) => {
             if arr.is_empty() {
                 "[]".to_string()
             } else {
                 let items: Vec<String> = arr
                     .iter()
-                    .map(|v| format!("{}{}", next_indent, colorize_json(v, indent + 1)))
+                    .map(|v| format!("{}{}", next_indent, colorize_json(v, indent + 1, theme)))
                     .collect();
                 format!("[\n{}\n{}]", items.join(",\n"), indent_str)
             }
@@ -335,8 +1538,8 @@ fn colorize_json(value: &Value, indent: usize) -> String {
                         format!(
                             "{}{}: {}",
                             next_indent,
-                            format!("\"{}\"", k).blue().bold(),
-                            colorize_json(v, indent + 1)
+                            theme.key.paint(&format!("\"{}\"", k)),
+                            colorize_json(v, indent + 1, theme)
                         )
                     })
                     .collect();
@@ -346,17 +1549,17 @@ fn colorize_json(value: &Value, indent: usize) -> String {
     }
 }
 
-fn format_value_colored(value: &Value) -> String {
+fn format_value_colored(value: &Value, theme: &Theme) -> String {
     match value {
-        Value::String(s) => s.green().to_string(),
-        Value::Null => "null".bright_black().bold().to_string(),
-        Value::Bool(b) => b.to_string().white().to_string(),
-        Value::Number(n) => n.to_string().white().to_string(),
-        _ => colorize_json(value, 0),
+        Value::String(s) => theme.string.paint(s),
+        Value::Null => theme.null.paint("null"),
+        Value::Bool(b) => theme.boolean.paint(&b.to_string()),
+        Value::Number(n) => theme.number.paint(&n.to_string()),
+        _ => colorize_json(value, 0, theme),
     }
 }
 
-fn looks_like_secret_value(value: &str) -> bool {
+pub(crate) fn looks_like_secret_value(value: &str) -> bool {
     const SECRET_PREFIXES: &[&str] = &[
         "ghp_",
         "gho_",
@@ -390,24 +1593,85 @@ fn looks_like_secret_value(value: &str) -> bool {
     value.starts_with("eyJ") && value.matches('.').count() == 2
 }
 
+/// Env var holding extra comma-separated key patterns for `--redact`.
+const MASK_PATTERNS_ENV: &str = "CONFCTL_MASK_PATTERNS";
+
+/// What `--redact` masks on top of the built-in rules, and what it writes
+/// in place of a masked value.
+pub(crate) struct RedactOptions {
+    /// Extra case-insensitive key substrings treated as sensitive.
+    pub(crate) extra_patterns: Vec<String>,
+    pub(crate) mask: String,
+}
+
+impl Default for RedactOptions {
+    fn default() -> Self {
+        Self {
+            extra_patterns: Vec::new(),
+            mask: "<redacted>".to_string(),
+        }
+    }
+}
+
+impl RedactOptions {
+    /// `--mask-pattern` flags plus `$CONFCTL_MASK_PATTERNS` and the
+    /// settings file's `mask_patterns`, and the `--mask-with` replacement
+    /// (else the settings file's `mask_with`) when given.
+    pub(crate) fn from_cli(patterns: &[String], mask: Option<&str>) -> Self {
+        let settings = settings::get();
+        let mut extra_patterns: Vec<String> = patterns.to_vec();
+        if let Ok(env) = std::env::var(MASK_PATTERNS_ENV) {
+            extra_patterns.extend(
+                env.split(',')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        extra_patterns.extend(settings.mask_patterns.iter().cloned());
+        let mut opts = Self {
+            extra_patterns,
+            ..Self::default()
+        };
+        if let Some(mask) = mask.or(settings.mask_with.as_deref()) {
+            opts.mask = mask.to_string();
+        }
+        opts
+    }
+
+    fn is_sensitive_key(&self, key: &str) -> bool {
+        if diff::is_sensitive_path(key) {
+            return true;
+        }
+        let upper = key.to_ascii_uppercase();
+        self.extra_patterns
+            .iter()
+            .any(|p| upper.contains(&p.to_ascii_uppercase()))
+    }
+}
+
+/// Built-in rules only — what plain `--redact` applies.
+#[cfg(test)]
 fn redact_sensitive(value: &Value) -> Value {
+    redact_with(value, &RedactOptions::default())
+}
+
+pub(crate) fn redact_with(value: &Value, opts: &RedactOptions) -> Value {
     match value {
         Value::Object(map) => Value::Object(
             map.iter()
                 .map(|(key, val)| {
-                    let new_val = if diff::is_sensitive_path(key) {
-                        Value::String("<redacted>".to_string())
+                    let new_val = if opts.is_sensitive_key(key) {
+                        Value::String(opts.mask.clone())
                     } else {
-                        redact_sensitive(val)
+                        redact_with(val, opts)
                     };
                     (key.clone(), new_val)
                 })
                 .collect(),
         ),
-        Value::Array(arr) => Value::Array(arr.iter().map(redact_sensitive).collect()),
-        Value::String(s) if looks_like_secret_value(s) => {
-            Value::String("<redacted>".to_string())
-        }
+        Value::Array(arr) => Value::Array(arr.iter().map(|v| redact_with(v, opts)).collect()),
+        Value::String(s) if looks_like_secret_value(s) => Value::String(opts.mask.clone()),
         other => other.clone(),
     }
 }
@@ -425,20 +1689,44 @@ fn apply_base64_transform(input: &str, decode: bool, encode: bool) -> Result<Str
     }
 }
 
-fn interactive_usage_tutorial() -> &'static str {
-    "No input detected.
-
-Mini tutorial:
-  confctl config.yaml clubs.0.name
-  confctl config.toml
-  cat config.json | confctl user.name
-  curl -s https://api.github.com/users | confctl
-  curl -s https://api.github.com/users | confctl 0.login --format json
-
-Tip: use '-' to force stdin explicitly:
-  curl -s https://api.github.com/users | confctl - 0.login
+/// `--decode-path`: base64-decode, in place, every string leaf under the
+/// subtrees `pattern` selects. Decoded text must be UTF-8, as with `-d`.
+fn decode_base64_leaves(value: &mut Value, pattern: &str) -> Result<()> {
+    let matches: Vec<Vec<String>> = select_paths(value, pattern)?
+        .into_iter()
+        .map(|(segments, _)| segments)
+        .collect();
+    for segments in matches {
+        let pointer: String = segments
+            .iter()
+            .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        if let Some(target) = value.pointer_mut(&pointer) {
+            decode_leaves(target, &path::join(&segments))?;
+        }
+    }
+    Ok(())
+}
 
-Run 'confctl --help' for full usage."
+fn decode_leaves(value: &mut Value, path: &str) -> Result<()> {
+    match value {
+        Value::String(text) => {
+            *text = apply_base64_transform(text, true, false)
+                .with_context(|| format!("--decode-path: cannot decode the value at '{path}'"))?;
+        }
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                decode_leaves(child, &format!("{path}.{}", path::quote_segment(key)))?;
+            }
+        }
+        Value::Array(items) => {
+            for (index, child) in items.iter_mut().enumerate() {
+                decode_leaves(child, &format!("{path}.{index}"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
 }
 
 fn resolve_input(
@@ -449,7 +1737,7 @@ fn resolve_input(
     match (file, path) {
         (Some(file), Some(path)) => Ok((file, Some(path))),
         (Some(file), None) => {
-            if file == "-" {
+            if file == "-" || file == input::CLIPBOARD || input::is_remote(&file) {
                 return Ok((file, None));
             }
 
@@ -461,74 +1749,634 @@ fn resolve_input(
         }
         (None, maybe_path) => {
             if stdin_is_tty {
-                bail!(interactive_usage_tutorial());
+                bail!(examples::tutorial());
             }
             Ok(("-".to_string(), maybe_path))
         }
     }
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
+    let started = Instant::now();
     let cli = Cli::parse();
+    let error_format = cli.error_format;
+    let input = query_input(&cli);
+    let no_fail = cli.no_fail;
+    let outcome = match run(cli) {
+        Err(err) if no_fail && errors::is_missing_path(&err) => Ok(0),
+        outcome => outcome,
+    };
+    history::record(&outcome, started);
+
+    match outcome {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            errors::print(&err, error_format, input.as_deref());
+            ExitCode::from(errors::exit_code(&err))
+        }
+    }
+}
+
+/// The file a query reads, when it names one, for error reports.
+fn query_input(cli: &Cli) -> Option<String> {
+    if cli.command.is_some() || cli.from_env {
+        return None;
+    }
+    if let Some(bundle) = &cli.from_snapshot {
+        return Some(bundle.display().to_string());
+    }
+    if cli.clipboard {
+        return Some(input::CLIPBOARD.to_string());
+    }
+    let stdin_is_tty = atty::is(atty::Stream::Stdin);
+    resolve_input(cli.file.clone(), cli.path.clone(), stdin_is_tty)
+        .ok()
+        .map(|(file, _)| file)
+}
+
+/// Whether the query needs FILE's whole document rather than a `--stream`
+/// read of one value. `--verify-sig` needs the whole file so its signature
+/// is checked before anything is read out of it.
+fn loads_whole(cli: &Cli) -> bool {
+    cli.from_snapshot.is_some()
+        || cli.from_env
+        || cli.profile.is_some()
+        || cli.env_prefix.is_some()
+        || !cli.select.is_empty()
+        || cli.parse_embedded
+        || cli.yaml_anchors.is_some()
+        || cli.verify_sig.is_some()
+}
+
+/// FILE's document with the query flags that rewrite it applied:
+/// `--env-prefix`, `--select`, `--decode-path`, `--parse-embedded` and
+/// redaction, in that order.
+fn load_query_value(
+    cli: &Cli,
+    file: &str,
+    path: Option<&str>,
+    parse_opts: &ParseOptions,
+) -> Result<Value> {
+    let streamed = if loads_whole(cli) {
+        None
+    } else {
+        stream::plan(file, path, cli.format, cli.stream)?
+    };
+    let source = match &cli.from_snapshot {
+        Some(bundle) => input::archive(bundle, file, cli.snapshot_signers.as_deref())?,
+        None if cli.from_env => input::environment(),
+        None => match &cli.profile {
+            Some(name) => profile::layered(file, name, &cli.layers, cli.env_prefix.as_deref())?,
+            None => input::open(file),
+        },
+    };
+    let mut value = match streamed {
+        Some(segments) => stream::load(file, &segments)?,
+        None => source.load(cli.format, parse_opts)?,
+    };
+    if let Some(prefix) = &cli.env_prefix {
+        env_override::apply(&mut value, prefix, &env_override::process_vars())?;
+    }
+    if !cli.select.is_empty() {
+        value = select::apply(value, &cli.select)?;
+    }
+    for pattern in &cli.decode_path {
+        decode_base64_leaves(&mut value, pattern)?;
+    }
+    if cli.parse_embedded {
+        embedded::expand(&mut value);
+    }
+
+    if cli.redact || !cli.mask_patterns.is_empty() || cli.mask_with.is_some() {
+        let opts = RedactOptions::from_cli(&cli.mask_patterns, cli.mask_with.as_deref());
+        value = redact_with(&value, &opts);
+    }
+    Ok(value)
+}
+
+/// The query over every file a glob FILE matched: one `file: value` line
+/// each, or with `-o` one document keyed by file. Files without PATH are
+/// left out; it is an error only when no file has it.
+fn query_files(
+    cli: &Cli,
+    files: &[String],
+    path: Option<&str>,
+    parse_opts: &ParseOptions,
+) -> Result<u8> {
+    if cli.aggregate().is_some()
+        || cli.decode
+        || cli.encode
+        || !cli.encrypt_age.is_empty()
+        || cli.decrypt_age
+        || cli.copy
+        || cli.out.is_some()
+    {
+        bail!("aggregates, -d/-e, age, --copy and --out take a single FILE, not a glob");
+    }
+    // Without -o each line is printed as soon as its file (and every file
+    // before it) is done.
+    let results = query_each(cli, files, path, parse_opts, |file, result| {
+        if cli.output.is_none() {
+            println!("{file}: {}", format_value_with(result, true));
+        }
+    })?;
+    if let Some(format) = cli.output {
+        let emit_opts = EmitOptions {
+            xml_root: cli.xml_root.clone(),
+            xml_attr_prefix: cli.xml_attr_prefix.clone(),
+            xml_indent: cli.xml_indent,
+            color: false,
+            columns: cli.pluck.clone(),
+            hcl_templates: cli.hcl_templates,
+        };
+        print!(
+            "{}",
+            emit::render_with(&Value::Object(results), format, &emit_opts)?
+        );
+    }
+    Ok(0)
+}
+
+/// PATH in each of `files`, keyed by file, queried on `--jobs` threads.
+/// `each` sees every result in file order as soon as it is ready.
+fn query_each(
+    cli: &Cli,
+    files: &[String],
+    path: Option<&str>,
+    parse_opts: &ParseOptions,
+    mut each: impl FnMut(&str, &Value),
+) -> Result<Map<String, Value>> {
+    let mut results = Map::new();
+    let mut first_miss = None;
+    parallel::for_each_ordered(
+        files,
+        parallel::jobs(cli.jobs),
+        |file| query_one(cli, file, path, parse_opts),
+        |file, outcome| {
+            match outcome? {
+                Ok(result) => {
+                    each(file, &result);
+                    results.insert(file.clone(), result);
+                }
+                Err(miss) => {
+                    first_miss.get_or_insert(miss);
+                }
+            }
+            Ok(())
+        },
+    )?;
+    match first_miss {
+        Some(err) if results.is_empty() => Err(err),
+        _ => Ok(results),
+    }
+}
+
+/// PATH in one file of a glob query: `Ok(Err(miss))` when the file does not
+/// have it, which only fails the query if no file does.
+fn query_one(
+    cli: &Cli,
+    file: &str,
+    path: Option<&str>,
+    parse_opts: &ParseOptions,
+) -> Result<Result<Value>> {
+    let expression = expr::parse_query(path)?;
+    let path = if expression.is_some() { None } else { path };
+    // A streamed file is cut down to PATH as it loads, so a miss shows up
+    // here rather than at resolve_path.
+    let mut value = match load_query_value(cli, file, path, parse_opts) {
+        Ok(value) => value,
+        Err(err) if err.downcast_ref::<PathError>().is_some() => return Ok(Err(err)),
+        Err(err) => return Err(err),
+    };
+    let joined = match path {
+        Some(path) if cli.parse_embedded => Some(embedded::join_dotted_keys(&value, path)?),
+        _ => None,
+    };
+    let path = joined.as_deref().or(path);
+    if let Some(expression) = &expression {
+        value = match expression.eval(&value) {
+            Ok(value) => value,
+            Err(err) if err.downcast_ref::<PathError>().is_some() => return Ok(Err(err)),
+            Err(err) => return Err(err),
+        };
+    }
+    let path = if cli.apply.is_empty() {
+        path
+    } else {
+        let target = match path {
+            Some(path) => resolve_path(&value, path).cloned(),
+            None => Ok(value),
+        };
+        value = match target.and_then(|target| apply::run(target, &cli.apply)) {
+            Ok(value) => value,
+            Err(err) if err.downcast_ref::<PathError>().is_some() => return Ok(Err(err)),
+            Err(err) => return Err(err),
+        };
+        None
+    };
+    match convert_units(cli, &mut value, path) {
+        Ok(()) => {}
+        Err(err) if err.downcast_ref::<PathError>().is_some() => return Ok(Err(err)),
+        Err(err) => return Err(err),
+    }
+    if let Some(key) = &cli.sort_by {
+        if let Err(err) = sort::apply(&mut value, path, key, cli.reverse) {
+            return Ok(Err(err));
+        }
+    }
+    if !cli.pluck.is_empty() {
+        if let Err(err) = pluck::apply(&mut value, path, &cli.pluck) {
+            return Ok(Err(err));
+        }
+    }
+    let result = match path {
+        Some(path) => resolve_path(&value, path).cloned(),
+        None => Ok(value),
+    };
+    Ok(match &cli.template {
+        Some(template) => {
+            result.and_then(|value| template::render(template, &value).map(Value::String))
+        }
+        None => result,
+    })
+}
+
+/// `--as-epoch`, `--humanize-bytes` and the like, on the value at `path`.
+fn convert_units(cli: &Cli, value: &mut Value, path: Option<&str>) -> Result<()> {
+    if !cli.converts_units() {
+        return Ok(());
+    }
+    let target = match path {
+        Some(path) => resolve_path_mut(value, path)?,
+        None => value,
+    };
+    if let Some(conversion) = cli.time_conversion() {
+        datetime::convert(target, conversion)?;
+    }
+    if let Some(conversion) = cli.size_conversion() {
+        sizes::convert(target, conversion)?;
+    }
+    Ok(())
+}
+
+/// Query defaults from the settings file: `@NAME` FILE aliases (unless a
+/// file has that name) and, when no flag overrode it, the `-o` format.
+fn apply_settings(cli: &mut Cli, settings: &settings::Settings) -> Result<()> {
+    if !cli.from_env && !cli.clipboard && cli.from_snapshot.is_none() {
+        if let Some(file) = &cli.file {
+            if let Some(name) = file.strip_prefix('@').filter(|_| !Path::new(file).exists()) {
+                cli.file = Some(settings.alias(name)?);
+            }
+        }
+    }
+    let output_allowed = !cli.decode
+        && !cli.compact
+        && !cli.resolve_preview
+        && cli.template.is_none()
+        && cli.yaml_anchors != Some(yaml_anchors::AnchorMode::Preserve);
+    if cli.output.is_none() && output_allowed && cli.aggregate().is_none() {
+        cli.output = settings.output;
+    }
+    Ok(())
+}
 
-    let use_color = atty::is(atty::Stream::Stdout);
+/// Everything `main` does, returning the process exit code so the outcome
+/// can be recorded in the history before exiting.
+fn run(mut cli: Cli) -> Result<u8> {
+    let settings = settings::init()?;
+    // A file written with --out gets no escape codes.
+    let use_color = theme::use_color(cli.color, settings.color) && cli.out.is_none();
+    colored::control::set_override(use_color);
+    let read_only = cli.read_only || env_flag(READ_ONLY_ENV);
+    net::configure(cli.timeout, cli.retries);
+    cache::configure(match cli.cache_ttl {
+        _ if cli.no_cache => None,
+        Some(ttl) => Some(ttl),
+        None => match std::env::var(cache::TTL_ENV) {
+            Ok(text) if !text.trim().is_empty() => Some(
+                datetime::parse_duration_arg(&text)
+                    .map_err(|err| anyhow!("{}: {err}", cache::TTL_ENV))?,
+            ),
+            _ => None,
+        },
+    });
+    signature::configure(cli.verify_sig.as_deref(), cli.signature.as_deref())?;
+    atomic::configure(cli.diff, use_color);
 
     if let Some(command) = cli.command {
+        let mutation = command.mutation();
+        if cli.diff {
+            enforce_preview(mutation)?;
+        }
+        // A preview writes nothing, so read-only mode allows it.
+        enforce_read_only(mutation.filter(|_| !cli.diff), read_only)?;
         match command {
-            Command::Diff(diff_cli) => {
-                if diff::run(diff_cli, use_color)? {
-                    std::process::exit(1);
-                }
-                return Ok(());
+            Command::Diff(diff_cli) => return Ok(u8::from(diff::run(diff_cli, use_color)?)),
+            Command::Set(set_cli) => env_edit::run_set(set_cli)?,
+            Command::Unset(unset_cli) => env_edit::run_unset(unset_cli)?,
+            Command::Patch(patch_cli) => patch::run(patch_cli)?,
+            Command::Edit(edit_cli) => edit::run(edit_cli)?,
+            Command::Convert(convert_cli) => convert::run(convert_cli, cli.strict_convert)?,
+            Command::Exec(exec_cli) => return exec::run(exec_cli),
+            Command::Render(render_cli) => render::run(render_cli)?,
+            #[cfg(feature = "archive")]
+            Command::Snapshot(snapshot_cli) => snapshot::run(snapshot_cli)?,
+            Command::Split(split_cli) => split::run(split_cli)?,
+            Command::ComposeDir(compose_cli) => merge::run_compose_dir(compose_cli)?,
+            Command::Merge(merge_cli) => return Ok(u8::from(merge3::run(merge_cli)?)),
+            Command::GitMergeDriver(driver_cli) => {
+                return Ok(u8::from(git_driver::merge_driver(driver_cli)?))
             }
-            Command::Set(set_cli) => return env_edit::run_set(set_cli),
-            Command::Unset(unset_cli) => return env_edit::run_unset(unset_cli),
-            Command::Vault(vault_cli) => return vault::cli::run(vault_cli),
+            Command::GitTextconv(textconv_cli) => git_driver::textconv(textconv_cli)?,
+            Command::ToSchema(schema_cli) => schema::run(schema_cli)?,
+            Command::Generate(generate_cli) => generate::run(generate_cli)?,
+            Command::Hash(hash_cli) => hash::run(hash_cli)?,
+            Command::K8s(k8s_cli) => k8s::run(k8s_cli)?,
+            Command::Compose(compose_cli) => compose::run(compose_cli)?,
+            Command::Find(find_cli) => return Ok(u8::from(!find::run(find_cli)?)),
+            Command::Explain(explain_cli) => explain::run(explain_cli)?,
+            Command::Grep(grep_cli) => return Ok(u8::from(!grep::run(grep_cli)?)),
+            Command::Lint(lint_cli) => return Ok(u8::from(lint::run(lint_cli)?)),
+            Command::Check(check_cli) => return Ok(u8::from(check::run(check_cli)?)),
+            Command::Assert(assert_cli) => return Ok(u8::from(assert::run(assert_cli)?)),
+            Command::Examples(examples_cli) => examples::run(examples_cli)?,
+            Command::Man(man_cli) => man::run(man_cli)?,
+            Command::Completions(completions_cli) => completions::run(completions_cli)?,
+            Command::Complete(complete_cli) => completions::run_complete(complete_cli)?,
+            Command::Repl(repl_cli) => return Ok(u8::from(repl::run(repl_cli, use_color)?)),
+            Command::History(history_cli) => return history::run(history_cli, read_only),
+            Command::Undo(undo_cli) => undo::run(undo_cli)?,
+            #[cfg(feature = "vault")]
+            Command::Vault(vault_cli) => vault::cli::run(vault_cli)?,
         }
+        return Ok(0);
     }
 
+    let mutation = cli.out.as_ref().map(|_| "--out");
+    if cli.diff {
+        enforce_preview(mutation)?;
+    }
+    enforce_read_only(mutation.filter(|_| !cli.diff), read_only)?;
     let stdin_is_tty = atty::is(atty::Stream::Stdin);
+    apply_settings(&mut cli, settings)?;
+
+    let aggregate = cli.aggregate();
+    let (file, path) = match &cli.from_snapshot {
+        _ if cli.from_env => {
+            if cli.path.is_some() {
+                bail!("--from-env reads no file; pass just the path to query");
+            }
+            (String::new(), cli.file.clone())
+        }
+        _ if cli.clipboard => {
+            if cli.path.is_some() {
+                bail!("--clipboard reads no file; pass just the path to query");
+            }
+            (input::CLIPBOARD.to_string(), cli.file.clone())
+        }
+        Some(_) => (cli.file.clone().unwrap_or_default(), cli.path.clone()),
+        None => resolve_input(cli.file.clone(), cli.path.clone(), stdin_is_tty)?,
+    };
+
+    // A PATH such as `replicas * 2` is an expression over the whole
+    // document; its result is then printed as a resolved value would be.
+    let expression = expr::parse_query(path.as_deref())?;
+    let reads_text = cli
+        .yaml_anchors
+        .is_some_and(|mode| mode != yaml_anchors::AnchorMode::Expand);
+    if expression.is_some() && (cli.stream || cli.with_location || cli.location_only || reads_text)
+    {
+        bail!(
+            "--stream, --with-location, --location-only and --yaml-anchors preserve/report \
+             need a plain PATH, not an expression"
+        );
+    }
+    if !cli.apply.is_empty() && (cli.with_location || cli.location_only || reads_text) {
+        bail!(
+            "--with-location, --location-only and --yaml-anchors preserve/report print PATH's \
+             value as written, so they do not combine with --apply"
+        );
+    }
+    if reads_text && cli.converts_units() {
+        bail!(
+            "--yaml-anchors preserve/report print the YAML as written, so they do not combine \
+             with --as-epoch, --as-rfc3339, --humanize-duration, --as-bytes or --humanize-bytes"
+        );
+    }
+
+    if cli.resolve_preview {
+        let preview = resolve_preview(&file, cli.format)?;
+        write_output(&cli, &preview)?;
+        if cli.copy {
+            copy_to_clipboard(&preview)?;
+        }
+        return Ok(0);
+    }
+
+    let anchor_sites = match cli.yaml_anchors {
+        Some(mode @ (yaml_anchors::AnchorMode::Preserve | yaml_anchors::AnchorMode::Report)) => {
+            if cli.from_env || cli.from_snapshot.is_some() || cli.profile.is_some() {
+                bail!("--yaml-anchors preserve and report read FILE's YAML text; they cannot be combined with --from-env, --from-snapshot or --profile");
+            }
+            let sites = yaml_anchors::scan(&yaml_text(&file, cli.format, "--yaml-anchors")?);
+            if mode == yaml_anchors::AnchorMode::Report {
+                let base = match &path {
+                    Some(path) => path::segments(path)?,
+                    None => Vec::new(),
+                };
+                write_output(&cli, &yaml_anchors::report(&sites, &base))?;
+                return Ok(0);
+            }
+            match cli.output {
+                None | Some(OutputFormat::Yaml) => cli.output = Some(OutputFormat::Yaml),
+                Some(_) => bail!("--yaml-anchors preserve prints YAML; it cannot be combined with another -o/--output"),
+            }
+            Some(sites)
+        }
+        _ => None,
+    };
 
-    let (file, path) = resolve_input(cli.file, cli.path, stdin_is_tty)?;
+    let location = if cli.with_location || cli.location_only {
+        let Some(path) = &path else {
+            bail!("--with-location and --location-only need a PATH to locate");
+        };
+        if glob::is_pattern(&file) && !Path::new(&file).exists() {
+            bail!("--with-location and --location-only take one file, not a glob");
+        }
+        let location = location::find(&file, cli.format, path)?;
+        if cli.location_only {
+            println!("{location}");
+            return Ok(0);
+        }
+        Some(location)
+    } else {
+        None
+    };
 
-    let mut value = parse_file(&file, cli.format)?;
+    let parse_opts = ParseOptions {
+        delimiter: cli.delimiter,
+        no_headers: cli.no_headers,
+        strict_env: cli.strict_env,
+        infer_types: cli.infer_types,
+        merge_keys: cli.yaml_anchors == Some(yaml_anchors::AnchorMode::Expand),
+        yaml_1_2: cli.yaml_1_2,
+        recurse: cli.recurse,
+    };
+    if cli.recurse && !kvstore::is_uri(&file) {
+        bail!("--recurse reads a prefix of an etcd:// or consul:// FILE");
+    }
+    if cli.from_snapshot.is_none()
+        && !cli.from_env
+        && glob::is_pattern(&file)
+        && !input::is_remote(&file)
+        && !Path::new(&file).exists()
+    {
+        return query_files(&cli, &glob::expand(&file)?, path.as_deref(), &parse_opts);
+    }
+    let path = if expression.is_some() { None } else { path };
+    let theme = if use_color {
+        Theme::load(settings)?
+    } else {
+        Theme::default()
+    };
+    let mut value = load_query_value(&cli, &file, path.as_deref(), &parse_opts)?;
+    // Keys such as `config.json` can be written without quotes once the
+    // documents in strings are expanded.
+    let path = match path {
+        Some(path) if cli.parse_embedded => Some(embedded::join_dotted_keys(&value, &path)?),
+        path => path,
+    };
+    if let Some(expression) = &expression {
+        value = expression.eval(&value)?;
+    }
+    // The transformed value stands in for the document from here on, as an
+    // expression's result does, so it prints as a resolved value would.
+    let computed = expression.is_some() || !cli.apply.is_empty();
+    let path = if cli.apply.is_empty() {
+        path
+    } else {
+        let target = match &path {
+            Some(path) => resolve_path(&value, path)?.clone(),
+            None => value,
+        };
+        value = apply::run(target, &cli.apply)?;
+        None
+    };
+    convert_units(&cli, &mut value, path.as_deref())?;
+    if let Some(key) = &cli.sort_by {
+        sort::apply(&mut value, path.as_deref(), key, cli.reverse)?;
+    }
+    if !cli.pluck.is_empty() {
+        pluck::apply(&mut value, path.as_deref(), &cli.pluck)?;
+    }
 
-    if cli.redact {
-        value = redact_sensitive(&value);
+    if let Some(op) = aggregate {
+        let target = match &path {
+            Some(path) => resolve_path(&value, path)?,
+            None => &value,
+        };
+        let result = aggregate::apply(op, target)?;
+        let output = format_value_with(&result, cli.compact);
+        if use_color && !cli.compact {
+            println!("{}", format_value_colored(&result, &theme));
+        } else {
+            write_output(&cli, &format!("{output}\n"))?;
+        }
+        if cli.copy {
+            copy_to_clipboard(&output)?;
+        }
+        return Ok(0);
     }
 
+    let age_op = age::AgeOp::from_cli(&cli.encrypt_age, cli.decrypt_age, cli.identity.as_deref());
+
+    let emit_as = cli.output.filter(|format| *format != OutputFormat::Json);
+    let emit_opts = EmitOptions {
+        xml_root: cli.xml_root.clone(),
+        xml_attr_prefix: cli.xml_attr_prefix.clone(),
+        xml_indent: cli.xml_indent,
+        color: use_color,
+        columns: cli.pluck.clone(),
+        hcl_templates: cli.hcl_templates,
+    };
+    let render = |value: &Value, format, base: &[String]| match &anchor_sites {
+        Some(sites) => yaml_anchors::render(value, sites, base),
+        None => emit::render_with(value, format, &emit_opts),
+    };
+
     let final_output = match path {
         Some(path) => {
             let result = resolve_path(&value, &path)?;
-            let output = format_value_with(result, cli.compact);
-            let final_output = apply_base64_transform(&output, cli.decode, cli.encode)?;
-
-            if cli.decode || cli.encode {
-                print!("{}", final_output);
-            } else if use_color && !cli.compact {
-                println!("{}", format_value_colored(result));
+            if let Some(format) = emit_as {
+                emit::loss::check(
+                    &emit::loss::report(result, format),
+                    format,
+                    cli.strict_convert,
+                )?;
+            }
+            let output = match (&cli.template, emit_as) {
+                (Some(template), _) => template::render(template, result)?,
+                (None, Some(format)) => render(result, format, &path::segments(&path)?)?,
+                (None, None) => format_value_with(result, cli.compact),
+            };
+            let mut final_output = apply_base64_transform(&output, cli.decode, cli.encode)?;
+            if let Some(op) = &age_op {
+                final_output = op.apply(&final_output)?;
+            }
+            let mut printed = match &location {
+                Some(location) => format!("{location}\n"),
+                None => String::new(),
+            };
+            if cli.decode || cli.encode || age_op.is_some() || emit_as.is_some() {
+                printed.push_str(&final_output);
+            } else if use_color && !cli.compact && cli.template.is_none() {
+                printed.push_str(&format!("{}\n", format_value_colored(result, &theme)));
             } else {
-                println!("{}", final_output);
+                printed.push_str(&format!("{final_output}\n"));
             }
+            write_output(&cli, &printed)?;
             final_output
         }
         None => {
-            let json_str = if cli.compact {
+            if let Some(format) = emit_as {
+                emit::loss::check(
+                    &emit::loss::report(&value, format),
+                    format,
+                    cli.strict_convert,
+                )?;
+            }
+            let json_str = if let Some(template) = &cli.template {
+                template::render(template, &value)?
+            } else if let Some(format) = emit_as {
+                render(&value, format, &[])?
+            } else if computed {
+                format_value_with(&value, cli.compact)
+            } else if cli.compact {
                 serde_json::to_string(&value).context("Failed to serialize value to JSON")?
             } else {
-                serde_json::to_string_pretty(&value)
-                    .context("Failed to serialize value to JSON")?
+                serde_json::to_string_pretty(&value).context("Failed to serialize value to JSON")?
             };
             if cli.encode {
                 let encoded = STANDARD.encode(&json_str);
-                print!("{}", encoded);
+                write_output(&cli, &encoded)?;
                 encoded
+            } else if let Some(op) = &age_op {
+                let transformed = op.apply(&json_str)?;
+                write_output(&cli, &transformed)?;
+                transformed
             } else {
-                if use_color && !cli.compact {
-                    println!("{}", colorize_json(&value, 0));
+                let printed = if emit_as.is_some() {
+                    json_str.clone()
+                } else if use_color && !cli.compact && cli.template.is_none() {
+                    if computed {
+                        format!("{}\n", format_value_colored(&value, &theme))
+                    } else {
+                        format!("{}\n", colorize_json(&value, 0, &theme))
+                    }
                 } else {
-                    println!("{json_str}");
-                }
+                    format!("{json_str}\n")
+                };
+                write_output(&cli, &printed)?;
                 json_str
             }
         }
@@ -538,7 +2386,16 @@ fn main() -> Result<()> {
         copy_to_clipboard(&final_output)?;
     }
 
-    Ok(())
+    Ok(0)
+}
+
+/// An empty directory of a test's own, `confctl-TAG-PID` under the temp dir.
+#[cfg(test)]
+pub(crate) fn scratch_dir(tag: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("confctl-{tag}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
 }
 
 #[cfg(test)]
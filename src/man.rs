@@ -0,0 +1,273 @@
+//! `confctl man [COMMAND]...`: the manual page as roff, generated from the
+//! same clap definitions `--help` prints so the two cannot drift apart.
+//! `confctl man > confctl.1` gives the top-level page, with the cookbook of
+//! `confctl examples` as its EXAMPLES section; `confctl man convert` gives a
+//! subcommand's page with the examples that use it.
+
+use anyhow::{bail, Result};
+use clap::{Arg, Args, Command, CommandFactory};
+
+use crate::examples::{Example, TOPICS};
+
+#[derive(Args, Debug)]
+pub(crate) struct ManCli {
+    /// Subcommand whose page to print, e.g. `convert` or `history show`.
+    #[arg(value_name = "COMMAND")]
+    pub(crate) command: Vec<String>,
+}
+
+pub(crate) fn run(cli: ManCli) -> Result<()> {
+    let mut root = crate::Cli::command();
+    root.build();
+    let mut command = &root;
+    let mut names = vec![root.get_name().to_string()];
+    for name in &cli.command {
+        command = match command.find_subcommand(name) {
+            Some(sub) if !sub.is_hide_set() => sub,
+            _ => bail!("`{}` has no subcommand `{name}`", names.join(" ")),
+        };
+        names.push(command.get_name().to_string());
+    }
+    print!("{}", page(command, &names));
+    Ok(())
+}
+
+/// The page for `command`, reached from the top through `names`.
+fn page(command: &Command, names: &[String]) -> String {
+    let title = names.join("-");
+    let mut roff = format!(
+        ".TH {} 1 \"\" \"confctl {}\" \"User Commands\"\n",
+        title.to_uppercase(),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    roff.push_str(".SH NAME\n");
+    match command.get_about() {
+        Some(about) => roff.push_str(&format!("{title} \\- {}\n", escape(&about.to_string()))),
+        None => roff.push_str(&format!("{title}\n")),
+    }
+
+    let usage = command.clone().render_usage().to_string();
+    let usage = usage.strip_prefix("Usage: ").unwrap_or(&usage);
+    let (program, rest) = usage.split_at(names.join(" ").len().min(usage.len()));
+    roff.push_str(&format!(
+        ".SH SYNOPSIS\n\\fB{}\\fR{}\n",
+        escape(program),
+        escape(rest)
+    ));
+
+    if let Some(description) = command.get_long_about() {
+        roff.push_str(".SH DESCRIPTION\n");
+        roff.push_str(&paragraphs(&description.to_string()));
+    }
+
+    let visible = || command.get_arguments().filter(|arg| !arg.is_hide_set());
+    let positionals: Vec<&Arg> = visible().filter(|arg| arg.is_positional()).collect();
+    if !positionals.is_empty() {
+        roff.push_str(".SH ARGUMENTS\n");
+        for arg in positionals {
+            roff.push_str(&item(arg));
+        }
+    }
+    let options: Vec<&Arg> = visible().filter(|arg| !arg.is_positional()).collect();
+    if !options.is_empty() {
+        roff.push_str(".SH OPTIONS\n");
+        for arg in options {
+            roff.push_str(&item(arg));
+        }
+    }
+
+    let subcommands: Vec<&Command> = command
+        .get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+        .collect();
+    if !subcommands.is_empty() {
+        roff.push_str(".SH COMMANDS\n");
+        for sub in subcommands {
+            roff.push_str(&format!(".TP\n\\fB{}\\fR\n", escape(sub.get_name())));
+            if let Some(about) = sub.get_about() {
+                roff.push_str(&format!("{}\n", escape(&about.to_string())));
+            }
+        }
+        roff.push_str(&format!(
+            ".PP\nRun \\fBconfctl man {}\\fR \\fICOMMAND\\fR for a command's page.\n",
+            escape(
+                &names[1..]
+                    .iter()
+                    .map(|name| format!("{name} "))
+                    .collect::<String>()
+            )
+        ));
+    }
+
+    let examples = examples(&names[1..]);
+    if !examples.is_empty() {
+        roff.push_str(".SH EXAMPLES\n");
+        for (topic, examples) in examples {
+            if names.len() == 1 {
+                roff.push_str(&format!(".SS {}\n", escape(topic)));
+            }
+            for example in examples {
+                roff.push_str(&format!(
+                    ".PP\n{}\n.RS\n.nf\n{}\n.fi\n.RE\n",
+                    escape(example.about),
+                    escape(example.command)
+                ));
+            }
+        }
+    }
+    roff
+}
+
+/// The cookbook entries for a page by topic: all of them on the top-level
+/// page, and on a subcommand's page those that run it.
+fn examples(subcommand: &[String]) -> Vec<(&'static str, Vec<&'static Example>)> {
+    let prefix = format!("confctl {} ", subcommand.join(" "));
+    TOPICS
+        .iter()
+        .map(|topic| {
+            let matching = topic
+                .examples
+                .iter()
+                .filter(|example| subcommand.is_empty() || example.command.contains(&prefix))
+                .collect();
+            (topic.summary, matching)
+        })
+        .filter(|(_, examples): &(_, Vec<_>)| !examples.is_empty())
+        .collect()
+}
+
+/// One `.TP` entry: the flags or the value name, then the help text and the
+/// values and defaults clap knows about.
+fn item(arg: &Arg) -> String {
+    let value = match arg.get_value_names() {
+        Some(names) => names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>(),
+        None => vec![arg.get_id().to_string().to_uppercase()],
+    };
+    let value = value.join(" ");
+    let mut tag = Vec::new();
+    if let Some(short) = arg.get_short() {
+        tag.push(format!("\\fB\\-{short}\\fR"));
+    }
+    if let Some(long) = arg.get_long() {
+        tag.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+    let mut tag = tag.join(", ");
+    if arg.is_positional() {
+        tag = format!("\\fI{}\\fR", escape(&value));
+    } else if arg.get_action().takes_values() {
+        tag.push_str(&format!(" \\fI{}\\fR", escape(&value)));
+    }
+
+    let mut roff = format!(".TP\n{tag}\n");
+    if let Some(help) = arg.get_long_help().or(arg.get_help()) {
+        // The first paragraph follows the tag; later ones stay indented.
+        let help = paragraphs(&help.to_string()).replace(".PP\n", ".IP\n");
+        roff.push_str(help.strip_prefix(".IP\n").unwrap_or(&help));
+    }
+    let values: Vec<_> = arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set())
+        .collect();
+    if !values.is_empty() && !arg.is_hide_possible_values_set() {
+        if values.iter().any(|value| value.get_help().is_some()) {
+            roff.push_str(".IP\nPossible values:\n.RS\n");
+            for value in &values {
+                roff.push_str(&format!(".TP\n\\fB{}\\fR\n", escape(value.get_name())));
+                if let Some(help) = value.get_help() {
+                    roff.push_str(&format!("{}\n", escape(&help.to_string())));
+                }
+            }
+            roff.push_str(".RE\n");
+        } else {
+            let names: Vec<&str> = values.iter().map(|value| value.get_name()).collect();
+            roff.push_str(&format!(
+                ".IP\n[possible values: {}]\n",
+                escape(&names.join(", "))
+            ));
+        }
+    }
+    let defaults = arg.get_default_values();
+    if !defaults.is_empty() && !arg.is_hide_default_value_set() {
+        let defaults: Vec<_> = defaults
+            .iter()
+            .map(|value| value.to_string_lossy())
+            .collect();
+        roff.push_str(&format!(
+            ".IP\n[default: {}]\n",
+            escape(&defaults.join(", "))
+        ));
+    }
+    roff
+}
+
+/// Text with blank-line separated paragraphs, each after a `.PP`.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!(".PP\n{}\n", escape(paragraph)))
+        .collect()
+}
+
+/// `text` safe to put in roff: backslashes and hyphens escaped, and lines
+/// that would start with a request (`.` or `'`) guarded with `\&`.
+fn escape(text: &str) -> String {
+    let text = text.replace('\\', "\\e").replace('-', "\\-");
+    text.split('\n')
+        .map(|line| {
+            if line.starts_with(['.', '\'']) {
+                format!("\\&{line}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_for(names: &[&str]) -> String {
+        let mut root = crate::Cli::command();
+        root.build();
+        let mut command = &root;
+        for name in &names[1..] {
+            command = command.find_subcommand(name).unwrap();
+        }
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        page(command, &names)
+    }
+
+    #[test]
+    fn pages_are_built_from_the_cli_definition() {
+        let top = page_for(&["confctl"]);
+        assert!(top.starts_with(".TH CONFCTL 1 \"\" \"confctl "));
+        assert!(top.contains(".SH NAME\nconfctl \\- CLI for querying configuration files"));
+        assert!(top.contains(".SH SYNOPSIS\n\\fBconfctl\\fR [OPTIONS] [FILE] [PATH] [COMMAND]\n"));
+        assert!(top.contains(".TP\n\\fB\\-o\\fR, \\fB\\-\\-output\\fR \\fIFORMAT\\fR\n"));
+        assert!(top.contains(".TP\n\\fBjson5\\fR\nJSON5, which also covers JSONC"));
+        assert!(top.contains(".IP\n[default: config]\n"));
+        assert!(top.contains(".TP\n\\fBconvert\\fR\nRe\\-serialise a config file"));
+        assert!(top.contains(".SS Read values by path"));
+        assert!(!top.contains("\\fBhelp\\fR"));
+
+        let convert = page_for(&["confctl", "convert"]);
+        assert!(convert.starts_with(".TH CONFCTL-CONVERT 1"));
+        assert!(convert.contains(".SH SYNOPSIS\n\\fBconfctl convert\\fR [OPTIONS]"));
+        assert!(convert.contains(".RS\n.nf\nconfctl convert app.yaml \\-\\-out app.json\n.fi\n"));
+        assert!(!convert.contains("confctl config.toml \\-o yaml"));
+    }
+
+    #[test]
+    fn roff_requests_and_escapes_are_guarded() {
+        assert_eq!(escape("a-b \\n\n.x\n'y"), "a\\-b \\en\n\\&.x\n\\&'y");
+        assert_eq!(paragraphs("one\n\n\ntwo\n"), ".PP\none\n.PP\ntwo\n");
+    }
+}
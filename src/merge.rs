@@ -0,0 +1,523 @@
+//! Deep merging of config documents, and `confctl compose-dir`.
+//!
+//! `compose-dir` mirrors the `Include conf.d/*` convention of nginx/sshd:
+//! every config file in a directory is parsed and merged in lexical filename
+//! order, later files overriding earlier ones, to produce the effective
+//! document. With `--annotate-source` it is printed as YAML saying which
+//! file each value came from, for reviewing layered config.
+//!
+//! Arrays are replaced whole, unless `--array-key FIELD` names a field that
+//! identifies their elements (`name` for a list of servers or containers):
+//! when every element of both arrays is an object with a distinct scalar
+//! FIELD, an element merges into the one with the same FIELD and new ones
+//! are appended.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde_json::{Map, Value};
+
+use crate::emit::commented::{self, Node, Shape};
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::{detect_format, parse_bytes, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct ComposeDirCli {
+    /// Directory whose config files are merged (non-recursive).
+    pub(crate) dir: PathBuf,
+
+    /// Output format of the combined document (default: json).
+    #[arg(long, value_enum)]
+    pub(crate) output: Option<OutputFormat>,
+
+    /// Force the input format of every file instead of detecting per file.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Nest each file under a key named after it (`10-db.yaml` → `db`)
+    /// instead of merging every file at the root.
+    #[arg(long)]
+    pub(crate) namespace: bool,
+
+    /// Keep a leading ordering prefix (`10-`, `020_`) in namespace keys.
+    #[arg(long, requires = "namespace")]
+    pub(crate) keep_prefix: bool,
+
+    /// Print YAML with a comment after each key naming the file its value
+    /// came from.
+    #[arg(long)]
+    pub(crate) annotate_source: bool,
+
+    /// Match the elements of arrays of objects by this field instead of
+    /// replacing the array; repeat for lists keyed differently.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) array_key: Vec<String>,
+}
+
+/// Recursively merge `overlay` into `base`. Objects merge key by key; any
+/// other value (scalars, arrays, type changes) is replaced by the overlay.
+pub(crate) fn deep_merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base_map), Value::Object(overlay_map)) => {
+            for (key, value) in overlay_map {
+                match base_map.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => {
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (slot, value) => *slot = value,
+    }
+}
+
+/// The first of `fields` that identifies the elements of every one of
+/// `arrays`: each element is an object holding it as a scalar, and no two
+/// elements of an array share it.
+pub(crate) fn matching_key<'a>(fields: &'a [String], arrays: &[&[Value]]) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|field| {
+            arrays.iter().all(|items| {
+                let mut ids = Vec::with_capacity(items.len());
+                items.iter().all(|item| match item.get(field.as_str()) {
+                    Some(id) if !(id.is_object() || id.is_array() || id.is_null()) => {
+                        let fresh = !ids.contains(&id);
+                        ids.push(id);
+                        fresh
+                    }
+                    _ => false,
+                })
+            })
+        })
+        .map(String::as_str)
+}
+
+pub(crate) fn run_compose_dir(cli: ComposeDirCli) -> Result<()> {
+    let files = config_files(&cli.dir)?;
+    if files.is_empty() {
+        bail!("no config files found in {}", cli.dir.display());
+    }
+    if cli.annotate_source
+        && cli
+            .output
+            .is_some_and(|output| output != OutputFormat::Yaml)
+    {
+        bail!("--annotate-source writes YAML comments; leave out --output or use --output yaml");
+    }
+
+    let keys = if cli.namespace {
+        namespace_keys(&files, cli.keep_prefix)?
+    } else {
+        Vec::new()
+    };
+
+    let mut combined = Value::Object(Map::new());
+    let mut origin = Origin::Keys(BTreeMap::new());
+    let mut names = Vec::new();
+    for (i, path) in files.iter().enumerate() {
+        let display = path.display().to_string();
+        let bytes = fs::read(path).with_context(|| format!("Failed to read file: {display}"))?;
+        let value = parse_bytes(&display, &bytes, cli.format, &ParseOptions::default())?;
+        let layer = if cli.namespace {
+            let mut map = Map::new();
+            map.insert(keys[i].clone(), value);
+            Value::Object(map)
+        } else {
+            value
+        };
+        traced_merge(
+            &mut combined,
+            &mut origin,
+            layer,
+            names.len(),
+            &cli.array_key,
+        );
+        names.push(display);
+    }
+
+    if cli.annotate_source {
+        print!(
+            "{}",
+            commented::render(&annotated(&combined, &origin, &names))
+        );
+    } else {
+        let output = cli.output.unwrap_or(OutputFormat::Json);
+        print!("{}", emit::render(&combined, output)?);
+    }
+    Ok(())
+}
+
+/// Which layer set each value of a merged document.
+#[derive(Debug, PartialEq)]
+enum Origin {
+    Layer(usize),
+    Keys(BTreeMap<String, Origin>),
+    /// Per element of an array merged by `--array-key`.
+    Items(Vec<Origin>),
+}
+
+/// `deep_merge` with arrays matched by `array_keys`, noting in `origin` that
+/// the values of `overlay` came from layer `layer`.
+fn traced_merge(
+    base: &mut Value,
+    origin: &mut Origin,
+    overlay: Value,
+    layer: usize,
+    array_keys: &[String],
+) {
+    match (base, overlay) {
+        (Value::Object(base_map), Value::Object(overlay_map)) => {
+            if overlay_map.is_empty() {
+                return;
+            }
+            // An object one layer set whole now has keys from two.
+            if let Origin::Layer(earlier) = *origin {
+                *origin = Origin::Keys(
+                    base_map
+                        .keys()
+                        .map(|key| (key.clone(), Origin::Layer(earlier)))
+                        .collect(),
+                );
+            }
+            let Origin::Keys(origins) = origin else {
+                unreachable!("split into keys above");
+            };
+            for (key, value) in overlay_map {
+                match (base_map.get_mut(&key), origins.get_mut(&key)) {
+                    (Some(existing), Some(origin)) => {
+                        traced_merge(existing, origin, value, layer, array_keys)
+                    }
+                    _ => {
+                        origins.insert(key.clone(), Origin::Layer(layer));
+                        base_map.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base_items), Value::Array(overlay_items))
+            if matching_key(array_keys, &[base_items, &overlay_items]).is_some() =>
+        {
+            traced_items(base_items, origin, overlay_items, layer, array_keys);
+        }
+        (slot, value) => {
+            *slot = value;
+            *origin = Origin::Layer(layer);
+        }
+    }
+}
+
+/// `traced_merge` of two arrays whose elements `array_keys` identifies: an
+/// element merges into the one with the same id, a new one is appended.
+fn traced_items(
+    base: &mut Vec<Value>,
+    origin: &mut Origin,
+    overlay: Vec<Value>,
+    layer: usize,
+    array_keys: &[String],
+) {
+    let field = matching_key(array_keys, &[base, &overlay]).expect("checked by the caller");
+    if overlay.is_empty() {
+        return;
+    }
+    if let Origin::Layer(earlier) = *origin {
+        *origin = Origin::Items(base.iter().map(|_| Origin::Layer(earlier)).collect());
+    }
+    let Origin::Items(origins) = origin else {
+        unreachable!("split into items above");
+    };
+    for item in overlay {
+        match base
+            .iter()
+            .position(|existing| existing.get(field) == item.get(field))
+        {
+            Some(at) => traced_merge(&mut base[at], &mut origins[at], item, layer, array_keys),
+            None => {
+                base.push(item);
+                origins.push(Origin::Layer(layer));
+            }
+        }
+    }
+}
+
+/// The one layer everything under `origin` came from, if there is one.
+fn single(origin: &Origin) -> Option<usize> {
+    match origin {
+        Origin::Layer(layer) => Some(*layer),
+        Origin::Keys(origins) => {
+            let mut layers = origins.values().map(single);
+            let first = layers.next()??;
+            layers.all(|layer| layer == Some(first)).then_some(first)
+        }
+        Origin::Items(origins) => {
+            let mut layers = origins.iter().map(single);
+            let first = layers.next()??;
+            layers.all(|layer| layer == Some(first)).then_some(first)
+        }
+    }
+}
+
+/// `value` with the file it came from noted at the highest key everything
+/// under which came from that one file.
+fn annotated(value: &Value, origin: &Origin, names: &[String]) -> Node {
+    if let Some(layer) = single(origin) {
+        return Node {
+            note: Some(names[layer].clone()),
+            ..Node::plain(value)
+        };
+    }
+    match (value, origin) {
+        (Value::Object(map), Origin::Keys(origins)) => Node {
+            shape: Shape::Object(
+                map.iter()
+                    .map(|(key, value)| {
+                        let node = match origins.get(key) {
+                            Some(origin) => annotated(value, origin, names),
+                            None => Node::plain(value),
+                        };
+                        (key.clone(), node)
+                    })
+                    .collect(),
+            ),
+            ..Node::default()
+        },
+        (Value::Array(items), Origin::Items(origins)) => Node {
+            shape: Shape::Array(
+                items
+                    .iter()
+                    .zip(origins)
+                    .map(|(item, origin)| annotated(item, origin, names))
+                    .collect(),
+            ),
+            ..Node::default()
+        },
+        _ => Node::plain(value),
+    }
+}
+
+/// Regular, non-hidden files in `dir` whose format can be detected from the
+/// name, sorted lexically. `.env`-style names are included; anything else
+/// (READMEs, editor backups) is skipped.
+fn config_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let is_env = name == ".env" || name.starts_with(".env.");
+        if !path.is_file() || (name.starts_with('.') && !is_env) {
+            continue;
+        }
+        if is_env || path.extension().is_some() && detect_format(name, "", None).is_ok() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The namespace key of each of `files`. Two files with the same key would
+/// silently merge into one namespace, so that is an error naming both.
+fn namespace_keys(files: &[PathBuf], keep_prefix: bool) -> Result<Vec<String>> {
+    let mut seen: BTreeMap<String, &Path> = BTreeMap::new();
+    let mut keys = Vec::with_capacity(files.len());
+    for path in files {
+        let key = namespace_key(path, keep_prefix);
+        if let Some(first) = seen.insert(key.clone(), path) {
+            let hint = if keep_prefix {
+                ""
+            } else {
+                " (--keep-prefix keeps ordering prefixes in the key)"
+            };
+            bail!(
+                "{} and {} both namespace to \"{key}\"; rename one{hint}",
+                first.display(),
+                path.display()
+            );
+        }
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+/// Namespace key for a file: its stem, minus a numeric ordering prefix
+/// such as `10-` or `020_` unless `keep_prefix` is set.
+pub(crate) fn namespace_key(path: &Path, keep_prefix: bool) -> String {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .trim_start_matches('.');
+    if keep_prefix {
+        return stem.to_string();
+    }
+    let digits = stem.chars().take_while(char::is_ascii_digit).count();
+    match stem[digits..].strip_prefix(['-', '_']) {
+        Some(rest) if digits > 0 && !rest.is_empty() => rest.to_string(),
+        _ => stem.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn deep_merge_overrides_scalars_and_merges_objects() {
+        let mut base = json!({"server": {"host": "0.0.0.0", "port": 80}, "tags": ["a"]});
+        deep_merge(
+            &mut base,
+            json!({"server": {"port": 8080}, "tags": ["b"], "debug": true}),
+        );
+        assert_eq!(
+            base,
+            json!({"server": {"host": "0.0.0.0", "port": 8080}, "tags": ["b"], "debug": true})
+        );
+    }
+
+    #[test]
+    fn deep_merge_replaces_on_type_change() {
+        let mut base = json!({"db": {"host": "x"}});
+        deep_merge(&mut base, json!({"db": "sqlite://memory"}));
+        assert_eq!(base, json!({"db": "sqlite://memory"}));
+    }
+
+    #[test]
+    fn namespace_key_strips_ordering_prefix() {
+        assert_eq!(namespace_key(Path::new("conf.d/10-db.yaml"), false), "db");
+        assert_eq!(namespace_key(Path::new("020_cache.toml"), false), "cache");
+        assert_eq!(namespace_key(Path::new("10-db.yaml"), true), "10-db");
+        assert_eq!(namespace_key(Path::new("2024.json"), false), "2024");
+        assert_eq!(namespace_key(Path::new(".env"), false), "env");
+    }
+
+    #[test]
+    fn files_sharing_a_namespace_are_an_error() {
+        let files = ["conf.d/10-db.yaml", "conf.d/20-db.json"].map(PathBuf::from);
+        let err = namespace_keys(&files, false).unwrap_err().to_string();
+        assert!(
+            err.contains("conf.d/10-db.yaml and conf.d/20-db.json both namespace to \"db\""),
+            "{err}"
+        );
+        assert_eq!(namespace_keys(&files, true).unwrap(), ["10-db", "20-db"]);
+    }
+
+    #[test]
+    fn config_files_are_lexical_and_skip_unknown() {
+        let dir = std::env::temp_dir().join(format!(
+            "confctl-compose-dir-{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["20-b.yaml", "10-a.json", "README.md", ".hidden.yaml"] {
+            fs::write(dir.join(name), "{}").unwrap();
+        }
+
+        let files = config_files(&dir).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["10-a.json", "20-b.yaml"]);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn annotations_name_the_file_each_value_came_from() {
+        let layers = [
+            json!({"db": {"host": "localhost", "port": 5432}, "tags": ["a"], "debug": true}),
+            json!({"db": {"host": "db.prod"}, "tags": ["b", "c"], "cache": {"ttl": 60}}),
+            json!({"debug": false, "cache": {}}),
+        ];
+        let mut combined = json!({});
+        let mut origin = Origin::Keys(BTreeMap::new());
+        for (layer, value) in layers.into_iter().enumerate() {
+            traced_merge(&mut combined, &mut origin, value, layer, &[]);
+        }
+        let names = ["base.yaml", "prod.yaml", "local.json"].map(String::from);
+        assert_eq!(
+            commented::render(&annotated(&combined, &origin, &names)),
+            "cache:  # prod.yaml\n\
+             \x20 ttl: 60\n\
+             db:\n\
+             \x20 host: db.prod  # prod.yaml\n\
+             \x20 port: 5432  # base.yaml\n\
+             debug: false  # local.json\n\
+             tags:  # prod.yaml\n\
+             \x20 - b\n\
+             \x20 - c\n"
+        );
+
+        // A value one file set whole is noted once, at the top.
+        let mut combined = json!({});
+        let mut origin = Origin::Keys(BTreeMap::new());
+        traced_merge(&mut combined, &mut origin, json!({"a": {"b": 1}}), 0, &[]);
+        assert_eq!(
+            commented::render(&annotated(&combined, &origin, &names)),
+            "# base.yaml\na:\n  b: 1\n"
+        );
+    }
+
+    #[test]
+    fn array_keys_match_elements_by_their_field() {
+        let layers = [
+            json!({"servers": [{"name": "a", "port": 1}, {"name": "b", "port": 2}], "ports": [1]}),
+            json!({"servers": [{"name": "b", "port": 3}, {"name": "c", "port": 4}], "ports": [2]}),
+        ];
+        let keys = ["id".to_string(), "name".to_string()];
+        let mut combined = json!({});
+        let mut origin = Origin::Keys(BTreeMap::new());
+        for (layer, value) in layers.into_iter().enumerate() {
+            traced_merge(&mut combined, &mut origin, value, layer, &keys);
+        }
+        assert_eq!(
+            combined,
+            json!({
+                "servers": [{"name": "a", "port": 1}, {"name": "b", "port": 3}, {"name": "c", "port": 4}],
+                "ports": [2]
+            })
+        );
+        let names = ["base.yaml", "prod.yaml"].map(String::from);
+        assert_eq!(
+            commented::render(&annotated(&combined, &origin, &names)),
+            "ports:  # prod.yaml\n\
+             \x20 - 2\n\
+             servers:\n\
+             \x20 # base.yaml\n\
+             \x20 - name: a\n\
+             \x20   port: 1\n\
+             \x20 # prod.yaml\n\
+             \x20 - name: b\n\
+             \x20   port: 3\n\
+             \x20 # prod.yaml\n\
+             \x20 - name: c\n\
+             \x20   port: 4\n"
+        );
+
+        // Elements that are not all identified are replaced whole.
+        let items = [json!({"name": "a"}), json!({"name": "a"})];
+        assert_eq!(matching_key(&keys, &[&items]), None);
+        assert_eq!(
+            matching_key(&keys, &[&[json!({"name": "a"}), json!("b")]]),
+            None
+        );
+        assert_eq!(
+            matching_key(&keys, &[&[json!({"id": 1, "name": "a"})], &[]]),
+            Some("id")
+        );
+    }
+}
@@ -0,0 +1,413 @@
+//! `confctl merge` — a structural three-way merge of config documents, for
+//! resolving the merges git would otherwise do line by line.
+//!
+//! BASE is the common ancestor of OURS and THEIRS. Key by key: a value only
+//! one side changed (or removed) takes that side's version, and one both
+//! sides changed the same way is kept. Objects both sides changed are merged
+//! key by key; any other value both changed differently — arrays included,
+//! which are compared whole — is a conflict. With `--array-key FIELD`,
+//! arrays whose elements FIELD identifies (see `merge::matching_key`) are
+//! merged element by element instead, matched by FIELD: ours's elements in
+//! their order, then those only theirs added. Conflict paths name such an
+//! element `FIELD=VALUE`.
+//!
+//! The result is written in OURS's format. Conflicts are listed on stderr
+//! and, in YAML, written between git's `<<<<<<<`, `=======` and `>>>>>>>`
+//! lines for an editor to resolve; other formats cannot hold markers, so
+//! nothing is written. `--favor` settles conflicts with one side instead.
+//! Comments and layout are not kept: the document is written anew.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use crate::emit::commented::{self, Conflict, Node, Shape};
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::{atomic, detect_format, input, merge, parse_bytes, path, undo, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct MergeCli {
+    /// The common ancestor of OURS and THEIRS.
+    #[arg(long, value_name = "FILE")]
+    pub(crate) base: String,
+
+    /// Our version; the result is written in its format.
+    #[arg(long, value_name = "FILE")]
+    pub(crate) ours: String,
+
+    /// Their version.
+    #[arg(long, value_name = "FILE")]
+    pub(crate) theirs: String,
+
+    /// Input format of all three, instead of detecting each.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Settle conflicts with this side's version instead of reporting them.
+    #[arg(long, value_enum, value_name = "SIDE")]
+    pub(crate) favor: Option<Side>,
+
+    /// Match the elements of arrays of objects by this field instead of
+    /// comparing arrays whole; repeat for lists keyed differently.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) array_key: Vec<String>,
+
+    /// Write the result over OURS instead of printing it.
+    #[arg(long)]
+    pub(crate) in_place: bool,
+}
+
+impl MergeCli {
+    pub(crate) fn mutation(&self) -> Option<&'static str> {
+        self.in_place.then_some("merge --in-place")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum Side {
+    Ours,
+    Theirs,
+}
+
+/// Merge and write the result; whether conflicts are left.
+pub(crate) fn run(cli: MergeCli) -> Result<bool> {
+    let parse_opts = ParseOptions::default();
+    let base = input::open(&cli.base).load(cli.format, &parse_opts)?;
+    let (name, bytes) = input::open(&cli.ours).read()?;
+    let format = detect_format(&name, &String::from_utf8_lossy(&bytes), cli.format)?;
+    let ours = parse_bytes(&name, &bytes, Some(format), &parse_opts)?;
+    let theirs = input::open(&cli.theirs).load(cli.format, &parse_opts)?;
+
+    let labels = [cli.ours.clone(), cli.theirs.clone()];
+    let (merged, conflicts) = merge(&base, &ours, &theirs, cli.favor, &cli.array_key, labels);
+    report(&conflicts);
+
+    let output = OutputFormat::from_input(format).unwrap_or(OutputFormat::Json);
+    let Some(body) = render(&merged, conflicts.is_empty(), output)? else {
+        bail!(
+            "conflicts are left, and only YAML can hold conflict markers; resolve them with \
+             --favor or by hand"
+        );
+    };
+    if !cli.in_place {
+        print!("{body}");
+    } else if cli.ours == "-" {
+        bail!("--in-place needs a file, not stdin");
+    } else {
+        undo::record(Path::new(&cli.ours), "merge")?;
+        atomic::write(Path::new(&cli.ours), body, None)?;
+        if conflicts.is_empty() && !atomic::previewing() {
+            println!(
+                "{} merged {} into {}",
+                "✓".green().bold(),
+                cli.theirs,
+                cli.ours
+            );
+        }
+    }
+    Ok(!conflicts.is_empty())
+}
+
+/// The merge of three documents and the conflicts left in it, with arrays
+/// matched by `array_keys` and `labels` on OURS's and THEIRS's markers.
+pub(crate) fn merge(
+    base: &Value,
+    ours: &Value,
+    theirs: &Value,
+    favor: Option<Side>,
+    array_keys: &[String],
+    labels: [String; 2],
+) -> (Node, Vec<Clash>) {
+    let mut merger = Merger {
+        favor,
+        array_keys,
+        labels,
+        conflicts: Vec::new(),
+    };
+    let merged = merger
+        .merge(Some(base), Some(ours), Some(theirs), &mut Vec::new())
+        .unwrap_or_else(|| Node::plain(&Value::Null));
+    (merged, merger.conflicts)
+}
+
+/// List `conflicts` on stderr.
+pub(crate) fn report(conflicts: &[Clash]) {
+    for conflict in conflicts {
+        eprintln!("{} {conflict}", "conflict:".red().bold());
+    }
+}
+
+/// The merged document in `output`; `None` when it is not `clean` and
+/// `output` cannot hold conflict markers (anything but YAML).
+pub(crate) fn render(merged: &Node, clean: bool, output: OutputFormat) -> Result<Option<String>> {
+    Ok(match output {
+        _ if clean => Some(emit::render(&merged.value(), output)?),
+        OutputFormat::Yaml => Some(commented::render(merged)),
+        _ => None,
+    })
+}
+
+/// A value both sides changed differently.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Clash {
+    path: String,
+    base: Option<Value>,
+    ours: Option<Value>,
+    theirs: Option<Value>,
+}
+
+impl std::fmt::Display for Clash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |value: &Option<Value>| match value {
+            Some(value) => value.to_string(),
+            None => "removed".to_string(),
+        };
+        match &self.base {
+            Some(base) => write!(f, "{}: was {base}", self.path)?,
+            None => write!(f, "{}: added on both sides", self.path)?,
+        }
+        write!(
+            f,
+            ", ours {}, theirs {}",
+            show(&self.ours),
+            show(&self.theirs)
+        )
+    }
+}
+
+struct Merger<'a> {
+    favor: Option<Side>,
+    array_keys: &'a [String],
+    labels: [String; 2],
+    conflicts: Vec<Clash>,
+}
+
+impl Merger<'_> {
+    /// The merge of one value (`None` where it is absent), or `None` when
+    /// the result has none.
+    fn merge(
+        &mut self,
+        base: Option<&Value>,
+        ours: Option<&Value>,
+        theirs: Option<&Value>,
+        trail: &mut Vec<String>,
+    ) -> Option<Node> {
+        if ours == theirs || theirs == base {
+            return ours.map(Node::plain);
+        }
+        if ours == base {
+            return theirs.map(Node::plain);
+        }
+        if let (Some(Value::Object(mine)), Some(Value::Object(yours))) = (ours, theirs) {
+            // An ancestor that was no object (or none) had none of the keys.
+            let empty = Map::new();
+            let before = match base {
+                Some(Value::Object(before)) => before,
+                _ => &empty,
+            };
+            let keys: BTreeSet<&String> = before
+                .keys()
+                .chain(mine.keys())
+                .chain(yours.keys())
+                .collect();
+            let mut entries = Vec::new();
+            for key in keys {
+                trail.push(key.clone());
+                let merged = self.merge(before.get(key), mine.get(key), yours.get(key), trail);
+                trail.pop();
+                if let Some(node) = merged {
+                    entries.push((key.clone(), node));
+                }
+            }
+            return Some(Node {
+                shape: Shape::Object(entries),
+                ..Node::default()
+            });
+        }
+        if let (Some(Value::Array(mine)), Some(Value::Array(yours))) = (ours, theirs) {
+            let before = match base {
+                Some(Value::Array(before)) => before.as_slice(),
+                _ => &[],
+            };
+            if let Some(field) = merge::matching_key(self.array_keys, &[before, mine, yours]) {
+                return Some(self.merge_items(field, before, mine, yours, trail));
+            }
+        }
+        match self.favor {
+            Some(Side::Ours) => return ours.map(Node::plain),
+            Some(Side::Theirs) => return theirs.map(Node::plain),
+            None => {}
+        }
+        self.conflicts.push(Clash {
+            path: if trail.is_empty() {
+                "the document".to_string()
+            } else {
+                path::join(trail)
+            },
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+        });
+        Some(Node {
+            shape: Shape::Conflict(Box::new(Conflict {
+                ours: ours.map(Node::plain),
+                theirs: theirs.map(Node::plain),
+                labels: self.labels.clone(),
+            })),
+            ..Node::default()
+        })
+    }
+
+    /// The merge of arrays whose elements `field` identifies, element by
+    /// element: ours's in their order, then those only theirs has.
+    fn merge_items(
+        &mut self,
+        field: &str,
+        base: &[Value],
+        ours: &[Value],
+        theirs: &[Value],
+        trail: &mut Vec<String>,
+    ) -> Node {
+        let find = |items: &'_ [Value], id: &Value| -> Option<usize> {
+            items.iter().position(|item| item.get(field) == Some(id))
+        };
+        let ids: Vec<&Value> = ours
+            .iter()
+            .chain(
+                theirs
+                    .iter()
+                    .filter(|item| find(ours, &item[field]).is_none()),
+            )
+            .map(|item| &item[field])
+            .collect();
+        let mut items = Vec::new();
+        for id in ids {
+            let [before, mine, yours] =
+                [base, ours, theirs].map(|side| Some(&side[find(side, id)?]));
+            trail.push(match id {
+                Value::String(id) => format!("{field}={id}"),
+                id => format!("{field}={id}"),
+            });
+            items.extend(self.merge(before, mine, yours, trail));
+            trail.pop();
+        }
+        Node {
+            shape: Shape::Array(items),
+            ..Node::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn labelled(
+        base: Value,
+        ours: Value,
+        theirs: Value,
+        favor: Option<Side>,
+    ) -> (Node, Vec<Clash>) {
+        let labels = ["ours.yaml".into(), "theirs.yaml".into()];
+        merge(&base, &ours, &theirs, favor, &[], labels)
+    }
+
+    #[test]
+    fn changes_on_either_side_merge_cleanly() {
+        let base = json!({"db": {"host": "a", "port": 1}, "tags": ["x"], "old": true, "same": 1});
+        let ours = json!({"db": {"host": "b", "port": 1}, "tags": ["x"], "same": 2, "mine": 1});
+        let theirs = json!({"db": {"host": "a", "port": 2}, "tags": ["y"], "old": true, "same": 2});
+        let (node, conflicts) = labelled(base, ours, theirs, None);
+        assert_eq!(conflicts, vec![]);
+        assert_eq!(
+            node.value(),
+            json!({"db": {"host": "b", "port": 2}, "tags": ["y"], "same": 2, "mine": 1})
+        );
+    }
+
+    #[test]
+    fn differing_changes_conflict_with_markers() {
+        let base = json!({"db": {"port": 1, "user": "app"}, "tags": ["x"]});
+        let ours = json!({"db": {"port": 2}, "tags": ["x", "y"], "new": "a"});
+        let theirs = json!({"db": {"port": 3, "user": "admin"}, "tags": ["z"], "new": "b"});
+        let (node, conflicts) = labelled(base.clone(), ours.clone(), theirs.clone(), None);
+        let lines: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "db.port: was 1, ours 2, theirs 3",
+                "db.user: was \"app\", ours removed, theirs \"admin\"",
+                "new: added on both sides, ours \"a\", theirs \"b\"",
+                "tags: was [\"x\"], ours [\"x\",\"y\"], theirs [\"z\"]",
+            ]
+        );
+        assert_eq!(
+            commented::render(&node),
+            "db:\n\
+             <<<<<<< ours.yaml\n\
+             \x20 port: 2\n\
+             =======\n\
+             \x20 port: 3\n\
+             >>>>>>> theirs.yaml\n\
+             <<<<<<< ours.yaml\n\
+             =======\n\
+             \x20 user: admin\n\
+             >>>>>>> theirs.yaml\n\
+             <<<<<<< ours.yaml\n\
+             new: a\n\
+             =======\n\
+             new: b\n\
+             >>>>>>> theirs.yaml\n\
+             <<<<<<< ours.yaml\n\
+             tags:\n\
+             \x20 - x\n\
+             \x20 - \"y\"\n\
+             =======\n\
+             tags:\n\
+             \x20 - z\n\
+             >>>>>>> theirs.yaml\n"
+        );
+
+        let (node, conflicts) = labelled(base, ours, theirs.clone(), Some(Side::Theirs));
+        assert!(conflicts.is_empty());
+        assert_eq!(node.value(), theirs);
+    }
+
+    #[test]
+    fn array_keys_merge_elements_by_their_field() {
+        let base =
+            json!([{"name": "a", "port": 1}, {"name": "b", "port": 2}, {"name": "c", "port": 3}]);
+        let ours = json!([{"name": "b", "port": 2, "tls": true}, {"name": "a", "port": 1}, {"name": "c", "port": 9}]);
+        let theirs =
+            json!([{"name": "a", "port": 5}, {"name": "c", "port": 8}, {"name": "d", "port": 4}]);
+        let keys = ["name".to_string()];
+        let labels = || ["ours".to_string(), "theirs".to_string()];
+        let (node, conflicts) = merge(&base, &ours, &theirs, None, &keys, labels());
+        let lines: Vec<String> = conflicts.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "name=b: was {\"name\":\"b\",\"port\":2}, ours {\"name\":\"b\",\"port\":2,\"tls\":true}, theirs removed",
+                "name=c.port: was 3, ours 9, theirs 8",
+            ]
+        );
+        assert_eq!(
+            node.value(),
+            json!([
+                {"name": "b", "port": 2, "tls": true},
+                {"name": "a", "port": 5},
+                {"name": "c", "port": 9},
+                {"name": "d", "port": 4}
+            ])
+        );
+
+        let (node, _) = merge(&base, &ours, &theirs, Some(Side::Theirs), &keys, labels());
+        assert_eq!(node.value(), theirs);
+    }
+}
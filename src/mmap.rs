@@ -0,0 +1,98 @@
+//! Memory-mapped input for large files (the `mmap` feature, unix only).
+//!
+//! Reading a multi-hundred-megabyte file into a `Vec` holds the whole text
+//! on the heap next to the parsed `Value`. Mapping it instead lets the
+//! parsers borrow the text straight from the page cache, which the kernel
+//! can drop and re-read under pressure, so peak memory is the `Value`
+//! alone. Small files are cheaper to read, and a file that cannot be mapped
+//! (a FIFO, `/proc`, a filesystem without mmap) is read as before.
+//!
+//! As with any mapping, a file truncated by another process while it is
+//! being parsed makes the read fault (SIGBUS) rather than fail cleanly.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+/// Files at least this large are mapped rather than read.
+pub(crate) const THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// A read-only, private mapping of a whole file.
+pub(crate) struct Mapped {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapped {
+    /// `path` mapped when it is a regular file of at least [`THRESHOLD`]
+    /// bytes; `None` when it should be read normally instead (including
+    /// when it cannot be opened, so the read reports the error).
+    pub(crate) fn large(path: &str) -> Option<Mapped> {
+        let file = File::open(path).ok()?;
+        let meta = file.metadata().ok()?;
+        if !meta.is_file() || meta.len() < THRESHOLD {
+            return None;
+        }
+        Mapped::new(&file, usize::try_from(meta.len()).ok()?).ok()
+    }
+
+    /// The first `len` bytes of `file`; `len` must not be zero.
+    pub(crate) fn new(file: &File, len: usize) -> io::Result<Mapped> {
+        // SAFETY: a fresh PROT_READ/MAP_PRIVATE mapping chosen by the kernel
+        // aliases no Rust memory; failure is reported as MAP_FAILED.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapped { ptr, len })
+    }
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `ptr` maps `len` readable bytes until `drop` unmaps them,
+        // and nothing writes through the private mapping.
+        unsafe { std::slice::from_raw_parts(self.ptr.cast::<u8>(), self.len) }
+    }
+}
+
+impl Drop for Mapped {
+    fn drop(&mut self) {
+        // SAFETY: unmaps exactly the mapping `new` created, once.
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_the_file_contents() {
+        let path = std::env::temp_dir().join(format!("confctl-mmap-{}.json", std::process::id()));
+        std::fs::write(&path, "{\"a\": [1, 2]}").unwrap();
+        let file = File::open(&path).unwrap();
+        let mapped = Mapped::new(&file, 13).unwrap();
+        assert_eq!(&*mapped, b"{\"a\": [1, 2]}");
+
+        // Small files are read, not mapped.
+        assert!(Mapped::large(path.to_str().unwrap()).is_none());
+        assert!(Mapped::large("/nonexistent/confctl.json").is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+//! Timeouts and retries for the network sources: URLs, cloud storage,
+//! `vault://`, `etcd://` and `consul://` (and kubectl's request timeout for
+//! `k8s://`). `--timeout` bounds each request, 30s by default; a request
+//! that fails in a way that may pass (connection refused or reset, a
+//! timeout, 408, 429 or 5xx) is tried again up to `--retries` times, 2 by
+//! default, waiting 0.5s, 1s, 2s… (at most 8s, or what `Retry-After`
+//! asks up to 30s) in between. DNS, TLS and other 4xx failures are not
+//! retried. The error that ends it is an `errors::RemoteError`, classified
+//! for `--error-format json`.
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+#[cfg(feature = "http")]
+use crate::errors::{RemoteError, RemoteKind};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RETRIES: u32 = 2;
+
+struct Policy {
+    timeout: Duration,
+    #[cfg_attr(not(feature = "http"), allow(dead_code))]
+    retries: u32,
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Set `--timeout` and `--retries` for this process.
+pub(crate) fn configure(timeout: Option<Duration>, retries: Option<u32>) {
+    let _ = POLICY.set(Policy {
+        timeout: timeout
+            .filter(|timeout| !timeout.is_zero())
+            .unwrap_or(DEFAULT_TIMEOUT),
+        retries: retries.unwrap_or(DEFAULT_RETRIES),
+    });
+}
+
+fn policy() -> &'static Policy {
+    POLICY.get_or_init(|| Policy {
+        timeout: DEFAULT_TIMEOUT,
+        retries: DEFAULT_RETRIES,
+    })
+}
+
+/// How long one request may take.
+pub(crate) fn timeout() -> Duration {
+    policy().timeout
+}
+
+/// An agent whose requests give up after `--timeout`; connecting gets at
+/// most 10s of it.
+#[cfg(feature = "http")]
+pub(crate) fn agent() -> ureq::Agent {
+    let timeout = timeout();
+    ureq::AgentBuilder::new()
+        .timeout_connect(timeout.min(Duration::from_secs(10)))
+        .timeout(timeout)
+        .user_agent(concat!("confctl/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// A request that failed for good.
+#[cfg(feature = "http")]
+pub(crate) struct Failed {
+    error: ureq::Error,
+    attempts: u32,
+}
+
+#[cfg(feature = "http")]
+impl Failed {
+    /// The HTTP status, when the server answered.
+    pub(crate) fn status(&self) -> Option<u16> {
+        match &self.error {
+            ureq::Error::Status(status, _) => Some(*status),
+            ureq::Error::Transport(_) => None,
+        }
+    }
+
+    /// A header of the server's answer.
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        match &self.error {
+            ureq::Error::Status(_, response) => response.header(name),
+            ureq::Error::Transport(_) => None,
+        }
+    }
+
+    /// The error `describe` makes of the last failure, as a RemoteError
+    /// that also says how often it was tried.
+    pub(crate) fn describe(
+        self,
+        describe: impl FnOnce(ureq::Error) -> anyhow::Error,
+    ) -> anyhow::Error {
+        let (kind, status) = classify(&self.error);
+        let mut message = describe(self.error).to_string();
+        if self.attempts > 1 {
+            message.push_str(&format!(" (gave up after {} attempts)", self.attempts));
+        }
+        anyhow::Error::new(RemoteError {
+            kind,
+            status,
+            attempts: self.attempts,
+            message,
+        })
+    }
+}
+
+/// `request` without a body, sent again after a pause for as long as it
+/// fails in a way that may pass and `--retries` allows.
+#[cfg(feature = "http")]
+pub(crate) fn call(request: &ureq::Request) -> Result<ureq::Response, Box<Failed>> {
+    send(request, None, policy().retries, Duration::from_millis(500))
+}
+
+/// `request` with a JSON body, retried as `call` does.
+#[cfg(feature = "http")]
+pub(crate) fn send_json(
+    request: &ureq::Request,
+    body: &serde_json::Value,
+) -> Result<ureq::Response, Box<Failed>> {
+    send(
+        request,
+        Some(body),
+        policy().retries,
+        Duration::from_millis(500),
+    )
+}
+
+#[cfg(feature = "http")]
+fn send(
+    request: &ureq::Request,
+    body: Option<&serde_json::Value>,
+    retries: u32,
+    first_delay: Duration,
+) -> Result<ureq::Response, Box<Failed>> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let sent = match body {
+            Some(body) => request.clone().send_json(body),
+            None => request.clone().call(),
+        };
+        let error = match sent {
+            Ok(response) => return Ok(response),
+            Err(error) => error,
+        };
+        let failed = Box::new(Failed { error, attempts });
+        if attempts > retries || !retryable(&failed.error) {
+            return Err(failed);
+        }
+        std::thread::sleep(delay(first_delay, attempts, failed.header("Retry-After")));
+    }
+}
+
+/// The pause before try `attempts + 1`: doubling from `first`, at most 8s,
+/// unless the server named a wait (in seconds) of up to 30s.
+#[cfg(feature = "http")]
+fn delay(first: Duration, attempts: u32, retry_after: Option<&str>) -> Duration {
+    if let Some(seconds) = retry_after.and_then(|value| value.trim().parse::<u64>().ok()) {
+        return Duration::from_secs(seconds.min(30));
+    }
+    (first * 2u32.saturating_pow(attempts - 1)).min(Duration::from_secs(8))
+}
+
+#[cfg(feature = "http")]
+fn retryable(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(status, _) => matches!(status, 408 | 429 | 500..=599),
+        ureq::Error::Transport(_) => {
+            matches!(classify(error).0, RemoteKind::Connect | RemoteKind::Timeout)
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+fn classify(error: &ureq::Error) -> (RemoteKind, Option<u16>) {
+    let transport = match error {
+        ureq::Error::Status(status, _) if *status >= 500 => {
+            return (RemoteKind::Server, Some(*status))
+        }
+        ureq::Error::Status(status, _) => return (RemoteKind::Client, Some(*status)),
+        ureq::Error::Transport(transport) => transport,
+    };
+    let kind = match transport.kind() {
+        ureq::ErrorKind::Dns => RemoteKind::Dns,
+        _ if timed_out(transport) => RemoteKind::Timeout,
+        ureq::ErrorKind::ConnectionFailed
+            if transport.message().is_some_and(|m| m.contains("tls")) =>
+        {
+            RemoteKind::Tls
+        }
+        ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::ProxyConnect => RemoteKind::Connect,
+        ureq::ErrorKind::Io => RemoteKind::Connect,
+        _ => RemoteKind::Other,
+    };
+    (kind, None)
+}
+
+/// Whether an I/O timeout is among the causes.
+#[cfg(feature = "http")]
+fn timed_out(transport: &ureq::Transport) -> bool {
+    let mut cause = std::error::Error::source(transport);
+    while let Some(err) = cause {
+        if let Some(io) = err.downcast_ref::<std::io::Error>() {
+            if matches!(
+                io.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+            ) {
+                return true;
+            }
+        }
+        cause = err.source();
+    }
+    false
+}
+
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    /// A server answering each connection with the next of `statuses`.
+    fn serve(statuses: &'static [u16]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/app.json", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut stream = stream.unwrap();
+                let _ = stream.read(&mut [0; 4096]);
+                let body = format!("{{\"status\": {status}}}");
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn passing_failures_are_retried_until_they_stop() {
+        let agent = agent();
+        let get = |url: &str, retries| send(&agent.get(url), None, retries, Duration::ZERO);
+
+        let url = serve(&[503, 429, 200]);
+        assert_eq!(
+            get(&url, 2).ok().map(|response| response.status()),
+            Some(200)
+        );
+
+        let url = serve(&[500, 502]);
+        let failed = get(&url, 1).err().unwrap();
+        assert_eq!((failed.attempts, failed.status()), (2, Some(502)));
+        let err = failed.describe(|err| anyhow::anyhow!("{err}"));
+        let remote = err.downcast_ref::<RemoteError>().unwrap();
+        assert_eq!(
+            (remote.kind, remote.status),
+            (RemoteKind::Server, Some(502))
+        );
+        assert!(remote.message.ends_with("(gave up after 2 attempts)"));
+
+        // A 404 will not pass by asking again.
+        let url = serve(&[404]);
+        let failed = get(&url, 2).err().unwrap();
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(classify(&failed.error), (RemoteKind::Client, Some(404)));
+
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let failed = get(&format!("http://{closed}/"), 0).err().unwrap();
+        assert_eq!(classify(&failed.error), (RemoteKind::Connect, None));
+    }
+
+    #[test]
+    fn pauses_double_up_to_a_cap() {
+        let first = Duration::from_millis(500);
+        assert_eq!(delay(first, 1, None), Duration::from_millis(500));
+        assert_eq!(delay(first, 3, None), Duration::from_secs(2));
+        assert_eq!(delay(first, 10, None), Duration::from_secs(8));
+        assert_eq!(delay(first, 1, Some("3")), Duration::from_secs(3));
+        assert_eq!(delay(first, 1, Some("3600")), Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,114 @@
+//! `--jobs`: a small worker pool for per-file work. No rayon: the threads
+//! pull items off a shared counter, and results come back to the calling
+//! thread in input order, each as soon as it and everything before it is
+//! done, so output streams while keeping the order a serial run prints.
+
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::Result;
+
+/// Worker count for `--jobs`: as asked, else one per CPU.
+pub(crate) fn jobs(requested: Option<NonZeroUsize>) -> usize {
+    requested
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+}
+
+/// `work` over every item on up to `jobs` threads; `done` sees each item
+/// with its result, in input order, on this thread. An error from `done`
+/// stops handing out items and is returned once the busy workers finish.
+pub(crate) fn for_each_ordered<T, R>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> R + Sync,
+    mut done: impl FnMut(&T, R) -> Result<()>,
+) -> Result<()>
+where
+    T: Sync,
+    R: Send,
+{
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().try_for_each(|item| done(item, work(item)));
+    }
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (next, stop, work) = (&next, &stop, &work);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let at = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(at) else { break };
+                    if sender.send((at, work(item))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut ready = BTreeMap::new();
+        let mut emitted = 0;
+        for (at, result) in receiver {
+            ready.insert(at, result);
+            while let Some(result) = ready.remove(&emitted) {
+                if let Err(err) = done(&items[emitted], result) {
+                    stop.store(true, Ordering::Relaxed);
+                    return Err(err);
+                }
+                emitted += 1;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_come_back_in_input_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let mut seen = Vec::new();
+        // Later items finish first; the order must not follow.
+        for_each_ordered(
+            &items,
+            4,
+            |n| {
+                thread::sleep(Duration::from_millis(20 - n));
+                n * 10
+            },
+            |n, result| {
+                seen.push((*n, result));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(seen, items.iter().map(|n| (*n, n * 10)).collect::<Vec<_>>());
+
+        let mut count = 0;
+        let err = for_each_ordered(
+            &items,
+            3,
+            |n| *n,
+            |n, _| {
+                count += 1;
+                anyhow::ensure!(*n < 5, "stop at {n}");
+                Ok(())
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "stop at 5");
+        assert_eq!(count, 6);
+    }
+}
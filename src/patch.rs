@@ -0,0 +1,460 @@
+//! `confctl patch` — apply a JSON Patch (RFC 6902, a list of operations) or
+//! a JSON Merge Patch (RFC 7386, a partial document) to a config file:
+//!
+//! ```text
+//! confctl patch config.json --patch ops.json            # print the result
+//! confctl patch values.yaml --patch prod.yaml -i        # edit in place
+//! ```
+//!
+//! The patch may be any format confctl reads; an array is a JSON Patch and
+//! anything else a merge patch. JSON Patch is all-or-nothing: when one
+//! operation fails (including a `test`) nothing is printed or written.
+//!
+//! [`between`] goes the other way, for `confctl diff --output json-patch`.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::atomic::{self, BackupArg};
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::{detect_format, input, parse_bytes, undo, Format};
+
+#[derive(Args, Debug)]
+#[command(mut_arg("backup", |arg| arg.requires("in_place")))]
+pub(crate) struct PatchCli {
+    /// Config file to patch, or `-` for stdin.
+    pub(crate) file: String,
+
+    /// The patch: a JSON Patch operation list or a merge patch document, in
+    /// any supported format.
+    #[arg(long, value_name = "PATCH")]
+    pub(crate) patch: String,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Write the result back to FILE instead of printing it.
+    #[arg(short = 'i', long)]
+    pub(crate) in_place: bool,
+
+    #[command(flatten)]
+    pub(crate) backup: BackupArg,
+}
+
+impl PatchCli {
+    pub(crate) fn mutation(&self) -> Option<&'static str> {
+        self.in_place.then_some("patch --in-place")
+    }
+}
+
+/// One RFC 6902 operation. Members an operation does not define, such as a
+/// `comment`, are ignored, as §4 requires.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub(crate) enum Operation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+impl Operation {
+    fn describe(&self) -> String {
+        match self {
+            Operation::Add { path, .. } => format!("add {path}"),
+            Operation::Remove { path } => format!("remove {path}"),
+            Operation::Replace { path, .. } => format!("replace {path}"),
+            Operation::Move { from, path } => format!("move {from} to {path}"),
+            Operation::Copy { from, path } => format!("copy {from} to {path}"),
+            Operation::Test { path, .. } => format!("test {path}"),
+        }
+    }
+
+    fn apply(&self, doc: &mut Value) -> Result<()> {
+        match self {
+            Operation::Add { path, value } => add(doc, path, value.clone()),
+            Operation::Remove { path } => remove(doc, path).map(drop),
+            Operation::Replace { path, value } => {
+                *lookup(doc, path)? = value.clone();
+                Ok(())
+            }
+            Operation::Move { from, path } => {
+                if path.starts_with(&format!("{from}/")) {
+                    bail!("cannot move {from} into itself");
+                }
+                let value = remove(doc, from)?;
+                add(doc, path, value)
+            }
+            Operation::Copy { from, path } => {
+                let value = lookup(doc, from)?.clone();
+                add(doc, path, value)
+            }
+            Operation::Test { path, value } => {
+                let actual = lookup(doc, path)?;
+                if !same(actual, value) {
+                    bail!("{path} is {actual}, not {value}");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// RFC 6902 §4.6 equality: numbers by their value (`1` is `1.0`), arrays
+/// item by item, objects key by key.
+fn same(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => a.as_f64() == b.as_f64(),
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len() && a.iter().all(|(k, v)| b.get(k).is_some_and(|w| same(v, w)))
+        }
+        _ => a == b,
+    }
+}
+
+/// Apply `ops` in order. `doc` is left untouched when any of them fails.
+pub(crate) fn apply_json_patch(doc: &mut Value, ops: &[Operation]) -> Result<()> {
+    let mut patched = doc.clone();
+    for (i, op) in ops.iter().enumerate() {
+        op.apply(&mut patched)
+            .with_context(|| format!("operation {} ({}) failed", i + 1, op.describe()))?;
+    }
+    *doc = patched;
+    Ok(())
+}
+
+/// RFC 7386: objects merge key by key, `null` removes a key, and anything
+/// else (arrays included) replaces the target.
+pub(crate) fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let Value::Object(target) = target else {
+        unreachable!("made an object above");
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// The operations that turn `left` into `right`: `replace` for changed
+/// values, `remove` and `add` for keys and array items only one side has.
+/// Array items are compared by position, and trailing removals run from the
+/// end so every index is still valid when its operation applies.
+pub(crate) fn between(left: &Value, right: &Value) -> Vec<Operation> {
+    let mut ops = Vec::new();
+    diff_at(String::new(), left, right, &mut ops);
+    ops
+}
+
+fn diff_at(pointer: String, left: &Value, right: &Value, ops: &mut Vec<Operation>) {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            for (key, value) in left {
+                let path = format!("{pointer}/{}", escape(key));
+                match right.get(key) {
+                    Some(other) => diff_at(path, value, other, ops),
+                    None => ops.push(Operation::Remove { path }),
+                }
+            }
+            for (key, value) in right.iter().filter(|(key, _)| !left.contains_key(*key)) {
+                ops.push(Operation::Add {
+                    path: format!("{pointer}/{}", escape(key)),
+                    value: value.clone(),
+                });
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            for (i, (a, b)) in left.iter().zip(right).enumerate() {
+                diff_at(format!("{pointer}/{i}"), a, b, ops);
+            }
+            for i in (right.len()..left.len()).rev() {
+                ops.push(Operation::Remove {
+                    path: format!("{pointer}/{i}"),
+                });
+            }
+            for value in right.iter().skip(left.len()) {
+                ops.push(Operation::Add {
+                    path: format!("{pointer}/-"),
+                    value: value.clone(),
+                });
+            }
+        }
+        _ if left == right => {}
+        _ => ops.push(Operation::Replace {
+            path: pointer,
+            value: right.clone(),
+        }),
+    }
+}
+
+/// `key` as a JSON Pointer reference token.
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// The reference tokens of a JSON Pointer, `~1` and `~0` unescaped.
+fn tokens(pointer: &str) -> Result<Vec<String>> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(rest) = pointer.strip_prefix('/') else {
+        bail!("`{pointer}` is not a JSON Pointer (it must start with `/`)");
+    };
+    Ok(rest
+        .split('/')
+        .map(|t| t.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn lookup<'a>(doc: &'a mut Value, pointer: &str) -> Result<&'a mut Value> {
+    walk(doc, &tokens(pointer)?, pointer)
+}
+
+/// The value `tokens` lead to; `pointer` is what they came from, for errors.
+fn walk<'a>(doc: &'a mut Value, tokens: &[String], pointer: &str) -> Result<&'a mut Value> {
+    let mut current = doc;
+    for token in tokens {
+        current = match current {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(items) => index(token).and_then(|i| items.get_mut(i)),
+            _ => None,
+        }
+        .with_context(|| format!("{pointer} does not exist"))?;
+    }
+    Ok(current)
+}
+
+/// An array index token: `0`, or digits without a leading zero (RFC 6901).
+fn index(token: &str) -> Option<usize> {
+    let digits = !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit());
+    if !digits || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// The container holding `pointer`'s target, and the last token.
+fn parent<'a>(doc: &'a mut Value, pointer: &str) -> Result<(&'a mut Value, String)> {
+    let mut tokens = tokens(pointer)?;
+    let Some(last) = tokens.pop() else {
+        bail!("the whole document has no parent");
+    };
+    Ok((walk(doc, &tokens, pointer)?, last))
+}
+
+fn add(doc: &mut Value, pointer: &str, value: Value) -> Result<()> {
+    if pointer.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let (container, last) = parent(doc, pointer)?;
+    match container {
+        Value::Object(map) => {
+            map.insert(last, value);
+        }
+        Value::Array(items) if last == "-" => items.push(value),
+        Value::Array(items) => match index(&last) {
+            Some(index) if index <= items.len() => items.insert(index, value),
+            _ => bail!("{pointer}: no index {last} in an array of {}", items.len()),
+        },
+        _ => bail!("{pointer}: the parent is not an object or array"),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, pointer: &str) -> Result<Value> {
+    if pointer.is_empty() {
+        return Ok(std::mem::take(doc));
+    }
+    let (container, last) = parent(doc, pointer)?;
+    let removed = match container {
+        Value::Object(map) => map.remove(&last),
+        Value::Array(items) => match index(&last) {
+            Some(index) if index < items.len() => Some(items.remove(index)),
+            _ => None,
+        },
+        _ => None,
+    };
+    removed.with_context(|| format!("{pointer} does not exist"))
+}
+
+pub(crate) fn run(cli: PatchCli) -> Result<()> {
+    let parse_opts = ParseOptions::default();
+    let (name, bytes) = input::open(&cli.file).read()?;
+    let format = detect_format(&name, &String::from_utf8_lossy(&bytes), cli.format)?;
+    let mut doc = parse_bytes(&name, &bytes, Some(format), &parse_opts)?;
+    let patch = input::open(&cli.patch).load(None, &parse_opts)?;
+    match patch {
+        Value::Array(_) => {
+            let ops: Vec<Operation> = serde_json::from_value(patch)
+                .with_context(|| format!("{} is not a JSON Patch", cli.patch))?;
+            apply_json_patch(&mut doc, &ops)?;
+        }
+        patch => merge_patch(&mut doc, &patch),
+    }
+
+    let output = OutputFormat::from_input(format).unwrap_or(OutputFormat::Json);
+    let body =
+        emit::render(&doc, output).with_context(|| format!("writing the patched {}", cli.file))?;
+    if !cli.in_place {
+        print!("{body}");
+        return Ok(());
+    }
+    if cli.file == "-" {
+        bail!("--in-place needs a file, not stdin");
+    }
+    let file = std::path::Path::new(&cli.file);
+    undo::record(file, "patch")?;
+    atomic::write(file, body, cli.backup.suffix())?;
+    if !atomic::previewing() {
+        println!("{} patched {}", "✓".green().bold(), cli.file);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn ops(value: Value) -> Vec<Operation> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn json_patch_operations() {
+        let mut doc = json!({"db": {"host": "a", "port": 1}, "tags": ["x"], "a/b": 1});
+        let patch = ops(json!([
+            {"op": "replace", "path": "/db/host", "value": "b"},
+            {"op": "add", "path": "/tags/-", "value": "z"},
+            {"op": "add", "path": "/tags/1", "value": "y"},
+            {"op": "remove", "path": "/a~1b"},
+            {"op": "copy", "from": "/db/port", "path": "/port"},
+            {"op": "move", "from": "/db/port", "path": "/db/pool"},
+            {"op": "test", "path": "/port", "value": 1}
+        ]));
+        apply_json_patch(&mut doc, &patch).unwrap();
+        assert_eq!(
+            doc,
+            json!({"db": {"host": "b", "pool": 1}, "tags": ["x", "y", "z"], "port": 1})
+        );
+    }
+
+    #[test]
+    fn a_failing_operation_changes_nothing() {
+        let original = json!({"replicas": 2});
+        let mut doc = original.clone();
+        let patch = ops(json!([
+            {"op": "replace", "path": "/replicas", "value": 5},
+            {"op": "test", "path": "/replicas", "value": 3}
+        ]));
+        let err = apply_json_patch(&mut doc, &patch).unwrap_err();
+        assert_eq!(err.to_string(), "operation 2 (test /replicas) failed");
+        assert_eq!(
+            format!("{:#}", err),
+            "operation 2 (test /replicas) failed: /replicas is 5, not 3"
+        );
+        assert_eq!(doc, original);
+
+        let err = apply_json_patch(&mut doc, &ops(json!([{"op": "remove", "path": "/nope"}])))
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "operation 1 (remove /nope) failed: /nope does not exist"
+        );
+        assert!(
+            serde_json::from_value::<Vec<Operation>>(json!([{"op": "rename", "path": "/a"}]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn operations_follow_rfc_6902_details() {
+        let mut doc = json!({"v": 1, "list": ["a", "b"], "m": {"n": [2.0]}});
+        let patch = ops(json!([
+            {"op": "replace", "path": "/v", "value": 2, "comment": "bump"},
+            {"op": "test", "path": "/v", "value": 2.0},
+            {"op": "test", "path": "/m", "value": {"n": [2]}}
+        ]));
+        apply_json_patch(&mut doc, &patch).unwrap();
+        assert_eq!(doc["v"], json!(2));
+
+        for pointer in ["/list/01", "/list/+1", "/list/"] {
+            let patch = ops(json!([{"op": "remove", "path": pointer}]));
+            assert!(apply_json_patch(&mut doc, &patch).is_err(), "{pointer}");
+            let patch = ops(json!([{"op": "replace", "path": pointer, "value": 0}]));
+            assert!(apply_json_patch(&mut doc, &patch).is_err(), "{pointer}");
+        }
+        let patch = ops(json!([{"op": "add", "path": "/list/01", "value": "c"}]));
+        assert!(apply_json_patch(&mut doc, &patch).is_err());
+        assert_eq!(doc["list"], json!(["a", "b"]));
+    }
+
+    #[test]
+    fn merge_patch_follows_rfc_7386() {
+        let mut doc = json!({"a": "b", "c": {"d": "e", "f": "g"}, "list": [1, 2]});
+        merge_patch(
+            &mut doc,
+            &json!({"a": "z", "c": {"f": null}, "list": [3], "new": {"x": 1}}),
+        );
+        assert_eq!(
+            doc,
+            json!({"a": "z", "c": {"d": "e"}, "list": [3], "new": {"x": 1}})
+        );
+        merge_patch(&mut doc, &json!(["replaced"]));
+        assert_eq!(doc, json!(["replaced"]));
+    }
+
+    #[test]
+    fn between_produces_a_patch_that_replays() {
+        let left = json!({"a/b": 1, "db": {"host": "a", "old": true}, "list": [1, 2, 3], "x": 1});
+        let right = json!({"a/b": 2, "db": {"host": "b", "new": null}, "list": [1, 5], "x": [1]});
+        let patch = between(&left, &right);
+        assert_eq!(
+            serde_json::to_value(&patch).unwrap(),
+            json!([
+                {"op": "replace", "path": "/a~1b", "value": 2},
+                {"op": "replace", "path": "/db/host", "value": "b"},
+                {"op": "remove", "path": "/db/old"},
+                {"op": "add", "path": "/db/new", "value": null},
+                {"op": "replace", "path": "/list/1", "value": 5},
+                {"op": "remove", "path": "/list/2"},
+                {"op": "replace", "path": "/x", "value": [1]}
+            ])
+        );
+        let mut doc = left.clone();
+        apply_json_patch(&mut doc, &patch).unwrap();
+        assert_eq!(doc, right);
+
+        let mut doc = json!([1]);
+        apply_json_patch(&mut doc, &between(&json!([1]), &json!([1, 2, 3]))).unwrap();
+        assert_eq!(doc, json!([1, 2, 3]));
+        assert!(between(&left, &left).is_empty());
+        assert_eq!(
+            serde_json::to_value(between(&json!(1), &json!("one"))).unwrap(),
+            json!([{"op": "replace", "path": "", "value": "one"}])
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Path syntax shared by queries, `--sort-by`, `--pluck`, `split --by` and
+//! every other flag that takes a path: one parser turns both notations into
+//! the same list of keys.
+//!
+//! - Dotted: `spec.containers.0.image`. A double-quoted segment keeps its
+//!   dots (`labels."app.kubernetes.io/name"`), and so does `\.`.
+//! - Brackets, as in JavaScript or jq: `spec.containers[0].image`,
+//!   `labels["app.kubernetes.io/name"]`, `labels['team']`. `[*]` is the
+//!   same wildcard as a `*` segment.
+//!
+//! Inside quotes, and anywhere with a backslash, `\"`, `\'` and `\\` stand
+//! for a quote and a backslash.
+
+use std::borrow::Cow;
+
+use anyhow::{bail, Result};
+
+/// The keys `path` names, in order.
+pub(crate) fn segments(path: &str) -> Result<Vec<String>> {
+    let mut parser = Parser {
+        path,
+        chars: path.chars().peekable(),
+    };
+    parser.segments()
+}
+
+struct Parser<'a> {
+    path: &'a str,
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn segments(&mut self) -> Result<Vec<String>> {
+        let mut segments = Vec::new();
+        // A bracket at the very start (`[0].name`) has no dotted key before it.
+        if self.chars.peek() != Some(&'[') {
+            segments.push(self.dotted()?);
+        }
+        while let Some(c) = self.chars.next() {
+            match c {
+                // `a.[0]`, as jq allows, is `a[0]`.
+                '.' if self.chars.peek() == Some(&'[') => {}
+                '.' => segments.push(self.dotted()?),
+                '[' => {
+                    segments.push(self.bracketed()?);
+                    match self.chars.peek() {
+                        None | Some('.' | '[') => {}
+                        Some(_) => self.fail("expected `.` or `[` after `]`")?,
+                    }
+                }
+                _ => unreachable!("dotted() stops only at `.`, `[` or the end"),
+            }
+        }
+        Ok(segments)
+    }
+
+    /// A dotted segment, up to the next unquoted `.` or `[`.
+    fn dotted(&mut self) -> Result<String> {
+        let mut segment = String::new();
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '.' | '[' => break,
+                '"' => {
+                    self.chars.next();
+                    segment.push_str(&self.quoted('"')?);
+                }
+                '\\' => {
+                    self.chars.next();
+                    segment.push_str(&self.escaped(&['.', '[', '"', '\\']));
+                }
+                _ => {
+                    self.chars.next();
+                    segment.push(c);
+                }
+            }
+        }
+        Ok(segment)
+    }
+
+    /// The inside of `[...]`, the `[` already read: an index, `*`, or a
+    /// quoted key.
+    fn bracketed(&mut self) -> Result<String> {
+        let segment = match self.chars.next() {
+            Some(quote @ ('"' | '\'')) => self.quoted(quote)?,
+            Some(c) if c == '*' || c.is_ascii_digit() => {
+                let mut segment = c.to_string();
+                while let Some(&d) = self.chars.peek().filter(|d| d.is_ascii_digit()) {
+                    self.chars.next();
+                    segment.push(d);
+                }
+                segment
+            }
+            _ => self.fail("expected an index, `*` or a quoted key inside `[...]`")?,
+        };
+        if self.chars.next() != Some(']') {
+            self.fail("unclosed `[`")?;
+        }
+        Ok(segment)
+    }
+
+    /// Text up to the closing `quote`, the opening one already read.
+    fn quoted(&mut self, quote: char) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some('\\') => text.push_str(&self.escaped(&['"', '\'', '\\'])),
+                Some(c) if c == quote => return Ok(text),
+                Some(c) => text.push(c),
+                None => self.fail(&format!("unclosed `{quote}`"))?,
+            }
+        }
+    }
+
+    /// What a backslash stands for: one of `special` as itself, anything
+    /// else kept with its backslash.
+    fn escaped(&mut self, special: &[char]) -> String {
+        match self.chars.peek() {
+            Some(c) if special.contains(c) => self.chars.next().into_iter().collect(),
+            _ => "\\".to_string(),
+        }
+    }
+
+    fn fail<T>(&self, problem: &str) -> Result<T> {
+        bail!("invalid path `{}`: {problem}", self.path)
+    }
+}
+
+/// `segment` as it has to be written in a dotted path: quoted when it holds
+/// a dot, a bracket, a quote or a backslash, so `segments` reads it back as
+/// one key.
+pub(crate) fn quote_segment(segment: &str) -> Cow<'_, str> {
+    if segment.contains(['.', '[', '"', '\\']) {
+        let escaped = segment.replace('\\', "\\\\").replace('"', "\\\"");
+        Cow::Owned(format!("\"{escaped}\""))
+    } else {
+        Cow::Borrowed(segment)
+    }
+}
+
+/// The inverse of `segments`, in dotted notation.
+pub(crate) fn join<S: AsRef<str>>(segments: &[S]) -> String {
+    let quoted: Vec<Cow<'_, str>> = segments.iter().map(|s| quote_segment(s.as_ref())).collect();
+    quoted.join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(path: &str) -> Vec<String> {
+        segments(path).unwrap()
+    }
+
+    #[test]
+    fn dotted_and_bracket_paths_agree() {
+        assert_eq!(parse("a.0.b"), ["a", "0", "b"]);
+        assert_eq!(parse("a[0].b"), ["a", "0", "b"]);
+        assert_eq!(parse("[0].login"), ["0", "login"]);
+        assert_eq!(parse("a[0][1]"), ["a", "0", "1"]);
+        assert_eq!(parse("services[*].image"), ["services", "*", "image"]);
+        assert_eq!(parse("a.[0].[1]"), ["a", "0", "1"]);
+        assert_eq!(
+            parse(r#"a["weird key"].b['team']"#),
+            ["a", "weird key", "b", "team"]
+        );
+        assert_eq!(
+            parse(r#"labels["app.kubernetes.io/name"]"#),
+            parse(r#"labels."app.kubernetes.io/name""#)
+        );
+        assert_eq!(
+            parse(r"labels.app\.kubernetes\.io/name"),
+            ["labels", "app.kubernetes.io/name"]
+        );
+        assert_eq!(parse(r#"a['say \'hi\'']"#), ["a", "say 'hi'"]);
+        assert_eq!(parse("a..b"), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn malformed_brackets_are_errors() {
+        let err = |path: &str| segments(path).unwrap_err().to_string();
+        assert_eq!(err("a[0"), "invalid path `a[0`: unclosed `[`");
+        assert_eq!(
+            err("a[key]"),
+            "invalid path `a[key]`: expected an index, `*` or a quoted key inside `[...]`"
+        );
+        assert_eq!(
+            err("a[0]b"),
+            "invalid path `a[0]b`: expected `.` or `[` after `]`"
+        );
+        assert_eq!(err(r#"a["b"#), r#"invalid path `a["b`: unclosed `"`"#);
+    }
+
+    #[test]
+    fn join_round_trips_awkward_keys() {
+        let keys = [
+            "spec",
+            "app.kubernetes.io/name",
+            "say \"hi\"",
+            r"C:\tmp",
+            "a[0]",
+            "",
+        ];
+        let path = join(&keys);
+        assert_eq!(
+            path,
+            r#"spec."app.kubernetes.io/name"."say \"hi\""."C:\\tmp"."a[0]"."#
+        );
+        assert_eq!(parse(&path), keys);
+    }
+}
@@ -0,0 +1,84 @@
+//! `--pluck FIELDS` (alias `--map`): project each item of the resolved
+//! array to the fields that matter, so `users.json --pluck id,email` prints
+//! a slim array — or, with `-o tsv`, one row per item, its columns in the
+//! order the fields were given.
+//!
+//! A single field maps each item to its value (`--map name` gives a list of
+//! names); several give each item an object with just those keys. Fields
+//! are dotted paths inside the item; a missing one is `null`.
+
+use anyhow::{bail, Result};
+use serde_json::{Map, Value};
+
+use crate::aggregate::type_name;
+use crate::{resolve_path, resolve_path_mut};
+
+/// Replace the array at `path` (the whole document when `None`) with its
+/// projection.
+pub(crate) fn apply(value: &mut Value, path: Option<&str>, fields: &[String]) -> Result<()> {
+    let target = match path {
+        Some(path) => resolve_path_mut(value, path)?,
+        None => value,
+    };
+    let Value::Array(items) = target else {
+        bail!("--pluck needs an array, got {}", type_name(target));
+    };
+    for item in items.iter_mut() {
+        *item = project(item, fields);
+    }
+    Ok(())
+}
+
+fn project(item: &Value, fields: &[String]) -> Value {
+    let field = |field: &str| resolve_path(item, field).cloned().unwrap_or(Value::Null);
+    match fields {
+        [single] => field(single),
+        _ => Value::Object(
+            fields
+                .iter()
+                .map(|name| (name.clone(), field(name)))
+                .collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emit::{self, EmitOptions, OutputFormat};
+    use serde_json::json;
+
+    #[test]
+    fn projects_items_to_fields() {
+        let users = json!({"users": [
+            {"id": 1, "email": "ann@example.com", "meta": {"team": "core"}},
+            {"id": 2, "name": "bob"}
+        ]});
+        let mut value = users.clone();
+        let fields = vec!["meta.team".to_string(), "id".to_string()];
+        apply(&mut value, Some("users"), &fields).unwrap();
+        assert_eq!(
+            value["users"],
+            json!([{"id": 1, "meta.team": "core"}, {"id": 2, "meta.team": null}])
+        );
+        // Tabular output keeps the order the fields were asked for.
+        let opts = EmitOptions {
+            columns: fields,
+            ..EmitOptions::default()
+        };
+        assert_eq!(
+            emit::render_with(&value["users"], OutputFormat::Tsv, &opts).unwrap(),
+            "meta.team\tid\ncore\t1\n\t2\n"
+        );
+        let mut value = users;
+        apply(&mut value, Some("users"), &["id".into()]).unwrap();
+        assert_eq!(value["users"], json!([1, 2]));
+    }
+
+    #[test]
+    fn needs_an_array() {
+        let mut value = json!({"users": "none"});
+        let err = apply(&mut value, Some("users"), &["id".into()]).unwrap_err();
+        assert_eq!(err.to_string(), "--pluck needs an array, got a string");
+    }
+}
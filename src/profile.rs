@@ -0,0 +1,209 @@
+//! `--profile NAME`: the layered lookup apps do at startup, so a query sees
+//! the configuration exactly as the app would. For `config.yaml` and
+//! `--profile prod` the layers are, by default:
+//!
+//! 1. `base`: `config.yaml` itself.
+//! 2. `profile`: `config.prod.yaml` next to it, deep-merged over the base.
+//! 3. `dotenv`: `.env` in the same directory, if there is one. Its variables
+//!    override existing keys by their `-o env` name, as `--env-prefix` does
+//!    for the process environment (`SERVER_PORT` → `server.port`).
+//!
+//! `--layers` picks the order (later layers win) or leaves layers out.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use crate::formats::ParseOptions;
+use crate::input::{self, InputSource};
+use crate::merge::deep_merge;
+use crate::{env_override, parse_bytes, Format};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum Layer {
+    /// The file given on the command line.
+    Base,
+    /// `<stem>.<profile>.<ext>` next to it.
+    Profile,
+    /// `.env` in the same directory; skipped when missing.
+    Dotenv,
+}
+
+pub(crate) const DEFAULT_LAYERS: [Layer; 3] = [Layer::Base, Layer::Profile, Layer::Dotenv];
+
+/// `file` under `profile`, stacking `layers` (the default order when empty).
+/// `.env` variables are looked up with `env_prefix`, as `--env-prefix`
+/// looks up the process environment.
+pub(crate) fn layered(
+    file: &str,
+    profile: &str,
+    layers: &[Layer],
+    env_prefix: Option<&str>,
+) -> Result<Box<dyn InputSource>> {
+    if file == "-" {
+        bail!("--profile needs a file to find the profile's layers next to, not stdin");
+    }
+    Ok(Box::new(Layered {
+        file: PathBuf::from(file),
+        profile: profile.to_string(),
+        layers: if layers.is_empty() {
+            DEFAULT_LAYERS.to_vec()
+        } else {
+            layers.to_vec()
+        },
+        env_prefix: env_prefix.unwrap_or_default().to_string(),
+    }))
+}
+
+struct Layered {
+    file: PathBuf,
+    profile: String,
+    layers: Vec<Layer>,
+    env_prefix: String,
+}
+
+impl InputSource for Layered {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        let document = self.load(None, &ParseOptions::default())?;
+        Ok((
+            self.file.display().to_string(),
+            serde_json::to_vec(&document)?,
+        ))
+    }
+
+    /// `format` applies to the base and profile files; `.env` is always
+    /// read as .env.
+    fn load(&self, format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        let mut value = Value::Object(Map::new());
+        for layer in &self.layers {
+            match layer {
+                Layer::Base => {
+                    let file = self.file.display().to_string();
+                    deep_merge(&mut value, input::open(&file).load(format, opts)?);
+                }
+                Layer::Profile => {
+                    let file = profile_file(&self.file, &self.profile)?;
+                    let file = file.display().to_string();
+                    deep_merge(&mut value, input::open(&file).load(format, opts)?);
+                }
+                Layer::Dotenv => {
+                    let path = self.file.with_file_name(".env");
+                    if path.is_file() {
+                        self.apply_dotenv(&mut value, &path, opts)?;
+                    }
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+impl Layered {
+    fn apply_dotenv(&self, value: &mut Value, path: &Path, opts: &ParseOptions) -> Result<()> {
+        let vars = dotenv_vars(path, opts)?;
+        env_override::apply(value, &self.env_prefix, &vars)
+            .with_context(|| path.display().to_string())
+    }
+}
+
+/// The variables of the .env file at `path`, as text.
+pub(crate) fn dotenv_vars(path: &Path, opts: &ParseOptions) -> Result<HashMap<String, String>> {
+    let display = path.display().to_string();
+    let bytes = fs::read(path).with_context(|| format!("Failed to read file: {display}"))?;
+    // Strings, so each override is typed like the value it replaces.
+    let opts = ParseOptions {
+        infer_types: false,
+        ..opts.clone()
+    };
+    Ok(
+        match parse_bytes(&display, &bytes, Some(Format::Env), &opts)? {
+            Value::Object(map) => map
+                .into_iter()
+                .map(|(key, value)| match value {
+                    Value::String(text) => (key, text),
+                    other => (key, other.to_string()),
+                })
+                .collect(),
+            _ => Default::default(),
+        },
+    )
+}
+
+/// The profile's file next to `file`, which has to exist.
+pub(crate) fn profile_file(file: &Path, profile: &str) -> Result<PathBuf> {
+    let path = profile_path(file, profile);
+    if !path.is_file() {
+        bail!(
+            "profile `{profile}` has no file: {} not found",
+            path.display()
+        );
+    }
+    Ok(path)
+}
+
+/// `config.yaml` → `config.prod.yaml`; a file without an extension gets the
+/// profile as one (`config` → `config.prod`).
+fn profile_path(file: &Path, profile: &str) -> PathBuf {
+    let stem = file
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match file.extension() {
+        Some(ext) => format!("{stem}.{profile}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{profile}"),
+    };
+    file.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn layers_stack_base_profile_and_dotenv() {
+        let load = |layers: &[Layer]| {
+            layered("testdata/profile/config.yaml", "prod", layers, None)
+                .unwrap()
+                .load(None, &ParseOptions::default())
+                .unwrap()
+        };
+        assert_eq!(
+            load(&[]),
+            json!({
+                "server": {"host": "0.0.0.0", "port": 9443},
+                "db": {"url": "postgres://prod-db/app", "pool": 20},
+                "debug": false
+            })
+        );
+        // Reversed: the base wins over the profile and .env finds no keys yet.
+        assert_eq!(
+            load(&[Layer::Dotenv, Layer::Profile, Layer::Base]),
+            json!({
+                "server": {"host": "0.0.0.0", "port": 8080},
+                "db": {"url": "postgres://localhost/app", "pool": 20},
+                "debug": true
+            })
+        );
+    }
+
+    #[test]
+    fn missing_profile_file_is_an_error() {
+        let err = layered("testdata/profile/config.yaml", "qa", &[], None)
+            .unwrap()
+            .load(None, &ParseOptions::default())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "profile `qa` has no file: testdata/profile/config.qa.yaml not found"
+        );
+        assert_eq!(
+            profile_path(Path::new("etc/app"), "qa"),
+            Path::new("etc/app.qa")
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! A small backtracking regular expression matcher for `find --regex` and
+//! `grep`, covering what a config search needs: literals, `.`, classes
+//! (`[a-z]`, `[^0-9]`, `\d`, `\w`, `\s` and their negations), anchors `^`
+//! and `$`, groups `(...)` / `(?:...)`, alternation `|` and the greedy
+//! quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`. A match may start
+//! anywhere in the text unless anchored. There are no captures,
+//! backreferences or lookaround.
+
+use anyhow::{bail, Result};
+
+#[derive(Debug)]
+pub(crate) struct Regex {
+    root: Node,
+    ignore_case: bool,
+}
+
+#[derive(Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+impl Regex {
+    pub(crate) fn new(pattern: &str, ignore_case: bool) -> Result<Self> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let root = parser
+            .alternation()
+            .map_err(|err| anyhow::anyhow!("invalid regex `{pattern}`: {err}"))?;
+        if parser.pos < parser.chars.len() {
+            bail!("invalid regex `{pattern}`: unmatched `)`");
+        }
+        Ok(Self { root, ignore_case })
+    }
+
+    /// Whether the pattern matches anywhere in `text`.
+    pub(crate) fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        (0..=text.len()).any(|start| self.matches(&self.root, &text, start, &mut |_| true))
+    }
+
+    /// Match `node` at `pos`, then hand the end position to `next`;
+    /// backtracks until `next` accepts one.
+    fn matches(
+        &self,
+        node: &Node,
+        text: &[char],
+        pos: usize,
+        next: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        match node {
+            Node::Start => pos == 0 && next(pos),
+            Node::End => pos == text.len() && next(pos),
+            Node::Char(_) | Node::Any | Node::Class { .. } => {
+                text.get(pos).is_some_and(|&c| self.single(node, c)) && next(pos + 1)
+            }
+            Node::Concat(nodes) => self.sequence(nodes, text, pos, next),
+            Node::Alt(options) => options
+                .iter()
+                .any(|option| self.matches(option, text, pos, next)),
+            Node::Repeat { node, min, max } => self.repeat(node, *min, *max, 0, text, pos, next),
+        }
+    }
+
+    fn sequence(
+        &self,
+        nodes: &[Node],
+        text: &[char],
+        pos: usize,
+        next: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => next(pos),
+            Some((first, rest)) => self.matches(first, text, pos, &mut |after| {
+                self.sequence(rest, text, after, next)
+            }),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn repeat(
+        &self,
+        node: &Node,
+        min: usize,
+        max: Option<usize>,
+        count: usize,
+        text: &[char],
+        pos: usize,
+        next: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        // Greedy: one more repetition first. Past `min`, one that consumes
+        // nothing would loop forever, so it does not count.
+        if max.is_none_or(|max| count < max)
+            && self.matches(node, text, pos, &mut |after| {
+                (after > pos || count < min)
+                    && self.repeat(node, min, max, count + 1, text, after, next)
+            })
+        {
+            return true;
+        }
+        count >= min && next(pos)
+    }
+
+    fn single(&self, node: &Node, c: char) -> bool {
+        let fold = |c: char| {
+            if self.ignore_case {
+                c.to_lowercase().next().unwrap_or(c)
+            } else {
+                c
+            }
+        };
+        match node {
+            Node::Any => c != '\n',
+            Node::Char(expected) => fold(*expected) == fold(c),
+            Node::Class { ranges, negated } => {
+                let within = |c: char| ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c));
+                let hit = within(c)
+                    || (self.ignore_case
+                        && (within(c.to_ascii_lowercase()) || within(c.to_ascii_uppercase())));
+                hit != *negated
+            }
+            _ => false,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let hit = self.peek() == Some(c);
+        if hit {
+            self.pos += 1;
+        }
+        hit
+    }
+
+    fn alternation(&mut self) -> Result<Node> {
+        let mut options = vec![self.concat()?];
+        while self.eat('|') {
+            options.push(self.concat()?);
+        }
+        Ok(if options.len() == 1 {
+            options.remove(0)
+        } else {
+            Node::Alt(options)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantified(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn atom(&mut self) -> Result<Node> {
+        let at = self.pos;
+        let Some(c) = self.peek() else {
+            bail!("unexpected end");
+        };
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.eat('?') && !self.eat(':') {
+                    bail!("only `(?:` groups are supported (at {at})");
+                }
+                let inner = self.alternation()?;
+                if !self.eat(')') {
+                    bail!("unclosed `(` at {at}");
+                }
+                inner
+            }
+            '[' => self.class(at)?,
+            '\\' => self.escape()?,
+            '*' | '+' | '?' | '{' => bail!("`{c}` at {at} has nothing to repeat"),
+            literal => Node::Char(literal),
+        })
+    }
+
+    fn escape(&mut self) -> Result<Node> {
+        let Some(c) = self.peek() else {
+            bail!("trailing `\\`");
+        };
+        self.pos += 1;
+        let class = |ranges: &[(char, char)], negated| Node::Class {
+            ranges: ranges.to_vec(),
+            negated,
+        };
+        Ok(match c {
+            'd' => class(DIGIT, false),
+            'D' => class(DIGIT, true),
+            'w' => class(WORD, false),
+            'W' => class(WORD, true),
+            's' => class(SPACE, false),
+            'S' => class(SPACE, true),
+            'n' => Node::Char('\n'),
+            't' => Node::Char('\t'),
+            'r' => Node::Char('\r'),
+            c if c.is_ascii_alphanumeric() => bail!("unsupported escape `\\{c}`"),
+            c => Node::Char(c),
+        })
+    }
+
+    fn class(&mut self, at: usize) -> Result<Node> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                bail!("unclosed `[` at {at}");
+            };
+            self.pos += 1;
+            let lo = match c {
+                ']' if !first => break,
+                '\\' => match self.escape()? {
+                    Node::Char(c) => c,
+                    Node::Class {
+                        ranges: inner,
+                        negated: false,
+                    } => {
+                        ranges.extend(inner);
+                        first = false;
+                        continue;
+                    }
+                    _ => bail!("negated class escape inside `[...]` at {at}"),
+                },
+                c => c,
+            };
+            first = false;
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let hi = match self.peek() {
+                    Some('\\') => {
+                        self.pos += 1;
+                        match self.escape()? {
+                            Node::Char(c) => c,
+                            _ => bail!("class escape as a range end at {at}"),
+                        }
+                    }
+                    Some(c) => {
+                        self.pos += 1;
+                        c
+                    }
+                    None => bail!("unclosed `[` at {at}"),
+                };
+                if hi < lo {
+                    bail!("range `{lo}-{hi}` out of order at {at}");
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn quantified(&mut self, atom: Node) -> Result<Node> {
+        let at = self.pos;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.bounds() {
+                Some(bounds) => bounds,
+                None => bail!("malformed `{{n,m}}` at {at}"),
+            },
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if matches!(atom, Node::Start | Node::End) {
+            bail!("anchor at {at} cannot be repeated");
+        }
+        if matches!(self.peek(), Some('*' | '+' | '?' | '{')) {
+            bail!(
+                "lazy and nested quantifiers are not supported (at {})",
+                self.pos
+            );
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+        })
+    }
+
+    /// `{n}`, `{n,}` or `{n,m}`, leaving `pos` on the closing `}`.
+    fn bounds(&mut self) -> Option<(usize, Option<usize>)> {
+        let close = self.chars[self.pos..].iter().position(|&c| c == '}')? + self.pos;
+        let body: String = self.chars[self.pos + 1..close].iter().collect();
+        let (min, max) = match body.split_once(',') {
+            None => {
+                let n = body.parse().ok()?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.parse().ok()?, None),
+            Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+        };
+        if max.is_some_and(|max| max < min) {
+            return None;
+        }
+        self.pos = close;
+        Some((min, max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern, false).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn matching() {
+        assert!(is_match("pass", "db_password"));
+        assert!(!is_match("^pass", "db_password"));
+        assert!(is_match("^(db|cache)_pass(word)?$", "cache_pass"));
+        assert!(!is_match("^(db|cache)_pass(word)?$", "cache_passwd"));
+        assert!(is_match(
+            r"^redis://[\w.-]+:\d{2,5}$",
+            "redis://cache.local:6379"
+        ));
+        assert!(!is_match(
+            r"^redis://[\w.-]+:\d{2,5}$",
+            "redis://cache.local:6"
+        ));
+        assert!(is_match("a.*b+c?$", "xxaaaabbb"));
+        assert!(is_match("[^a-z]", "abc1"));
+        assert!(!is_match("[^a-z]", "abc"));
+        assert!(is_match(r"\.yaml$", "app.yaml"));
+        assert!(!is_match(r"\.yaml$", "app-yaml"));
+        assert!(is_match("(a*)*b", "aaab"));
+        assert!(!is_match("(a*)*b", "aaaa"));
+        assert!(is_match("^(a?){2}$", ""));
+        assert!(Regex::new("TOKEN", true).unwrap().is_match("api_token"));
+        assert!(Regex::new("[A-Z]+", true).unwrap().is_match("abc"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let err = |pattern: &str| Regex::new(pattern, false).unwrap_err().to_string();
+        assert_eq!(err("(ab"), "invalid regex `(ab`: unclosed `(` at 0");
+        assert_eq!(err("ab)"), "invalid regex `ab)`: unmatched `)`");
+        assert_eq!(
+            err("*a"),
+            "invalid regex `*a`: `*` at 0 has nothing to repeat"
+        );
+        assert_eq!(
+            err("[z-a]"),
+            "invalid regex `[z-a]`: range `z-a` out of order at 0"
+        );
+        assert_eq!(
+            err("a+?"),
+            "invalid regex `a+?`: lazy and nested quantifiers are not supported (at 2)"
+        );
+    }
+}
@@ -0,0 +1,623 @@
+//! `confctl render` — fill a template from config values, e.g.
+//! `confctl render nginx.conf.tmpl --values site.yaml > nginx.conf`.
+//!
+//! The syntax is the everyday subset of Handlebars, hand-rolled like the
+//! other parsers in this crate:
+//!
+//! - `{{path}}` / `{{{path}}}` — the value at a dotted path (no HTML escaping;
+//!   the output is a config file, not a web page). Objects and arrays are
+//!   written as compact JSON.
+//! - `{{#if p}}…{{else}}…{{/if}}`, `{{#unless p}}`, `{{#with p}}`,
+//!   `{{#each p}}…{{else}}…{{/each}}` over arrays and objects.
+//! - `this`, `../`, `@root`, and `@index` / `@key` / `@first` / `@last`
+//!   inside `each`.
+//! - `{{! comment }}`, `{{~ … ~}}` whitespace control, and `\{{` for a
+//!   literal `{{`. Block tags alone on a line leave no blank line behind.
+//!
+//! Unlike Handlebars, a path that does not exist is an error rather than an
+//! empty string, so a typo can't produce a half-filled config. `#if` and
+//! `#unless` treat a missing path as false.
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use crate::atomic::{self, BackupArg};
+use crate::formats::ParseOptions;
+use crate::merge::deep_merge;
+use crate::{parse_file, resolve_path, Format};
+
+#[derive(Args, Debug)]
+#[command(mut_arg("backup", |arg| arg.requires("out")))]
+pub(crate) struct RenderCli {
+    /// Template file, or `-` for stdin.
+    pub(crate) template: String,
+
+    /// Config file whose contents are the template context. Repeat to
+    /// deep-merge several, later files winning.
+    #[arg(long, value_name = "FILE", required = true)]
+    pub(crate) values: Vec<String>,
+
+    /// Input format override for the values files, as in `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Write the result to this file instead of stdout.
+    #[arg(long, value_name = "PATH")]
+    pub(crate) out: Option<PathBuf>,
+
+    /// Overwrite `--out` if it already exists; --backup implies it.
+    #[arg(long, requires = "out")]
+    pub(crate) force: bool,
+
+    #[command(flatten)]
+    pub(crate) backup: BackupArg,
+}
+
+impl RenderCli {
+    pub(crate) fn mutation(&self) -> Option<&'static str> {
+        self.out.as_ref().map(|_| "render --out")
+    }
+}
+
+pub(crate) fn run(cli: RenderCli) -> Result<()> {
+    if cli.template == "-" && cli.values.iter().any(|v| v == "-") {
+        bail!("the template and --values cannot both come from stdin");
+    }
+    let mut context = Value::Object(Map::new());
+    for values in &cli.values {
+        deep_merge(
+            &mut context,
+            parse_file(values, cli.format, &ParseOptions::default())?,
+        );
+    }
+    let source = if cli.template == "-" {
+        std::io::read_to_string(std::io::stdin()).context("Failed to read from stdin")?
+    } else {
+        fs::read_to_string(&cli.template)
+            .with_context(|| format!("Failed to read file: {}", cli.template))?
+    };
+    let body = render(&source, &context).with_context(|| format!("rendering {}", cli.template))?;
+
+    let Some(path) = cli.out else {
+        print!("{body}");
+        return Ok(());
+    };
+    if path.exists() && !cli.force && cli.backup.suffix().is_none() {
+        bail!(
+            "{} already exists; pass --force to overwrite",
+            path.display()
+        );
+    }
+    atomic::write(&path, body, cli.backup.suffix())?;
+    if !atomic::previewing() {
+        println!("{} wrote {}", "✓".green().bold(), path.display());
+    }
+    Ok(())
+}
+
+/// Render `template` with `context` as the root value.
+pub(crate) fn render(template: &str, context: &Value) -> Result<String> {
+    let mut tokens = tokenize(template)?;
+    trim_whitespace(&mut tokens);
+    let mut tokens = tokens.into_iter();
+    let (nodes, _) = parse(&mut tokens, None)?;
+    let mut out = String::new();
+    let mut scope = vec![Frame {
+        value: context,
+        each: None,
+    }];
+    write_nodes(&nodes, &mut scope, &mut out)?;
+    Ok(out)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BlockKind {
+    If,
+    Unless,
+    Each,
+    With,
+}
+
+impl BlockKind {
+    fn name(self) -> &'static str {
+        match self {
+            BlockKind::If => "if",
+            BlockKind::Unless => "unless",
+            BlockKind::Each => "each",
+            BlockKind::With => "with",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Tag {
+    Value(String),
+    Open(BlockKind, String),
+    Else,
+    Close(String),
+    Comment,
+}
+
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    Tag {
+        tag: Tag,
+        line: usize,
+        strip_before: bool,
+        strip_after: bool,
+    },
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+    let mut line = 1;
+    while let Some(at) = rest.find("{{") {
+        if rest[..at].ends_with('\\') {
+            text.push_str(&rest[..at - 1]);
+            text.push_str("{{");
+            line += rest[..at].matches('\n').count();
+            rest = &rest[at + 2..];
+            continue;
+        }
+        text.push_str(&rest[..at]);
+        line += rest[..at].matches('\n').count();
+        rest = &rest[at..];
+
+        let (inner, len) = if let Some(body) = rest.strip_prefix("{{{") {
+            let end = body
+                .find("}}}")
+                .ok_or_else(|| anyhow!("line {line}: `{{{{{{` is never closed"))?;
+            (&body[..end], end + 6)
+        } else if rest[2..].trim_start_matches('~').starts_with("!--") {
+            let (end, close) = [("--}}", 4), ("--~}}", 5)]
+                .into_iter()
+                .filter_map(|(marker, len)| Some((rest.find(marker)?, len)))
+                .min()
+                .ok_or_else(|| anyhow!("line {line}: comment is never closed"))?;
+            (&rest[2..end + close - 2], end + close)
+        } else {
+            let end = rest[2..]
+                .find("}}")
+                .ok_or_else(|| anyhow!("line {line}: `{{{{` is never closed"))?;
+            (&rest[2..end + 2], end + 4)
+        };
+        let strip_before = inner.starts_with('~');
+        let strip_after = inner.ends_with('~') && inner.len() > usize::from(strip_before);
+        let inner = inner.strip_prefix('~').unwrap_or(inner);
+        let inner = inner.strip_suffix('~').unwrap_or(inner).trim();
+        let tag = parse_tag(inner).map_err(|e| anyhow!("line {line}: {e}"))?;
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(std::mem::take(&mut text)));
+        }
+        tokens.push(Token::Tag {
+            tag,
+            line,
+            strip_before,
+            strip_after,
+        });
+        line += rest[..len].matches('\n').count();
+        rest = &rest[len..];
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    Ok(tokens)
+}
+
+fn parse_tag(inner: &str) -> Result<Tag> {
+    if inner.starts_with('!') {
+        return Ok(Tag::Comment);
+    }
+    if inner == "else" {
+        return Ok(Tag::Else);
+    }
+    if let Some(name) = inner.strip_prefix('/') {
+        return Ok(Tag::Close(name.trim().to_string()));
+    }
+    if let Some(block) = inner.strip_prefix('#') {
+        let (name, arg) = block.split_once(char::is_whitespace).unwrap_or((block, ""));
+        let kind = match name {
+            "if" => BlockKind::If,
+            "unless" => BlockKind::Unless,
+            "each" => BlockKind::Each,
+            "with" => BlockKind::With,
+            other => bail!("unknown block helper `#{other}` (expected if, unless, each or with)"),
+        };
+        let arg = arg.trim();
+        if arg.is_empty() || arg.contains(char::is_whitespace) {
+            bail!("`#{name}` takes exactly one path");
+        }
+        return Ok(Tag::Open(kind, arg.to_string()));
+    }
+    if inner.is_empty() || inner.contains(char::is_whitespace) {
+        bail!("expected a path in `{{{{{inner}}}}}`");
+    }
+    Ok(Tag::Value(inner.to_string()))
+}
+
+/// Apply `~` stripping, then drop the surrounding whitespace and newline of
+/// block, `else` and comment tags that sit alone on their line.
+fn trim_whitespace(tokens: &mut [Token]) {
+    // Decided up front: two block tags on consecutive lines share the text
+    // between them, and trimming for the first must not hide the second.
+    let standalone: Vec<bool> = (0..tokens.len())
+        .map(|i| {
+            matches!(&tokens[i], Token::Tag { tag, .. } if !matches!(tag, Tag::Value(_)))
+                && (i == 0
+                    || matches!(&tokens[i - 1], Token::Text(t) if line_tail_blank(t, i == 1)))
+                && (i + 1 == tokens.len()
+                    || matches!(&tokens[i + 1], Token::Text(t) if line_head_blank(t)))
+        })
+        .collect();
+
+    for i in 0..tokens.len() {
+        let Token::Tag {
+            strip_before,
+            strip_after,
+            ..
+        } = tokens[i]
+        else {
+            continue;
+        };
+        if i > 0 {
+            if let Token::Text(before) = &mut tokens[i - 1] {
+                if strip_before {
+                    before.truncate(before.trim_end().len());
+                } else if standalone[i] {
+                    let keep = before.rfind('\n').map_or(0, |n| n + 1);
+                    before.truncate(keep);
+                }
+            }
+        }
+        if let Some(Token::Text(after)) = tokens.get_mut(i + 1) {
+            if strip_after {
+                *after = after.trim_start().to_string();
+            } else if standalone[i] {
+                let cut = after.find('\n').map_or(after.len(), |n| n + 1);
+                after.replace_range(..cut, "");
+            }
+        }
+    }
+}
+
+/// Only spaces/tabs after the last newline (and a newline exists, unless
+/// this text starts the template).
+fn line_tail_blank(text: &str, at_start: bool) -> bool {
+    match text.rfind('\n') {
+        Some(n) => text[n + 1..].chars().all(|c| c == ' ' || c == '\t'),
+        None => at_start && text.chars().all(|c| c == ' ' || c == '\t'),
+    }
+}
+
+fn line_head_blank(text: &str) -> bool {
+    let head = text.split('\n').next().unwrap_or("");
+    head.trim_end_matches('\r')
+        .chars()
+        .all(|c| c == ' ' || c == '\t')
+        && (text.contains('\n') || head.trim().is_empty())
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Value {
+        path: String,
+        line: usize,
+    },
+    Block {
+        kind: BlockKind,
+        path: String,
+        line: usize,
+        body: Vec<Node>,
+        inverse: Vec<Node>,
+    },
+}
+
+/// Nodes up to the close of `open` (kind and line), or to the end of input
+/// at the top level. The second list is what follows `{{else}}`.
+fn parse(
+    tokens: &mut impl Iterator<Item = Token>,
+    open: Option<(BlockKind, usize)>,
+) -> Result<(Vec<Node>, Vec<Node>)> {
+    let mut body = Vec::new();
+    let mut inverse: Option<Vec<Node>> = None;
+    while let Some(token) = tokens.next() {
+        let nodes = inverse.as_mut().unwrap_or(&mut body);
+        let (tag, line) = match token {
+            Token::Text(text) if text.is_empty() => continue,
+            Token::Text(text) => {
+                nodes.push(Node::Text(text));
+                continue;
+            }
+            Token::Tag { tag, line, .. } => (tag, line),
+        };
+        match tag {
+            Tag::Comment => {}
+            Tag::Value(path) => nodes.push(Node::Value { path, line }),
+            Tag::Open(kind, path) => {
+                let (body, inverse) = parse(tokens, Some((kind, line)))?;
+                nodes.push(Node::Block {
+                    kind,
+                    path,
+                    line,
+                    body,
+                    inverse,
+                });
+            }
+            Tag::Else => match open {
+                None => bail!("line {line}: `{{{{else}}}}` outside a block"),
+                Some(_) if inverse.is_some() => {
+                    bail!("line {line}: second `{{{{else}}}}` in the same block")
+                }
+                Some(_) => inverse = Some(Vec::new()),
+            },
+            Tag::Close(name) => match open {
+                Some((kind, _)) if kind.name() == name => {
+                    return Ok((body, inverse.unwrap_or_default()))
+                }
+                Some((kind, opened)) => bail!(
+                    "line {line}: `{{{{/{name}}}}}` closes `{{{{#{}}}}}` from line {opened}",
+                    kind.name()
+                ),
+                None => bail!("line {line}: `{{{{/{name}}}}}` has no matching block"),
+            },
+        }
+    }
+    match open {
+        Some((kind, line)) => bail!("line {line}: `{{{{#{}}}}}` is never closed", kind.name()),
+        None => Ok((body, Vec::new())),
+    }
+}
+
+struct Frame<'v> {
+    value: &'v Value,
+    /// (`@index`, `@key`, item count) when pushed by `#each`.
+    each: Option<(usize, Option<&'v str>, usize)>,
+}
+
+fn write_nodes<'v>(nodes: &[Node], scope: &mut Vec<Frame<'v>>, out: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Value { path, line } => {
+                let value = lookup(scope, path).map_err(|e| anyhow!("line {line}: {e}"))?;
+                out.push_str(&text_of(&value));
+            }
+            Node::Block {
+                kind,
+                path,
+                line,
+                body,
+                inverse,
+            } => write_block(*kind, path, body, inverse, scope, out)
+                .map_err(|e| anyhow!("line {line}: {e}"))?,
+        }
+    }
+    Ok(())
+}
+
+fn write_block<'v>(
+    kind: BlockKind,
+    path: &str,
+    body: &[Node],
+    inverse: &[Node],
+    scope: &mut Vec<Frame<'v>>,
+    out: &mut String,
+) -> Result<()> {
+    let found = lookup(scope, path);
+    if matches!(kind, BlockKind::If | BlockKind::Unless) {
+        let truthy = found.as_deref().is_ok_and(is_truthy);
+        let branch = if truthy == (kind == BlockKind::If) {
+            body
+        } else {
+            inverse
+        };
+        return write_nodes(branch, scope, out);
+    }
+
+    let value = match found? {
+        Cow::Borrowed(value) => value,
+        Cow::Owned(_) => bail!(
+            "`#{}` needs a path into the values, got `{path}`",
+            kind.name()
+        ),
+    };
+    if !is_truthy(value) {
+        return write_nodes(inverse, scope, out);
+    }
+    let items: Vec<(Option<&str>, &Value)> = match (kind, value) {
+        (BlockKind::With, _) => {
+            scope.push(Frame { value, each: None });
+            let result = write_nodes(body, scope, out);
+            scope.pop();
+            return result;
+        }
+        (_, Value::Array(items)) => items.iter().map(|item| (None, item)).collect(),
+        (_, Value::Object(map)) => map.iter().map(|(k, v)| (Some(k.as_str()), v)).collect(),
+        (_, other) => bail!(
+            "`#each {path}` needs an array or object, got {}",
+            type_name(other)
+        ),
+    };
+    let count = items.len();
+    for (index, (key, item)) in items.into_iter().enumerate() {
+        scope.push(Frame {
+            value: item,
+            each: Some((index, key, count)),
+        });
+        let result = write_nodes(body, scope, out);
+        scope.pop();
+        result?;
+    }
+    Ok(())
+}
+
+/// Resolve a template path against the scope: `@` data variables, `@root`,
+/// `../` parents, `this`, then an ordinary dotted path.
+fn lookup<'v>(scope: &[Frame<'v>], path: &str) -> Result<Cow<'v, Value>> {
+    if let Some(var) = path.strip_prefix('@').filter(|v| !v.starts_with("root")) {
+        let Some((index, key, count)) = scope.iter().rev().find_map(|f| f.each) else {
+            bail!("`{path}` is only defined inside `#each`");
+        };
+        let value = match var {
+            "index" => Value::from(index),
+            "first" => Value::Bool(index == 0),
+            "last" => Value::Bool(index + 1 == count),
+            "key" => match key {
+                Some(key) => Value::from(key),
+                None => bail!("`@key` is only defined when `#each` walks an object"),
+            },
+            _ => bail!("unknown data variable `{path}`"),
+        };
+        return Ok(Cow::Owned(value));
+    }
+
+    let (frame, rest) = if let Some(rest) = path.strip_prefix("@root") {
+        (&scope[0], rest.strip_prefix('.').unwrap_or(rest))
+    } else {
+        let mut rest = path;
+        let mut depth = 0;
+        while let Some(parent) = rest.strip_prefix("../") {
+            rest = parent;
+            depth += 1;
+        }
+        if depth >= scope.len() {
+            bail!("`{path}` climbs above the root");
+        }
+        let rest = match rest {
+            "this" | "." => "",
+            _ => rest
+                .strip_prefix("this.")
+                .or_else(|| rest.strip_prefix("./"))
+                .unwrap_or(rest),
+        };
+        (&scope[scope.len() - 1 - depth], rest)
+    };
+    if rest.is_empty() {
+        return Ok(Cow::Borrowed(frame.value));
+    }
+    resolve_path(frame.value, rest).map(Cow::Borrowed)
+}
+
+/// Handlebars truthiness: `false`, `null`, `0`, `""` and `[]` are false;
+/// every object (even `{}`) is true.
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+fn text_of(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values() -> Value {
+        json!({
+            "name": "api",
+            "port": 8080,
+            "tls": null,
+            "hosts": [{"ip": "10.0.0.1"}, {"ip": "10.0.0.2"}],
+            "env": {"LOG": "info", "MODE": "prod"}
+        })
+    }
+
+    #[test]
+    fn blocks_on_their_own_lines_leave_no_blank_lines() {
+        let template = "[Service]\n\
+            {{#each env}}\n\
+            Environment={{@key}}={{this}}\n\
+            {{/each}}\n\
+            {{#if tls}}\n\
+            TLS=on\n\
+            {{else}}\n\
+            TLS=off\n\
+            {{/if}}\n\
+            {{#each hosts}}\n\
+            # {{@index}} {{ip}}{{#if @last}} (last){{/if}}\n\
+            {{/each}}\n";
+        assert_eq!(
+            render(template, &values()).unwrap(),
+            "[Service]\nEnvironment=LOG=info\nEnvironment=MODE=prod\nTLS=off\n\
+             # 0 10.0.0.1\n# 1 10.0.0.2 (last)\n"
+        );
+    }
+
+    #[test]
+    fn paths_whitespace_control_and_escapes() {
+        let template = "{{! note }}{{#with hosts.0}}{{ip}}:{{../port}}/{{@root.name}}{{/with}} \
+            [ {{~#each hosts~}} {{this.ip}} {{~/each~}} ] \\{{name}} {{{port}}} {{hosts}}";
+        assert_eq!(
+            render(template, &values()).unwrap(),
+            "10.0.0.1:8080/api [10.0.0.110.0.0.2] {{name}} 8080 \
+             [{\"ip\":\"10.0.0.1\"},{\"ip\":\"10.0.0.2\"}]"
+        );
+        let empty = render(
+            "{{#each missing.list}}x{{/each}}",
+            &json!({"missing": {"list": []}}),
+        );
+        assert_eq!(empty.unwrap(), "");
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let err = |template: &str| render(template, &values()).unwrap_err().to_string();
+        assert_eq!(
+            err("a\n{{prot}}"),
+            "line 2: Key not found: 'prot' (at path 'prot')"
+        );
+        assert_eq!(
+            err("{{#each hosts}}\n"),
+            "line 1: `{{#each}}` is never closed"
+        );
+        assert_eq!(
+            err("{{#if port}}\n{{/each}}"),
+            "line 2: `{{/each}}` closes `{{#if}}` from line 1"
+        );
+        assert_eq!(err("{{else}}"), "line 1: `{{else}}` outside a block");
+        assert_eq!(
+            err("{{#each port}}{{/each}}"),
+            "line 1: `#each port` needs an array or object, got a number"
+        );
+        assert!(err("{{#lookup env}}{{/lookup}}").starts_with("line 1: unknown block helper"));
+        assert_eq!(
+            err("{{@index}}"),
+            "line 1: `@index` is only defined inside `#each`"
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! The line editor behind `repl`: cursor movement, history and tab
+//! completion on a terminal switched out of canonical mode with `stty`, so
+//! no terminal library is needed. Emacs-style keys: Ctrl-A/E to the start
+//! and end, Ctrl-U and Ctrl-W to delete back to the start or over a word,
+//! Ctrl-C to drop the line and Ctrl-D on an empty line to quit.
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+/// What one `Editor::read` produced.
+pub(crate) enum Input {
+    Line(String),
+    /// Ctrl-C: the line was dropped.
+    Interrupted,
+    /// Ctrl-D on an empty line, or the end of input.
+    Eof,
+}
+
+/// Candidates for the word that starts at char `start` of the text before
+/// the cursor; each replaces that word.
+pub(crate) struct Completion {
+    pub(crate) start: usize,
+    pub(crate) candidates: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    Tab,
+    Interrupt,
+    Eof,
+    KillToStart,
+    KillWord,
+    Other,
+}
+
+/// The text being edited and the cursor, as a char index.
+#[derive(Debug, Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    /// Replace chars `start..cursor` with `text`, leaving the cursor after it.
+    fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        let inserted: Vec<char> = text.chars().collect();
+        let count = inserted.len();
+        self.chars.splice(start..self.cursor, inserted);
+        self.cursor = start + count;
+    }
+
+    /// Apply an editing key; false for keys that are not edits.
+    fn edit(&mut self, key: &Key) -> bool {
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, *c);
+                self.cursor += 1;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.chars.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            Key::KillToStart => self.replace_before_cursor(0, ""),
+            Key::KillWord => {
+                let mut start = self.cursor;
+                while start > 0 && self.chars[start - 1] == ' ' {
+                    start -= 1;
+                }
+                while start > 0 && self.chars[start - 1] != ' ' {
+                    start -= 1;
+                }
+                self.replace_before_cursor(start, "");
+            }
+            Key::Backspace | Key::Delete => {}
+            _ => return false,
+        }
+        true
+    }
+}
+
+pub(crate) struct Editor {
+    pub(crate) history: Vec<String>,
+}
+
+impl Editor {
+    pub(crate) fn new(history: Vec<String>) -> Self {
+        Self { history }
+    }
+
+    /// Remember `line`, unless it repeats the last one.
+    pub(crate) fn add_history(&mut self, line: &str) {
+        if self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+        }
+    }
+
+    /// Read one line from the terminal after printing `prompt`.
+    pub(crate) fn read(
+        &mut self,
+        prompt: &str,
+        complete: &dyn Fn(&str) -> Completion,
+    ) -> Result<Input> {
+        let _raw = RawMode::enter()?;
+        let mut stdin = io::stdin().lock();
+        let mut out = io::stdout().lock();
+        let mut line = Line::default();
+        // Where Up/Down are in the history; the line being typed is kept
+        // while browsing it.
+        let mut browsing = self.history.len();
+        let mut draft = String::new();
+        redraw(&mut out, prompt, &line)?;
+        loop {
+            let key = read_key(&mut stdin)?;
+            match key {
+                Key::Enter => {
+                    write!(out, "\r\n")?;
+                    return Ok(Input::Line(line.text()));
+                }
+                Key::Interrupt => {
+                    write!(out, "^C\r\n")?;
+                    return Ok(Input::Interrupted);
+                }
+                Key::Eof if line.chars.is_empty() => {
+                    write!(out, "\r\n")?;
+                    return Ok(Input::Eof);
+                }
+                Key::Eof => {
+                    line.edit(&Key::Delete);
+                }
+                Key::Up if browsing > 0 => {
+                    if browsing == self.history.len() {
+                        draft = line.text();
+                    }
+                    browsing -= 1;
+                    line.set(&self.history[browsing]);
+                }
+                Key::Down if browsing < self.history.len() => {
+                    browsing += 1;
+                    match self.history.get(browsing) {
+                        Some(entry) => line.set(entry),
+                        None => line.set(&draft),
+                    }
+                }
+                Key::Tab => {
+                    let before: String = line.chars[..line.cursor].iter().collect();
+                    let Completion { start, candidates } = complete(&before);
+                    let typed = line.cursor - start;
+                    match candidates.as_slice() {
+                        [] => write!(out, "\x07")?,
+                        [only] => line.replace_before_cursor(start, only),
+                        _ => {
+                            let common = common_prefix(&candidates);
+                            if common.chars().count() > typed {
+                                line.replace_before_cursor(start, &common);
+                            } else {
+                                write!(out, "\r\n{}\r\n", candidates.join("  "))?;
+                            }
+                        }
+                    }
+                }
+                key => {
+                    line.edit(&key);
+                }
+            }
+            redraw(&mut out, prompt, &line)?;
+        }
+    }
+}
+
+/// The longest prefix every candidate shares.
+fn common_prefix(candidates: &[String]) -> String {
+    let Some((first, rest)) = candidates.split_first() else {
+        return String::new();
+    };
+    let mut common: &str = first;
+    for candidate in rest {
+        let shared = common
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(common.len().min(candidate.len()), |((at, _), _)| at);
+        common = &common[..shared];
+    }
+    common.to_string()
+}
+
+fn redraw(out: &mut impl Write, prompt: &str, line: &Line) -> io::Result<()> {
+    write!(out, "\r{prompt}{}\x1b[K", line.text())?;
+    let back = line.chars.len() - line.cursor;
+    if back > 0 {
+        write!(out, "\x1b[{back}D")?;
+    }
+    out.flush()
+}
+
+/// One key press from the terminal's bytes: UTF-8 characters, control
+/// keys and the `ESC [` / `ESC O` sequences of the arrow and editing keys.
+fn read_key(input: &mut impl Read) -> io::Result<Key> {
+    let Some(byte) = next_byte(input)? else {
+        return Ok(Key::Eof);
+    };
+    Ok(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x03 => Key::Interrupt,
+        0x04 => Key::Eof,
+        0x05 => Key::End,
+        0x15 => Key::KillToStart,
+        0x17 => Key::KillWord,
+        0x1b => escape(input)?,
+        byte if byte < 0x20 => Key::Other,
+        byte => {
+            let width = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..width {
+                bytes.extend(next_byte(input)?);
+            }
+            match std::str::from_utf8(&bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Other,
+            }
+        }
+    })
+}
+
+fn escape(input: &mut impl Read) -> io::Result<Key> {
+    match next_byte(input)? {
+        Some(b'[' | b'O') => {}
+        _ => return Ok(Key::Other),
+    }
+    let mut params = Vec::new();
+    let last = loop {
+        match next_byte(input)? {
+            Some(byte @ 0x40..=0x7e) => break byte,
+            Some(byte) => params.push(byte),
+            None => return Ok(Key::Other),
+        }
+    };
+    Ok(match (last, params.as_slice()) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) | (b'~', b"1" | b"7") => Key::Home,
+        (b'F', _) | (b'~', b"4" | b"8") => Key::End,
+        (b'~', b"3") => Key::Delete,
+        _ => Key::Other,
+    })
+}
+
+fn next_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match input.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+/// The terminal in non-canonical, no-echo mode, restored on drop.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&[
+            "-icanon", "-echo", "-isig", "-ixon", "min", "1", "time", "0",
+        ])?;
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[self.saved.as_str()]);
+    }
+}
+
+/// Run `stty` on the terminal on stdin.
+fn stty(args: &[&str]) -> Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::piped())
+        .output()
+        .context("running stty (is this a terminal?)")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("stty failed: {}", stderr.trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_decoded_from_terminal_bytes() {
+        let mut input: &[u8] = b"a\xc3\xa9\x1b[A\x1b[3~\x1bOH\x17\t\r";
+        let keys: Vec<Key> = std::iter::from_fn(|| match read_key(&mut input).unwrap() {
+            Key::Eof => None,
+            key => Some(key),
+        })
+        .collect();
+        assert_eq!(
+            keys,
+            [
+                Key::Char('a'),
+                Key::Char('é'),
+                Key::Up,
+                Key::Delete,
+                Key::Home,
+                Key::KillWord,
+                Key::Tab,
+                Key::Enter,
+            ]
+        );
+    }
+
+    #[test]
+    fn editing_and_completion_prefixes() {
+        let mut line = Line::default();
+        for c in "db.host port".chars() {
+            line.edit(&Key::Char(c));
+        }
+        line.edit(&Key::KillWord);
+        assert_eq!(line.text(), "db.host ");
+        line.edit(&Key::Home);
+        line.edit(&Key::Delete);
+        line.edit(&Key::End);
+        line.edit(&Key::Backspace);
+        assert_eq!(line.text(), "b.host");
+        line.cursor = 2;
+        line.replace_before_cursor(0, "server.");
+        assert_eq!((line.text().as_str(), line.cursor), ("server.host", 7));
+
+        let candidates = |words: &[&str]| words.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            common_prefix(&candidates(&["db.host", "db.hostname"])),
+            "db.host"
+        );
+        assert_eq!(common_prefix(&candidates(&["é1", "é2"])), "é");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
@@ -0,0 +1,504 @@
+//! `confctl repl FILE...`: parse files once and query them interactively,
+//! for exploring a big document without re-parsing it for every lookup.
+//!
+//! Each line is a PATH or an expression, as on the command line, run
+//! against the current file; `.` prints the whole of it. Lines starting with
+//! `:` are commands (`:help` lists them). On a terminal, Tab completes keys
+//! and commands, Up and Down walk the history, which is kept across sessions
+//! in `~/.local/share/confctl/repl_history` (`CONFCTL_REPL_HISTORY` moves it,
+//! or turns it off when empty). With stdin piped, lines are read without
+//! prompts, and the exit code is 1 when any of them failed.
+
+mod line;
+
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use serde_json::Value;
+
+use crate::emit::{self, EmitOptions, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::theme::{self, Theme};
+use crate::{
+    expr, format_value_colored, format_value_with, parse_file, path, resolve_path, Format,
+};
+use line::{Completion, Editor, Input};
+
+/// Overrides the history file location; empty turns history off.
+const HISTORY_ENV: &str = "CONFCTL_REPL_HISTORY";
+/// How many lines of history are kept.
+const HISTORY_LIMIT: usize = 1000;
+
+const COMMANDS: &[&str] = &[
+    ":files", ":help", ":keys", ":output", ":quit", ":reload", ":use",
+];
+
+const HELP: &str = "\
+PATH or EXPRESSION   query the current file (`.` for all of it)
+:keys [PATH]         list the keys or indexes under PATH
+:files               list the loaded files
+:use N|FILE          switch to another loaded file
+:output [FORMAT]     print results as FORMAT (json, yaml, toml, env, …)
+:reload              parse the files again
+:help                this help
+:quit                leave (also Ctrl-D)
+";
+
+#[derive(Args, Debug)]
+pub(crate) struct ReplCli {
+    /// Config files to load; `:use` switches between them.
+    #[arg(required = true, value_name = "FILE")]
+    pub(crate) files: Vec<String>,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Print results in this format, as the top-level `-o` does.
+    #[arg(short = 'o', long, value_enum)]
+    pub(crate) output: Option<OutputFormat>,
+}
+
+/// The loaded files and the settings queries print with.
+struct Session {
+    files: Vec<String>,
+    format: Option<Format>,
+    documents: Vec<Value>,
+    current: usize,
+    output: Option<OutputFormat>,
+    theme: Option<Theme>,
+}
+
+enum Reply {
+    Text(String),
+    Quit,
+}
+
+/// Runs until `:quit` or the end of input; `true` when a line failed and
+/// stdin was not a terminal.
+pub(crate) fn run(cli: ReplCli, use_color: bool) -> Result<bool> {
+    let theme = if use_color {
+        Some(Theme::load(crate::settings::get())?)
+    } else {
+        None
+    };
+    let mut session = Session {
+        documents: Vec::new(),
+        files: cli.files,
+        format: cli.format,
+        current: 0,
+        output: cli.output,
+        theme,
+    };
+    session.load()?;
+
+    if !atty::is(atty::Stream::Stdin) {
+        let mut failed = false;
+        for line in io::stdin().lock().lines() {
+            let line = line.context("Failed to read from stdin")?;
+            match session.execute(line.trim()) {
+                Ok(Reply::Text(text)) => print!("{text}"),
+                Ok(Reply::Quit) => break,
+                Err(err) => {
+                    report(&err);
+                    failed = true;
+                }
+            }
+        }
+        return Ok(failed);
+    }
+
+    eprintln!(
+        "loaded {}; :help lists the commands",
+        session.files.join(", ")
+    );
+    let history = history_path();
+    let mut editor = Editor::new(history.as_deref().map(load_history).unwrap_or_default());
+    loop {
+        let file = &session.files[session.current];
+        let name = Path::new(file)
+            .file_name()
+            .map_or(file.into(), |n| n.to_string_lossy());
+        let prompt = format!("{name}> ");
+        let input = editor.read(&prompt, &|before| session.complete(before))?;
+        let line = match input {
+            Input::Line(line) => line,
+            Input::Interrupted => continue,
+            Input::Eof => break,
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history(line);
+        match session.execute(line) {
+            Ok(Reply::Text(text)) => print!("{text}"),
+            Ok(Reply::Quit) => break,
+            Err(err) => report(&err),
+        }
+    }
+    if let Some(path) = history {
+        save_history(&path, &editor.history);
+    }
+    Ok(false)
+}
+
+fn report(err: &anyhow::Error) {
+    eprintln!("{} {err:#}", theme::stderr("error:".red().bold()));
+}
+
+impl Session {
+    /// Parse every file, replacing what was loaded only if all of them parse.
+    fn load(&mut self) -> Result<()> {
+        self.documents = self
+            .files
+            .iter()
+            .map(|file| parse_file(file, self.format, &ParseOptions::default()))
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    fn document(&self) -> &Value {
+        &self.documents[self.current]
+    }
+
+    fn execute(&mut self, line: &str) -> Result<Reply> {
+        let Some(command) = line.strip_prefix(':') else {
+            if line.is_empty() {
+                return Ok(Reply::Text(String::new()));
+            }
+            let value = self.query(line)?;
+            return self.show(&value).map(Reply::Text);
+        };
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+        let text = match name {
+            "q" | "quit" | "exit" => return Ok(Reply::Quit),
+            "h" | "help" => HELP.to_string(),
+            "files" => self
+                .files
+                .iter()
+                .enumerate()
+                .map(|(index, file)| {
+                    let mark = if index == self.current { '*' } else { ' ' };
+                    format!("{mark} {} {file}\n", index + 1)
+                })
+                .collect(),
+            "use" => {
+                self.current = self.find_file(argument)?;
+                String::new()
+            }
+            "keys" => {
+                let value = match argument {
+                    "" | "." => self.document(),
+                    path => resolve_path(self.document(), path)?,
+                };
+                children(value).into_iter().map(|key| key + "\n").collect()
+            }
+            "output" | "o" => {
+                if !argument.is_empty() {
+                    let format = OutputFormat::from_str(argument, true)
+                        .map_err(|_| anyhow::anyhow!("unknown output format `{argument}`"))?;
+                    format.ensure_compiled()?;
+                    self.output = Some(format);
+                }
+                format!(
+                    "{}\n",
+                    format_name(self.output.unwrap_or(OutputFormat::Json))
+                )
+            }
+            "reload" => {
+                self.load()?;
+                format!("reloaded {}\n", self.files.join(", "))
+            }
+            _ => bail!("unknown command `:{name}`; :help lists the commands"),
+        };
+        Ok(Reply::Text(text))
+    }
+
+    /// A loaded file by its 1-based number or its name.
+    fn find_file(&self, argument: &str) -> Result<usize> {
+        let by_number = argument
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=self.files.len()).contains(n))
+            .map(|n| n - 1);
+        match by_number.or_else(|| self.files.iter().position(|file| file == argument)) {
+            Some(index) => Ok(index),
+            None => bail!("no loaded file `{argument}`; :files lists them"),
+        }
+    }
+
+    fn query(&self, line: &str) -> Result<Value> {
+        if line == "." {
+            return Ok(self.document().clone());
+        }
+        match expr::parse_query(Some(line))? {
+            Some(expression) => expression.eval(self.document()),
+            None => resolve_path(self.document(), line).cloned(),
+        }
+    }
+
+    fn show(&self, value: &Value) -> Result<String> {
+        if let Some(format) = self.output.filter(|format| *format != OutputFormat::Json) {
+            let opts = EmitOptions {
+                color: self.theme.is_some(),
+                ..EmitOptions::default()
+            };
+            return emit::render_with(value, format, &opts);
+        }
+        Ok(match &self.theme {
+            Some(theme) => format!("{}\n", format_value_colored(value, theme)),
+            None => format!("{}\n", format_value_with(value, false)),
+        })
+    }
+
+    /// Tab completion for the text before the cursor: commands after a
+    /// leading `:`, file and format names after `:use` and `:output`, and
+    /// otherwise the keys under the path being typed.
+    fn complete(&self, before: &str) -> Completion {
+        let start = word_start(before);
+        let word: String = before.chars().skip(start).collect();
+        let starting = |names: Vec<String>| Completion {
+            start,
+            candidates: names
+                .into_iter()
+                .filter(|name| name.starts_with(&word))
+                .collect(),
+        };
+        if start == 0 && word.starts_with(':') {
+            return starting(COMMANDS.iter().map(|c| c.to_string()).collect());
+        }
+        match before.split_whitespace().next() {
+            Some(":use") => return starting(self.files.clone()),
+            Some(":output" | ":o") => {
+                let names = OutputFormat::value_variants()
+                    .iter()
+                    .map(|format| format_name(*format))
+                    .collect();
+                return starting(names);
+            }
+            _ => {}
+        }
+        Completion {
+            start,
+            candidates: complete_path(self.document(), &word),
+        }
+    }
+}
+
+/// The paths one key longer than `word` in `document` that start with it:
+/// `db.h` gives `db.host`, `db.` every key under `db`. Shell completion
+/// (`confctl completions`) uses this too.
+pub(crate) fn complete_path(document: &Value, word: &str) -> Vec<String> {
+    let (parent, value) = match last_dot(word) {
+        Some(dot) => {
+            let parent = &word[..dot];
+            match resolve_path(document, parent) {
+                Ok(value) if !parent.contains('*') => (Some(parent), value),
+                _ => return Vec::new(),
+            }
+        }
+        None => (None, document),
+    };
+    children(value)
+        .into_iter()
+        .map(|child| match parent {
+            Some(parent) => format!("{parent}.{child}"),
+            None => child,
+        })
+        .filter(|path| path.starts_with(word))
+        .collect()
+}
+
+fn format_name(format: OutputFormat) -> String {
+    format
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// The keys of an object, as they are written in a path, or the indexes of
+/// an array.
+fn children(value: &Value) -> Vec<String> {
+    match value {
+        Value::Object(map) => map.keys().map(|key| written(key)).collect(),
+        Value::Array(items) => (0..items.len()).map(|i| i.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `key` as a path segment a line can hold: quoted when it has a dot, a
+/// quote or anything an expression would read as an operator.
+fn written(key: &str) -> String {
+    let special = |c: char| c.is_whitespace() || "()+,/%<>=!&|*'".contains(c);
+    if key.contains(special) && !key.contains(['"', '\\']) {
+        format!("\"{key}\"")
+    } else {
+        path::quote_segment(key).into_owned()
+    }
+}
+
+/// The char index where the word being typed starts: after the last space
+/// or operator outside quotes.
+fn word_start(before: &str) -> usize {
+    let mut start = 0;
+    let mut quote = None;
+    let mut previous = None;
+    for (index, c) in before.chars().enumerate() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c.is_whitespace() || "(),+/%<>=!&|".contains(c) => start = index + 1,
+            None if c == '*' && previous != Some('.') => start = index + 1,
+            None => {}
+        }
+        previous = Some(c);
+    }
+    start
+}
+
+/// The byte offset of the last `.` in `word` outside quotes.
+fn last_dot(word: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut dot = None;
+    for (at, c) in word.char_indices() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' => quote = Some(c),
+            None if c == '.' => dot = Some(at),
+            None => {}
+        }
+    }
+    dot
+}
+
+fn history_path() -> Option<PathBuf> {
+    match std::env::var_os(HISTORY_ENV) {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::data_local_dir().map(|dir| dir.join("confctl").join("repl_history")),
+    }
+}
+
+fn load_history(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Failures are ignored: losing the history must not fail the session.
+fn save_history(path: &Path, history: &[String]) {
+    let kept = &history[history.len().saturating_sub(HISTORY_LIMIT)..];
+    let mut text = kept.join("\n");
+    text.push('\n');
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(path, text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn session() -> Session {
+        Session {
+            files: vec!["app.yaml".to_string(), "db.json".to_string()],
+            format: None,
+            documents: vec![
+                json!({
+                    "server": {"host": "0.0.0.0", "port": 8080, "hosts": ["a", "b"]},
+                    "labels": {"app.kubernetes.io/name": "web", "team name": "core"},
+                    "replicas": 3
+                }),
+                json!({"db": {"port": 5432}}),
+            ],
+            current: 0,
+            output: None,
+            theme: None,
+        }
+    }
+
+    fn text(session: &mut Session, line: &str) -> String {
+        match session.execute(line).unwrap() {
+            Reply::Text(text) => text,
+            Reply::Quit => panic!("{line} quit"),
+        }
+    }
+
+    #[test]
+    fn lines_are_queries_and_commands() {
+        let mut session = session();
+        assert_eq!(text(&mut session, "server.port"), "8080\n");
+        assert_eq!(text(&mut session, "replicas * 2"), "6\n");
+        assert_eq!(
+            text(&mut session, "server.hosts"),
+            "[\n  \"a\",\n  \"b\"\n]\n"
+        );
+        assert_eq!(text(&mut session, ":keys server"), "host\nhosts\nport\n");
+        assert_eq!(
+            text(&mut session, ":keys labels"),
+            "\"app.kubernetes.io/name\"\n\"team name\"\n"
+        );
+        assert_eq!(text(&mut session, ":use db.json"), "");
+        assert_eq!(text(&mut session, ":files"), "  1 app.yaml\n* 2 db.json\n");
+        assert_eq!(text(&mut session, ":output env"), "env\n");
+        assert_eq!(text(&mut session, "db"), "PORT=5432\n");
+        assert!(matches!(session.execute(":q").unwrap(), Reply::Quit));
+
+        let err = |session: &mut Session, line: &str| session.execute(line).err().unwrap();
+        assert_eq!(
+            err(&mut session, "db.user").to_string(),
+            "Key not found: 'user' (at path 'db.user')"
+        );
+        assert!(err(&mut session, ":use 3")
+            .to_string()
+            .starts_with("no loaded file"));
+        assert!(err(&mut session, ":nope")
+            .to_string()
+            .starts_with("unknown command"));
+    }
+
+    #[test]
+    fn completion_follows_the_path_being_typed() {
+        let session = session();
+        let complete = |before: &str| {
+            let Completion { start, candidates } = session.complete(before);
+            (start, candidates)
+        };
+        assert_eq!(complete("ser"), (0, vec!["server".to_string()]));
+        assert_eq!(
+            complete("server.ho"),
+            (
+                0,
+                vec!["server.host".to_string(), "server.hosts".to_string()]
+            )
+        );
+        assert_eq!(
+            complete("server.hosts.").1,
+            ["server.hosts.0", "server.hosts.1"]
+        );
+        assert_eq!(
+            complete("upper(labels.\"te"),
+            (6, vec!["labels.\"team name\"".to_string()])
+        );
+        assert_eq!(
+            complete("replicas * rep"),
+            (11, vec!["replicas".to_string()])
+        );
+        assert_eq!(complete(":k").1, [":keys"]);
+        assert_eq!(complete(":use d").1, ["db.json"]);
+        assert_eq!(complete(":output ya").1, ["yaml"]);
+        assert!(complete("missing.").1.is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+//! `confctl to-schema` — infer a JSON Schema (draft 2020-12) from a config
+//! file, as a starting point for validating configs that never had one.
+//!
+//! The document is the only sample, so the schema says what it shows:
+//!
+//! - Types come from the values; a key holding several types gets a list.
+//!   Integers and floats in the same place are `number`.
+//! - Every key of an object is `required`. For arrays of objects, only the
+//!   keys every item has are.
+//! - A string field seen several times (across array items) with a few
+//!   repeating values becomes an `enum` of them; `--max-enum` caps how many.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use clap::Args;
+use serde_json::{json, Map, Value};
+
+use crate::emit::{self, OutputFormat};
+use crate::formats::ParseOptions;
+use crate::{parse_file, Format};
+
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+#[derive(Args, Debug)]
+pub(crate) struct ToSchemaCli {
+    /// Config file to describe, or `-` for stdin.
+    pub(crate) file: String,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Format the schema is printed in.
+    #[arg(long, value_enum, default_value = "json")]
+    pub(crate) output: OutputFormat,
+
+    /// Most distinct values a string field may have to become an `enum`;
+    /// 0 never infers one.
+    #[arg(long, default_value_t = 5)]
+    pub(crate) max_enum: usize,
+}
+
+pub(crate) fn run(cli: ToSchemaCli) -> Result<()> {
+    let value = parse_file(&cli.file, cli.format, &ParseOptions::default())?;
+    print!(
+        "{}",
+        emit::render(&infer(&value, cli.max_enum), cli.output)?
+    );
+    Ok(())
+}
+
+/// The schema of `value`, with `$schema` naming the dialect.
+pub(crate) fn infer(value: &Value, max_enum: usize) -> Value {
+    let mut shape = Shape::default();
+    shape.observe(value, max_enum);
+    let mut schema = shape.schema(max_enum);
+    if let Value::Object(map) = &mut schema {
+        map.insert("$schema".into(), json!(DIALECT));
+    }
+    schema
+}
+
+/// Everything seen at one position of the document.
+#[derive(Default)]
+struct Shape {
+    seen: usize,
+    null: bool,
+    boolean: bool,
+    integer: bool,
+    float: bool,
+    strings: usize,
+    /// Distinct strings and how often each occurred; `None` once there are
+    /// more than an enum could hold.
+    values: Option<BTreeMap<String, usize>>,
+    objects: usize,
+    properties: BTreeMap<String, Shape>,
+    arrays: bool,
+    items: Option<Box<Shape>>,
+}
+
+impl Shape {
+    fn observe(&mut self, value: &Value, max_enum: usize) {
+        self.seen += 1;
+        match value {
+            Value::Null => self.null = true,
+            Value::Bool(_) => self.boolean = true,
+            Value::Number(n) if n.is_f64() => self.float = true,
+            Value::Number(_) => self.integer = true,
+            Value::String(text) => {
+                if self.strings == 0 {
+                    self.values = Some(BTreeMap::new());
+                }
+                self.strings += 1;
+                if let Some(values) = &mut self.values {
+                    *values.entry(text.clone()).or_default() += 1;
+                    if values.len() > max_enum {
+                        self.values = None;
+                    }
+                }
+            }
+            Value::Array(items) => {
+                self.arrays = true;
+                for item in items {
+                    self.items
+                        .get_or_insert_with(Default::default)
+                        .observe(item, max_enum);
+                }
+            }
+            Value::Object(map) => {
+                self.objects += 1;
+                for (key, child) in map {
+                    self.properties
+                        .entry(key.clone())
+                        .or_default()
+                        .observe(child, max_enum);
+                }
+            }
+        }
+    }
+
+    fn schema(&self, max_enum: usize) -> Value {
+        let mut types = Vec::new();
+        let mut out = Map::new();
+        if self.objects > 0 {
+            types.push("object");
+            let properties: Map<String, Value> = self
+                .properties
+                .iter()
+                .map(|(key, shape)| (key.clone(), shape.schema(max_enum)))
+                .collect();
+            let required: Vec<&String> = self
+                .properties
+                .iter()
+                .filter(|(_, shape)| shape.seen == self.objects)
+                .map(|(key, _)| key)
+                .collect();
+            out.insert("properties".into(), Value::Object(properties));
+            if !required.is_empty() {
+                out.insert("required".into(), json!(required));
+            }
+        }
+        if self.arrays {
+            types.push("array");
+            if let Some(items) = &self.items {
+                out.insert("items".into(), items.schema(max_enum));
+            }
+        }
+        if self.strings > 0 {
+            types.push("string");
+        }
+        if self.float {
+            types.push("number");
+        } else if self.integer {
+            types.push("integer");
+        }
+        if self.boolean {
+            types.push("boolean");
+        }
+        if self.null {
+            types.push("null");
+        }
+        if let Some(values) = self.enum_candidates(max_enum) {
+            out.insert("enum".into(), json!(values));
+        }
+        let kind = match types.as_slice() {
+            [] => return Value::Object(out),
+            [single] => json!(single),
+            several => json!(several),
+        };
+        let mut schema = Map::new();
+        schema.insert("type".into(), kind);
+        schema.extend(out);
+        Value::Object(schema)
+    }
+
+    /// Only strings, with at least two distinct values and fewer distinct
+    /// values than occurrences: a value that is new each time is not a
+    /// choice, and one that never changes is not evidence of others.
+    fn enum_candidates(&self, max_enum: usize) -> Option<Vec<&String>> {
+        let values = self.values.as_ref()?;
+        let only_strings = self.strings == self.seen;
+        (max_enum > 0 && only_strings && values.len() > 1 && values.len() < self.strings)
+            .then(|| values.keys().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn types_required_keys_and_enums() {
+        let value = json!({
+            "name": "api",
+            "port": 8080,
+            "ratio": 0.5,
+            "services": [
+                {"name": "web", "tier": "front", "replicas": 2, "debug": true},
+                {"name": "db", "tier": "back", "replicas": 1.5},
+                {"name": "cache", "tier": "back", "replicas": 1, "debug": null}
+            ],
+            "tags": []
+        });
+        assert_eq!(
+            infer(&value, 5),
+            json!({
+                "$schema": DIALECT,
+                "type": "object",
+                "properties": {
+                    "name": {"type": "string"},
+                    "port": {"type": "integer"},
+                    "ratio": {"type": "number"},
+                    "services": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "debug": {"type": ["boolean", "null"]},
+                                "name": {"type": "string"},
+                                "replicas": {"type": "number"},
+                                "tier": {"type": "string", "enum": ["back", "front"]}
+                            },
+                            "required": ["name", "replicas", "tier"]
+                        }
+                    },
+                    "tags": {"type": "array"}
+                },
+                "required": ["name", "port", "ratio", "services", "tags"]
+            })
+        );
+    }
+
+    #[test]
+    fn enums_respect_max_enum() {
+        let value = json!(["a", "b", "c", "a"]);
+        assert_eq!(infer(&value, 3)["items"]["enum"], json!(["a", "b", "c"]));
+        assert_eq!(infer(&value, 2)["items"].get("enum"), None);
+        assert_eq!(infer(&value, 0)["items"].get("enum"), None);
+        // No repeats: names, not choices. One value: a constant, not a choice.
+        assert_eq!(infer(&json!(["a", "b"]), 5)["items"].get("enum"), None);
+        assert_eq!(infer(&json!(["a", "a"]), 5)["items"].get("enum"), None);
+    }
+}
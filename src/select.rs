@@ -0,0 +1,118 @@
+//! `--select PATH=VALUE`: pick documents out of a multi-document stream
+//! (or items out of a top-level array) before the path is resolved, e.g.
+//! `confctl bundle.yaml spec.replicas --select kind=Deployment`.
+//!
+//! Every selector must match. A single match is returned on its own so the
+//! path applies to it directly; several matches stay an array.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+use crate::resolve_path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Selector {
+    path: String,
+    value: String,
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.path, self.value)
+    }
+}
+
+impl Selector {
+    /// Whether `doc` has a scalar at the path whose text equals the value.
+    fn matches(&self, doc: &Value) -> bool {
+        match resolve_path(doc, &self.path) {
+            Ok(Value::String(s)) => *s == self.value,
+            Ok(other @ (Value::Number(_) | Value::Bool(_) | Value::Null)) => {
+                serde_json::to_string(other).is_ok_and(|text| text == self.value)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// clap value parser for `--select`.
+pub(crate) fn parse_selector(raw: &str) -> Result<Selector, String> {
+    match raw.split_once('=') {
+        Some((path, value)) if !path.trim().is_empty() => Ok(Selector {
+            path: path.trim().to_string(),
+            value: value.to_string(),
+        }),
+        _ => Err(format!(
+            "expected PATH=VALUE (e.g. kind=Deployment), got `{raw}`"
+        )),
+    }
+}
+
+pub(crate) fn apply(value: Value, selectors: &[Selector]) -> Result<Value> {
+    let docs = match value {
+        Value::Array(items) => items,
+        other => vec![other],
+    };
+    let total = docs.len();
+    let mut matched: Vec<Value> = docs
+        .into_iter()
+        .filter(|doc| selectors.iter().all(|s| s.matches(doc)))
+        .collect();
+    match matched.len() {
+        0 => {
+            let wanted: Vec<String> = selectors.iter().map(Selector::to_string).collect();
+            bail!(
+                "no document matches --select {} ({total} document{} checked)",
+                wanted.join(" --select "),
+                if total == 1 { "" } else { "s" }
+            )
+        }
+        1 => Ok(matched.remove(0)),
+        _ => Ok(Value::Array(matched)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bundle() -> Value {
+        json!([
+            {"kind": "Deployment", "metadata": {"name": "api"}, "spec": {"replicas": 3}},
+            {"kind": "Service", "metadata": {"name": "api"}},
+            {"kind": "Deployment", "metadata": {"name": "worker"}, "spec": {"replicas": 1}}
+        ])
+    }
+
+    #[test]
+    fn single_match_is_unwrapped() {
+        let selectors = [
+            parse_selector("kind=Deployment").unwrap(),
+            parse_selector("metadata.name=worker").unwrap(),
+        ];
+        let doc = apply(bundle(), &selectors).unwrap();
+        assert_eq!(doc["spec"]["replicas"], json!(1));
+    }
+
+    #[test]
+    fn several_matches_stay_an_array_and_scalars_compare_as_text() {
+        let doc = apply(bundle(), &[parse_selector("kind=Deployment").unwrap()]).unwrap();
+        assert_eq!(doc.as_array().map(Vec::len), Some(2));
+        let doc = apply(bundle(), &[parse_selector("spec.replicas=3").unwrap()]).unwrap();
+        assert_eq!(doc["metadata"]["name"], json!("api"));
+    }
+
+    #[test]
+    fn no_match_and_bad_syntax_are_errors() {
+        let err = apply(bundle(), &[parse_selector("kind=Ingress").unwrap()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no document matches --select kind=Ingress (3 documents checked)"
+        );
+        assert!(parse_selector("kind").is_err());
+        assert!(parse_selector("=x").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! `confctl set` on JSON, YAML and TOML files: `PATH=VALUE` pairs, or a
+//! single `PATH VALUE`, written into the parsed document.
+//!
+//! Unlike the .env editor this re-serialises the whole file, as `convert`
+//! does: comments are not kept and keys come out sorted. Missing objects
+//! along the path are created (an array when the next segment is `0`), and
+//! an array index one past the end appends.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+use crate::atomic;
+use crate::emit::{self, OutputFormat};
+use crate::env_edit::{SetCli, SetOutcome};
+use crate::errors::PathError;
+use crate::{detect_format, parse_content, path, undo, Format};
+
+/// How `--type` reads VALUE.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum ValueType {
+    String,
+    Int,
+    Float,
+    Bool,
+    /// Any JSON literal: objects, arrays, numbers, `true`, `null`, "text".
+    Json,
+}
+
+impl ValueType {
+    /// `raw` as this type, or an error naming what it is not.
+    pub(crate) fn parse(self, raw: &str) -> Result<Value> {
+        self.parse_for(raw, &format!("--type {}", self.name()))
+    }
+
+    /// `parse`, with `flag` as the hint in the error.
+    pub(crate) fn parse_for(self, raw: &str, flag: &str) -> Result<Value> {
+        let text = raw.trim();
+        let value = match self {
+            ValueType::String => Some(Value::String(raw.to_string())),
+            ValueType::Int => text.parse::<i64>().ok().map(Value::from),
+            ValueType::Float => text
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number),
+            ValueType::Bool => match text.to_ascii_lowercase().as_str() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            ValueType::Json => {
+                return serde_json::from_str(raw)
+                    .with_context(|| format!("`{raw}` is not valid JSON ({flag})"));
+            }
+        };
+        let Some(value) = value else {
+            let what = match self {
+                ValueType::Int => "an integer",
+                ValueType::Float => "a finite number",
+                _ => "a boolean (true or false)",
+            };
+            bail!("`{raw}` is not {what} ({flag})");
+        };
+        Ok(value)
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Int => "int",
+            ValueType::Float => "float",
+            ValueType::Bool => "bool",
+            ValueType::Json => "json",
+        }
+    }
+}
+
+/// The format `set` edits `file` as when it is a structured document rather
+/// than a .env file. Anything else, including files detection cannot place,
+/// goes to the .env editor as before.
+pub(crate) fn structured_format(file: &str) -> Result<Option<Format>> {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(_) if !Path::new(file).exists() => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("reading {file}")),
+    };
+    Ok(match detect_format(file, &content, None) {
+        Ok(format @ (Format::Json | Format::Json5 | Format::Yaml | Format::Toml)) => Some(format),
+        _ => None,
+    })
+}
+
+/// `PATH VALUE`, or one or more `PATH=VALUE` pairs.
+fn assignments(args: &[String]) -> Result<Vec<(&str, &str)>> {
+    match args {
+        [path, value] if !path.contains('=') => Ok(vec![(path.as_str(), value.as_str())]),
+        _ => args
+            .iter()
+            .map(|arg| {
+                arg.split_once('=')
+                    .with_context(|| format!("expected PATH=VALUE, got {arg:?}"))
+            })
+            .collect(),
+    }
+}
+
+/// Write `value` at `segments` in `doc`, creating missing objects on the
+/// way. Fails like `resolve_path` when the path runs into a scalar or an
+/// array with a key, or indexes past the end of an array.
+pub(crate) fn set_path(doc: &mut Value, segments: &[String], value: Value) -> Result<SetOutcome> {
+    let Some(last) = segments.last() else {
+        bail!("set needs a path");
+    };
+    let at = segments.len() - 1;
+    let mut current = doc;
+    for i in 0..at {
+        current = child(current, segments, i)?;
+        if current.is_null() {
+            // A key created on the way, or an explicit null: either way the
+            // path goes through it, as an array when it starts one (`tags.0`).
+            *current = match segments[i + 1].as_str() {
+                "0" => Value::Array(Vec::new()),
+                _ => Value::Object(Map::new()),
+            };
+        }
+    }
+
+    match current {
+        Value::Object(map) => Ok(match map.insert(last.clone(), value) {
+            Some(_) => SetOutcome::Updated,
+            None => SetOutcome::Added,
+        }),
+        Value::Array(items) => {
+            let index = array_index(items, segments, at)?;
+            if index == items.len() {
+                items.push(value);
+                Ok(SetOutcome::Added)
+            } else {
+                items[index] = value;
+                Ok(SetOutcome::Updated)
+            }
+        }
+        _ => Err(scalar(segments, at).into()),
+    }
+}
+
+/// The child `segments[i]` names, inserted as null when missing.
+fn child<'a>(current: &'a mut Value, segments: &[String], i: usize) -> Result<&'a mut Value> {
+    match current {
+        Value::Object(map) => Ok(map.entry(segments[i].clone()).or_insert(Value::Null)),
+        Value::Array(items) => {
+            let index = array_index(items, segments, i)?;
+            if index == items.len() {
+                items.push(Value::Null);
+            }
+            Ok(&mut items[index])
+        }
+        _ => Err(scalar(segments, i).into()),
+    }
+}
+
+/// `segments[i]` as an index into `items`, at most one past the end.
+fn array_index(items: &[Value], segments: &[String], i: usize) -> Result<usize> {
+    let segment = &segments[i];
+    let index: usize = segment.parse().map_err(|_| PathError::NotAnIndex {
+        segment: segment.clone(),
+        path: path::join(&segments[..=i]),
+    })?;
+    if index > items.len() {
+        return Err(PathError::OutOfBounds {
+            segment: segment.clone(),
+            path: path::join(&segments[..=i]),
+            index,
+            len: items.len(),
+        }
+        .into());
+    }
+    Ok(index)
+}
+
+fn scalar(segments: &[String], i: usize) -> PathError {
+    PathError::Scalar {
+        segment: segments[i].clone(),
+        path: path::join(&segments[..=i]),
+        parent: path::join(&segments[..i]),
+    }
+}
+
+pub(crate) fn run(cli: &SetCli, format: Format) -> Result<()> {
+    let file = &cli.file;
+    let mut doc = if Path::new(file).exists() {
+        let content = fs::read_to_string(file).with_context(|| format!("reading {file}"))?;
+        parse_content(file, &content, Some(format))?
+    } else {
+        Value::Object(Map::new())
+    };
+
+    let mut report = Vec::new();
+    for (raw_path, raw_value) in assignments(&cli.pairs)? {
+        let value = match cli.value_type {
+            Some(value_type) => value_type.parse(raw_value)?,
+            None => crate::formats::dotenv::typed(raw_value),
+        };
+        let segments = path::segments(raw_path)?;
+        let outcome = set_path(&mut doc, &segments, value)
+            .with_context(|| format!("setting {raw_path} in {file}"))?;
+        report.push((raw_path, outcome));
+    }
+
+    let output = OutputFormat::from_input(format).unwrap_or(OutputFormat::Json);
+    let rendered = emit::render(&doc, output).with_context(|| format!("writing {file}"))?;
+    undo::record(Path::new(file), "set")?;
+    atomic::write(Path::new(file), rendered, cli.backup.suffix())?;
+    if atomic::previewing() {
+        return Ok(());
+    }
+
+    for (raw_path, outcome) in report {
+        let verb = match outcome {
+            SetOutcome::Added => "added",
+            SetOutcome::Updated => "updated",
+        };
+        println!("{} {verb} {}", "✓".green().bold(), raw_path.bold());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn segments(path: &str) -> Vec<String> {
+        path::segments(path).unwrap()
+    }
+
+    #[test]
+    fn value_types_coerce_or_explain() {
+        assert_eq!(ValueType::String.parse("5432").unwrap(), json!("5432"));
+        assert_eq!(ValueType::Int.parse(" 5432 ").unwrap(), json!(5432));
+        assert_eq!(ValueType::Float.parse("1").unwrap(), json!(1.0));
+        assert_eq!(ValueType::Bool.parse("False").unwrap(), json!(false));
+        assert_eq!(
+            ValueType::Json
+                .parse(r#"{"host":"x","port":5432}"#)
+                .unwrap(),
+            json!({"host": "x", "port": 5432})
+        );
+
+        let err = |t: ValueType, raw: &str| t.parse(raw).unwrap_err().to_string();
+        assert_eq!(
+            err(ValueType::Int, "5432.5"),
+            "`5432.5` is not an integer (--type int)"
+        );
+        assert_eq!(
+            err(ValueType::Bool, "yes"),
+            "`yes` is not a boolean (true or false) (--type bool)"
+        );
+        assert_eq!(
+            err(ValueType::Json, "{host"),
+            "`{host` is not valid JSON (--type json)"
+        );
+    }
+
+    #[test]
+    fn set_path_creates_updates_and_appends() {
+        let mut doc = json!({"db": {"host": "a"}, "hosts": ["x"]});
+        let outcome = set_path(&mut doc, &segments("db.host"), json!("b")).unwrap();
+        assert_eq!(outcome, SetOutcome::Updated);
+        let outcome = set_path(&mut doc, &segments("db.pool.size"), json!(5)).unwrap();
+        assert_eq!(outcome, SetOutcome::Added);
+        let outcome = set_path(&mut doc, &segments("hosts[1]"), json!("y")).unwrap();
+        assert_eq!(outcome, SetOutcome::Added);
+        set_path(&mut doc, &segments("tags[0].name"), json!("web")).unwrap();
+        assert_eq!(
+            doc,
+            json!({
+                "db": {"host": "b", "pool": {"size": 5}},
+                "hosts": ["x", "y"],
+                "tags": [{"name": "web"}]
+            })
+        );
+    }
+
+    #[test]
+    fn set_path_refuses_what_resolve_path_would() {
+        let mut doc = json!({"port": 80, "hosts": ["x"]});
+        let err = |doc: &mut Value, path: &str| {
+            set_path(doc, &segments(path), json!(1))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err(&mut doc, "port.number"),
+            "Cannot traverse into a scalar value at 'port' (trying to access 'number')"
+        );
+        assert_eq!(
+            err(&mut doc, "hosts.5"),
+            "Array index 5 out of bounds (length 1) at path 'hosts.5'"
+        );
+        assert_eq!(
+            err(&mut doc, "hosts.first"),
+            "Expected numeric index for array access, got 'first' (at path 'hosts.first')"
+        );
+    }
+
+    #[test]
+    fn assignments_take_a_pair_list_or_path_and_value() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let two = args(&["db", r#"{"host":"x"}"#]);
+        assert_eq!(assignments(&two).unwrap(), [("db", r#"{"host":"x"}"#)]);
+        let pairs = args(&["db.port=5432", "url=a=b"]);
+        assert_eq!(
+            assignments(&pairs).unwrap(),
+            [("db.port", "5432"), ("url", "a=b")]
+        );
+        assert!(assignments(&args(&["db.port"])).is_err());
+    }
+}
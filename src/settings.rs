@@ -0,0 +1,165 @@
+//! confctl's own defaults, from `~/.config/confctl/config.toml` (or the
+//! file `$CONFCTL_USER_CONFIG` names):
+//!
+//! ```toml
+//! color = "auto"                  # auto | always | never
+//! theme = "light,key=cyan"        # as CONFCTL_THEME
+//! output = "yaml"                 # default -o for queries
+//! mask_patterns = ["dsn"]         # added to --mask-pattern when redacting
+//! mask_with = "*****"
+//!
+//! [aliases]
+//! prod = "~/deploy/prod/values.yaml"   # `confctl @prod db.host`
+//! ```
+//!
+//! Every setting is a fallback: a CLI flag wins over an env var, which wins
+//! over this file. A missing file means no defaults; unknown keys are an
+//! error, so a typo does not silently do nothing.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::emit::OutputFormat;
+use crate::theme::ColorChoice;
+
+/// Env var naming the settings file; empty disables it.
+const SETTINGS_ENV: &str = "CONFCTL_USER_CONFIG";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Settings {
+    pub(crate) color: Option<ColorChoice>,
+    pub(crate) theme: Option<String>,
+    pub(crate) output: Option<OutputFormat>,
+    pub(crate) mask_patterns: Vec<String>,
+    pub(crate) mask_with: Option<String>,
+    /// Short names for files queried often.
+    pub(crate) aliases: BTreeMap<String, String>,
+    /// Where these settings came from, for error messages.
+    #[serde(skip)]
+    pub(crate) path: Option<PathBuf>,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Load the settings file once per process; later `get` calls see it.
+pub(crate) fn init() -> Result<&'static Settings> {
+    if let Some(settings) = SETTINGS.get() {
+        return Ok(settings);
+    }
+    let settings = match settings_path() {
+        Some(path) => Settings::load(&path)?,
+        None => Settings::default(),
+    };
+    Ok(SETTINGS.get_or_init(|| settings))
+}
+
+/// The loaded settings, or none before `init` (as in unit tests).
+pub(crate) fn get() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+fn settings_path() -> Option<PathBuf> {
+    match std::env::var_os(SETTINGS_ENV) {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::config_dir().map(|dir| dir.join("confctl").join("config.toml")),
+    }
+}
+
+impl Settings {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self {
+                path: Some(path.to_path_buf()),
+                ..Self::default()
+            });
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("reading confctl settings from {}", path.display()))?;
+        Self::parse(&content, path)
+    }
+
+    #[cfg(feature = "toml")]
+    fn parse(content: &str, path: &Path) -> Result<Self> {
+        let mut settings: Settings = toml::from_str(content)
+            .with_context(|| format!("parsing confctl settings at {}", path.display()))?;
+        settings.path = Some(path.to_path_buf());
+        Ok(settings)
+    }
+
+    /// A build without TOML cannot read the file, but should not ignore it.
+    #[cfg(not(feature = "toml"))]
+    fn parse(_: &str, path: &Path) -> Result<Self> {
+        bail!(
+            "cannot read confctl settings at {}: confctl was built without the `toml` feature",
+            path.display()
+        );
+    }
+
+    /// The file `@name` stands for, with a leading `~/` expanded.
+    pub(crate) fn alias(&self, name: &str) -> Result<String> {
+        let Some(target) = self.aliases.get(name) else {
+            let file = match &self.path {
+                Some(path) => path.display().to_string(),
+                None => "the settings file".to_string(),
+            };
+            if self.aliases.is_empty() {
+                bail!("unknown alias `@{name}`: no [aliases] in {file}");
+            }
+            let known: Vec<&str> = self.aliases.keys().map(String::as_str).collect();
+            bail!(
+                "unknown alias `@{name}` (defined in {file}: {})",
+                known.join(", ")
+            );
+        };
+        Ok(match (target.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest).display().to_string(),
+            _ => target.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_setting() {
+        let text = r#"
+            color = "never"
+            theme = "light"
+            output = "json-canonical"
+            mask_patterns = ["dsn"]
+            mask_with = "***"
+
+            [aliases]
+            prod = "deploy/prod.yaml"
+        "#;
+        let settings = Settings::parse(text, Path::new("config.toml")).unwrap();
+        assert_eq!(settings.color, Some(ColorChoice::Never));
+        assert_eq!(settings.output, Some(OutputFormat::JsonCanonical));
+        assert_eq!(settings.mask_patterns, ["dsn"]);
+        assert_eq!(settings.alias("prod").unwrap(), "deploy/prod.yaml");
+        assert_eq!(
+            settings.alias("dev").unwrap_err().to_string(),
+            "unknown alias `@dev` (defined in config.toml: prod)"
+        );
+        assert_eq!(
+            Settings::default().alias("dev").unwrap_err().to_string(),
+            "unknown alias `@dev`: no [aliases] in the settings file"
+        );
+    }
+
+    #[test]
+    fn typos_are_errors() {
+        let err = Settings::parse("colour = \"never\"", Path::new("c.toml")).unwrap_err();
+        assert_eq!(err.to_string(), "parsing confctl settings at c.toml");
+        assert!(format!("{:#}", err).contains("unknown field `colour`"));
+        assert!(Settings::parse("output = \"yml\"", Path::new("c.toml")).is_err());
+    }
+}
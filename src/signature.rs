@@ -0,0 +1,367 @@
+//! `--verify-sig KEY`: check a detached signature over each input's bytes
+//! before they are parsed, so a deploy script can trust a config it fetched.
+//!
+//! As with age and snapshot signatures, nothing is linked in: KEY picks the
+//! tool. A minisign public key is checked with `minisign -V`, a PEM public
+//! key with `cosign verify-blob --key`, and an OpenPGP public key (armored or
+//! binary) with `gpg --verify` in a throwaway keyring holding only that key.
+//! The signature is `--signature SIG`, or else sits next to the input and
+//! is read the same way: `FILE.minisig` for minisign, `FILE.sig` for cosign,
+//! `FILE.sig` or `FILE.asc` for GPG, so `s3://configs/app.yaml` is checked
+//! against `s3://configs/app.yaml.sig`.
+//!
+//! It fails closed: a missing signature, a missing tool, an unknown key or
+//! a bad signature is an error and the document is never parsed.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use crate::compress::pipe;
+use crate::formats::ParseOptions;
+use crate::input::{self, InputSource};
+use crate::{parse_bytes, Format};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tool {
+    Minisign,
+    Cosign,
+    Gpg,
+}
+
+impl Tool {
+    /// The tool that checks signatures made with `key`'s private half.
+    fn of_key(key: &[u8]) -> Option<Tool> {
+        let text = String::from_utf8_lossy(key);
+        if text.starts_with("untrusted comment:") && text.contains("minisign") {
+            Some(Tool::Minisign)
+        } else if text.contains("-----BEGIN PUBLIC KEY-----") {
+            Some(Tool::Cosign)
+        } else if text.contains("-----BEGIN PGP PUBLIC KEY BLOCK-----") {
+            Some(Tool::Gpg)
+        } else if key.first().is_some_and(|tag| tag & 0x80 != 0) {
+            // An OpenPGP packet: a binary key export.
+            Some(Tool::Gpg)
+        } else {
+            None
+        }
+    }
+
+    /// Where the signature of a file is looked for, in order.
+    fn suffixes(self) -> &'static [&'static str] {
+        match self {
+            Tool::Minisign => &[".minisig"],
+            Tool::Cosign => &[".sig"],
+            Tool::Gpg => &[".sig", ".asc"],
+        }
+    }
+}
+
+struct Policy {
+    key: PathBuf,
+    tool: Tool,
+    signature: Option<String>,
+}
+
+static POLICY: OnceLock<Option<Policy>> = OnceLock::new();
+
+/// Check every input against `key` for this process; `signature` replaces
+/// the signature next to the input. An unreadable or unknown key is an
+/// error here, before anything is read.
+pub(crate) fn configure(key: Option<&Path>, signature: Option<&str>) -> Result<()> {
+    let policy = match key {
+        Some(key) => {
+            let bytes =
+                fs::read(key).with_context(|| format!("reading --verify-sig {}", key.display()))?;
+            let Some(tool) = Tool::of_key(&bytes) else {
+                bail!(
+                    "--verify-sig {}: not a minisign, PEM (cosign) or OpenPGP public key",
+                    key.display()
+                );
+            };
+            Some(Policy {
+                key: key.to_path_buf(),
+                tool,
+                signature: signature.map(str::to_string),
+            })
+        }
+        None => None,
+    };
+    let _ = POLICY.set(policy);
+    Ok(())
+}
+
+/// `source` behind the signature check, when `--verify-sig` is on.
+pub(crate) fn wrap(file: &str, source: Box<dyn InputSource>) -> Box<dyn InputSource> {
+    match POLICY.get() {
+        Some(Some(policy)) => Box::new(Verified {
+            file: file.to_string(),
+            source,
+            policy,
+        }),
+        _ => source,
+    }
+}
+
+struct Verified {
+    file: String,
+    source: Box<dyn InputSource>,
+    policy: &'static Policy,
+}
+
+impl InputSource for Verified {
+    fn read(&self) -> Result<(String, Vec<u8>)> {
+        if self.file.starts_with(input::VAULT_KV) || self.file.starts_with(crate::k8s::PREFIX) {
+            bail!(
+                "--verify-sig cannot check {}: vault:// and k8s:// documents are not \
+                 stored bytes a signature could cover",
+                self.file
+            );
+        }
+        let (name, bytes) = self.source.read()?;
+        let signature = self.signature()?;
+        verify(self.policy, &bytes, &signature)
+            .with_context(|| format!("signature check of {} failed", self.file))?;
+        Ok((name, bytes))
+    }
+
+    /// Always parsed from the checked bytes, never from the source's own
+    /// reading of them.
+    fn load(&self, format: Option<Format>, opts: &ParseOptions) -> Result<Value> {
+        if opts.recurse {
+            bail!("--verify-sig checks one document; it cannot check the keys --recurse reads");
+        }
+        let (name, bytes) = self.read()?;
+        parse_bytes(&name, &bytes, format, opts)
+    }
+}
+
+impl Verified {
+    fn signature(&self) -> Result<Vec<u8>> {
+        if let Some(signature) = &self.policy.signature {
+            let (_, bytes) = input::fetch(signature)
+                .read()
+                .with_context(|| format!("reading signature {signature}"))?;
+            return Ok(bytes);
+        }
+        let Some(candidates) = self
+            .policy
+            .tool
+            .suffixes()
+            .iter()
+            .map(|suffix| sibling(&self.file, suffix))
+            .collect::<Option<Vec<_>>>()
+        else {
+            bail!(
+                "`{}` has no place for a signature next to it; name one with --signature",
+                self.file
+            );
+        };
+        let mut last = None;
+        for candidate in &candidates {
+            match input::fetch(candidate).read() {
+                Ok((_, bytes)) => return Ok(bytes),
+                Err(err) => last = Some(err),
+            }
+        }
+        let looked = candidates.join(", ");
+        Err(last.expect("every tool has a suffix").context(format!(
+            "no signature for {} (looked for {looked})",
+            self.file
+        )))
+    }
+}
+
+/// The signature file of `file`: `suffix` after the path, before the ref of
+/// a `git::` argument. Stdin and the clipboard have none.
+fn sibling(file: &str, suffix: &str) -> Option<String> {
+    if file == "-" || file == input::CLIPBOARD {
+        return None;
+    }
+    if file.starts_with(crate::git::PREFIX) {
+        if let Some(split) = file.rfind("//") {
+            if let Some((path, reference)) = file[split..].rsplit_once('@') {
+                return Some(format!("{}{path}{suffix}@{reference}", &file[..split]));
+            }
+        }
+    }
+    Some(format!("{file}{suffix}"))
+}
+
+fn verify(policy: &Policy, document: &[u8], signature: &[u8]) -> Result<()> {
+    let dir = Scratch::new()?;
+    let data = dir.write("document", document)?;
+    let sig = dir.write("signature", signature)?;
+    let key = policy.key.display().to_string();
+    match policy.tool {
+        Tool::Minisign => {
+            pipe(
+                "minisign",
+                &["-V", "-q", "-p", &key, "-m", &data, "-x", &sig],
+                &[],
+            )?;
+        }
+        Tool::Cosign => {
+            pipe(
+                "cosign",
+                &["verify-blob", "--key", &key, "--signature", &sig, &data],
+                &[],
+            )?;
+        }
+        Tool::Gpg => {
+            let home = dir.path("gnupg");
+            private_dir(Path::new(&home))?;
+            pipe(
+                "gpg",
+                &["--batch", "--quiet", "--homedir", &home, "--import", &key],
+                &[],
+            )?;
+            let status = pipe(
+                "gpg",
+                &[
+                    "--batch",
+                    "--quiet",
+                    "--homedir",
+                    &home,
+                    "--status-fd",
+                    "1",
+                    "--verify",
+                    &sig,
+                    &data,
+                ],
+                &[],
+            )?;
+            // gpg also succeeds for expired and revoked keys; only GOODSIG
+            // is a good signature by a valid key.
+            if !String::from_utf8_lossy(&status)
+                .lines()
+                .any(|line| line.starts_with("[GNUPG:] GOODSIG "))
+            {
+                bail!("gpg did not report a good signature by a valid key");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A private temporary directory, removed when dropped. Its files are
+/// created new, so nothing planted in a shared temp dir is written through.
+pub(crate) struct Scratch(PathBuf);
+
+impl Scratch {
+    pub(crate) fn new() -> Result<Self> {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "confctl-verify-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        private_dir(&dir)?;
+        Ok(Scratch(dir))
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).display().to_string()
+    }
+
+    pub(crate) fn write(&self, name: &str, contents: &[u8]) -> Result<String> {
+        let path = self.path(name);
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(contents))
+            .with_context(|| format!("writing {path}"))?;
+        Ok(path)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A new directory only the owner can enter, as gpg wants its home.
+fn private_dir(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(dir)
+        .with_context(|| format!("creating {}", dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_key_picks_the_tool() {
+        let minisign = b"untrusted comment: minisign public key 1A2B\nRWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3\n";
+        assert_eq!(Tool::of_key(minisign), Some(Tool::Minisign));
+        let pem = b"-----BEGIN PUBLIC KEY-----\nMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE\n-----END PUBLIC KEY-----\n";
+        assert_eq!(Tool::of_key(pem), Some(Tool::Cosign));
+        let armored =
+            b"-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nmDMEZ\n-----END PGP PUBLIC KEY BLOCK-----\n";
+        assert_eq!(Tool::of_key(armored), Some(Tool::Gpg));
+        assert_eq!(Tool::of_key(&[0x98, 0x33, 0x04]), Some(Tool::Gpg));
+        assert_eq!(Tool::of_key(b"ssh-ed25519 AAAAC3Nza me@host"), None);
+        assert_eq!(Tool::of_key(b""), None);
+    }
+
+    #[test]
+    fn signatures_sit_next_to_the_input() {
+        assert_eq!(
+            sibling("prod/app.yaml", ".minisig").as_deref(),
+            Some("prod/app.yaml.minisig")
+        );
+        assert_eq!(
+            sibling("s3://configs/app.yaml", ".sig").as_deref(),
+            Some("s3://configs/app.yaml.sig")
+        );
+        assert_eq!(
+            sibling(
+                "git::https://github.com/org/infra//prod/app.yaml@v2",
+                ".asc"
+            )
+            .as_deref(),
+            Some("git::https://github.com/org/infra//prod/app.yaml.asc@v2")
+        );
+        assert_eq!(
+            sibling("git::../infra//app.yaml", ".sig").as_deref(),
+            Some("git::../infra//app.yaml.sig")
+        );
+        assert_eq!(
+            sibling("bundle.tar::app.yaml", ".sig").as_deref(),
+            Some("bundle.tar::app.yaml.sig")
+        );
+        assert_eq!(sibling("-", ".sig"), None);
+        assert_eq!(sibling("clip:", ".sig"), None);
+    }
+
+    #[test]
+    fn scratch_files_are_private_and_new() {
+        let scratch = Scratch::new().unwrap();
+        let path = scratch.write("doc.sig", b"sig").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"sig");
+        assert!(scratch.write("doc.sig", b"other").is_err());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&scratch.0).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        let dir = scratch.0.clone();
+        drop(scratch);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,211 @@
+//! `--as-bytes` and `--humanize-bytes`: rewrite sizes at PATH as a byte
+//! count or as `1.5Gi`, for auditing memory limits and disk quotas written
+//! as `10Mi` in one manifest and `10485760` in the next.
+//!
+//! Sizes are read with Kubernetes' suffixes (`Ki`, `Mi`, ... `Ei` in powers
+//! of 1024; `k`, `M`, ... `E` in powers of 1000), their SI and IEC
+//! spellings (`KB`, `MB`, `KiB`, `GiB`, any case) and `B`. A lone `m` is
+//! Kubernetes' milli, not mega, and is not a size.
+//!
+//! As with `--as-epoch`, an object or array has each string leaf that reads
+//! as a size rewritten, and bare numbers only count as bytes when they are
+//! PATH's value itself.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Conversion {
+    Bytes,
+    Human,
+}
+
+/// Rewrite the value at PATH (or every size under it).
+pub(crate) fn convert(value: &mut Value, conversion: Conversion) -> Result<()> {
+    match value {
+        Value::Object(_) | Value::Array(_) => {
+            convert_leaves(value, conversion);
+            Ok(())
+        }
+        scalar => match convert_scalar(scalar, conversion, true) {
+            Some(converted) => {
+                *scalar = converted;
+                Ok(())
+            }
+            None => bail!(
+                "`{}` is not a size: expected e.g. 512Mi, 2GB, 1.5GiB, 10k or a byte count",
+                crate::format_value_with(scalar, true)
+            ),
+        },
+    }
+}
+
+fn convert_leaves(value: &mut Value, conversion: Conversion) {
+    match value {
+        Value::Object(map) => map
+            .values_mut()
+            .for_each(|child| convert_leaves(child, conversion)),
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|child| convert_leaves(child, conversion)),
+        Value::String(_) => {
+            if let Some(converted) = convert_scalar(value, conversion, false) {
+                *value = converted;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The converted scalar, or `None` when it is not a size. Numbers, and
+/// strings that are just a number, count only when `numbers`.
+fn convert_scalar(value: &Value, conversion: Conversion, numbers: bool) -> Option<Value> {
+    let bytes = match value {
+        Value::Number(number) if numbers => number.as_f64()?,
+        Value::String(text) => match text.trim().parse::<f64>() {
+            Ok(bytes) if numbers => bytes,
+            Ok(_) => return None,
+            Err(_) => parse_size(text)?,
+        },
+        _ => return None,
+    };
+    if !bytes.is_finite() || bytes < 0.0 || bytes > u64::MAX as f64 {
+        return None;
+    }
+    Some(match conversion {
+        Conversion::Bytes => Value::from(bytes.round() as u64),
+        Conversion::Human => Value::String(humanize(bytes.round() as u64)),
+    })
+}
+
+/// Bytes in `text`: a number, optionally spaced from one of the suffixes
+/// the module comment lists.
+fn parse_size(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let unit_start = text
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let amount = text[..unit_start].trim_end().parse::<f64>().ok()?;
+    Some(amount * unit_bytes(&text[unit_start..])?)
+}
+
+fn unit_bytes(unit: &str) -> Option<f64> {
+    const PREFIXES: &str = "KMGTPE";
+    if unit.eq_ignore_ascii_case("b") || unit.eq_ignore_ascii_case("bytes") {
+        return Some(1.0);
+    }
+    let mut chars = unit.chars();
+    let prefix = chars.next()?;
+    let power = PREFIXES.find(prefix.to_ascii_uppercase())? as i32 + 1;
+    let suffix = chars.as_str();
+    // `m` alone is milli; `mb` and `mi` are unambiguous.
+    if prefix == 'm' && suffix.is_empty() {
+        return None;
+    }
+    match suffix.to_ascii_lowercase().as_str() {
+        "" | "b" => Some(1000f64.powi(power)),
+        "i" | "ib" => Some(1024f64.powi(power)),
+        _ => None,
+    }
+}
+
+/// `bytes` in the largest power-of-1024 unit it fills, with up to two
+/// decimals: `512B`, `1.5Ki`, `10Mi`, `1.86Gi`.
+fn humanize(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["Ki", "Mi", "Gi", "Ti", "Pi", "Ei"];
+    let mut unit = None;
+    let mut amount = bytes as f64;
+    for name in UNITS {
+        if amount < 1024.0 {
+            break;
+        }
+        amount /= 1024.0;
+        unit = Some(name);
+    }
+    match unit {
+        None => format!("{bytes}B"),
+        Some(unit) => {
+            let text = format!("{amount:.2}");
+            let text = text.trim_end_matches('0').trim_end_matches('.');
+            format!("{text}{unit}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn converted(value: Value, conversion: Conversion) -> Value {
+        let mut value = value;
+        convert(&mut value, conversion).unwrap();
+        value
+    }
+
+    #[test]
+    fn sizes_in_any_spelling_become_bytes() {
+        let cases = [
+            (json!("10Mi"), 10_485_760_u64),
+            (json!("10MiB"), 10_485_760),
+            (json!("10 mib"), 10_485_760),
+            (json!("2GB"), 2_000_000_000),
+            (json!("2G"), 2_000_000_000),
+            (json!("1.5Ki"), 1536),
+            (json!("128k"), 128_000),
+            (json!("512B"), 512),
+            (json!("1Ei"), 1_152_921_504_606_846_976),
+            (json!(4096), 4096),
+            (json!("4096"), 4096),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(
+                converted(value.clone(), Conversion::Bytes),
+                json!(bytes),
+                "{value}"
+            );
+        }
+        for text in ["500m", "10Xi", "Mi", "-1Ki", "ten"] {
+            assert!(
+                convert(&mut json!(text), Conversion::Bytes).is_err(),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn byte_counts_are_humanized() {
+        let cases = [
+            (json!(512), "512B"),
+            (json!(1024), "1Ki"),
+            (json!(1536), "1.5Ki"),
+            (json!("10485760"), "10Mi"),
+            (json!("2GB"), "1.86Gi"),
+            (json!("1024Mi"), "1Gi"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                converted(value.clone(), Conversion::Human),
+                json!(expected),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn subtrees_convert_only_the_strings_that_parse() {
+        let limits = json!({
+            "limits": {"cpu": "500m", "memory": "1Gi"},
+            "requests": {"cpu": "2", "memory": "512Mi"},
+            "replicas": 3
+        });
+        assert_eq!(
+            converted(limits, Conversion::Bytes),
+            json!({
+                "limits": {"cpu": "500m", "memory": 1_073_741_824},
+                "requests": {"cpu": "2", "memory": 536_870_912},
+                "replicas": 3
+            })
+        );
+    }
+}
@@ -0,0 +1,433 @@
+//! `confctl snapshot` — capture every declared source (local files, URLs,
+//! vault secrets) into one timestamped bundle, and `--from-snapshot` to
+//! query that bundle later without network access.
+//!
+//! A bundle is a tar archive holding `snapshot.json` (metadata plus every
+//! source as a parsed, redacted document) and, when signed, the SSH
+//! signature `snapshot.json.sig`. As with age, nothing is linked in:
+//! compression pipes through `zstd`/`gzip` and signatures through
+//! `ssh-keygen -Y`, so the bundle can also be checked by hand.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::archive::tar;
+use crate::atomic;
+use crate::compress::{pipe, Compression};
+use crate::formats::ParseOptions;
+use crate::input;
+use crate::signature::Scratch;
+use crate::{redact_with, Format, RedactOptions};
+
+const DOCUMENT: &str = "snapshot.json";
+const SIGNATURE: &str = "snapshot.json.sig";
+/// `ssh-keygen -Y` namespace, so a snapshot signature can't be replayed as
+/// a signature over something else (a git commit, a file).
+const NAMESPACE: &str = "confctl-snapshot";
+
+#[derive(Args, Debug)]
+pub(crate) struct SnapshotCli {
+    /// YAML file listing the sources to capture (see README).
+    #[arg(long, value_name = "FILE")]
+    pub(crate) sources: PathBuf,
+
+    /// Bundle to write: `.tar.zst`, `.tar.gz` or `.tar`.
+    #[arg(long, value_name = "BUNDLE")]
+    pub(crate) out: PathBuf,
+
+    /// SSH private key to sign the bundle with (`ssh-keygen -Y sign`).
+    #[arg(long, value_name = "KEY")]
+    pub(crate) sign_key: Option<PathBuf>,
+
+    /// Overwrite `--out` if it already exists.
+    #[arg(long)]
+    pub(crate) force: bool,
+}
+
+/// The `--sources` file.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SourcesFile {
+    #[serde(default)]
+    redact: RedactRules,
+    sources: Vec<SourceSpec>,
+}
+
+/// Masking applied to every captured document: the built-in `--redact`
+/// rules always, plus these key patterns.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RedactRules {
+    #[serde(default)]
+    patterns: Vec<String>,
+    mask: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SourceSpec {
+    name: String,
+    file: Option<PathBuf>,
+    url: Option<String>,
+    /// Secret name for `vault pull`.
+    vault: Option<String>,
+    /// `--backend` name for the vault source.
+    backend: Option<String>,
+    /// Input format override, as in `--format`.
+    format: Option<String>,
+}
+
+/// What `snapshot.json` holds.
+#[derive(Serialize, Deserialize, Debug)]
+struct Snapshot {
+    created_at: String,
+    confctl_version: String,
+    sources: Vec<Captured>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Captured {
+    name: String,
+    kind: String,
+    origin: String,
+    document: Value,
+}
+
+enum Origin {
+    File(PathBuf),
+    Url(String),
+    Vault(String, Option<String>),
+}
+
+impl SourceSpec {
+    fn origin(&self, base: &Path) -> Result<Origin> {
+        let origin = match (&self.file, &self.url, &self.vault) {
+            (Some(file), None, None) => Origin::File(base.join(file)),
+            (None, Some(url), None) => Origin::Url(url.clone()),
+            (None, None, Some(secret)) => Origin::Vault(secret.clone(), self.backend.clone()),
+            _ => bail!(
+                "source `{}` needs exactly one of file, url or vault",
+                self.name
+            ),
+        };
+        if self.backend.is_some() && !matches!(origin, Origin::Vault(..)) {
+            bail!(
+                "source `{}`: backend only applies to vault sources",
+                self.name
+            );
+        }
+        Ok(origin)
+    }
+
+    fn format(&self) -> Result<Option<Format>> {
+        self.format
+            .as_deref()
+            .map(|f| {
+                Format::from_str(f, true)
+                    .map_err(|_| anyhow!("source `{}`: unknown format `{f}`", self.name))
+            })
+            .transpose()
+    }
+}
+
+pub(crate) fn run(cli: SnapshotCli) -> Result<()> {
+    let compression = Compression::for_path(&cli.out)?;
+    if cli.out.exists() && !cli.force {
+        bail!(
+            "{} already exists; pass --force to overwrite",
+            cli.out.display()
+        );
+    }
+    let text = fs::read_to_string(&cli.sources)
+        .with_context(|| format!("reading {}", cli.sources.display()))?;
+    let spec: SourcesFile = serde_yaml::from_str(&text)
+        .with_context(|| format!("parsing {}", cli.sources.display()))?;
+    check_names(&spec.sources)?;
+
+    let redact = RedactOptions {
+        extra_patterns: spec.redact.patterns,
+        mask: spec
+            .redact
+            .mask
+            .unwrap_or_else(|| RedactOptions::default().mask),
+    };
+    let base = cli.sources.parent().unwrap_or(Path::new("."));
+    let created = Utc::now();
+    let mut captured = Vec::with_capacity(spec.sources.len());
+    for source in &spec.sources {
+        let (kind, origin, document) =
+            fetch(source, base).with_context(|| format!("source `{}`", source.name))?;
+        println!("{} {} ({kind} {origin})", "✓".green().bold(), source.name);
+        captured.push(Captured {
+            name: source.name.clone(),
+            kind: kind.to_string(),
+            origin,
+            document: redact_with(&document, &redact),
+        });
+    }
+
+    let snapshot = Snapshot {
+        created_at: created.to_rfc3339(),
+        confctl_version: env!("CARGO_PKG_VERSION").to_string(),
+        sources: captured,
+    };
+    let document = serde_json::to_vec_pretty(&snapshot)?;
+    let mut entries: Vec<(&str, &[u8])> = vec![(DOCUMENT, &document)];
+    let signature = match &cli.sign_key {
+        Some(key) => Some(sign(&document, key)?),
+        None => None,
+    };
+    if let Some(signature) = &signature {
+        entries.push((SIGNATURE, signature));
+    }
+    let archive = tar::write(&entries, created.timestamp())?;
+    let bytes = compression.compress(&archive)?;
+    atomic::write(&cli.out, bytes, None)?;
+    println!(
+        "{} wrote {} ({} source{}, {})",
+        "✓".green().bold(),
+        cli.out.display(),
+        snapshot.sources.len(),
+        if snapshot.sources.len() == 1 { "" } else { "s" },
+        if signature.is_some() {
+            "signed"
+        } else {
+            "unsigned"
+        }
+    );
+    Ok(())
+}
+
+fn check_names(sources: &[SourceSpec]) -> Result<()> {
+    if sources.is_empty() {
+        bail!("no sources declared");
+    }
+    for (i, source) in sources.iter().enumerate() {
+        if source.name.trim().is_empty() {
+            bail!("source {i} has an empty name");
+        }
+        if sources[..i].iter().any(|s| s.name == source.name) {
+            bail!("source name `{}` is declared twice", source.name);
+        }
+    }
+    Ok(())
+}
+
+/// Kind, printable origin and parsed document of one source.
+fn fetch(source: &SourceSpec, base: &Path) -> Result<(&'static str, String, Value)> {
+    let format = source.format()?;
+    let (kind, origin, input) = match source.origin(base)? {
+        Origin::File(path) => {
+            let shown = path.display().to_string();
+            let input = input::open(&shown);
+            ("file", shown, input)
+        }
+        Origin::Url(url) => {
+            let input = input::http(&url)?;
+            ("url", url, input)
+        }
+        Origin::Vault(secret, backend) => {
+            let input = input::vault(&secret, backend.as_deref())?;
+            ("vault", secret, input)
+        }
+    };
+    let value = input.load(format, &ParseOptions::default())?;
+    Ok((kind, origin, value))
+}
+
+/// `--from-snapshot`: the named source's document, or with an empty name
+/// the bundle's metadata (when it was taken, what it holds). With
+/// `signers`, the signature must verify against that allowed-signers file.
+pub(crate) fn load(bundle: &Path, name: &str, signers: Option<&Path>) -> Result<Value> {
+    let raw = fs::read(bundle).with_context(|| format!("reading {}", bundle.display()))?;
+    let archive = Compression::sniff(&raw)
+        .decompress(&raw)
+        .with_context(|| format!("decompressing {}", bundle.display()))?;
+    let entries = tar::read(&archive).with_context(|| format!("reading {}", bundle.display()))?;
+    let find = |wanted: &str| {
+        entries
+            .iter()
+            .find(|(name, _)| name == wanted)
+            .map(|(_, data)| data.as_slice())
+    };
+    let document =
+        find(DOCUMENT).ok_or_else(|| anyhow!("{} has no {DOCUMENT}", bundle.display()))?;
+    let signature = find(SIGNATURE);
+    if let Some(signers) = signers {
+        let Some(signature) = signature else {
+            bail!("{} is not signed", bundle.display());
+        };
+        verify(document, signature, signers)
+            .with_context(|| format!("verifying {}", bundle.display()))?;
+    }
+    let mut snapshot: Snapshot = serde_json::from_slice(document)
+        .with_context(|| format!("parsing {DOCUMENT} in {}", bundle.display()))?;
+
+    if name.is_empty() {
+        return Ok(metadata(&snapshot, signature.is_some()));
+    }
+    match snapshot.sources.iter().position(|s| s.name == name) {
+        Some(i) => Ok(snapshot.sources.swap_remove(i).document),
+        None => {
+            let names: Vec<&str> = snapshot.sources.iter().map(|s| s.name.as_str()).collect();
+            bail!(
+                "{} has no source `{name}` (available: {})",
+                bundle.display(),
+                names.join(", ")
+            )
+        }
+    }
+}
+
+fn metadata(snapshot: &Snapshot, signed: bool) -> Value {
+    let sources: Vec<Value> = snapshot
+        .sources
+        .iter()
+        .map(|s| serde_json::json!({"name": s.name, "kind": s.kind, "origin": s.origin}))
+        .collect();
+    serde_json::json!({
+        "created_at": snapshot.created_at,
+        "confctl_version": snapshot.confctl_version,
+        "signed": signed,
+        "sources": sources,
+    })
+}
+
+impl Compression {
+    fn for_path(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Ok(Compression::Zstd)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Ok(Compression::Gzip)
+        } else if name.ends_with(".tar") {
+            Ok(Compression::None)
+        } else {
+            bail!(
+                "cannot tell the bundle format of {}; use .tar.zst, .tar.gz or .tar",
+                path.display()
+            )
+        }
+    }
+}
+
+fn sign(document: &[u8], key: &Path) -> Result<Vec<u8>> {
+    if !key.is_file() {
+        bail!("signing key not found: {}", key.display());
+    }
+    let key = key.display().to_string();
+    pipe(
+        "ssh-keygen",
+        &["-q", "-Y", "sign", "-n", NAMESPACE, "-f", &key],
+        document,
+    )
+}
+
+/// `ssh-keygen -Y find-principals` picks the signer out of the allowed
+/// signers file, then `-Y verify` checks the document against it.
+fn verify(document: &[u8], signature: &[u8], signers: &Path) -> Result<()> {
+    if !signers.is_file() {
+        bail!("allowed signers file not found: {}", signers.display());
+    }
+    let scratch = Scratch::new()?;
+    let sig = scratch.write("snapshot.sig", signature)?;
+    let signers = signers.display().to_string();
+    let found = pipe(
+        "ssh-keygen",
+        &["-Y", "find-principals", "-s", &sig, "-f", &signers],
+        &[],
+    )
+    .context("the signing key is not in the allowed signers file")?;
+    let found = String::from_utf8_lossy(&found);
+    let principal = found
+        .lines()
+        .next()
+        .ok_or_else(|| anyhow!("the signing key is not in the allowed signers file"))?;
+    pipe(
+        "ssh-keygen",
+        &[
+            "-Y", "verify", "-n", NAMESPACE, "-s", &sig, "-f", &signers, "-I", principal,
+        ],
+        document,
+    )
+    .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_need_one_origin_and_unique_names() {
+        let spec: SourcesFile = serde_yaml::from_str(
+            "sources:\n  - {name: app, file: app.yaml}\n  - {name: app, url: https://x/c.json}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            check_names(&spec.sources).unwrap_err().to_string(),
+            "source name `app` is declared twice"
+        );
+        let both: SourceSpec =
+            serde_yaml::from_str("{name: db, file: a.env, vault: db.env}").unwrap();
+        assert_eq!(
+            both.origin(Path::new(".")).err().unwrap().to_string(),
+            "source `db` needs exactly one of file, url or vault"
+        );
+        let stray: SourceSpec =
+            serde_yaml::from_str("{name: db, file: a.env, backend: hcp}").unwrap();
+        assert!(stray.origin(Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn compression_follows_the_extension_and_the_magic() {
+        let pick = |name: &str| Compression::for_path(Path::new(name)).ok();
+        assert_eq!(pick("audit.tar.zst"), Some(Compression::Zstd));
+        assert_eq!(pick("audit.TGZ"), Some(Compression::Gzip));
+        assert_eq!(pick("audit.tar"), Some(Compression::None));
+        assert_eq!(pick("audit.zip"), None);
+        assert_eq!(Compression::sniff(&[0x1f, 0x8b, 8]), Compression::Gzip);
+        assert_eq!(Compression::sniff(b"snapshot.json"), Compression::None);
+    }
+
+    #[test]
+    fn load_picks_a_source_or_the_metadata() {
+        let snapshot = Snapshot {
+            created_at: "2026-10-15T09:00:00+00:00".to_string(),
+            confctl_version: "0.0.0".to_string(),
+            sources: vec![Captured {
+                name: "app".to_string(),
+                kind: "file".to_string(),
+                origin: "app.yaml".to_string(),
+                document: serde_json::json!({"db": {"host": "pg"}}),
+            }],
+        };
+        let document = serde_json::to_vec(&snapshot).unwrap();
+        let archive = tar::write(&[(DOCUMENT, &document)], 0).unwrap();
+        let path = std::env::temp_dir().join(format!("confctl-load-{}.tar", std::process::id()));
+        fs::write(&path, archive).unwrap();
+
+        assert_eq!(load(&path, "app", None).unwrap()["db"]["host"], "pg");
+        let meta = load(&path, "", None).unwrap();
+        assert_eq!(meta["signed"], false);
+        assert_eq!(meta["sources"][0]["origin"], "app.yaml");
+        let err = load(&path, "db", None).unwrap_err().to_string();
+        assert!(
+            err.ends_with("has no source `db` (available: app)"),
+            "{err}"
+        );
+        let err = load(&path, "app", Some(Path::new("allowed_signers"))).unwrap_err();
+        assert!(err.to_string().ends_with("is not signed"));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+//! `--sort-by KEY` / `--reverse`: order the resolved array by a dotted path
+//! inside each item before printing, so unordered API responses read (and
+//! diff) the same every time.
+//!
+//! Numbers, and strings that parse as numbers (ENV and CSV values are
+//! always strings), compare numerically; other strings lexically; mixed
+//! types by kind (null, boolean, number, string, array, object). Items
+//! without KEY go last either way, and the sort is stable, so ties keep
+//! their order.
+
+use std::cmp::Ordering;
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+use crate::aggregate::type_name;
+use crate::{resolve_path, resolve_path_mut};
+
+/// Sort the array at `path` (the whole document when `None`) in place.
+pub(crate) fn apply(value: &mut Value, path: Option<&str>, key: &str, reverse: bool) -> Result<()> {
+    let target = match path {
+        Some(path) => resolve_path_mut(value, path)?,
+        None => value,
+    };
+    let Value::Array(items) = target else {
+        bail!("--sort-by needs an array, got {}", type_name(target));
+    };
+    items.sort_by(
+        |a, b| match (resolve_path(a, key).ok(), resolve_path(b, key).ok()) {
+            (Some(a), Some(b)) if reverse => compare(b, a),
+            (Some(a), Some(b)) => compare(a, b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
+    );
+    Ok(())
+}
+
+fn compare(a: &Value, b: &Value) -> Ordering {
+    if let (Some(x), Some(y)) = (numeric(a), numeric(b)) {
+        return x.total_cmp(&y);
+    }
+    match (a, b) {
+        (Value::String(x), Value::String(y)) => x.cmp(y),
+        _ => rank(a)
+            .cmp(&rank(b))
+            .then_with(|| a.to_string().cmp(&b.to_string())),
+    }
+}
+
+fn numeric(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok().filter(|f| f.is_finite()),
+        _ => None,
+    }
+}
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn names(value: &Value) -> Vec<&str> {
+        value
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["name"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_by_sub_path_with_missing_last() {
+        let mut value = json!({"items": [
+            {"name": "c", "meta": {"port": "10"}},
+            {"name": "a", "meta": {"port": 9}},
+            {"name": "x"},
+            {"name": "b", "meta": {"port": 80}},
+            {"name": "d", "meta": {"port": 9}}
+        ]});
+        apply(&mut value, Some("items"), "meta.port", false).unwrap();
+        assert_eq!(names(&value["items"]), ["a", "d", "c", "b", "x"]);
+        apply(&mut value, Some("items"), "meta.port", true).unwrap();
+        assert_eq!(names(&value["items"]), ["b", "c", "a", "d", "x"]);
+        apply(&mut value, Some("items"), "name", false).unwrap();
+        assert_eq!(names(&value["items"]), ["a", "b", "c", "d", "x"]);
+    }
+
+    #[test]
+    fn needs_an_array() {
+        let mut value = json!({"items": {"a": 1}});
+        let err = apply(&mut value, Some("items"), "name", false).unwrap_err();
+        assert_eq!(err.to_string(), "--sort-by needs an array, got an object");
+        let err = apply(&mut value, Some("nope"), "name", false).unwrap_err();
+        assert_eq!(err.to_string(), "Key not found: 'nope' (at path 'nope')");
+    }
+}
@@ -0,0 +1,187 @@
+//! `confctl split` — the inverse of merging: break a monolithic config into
+//! one file per matched subtree.
+//!
+//! `--by services.*` selects every child of `services`; each match is written
+//! to `<out-dir>/<key>.<ext>`, where `<key>` is built from the segments the
+//! `*` wildcards matched (joined with `-` when there are several).
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use colored::Colorize;
+
+use crate::atomic::{self, BackupArg};
+use crate::emit::{self, OutputFormat};
+use crate::{detect_format, parse_content, path, select_paths, Format};
+
+#[derive(Args, Debug)]
+pub(crate) struct SplitCli {
+    /// Config file to split.
+    pub(crate) file: String,
+
+    /// Dotted pattern selecting the subtrees; `*` matches any key or index.
+    #[arg(long)]
+    pub(crate) by: String,
+
+    /// Directory the pieces are written to. Created if missing.
+    #[arg(long, default_value = ".")]
+    pub(crate) out_dir: PathBuf,
+
+    /// Input format override, as in the top-level `--format`.
+    #[arg(long, value_enum)]
+    pub(crate) format: Option<Format>,
+
+    /// Format of the written pieces. Defaults to the input's format.
+    #[arg(long, value_enum)]
+    pub(crate) to: Option<OutputFormat>,
+
+    /// Overwrite pieces that already exist; --backup implies it.
+    #[arg(long)]
+    pub(crate) force: bool,
+
+    #[command(flatten)]
+    pub(crate) backup: BackupArg,
+}
+
+pub(crate) fn run(cli: SplitCli) -> Result<()> {
+    let content = fs::read_to_string(&cli.file)
+        .with_context(|| format!("Failed to read file: {}", cli.file))?;
+    let input_format = detect_format(&cli.file, &content, cli.format)?;
+    let value = parse_content(&cli.file, &content, Some(input_format))?;
+    let output_format = match cli.to.or_else(|| OutputFormat::from_input(input_format)) {
+        Some(format) => format,
+        None => bail!(
+            "{} input cannot be written back; pass --to",
+            input_format.extension()
+        ),
+    };
+
+    let matches = select_paths(&value, &cli.by)?;
+    if matches.is_empty() {
+        bail!("no subtree matches '{}' in {}", cli.by, cli.file);
+    }
+
+    let mut pieces = Vec::new();
+    for (segments, subtree) in &matches {
+        let name = piece_name(&cli.by, segments);
+        let path = cli
+            .out_dir
+            .join(format!("{name}.{}", output_format.extension()));
+        if pieces.iter().any(|(p, _)| p == &path) {
+            bail!("two subtrees map to the same file {}", path.display());
+        }
+        let body = emit::render(subtree, output_format)
+            .with_context(|| format!("rendering '{}'", path::join(segments)))?;
+        pieces.push((path, body));
+    }
+
+    if !atomic::previewing() {
+        fs::create_dir_all(&cli.out_dir)
+            .with_context(|| format!("creating {}", cli.out_dir.display()))?;
+    }
+    for (path, body) in &pieces {
+        write_piece(path, body, cli.force, cli.backup.suffix())?;
+        if !atomic::previewing() {
+            println!("{} wrote {}", "✓".green().bold(), path.display());
+        }
+    }
+    Ok(())
+}
+
+/// File stem for one match: the segments captured by `*` wildcards, or the
+/// last segment when the pattern has no wildcard.
+pub(crate) fn piece_name(pattern: &str, segments: &[String]) -> String {
+    // select_paths has already rejected a malformed pattern.
+    let pattern = path::segments(pattern).unwrap_or_default();
+    let captured: Vec<&str> = pattern
+        .iter()
+        .zip(segments)
+        .filter(|(pat, _)| *pat == "*")
+        .map(|(_, seg)| seg.as_str())
+        .collect();
+    let raw = if captured.is_empty() {
+        segments
+            .last()
+            .map(String::as_str)
+            .unwrap_or("root")
+            .to_string()
+    } else {
+        captured.join("-")
+    };
+    sanitize_file_stem(&raw)
+}
+
+/// Keep names portable: anything outside `[A-Za-z0-9._-]` becomes `_`, and
+/// a leading dot is dropped so pieces never end up as hidden files.
+fn sanitize_file_stem(raw: &str) -> String {
+    let cleaned: String = raw
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let trimmed = cleaned.trim_start_matches('.');
+    if trimmed.is_empty() {
+        "_".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+fn write_piece(path: &Path, body: &str, force: bool, backup: Option<&str>) -> Result<()> {
+    if path.exists() && !force && backup.is_none() {
+        bail!(
+            "{} already exists; pass --force to overwrite",
+            path.display()
+        );
+    }
+    atomic::write(path, body, backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn piece_name_uses_wildcard_captures() {
+        let segs = vec!["services".to_string(), "api".to_string()];
+        assert_eq!(piece_name("services.*", &segs), "api");
+
+        let segs = vec![
+            "envs".to_string(),
+            "prod".to_string(),
+            "apps".to_string(),
+            "web".to_string(),
+        ];
+        assert_eq!(piece_name("envs.*.apps.*", &segs), "prod-web");
+    }
+
+    #[test]
+    fn piece_name_without_wildcard_uses_last_segment() {
+        let segs = vec!["services".to_string(), "api".to_string()];
+        assert_eq!(piece_name("services.api", &segs), "api");
+    }
+
+    #[test]
+    fn piece_name_is_filesystem_safe() {
+        let segs = vec!["labels".to_string(), "app.kubernetes.io/name".to_string()];
+        assert_eq!(piece_name("labels.*", &segs), "app.kubernetes.io_name");
+        let segs = vec![".hidden".to_string()];
+        assert_eq!(piece_name("*", &segs), "hidden");
+    }
+
+    #[test]
+    fn select_paths_matches_every_child() {
+        let value = json!({"services": {"api": {"port": 1}, "web": {"port": 2}}});
+        let matches = select_paths(&value, "services[*]").unwrap();
+        let names: Vec<String> = matches.iter().map(|(s, _)| s.join(".")).collect();
+        assert_eq!(names, vec!["services.api", "services.web"]);
+    }
+}
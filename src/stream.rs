@@ -0,0 +1,296 @@
+//! `--stream`: one path out of a JSON file too large to parse whole.
+//!
+//! Parsing a multi-gigabyte dump into a `Value` takes several times its size
+//! in memory. [`extract`] instead drives serde_json's reader with a seed
+//! that skips every entry off the path (`IgnoredAny`) and builds only the
+//! value the path names, then stops reading. Memory is bounded by that
+//! value, not the file. The cost: the file after the value is not read, so
+//! a syntax error there goes unnoticed, and a duplicated key resolves to
+//! its first occurrence rather than the last.
+//!
+//! It is used for `--stream`, and without it for `.json` files over
+//! [`THRESHOLD`] when the query names a path and no flag needs the whole
+//! document.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::errors::{json_position, ParseFailure, PathError};
+use crate::{path, Format};
+
+/// Files at least this large are streamed without `--stream`.
+pub(crate) const THRESHOLD: u64 = 256 * 1024 * 1024;
+
+/// The segments of `path` when `file` should be streamed: always with
+/// `--stream` (`forced`), which then insists on a local JSON file and a
+/// path, otherwise only for a large `.json` file.
+pub(crate) fn plan(
+    file: &str,
+    path: Option<&str>,
+    format: Option<Format>,
+    forced: bool,
+) -> Result<Option<Vec<String>>> {
+    let local = file != "-" && Path::new(file).is_file();
+    let extension = Path::new(file)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    let json = match format {
+        Some(format) => format == Format::Json,
+        None => extension.as_deref() == Some("json") || (forced && extension.is_none()),
+    };
+    if !forced {
+        let large = local && json && std::fs::metadata(file).is_ok_and(|m| m.len() >= THRESHOLD);
+        return match path {
+            Some(path) if large => Ok(Some(path::segments(path)?)),
+            _ => Ok(None),
+        };
+    }
+    if !local {
+        bail!("--stream reads a local JSON file, not {file}");
+    }
+    if !json {
+        bail!("--stream works on JSON files; pass --format json if {file} is one");
+    }
+    let Some(path) = path else {
+        bail!("--stream needs a PATH to extract");
+    };
+    Ok(Some(path::segments(path)?))
+}
+
+/// [`extract`] over `file`, with syntax errors reported as a failed parse.
+pub(crate) fn load(file: &str, segments: &[String]) -> Result<Value> {
+    let handle = File::open(file).with_context(|| format!("Failed to read file: {file}"))?;
+    extract(BufReader::new(handle), segments).map_err(|err| {
+        match err.downcast::<serde_json::Error>() {
+            Ok(err) => {
+                let failure = ParseFailure {
+                    file: file.to_string(),
+                    format: Format::Json,
+                    position: json_position(&err),
+                    source_line: None,
+                };
+                anyhow::Error::new(err).context(failure)
+            }
+            Err(err) => err,
+        }
+    })
+}
+
+/// The document in the JSON `reader` cut down to the path `segments`: the
+/// value there, inside the objects and arrays above it (earlier array items
+/// are nulls), so the rest of the query resolves the path as usual. Reads
+/// no further than the end of the value. A path that names nothing fails
+/// with the `PathError` `resolve_path` would give.
+pub(crate) fn extract<R: Read>(reader: R, segments: &[String]) -> Result<Value> {
+    let mut found = Found::default();
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let result = Seek {
+        segments,
+        depth: 0,
+        found: &mut found,
+    }
+    .deserialize(&mut deserializer);
+    let value = match (found.outcome, result) {
+        (Some(outcome), _) => outcome?,
+        (None, Err(err)) => return Err(err.into()),
+        (None, Ok(())) => unreachable!("Seek records an outcome before returning"),
+    };
+    let containers = segments.iter().zip(found.in_array).rev();
+    Ok(containers.fold(value, |value, (segment, in_array)| {
+        if in_array {
+            let index = segment
+                .parse()
+                .expect("an index, or visit_seq would have failed");
+            let mut items = vec![Value::Null; index];
+            items.push(value);
+            Value::Array(items)
+        } else {
+            Value::Object(Map::from_iter([(segment.clone(), value)]))
+        }
+    }))
+}
+
+/// Message of the error that unwinds the parser once the outcome is known.
+const DONE: &str = "stream: done";
+
+#[derive(Default)]
+struct Found {
+    outcome: Option<Result<Value, PathError>>,
+    /// For each segment passed, whether it indexed an array.
+    in_array: Vec<bool>,
+}
+
+struct Seek<'a> {
+    segments: &'a [String],
+    depth: usize,
+    found: &'a mut Found,
+}
+
+impl Seek<'_> {
+    fn finish<E: de::Error>(self, outcome: Result<Value, PathError>) -> Result<(), E> {
+        self.found.outcome = Some(outcome);
+        Err(E::custom(DONE))
+    }
+
+    fn segment(&self) -> &str {
+        &self.segments[self.depth]
+    }
+
+    fn path_so_far(&self) -> String {
+        path::join(&self.segments[..=self.depth])
+    }
+
+    fn child(&mut self) -> Seek<'_> {
+        Seek {
+            segments: self.segments,
+            depth: self.depth + 1,
+            found: self.found,
+        }
+    }
+
+    fn scalar<E: de::Error>(self) -> Result<(), E> {
+        let err = PathError::Scalar {
+            segment: self.segment().to_string(),
+            path: self.path_so_far(),
+            parent: path::join(&self.segments[..self.depth]),
+        };
+        self.finish(Err(err))
+    }
+
+    fn out_of_bounds<E: de::Error>(self, index: usize, len: usize) -> Result<(), E> {
+        let err = PathError::OutOfBounds {
+            segment: self.segment().to_string(),
+            path: self.path_so_far(),
+            index,
+            len,
+        };
+        self.finish(Err(err))
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Seek<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        if self.depth == self.segments.len() {
+            let value = Value::deserialize(deserializer)?;
+            return self.finish(Ok(value));
+        }
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Seek<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == self.segment() {
+                self.found.in_array.push(false);
+                return map.next_value_seed(self.child());
+            }
+            map.next_value::<IgnoredAny>()?;
+        }
+        let err = PathError::KeyNotFound {
+            segment: self.segment().to_string(),
+            path: self.path_so_far(),
+        };
+        self.finish(Err(err))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let Ok(index) = self.segment().parse::<usize>() else {
+            let err = PathError::NotAnIndex {
+                segment: self.segment().to_string(),
+                path: self.path_so_far(),
+            };
+            return self.finish(Err(err));
+        };
+        for skipped in 0..index {
+            if seq.next_element::<IgnoredAny>()?.is_none() {
+                return self.out_of_bounds(index, skipped);
+            }
+        }
+        self.found.in_array.push(true);
+        match seq.next_element_seed(self.child())? {
+            Some(()) => Ok(()),
+            None => self.out_of_bounds(index, index),
+        }
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        self.scalar()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.scalar()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolve_path;
+    use serde_json::json;
+
+    fn segments(path: &str) -> Vec<String> {
+        path::segments(path).unwrap()
+    }
+
+    #[test]
+    fn extracts_the_path_and_nothing_else() {
+        let doc = br#"{"big": [1, 2, {"x": "skip"}], "data": {"items": [{"id": 1}, {"id": 2, "tags": ["a"]}]}}"#;
+        let value = extract(&doc[..], &segments("data.items.1")).unwrap();
+        assert_eq!(
+            value,
+            json!({"data": {"items": [null, {"id": 2, "tags": ["a"]}]}})
+        );
+        assert_eq!(resolve_path(&value, "data.items.1.id").unwrap(), &json!(2));
+
+        // Reading stops at the value: what follows is never parsed.
+        let value = extract(&br#"{"a": 1, "b": {"c": true}, "#[..], &segments("b.c")).unwrap();
+        assert_eq!(value, json!({"b": {"c": true}}));
+    }
+
+    #[test]
+    fn missing_paths_fail_like_resolve_path() {
+        let doc = br#"{"a": {"list": [10, 20]}, "s": "text"}"#;
+        let parsed: Value = serde_json::from_slice(doc).unwrap();
+        for path in ["a.missing", "a.list.5", "a.list.x", "s.deeper"] {
+            let streamed = extract(&doc[..], &segments(path)).unwrap_err();
+            let resolved = resolve_path(&parsed, path).unwrap_err();
+            assert_eq!(streamed.to_string(), resolved.to_string(), "{path}");
+            assert!(streamed.downcast_ref::<PathError>().is_some());
+        }
+
+        let err = extract(&br#"{"a": [1,, 2]}"#[..], &segments("b")).unwrap_err();
+        assert!(err.downcast_ref::<serde_json::Error>().is_some(), "{err}");
+    }
+}
@@ -0,0 +1,108 @@
+//! `--template TEXT`: interpolate several values into one line, like
+//! kubectl's jsonpath output, instead of one invocation per value:
+//! `confctl app.yaml --template 'host={.db.host} port={.db.port}'`.
+//!
+//! `{.a.b}` is a path from the queried value (the document, or PATH's value
+//! when one is given) and `{.}` the value itself. Strings print raw, arrays
+//! and objects as compact JSON, and a path with `*` prints its matches
+//! separated by spaces. `{{` and `}}` are literal braces; `\n` and `\t` are
+//! a newline and a tab. A missing path is an error, as in a query.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+
+use crate::{format_value_with, path, resolve_path, select_paths};
+
+/// `template` with each `{.path}` replaced by the value at that path under
+/// `value`.
+pub(crate) fn render(template: &str, value: &Value) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => out.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => out.push('}'),
+            '{' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => field.push(c),
+                        None => bail!("--template: `{{{field}` is not closed with `}}`"),
+                    }
+                }
+                out.push_str(&field_text(field.trim(), value)?);
+            }
+            '}' => bail!("--template: unmatched `}}` (write `}}}}` for a literal brace)"),
+            '\\' => match chars.next_if(|c| matches!(c, 'n' | 't' | '\\')) {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                _ => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// The text of one `{.path}` field.
+fn field_text(field: &str, value: &Value) -> Result<String> {
+    let Some(field_path) = field.strip_prefix('.') else {
+        bail!("--template: `{{{field}}}` should be a path starting with `.`, e.g. {{.db.host}}");
+    };
+    if field_path.is_empty() {
+        return Ok(format_value_with(value, true));
+    }
+    let segments = path::segments(field_path)?;
+    if !segments.iter().any(|segment| segment == "*") {
+        return Ok(format_value_with(resolve_path(value, field_path)?, true));
+    }
+    let matches = select_paths(value, field_path)?;
+    if matches.is_empty() {
+        bail!("--template: nothing matches `{{{field}}}`");
+    }
+    Ok(matches
+        .into_iter()
+        .map(|(_, found)| format_value_with(found, true))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> Value {
+        json!({
+            "db": {"host": "db.local", "port": 5432, "tls": null},
+            "hosts": [{"name": "a"}, {"name": "b"}],
+            "tags": ["x", "y"]
+        })
+    }
+
+    #[test]
+    fn fields_are_interpolated() {
+        let config = config();
+        let out = |template: &str| render(template, &config).unwrap();
+        assert_eq!(
+            out("host={.db.host} port={ .db.port }"),
+            "host=db.local port=5432"
+        );
+        assert_eq!(out("{.tags}|{.tags[1]}|{.db.tls}"), "[\"x\",\"y\"]|y|null");
+        assert_eq!(out("{.hosts.*.name}"), "a b");
+        assert_eq!(out("{{{.db.port}}}\\t\\n"), "{5432}\t\n");
+        assert_eq!(render("{.}", &json!("plain")).unwrap(), "plain");
+    }
+
+    #[test]
+    fn bad_templates_and_missing_paths_fail() {
+        let config = config();
+        let err = |template: &str| render(template, &config).unwrap_err().to_string();
+        assert!(err("{.db.user}").contains("Key not found: 'user'"));
+        assert!(err("{db.host}").contains("starting with `.`"));
+        assert!(err("host={.db.host").contains("not closed"));
+        assert!(err("a}b").contains("unmatched"));
+        assert!(err("{.hosts.*.id}").contains("nothing matches"));
+    }
+}
@@ -40,6 +40,57 @@ fn test_resolve_scalar_traversal() {
     assert!(result.unwrap_err().to_string().contains("scalar value"));
 }
 
+#[test]
+fn test_resolve_quoted_escaped_and_bracketed_keys() {
+    let data = json!({"metadata": {"labels": {"app.kubernetes.io/name": "api", "a": {"b": 1}}}});
+    let expected = json!("api");
+    assert_eq!(
+        resolve_path(&data, "metadata.labels.\"app.kubernetes.io/name\"").unwrap(),
+        &expected
+    );
+    assert_eq!(
+        resolve_path(&data, r"metadata.labels.app\.kubernetes\.io/name").unwrap(),
+        &expected
+    );
+    assert_eq!(
+        resolve_path(&data, "metadata.labels[\"app.kubernetes.io/name\"]").unwrap(),
+        &expected
+    );
+    assert_eq!(
+        resolve_path(&data, "metadata.labels.a.b").unwrap(),
+        &json!(1)
+    );
+
+    let err = resolve_path(&data, "metadata.labels.\"a.b\"").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Key not found: 'a.b' (at path 'metadata.labels.\"a.b\"')"
+    );
+}
+
+#[test]
+fn test_keys_with_slashes_and_escapes_are_not_expressions() {
+    let data = json!({
+        "metadata": {"labels": {"app.kubernetes.io/name": "api"}},
+        "url/path": "/v1",
+        "k+1": 2,
+        "k": 1,
+        "a": {"x*y": 3, "len(x)": 4}
+    });
+    for (query, expected) in [
+        (r"metadata.labels.app\.kubernetes\.io/name", json!("api")),
+        ("url/path", json!("/v1")),
+        ("k+1", json!(2)),
+        ("a.x*y", json!(3)),
+        ("a.len(x)", json!(4)),
+    ] {
+        assert_eq!(expr::parse_query(Some(query)).unwrap(), None, "{query}");
+        assert_eq!(resolve_path(&data, query).unwrap(), &expected);
+    }
+    let sum = expr::parse_query(Some("k + 1")).unwrap().unwrap();
+    assert_eq!(sum.eval(&data).unwrap(), json!(2));
+}
+
 #[test]
 fn test_format_string_no_quotes() {
     assert_eq!(format_value_with(&json!("Edmundo"), false), "Edmundo");
@@ -139,6 +190,27 @@ fn test_redact_recurses_nested_objects_and_arrays() {
     assert_eq!(redacted["secrets"], json!("<redacted>"));
 }
 
+#[test]
+fn test_redact_with_extra_patterns_and_custom_mask() {
+    let data = json!({
+        "db": {"dsn": "postgres://u:p@host/db", "host": "db.internal"},
+        "API_KEY": "abc",
+        "session": "eyJhbGciOiJIUzI1NiJ9.eyJzdWIiOiIxIn0.abc123"
+    });
+    let opts = RedactOptions {
+        extra_patterns: vec!["dsn".to_string()],
+        mask: "*****".to_string(),
+    };
+
+    let redacted = redact_with(&data, &opts);
+
+    assert_eq!(redacted["db"]["dsn"], json!("*****"));
+    assert_eq!(redacted["db"]["host"], json!("db.internal"));
+    // Built-in key and value-shape rules still apply, with the custom mask.
+    assert_eq!(redacted["API_KEY"], json!("*****"));
+    assert_eq!(redacted["session"], json!("*****"));
+}
+
 #[test]
 fn test_diff_values_env_added_removed_changed() {
     let left = json!({
@@ -242,6 +314,25 @@ fn test_format_text_diff_marks_added_and_removed_lines() {
     assert!(output.contains("  same"));
 }
 
+#[test]
+fn test_unified_diff_hunks_with_context() {
+    let old: String = (1..=12).map(|n| format!("line{n}\n")).collect();
+    let new = old.replace("line2\n", "two\n").replace("line11\n", "");
+    let output = diff::unified(&old, &new, "a.txt", "b.txt", false);
+    assert_eq!(
+        output,
+        "--- a.txt\n+++ b.txt\n\
+         @@ -1,5 +1,5 @@\n line1\n-line2\n+two\n line3\n line4\n line5\n\
+         @@ -8,5 +8,4 @@\n line8\n line9\n line10\n-line11\n line12\n"
+    );
+
+    assert_eq!(
+        diff::unified("", "a: 1\n", "/dev/null", "new.yaml", false),
+        "--- /dev/null\n+++ new.yaml\n@@ -0,0 +1 @@\n+a: 1\n"
+    );
+    assert_eq!(diff::unified("same\n", "same\n", "a", "b", false), "");
+}
+
 #[test]
 fn test_detect_format_no_extension_json() {
     let content = r#"{"club":"Vasco"}"#;
@@ -256,6 +347,92 @@ fn test_detect_format_no_extension_toml() {
     assert_eq!(format, Format::Toml);
 }
 
+/// What TOML parsing did before `toml_to_json`: JSON text, parsed again.
+#[cfg(feature = "toml")]
+fn toml_via_json_text(value: &toml::Value) -> Value {
+    serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+}
+
+#[cfg(feature = "toml")]
+#[test]
+fn test_toml_converts_like_the_json_round_trip() {
+    let content = "when = 1979-05-27T07:32:00Z\nday = 1979-05-27\nratio = 0.1\nnan = nan\n"
+        .to_string()
+        + &std::fs::read_to_string("testdata/config.toml").unwrap();
+    let parsed: toml::Value = toml::from_str(&content).unwrap();
+    let expected = toml_via_json_text(&parsed);
+    assert_eq!(toml_to_json(parsed), expected);
+    assert_eq!(
+        expected["when"],
+        json!({"$__toml_private_datetime": "1979-05-27T07:32:00Z"})
+    );
+    assert_eq!(expected["nan"], Value::Null);
+}
+
+/// `cargo test --release -- --ignored --nocapture bench_toml` (or
+/// `mise run bench`) times both conversions on a generated document.
+#[cfg(feature = "toml")]
+#[test]
+#[ignore = "benchmark; run with --release -- --ignored --nocapture"]
+fn bench_toml_conversion() {
+    use std::fmt::Write;
+    use std::time::Instant;
+
+    let mut content = String::new();
+    for i in 0..20_000 {
+        write!(
+            content,
+            "[[service]]\nname = \"svc-{i}\"\nport = {}\nweight = {i}.5\nenabled = true\n\
+             tags = [\"a\", \"b\"]\n[service.limits]\ncpu = \"500m\"\nmemory = {}\n",
+            8000 + i % 1000,
+            i * 1024
+        )
+        .unwrap();
+    }
+    let parsed: toml::Value = toml::from_str(&content).unwrap();
+    let runs = 5;
+    let time = |convert: &dyn Fn(toml::Value) -> Value| {
+        let start = Instant::now();
+        for _ in 0..runs {
+            std::hint::black_box(convert(parsed.clone()));
+        }
+        start.elapsed() / runs
+    };
+    let text = time(&|value| toml_via_json_text(&value));
+    let direct = time(&toml_to_json);
+    println!(
+        "TOML → Value, {} KiB: JSON round trip {text:?}, direct {direct:?} ({:.1}x)",
+        content.len() / 1024,
+        text.as_secs_f64() / direct.as_secs_f64()
+    );
+}
+
+#[test]
+fn test_version_lists_the_compiled_formats() {
+    let version = long_version();
+    assert!(version.starts_with(env!("CARGO_PKG_VERSION")), "{version}");
+    let listed: Vec<&str> = version
+        .lines()
+        .find_map(|line| line.strip_prefix("formats: "))
+        .unwrap()
+        .split(", ")
+        .collect();
+    for format in Format::value_variants() {
+        assert_eq!(listed.contains(&format.extension()), format.compiled());
+        match format.ensure_compiled() {
+            Ok(()) => assert!(format.compiled()),
+            Err(err) => assert!(
+                err.to_string().ends_with(&format!(
+                    "built without the `{}` feature",
+                    format.feature().unwrap()
+                )),
+                "{err}"
+            ),
+        }
+    }
+    assert!(Format::Json.compiled() && Format::Env.compiled());
+}
+
 #[test]
 fn test_detect_format_forced_overrides_extension() {
     let content = r#"club: Vasco"#;
@@ -263,6 +440,76 @@ fn test_detect_format_forced_overrides_extension() {
     assert_eq!(format, Format::Yaml);
 }
 
+#[test]
+fn test_detect_format_csv_and_tsv_extensions() {
+    assert_eq!(detect_format("users.csv", "", None).unwrap(), Format::Csv);
+    assert_eq!(detect_format("hosts.TSV", "", None).unwrap(), Format::Csv);
+}
+
+#[test]
+fn test_parse_bytes_sniffs_binary_formats_on_stdin() {
+    let opts = ParseOptions::default();
+    // {"a": 1} as MessagePack, then as CBOR.
+    let msgpack = parse_bytes("-", &[0x81, 0xa1, b'a', 0x01], None, &opts).unwrap();
+    let cbor = parse_bytes("-", &[0xa1, 0x61, b'a', 0x01], None, &opts).unwrap();
+    assert_eq!(msgpack, serde_json::json!({"a": 1}));
+    assert_eq!(cbor, serde_json::json!({"a": 1}));
+
+    let err = parse_bytes("blob.json", &[0x81, 0xa1], None, &opts).unwrap_err();
+    assert!(err.to_string().contains("not valid UTF-8"), "{err}");
+}
+
+#[test]
+fn test_env_values_stay_strings_unless_inferring() {
+    let content = "VERSION=1.10\nBUILD=007\nPORT=8080\nDEBUG=true\nRATIO=\"0.5\"\n";
+    let value = parse_content(".env", content, None).unwrap();
+    assert_eq!(
+        value,
+        json!({"VERSION": "1.10", "BUILD": "007", "PORT": "8080", "DEBUG": "true", "RATIO": "0.5"})
+    );
+
+    let opts = ParseOptions {
+        infer_types: true,
+        ..ParseOptions::default()
+    };
+    let value = parse_content_with(".env", content, None, &opts).unwrap();
+    assert_eq!(value["VERSION"], json!(1.1));
+    assert_eq!(value["BUILD"], json!(7));
+    assert_eq!(value["PORT"], json!(8080));
+    assert_eq!(value["DEBUG"], json!(true));
+}
+
+#[test]
+fn test_yaml_1_2_types_only_json_spellings() {
+    let content = "a: True\nb: 0x1F\nc: !!int 0x1F\nd: no\ne: 8080\nf: null\n";
+    let value = parse_content("c.yaml", content, None).unwrap();
+    assert_eq!(
+        value,
+        json!({"a": true, "b": 31, "c": 31, "d": "no", "e": 8080, "f": null})
+    );
+
+    let opts = ParseOptions {
+        yaml_1_2: true,
+        ..ParseOptions::default()
+    };
+    let value = parse_content_with("c.yaml", content, None, &opts).unwrap();
+    assert_eq!(
+        value,
+        json!({"a": "True", "b": "0x1F", "c": 31, "d": "no", "e": 8080, "f": null})
+    );
+}
+
+#[test]
+fn test_json_files_with_comments_fall_back_to_json5() {
+    let content = "{\n  // editor\n  \"tabSize\": 2,\n}";
+    let value = parse_content("settings.json", content, None).unwrap();
+    assert_eq!(value, serde_json::json!({"tabSize": 2}));
+    assert_eq!(detect_format("a.jsonc", "", None).unwrap(), Format::Json5);
+
+    let err = parse_content("broken.json", "{\"a\": }", None).unwrap_err();
+    assert_eq!(err.to_string(), "Failed to parse JSON: broken.json");
+}
+
 #[test]
 fn test_resolve_input_no_file_uses_stdin_when_piped() {
     let (file, path) = resolve_input(None, None, false).unwrap();
@@ -285,12 +532,96 @@ fn test_resolve_input_keeps_explicit_file_when_present() {
     assert_eq!(path, None);
 }
 
+#[test]
+fn test_resolve_input_keeps_clipboard_file_when_piped() {
+    let (file, path) = resolve_input(Some("clip:".to_string()), None, false).unwrap();
+    assert_eq!(file, "clip:");
+    assert_eq!(path, None);
+}
+
+#[test]
+fn test_resolve_input_keeps_git_file_when_piped() {
+    let spec = "git::git@github.com:org/infra//values.yaml@main".to_string();
+    let (file, path) = resolve_input(Some(spec.clone()), None, false).unwrap();
+    assert_eq!(file, spec);
+    assert_eq!(path, None);
+}
+
+#[test]
+fn test_resolve_input_keeps_remote_uris_when_piped() {
+    for uri in [
+        "s3://configs/app.yaml",
+        "gs://configs/app.yaml",
+        "az://configs/app.yaml",
+        "vault://secret/data/app",
+        "etcd:///config/app.yaml",
+        "consul://localhost/kv/config/app.yaml",
+        "k8s://configmap/my-ns/app-config",
+    ] {
+        let (file, path) = resolve_input(Some(uri.to_string()), None, false).unwrap();
+        assert_eq!(file, uri);
+        assert_eq!(path, None);
+    }
+}
+
 #[test]
 fn test_resolve_input_no_file_and_interactive_shows_tutorial() {
     let err = resolve_input(None, None, true).unwrap_err();
     assert!(err.to_string().contains("Mini tutorial"));
 }
 
+#[test]
+fn test_read_only_blocks_mutating_commands() {
+    let set = crate::Command::Set(env_edit::SetCli {
+        file: ".env".to_string(),
+        pairs: vec!["A=1".to_string()],
+        value_type: None,
+        backup: crate::atomic::BackupArg::default(),
+    });
+    let err = enforce_read_only(set.mutation(), true).unwrap_err();
+    assert!(err.to_string().contains("read-only mode"));
+    assert!(err.to_string().contains("`set`"));
+
+    // Same command is fine when the policy is off.
+    assert!(enforce_read_only(set.mutation(), false).is_ok());
+}
+
+#[test]
+fn test_read_only_allows_queries() {
+    let diff = crate::Command::Diff(diff::DiffCli {
+        left_file: "a.json".to_string(),
+        right_file: "b.json".to_string(),
+        format: None,
+        show_secrets: false,
+        output: diff::DiffOutput::Text,
+    });
+    assert!(enforce_read_only(diff.mutation(), true).is_ok());
+}
+
+#[test]
+fn test_diff_previews_only_file_writes() {
+    assert!(enforce_preview(Some("set")).is_ok());
+    assert!(enforce_preview(Some("--out")).is_ok());
+    let err = enforce_preview(Some("exec")).unwrap_err();
+    assert!(err.to_string().contains("cannot preview `exec`"));
+    assert!(enforce_preview(None).is_err());
+}
+
+#[cfg(feature = "vault")]
+#[test]
+fn test_read_only_allows_stdout_pulls() {
+    let pull = |out: Option<&str>| vault::cli::VaultCommand::Pull {
+        name: Some("x".to_string()),
+        id: None,
+        out: out.map(std::path::PathBuf::from),
+        force: false,
+    };
+    assert_eq!(pull(Some("-")).mutation(), None);
+    assert!(pull(Some("secret.env")).mutation().is_some());
+    assert!(pull(None).mutation().is_some());
+}
+
+#[cfg(feature = "vault")]
 #[test]
 fn test_auto_secret_name_is_dir_file_date_slug() {
     let name = vault::cli::auto_secret_name(".env");
@@ -329,3 +660,133 @@ fn test_github_users_api_query() {
         other => panic!("expected 0.login to be a string, got: {other}"),
     }
 }
+
+#[test]
+fn test_resolve_preview_rejects_non_yaml_input() {
+    let err = resolve_preview("testdata/config.json", None).unwrap_err();
+    assert!(err.to_string().contains("needs YAML input"), "{err}");
+    let preview = resolve_preview("testdata/config.yaml", None).unwrap();
+    assert_eq!(
+        preview,
+        std::fs::read_to_string("testdata/config.yaml").unwrap()
+    );
+}
+
+#[test]
+fn test_multi_document_yaml_parses_to_array_and_selects() {
+    let value = parse_file("testdata/k8s-bundle.yaml", None, &ParseOptions::default()).unwrap();
+    let docs = value.as_array().expect("two documents");
+    assert_eq!(docs.len(), 2, "empty trailing document is skipped");
+
+    let selectors = [select::parse_selector("kind=Deployment").unwrap()];
+    let deployment = select::apply(value, &selectors).unwrap();
+    assert_eq!(
+        resolve_path(&deployment, "spec.replicas").unwrap(),
+        &json!(3)
+    );
+}
+
+#[test]
+fn test_length_of_resolved_array() {
+    let value = parse_file("testdata/config.json", None, &ParseOptions::default()).unwrap();
+    let clubs = resolve_path(&value, "clubs").unwrap();
+    assert_eq!(
+        aggregate::length(clubs).unwrap(),
+        clubs.as_array().unwrap().len()
+    );
+}
+
+#[test]
+fn test_decode_path_decodes_leaves_in_place() {
+    let mut value = json!({
+        "kind": "Secret",
+        "data": {"user": "YWRtaW4=", "certs": ["aGk="]},
+        "note": "aGk="
+    });
+    decode_base64_leaves(&mut value, "data.*").unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "kind": "Secret",
+            "data": {"user": "admin", "certs": ["hi"]},
+            "note": "aGk="
+        })
+    );
+
+    let err = decode_base64_leaves(&mut value, "kind").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "--decode-path: cannot decode the value at 'kind'"
+    );
+}
+
+#[test]
+fn test_glob_query_keys_results_by_file() {
+    let cli = Cli::parse_from(["confctl", "testdata/services/*/config.*"]);
+    let files = glob::expand("testdata/services/*/config.*").unwrap();
+    let opts = ParseOptions::default();
+
+    let results = query_each(&cli, &files, Some("replicas"), &opts, |_, _| {}).unwrap();
+    assert_eq!(
+        Value::Object(results),
+        json!({
+            "testdata/services/api/config.yaml": 3,
+            "testdata/services/web/config.yaml": 2
+        })
+    );
+
+    let err = query_each(&cli, &files, Some("nope"), &opts, |_, _| {}).unwrap_err();
+    assert_eq!(err.to_string(), "Key not found: 'nope' (at path 'nope')");
+}
+
+#[test]
+fn test_out_writes_the_file_and_refuses_to_overwrite() {
+    let dir = std::env::temp_dir().join(format!("confctl-out-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let out = dir.join("nested/result.yaml");
+    let out = out.to_str().unwrap();
+    let cli = |flags: &[&str]| {
+        Cli::parse_from(["confctl", "config.json", "--out", out].iter().chain(flags))
+    };
+
+    let err = write_output(&cli(&[]), "a: 1\n").unwrap_err().to_string();
+    assert!(err.ends_with("does not exist; pass --mkdir to create it"));
+
+    write_output(&cli(&["--mkdir"]), "a: 1\n").unwrap();
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "a: 1\n");
+
+    let err = write_output(&cli(&[]), "a: 2\n").unwrap_err().to_string();
+    assert!(err.ends_with("already exists; pass --force to overwrite"));
+    write_output(&cli(&["--force"]), "a: 2\n").unwrap();
+    assert_eq!(std::fs::read_to_string(out).unwrap(), "a: 2\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_backup_suffix_is_given_with_equals() {
+    let set = |args: &[&str]| {
+        let argv = ["confctl", "set"].iter().chain(args);
+        match Cli::parse_from(argv).command {
+            Some(super::Command::Set(set_cli)) => set_cli,
+            _ => panic!("not a set"),
+        }
+    };
+    let cli = set(&["prod.yaml", "replicas=5", "--backup=.orig"]);
+    assert_eq!(cli.backup.suffix(), Some(".orig"));
+    let cli = set(&["prod.yaml", "replicas=5", "--backup"]);
+    assert_eq!(cli.backup.suffix(), Some(".bak"));
+    // A separate word is never taken as the suffix, so FILE is not lost.
+    let cli = set(&["--backup", "prod.yaml", "replicas=5"]);
+    assert_eq!(cli.backup.suffix(), Some(".bak"));
+    assert_eq!(cli.file, "prod.yaml");
+}
+
+#[test]
+fn test_verify_sig_never_streams() {
+    let argv = ["confctl", "s.json", "z", "--stream"];
+    assert!(!loads_whole(&Cli::parse_from(argv)));
+    // A streamed read skips the input source, and with it the check.
+    let cli = Cli::parse_from(argv.iter().chain(&["--verify-sig", "k.pub"]));
+    assert!(loads_whole(&cli));
+}
@@ -0,0 +1,245 @@
+//! When to colour output (`--color`, `NO_COLOR`, `CLICOLOR_FORCE`) and
+//! with which palette (`CONFCTL_THEME`).
+//!
+//! Unless colour is forced, escape codes only go to a terminal: stdout and
+//! stderr are checked separately, so `confctl … 2> log` keeps the log plain
+//! while the value is still coloured, and `TERM=dumb` counts as no terminal.
+//!
+//! A theme is a preset name, `key=style` overrides, or both:
+//! `CONFCTL_THEME=light`, `CONFCTL_THEME='key=cyan+bold,number=yellow'`,
+//! `CONFCTL_THEME='light,string=#2e7d32'`. A style is a colour name
+//! (`red`, `bright_black`, …), a `#rrggbb` hex colour or `none`, optionally
+//! followed by `+bold`.
+
+use std::env;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use colored::{Color, ColoredString, Colorize};
+use serde::Deserialize;
+
+use crate::settings::Settings;
+
+/// Env var holding the colour theme.
+const THEME_ENV: &str = "CONFCTL_THEME";
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ColorChoice {
+    /// Colour when stdout is a terminal, unless `NO_COLOR` is set
+    /// (`CLICOLOR_FORCE` forces it on).
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Whether stderr gets colour too, decided with stdout's by `use_color`.
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Whether output should be coloured: `--color` when given, else
+/// `CLICOLOR_FORCE` / `NO_COLOR` (https://no-color.org), else the settings
+/// file's `color`, else whether stdout is a terminal. Also decides for
+/// stderr, see `stderr`.
+pub(crate) fn use_color(flag: Option<ColorChoice>, configured: Option<ColorChoice>) -> bool {
+    let set = |name: &str| env::var(name).is_ok_and(|v| !v.is_empty() && v != "0");
+    let choice = match flag {
+        Some(choice) => choice,
+        None if set("CLICOLOR_FORCE") => ColorChoice::Always,
+        None if set("NO_COLOR") => ColorChoice::Never,
+        None => configured.unwrap_or_default(),
+    };
+    let (stdout, stderr) = match choice {
+        ColorChoice::Always => (true, true),
+        ColorChoice::Never => (false, false),
+        ColorChoice::Auto => (
+            terminal(atty::Stream::Stdout),
+            terminal(atty::Stream::Stderr),
+        ),
+    };
+    STDERR_COLOR.store(stdout && stderr, Ordering::Relaxed);
+    stdout
+}
+
+fn terminal(stream: atty::Stream) -> bool {
+    atty::is(stream) && env::var("TERM").ok().is_none_or(|term| term != "dumb")
+}
+
+/// `styled` for a message on stderr: plain unless stderr is a terminal (or
+/// colour is forced) as well as stdout.
+pub(crate) fn stderr(styled: ColoredString) -> String {
+    if STDERR_COLOR.load(Ordering::Relaxed) {
+        styled.to_string()
+    } else {
+        styled.deref().to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Style {
+    color: Option<Color>,
+    bold: bool,
+}
+
+impl Style {
+    const fn new(color: Color, bold: bool) -> Self {
+        Self {
+            color: Some(color),
+            bold,
+        }
+    }
+
+    pub(crate) fn paint(self, text: &str) -> String {
+        let mut painted = text.normal();
+        if let Some(color) = self.color {
+            painted = painted.color(color);
+        }
+        if self.bold {
+            painted = painted.bold();
+        }
+        painted.to_string()
+    }
+
+    fn parse(raw: &str, source: &str) -> Result<Self> {
+        let (name, bold) = match raw.strip_suffix("+bold") {
+            Some(name) => (name, true),
+            None => (raw, false),
+        };
+        let color = match name.trim() {
+            "none" | "" => None,
+            hex if hex.starts_with('#') => Some(parse_hex(hex, source)?),
+            named => match named.replace(['_', '-'], " ").parse::<Color>() {
+                Ok(color) => Some(color),
+                Err(()) => bail!("{source}: unknown colour `{named}`"),
+            },
+        };
+        Ok(Self { color, bold })
+    }
+}
+
+fn parse_hex(hex: &str, source: &str) -> Result<Color> {
+    let digits = &hex[1..];
+    let channel = |i: usize| u8::from_str_radix(digits.get(i..i + 2).unwrap_or("?"), 16);
+    match (digits.len(), channel(0), channel(2), channel(4)) {
+        (6, Ok(r), Ok(g), Ok(b)) => Ok(Color::TrueColor { r, g, b }),
+        _ => bail!("{source}: `{hex}` is not a #rrggbb colour"),
+    }
+}
+
+/// Colours for the parts of a JSON value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct Theme {
+    pub(crate) key: Style,
+    pub(crate) string: Style,
+    pub(crate) number: Style,
+    pub(crate) boolean: Style,
+    pub(crate) null: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DARK
+    }
+}
+
+impl Theme {
+    const DARK: Theme = Theme {
+        key: Style::new(Color::Blue, true),
+        string: Style::new(Color::Green, false),
+        number: Style::new(Color::White, false),
+        boolean: Style::new(Color::White, false),
+        null: Style::new(Color::BrightBlack, true),
+    };
+
+    /// No white or bright text, for light backgrounds.
+    const LIGHT: Theme = Theme {
+        key: Style::new(Color::Blue, true),
+        string: Style::new(Color::Green, false),
+        number: Style::new(Color::Magenta, false),
+        boolean: Style::new(Color::Magenta, false),
+        null: Style::new(Color::Black, true),
+    };
+
+    /// The theme `CONFCTL_THEME` describes, else the settings file's
+    /// `theme`, else the default.
+    pub(crate) fn load(settings: &Settings) -> Result<Self> {
+        if let Ok(spec) = env::var(THEME_ENV) {
+            return Self::parse(&spec, THEME_ENV);
+        }
+        match (&settings.theme, &settings.path) {
+            (Some(spec), Some(path)) => Self::parse(spec, &format!("theme in {}", path.display())),
+            (Some(spec), None) => Self::parse(spec, "theme"),
+            (None, _) => Ok(Self::default()),
+        }
+    }
+
+    fn parse(spec: &str, source: &str) -> Result<Self> {
+        let mut theme = Self::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let Some((role, style)) = part.split_once('=') else {
+                theme = match part {
+                    "dark" => Self::DARK,
+                    "light" => Self::LIGHT,
+                    _ => bail!("{source}: unknown theme `{part}` (dark, light)"),
+                };
+                continue;
+            };
+            let style = Style::parse(style, source)?;
+            match role.trim() {
+                "key" => theme.key = style,
+                "string" => theme.string = style,
+                "number" => theme.number = style,
+                "bool" | "boolean" => theme.boolean = style,
+                "null" => theme.null = style,
+                other => {
+                    bail!("{source}: unknown part `{other}` (key, string, number, bool, null)")
+                }
+            }
+        }
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_and_overrides() {
+        assert_eq!(Theme::parse("", THEME_ENV).unwrap(), Theme::DARK);
+        let theme = Theme::parse("light, key=cyan, null=bright_black+bold", THEME_ENV).unwrap();
+        assert_eq!(theme.number, Theme::LIGHT.number);
+        assert_eq!(theme.key, Style::new(Color::Cyan, false));
+        assert_eq!(theme.null, Style::new(Color::BrightBlack, true));
+        let theme = Theme::parse("string=#2e7d32,number=none", THEME_ENV).unwrap();
+        assert_eq!(
+            theme.string.color,
+            Some(Color::TrueColor {
+                r: 0x2e,
+                g: 0x7d,
+                b: 0x32
+            })
+        );
+        assert_eq!(theme.number.color, None);
+    }
+
+    #[test]
+    fn bad_specs_name_the_problem() {
+        let err = |spec: &str| Theme::parse(spec, THEME_ENV).unwrap_err().to_string();
+        assert_eq!(
+            err("solarized"),
+            "CONFCTL_THEME: unknown theme `solarized` (dark, light)"
+        );
+        assert_eq!(err("key=teal"), "CONFCTL_THEME: unknown colour `teal`");
+        assert_eq!(
+            err("string=#12345"),
+            "CONFCTL_THEME: `#12345` is not a #rrggbb colour"
+        );
+        assert_eq!(
+            err("value=red"),
+            "CONFCTL_THEME: unknown part `value` (key, string, number, bool, null)"
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! Opt-in, local-only undo for in-place edits. With `CONFCTL_UNDO=1`,
+//! `set`, `unset`, `patch --in-place`, `edit`, `convert --in-place` and
+//! `merge --in-place` keep the file as it was before they change it, and
+//! `confctl undo FILE` puts the last version back — a safety net for a
+//! fat-fingered `set`.
+//!
+//! Versions live under the user data dir (`CONFCTL_UNDO_DIR` moves them,
+//! e.g. to `.confctl-history` next to a repo), in one private directory per
+//! file named by the SHA-256 of its absolute path. Each version is an
+//! owner-only copy named `<nanoseconds>.<command>`; a file the command
+//! created is kept as an empty `.new` marker, so undoing it removes the
+//! file. The last 20 versions of each file are kept. Undo pops: the
+//! versions it goes back past are dropped.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use clap::Args;
+use colored::Colorize;
+
+use crate::atomic;
+
+/// Set to 1/true/yes/on to start keeping versions.
+pub(crate) const UNDO_ENV: &str = "CONFCTL_UNDO";
+/// Overrides where versions are kept.
+pub(crate) const UNDO_DIR_ENV: &str = "CONFCTL_UNDO_DIR";
+
+/// Versions kept per file; older ones are dropped.
+const KEEP: usize = 20;
+/// Suffix of the marker for a file that did not exist.
+const NEW: &str = ".new";
+
+#[derive(Args, Debug)]
+pub(crate) struct UndoCli {
+    /// The file to restore.
+    pub(crate) file: PathBuf,
+
+    /// Go back N versions; 1 is the file before its last change.
+    #[arg(short = 'n', long, default_value_t = 1, value_name = "N")]
+    pub(crate) steps: usize,
+
+    /// List the kept versions, newest first, instead of restoring one.
+    #[arg(long, conflicts_with = "steps")]
+    pub(crate) list: bool,
+}
+
+impl UndoCli {
+    pub(crate) fn mutation(&self) -> Option<&'static str> {
+        (!self.list).then_some("undo")
+    }
+}
+
+/// One kept version of a file.
+#[derive(Debug, PartialEq)]
+struct Version {
+    path: PathBuf,
+    /// Nanoseconds since the epoch when it was kept.
+    at: u128,
+    /// The command that was about to change the file.
+    command: String,
+    /// The file did not exist yet.
+    absent: bool,
+}
+
+impl Version {
+    fn when(&self) -> String {
+        let at = DateTime::from_timestamp_nanos(self.at as i64);
+        at.with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+}
+
+fn undo_root() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(UNDO_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    let dir = dirs::data_local_dir().context("could not resolve user data dir")?;
+    Ok(dir.join("confctl").join("undo"))
+}
+
+/// Keep `file` as it is now, before `command` changes it, when undo is on.
+/// A version that cannot be kept is an error, so the change is not made
+/// without its safety net.
+pub(crate) fn record(file: &Path, command: &str) -> Result<()> {
+    if !crate::env_flag(UNDO_ENV) || atomic::previewing() {
+        return Ok(());
+    }
+    let root = undo_root()?;
+    record_in(&root, file, command).with_context(|| {
+        format!(
+            "could not keep {} for undo in {} (unset {UNDO_ENV} to write without it)",
+            file.display(),
+            root.display()
+        )
+    })
+}
+
+fn record_in(root: &Path, file: &Path, command: &str) -> Result<()> {
+    let absolute = absolute(file)?;
+    let dir = versions_dir(root, &absolute);
+    let contents = match fs::read(file) {
+        Ok(contents) => Some(contents),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err).with_context(|| format!("reading {}", file.display())),
+    };
+    private_dir(&dir)?;
+    store(&dir.join("path"), absolute.display().to_string().as_bytes())?;
+    let at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let name = format!("{at:020}.{command}");
+    match contents {
+        Some(contents) => store(&dir.join(name), &contents)?,
+        None => store(&dir.join(format!("{name}{NEW}")), b"")?,
+    }
+    for old in versions(&dir)?.into_iter().skip(KEEP) {
+        fs::remove_file(&old.path).with_context(|| format!("removing {}", old.path.display()))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn run(cli: UndoCli) -> Result<()> {
+    let root = undo_root()?;
+    let file = &cli.file;
+    let dir = versions_dir(&root, &absolute(file)?);
+    let kept = versions(&dir)?;
+    if kept.is_empty() {
+        let hint = if crate::env_flag(UNDO_ENV) {
+            String::new()
+        } else {
+            format!("; set {UNDO_ENV}=1 to keep them from now on")
+        };
+        bail!("no earlier versions of {} are kept{hint}", file.display());
+    }
+    if cli.list {
+        for (n, version) in kept.iter().enumerate() {
+            let size = if version.absent {
+                "(did not exist)".to_string()
+            } else {
+                format!("{} bytes", fs::metadata(&version.path)?.len())
+            };
+            println!(
+                "{:>3}  {}  {:<18}  {size}",
+                n + 1,
+                version.when(),
+                version.command
+            );
+        }
+        return Ok(());
+    }
+
+    let target = restore(&kept, file, cli.steps)?;
+    if atomic::previewing() {
+        return Ok(());
+    }
+    for version in &kept[..cli.steps] {
+        fs::remove_file(&version.path)
+            .with_context(|| format!("removing {}", version.path.display()))?;
+    }
+    let verb = if target.absent { "removed" } else { "restored" };
+    println!(
+        "{} {verb} {} as it was before `{}` at {}",
+        "✓".green().bold(),
+        file.display(),
+        target.command,
+        target.when()
+    );
+    Ok(())
+}
+
+/// Put `file` back as it was `steps` versions ago; the version used.
+fn restore<'a>(kept: &'a [Version], file: &Path, steps: usize) -> Result<&'a Version> {
+    if steps == 0 {
+        bail!("--steps must be at least 1");
+    }
+    let Some(target) = kept.get(steps - 1) else {
+        bail!(
+            "only {} earlier versions of {} are kept",
+            kept.len(),
+            file.display()
+        );
+    };
+    if target.absent {
+        atomic::remove(file)?;
+    } else {
+        let contents =
+            fs::read(&target.path).with_context(|| format!("reading {}", target.path.display()))?;
+        atomic::write(file, contents, None)?;
+    }
+    Ok(target)
+}
+
+/// `file` from the root, through symlinked directories, as writes follow
+/// them; the file itself need not exist.
+fn absolute(file: &Path) -> Result<PathBuf> {
+    if let Ok(path) = fs::canonicalize(file) {
+        return Ok(path);
+    }
+    let parent = match file.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => parent,
+        None => Path::new("."),
+    };
+    let Some(name) = file.file_name() else {
+        bail!("{} is not a file", file.display());
+    };
+    let parent =
+        fs::canonicalize(parent).with_context(|| format!("resolving {}", parent.display()))?;
+    Ok(parent.join(name))
+}
+
+fn versions_dir(root: &Path, absolute: &Path) -> PathBuf {
+    let digest = crate::hash::sha2::sha256(absolute.display().to_string().as_bytes());
+    let name: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+    root.join(name)
+}
+
+/// The versions in `dir`, newest first; none when it does not exist.
+fn versions(dir: &Path) -> Result<Vec<Version>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("reading {}", dir.display())),
+    };
+    let mut versions = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some((at, command)) = name.split_once('.') else {
+            continue;
+        };
+        let Ok(at) = at.parse() else {
+            continue;
+        };
+        let (command, absent) = match command.strip_suffix(NEW) {
+            Some(command) => (command.to_string(), true),
+            None => (command.to_string(), false),
+        };
+        versions.push(Version {
+            path,
+            at,
+            command,
+            absent,
+        });
+    }
+    versions.sort_by_key(|version| std::cmp::Reverse(version.at));
+    Ok(versions)
+}
+
+fn private_dir(dir: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(dir)
+        .with_context(|| format!("creating {}", dir.display()))
+}
+
+/// Write `path` owner-only; configs hold secrets.
+fn store(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_come_back_newest_first() {
+        let dir = crate::scratch_dir("undo-restore");
+        let (root, file) = (dir.join("undo"), dir.join("app.yaml"));
+        record_in(&root, &file, "set").unwrap();
+        fs::write(&file, "port: 1\n").unwrap();
+        record_in(&root, &file, "set").unwrap();
+        fs::write(&file, "port: 2\n").unwrap();
+        record_in(&root, &file, "patch").unwrap();
+        fs::write(&file, "port: 3\n").unwrap();
+
+        let kept = versions(&versions_dir(&root, &absolute(&file).unwrap())).unwrap();
+        let commands: Vec<_> = kept
+            .iter()
+            .map(|v| (v.command.as_str(), v.absent))
+            .collect();
+        assert_eq!(commands, [("patch", false), ("set", false), ("set", true)]);
+        assert!(restore(&kept, &file, 4).is_err());
+        restore(&kept, &file, 2).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "port: 1\n");
+        restore(&kept, &file, 3).unwrap();
+        assert!(!file.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_the_last_versions_are_kept() {
+        let dir = crate::scratch_dir("undo-keep");
+        let (root, file) = (dir.join("undo"), dir.join("app.env"));
+        for n in 0..KEEP + 3 {
+            fs::write(&file, format!("N={n}\n")).unwrap();
+            record_in(&root, &file, "set").unwrap();
+        }
+        let kept = versions(&versions_dir(&root, &absolute(&file).unwrap())).unwrap();
+        assert_eq!(kept.len(), KEEP);
+        assert_eq!(fs::read_to_string(&kept[KEEP - 1].path).unwrap(), "N=3\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -13,17 +13,11 @@
 //! Google-managed (or CMEK) keys — no client-side crypto here, matching the
 //! hcp backend decision.
 //!
-//! Auth is ambient ADC — no token is ever cached by confctl. Discovery order:
-//!   1. `CLOUDSDK_AUTH_ACCESS_TOKEN` env var (gcloud's own override).
-//!   2. Credentials JSON (authorized_user): `gcp.credentials_file` from
-//!      vault.toml → `GOOGLE_APPLICATION_CREDENTIALS` → the gcloud ADC file
-//!      at ~/.config/gcloud/application_default_credentials.json. The
-//!      refresh_token is exchanged at https://oauth2.googleapis.com/token.
-//!   3. `gcloud auth application-default print-access-token`, then
-//!      `gcloud auth print-access-token` (covers service accounts too).
-//!   4. GCE metadata server (inside GCP compute).
-
-use std::path::{Path, PathBuf};
+//! Auth is ambient ADC through `crate::cloud::google`, which `gs://` inputs
+//! share — no token is ever cached by confctl. `gcp.credentials_file` from
+//! vault.toml is tried before `GOOGLE_APPLICATION_CREDENTIALS`.
+
+use std::path::PathBuf;
 use std::process::Command;
 
 use anyhow::{anyhow, bail, Context, Result};
@@ -32,15 +26,14 @@ use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use serde_json::json;
 
+use crate::cloud::google::{self, api_error as map_gcp_err};
+
 use super::super::config::{BackendKind, GcpConfig, VaultConfig};
 use super::{
     Backend, BackendStatus, Entry, LoginOpts, PullRequest, PulledSecret, PushRequest, SessionStatus,
 };
 
 const API_BASE: &str = "https://secretmanager.googleapis.com/v1";
-const OAUTH_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
-const METADATA_TOKEN_URL: &str =
-    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
 
 pub struct GcpBackend {
     cfg_path: PathBuf,
@@ -83,103 +76,16 @@ impl GcpBackend {
         if let Some(t) = &self.token {
             return Ok(t.clone());
         }
-        let token = self.discover_token()?;
+        let credentials_file = self
+            .cfg
+            .gcp
+            .as_ref()
+            .and_then(|c| c.credentials_file.clone());
+        let token = google::access_token(&self.agent, credentials_file)?;
         self.token = Some(token.clone());
         Ok(token)
     }
 
-    fn discover_token(&self) -> Result<String> {
-        if let Ok(t) = std::env::var("CLOUDSDK_AUTH_ACCESS_TOKEN") {
-            if !t.is_empty() {
-                return Ok(t);
-            }
-        }
-        if let Some(path) = self.adc_credentials_path() {
-            match self.token_from_adc_file(&path) {
-                Ok(t) => return Ok(t),
-                Err(err) => eprintln!(
-                    "warning: ADC credentials at {} unusable ({err}); trying gcloud CLI.",
-                    path.display()
-                ),
-            }
-        }
-        let gcloud_arg_sets: [&[&str]; 2] = [
-            &["auth", "application-default", "print-access-token"],
-            &["auth", "print-access-token"],
-        ];
-        for args in gcloud_arg_sets {
-            if let Some(t) = gcloud_token(args) {
-                return Ok(t);
-            }
-        }
-        if let Ok(t) = self.token_from_metadata_server() {
-            return Ok(t);
-        }
-        bail!(
-            "no Google credentials found. Run `gcloud auth application-default login`, \
-             set GOOGLE_APPLICATION_CREDENTIALS, or run inside GCP."
-        )
-    }
-
-    fn adc_credentials_path(&self) -> Option<PathBuf> {
-        if let Some(p) = self.cfg.gcp.as_ref().and_then(|c| c.credentials_file.clone()) {
-            return Some(p);
-        }
-        if let Ok(p) = std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
-            if !p.is_empty() {
-                return Some(PathBuf::from(p));
-            }
-        }
-        let default = dirs::config_dir()?
-            .join("gcloud")
-            .join("application_default_credentials.json");
-        default.exists().then_some(default)
-    }
-
-    /// Exchange an `authorized_user` ADC refresh_token for an access token.
-    /// Service-account JSONs need RS256 signing — those are served by the
-    /// gcloud-CLI fallback instead.
-    fn token_from_adc_file(&self, path: &Path) -> Result<String> {
-        let body = std::fs::read_to_string(path)
-            .with_context(|| format!("reading credentials at {}", path.display()))?;
-        let creds: AdcCredentials = serde_json::from_str(&body)
-            .with_context(|| format!("parsing credentials at {}", path.display()))?;
-        if creds.credential_type.as_deref() != Some("authorized_user") {
-            bail!(
-                "credentials type {:?} is not `authorized_user`",
-                creds.credential_type.as_deref().unwrap_or("unknown")
-            );
-        }
-        let resp = self
-            .agent
-            .post(OAUTH_TOKEN_URL)
-            .send_json(json!({
-                "client_id": creds.client_id,
-                "client_secret": creds.client_secret,
-                "refresh_token": creds.refresh_token,
-                "grant_type": "refresh_token",
-            }))
-            .map_err(map_gcp_err)?;
-        let token: OauthTokenResponse = resp.into_json().context("parsing oauth token response")?;
-        Ok(token.access_token)
-    }
-
-    fn token_from_metadata_server(&self) -> Result<String> {
-        let agent = ureq::AgentBuilder::new()
-            .timeout_connect(std::time::Duration::from_millis(500))
-            .timeout(std::time::Duration::from_secs(3))
-            .build();
-        let resp = agent
-            .get(METADATA_TOKEN_URL)
-            .set("Metadata-Flavor", "Google")
-            .call()
-            .map_err(map_gcp_err)?;
-        let token: OauthTokenResponse = resp
-            .into_json()
-            .context("parsing metadata server token response")?;
-        Ok(token.access_token)
-    }
-
     fn authed(&mut self, req: ureq::Request) -> Result<ureq::Request> {
         let token = self.resolve_token()?;
         Ok(req.set("Authorization", &format!("Bearer {token}")))
@@ -222,34 +128,8 @@ pub fn sanitize_secret_id(raw: &str) -> String {
     id
 }
 
-fn gcloud_token(args: &[&str]) -> Option<String> {
-    let output = Command::new("gcloud").args(args).output().ok()?;
-    if !output.status.success() {
-        return None;
-    }
-    let token = String::from_utf8(output.stdout).ok()?.trim().to_string();
-    (!token.is_empty()).then_some(token)
-}
-
 // ---------- Wire types ----------
 
-#[derive(Debug, Deserialize)]
-struct AdcCredentials {
-    #[serde(rename = "type")]
-    credential_type: Option<String>,
-    #[serde(default)]
-    client_id: String,
-    #[serde(default)]
-    client_secret: String,
-    #[serde(default)]
-    refresh_token: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct OauthTokenResponse {
-    access_token: String,
-}
-
 #[derive(Debug, Deserialize, Default)]
 #[serde(default)]
 struct GcpSecret {
@@ -300,7 +180,7 @@ impl Backend for GcpBackend {
                 .or_else(|_| std::env::var("CLOUDSDK_CORE_PROJECT"))
                 .ok()
                 .filter(|s| !s.is_empty())
-                .or_else(|| gcloud_token(&["config", "get-value", "project"]))
+                .or_else(|| google::gcloud(&["config", "get-value", "project"]))
                 .filter(|s| !s.is_empty() && s != "(unset)")
                 .ok_or_else(|| {
                     anyhow!("no project given. Pass --endpoint <PROJECT_ID> or set GOOGLE_CLOUD_PROJECT.")
@@ -350,7 +230,7 @@ impl Backend for GcpBackend {
             });
         };
         let env_token = std::env::var("CLOUDSDK_AUTH_ACCESS_TOKEN").is_ok_and(|v| !v.is_empty());
-        let creds = self.adc_credentials_path().is_some();
+        let creds = gcp.credentials_file.is_some() || google::adc_credentials_path().is_some();
         let session = if env_token || creds || which_gcloud() {
             SessionStatus::Ambient
         } else {
@@ -523,31 +403,6 @@ fn which_gcloud() -> bool {
         .unwrap_or(false)
 }
 
-// ---------- Error mapping ----------
-
-fn map_gcp_err(err: ureq::Error) -> anyhow::Error {
-    match err {
-        ureq::Error::Status(status, resp) => {
-            let body = resp
-                .into_string()
-                .unwrap_or_else(|_| format!("HTTP {status}"));
-            // Google errors look like {"error": {"message": "...", "status": "..."}}.
-            let pretty = serde_json::from_str::<serde_json::Value>(&body)
-                .ok()
-                .and_then(|v| {
-                    v.get("error")
-                        .and_then(|e| e.get("message"))
-                        .and_then(|m| m.as_str())
-                        .map(str::to_string)
-                })
-                .filter(|s| !s.is_empty())
-                .unwrap_or(body);
-            anyhow!("Google API returned {status}: {pretty}")
-        }
-        ureq::Error::Transport(t) => anyhow!("transport error against Google API: {t}"),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -24,6 +24,11 @@
 //!   4. `~/.vault-token` (the CLI's default).
 //!
 //! The first match wins; if none yields a token, calls fail with a clear hint.
+//!
+//! `vault://MOUNT/data/PATH` file arguments (`read_kv`) read any KV v2
+//! secret the way the Vault CLI does — `VAULT_ADDR`, `VAULT_TOKEN` or
+//! `~/.vault-token`, `VAULT_NAMESPACE` — without vault.toml or a login, and
+//! hand its key/value pairs to the query like a parsed file.
 
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
@@ -97,23 +102,9 @@ impl HcpBackend {
                 return Ok(token);
             }
         }
-        if let Ok(t) = std::env::var("VAULT_TOKEN") {
-            if !t.is_empty() {
-                self.token = Some(t.clone());
-                return Ok(t);
-            }
-        }
-        if let Some(home) = dirs::home_dir() {
-            let path = home.join(".vault-token");
-            if path.exists() {
-                let token = std::fs::read_to_string(&path)
-                    .with_context(|| format!("reading {}", path.display()))?;
-                let token = token.trim().to_string();
-                if !token.is_empty() {
-                    self.token = Some(token.clone());
-                    return Ok(token);
-                }
-            }
+        if let Some(t) = ambient_token()? {
+            self.token = Some(t.clone());
+            return Ok(t);
         }
         bail!(
             "no Vault token available. Run `confctl vault login --backend hcp`, set VAULT_TOKEN, or point `hcp.token_file` at a token file."
@@ -530,6 +521,84 @@ impl HcpBackend {
     }
 }
 
+// ---------- vault:// inputs ----------
+
+/// The key/value pairs of the KV v2 secret `spec` (what follows
+/// `vault://`) names, at its latest version unless `?version=N` says.
+pub fn read_kv(spec: &str) -> Result<serde_json::Value> {
+    let addr = std::env::var("VAULT_ADDR")
+        .ok()
+        .filter(|addr| !addr.is_empty())
+        .context("vault:// inputs need VAULT_ADDR, e.g. https://vault.example.com:8200")?;
+    let token = ambient_token()?
+        .context("no Vault token for vault:// inputs. Set VAULT_TOKEN or run `vault login`.")?;
+    let path = kv_api_path(spec)?;
+    let url = format!("{}/v1/{path}", addr.trim_end_matches('/'));
+    let mut req = crate::net::agent().get(&url).set("X-Vault-Token", &token);
+    if let Some(ns) = std::env::var("VAULT_NAMESPACE")
+        .ok()
+        .filter(|ns| !ns.is_empty())
+    {
+        req = req.set("X-Vault-Namespace", &ns);
+    }
+    let resp = match crate::net::call(&req) {
+        Ok(resp) => resp,
+        Err(failed) if failed.status() == Some(404) => bail!("Vault has no secret at /v1/{path}"),
+        Err(failed) => return Err(failed.describe(map_hcp_err)),
+    };
+    let mut body: serde_json::Value = resp.into_json().context("parsing GET /data response")?;
+    match body["data"]["data"].take() {
+        data @ serde_json::Value::Object(_) => Ok(data),
+        serde_json::Value::Null if body["data"]["metadata"].is_object() => {
+            bail!("the secret has no data: that version is deleted or destroyed")
+        }
+        _ => bail!("/v1/{path} is not a KV v2 secret"),
+    }
+}
+
+/// `MOUNT/data/PATH[?QUERY]` for `spec`. `data` goes after the first
+/// segment, the mount, unless a segment between the mount and the secret's
+/// name is `data` already (as for nested mounts, which must spell it out).
+fn kv_api_path(spec: &str) -> Result<String> {
+    let (path, query) = match spec.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (spec, None),
+    };
+    let mut segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.len() < 2 {
+        bail!("`vault://{spec}` names no secret: write vault://MOUNT/data/PATH, e.g. vault://secret/data/app");
+    }
+    if !segments[1..segments.len() - 1].contains(&"data") {
+        segments.insert(1, "data");
+    }
+    let path = segments.join("/");
+    Ok(match query {
+        Some(query) => format!("{path}?{query}"),
+        None => path,
+    })
+}
+
+/// `VAULT_TOKEN`, else the Vault CLI's `~/.vault-token`.
+fn ambient_token() -> Result<Option<String>> {
+    if let Ok(t) = std::env::var("VAULT_TOKEN") {
+        if !t.is_empty() {
+            return Ok(Some(t));
+        }
+    }
+    if let Some(home) = dirs::home_dir() {
+        let path = home.join(".vault-token");
+        if path.exists() {
+            let token = std::fs::read_to_string(&path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            let token = token.trim().to_string();
+            if !token.is_empty() {
+                return Ok(Some(token));
+            }
+        }
+    }
+    Ok(None)
+}
+
 // ---------- Error mapping ----------
 
 fn map_hcp_err(err: ureq::Error) -> anyhow::Error {
@@ -588,3 +657,25 @@ fn prompt_line(prompt: &str) -> Result<String> {
 fn config_path_placeholder() -> &'static Path {
     Path::new("/unused")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_specs_map_to_the_data_api() {
+        assert_eq!(kv_api_path("secret/data/app").unwrap(), "secret/data/app");
+        assert_eq!(kv_api_path("secret/app").unwrap(), "secret/data/app");
+        assert_eq!(
+            kv_api_path("secret/team/app?version=3").unwrap(),
+            "secret/data/team/app?version=3"
+        );
+        assert_eq!(kv_api_path("kv/team/data/app").unwrap(), "kv/team/data/app");
+        // A secret named `data` is not the API segment.
+        assert_eq!(kv_api_path("secret/data").unwrap(), "secret/data/data");
+        assert!(kv_api_path("secret")
+            .unwrap_err()
+            .to_string()
+            .contains("names no secret"));
+    }
+}
@@ -91,8 +91,25 @@ pub enum VaultCommand {
     },
 }
 
+impl VaultCommand {
+    /// Name of the operation when it writes remote secrets, local session
+    /// state, or a local file; `None` for read-only commands. `pull --out -`
+    /// only writes stdout and stays allowed.
+    pub fn mutation(&self) -> Option<&'static str> {
+        match self {
+            VaultCommand::Status | VaultCommand::List { .. } => None,
+            VaultCommand::Pull { out: Some(p), .. } if p.as_os_str() == "-" => None,
+            VaultCommand::Pull { .. } => Some("vault pull (to a file)"),
+            VaultCommand::Login { .. } => Some("vault login"),
+            VaultCommand::Logout => Some("vault logout"),
+            VaultCommand::Push { .. } => Some("vault push"),
+            VaultCommand::Rm { .. } => Some("vault rm"),
+        }
+    }
+}
+
 /// Path to read from: `--config` flag > `$CONFCTL_CONFIG` > user > /etc/.
-fn read_config_path(override_path: Option<&PathBuf>) -> Result<PathBuf> {
+pub(crate) fn read_config_path(override_path: Option<&PathBuf>) -> Result<PathBuf> {
     if let Some(p) = override_path {
         return Ok(p.clone());
     }
@@ -101,7 +118,7 @@ fn read_config_path(override_path: Option<&PathBuf>) -> Result<PathBuf> {
 
 /// Path for `vault login` to write to: `--config` flag > `$CONFCTL_CONFIG`
 /// > user config dir. Never `/etc/` — the system path is read-only.
-fn write_config_path(override_path: Option<&PathBuf>) -> Result<PathBuf> {
+pub(crate) fn write_config_path(override_path: Option<&PathBuf>) -> Result<PathBuf> {
     if let Some(p) = override_path {
         return Ok(p.clone());
     }
@@ -421,8 +438,7 @@ fn write_file(path: &Path, bytes: &[u8], force: bool) -> Result<()> {
                 .with_context(|| format!("creating {}", parent.display()))?;
         }
     }
-    std::fs::write(path, bytes).with_context(|| format!("writing {}", path.display()))?;
-    Ok(())
+    crate::atomic::write(path, bytes, None)
 }
 
 fn prompt_line(prompt: &str) -> Result<String> {
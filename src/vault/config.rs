@@ -192,8 +192,7 @@ impl VaultConfig {
                 .with_context(|| format!("creating config dir {}", parent.display()))?;
         }
         let body = toml::to_string_pretty(self).context("serializing vault config")?;
-        std::fs::write(path, body)
-            .with_context(|| format!("writing vault config to {}", path.display()))?;
+        crate::atomic::write(path, body, None).context("writing vault config")?;
         set_user_only_mode(path)?;
         Ok(())
     }
@@ -170,8 +170,7 @@ impl FileStore {
                 .with_context(|| format!("creating {}", parent.display()))?;
         }
         let body = toml::to_string_pretty(file).context("serializing secret store")?;
-        std::fs::write(&self.path, body)
-            .with_context(|| format!("writing {}", self.path.display()))?;
+        crate::atomic::write(&self.path, body, None)?;
         set_user_only_mode(&self.path)?;
         Ok(())
     }
@@ -0,0 +1,517 @@
+//! `--yaml-anchors preserve` and `--yaml-anchors report`: where a YAML
+//! file's anchors and aliases sit, and YAML output that keeps them.
+//!
+//! serde_yaml copies every alias and forgets the anchor, so [`scan`] reads
+//! the text with the same line model as `--resolve-preview` and records the
+//! path of every `&anchor` and `*alias`. [`render`] then writes the queried
+//! value as YAML, putting `&name` on anchored nodes and `*name` in place of
+//! a node that is still equal to the one its anchor names. Anything else
+//! (a value changed by `--env-prefix`, a layout the scan cannot follow) is
+//! written out in full, so the output always loads as the same document.
+//!
+//! The same scan also gives [`locate`] the line and column of every block
+//! key and `- ` item, for `--with-location`.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde_json::Value;
+
+use crate::path;
+use crate::yaml_preview::{alias_only, indent_of, is_block_header, is_trivia, merge_sources, Line};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum AnchorMode {
+    /// Apply `<<` merge keys as well as aliases.
+    Expand,
+    /// Keep anchors and aliases in YAML output.
+    Preserve,
+    /// List each alias and the anchor it resolves from.
+    Report,
+}
+
+/// An `&anchor` or `*alias` in the source, at the path of the node it is
+/// attached to. Paths start with the document index when the stream holds
+/// several documents, as they do in the parsed value.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Site {
+    pub(crate) path: Vec<String>,
+    /// 1-based source line.
+    pub(crate) line: usize,
+    pub(crate) kind: SiteKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SiteKind {
+    Anchor(String),
+    /// `from` is the anchor the alias resolves to: its path and line.
+    Alias {
+        name: String,
+        from: Option<(Vec<String>, usize)>,
+    },
+}
+
+/// Every anchor and alias in `content`, in source order.
+pub(crate) fn scan(content: &str) -> Vec<Site> {
+    let Scanner {
+        mut sites, docs, ..
+    } = Scanner::run(content);
+    if docs <= 1 {
+        for site in &mut sites {
+            site.path.remove(0);
+            if let SiteKind::Alias {
+                from: Some((from, _)),
+                ..
+            } = &mut site.kind
+            {
+                from.remove(0);
+            }
+        }
+    }
+    sites
+}
+
+/// The 1-based line and column of the key or `- ` that holds the node at
+/// `path`; the last one when a key repeats. None for the root and for
+/// nodes inside flow collections, which the scan does not enter.
+pub(crate) fn locate(content: &str, path: &[String]) -> Option<(usize, usize)> {
+    let scanner = Scanner::run(content);
+    let mut full = path.to_vec();
+    if scanner.docs <= 1 {
+        full.insert(0, "0".to_string());
+    }
+    scanner
+        .nodes
+        .iter()
+        .rev()
+        .find(|(node, _, _)| *node == full)
+        .map(|(_, line, col)| (*line, col + 1))
+}
+
+#[derive(Default)]
+struct Scanner {
+    sites: Vec<Site>,
+    /// Every key and item opened: its path, line and column.
+    nodes: Vec<(Vec<String>, usize, usize)>,
+    /// Path segments of the enclosing nodes, each with the column of the key
+    /// or `- ` that opened it; index segments also keep their number.
+    stack: Vec<(usize, String, Option<usize>)>,
+    /// Anchors of the current document, for resolving aliases.
+    anchors: HashMap<String, (Vec<String>, usize)>,
+    /// Documents with content so far; the current one is `docs - 1`.
+    docs: usize,
+    /// Whether the current document has content yet.
+    started: bool,
+    /// Inside a block scalar owned by this column.
+    literal: Option<usize>,
+}
+
+impl Scanner {
+    fn run(content: &str) -> Scanner {
+        let mut scanner = Scanner::default();
+        for (at, raw) in content.lines().enumerate() {
+            scanner.line(raw, at + 1);
+        }
+        scanner
+    }
+
+    fn line(&mut self, raw: &str, line_no: usize) {
+        if let Some(col) = self.literal {
+            if raw.trim().is_empty() || indent_of(raw) > col {
+                return;
+            }
+            self.literal = None;
+        }
+        if raw.starts_with("---") || raw.starts_with("...") {
+            self.stack.clear();
+            self.anchors.clear();
+            self.started = false;
+            return;
+        }
+        if is_trivia(raw) || raw.trim_start().starts_with('%') {
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            self.docs += 1;
+        }
+
+        let line = Line::parse(raw);
+        if let Some(dash) = line.dash_col {
+            // Every `- ` on the line opens an item; only the last is recorded
+            // by the parser, and nested `- - x` items start at index 0.
+            let mut col = line.indent;
+            while col <= dash {
+                self.open_item(col);
+                self.nodes.push((self.path(), line_no, col));
+                col += 2;
+            }
+        }
+        if let Some(name) = &line.item_anchor {
+            if line.key.is_none() {
+                let path = self.path();
+                self.anchor(name, path, line_no);
+            }
+        }
+
+        let owner = match &line.key {
+            Some(key) => {
+                self.stack.retain(|(col, _, _)| *col < line.key_col);
+                self.stack.push((line.key_col, key.clone(), None));
+                self.nodes.push((self.path(), line_no, line.key_col));
+                line.key_col
+            }
+            None => line.dash_col.unwrap_or(line.indent),
+        };
+        if line.key.is_none() && line.dash_col.is_none() {
+            // A continuation line (a wrapped scalar or flow collection).
+            return;
+        }
+
+        let path = self.path();
+        if let Some(name) = &line.value_anchor {
+            self.anchor(name, path.clone(), line_no);
+        }
+        if let Some(name) = alias_only(&line.value) {
+            self.alias(name, path, line_no);
+        } else if let Some(names) = merge_sources(&line.value) {
+            for (i, name) in names.into_iter().enumerate() {
+                let mut item = path.clone();
+                item.push(i.to_string());
+                self.alias(name, item, line_no);
+            }
+        }
+        if is_block_header(&line.value) {
+            self.literal = Some(owner);
+        }
+    }
+
+    /// Open the item a `- ` at `col` starts: the next index of the sequence
+    /// at that column, or 0 for a new one.
+    fn open_item(&mut self, col: usize) {
+        let mut index = 0;
+        while let Some((top, _, item)) = self.stack.last() {
+            if *top < col || (*top == col && item.is_none()) {
+                break;
+            }
+            if *top == col {
+                index = item.map_or(0, |i| i + 1);
+            }
+            self.stack.pop();
+        }
+        self.stack.push((col, index.to_string(), Some(index)));
+    }
+
+    fn path(&self) -> Vec<String> {
+        let doc = self.docs.saturating_sub(1).to_string();
+        std::iter::once(doc)
+            .chain(self.stack.iter().map(|(_, segment, _)| segment.clone()))
+            .collect()
+    }
+
+    fn anchor(&mut self, name: &str, path: Vec<String>, line: usize) {
+        self.anchors.insert(name.to_string(), (path.clone(), line));
+        self.sites.push(Site {
+            path,
+            line,
+            kind: SiteKind::Anchor(name.to_string()),
+        });
+    }
+
+    fn alias(&mut self, name: String, path: Vec<String>, line: usize) {
+        let from = self.anchors.get(&name).cloned();
+        self.sites.push(Site {
+            path,
+            line,
+            kind: SiteKind::Alias { name, from },
+        });
+    }
+}
+
+/// One line per alias: where it is, and the anchor it resolves to. Only
+/// aliases under `base` when a query path is given.
+pub(crate) fn report(sites: &[Site], base: &[String]) -> String {
+    let display = |segments: &[String]| match segments {
+        [] => "(root)".to_string(),
+        _ => path::join(segments),
+    };
+    let rows: Vec<(String, String)> = sites
+        .iter()
+        .filter(|site| site.path.starts_with(base))
+        .filter_map(|site| {
+            let SiteKind::Alias { name, from } = &site.kind else {
+                return None;
+            };
+            let target = match from {
+                Some((path, line)) => format!("{} (line {line})", display(path)),
+                None => "no anchor before it".to_string(),
+            };
+            Some((
+                format!("{} (line {})", display(&site.path), site.line),
+                format!("*{name} → {target}"),
+            ))
+        })
+        .collect();
+    let width = rows.iter().map(|(at, _)| at.chars().count()).max();
+    rows.iter()
+        .map(|(at, to)| format!("{at:<width$}  {to}\n", width = width.unwrap_or(0)))
+        .collect()
+}
+
+/// `value`, found at `base` in the scanned document, as YAML with its
+/// anchors and aliases.
+pub(crate) fn render(value: &Value, sites: &[Site], base: &[String]) -> Result<String> {
+    if let Some(text) = inline(value)? {
+        return Ok(format!("{text}\n"));
+    }
+    let mut emitter = Emitter {
+        sites: sites
+            .iter()
+            .map(|site| (site.path.clone(), &site.kind))
+            .collect(),
+        defined: HashMap::new(),
+    };
+    let mut path = base.to_vec();
+    let lines = emitter.block(value, &mut path, 0)?;
+    Ok(lines.join("\n") + "\n")
+}
+
+struct Emitter<'a> {
+    sites: HashMap<Vec<String>, &'a SiteKind>,
+    /// Anchors written so far and the node each one names.
+    defined: HashMap<String, &'a Value>,
+}
+
+enum Property {
+    Anchor(String),
+    Alias(String),
+}
+
+impl<'a> Emitter<'a> {
+    /// What to write before the node at `path`. Keys come out sorted, so an
+    /// alias can come before its anchor: whichever of them is written first
+    /// carries `&name`, and later ones become `*name` while their node is
+    /// still equal to it. An alias whose node changed is written in full.
+    fn property(&mut self, path: &[String], value: &'a Value) -> Option<Property> {
+        let (name, is_anchor) = match self.sites.get(path)? {
+            SiteKind::Anchor(name) => (name, true),
+            SiteKind::Alias { name, .. } => (name, false),
+        };
+        match self.defined.get(name) {
+            Some(node) if *node == value => Some(Property::Alias(name.clone())),
+            Some(_) if !is_anchor => None,
+            _ => {
+                self.defined.insert(name.clone(), value);
+                Some(Property::Anchor(name.clone()))
+            }
+        }
+    }
+
+    /// The entries of a non-empty mapping or sequence, at `indent`.
+    fn block(
+        &mut self,
+        value: &'a Value,
+        path: &mut Vec<String>,
+        indent: usize,
+    ) -> Result<Vec<String>> {
+        let pad = " ".repeat(indent);
+        let mut lines = Vec::new();
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map {
+                    let head = format!("{pad}{}:", scalar(key)?);
+                    path.push(key.clone());
+                    lines.extend(self.entry(head, child, path, indent, false)?);
+                    path.pop();
+                }
+            }
+            Value::Array(items) => {
+                for (i, child) in items.iter().enumerate() {
+                    path.push(i.to_string());
+                    lines.extend(self.entry(format!("{pad}-"), child, path, indent, true)?);
+                    path.pop();
+                }
+            }
+            _ => unreachable!("block() is only called for collections"),
+        }
+        Ok(lines)
+    }
+
+    /// `head` (`key:` or `-` at `indent`) followed by `value`.
+    fn entry(
+        &mut self,
+        head: String,
+        value: &'a Value,
+        path: &mut Vec<String>,
+        indent: usize,
+        dash: bool,
+    ) -> Result<Vec<String>> {
+        let anchor = match self.property(path, value) {
+            Some(Property::Alias(name)) => return Ok(vec![format!("{head} *{name}")]),
+            Some(Property::Anchor(name)) => format!(" &{name}"),
+            None => String::new(),
+        };
+        if let Some(text) = inline(value)? {
+            return Ok(vec![format!("{head}{anchor} {text}")]);
+        }
+        // A sequence under a key is not indented, as serde_yaml writes it.
+        let child_indent = match value {
+            Value::Array(_) if !dash => indent,
+            _ => indent + 2,
+        };
+        let mut lines = self.block(value, path, child_indent)?;
+        if dash && anchor.is_empty() {
+            // `- key: value`, the first entry on the dash's line.
+            lines[0] = format!("{head} {}", lines[0].trim_start());
+        } else {
+            lines.insert(0, format!("{head}{anchor}"));
+        }
+        Ok(lines)
+    }
+}
+
+/// A scalar or empty collection on one line; `None` for anything with
+/// entries.
+fn inline(value: &Value) -> Result<Option<String>> {
+    Ok(Some(match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => scalar(s)?,
+        Value::Array(items) if items.is_empty() => "[]".to_string(),
+        Value::Object(map) if map.is_empty() => "{}".to_string(),
+        _ => return Ok(None),
+    }))
+}
+
+/// A string as serde_yaml would quote it; multi-line text as a JSON string,
+/// which is valid YAML on one line.
+fn scalar(text: &str) -> Result<String> {
+    #[cfg(feature = "yaml")]
+    if !text.contains('\n') {
+        return Ok(serde_yaml::to_string(text)?.trim_end().to_string());
+    }
+    Ok(serde_json::to_string(text)?)
+}
+
+#[cfg(all(test, feature = "yaml"))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const COMPOSE: &str = "\
+x-defaults: &defaults
+  image: app:1
+  restart: always
+services:
+  api:
+    <<: *defaults
+    ports: &ports
+      - 80
+      - 443
+  worker:
+    <<: [*defaults]
+    ports: *ports
+    command: |
+      run: *not-an-alias
+";
+
+    fn strings(path: &[&str]) -> Vec<String> {
+        path.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn scan_finds_anchor_and_alias_paths() {
+        let sites = scan(COMPOSE);
+        let found: Vec<(String, usize, String)> = sites
+            .iter()
+            .map(|site| {
+                let what = match &site.kind {
+                    SiteKind::Anchor(name) => format!("&{name}"),
+                    SiteKind::Alias { name, .. } => format!("*{name}"),
+                };
+                (path::join(&site.path), site.line, what)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("x-defaults".to_string(), 1, "&defaults".to_string()),
+                ("services.api.<<".to_string(), 6, "*defaults".to_string()),
+                ("services.api.ports".to_string(), 7, "&ports".to_string()),
+                (
+                    "services.worker.<<.0".to_string(),
+                    11,
+                    "*defaults".to_string()
+                ),
+                (
+                    "services.worker.ports".to_string(),
+                    12,
+                    "*ports".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            report(&sites, &strings(&["services", "worker"])),
+            "services.worker.<<.0 (line 11)   *defaults → x-defaults (line 1)\n\
+             services.worker.ports (line 12)  *ports → services.api.ports (line 7)\n"
+        );
+
+        let stream = scan("a: &x 1\n---\n- b: *x\n- c: &y 2\n  d: *y\n");
+        let paths: Vec<String> = stream.iter().map(|s| path::join(&s.path)).collect();
+        assert_eq!(paths, ["0.a", "1.0.b", "1.1.c", "1.1.d"]);
+        assert!(matches!(
+            &stream[1].kind,
+            SiteKind::Alias { from: None, .. }
+        ));
+    }
+
+    #[test]
+    fn locate_points_at_keys_and_items() {
+        let at = |path: &[&str]| locate(COMPOSE, &strings(path));
+        assert_eq!(at(&["services", "api", "ports"]), Some((7, 5)));
+        assert_eq!(at(&["services", "api", "ports", "1"]), Some((9, 7)));
+        assert_eq!(at(&["services", "worker", "command"]), Some((13, 5)));
+        assert_eq!(at(&["services", "worker", "image"]), None);
+        let stream = "a: 1\n---\n- b: 2\n- c: 3\n";
+        assert_eq!(locate(stream, &strings(&["1", "1", "c"])), Some((4, 3)));
+    }
+
+    #[test]
+    fn render_keeps_anchors_that_still_hold() {
+        let value = crate::parse_content("c.yaml", COMPOSE, None).unwrap();
+        let sites = scan(COMPOSE);
+        let yaml = render(&value, &sites, &[]).unwrap();
+        assert_eq!(
+            yaml,
+            r#"services:
+  api:
+    <<: &defaults
+      image: app:1
+      restart: always
+    ports: &ports
+    - 80
+    - 443
+  worker:
+    <<:
+    - *defaults
+    command: "run: *not-an-alias\n"
+    ports: *ports
+x-defaults: *defaults
+"#
+        );
+        let reparsed: Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(reparsed, value);
+
+        // A node that no longer matches its anchor is written out in full.
+        let mut changed = value.clone();
+        changed["services"]["worker"]["ports"] = json!([8080]);
+        let yaml = render(&changed["services"], &sites, &strings(&["services"])).unwrap();
+        assert!(yaml.contains("  ports:\n  - 8080\n"), "{yaml}");
+        assert_eq!(
+            serde_yaml::from_str::<Value>(&yaml).unwrap(),
+            changed["services"]
+        );
+    }
+}
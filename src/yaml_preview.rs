@@ -0,0 +1,731 @@
+//! `--resolve-preview`: the YAML text with every anchor, alias and `<<`
+//! merge key expanded in place, comments and layout kept.
+//!
+//! serde_yaml resolves aliases but throws comments away, so this works on
+//! lines instead. Anchored nodes are captured from the already-expanded
+//! output (aliases inside anchors are resolved too) and re-indented where
+//! they are used:
+//! - `key: *name` / `- *name` become a copy of the node, tagged `# *name`;
+//! - `<<: *a` / `<<: [*a, *b]` become a `# <<: …` comment followed by the
+//!   merged keys, minus any the mapping sets itself (earlier sources win);
+//! - aliases inside flow collections are inlined (block nodes as JSON);
+//! - `&name` markers are dropped.
+//!
+//! The result is re-parsed and compared with the original document (merge
+//! keys applied), so a layout the line model gets wrong is an error rather
+//! than a misleading preview.
+//!
+//! Without the `yaml` feature only the line model ([`Line`]) is built: the
+//! `--yaml-anchors` code compiles against it, and `yaml_text` turns the
+//! input away before it runs.
+
+#[cfg(feature = "yaml")]
+use std::collections::{HashMap, HashSet};
+
+#[cfg(feature = "yaml")]
+use anyhow::{anyhow, bail, Context, Result};
+#[cfg(feature = "yaml")]
+use serde::Deserialize;
+#[cfg(feature = "yaml")]
+use serde_json::Value;
+
+#[cfg(feature = "yaml")]
+pub(crate) fn expand(content: &str) -> Result<String> {
+    let source: Vec<&str> = content.lines().collect();
+    let mut expander = Expander::default();
+    for (at, raw) in source.iter().enumerate() {
+        expander
+            .line(raw, &source, at)
+            .with_context(|| format!("line {}", at + 1))?;
+    }
+    expander.close_anchors(0, None);
+
+    let mut preview = expander.out.join("\n");
+    if content.ends_with('\n') {
+        preview.push('\n');
+    }
+    if documents(&preview)? != documents(content)? {
+        bail!("anchors are laid out in a way the preview cannot expand faithfully");
+    }
+    Ok(preview)
+}
+
+/// Every document in the stream, with aliases resolved and merge keys applied.
+#[cfg(feature = "yaml")]
+fn documents(content: &str) -> Result<Vec<Value>> {
+    serde_yaml::Deserializer::from_str(content)
+        .map(|doc| {
+            let mut value = serde_yaml::Value::deserialize(doc)?;
+            value.apply_merge()?;
+            Ok(serde_json::to_value(value)?)
+        })
+        .collect()
+}
+
+/// An anchored node. `scalar` is the text after the key (a plain or flow
+/// value, or a `|`/`>` header); `lines` is the block below it, dedented.
+#[cfg(feature = "yaml")]
+#[derive(Clone, Debug, Default)]
+struct Node {
+    scalar: Option<String>,
+    lines: Vec<String>,
+    /// Indent of the block relative to the owning key.
+    offset: usize,
+}
+
+/// Anchor whose node is still being written to the output.
+#[cfg(feature = "yaml")]
+struct Open {
+    name: String,
+    /// First output line of the node.
+    start: usize,
+    owner: Owner,
+    scalar: Option<String>,
+}
+
+#[cfg(feature = "yaml")]
+#[derive(Clone, Copy)]
+enum Owner {
+    /// `key: &name` with the key at this column.
+    Key(usize),
+    /// `- &name …` with the dash at this column.
+    Dash(usize),
+}
+
+#[cfg(feature = "yaml")]
+#[derive(Default)]
+struct Expander {
+    out: Vec<String>,
+    anchors: HashMap<String, Node>,
+    open: Vec<Open>,
+    /// Inside a block scalar owned by this column: lines are copied as-is.
+    literal: Option<usize>,
+}
+
+/// One non-empty, non-comment line split into its YAML parts.
+#[derive(Debug, Default)]
+pub(crate) struct Line {
+    pub(crate) indent: usize,
+    /// Column of the last `- ` on the line.
+    pub(crate) dash_col: Option<usize>,
+    /// `&name` before the key (anchoring the key) or before a bare item.
+    pub(crate) item_anchor: Option<String>,
+    /// Everything up to and including `key:`, or up to the last `- `.
+    #[cfg_attr(not(feature = "yaml"), allow(dead_code))]
+    head: String,
+    pub(crate) key: Option<String>,
+    pub(crate) key_col: usize,
+    /// `&name` in value position.
+    pub(crate) value_anchor: Option<String>,
+    pub(crate) value: String,
+    /// Trailing comment including the whitespace before `#`.
+    #[cfg_attr(not(feature = "yaml"), allow(dead_code))]
+    pub(crate) comment: String,
+}
+
+#[cfg(feature = "yaml")]
+impl Expander {
+    fn line(&mut self, raw: &str, source: &[&str], at: usize) -> Result<()> {
+        let trimmed = raw.trim_start();
+        if let Some(col) = self.literal {
+            if trimmed.is_empty() || indent_of(raw) > col {
+                self.out.push(raw.to_string());
+                return Ok(());
+            }
+            self.literal = None;
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('%') {
+            self.out.push(raw.to_string());
+            return Ok(());
+        }
+        if raw.starts_with("---") || raw.starts_with("...") {
+            // Anchors are scoped to their document.
+            self.close_anchors(0, None);
+            self.anchors.clear();
+            self.out.push(raw.to_string());
+            return Ok(());
+        }
+
+        let mut line = Line::parse(raw);
+        if let Some(alias) = line.key.as_deref().and_then(alias_only) {
+            let key = self.flow_text(self.node(&alias)?)?;
+            line.head = format!("{}{key}:", &line.head[..line.key_col]);
+            line.key = Some(key);
+        }
+        let first_is_dash = line.dash_col == Some(line.indent);
+        self.close_anchors(line.indent, Some(first_is_dash));
+
+        if line.key.as_deref() == Some("<<") {
+            return self.merge(&line, source, at);
+        }
+
+        if let Some(name) = line.item_anchor.clone() {
+            let dash = line.dash_col.unwrap_or(line.indent);
+            match &line.key {
+                // `&name key: value` anchors the key itself.
+                Some(key) => {
+                    self.anchors.insert(name, scalar_node(key.clone()));
+                }
+                None if !line.value.is_empty() && !is_block_header(&line.value) => {
+                    let scalar = self.inline_aliases(&line.value)?;
+                    self.anchors.insert(name, scalar_node(scalar));
+                }
+                None => self.open.push(Open {
+                    name,
+                    start: self.out.len() + 1,
+                    owner: Owner::Dash(dash),
+                    scalar: None,
+                }),
+            }
+        }
+
+        if let Some(name) = alias_only(&line.value) {
+            return self.emit_alias(&line, &name);
+        }
+
+        let value = self.inline_aliases(&line.value)?;
+        let owner_col = if line.key.is_some() {
+            line.key_col
+        } else {
+            line.dash_col.unwrap_or(line.indent)
+        };
+        if let Some(name) = line.value_anchor.clone() {
+            if value.is_empty() || is_block_header(&value) {
+                self.open.push(Open {
+                    name,
+                    start: self.out.len() + 1,
+                    owner: Owner::Key(owner_col),
+                    scalar: (!value.is_empty()).then(|| value.clone()),
+                });
+            } else {
+                self.anchors.insert(name, scalar_node(value.clone()));
+            }
+        }
+        if is_block_header(&value) {
+            self.literal = Some(owner_col);
+        }
+        self.out.push(join(&line.head, &value, &line.comment));
+        Ok(())
+    }
+
+    /// Finish every open anchor whose node ends before a line at `indent`
+    /// (`None`: end of document).
+    fn close_anchors(&mut self, indent: usize, first_is_dash: Option<bool>) {
+        while let Some(open) = self.open.last() {
+            let ends = match (open.owner, first_is_dash) {
+                (_, None) => true,
+                (Owner::Key(col), Some(dash)) => indent < col || (indent == col && !dash),
+                (Owner::Dash(col), Some(_)) => indent <= col,
+            };
+            if !ends {
+                break;
+            }
+            let open = self.open.pop().expect("checked by last()");
+            let node = self.capture(&open);
+            self.anchors.insert(open.name, node);
+        }
+    }
+
+    fn capture(&self, open: &Open) -> Node {
+        let mut lines: Vec<String> = self.out[open.start.min(self.out.len())..].to_vec();
+        // Inside a block scalar `#` lines are text, so only blank lines go.
+        let block_scalar = open.scalar.is_some();
+        let trailing = |l: &String| {
+            if block_scalar {
+                l.trim().is_empty()
+            } else {
+                is_trivia(l)
+            }
+        };
+        while lines.last().is_some_and(trailing) {
+            lines.pop();
+        }
+        let (base, offset) = match open.owner {
+            Owner::Dash(col) => (col + 2, 2),
+            Owner::Key(col) => {
+                let base = lines
+                    .iter()
+                    .filter(|l| !trailing(l))
+                    .map(|l| indent_of(l))
+                    .min()
+                    .unwrap_or(col + 2);
+                (base, base.saturating_sub(col))
+            }
+        };
+        Node {
+            scalar: open.scalar.clone(),
+            lines: lines.iter().map(|l| dedent(l, base)).collect(),
+            offset,
+        }
+    }
+
+    fn node(&self, name: &str) -> Result<&Node> {
+        self.anchors
+            .get(name)
+            .ok_or_else(|| anyhow!("alias *{name} has no matching anchor before it"))
+    }
+
+    fn emit_alias(&mut self, line: &Line, name: &str) -> Result<()> {
+        let node = self.node(name)?.clone();
+        let tag = if line.comment.is_empty() {
+            format!("  # *{name}")
+        } else {
+            line.comment.clone()
+        };
+
+        if line.key.is_some() {
+            let value = node.scalar.clone().unwrap_or_default();
+            let value = if value.is_empty() && node.lines.is_empty() {
+                "null".to_string()
+            } else {
+                value
+            };
+            self.out.push(join(&line.head, &value, &tag));
+            // A block mapping must be indented under its key; a sequence may not be.
+            let offset = match node.lines.iter().find(|l| !is_trivia(l)) {
+                Some(first) if node.offset == 0 && !first.starts_with('-') => 2,
+                _ => node.offset,
+            };
+            self.push_block(&node.lines, line.key_col + offset);
+            return Ok(());
+        }
+
+        let col = line.head.len();
+        match (&node.scalar, node.lines.first()) {
+            (Some(scalar), _) => self.out.push(join(&line.head, scalar, &tag)),
+            (None, Some(first)) if !is_trivia(first) => {
+                let (code, comment) = split_comment(first);
+                let comment = if comment.is_empty() { &tag } else { comment };
+                self.out.push(format!("{}{code}{comment}", line.head));
+                self.push_block(&node.lines[1..], col);
+                return Ok(());
+            }
+            (None, Some(_)) => self.out.push(join(&line.head, "", &tag)),
+            (None, None) => self.out.push(join(&line.head, "null", &tag)),
+        }
+        self.push_block(&node.lines, col);
+        Ok(())
+    }
+
+    fn merge(&mut self, line: &Line, source: &[&str], at: usize) -> Result<()> {
+        let names = merge_sources(&line.value)
+            .ok_or_else(|| anyhow!("`<<` expects *alias or [*a, *b], found `{}`", line.value))?;
+        let col = line.key_col;
+        let original = format!("# <<: {}{}", line.value, line.comment);
+        self.out.push(format!("{}{original}", &line.head[..col]));
+
+        let mut defined = explicit_keys(source, at, col);
+        for name in names {
+            let node = self.node(&name)?.clone();
+            let lines = match &node.scalar {
+                None => node.lines.clone(),
+                Some(flow) => {
+                    let value: serde_yaml::Value = serde_yaml::from_str(flow)
+                        .with_context(|| format!("merge source *{name}"))?;
+                    serde_yaml::to_string(&value)?
+                        .lines()
+                        .map(str::to_string)
+                        .collect()
+                }
+            };
+            let mut kept = Vec::new();
+            let mut skipping = false;
+            let mut added = Vec::new();
+            for entry in lines {
+                if !is_trivia(&entry) && indent_of(&entry) == 0 {
+                    let key = Line::parse(&entry).key;
+                    let Some(key) = key else {
+                        bail!("merge source *{name} is not a mapping");
+                    };
+                    skipping = defined.contains(&key);
+                    added.push(key);
+                }
+                if !skipping {
+                    kept.push(entry);
+                }
+            }
+            defined.extend(added);
+            self.push_block(&kept, col);
+        }
+        Ok(())
+    }
+
+    fn push_block(&mut self, lines: &[String], col: usize) {
+        let pad = " ".repeat(col);
+        for line in lines {
+            if line.is_empty() {
+                self.out.push(String::new());
+            } else {
+                self.out.push(format!("{pad}{line}"));
+            }
+        }
+    }
+
+    /// Replace aliases inside a flow collection with the node's flow text.
+    fn inline_aliases(&self, value: &str) -> Result<String> {
+        if !value.starts_with(['[', '{']) || !value.contains('*') {
+            return Ok(value.to_string());
+        }
+        let chars: Vec<char> = value.chars().collect();
+        let mut out = String::new();
+        let mut quote = None;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if c == '*' && flow_value_start(&out) => {
+                    let name: String = chars[i + 1..]
+                        .iter()
+                        .take_while(|c| is_anchor_char(**c))
+                        .collect();
+                    out.push_str(&self.flow_text(self.node(&name)?)?);
+                    i += 1 + name.chars().count();
+                    continue;
+                }
+                None => {}
+            }
+            out.push(c);
+            i += 1;
+        }
+        Ok(out)
+    }
+
+    fn flow_text(&self, node: &Node) -> Result<String> {
+        let value: serde_yaml::Value = match &node.scalar {
+            Some(scalar) if node.lines.is_empty() => return Ok(scalar.clone()),
+            // A block scalar only parses under a key.
+            Some(header) => {
+                let mut text = format!("v: {header}\n");
+                for line in &node.lines {
+                    text.push_str(&format!("  {line}\n"));
+                }
+                let mut map: serde_yaml::Mapping = serde_yaml::from_str(&text)?;
+                map.remove("v").unwrap_or_default()
+            }
+            None => serde_yaml::from_str(&node.lines.join("\n"))?,
+        };
+        Ok(serde_json::to_string(&value)?)
+    }
+}
+
+impl Line {
+    pub(crate) fn parse(raw: &str) -> Line {
+        let indent = indent_of(raw);
+        let (code, comment) = split_comment(raw);
+        let mut line = Line {
+            indent,
+            comment: comment.to_string(),
+            ..Line::default()
+        };
+
+        let mut pos = indent;
+        while code[pos..].starts_with("- ") || &code[pos..] == "-" {
+            line.dash_col = Some(pos);
+            pos = (pos + 2).min(code.len());
+            pos += code[pos..].len() - code[pos..].trim_start().len();
+        }
+        // Columns are those of the output, where `&name ` is gone.
+        let prefix = &code[..pos];
+        if let Some((name, rest)) = take_anchor(&code[pos..]) {
+            line.item_anchor = Some(name);
+            pos = code.len() - rest.len();
+        }
+
+        let rest = &code[pos..];
+        match key_split(rest) {
+            Some((key, value_at)) => {
+                line.key = Some(unquote(key.trim_end()));
+                line.key_col = prefix.len();
+                line.head = format!("{prefix}{}", &rest[..value_at]);
+                let mut value = rest[value_at..].trim();
+                if let Some((name, after)) = take_anchor(value) {
+                    line.value_anchor = Some(name);
+                    value = after.trim();
+                }
+                line.value = value.to_string();
+            }
+            None => {
+                line.head = prefix.to_string();
+                line.value = rest.trim().to_string();
+            }
+        }
+        line
+    }
+
+    /// The key as written, quotes included.
+    #[cfg_attr(not(feature = "yaml"), allow(dead_code))]
+    pub(crate) fn raw_key(&self) -> Option<&str> {
+        self.key.as_ref()?;
+        let key = self.head[self.key_col..].trim_end();
+        Some(key.strip_suffix(':').unwrap_or(key).trim_end())
+    }
+}
+
+/// `&name` at the start of `text`: the name and the text after it.
+fn take_anchor(text: &str) -> Option<(String, &str)> {
+    let rest = text.strip_prefix('&')?;
+    let len = rest
+        .find(|c: char| !is_anchor_char(c))
+        .unwrap_or(rest.len());
+    if len == 0 {
+        return None;
+    }
+    let after = &rest[len..];
+    Some((rest[..len].to_string(), after.trim_start()))
+}
+
+fn is_anchor_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, ',' | '[' | ']' | '{' | '}')
+}
+
+pub(crate) fn alias_only(value: &str) -> Option<String> {
+    let name = value.strip_prefix('*')?;
+    (!name.is_empty() && name.chars().all(is_anchor_char)).then(|| name.to_string())
+}
+
+pub(crate) fn merge_sources(value: &str) -> Option<Vec<String>> {
+    if let Some(name) = alias_only(value) {
+        return Some(vec![name]);
+    }
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?;
+    inner
+        .split(',')
+        .map(|item| alias_only(item.trim()))
+        .collect()
+}
+
+/// Keys set directly by the mapping around the `<<` at `source[at]`, whose
+/// keys sit at column `col`.
+#[cfg(feature = "yaml")]
+fn explicit_keys(source: &[&str], at: usize, col: usize) -> HashSet<String> {
+    let mut keys = HashSet::new();
+    let mut collect = |line: Line| {
+        if line.key_col == col && line.key.as_deref().is_some_and(|k| k != "<<") {
+            keys.insert(line.key.unwrap_or_default());
+        }
+    };
+    // `- key:` with the key at `col` starts the mapping (an item of a list).
+    let starts_item =
+        |line: &Line| line.key.is_some() && line.dash_col.is_some_and(|d| d + 2 == col);
+
+    if !starts_item(&Line::parse(source[at])) {
+        for raw in source[..at].iter().rev().filter(|raw| !is_trivia(raw)) {
+            let line = Line::parse(raw);
+            let first = starts_item(&line);
+            if line.indent < col && !first {
+                break;
+            }
+            collect(line);
+            if first {
+                break;
+            }
+        }
+    }
+    for raw in source[at + 1..].iter().filter(|raw| !is_trivia(raw)) {
+        let line = Line::parse(raw);
+        if line.indent < col {
+            break;
+        }
+        collect(line);
+    }
+    keys
+}
+
+/// Split `text` at its `key:` separator: the key and where the value starts.
+fn key_split(text: &str) -> Option<(&str, usize)> {
+    if text.starts_with(['[', '{', '|', '>', '&', '!']) {
+        return None;
+    }
+    let mut quote = None;
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if (c == '"' || c == '\'') && i == 0 => quote = Some(c),
+            None if c == ':' => {
+                let next = text[i + 1..].chars().next();
+                if next.is_none() || next.is_some_and(char::is_whitespace) {
+                    return Some((&text[..i], i + 1));
+                }
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+fn unquote(key: &str) -> String {
+    for q in ['"', '\''] {
+        if let Some(inner) = key.strip_prefix(q).and_then(|k| k.strip_suffix(q)) {
+            return inner.to_string();
+        }
+    }
+    key.to_string()
+}
+
+/// Code and trailing comment (with its leading whitespace) of a line.
+fn split_comment(raw: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut prev_space = true;
+    for (i, c) in raw.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            // A quote only opens a string at the start of a scalar.
+            None if (c == '"' || c == '\'')
+                && (prev_space || raw[..i].ends_with(['[', '{', ',', ':'])) =>
+            {
+                quote = Some(c)
+            }
+            None if c == '#' && prev_space => {
+                let code = raw[..i].trim_end();
+                return (code, &raw[code.len()..]);
+            }
+            None => {}
+        }
+        prev_space = c.is_whitespace();
+    }
+    (raw.trim_end(), "")
+}
+
+#[cfg(feature = "yaml")]
+fn flow_value_start(before: &str) -> bool {
+    before.trim_end().ends_with(['[', '{', ',', ':'])
+}
+
+pub(crate) fn is_block_header(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some('|' | '>'))
+        && chars.all(|c| c.is_ascii_digit() || c == '+' || c == '-')
+}
+
+pub(crate) fn is_trivia(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+pub(crate) fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+#[cfg(feature = "yaml")]
+fn dedent(line: &str, base: usize) -> String {
+    let strip = indent_of(line).min(base);
+    line[strip..].to_string()
+}
+
+#[cfg(feature = "yaml")]
+fn scalar_node(scalar: String) -> Node {
+    Node {
+        scalar: Some(scalar),
+        ..Node::default()
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn join(head: &str, value: &str, comment: &str) -> String {
+    let sep = if value.is_empty() || head.is_empty() || head.ends_with(' ') {
+        ""
+    } else {
+        " "
+    };
+    format!("{head}{sep}{value}{comment}")
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(all(test, feature = "yaml"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_keys_expand_with_overrides_and_comments() {
+        let text = "\
+x-defaults: &defaults  # shared
+  restart: always  # keep running
+  logging: &log
+    driver: json-file
+
+services:
+  api:
+    <<: *defaults
+    restart: \"no\"  # override
+    log: *log
+";
+        assert_eq!(
+            expand(text).unwrap(),
+            "\
+x-defaults:  # shared
+  restart: always  # keep running
+  logging:
+    driver: json-file
+
+services:
+  api:
+    # <<: *defaults
+    logging:
+      driver: json-file
+    restart: \"no\"  # override
+    log:  # *log
+      driver: json-file
+"
+        );
+    }
+
+    #[test]
+    fn aliases_in_sequences_flow_and_block_scalars() {
+        let text = "\
+base: &b {x: 1}
+list: &l
+- 1
+script: &s |
+  echo hi
+  # part of the script
+items:
+  - *l
+  - [*b, 2]
+  - <<: [*b]
+    y: 2
+again: *s
+---
+v: &b 3
+w: *b
+";
+        assert_eq!(
+            expand(text).unwrap(),
+            "\
+base: {x: 1}
+list:
+- 1
+script: |
+  echo hi
+  # part of the script
+items:
+  - - 1  # *l
+  - [{x: 1}, 2]
+  - # <<: [*b]
+    x: 1
+    y: 2
+again: |  # *s
+  echo hi
+  # part of the script
+---
+v: 3
+w: 3  # *b
+"
+        );
+    }
+
+    #[test]
+    fn unknown_aliases_are_errors() {
+        let err = expand("a: 1\nb: *missing\n").unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "line 2: alias *missing has no matching anchor before it"
+        );
+        assert_eq!(expand("plain: yes  # c\n").unwrap(), "plain: yes  # c\n");
+    }
+}
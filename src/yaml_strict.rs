@@ -0,0 +1,133 @@
+//! `--yaml-1.2`: plain YAML scalars typed by the YAML 1.2 JSON schema.
+//!
+//! serde_yaml resolves plain scalars with the wider core schema, so `True`,
+//! `~`, `0x1F`, `0o22`, `+12` and `.inf` become booleans, nulls and numbers.
+//! In strict mode only `true`, `false`, `null` and JSON numbers do; any
+//! other spelling stays the string it was written as unless an explicit tag
+//! asks for the type (`!!bool True`, `!!int 0x1F`). The rewrite quotes such
+//! scalars in the text before it reaches serde_yaml, so aliases, tags and
+//! error positions keep working. Scalars inside flow collections
+//! (`[a, b]`, `{a: b}`) keep the core schema.
+
+use serde_json::Value;
+
+use crate::yaml_preview::{indent_of, is_block_header, is_trivia, Line};
+
+/// `content` with every block-context plain scalar that the core schema
+/// would type, but the JSON schema would not, turned into a quoted string.
+pub(crate) fn quote_loose_scalars(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut out = String::with_capacity(content.len());
+    let mut literal: Option<usize> = None;
+    for (at, raw) in lines.iter().enumerate() {
+        if let Some(col) = literal {
+            if raw.trim().is_empty() || indent_of(raw) > col {
+                out.push_str(raw);
+                continue;
+            }
+            literal = None;
+        }
+        if is_trivia(raw) || raw.starts_with("---") || raw.starts_with("...") {
+            out.push_str(raw);
+            continue;
+        }
+        let line = Line::parse(raw.trim_end_matches(['\n', '\r']));
+        let owner = match (&line.key, line.dash_col) {
+            (Some(_), _) => line.key_col,
+            (None, Some(dash)) => dash,
+            (None, None) => {
+                out.push_str(raw);
+                continue;
+            }
+        };
+        if is_block_header(&line.value) {
+            literal = Some(owner);
+        }
+        if !loose(&line.value) || continues(&lines[at + 1..], owner) {
+            out.push_str(raw);
+            continue;
+        }
+        let end = raw.trim_end_matches(['\n', '\r']).len() - line.comment.len();
+        let end = raw[..end].trim_end().len();
+        let start = end - line.value.len();
+        out.push_str(&raw[..start]);
+        out.push_str(&Value::String(line.value.clone()).to_string());
+        out.push_str(&raw[end..]);
+    }
+    out
+}
+
+/// A plain scalar the core schema types and JSON would not read as the
+/// same scalar: `True`, `~`, `0x1F`, `+12`, `.inf`.
+pub(crate) fn loose(value: &str) -> bool {
+    if value.is_empty() || value.starts_with(['"', '\'', '[', '{', '!', '&', '*', '|', '>']) {
+        return false;
+    }
+    let typed = matches!(
+        serde_yaml::from_str::<serde_yaml::Value>(value),
+        Ok(serde_yaml::Value::Null | serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_))
+    );
+    let canonical = matches!(
+        serde_json::from_str::<Value>(value),
+        Ok(Value::Null | Value::Bool(_) | Value::Number(_))
+    );
+    typed && !canonical
+}
+
+/// Whether the scalar on the line before `rest` goes on over more lines, as
+/// a multi-line plain scalar does.
+fn continues(rest: &[&str], owner: usize) -> bool {
+    let Some(next) = rest.iter().find(|raw| !is_trivia(raw)) else {
+        return false;
+    };
+    let line = Line::parse(next.trim_end_matches(['\n', '\r']));
+    indent_of(next) > owner && line.key.is_none() && line.dash_col.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_json_spellings_keep_their_type() {
+        let content = "\
+a: True
+b: ~   # comment
+c: 0x1F\r
+d:
+  - +12
+  - .inf
+  - !!bool True
+e: true
+f: null
+g: -1.5e3
+h: 'True'
+i: |
+  ~
+j: [True, ~]
+k: 0x1F
+  continued
+";
+        assert_eq!(
+            quote_loose_scalars(content),
+            "\
+a: \"True\"
+b: \"~\"   # comment
+c: \"0x1F\"\r
+d:
+  - \"+12\"
+  - \".inf\"
+  - !!bool True
+e: true
+f: null
+g: -1.5e3
+h: 'True'
+i: |
+  ~
+j: [True, ~]
+k: 0x1F
+  continued
+"
+        );
+    }
+}
@@ -0,0 +1,91 @@
+//! Runs the binary with stdout and stderr piped, as in `confctl … | jq` or
+//! `> file`, to check that escape codes only appear when colour is forced.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const ESC: char = '\x1b';
+
+/// `confctl ARGS` with no colour or settings from the environment, plus
+/// `env`.
+fn confctl(args: &[&str], env: &[(&str, &str)]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_confctl"));
+    command
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env_remove("NO_COLOR")
+        .env_remove("CLICOLOR_FORCE")
+        .env_remove("CONFCTL_THEME")
+        .env_remove("CONFCTL_HISTORY")
+        .env("CONFCTL_USER_CONFIG", "")
+        .envs(env.iter().copied());
+    let output = command.output().expect("running confctl");
+    assert!(
+        output.status.success(),
+        "confctl {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn piped_output_is_plain() {
+    let commands: &[&[&str]] = &[
+        &["testdata/config.json"],
+        &["testdata/config.json", "clubs.0"],
+        &["testdata/config.yaml", "-o", "yaml"],
+        &["testdata/config.json", "--length"],
+        &["diff", "testdata/config.json", "testdata/config.toml"],
+        &["lint", "testdata/config.yaml"],
+        &["check", "testdata/config.json"],
+    ];
+    for args in commands {
+        let output = confctl(args, &[]);
+        assert!(!stdout(&output).is_empty(), "{args:?}");
+        assert!(!stdout(&output).contains(ESC), "{args:?}");
+    }
+}
+
+#[test]
+fn colour_is_only_there_when_forced() {
+    let query = ["testdata/config.json", "clubs.0"];
+    let forced = confctl(&[&query[..], &["--color", "always"]].concat(), &[]);
+    assert!(stdout(&forced).contains(ESC));
+    let forced = confctl(&query, &[("CLICOLOR_FORCE", "1")]);
+    assert!(stdout(&forced).contains(ESC));
+
+    let never = [&query[..], &["--color", "never"]].concat();
+    let output = confctl(&never, &[("CLICOLOR_FORCE", "1")]);
+    assert!(!stdout(&output).contains(ESC));
+    // NO_COLOR only matters on a terminal; piped output is plain anyway.
+    let output = confctl(&query, &[("NO_COLOR", "1")]);
+    assert!(!stdout(&output).contains(ESC));
+}
+
+#[test]
+fn out_files_are_never_coloured() {
+    let dir = std::env::temp_dir().join(format!("confctl-piped-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let out: PathBuf = dir.join("clubs.json");
+    let out = out.to_str().unwrap();
+
+    let args = ["testdata/config.json", "clubs", "--out", out, "--mkdir"];
+    let output = confctl(&args, &[]);
+    assert_eq!(stderr(&output), format!("✓ wrote {out}\n"));
+    let written = std::fs::read_to_string(out).unwrap();
+    assert!(written.starts_with('[') && !written.contains(ESC));
+
+    let forced = [&args[..], &["--force", "--color", "always"]].concat();
+    confctl(&forced, &[]);
+    assert_eq!(std::fs::read_to_string(out).unwrap(), written);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}